@@ -1,6 +1,10 @@
 use chrono::Utc;
-use crate::config::env::Config;
-use crate::db::DbPool;
+use crate::auth::failure::{AuthFailure, Service};
+use crate::config::env::{Config, Flags};
+use crate::db::{DbPool, InvoiceDocument, RunRecord};
+use crate::process::feedback::ProcessedFile;
+use crate::scheduler::runner::ScheduleDay;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum FocusedPanel {
@@ -21,6 +25,8 @@ pub enum PopupState {
     Help,
     SetupGuide,
     DetailedLogs,
+    Corrections,
+    ArchiveSearch,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -28,6 +34,8 @@ pub enum AuthStatus {
     NotAuthenticated,
     Authenticating,
     Authenticated,
+    /// The browser never came back from the sign-in page
+    TimedOut,
     Error(String),
 }
 
@@ -37,6 +45,8 @@ pub struct App {
     pub popup_state: PopupState,
     pub config: Option<Config>,
     pub db_pool: Option<DbPool>,
+    // Command-line switches (`--mock`, `--refresh`) for the configuration loaded
+    pub flags: Flags,
 
     // Manual mode state
     pub start_date_input: String,
@@ -52,14 +62,16 @@ pub struct App {
     pub total_failed: usize,
     pub billing_month: Option<String>,
     pub drive_folder: Option<String>,
+    pub drive_folder_link: Option<String>,
 
     // Auth status
     pub gmail_auth_status: AuthStatus,
     pub drive_auth_status: AuthStatus,
 
     // Scheduled mode
-    pub fetch_invoices_day: Option<u32>,
+    pub fetch_invoices_day: Option<ScheduleDay>,
     pub schedule_input: String,
+    pub last_scheduled_run: Option<RunRecord>,
 
     // Error handling
     pub error_message: Option<String>,
@@ -67,6 +79,17 @@ pub struct App {
 
     // Auth popup state
     pub auth_popup_success: bool,
+    /// Sign-in waiting for the browser, aborted when the popup is cancelled
+    pub auth_task: Option<tokio::task::JoinHandle<()>>,
+    /// Redirect address being pasted, when sign-ins are completed by pasting (`--paste-code`)
+    pub auth_paste_input: Option<String>,
+    /// Where the sign-in waiting for a pasted address reads it from
+    pub auth_paste: Option<tokio::sync::mpsc::UnboundedSender<String>>,
+    /// Why signing in to each service last failed, by `Service::key`
+    pub auth_failures: BTreeMap<String, AuthFailure>,
+    /// Google accounts the saved Gmail and Drive tokens were issued to (they may differ)
+    pub gmail_account: Option<String>,
+    pub drive_account: Option<String>,
 
     // Logging state
     pub scheduled_job_logged: bool,
@@ -76,6 +99,18 @@ pub struct App {
 
     // Detailed logs viewer
     pub logs_scroll_offset: usize,
+
+    // Corrections popup: recently processed files, the selected one and a folder being typed
+    pub processed_files: Vec<ProcessedFile>,
+    pub processed_selected: usize,
+    pub correction_folder_input: Option<String>,
+
+    // Archive search popup: the typed query, its results (None until searched) and the selected one;
+    // keys edit the query until a search returns results
+    pub archive_query: String,
+    pub archive_results: Option<Vec<InvoiceDocument>>,
+    pub archive_selected: usize,
+    pub archive_editing: bool,
 }
 
 impl App {
@@ -85,6 +120,7 @@ impl App {
             popup_state: PopupState::None,
             config: None,
             db_pool: None,
+            flags: Flags::default(),
             start_date_input: String::new(),
             end_date_input: String::new(),
             date_input_focus: true, // Start with start date focused
@@ -96,16 +132,31 @@ impl App {
             total_failed: 0,
             billing_month: None,
             drive_folder: None,
+            drive_folder_link: None,
             gmail_auth_status: AuthStatus::NotAuthenticated,
             drive_auth_status: AuthStatus::NotAuthenticated,
             fetch_invoices_day: None,
             schedule_input: String::new(),
+            last_scheduled_run: None,
             error_message: None,
             auth_url: None,
             auth_popup_success: false,
+            auth_task: None,
+            auth_paste_input: None,
+            auth_paste: None,
+            auth_failures: BTreeMap::new(),
+            gmail_account: None,
+            drive_account: None,
             scheduled_job_logged: false,
             animation_counter: 0,
             logs_scroll_offset: 0,
+            processed_files: Vec::new(),
+            processed_selected: 0,
+            correction_folder_input: None,
+            archive_query: String::new(),
+            archive_results: None,
+            archive_selected: 0,
+            archive_editing: true,
         }
     }
 
@@ -135,6 +186,14 @@ impl App {
         Ok(())
     }
 
+    /// Latest run of the `scheduled` command, for the Scheduled panel
+    pub async fn load_last_scheduled_run(&mut self) -> anyhow::Result<()> {
+        if let (Some(pool), Some(config)) = (&self.db_pool, &self.config) {
+            self.last_scheduled_run = crate::db::last_scheduled_run(pool, config.profile.as_deref().unwrap_or_default()).await?;
+        }
+        Ok(())
+    }
+
     pub fn set_processing(&mut self, processing: bool) {
         self.is_processing = processing;
         if processing {
@@ -157,6 +216,7 @@ impl App {
         self.total_failed = 0;
         self.billing_month = None;
         self.drive_folder = None;
+        self.drive_folder_link = None;
     }
 
     pub fn reset_manual_inputs(&mut self) {
@@ -180,15 +240,59 @@ impl App {
         self.error_message = None;
     }
 
+    pub fn auth_status_mut(&mut self, service: Service) -> &mut AuthStatus {
+        match service {
+            Service::Gmail => &mut self.gmail_auth_status,
+            Service::Drive => &mut self.drive_auth_status,
+        }
+    }
+
+    /// Read the failures sign-ins recorded (in this session or by runs elsewhere)
+    pub fn reload_auth_failures(&mut self) {
+        let profile = self.config.as_ref().and_then(|config| config.profile.clone());
+        match crate::auth::failure::load(profile.as_deref()) {
+            Ok(failures) => self.auth_failures = failures,
+            Err(e) => log::warn!("Failed to read auth errors: {:#}", e),
+        }
+    }
+
+    /// Read which accounts the saved tokens belong to
+    pub fn reload_auth_accounts(&mut self) {
+        let profile = self.config.as_ref().and_then(|config| config.profile.clone());
+        self.gmail_account = crate::auth::gmail_auth::signed_in_as(profile.as_deref());
+        self.drive_account = crate::auth::drive_auth::signed_in_as(profile.as_deref());
+    }
+
+    /// Stop a sign-in still waiting for the browser, which also frees its callback port
+    pub fn cancel_auth(&mut self) {
+        self.auth_paste_input = None;
+        self.auth_paste = None;
+        let Some(task) = self.auth_task.take() else {
+            return;
+        };
+        if task.is_finished() {
+            return;
+        }
+        task.abort();
+        if self.gmail_auth_status == AuthStatus::Authenticating {
+            self.gmail_auth_status = AuthStatus::NotAuthenticated;
+            self.add_progress_message("Gmail authentication cancelled".to_string());
+        }
+        if self.drive_auth_status == AuthStatus::Authenticating {
+            self.drive_auth_status = AuthStatus::NotAuthenticated;
+            self.add_progress_message("Google Drive authentication cancelled".to_string());
+        }
+    }
+
     pub fn is_popup_open(&self) -> bool {
         !matches!(self.popup_state, PopupState::None)
     }
 
     pub fn load_config(&mut self) -> Result<(), String> {
-        match Config::from_env() {
+        match Config::load_with(self.flags) {
             Ok(config) => {
                 self.config = Some(config.clone());
-                self.fetch_invoices_day = config.fetch_invoices_day.map(|d| d as u32);
+                self.fetch_invoices_day = config.fetch_invoices_day;
                 Ok(())
             }
             Err(e) => Err(format!("Failed to load config: {}", e)),
@@ -197,6 +301,13 @@ impl App {
 
     /// Validate existing authentication tokens and update auth status
     pub fn validate_existing_tokens(&mut self) {
+        if self.config.as_ref().is_some_and(|c| c.mock_mode) {
+            self.gmail_auth_status = AuthStatus::Authenticated;
+            self.drive_auth_status = AuthStatus::Authenticated;
+            self.add_progress_message("Mock mode: Gmail and Drive are backed by local fixtures".to_string());
+            return;
+        }
+
         if let Some(_config) = &self.config {
             // Check Gmail token
             match crate::auth::oauth::get_config_dir() {
@@ -247,6 +358,16 @@ impl App {
                     self.add_progress_message(format!("Warning: Could not access config directory: {}", e));
                 }
             }
+
+            // A failure since the tokens were saved (a revoked token) outranks them
+            self.reload_auth_accounts();
+            self.reload_auth_failures();
+            for service in [Service::Gmail, Service::Drive] {
+                if let Some(failure) = self.auth_failures.get(service.key()).cloned() {
+                    *self.auth_status_mut(service) = AuthStatus::Error(failure.message);
+                    self.add_progress_message(format!("{}: last sign-in failed ({})", service.label(), failure.kind.label()));
+                }
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,105 @@
+//! The page the browser lands on at the end of an OAuth sign-in. It says whether the sign-in went
+//! through and why not (access declined, a stale sign-in link), carries the title and message
+//! set in OAUTH_PAGE_TITLE / OAUTH_SUCCESS_MESSAGE, and closes itself after
+//! OAUTH_PAGE_CLOSE_SECONDS where the browser allows it.
+
+use anyhow::{Context, Result};
+
+const TEMPLATE: &str = include_str!("callback.html");
+
+#[derive(Debug, Clone)]
+pub struct CallbackPage {
+    pub title: String,
+    pub success_message: String,
+    /// Seconds before the page closes itself, 0 to leave it open
+    pub close_seconds: u64,
+}
+
+impl Default for CallbackPage {
+    fn default() -> Self {
+        Self {
+            title: "Invoice Pilot".to_string(),
+            success_message: "Invoice Pilot can now use your account.".to_string(),
+            close_seconds: 3,
+        }
+    }
+}
+
+impl CallbackPage {
+    pub fn from_env() -> Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            title: std::env::var("OAUTH_PAGE_TITLE").unwrap_or(default.title),
+            success_message: std::env::var("OAUTH_SUCCESS_MESSAGE").unwrap_or(default.success_message),
+            close_seconds: std::env::var("OAUTH_PAGE_CLOSE_SECONDS")
+                .ok()
+                .map(|s| s.parse().context("OAUTH_PAGE_CLOSE_SECONDS must be a number of seconds"))
+                .transpose()?
+                .unwrap_or(default.close_seconds),
+        })
+    }
+
+    /// Full HTTP response telling the browser the sign-in went through
+    pub fn success(&self) -> String {
+        response("200 OK", &self.render("ok", "✓ Authorization successful", &self.success_message, None))
+    }
+
+    /// Full HTTP response explaining why the sign-in didn't go through
+    pub fn failure(&self, heading: &str, message: &str, detail: Option<&str>) -> String {
+        response("400 Bad Request", &self.render("error", &format!("✗ {}", heading), message, detail))
+    }
+
+    fn render(&self, status: &str, heading: &str, message: &str, detail: Option<&str>) -> String {
+        let detail = detail.map(|detail| format!("<p class=\"detail\">{}</p>", escape(detail))).unwrap_or_default();
+        TEMPLATE
+            .replace("{{status}}", status)
+            .replace("{{title}}", &escape(&self.title))
+            .replace("{{heading}}", &escape(heading))
+            .replace("{{message}}", &escape(message))
+            .replace("{{detail}}", &detail)
+            .replace("{{close_seconds}}", &self.close_seconds.to_string())
+    }
+}
+
+/// Requests that aren't the redirect (the browser asking for a favicon)
+pub fn not_found() -> String {
+    response("404 Not Found", "")
+}
+
+fn response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Callback parameters come from whoever opened the URL, so nothing is put in the page as is
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pages() {
+        let page = CallbackPage { title: "Acme Books".to_string(), close_seconds: 0, ..Default::default() };
+        let success = page.success();
+        assert!(success.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(success.contains("<title>Acme Books</title>"));
+        assert!(success.contains("const seconds = 0;"));
+
+        let failure = page.failure("Access was declined", "Start again from the terminal.", Some("<script>alert(1)</script>"));
+        assert!(failure.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(failure.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        let (head, body) = failure.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+    }
+}
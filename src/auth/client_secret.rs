@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// The `client_secret_*.json` downloaded from the Cloud console's Credentials page. Desktop app
+/// clients nest their credentials under "installed", web application clients under "web".
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ClientSecretFile {
+    Installed(ClientSecret),
+    Web(ClientSecret),
+}
+
+/// OAuth client credentials, the pair otherwise set as GOOGLE_*_CLIENT_ID and GOOGLE_*_CLIENT_SECRET
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ClientSecret {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl ClientSecret {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read OAuth client file {}", path.display()))?;
+        Self::parse(&json).with_context(|| {
+            format!("{} is not an OAuth client JSON file (download it from APIs & Services > Credentials in the Cloud console)", path.display())
+        })
+    }
+
+    fn parse(json: &str) -> Result<Self> {
+        let (ClientSecretFile::Installed(secret) | ClientSecretFile::Web(secret)) = serde_json::from_str(json)?;
+        if secret.client_id.trim().is_empty() || secret.client_secret.trim().is_empty() {
+            anyhow::bail!("client_id or client_secret is empty");
+        }
+        Ok(secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_client_secret_file() {
+        let installed = r#"{"installed": {"client_id": "123-abc.apps.googleusercontent.com", "project_id": "invoices", "auth_uri": "https://accounts.google.com/o/oauth2/auth", "token_uri": "https://oauth2.googleapis.com/token", "client_secret": "GOCSPX-secret", "redirect_uris": ["http://localhost"]}}"#;
+        let secret = ClientSecret::parse(installed).unwrap();
+        assert_eq!(secret.client_id, "123-abc.apps.googleusercontent.com");
+        assert_eq!(secret.client_secret, "GOCSPX-secret");
+
+        let web = r#"{"web": {"client_id": "456-def.apps.googleusercontent.com", "client_secret": "GOCSPX-web"}}"#;
+        assert_eq!(ClientSecret::parse(web).unwrap().client_id, "456-def.apps.googleusercontent.com");
+
+        // A service account key is the other JSON the console hands out
+        assert!(ClientSecret::parse(r#"{"type": "service_account", "client_email": "a@b.iam.gserviceaccount.com"}"#).is_err());
+        assert!(ClientSecret::parse(r#"{"installed": {"client_id": "123", "client_secret": ""}}"#).is_err());
+    }
+}
@@ -2,23 +2,78 @@ use anyhow::Result;
 use log::info;
 use oauth2::TokenResponse;
 use super::oauth::{
-    TokenCache, create_oauth_client, get_config_dir, load_token, save_token,
-    perform_oauth_flow, refresh_token,
+    TokenCache, cached_account, check_account, create_oauth_client, get_config_dir, get_token_dir, load_token, save_token,
+    Paste, perform_oauth_flow, refresh_token, revoke_cached_token,
 };
+use serde::Deserialize;
 use std::fs;
+use std::path::{Path, PathBuf};
 
-const DRIVE_SCOPE: &str = "https://www.googleapis.com/auth/drive.file";
+pub const DRIVE_SCOPE: &str = "https://www.googleapis.com/auth/drive.file";
 const DRIVE_TOKEN_FILE: &str = "drive_token.json";
+// Cloud Storage (STORAGE_BACKEND=gcs) is authorized separately with the same OAuth client
+const STORAGE_TOKEN_FILE: &str = "gcs_token.json";
 
-/// Get or refresh Drive access token
-pub async fn get_drive_token(client_id: String, client_secret: String) -> Result<String> {
-    let config_dir = get_config_dir()?;
-    let token_path = config_dir.join(DRIVE_TOKEN_FILE);
+/// Get or refresh the Drive access token stored for a profile (None = default profile)
+pub async fn get_drive_token_for_profile(client_id: String, client_secret: String, account: Option<&str>, profile: Option<&str>, paste_code: bool) -> Result<String> {
+    let token_path = get_token_dir(profile)?.join(DRIVE_TOKEN_FILE);
+    get_cached_or_authorize(client_id, client_secret, &token_path, DRIVE_SCOPE, account, Paste::terminal(paste_code)).await
+}
+
+/// Get or refresh the Cloud Storage access token stored for a profile, authorized with the
+/// Drive OAuth client
+pub async fn get_storage_token_for_profile(client_id: String, client_secret: String, account: Option<&str>, profile: Option<&str>, paste_code: bool) -> Result<String> {
+    let token_path = get_token_dir(profile)?.join(STORAGE_TOKEN_FILE);
+    get_cached_or_authorize(client_id, client_secret, &token_path, crate::storage::gcs::STORAGE_SCOPE, account, Paste::terminal(paste_code)).await
+}
+
+/// Email of the account a Drive token belongs to; None for tokens without a Drive scope
+async fn signed_in_account(access_token: &str) -> Option<String> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct User {
+        email_address: String,
+    }
+    #[derive(Deserialize)]
+    struct About {
+        user: User,
+    }
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/about", crate::drive::client::DRIVE_API_BASE))
+        .query(&[("fields", "user(emailAddress)")])
+        .bearer_auth(access_token)
+        .send()
+        .await
+        .ok()?;
+    let about: About = response.error_for_status().ok()?.json().await.ok()?;
+    Some(about.user.email_address)
+}
+
+/// Fill in the account of a token saved before accounts were recorded, while it's still valid
+async fn remember_account(token_path: &Path, mut token: TokenCache) -> TokenCache {
+    if token.account.is_none()
+        && !token.is_expired()
+        && let Some(account) = signed_in_account(&token.access_token).await
+    {
+        token.account = Some(account);
+        if let Err(e) = save_token(token_path, &token) {
+            log::warn!("Failed to record the Drive account: {:#}", e);
+        }
+    }
+    token
+}
 
-    // Try to load existing token
+/// Use the cached token at `token_path`, refreshing it or running the OAuth flow as needed
+async fn get_cached_or_authorize(client_id: String, client_secret: String, token_path: &PathBuf, scope: &str, account: Option<&str>, paste: Option<Paste>) -> Result<String> {
+    // Try to load existing token, unless it was issued to another account than the configured one
     if token_path.exists() {
         info!("Loading cached Drive token...");
-        if let Ok(token_cache) = load_token(&token_path) {
+        let cached = match load_token(token_path) {
+            Ok(token) if scope == DRIVE_SCOPE => Some(remember_account(token_path, token).await),
+            token => token.ok(),
+        };
+        if let Some(token_cache) = cached.filter(|token| token.belongs_to(account)) {
             if !token_cache.is_expired() {
                 info!("Using cached Drive token");
                 return Ok(token_cache.access_token);
@@ -37,9 +92,11 @@ pub async fn get_drive_token(client_id: String, client_secret: String) -> Result
                         access_token: new_token.access_token().secret().clone(),
                         refresh_token: new_token.refresh_token().map(|t| t.secret().clone()).or(Some(refresh)),
                         expires_at,
+                        account: token_cache.account,
+                        signed_in_at: token_cache.signed_in_at,
                     };
 
-                    save_token(&token_path, &token_cache)?;
+                    save_token(token_path, &token_cache)?;
                     return Ok(token_cache.access_token);
                 }
             }
@@ -47,12 +104,12 @@ pub async fn get_drive_token(client_id: String, client_secret: String) -> Result
     }
 
     // Need new authorization
-    let (token, _) = authorize_drive(client_id, client_secret, None).await?;
+    let (token, _) = authorize_drive(client_id, client_secret, None, token_path, scope, account, paste).await?;
     Ok(token)
 }
 
 /// Get or refresh Drive access token with URL callback for TUI
-pub async fn get_drive_token_with_url(client_id: String, client_secret: String, tx: tokio::sync::mpsc::UnboundedSender<String>) -> Result<String> {
+pub async fn get_drive_token_with_url(client_id: String, client_secret: String, account: Option<&str>, tx: tokio::sync::mpsc::UnboundedSender<String>, pasted: Option<tokio::sync::mpsc::UnboundedReceiver<String>>) -> Result<String> {
     let config_dir = get_config_dir()?;
     let token_path = config_dir.join(DRIVE_TOKEN_FILE);
 
@@ -60,7 +117,11 @@ pub async fn get_drive_token_with_url(client_id: String, client_secret: String,
     // Try to load existing token first for immediate success if available
     if token_path.exists() {
         info!("Loading cached Drive token...");
-        if let Ok(token_cache) = load_token(&token_path) {
+        let cached = match load_token(&token_path) {
+            Ok(token) => Some(remember_account(&token_path, token).await),
+            Err(_) => None,
+        };
+        if let Some(token_cache) = cached.filter(|token| token.belongs_to(account)) {
             if !token_cache.is_expired() {
                 info!("Using cached Drive token");
                 // Send cached success message (popup stays open)
@@ -81,6 +142,8 @@ pub async fn get_drive_token_with_url(client_id: String, client_secret: String,
                         access_token: new_token.access_token().secret().clone(),
                         refresh_token: new_token.refresh_token().map(|t| t.secret().clone()).or(Some(refresh)),
                         expires_at,
+                        account: token_cache.account,
+                        signed_in_at: token_cache.signed_in_at,
                     };
 
                     save_token(&token_path, &token_cache)?;
@@ -93,34 +156,47 @@ pub async fn get_drive_token_with_url(client_id: String, client_secret: String,
     }
 
     // Need new authorization - URL will be sent via channel from perform_oauth_flow
-    let (token, _auth_url) = authorize_drive(client_id, client_secret, Some(tx)).await?;
+    let (token, _auth_url) = authorize_drive(client_id, client_secret, Some(tx), &token_path, DRIVE_SCOPE, account, pasted.map(Paste::Tui)).await?;
     Ok(token)
 }
 
 /// Perform full Drive authorization flow
-async fn authorize_drive(client_id: String, client_secret: String, tx: Option<tokio::sync::mpsc::UnboundedSender<String>>) -> Result<(String, String)> {
+async fn authorize_drive(client_id: String, client_secret: String, tx: Option<tokio::sync::mpsc::UnboundedSender<String>>, token_path: &Path, scope: &str, account: Option<&str>, paste: Option<Paste>) -> Result<(String, String)> {
     let client = create_oauth_client(client_id, client_secret)?;
-    let scopes = vec![DRIVE_SCOPE.to_string()];
+    let scopes = vec![scope.to_string()];
 
     let sender_with_prefix = tx.map(|sender| (sender, "DRIVE_"));
-    let (token, auth_url) = perform_oauth_flow(&client, scopes, sender_with_prefix).await?;
+    let (token, auth_url) = perform_oauth_flow(&client, scopes, sender_with_prefix, account, paste).await?;
 
     let expires_at = token.expires_in()
         .map(|d| chrono::Utc::now().timestamp() + d.as_secs() as i64);
 
+    let signed_in = signed_in_account(token.access_token().secret()).await;
+    check_account("Google Drive", "GOOGLE_DRIVE_ACCOUNT", signed_in.as_deref(), account)?;
+
     let token_cache = TokenCache {
         access_token: token.access_token().secret().clone(),
         refresh_token: token.refresh_token().map(|t| t.secret().clone()),
         expires_at,
+        account: signed_in,
+        signed_in_at: Some(chrono::Utc::now().timestamp()),
     };
 
-    let config_dir = get_config_dir()?;
-    let token_path = config_dir.join(DRIVE_TOKEN_FILE);
-    save_token(&token_path, &token_cache)?;
+    save_token(token_path, &token_cache)?;
 
     Ok((token_cache.access_token, auth_url))
 }
 
+/// The account the saved Drive token was issued to, for showing who documents are stored as
+pub fn signed_in_as(profile: Option<&str>) -> Option<String> {
+    cached_account(&get_token_dir(profile).ok()?.join(DRIVE_TOKEN_FILE))
+}
+
+/// Revoke the Drive grant with Google; false when none was cached
+pub async fn revoke_drive_token() -> Result<bool> {
+    revoke_cached_token(&get_config_dir()?.join(DRIVE_TOKEN_FILE)).await
+}
+
 /// Clear Drive token (force re-authorization)
 pub fn clear_drive_token() -> Result<()> {
     let config_dir = get_config_dir()?;
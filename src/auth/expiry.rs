@@ -0,0 +1,137 @@
+//! Warnings about saved sign-ins that won't last until the next scheduled run. Refresh tokens of
+//! an OAuth app whose publishing status is Testing stop working 7 days after signing in
+//! (OAUTH_REFRESH_TOKEN_DAYS), and a revoked one only shows when Google refuses a refresh; either
+//! way the scheduled run would fail long after anyone could have fixed it. `auth check`, and
+//! `scheduled` on the days it doesn't run, look ahead and send `auth_expiring` notifications.
+
+use crate::auth::failure::{self, AuthErrorKind, AuthFailure, Service};
+use crate::auth::keepalive;
+use crate::auth::oauth::{self, TokenCache};
+use crate::config::env::Config;
+use crate::notify::{self, Notification};
+use crate::scheduler::runner;
+use anyhow::Result;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+
+/// Without a scheduled run to look ahead to, sign-ins are warned about this long before they expire
+const WARN_AHEAD_DAYS: i64 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpiryWarning {
+    pub service: Service,
+    pub message: String,
+}
+
+/// When the refresh token stops working: OAUTH_REFRESH_TOKEN_DAYS after signing in, if both are known
+pub fn refresh_expires_at(token: &TokenCache, lifetime_days: u32) -> Option<DateTime<Utc>> {
+    if lifetime_days == 0 {
+        return None;
+    }
+    DateTime::from_timestamp(token.signed_in_at?, 0).map(|at| at + Duration::days(lifetime_days.into()))
+}
+
+/// What to warn about a saved sign-in at `now`, if anything: a refresh Google refused since
+/// signing in, or a refresh token expiring before `next_run`. An expiry is only raised once the
+/// run is close enough that signing in again carries the token past it.
+pub fn warning(token: &TokenCache, failure: Option<&AuthFailure>, lifetime_days: u32, next_run: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Option<String> {
+    let run = next_run.map_or_else(|| "the next run".to_string(), |run| format!("the run on {}", run.format("%b %-d")));
+    if let Some(failure) = failure
+        && failure.kind == AuthErrorKind::Revoked
+        && token.signed_in_at.is_none_or(|at| failure.at.timestamp() > at)
+    {
+        return Some(format!(
+            "Google refused to refresh the sign-in on {}; re-authenticate before {} ({})",
+            failure.at.format("%b %-d %H:%M UTC"),
+            run,
+            failure.message
+        ));
+    }
+
+    let expires_at = refresh_expires_at(token, lifetime_days)?;
+    let lifetime = Duration::days(lifetime_days.into());
+    let due = match next_run {
+        _ if expires_at <= now => true,
+        Some(next_run) => expires_at <= next_run && next_run - now <= lifetime,
+        None => expires_at - now <= Duration::days(WARN_AHEAD_DAYS),
+    };
+    let verb = if expires_at <= now { "expired" } else { "expires" };
+    due.then(|| {
+        format!(
+            "The sign-in {} on {} (refresh tokens last {} days after signing in); re-authenticate before {}",
+            verb,
+            expires_at.format("%b %-d %H:%M UTC"),
+            lifetime_days,
+            run
+        )
+    })
+}
+
+/// The next scheduled run (FETCH_INVOICES_DAY at SCHEDULE_TIME, local time), if there is a schedule
+pub fn next_scheduled_run(config: &Config, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let schedule = config.fetch_invoices_day?;
+    let next = runner::next_run(&schedule, &config.holidays, config.schedule_time, now.with_timezone(&chrono::Local).naive_local())?;
+    chrono::Local.from_local_datetime(&next).earliest().map(|at| at.with_timezone(&Utc))
+}
+
+/// Warnings for the Google sign-ins `config` uses; services not signed in yet are left to the
+/// run's own checks
+pub fn check(config: &Config, now: DateTime<Utc>) -> Result<Vec<ExpiryWarning>> {
+    let failures = failure::load(config.profile.as_deref())?;
+    let next_run = next_scheduled_run(config, now);
+    let mut warnings = Vec::new();
+    for kept in keepalive::kept_tokens(config)? {
+        let Some(service) = kept.tracked else {
+            continue;
+        };
+        let Ok(token) = oauth::load_token(&kept.path) else {
+            continue;
+        };
+        if let Some(message) = warning(&token, failures.get(service.key()), config.oauth_refresh_token_days, next_run, now) {
+            warnings.push(ExpiryWarning { service, message });
+        }
+    }
+    Ok(warnings)
+}
+
+/// Send each warning to the notification sinks subscribed to `auth_expiring`
+pub async fn alert(config: &Config, warnings: &[ExpiryWarning]) {
+    for warning in warnings {
+        let notification = Notification::auth_expiring(config, warning.service.label(), &warning.message, warning.service.reauth_command());
+        notify::send(config, &notification, None).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warns_before_the_next_run() {
+        let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2025, 2, day, hour, 0, 0).unwrap();
+        let token = TokenCache {
+            access_token: "a".to_string(),
+            refresh_token: Some("r".to_string()),
+            expires_at: None,
+            account: None,
+            signed_in_at: Some(at(10, 9).timestamp()),
+        };
+        let run = Some(at(26, 9));
+
+        // Signed in on the 10th, expiring on the 17th: too early to help the run on the 26th at first
+        assert_eq!(warning(&token, None, 7, run, at(12, 9)), None);
+        assert!(warning(&token, None, 7, run, at(19, 9)).unwrap().contains("expired on Feb 17 09:00 UTC"));
+        let soon = warning(&token, None, 7, Some(at(18, 9)), at(12, 9)).unwrap();
+        assert!(soon.contains("expires on Feb 17") && soon.ends_with("before the run on Feb 18"), "{}", soon);
+        assert_eq!(warning(&token, None, 7, None, at(14, 9)), None);
+        assert!(warning(&token, None, 7, None, at(15, 10)).unwrap().ends_with("before the next run"));
+        assert_eq!(warning(&token, None, 0, run, at(19, 9)), None);
+        assert_eq!(warning(&TokenCache { signed_in_at: None, ..token.clone() }, None, 7, run, at(19, 9)), None);
+
+        // A refused refresh counts until the next sign-in, whatever the lifetime
+        let refused = |day| AuthFailure { kind: AuthErrorKind::Revoked, message: "invalid_grant".to_string(), at: at(day, 9) };
+        assert!(warning(&token, Some(&refused(11)), 0, run, at(12, 9)).unwrap().starts_with("Google refused to refresh the sign-in on Feb 11"));
+        assert_eq!(warning(&token, Some(&refused(9)), 0, run, at(12, 9)), None);
+        let offline = AuthFailure { kind: AuthErrorKind::Network, ..refused(11) };
+        assert_eq!(warning(&token, Some(&offline), 0, run, at(12, 9)), None);
+    }
+}
@@ -0,0 +1,193 @@
+//! Why signing in to a service last failed. Auth errors are sorted into the causes that need
+//! different fixes (a revoked token, missing consent, the network, a misconfigured OAuth client)
+//! and the latest one per service is kept in `auth_errors.json`, so the TUI auth panel and
+//! `auth status` can say what to do about it after the run that hit it is gone.
+
+use crate::auth::oauth::get_token_dir;
+use crate::config::shared;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const AUTH_ERRORS_FILE: &str = "auth_errors.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthErrorKind {
+    /// The saved token was revoked or expired for good (`invalid_grant`)
+    Revoked,
+    /// Access wasn't granted, or granted with fewer permissions than needed
+    ConsentRequired,
+    /// "Deny" on the consent screen
+    Declined,
+    /// Google couldn't be reached
+    Network,
+    /// Google rejected the OAuth client (client ID/secret, redirect URI)
+    MisconfiguredClient,
+    /// The browser never came back from the sign-in page
+    TimedOut,
+    /// Signed in as another Google account than GOOGLE_GMAIL_ACCOUNT / GOOGLE_DRIVE_ACCOUNT
+    WrongAccount,
+    Other,
+}
+
+impl AuthErrorKind {
+    /// Sort an error message (as formatted with `{:#}`, so with its causes) into a kind
+    pub fn classify(message: &str) -> Self {
+        let message = message.to_lowercase();
+        let has = |needles: &[&str]| needles.iter().any(|needle| message.contains(needle));
+        if has(&["auth timed out"]) {
+            AuthErrorKind::TimedOut
+        } else if has(&["wrong google account"]) {
+            AuthErrorKind::WrongAccount
+        } else if has(&["access was declined", "access_denied"]) {
+            AuthErrorKind::Declined
+        } else if has(&["invalid_client", "unauthorized_client", "redirect_uri_mismatch", "invalid_request", "client_id"]) {
+            AuthErrorKind::MisconfiguredClient
+        } else if has(&["invalid_grant", "rejected by google", "revoked"]) {
+            AuthErrorKind::Revoked
+        } else if has(&["consent_required", "interaction_required", "doesn't grant", "insufficient"]) {
+            AuthErrorKind::ConsentRequired
+        } else if has(&["error sending request", "dns error", "connection refused", "network", "failed to reach", "timed out"]) {
+            AuthErrorKind::Network
+        } else {
+            AuthErrorKind::Other
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AuthErrorKind::Revoked => "Token revoked",
+            AuthErrorKind::ConsentRequired => "Consent required",
+            AuthErrorKind::Declined => "Access declined",
+            AuthErrorKind::Network => "Network",
+            AuthErrorKind::MisconfiguredClient => "Misconfigured client",
+            AuthErrorKind::TimedOut => "Timed out",
+            AuthErrorKind::WrongAccount => "Wrong account",
+            AuthErrorKind::Other => "Error",
+        }
+    }
+
+    /// What to do about it; `reauth` says how to sign in again (`re-run invoice-pilot auth gmail`)
+    pub fn remedy(self, reauth: &str) -> String {
+        match self {
+            AuthErrorKind::Revoked => format!("The saved sign-in was revoked or has expired; {} to sign in again", reauth),
+            AuthErrorKind::ConsentRequired => format!("Not every permission was granted; {} and allow everything requested", reauth),
+            AuthErrorKind::Declined => format!("Access was declined on Google's consent screen; {} and choose Allow", reauth),
+            AuthErrorKind::Network => "Google couldn't be reached; check the connection (proxy, DNS, firewall) and try again".to_string(),
+            AuthErrorKind::MisconfiguredClient => {
+                "Google rejected the OAuth client; check the client ID and secret in .env and that http://localhost:8080 is an authorized redirect URI".to_string()
+            }
+            AuthErrorKind::TimedOut => format!("The browser never came back from the sign-in page; {} (add --paste-code over SSH)", reauth),
+            AuthErrorKind::WrongAccount => format!("Another Google account than the configured one signed in; {} and choose the configured account", reauth),
+            AuthErrorKind::Other => format!("{} to try again", reauth),
+        }
+    }
+}
+
+/// A service's latest auth failure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthFailure {
+    pub kind: AuthErrorKind,
+    pub message: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Service {
+    Gmail,
+    Drive,
+}
+
+impl Service {
+    pub fn key(self) -> &'static str {
+        match self {
+            Service::Gmail => "gmail",
+            Service::Drive => "drive",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Service::Gmail => "Gmail",
+            Service::Drive => "Google Drive",
+        }
+    }
+
+    /// The command that signs in to the service again
+    pub fn reauth_command(self) -> &'static str {
+        match self {
+            Service::Gmail => "invoice-pilot auth gmail",
+            Service::Drive => "invoice-pilot auth drive",
+        }
+    }
+}
+
+/// The latest failure of each service, by `Service::key`
+pub fn load(profile: Option<&str>) -> Result<BTreeMap<String, AuthFailure>> {
+    let path = get_token_dir(profile)?.join(AUTH_ERRORS_FILE);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+/// Change the saved failures under the state lock, so machines sharing the config directory
+/// don't drop each other's
+fn update(profile: Option<&str>, change: impl FnOnce(&mut BTreeMap<String, AuthFailure>)) -> Result<()> {
+    shared::update_json(&get_token_dir(profile)?.join(AUTH_ERRORS_FILE), change)
+}
+
+/// Remember `error` as the latest failure of `service`
+pub fn record(profile: Option<&str>, service: Service, error: &anyhow::Error) {
+    let message = format!("{:#}", error);
+    let failure = AuthFailure { kind: AuthErrorKind::classify(&message), message, at: Utc::now() };
+    let saved = update(profile, |failures| {
+        failures.insert(service.key().to_string(), failure);
+    });
+    if let Err(e) = saved {
+        log::warn!("Failed to save the auth error: {:#}", e);
+    }
+}
+
+/// Forget the failure of a service that signed in fine
+pub fn clear(profile: Option<&str>, service: Service) {
+    if load(profile).is_ok_and(|failures| !failures.contains_key(service.key())) {
+        return;
+    }
+    let cleared = update(profile, |failures| {
+        failures.remove(service.key());
+    });
+    if let Err(e) = cleared {
+        log::warn!("Failed to clear the auth error: {:#}", e);
+    }
+}
+
+/// Record or clear the failure of `service` by how signing in to it went
+pub fn track<T>(profile: Option<&str>, service: Service, result: Result<T>) -> Result<T> {
+    match &result {
+        Ok(_) => clear(profile, service),
+        Err(e) => record(profile, service, e),
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let kind = AuthErrorKind::classify;
+        assert_eq!(kind("Failed to refresh token: Google answered invalid_grant: Token has been expired or revoked."), AuthErrorKind::Revoked);
+        assert_eq!(kind("The Gmail token doesn't grant gmail.readonly (granted: none); re-run `invoice-pilot auth gmail`"), AuthErrorKind::ConsentRequired);
+        assert_eq!(kind("Access was declined in the browser; start the sign-in again to grant it"), AuthErrorKind::Declined);
+        assert_eq!(kind("Failed to refresh token: Request failed: error sending request for url (https://oauth2.googleapis.com/token)"), AuthErrorKind::Network);
+        assert_eq!(kind("Failed to exchange authorization code for token: Google answered invalid_client: Unauthorized"), AuthErrorKind::MisconfiguredClient);
+        assert_eq!(kind("Auth timed out: no response from the browser within 5 minutes"), AuthErrorKind::TimedOut);
+        assert_eq!(kind("Signed in to Google Drive with the wrong Google account: me@gmail.com instead of billing@acme.com (GOOGLE_DRIVE_ACCOUNT)"), AuthErrorKind::WrongAccount);
+        assert_eq!(kind("CSRF token mismatch"), AuthErrorKind::Other);
+        assert!(AuthErrorKind::Revoked.remedy("press G").contains("press G to sign in again"));
+    }
+}
@@ -2,23 +2,82 @@ use anyhow::Result;
 use log::info;
 use oauth2::TokenResponse;
 use super::oauth::{
-    TokenCache, create_oauth_client, get_config_dir, load_token, save_token,
-    perform_oauth_flow, refresh_token,
+    TokenCache, cached_account, check_account, create_oauth_client, get_config_dir, get_token_dir, load_token, save_token,
+    Paste, perform_oauth_flow, refresh_token, revoke_cached_token,
 };
+use serde::Deserialize;
 use std::fs;
+use std::path::{Path, PathBuf};
 
-const GMAIL_SCOPE: &str = "https://www.googleapis.com/auth/gmail.readonly";
+pub const GMAIL_SCOPE: &str = "https://www.googleapis.com/auth/gmail.readonly";
 const GMAIL_TOKEN_FILE: &str = "gmail_token.json";
 
-/// Get or refresh Gmail access token
-pub async fn get_gmail_token(client_id: String, client_secret: String) -> Result<String> {
-    let config_dir = get_config_dir()?;
-    let token_path = config_dir.join(GMAIL_TOKEN_FILE);
+// Sending (e.g. the accountant package) uses its own token so read-only users never grant send access
+const GMAIL_SEND_SCOPE: &str = "https://www.googleapis.com/auth/gmail.send";
+const GMAIL_SEND_TOKEN_FILE: &str = "gmail_send_token.json";
+
+/// Get or refresh the Gmail access token stored for a profile (None = default profile)
+pub async fn get_gmail_token_for_profile(client_id: String, client_secret: String, account: Option<&str>, profile: Option<&str>, paste_code: bool) -> Result<String> {
+    let token_path = get_token_dir(profile)?.join(GMAIL_TOKEN_FILE);
+    get_cached_or_authorize(client_id, client_secret, &token_path, GMAIL_SCOPE, account, Paste::terminal(paste_code)).await
+}
+
+/// Get a read-only Gmail token for a Workspace user through a service account with
+/// domain-wide delegation (no OAuth flow, nothing cached)
+pub async fn get_delegated_gmail_token(service_account_key: &Path, user: &str) -> Result<String> {
+    let key = super::service_account::ServiceAccountKey::load(service_account_key)?;
+    key.access_token(Some(user), GMAIL_SCOPE).await
+}
+
+/// Get or refresh the Gmail access token with send permission (gmail.send scope)
+pub async fn get_gmail_send_token(client_id: String, client_secret: String, account: Option<&str>, profile: Option<&str>, paste_code: bool) -> Result<String> {
+    let token_path = get_token_dir(profile)?.join(GMAIL_SEND_TOKEN_FILE);
+    get_cached_or_authorize(client_id, client_secret, &token_path, GMAIL_SEND_SCOPE, account, Paste::terminal(paste_code)).await
+}
+
+/// Email of the account a Gmail token belongs to. Only read scopes may ask, so a send-only
+/// token gives None.
+async fn signed_in_account(access_token: &str) -> Option<String> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Profile {
+        email_address: String,
+    }
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/users/me/profile", crate::gmail::client::GMAIL_API_BASE))
+        .bearer_auth(access_token)
+        .send()
+        .await
+        .ok()?;
+    let profile: Profile = response.error_for_status().ok()?.json().await.ok()?;
+    Some(profile.email_address)
+}
+
+/// Fill in the account of a token saved before accounts were recorded, while it's still valid
+async fn remember_account(token_path: &Path, mut token: TokenCache) -> TokenCache {
+    if token.account.is_none()
+        && !token.is_expired()
+        && let Some(account) = signed_in_account(&token.access_token).await
+    {
+        token.account = Some(account);
+        if let Err(e) = save_token(token_path, &token) {
+            log::warn!("Failed to record the Gmail account: {:#}", e);
+        }
+    }
+    token
+}
 
-    // Try to load existing token
+/// Use the cached token at `token_path`, refreshing it or running the OAuth flow as needed
+async fn get_cached_or_authorize(client_id: String, client_secret: String, token_path: &PathBuf, scope: &str, account: Option<&str>, paste: Option<Paste>) -> Result<String> {
+    // Try to load existing token, unless it was issued to another account than the configured one
     if token_path.exists() {
         info!("Loading cached Gmail token...");
-        if let Ok(token_cache) = load_token(&token_path) {
+        let cached = match load_token(token_path) {
+            Ok(token) if scope == GMAIL_SCOPE => Some(remember_account(token_path, token).await),
+            token => token.ok(),
+        };
+        if let Some(token_cache) = cached.filter(|token| token.belongs_to(account)) {
             if !token_cache.is_expired() {
                 info!("Using cached Gmail token");
                 return Ok(token_cache.access_token);
@@ -37,9 +96,11 @@ pub async fn get_gmail_token(client_id: String, client_secret: String) -> Result
                         access_token: new_token.access_token().secret().clone(),
                         refresh_token: new_token.refresh_token().map(|t| t.secret().clone()).or(Some(refresh)),
                         expires_at,
+                        account: token_cache.account,
+                        signed_in_at: token_cache.signed_in_at,
                     };
 
-                    save_token(&token_path, &token_cache)?;
+                    save_token(token_path, &token_cache)?;
                     return Ok(token_cache.access_token);
                 }
             }
@@ -47,12 +108,12 @@ pub async fn get_gmail_token(client_id: String, client_secret: String) -> Result
     }
 
     // Need new authorization
-    let (token, _) = authorize_gmail(client_id, client_secret, None).await?;
+    let (token, _) = authorize_gmail(client_id, client_secret, None, token_path, scope, account, paste).await?;
     Ok(token)
 }
 
 /// Get or refresh Gmail access token with URL callback for TUI
-pub async fn get_gmail_token_with_url(client_id: String, client_secret: String, tx: tokio::sync::mpsc::UnboundedSender<String>) -> Result<String> {
+pub async fn get_gmail_token_with_url(client_id: String, client_secret: String, account: Option<&str>, tx: tokio::sync::mpsc::UnboundedSender<String>, pasted: Option<tokio::sync::mpsc::UnboundedReceiver<String>>) -> Result<String> {
     let config_dir = get_config_dir()?;
     let token_path = config_dir.join(GMAIL_TOKEN_FILE);
 
@@ -60,7 +121,11 @@ pub async fn get_gmail_token_with_url(client_id: String, client_secret: String,
     // But try to use cached tokens if available and valid
     if token_path.exists() {
         info!("Loading cached Gmail token...");
-        if let Ok(token_cache) = load_token(&token_path) {
+        let cached = match load_token(&token_path) {
+            Ok(token) => Some(remember_account(&token_path, token).await),
+            Err(_) => None,
+        };
+        if let Some(token_cache) = cached.filter(|token| token.belongs_to(account)) {
             if !token_cache.is_expired() {
                 info!("Using cached Gmail token");
                 // For TUI, send cached success message (popup stays open)
@@ -81,6 +146,8 @@ pub async fn get_gmail_token_with_url(client_id: String, client_secret: String,
                         access_token: new_token.access_token().secret().clone(),
                         refresh_token: new_token.refresh_token().map(|t| t.secret().clone()).or(Some(refresh)),
                         expires_at,
+                        account: token_cache.account,
+                        signed_in_at: token_cache.signed_in_at,
                     };
 
                     save_token(&token_path, &token_cache)?;
@@ -93,34 +160,60 @@ pub async fn get_gmail_token_with_url(client_id: String, client_secret: String,
     }
 
     // Need new authorization - URL will be sent via channel from perform_oauth_flow
-    let (token, _auth_url) = authorize_gmail(client_id, client_secret, Some(tx)).await?;
+    let (token, _auth_url) = authorize_gmail(client_id, client_secret, Some(tx), &token_path, GMAIL_SCOPE, account, pasted.map(Paste::Tui)).await?;
     Ok(token)
 }
 
 /// Perform full Gmail authorization flow
-async fn authorize_gmail(client_id: String, client_secret: String, tx: Option<tokio::sync::mpsc::UnboundedSender<String>>) -> Result<(String, String)> {
+async fn authorize_gmail(client_id: String, client_secret: String, tx: Option<tokio::sync::mpsc::UnboundedSender<String>>, token_path: &Path, scope: &str, account: Option<&str>, paste: Option<Paste>) -> Result<(String, String)> {
     let client = create_oauth_client(client_id, client_secret)?;
-    let scopes = vec![GMAIL_SCOPE.to_string()];
+    let scopes = vec![scope.to_string()];
 
     let sender_with_prefix = tx.map(|sender| (sender, "GMAIL_"));
-    let (token, auth_url) = perform_oauth_flow(&client, scopes, sender_with_prefix).await?;
+    let (token, auth_url) = perform_oauth_flow(&client, scopes, sender_with_prefix, account, paste).await?;
 
     let expires_at = token.expires_in()
         .map(|d| chrono::Utc::now().timestamp() + d.as_secs() as i64);
 
+    let signed_in = signed_in_account(token.access_token().secret()).await;
+    check_account("Gmail", "GOOGLE_GMAIL_ACCOUNT", signed_in.as_deref(), account)?;
+
     let token_cache = TokenCache {
         access_token: token.access_token().secret().clone(),
         refresh_token: token.refresh_token().map(|t| t.secret().clone()),
         expires_at,
+        account: signed_in,
+        signed_in_at: Some(chrono::Utc::now().timestamp()),
     };
 
-    let config_dir = get_config_dir()?;
-    let token_path = config_dir.join(GMAIL_TOKEN_FILE);
-    save_token(&token_path, &token_cache)?;
+    save_token(token_path, &token_cache)?;
 
     Ok((token_cache.access_token, auth_url))
 }
 
+/// The account the saved Gmail token was issued to, for showing who mail is read as
+pub fn signed_in_as(profile: Option<&str>) -> Option<String> {
+    cached_account(&get_token_dir(profile).ok()?.join(GMAIL_TOKEN_FILE))
+}
+
+/// Revoke the Gmail grants (reading and sending) with Google; false when none was cached. Both
+/// are tried, and the errors of those that failed are reported together.
+pub async fn revoke_gmail_token() -> Result<bool> {
+    let config_dir = get_config_dir()?;
+    let mut revoked = false;
+    let mut errors = Vec::new();
+    for (grant, file) in [("reading", GMAIL_TOKEN_FILE), ("sending", GMAIL_SEND_TOKEN_FILE)] {
+        match revoke_cached_token(&config_dir.join(file)).await {
+            Ok(was_revoked) => revoked |= was_revoked,
+            Err(e) => errors.push(format!("{}: {:#}", grant, e)),
+        }
+    }
+    if !errors.is_empty() {
+        anyhow::bail!("{}", errors.join("; "));
+    }
+    Ok(revoked)
+}
+
 /// Clear Gmail token (force re-authorization)
 pub fn clear_gmail_token() -> Result<()> {
     let config_dir = get_config_dir()?;
@@ -133,5 +226,11 @@ pub fn clear_gmail_token() -> Result<()> {
         info!("No Gmail token to clear");
     }
 
+    let send_token_path = config_dir.join(GMAIL_SEND_TOKEN_FILE);
+    if send_token_path.exists() {
+        fs::remove_file(&send_token_path)?;
+        info!("Gmail send token cleared");
+    }
+
     Ok(())
 }
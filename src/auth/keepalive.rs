@@ -0,0 +1,184 @@
+//! Background token refresh for long-lived sessions (the TUI, `serve`, `grpc` and `mcp`). Cached
+//! OAuth tokens are refreshed shortly before they expire, so the first run after the app has sat
+//! open overnight doesn't start with a refresh round-trip.
+
+use crate::auth::failure::{self, Service};
+use crate::auth::oauth::{self, TokenCache};
+use crate::config::env::{Config, MailSourceKind, StorageKind};
+use crate::notify::{self, Notification};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use oauth2::TokenResponse;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Tokens expiring within this many seconds are refreshed
+const REFRESH_AHEAD: i64 = 10 * 60;
+
+/// How often the cached tokens are looked at
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A cached token the session keeps fresh
+#[derive(Debug, Clone)]
+pub struct KeptToken {
+    /// Service prefix of the TUI markers (`GMAIL` for `__GMAIL_AUTH_ERROR__`)
+    pub service: &'static str,
+    pub label: &'static str,
+    pub path: PathBuf,
+    /// Service whose failures are kept for `auth status`
+    pub tracked: Option<Service>,
+    client_id: String,
+    client_secret: String,
+}
+
+/// The OAuth tokens `config` signs in with. Service account tokens are minted per run and have
+/// nothing to keep fresh.
+pub fn kept_tokens(config: &Config) -> Result<Vec<KeptToken>> {
+    let dir = oauth::get_token_dir(config.profile.as_deref())?;
+    let kept = |service, label, file: &str, client_id: &String, client_secret: &String| KeptToken {
+        service,
+        label,
+        path: dir.join(file),
+        tracked: match service {
+            "GMAIL" => Some(Service::Gmail),
+            "DRIVE" => Some(Service::Drive),
+            _ => None,
+        },
+        client_id: client_id.clone(),
+        client_secret: client_secret.clone(),
+    };
+
+    let mut tokens = Vec::new();
+    let delegated = config.google_service_account_key.is_some() && config.gmail_user.is_some();
+    if config.mail_source == MailSourceKind::Gmail && !delegated {
+        tokens.push(kept("GMAIL", "Gmail", "gmail_token.json", &config.gmail_client_id, &config.gmail_client_secret));
+    }
+    tokens.push(kept("DRIVE", "Google Drive", "drive_token.json", &config.drive_client_id, &config.drive_client_secret));
+    let uses_gcs = config.storage_backend == StorageKind::Gcs || config.storage_mirror == Some(StorageKind::Gcs);
+    if uses_gcs && config.gcs.as_ref().is_some_and(|gcs| gcs.service_account_key.is_none()) {
+        tokens.push(kept("GCS", "Cloud Storage", "gcs_token.json", &config.drive_client_id, &config.drive_client_secret));
+    }
+    Ok(tokens)
+}
+
+/// Whether `token` should be refreshed at `now` (Unix seconds)
+fn due(token: &TokenCache, now: i64) -> bool {
+    token.refresh_token.is_some() && token.expires_at.is_some_and(|expires_at| expires_at - now <= REFRESH_AHEAD)
+}
+
+/// Refresh the token if it's about to expire; false when it didn't need to be (or isn't cached)
+async fn refresh(kept: &KeptToken) -> Result<bool> {
+    if !kept.path.exists() {
+        return Ok(false);
+    }
+    let cached = oauth::load_token(&kept.path)?;
+    if !due(&cached, chrono::Utc::now().timestamp()) {
+        return Ok(false);
+    }
+
+    let refresh = cached.refresh_token.clone().context("no refresh token")?;
+    let client = oauth::create_oauth_client(kept.client_id.clone(), kept.client_secret.clone())?;
+    let new_token = oauth::refresh_token(&client, &refresh).await?;
+    let token_cache = TokenCache {
+        access_token: new_token.access_token().secret().clone(),
+        refresh_token: new_token.refresh_token().map(|t| t.secret().clone()).or(Some(refresh)),
+        expires_at: new_token.expires_in().map(|d| chrono::Utc::now().timestamp() + d.as_secs() as i64),
+        account: cached.account,
+        signed_in_at: cached.signed_in_at,
+    };
+    oauth::save_token(&kept.path, &token_cache)?;
+    Ok(true)
+}
+
+/// Keep `tokens` fresh until the session ends. With `tx` (the TUI), refreshes are reported as
+/// `__<SERVICE>_AUTH_KEPT_FRESH__` and a token that expired because its refresh keeps failing as
+/// `__<SERVICE>_AUTH_ERROR__:<error>`; a failure is reported once until the token refreshes again,
+/// and sent to the notification sinks subscribed to `auth_expiring`.
+pub fn spawn(tokens: Vec<KeptToken>, config: Config, tx: Option<mpsc::UnboundedSender<String>>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let profile = config.profile.clone();
+        let mut failing: HashSet<&'static str> = HashSet::new();
+        loop {
+            for kept in &tokens {
+                match refresh(kept).await {
+                    Ok(refreshed) => {
+                        failing.remove(kept.service);
+                        if refreshed {
+                            if let Some(service) = kept.tracked {
+                                failure::clear(profile.as_deref(), service);
+                            }
+                            info!("{} token refreshed ahead of expiry", kept.label);
+                            if let Some(tx) = &tx {
+                                let _ = tx.send(format!("__{}_AUTH_KEPT_FRESH__", kept.service));
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        if !failing.insert(kept.service) {
+                            continue;
+                        }
+                        warn!("{} token refresh failed: {:#}", kept.label, e);
+                        if let Some(service) = kept.tracked {
+                            failure::record(profile.as_deref(), service, &e);
+                        }
+                        let problem = format!("Refreshing the {} token failed: {:#}", kept.label, e);
+                        let reauth = kept.tracked.unwrap_or(Service::Drive).reauth_command();
+                        notify::send(&config, &Notification::auth_expiring(&config, kept.label, &problem, reauth), tx.as_ref()).await;
+                        let expired = oauth::load_token(&kept.path).is_ok_and(|cached| cached.is_expired());
+                        if let Some(tx) = &tx {
+                            let _ = tx.send(if expired {
+                                format!("__{}_AUTH_ERROR__:{:#}", kept.service, e)
+                            } else {
+                                format!("{} token refresh failed, retrying: {:#}", kept.label, e)
+                            });
+                        }
+                    }
+                }
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    })
+}
+
+/// Keep the tokens of `config` fresh in a daemon (`serve`, `grpc`, `mcp`); nothing in mock mode
+pub fn spawn_for(config: &Config) -> Option<JoinHandle<()>> {
+    if config.mock_mode {
+        return None;
+    }
+    match kept_tokens(config) {
+        Ok(tokens) => Some(spawn(tokens, config.clone(), None)),
+        Err(e) => {
+            warn!("{:#}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_due() {
+        let now = 1_700_000_000;
+        let token = |expires_in: Option<i64>, refresh: bool| TokenCache {
+            access_token: "access".to_string(),
+            refresh_token: refresh.then(|| "refresh".to_string()),
+            expires_at: expires_in.map(|seconds| now + seconds),
+            account: None,
+            signed_in_at: None,
+        };
+        assert!(due(&token(Some(5 * 60), true), now));
+        assert!(due(&token(Some(-60), true), now));
+        assert!(!due(&token(Some(50 * 60), true), now));
+        assert!(!due(&token(Some(5 * 60), false), now));
+        assert!(!due(&token(None, true), now));
+
+        let config = Config::for_test(&[]);
+        let services: Vec<&str> = kept_tokens(&config).unwrap().iter().map(|kept| kept.service).collect();
+        assert!(services.contains(&"DRIVE"));
+    }
+}
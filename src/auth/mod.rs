@@ -1,3 +1,11 @@
 pub mod oauth;
+pub mod callback_page;
+pub mod client_secret;
 pub mod gmail_auth;
+pub mod keepalive;
 pub mod drive_auth;
+pub mod expiry;
+pub mod failure;
+pub mod preflight;
+pub mod service_account;
+pub mod transfer;
@@ -1,30 +1,80 @@
+use crate::auth::callback_page::{self, CallbackPage};
+use crate::config::env::Config;
 use anyhow::{Context, Result};
 use log::{info, warn};
 use oauth2::{
     AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge,
-    RedirectUrl, Scope, TokenUrl,
+    RedirectUrl, RequestTokenError, Scope, TokenUrl,
 };
 use oauth2::basic::{BasicClient, BasicTokenType};
 use oauth2::reqwest::async_http_client;
 use oauth2::StandardTokenResponse;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::io::{BufRead, BufReader, Write};
-use std::net::TcpListener;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
 
 const GOOGLE_AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
 const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const GOOGLE_REVOKE_URL: &str = "https://oauth2.googleapis.com/revoke";
 const REDIRECT_URI: &str = "http://localhost:8080";
 
+/// How long the callback server waits for the browser before giving up on the flow
+pub const CALLBACK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Where a sign-in completed from a pasted redirect address instead of the localhost callback
+/// (`--paste-code`, OAUTH_PASTE_CODE) reads the address
+pub enum Paste {
+    Terminal,
+    /// The TUI's popup, which sends what was pasted here
+    Tui(mpsc::UnboundedReceiver<String>),
+}
+
+impl Paste {
+    /// Pasting on the terminal when `paste_code` is set, the localhost callback otherwise
+    pub fn terminal(paste_code: bool) -> Option<Self> {
+        paste_code.then_some(Self::Terminal)
+    }
+}
+
+/// The browser never came back with an authorization code (the flow was abandoned)
+#[derive(Debug)]
+pub struct AuthTimedOut;
+
+impl std::fmt::Display for AuthTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Auth timed out: no response from the browser within {} minutes", CALLBACK_TIMEOUT.as_secs() / 60)
+    }
+}
+
+impl std::error::Error for AuthTimedOut {}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenCache {
     pub access_token: String,
     pub refresh_token: Option<String>,
     pub expires_at: Option<i64>,
+    /// Email of the Google account that signed in, when it could be looked up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// When the refresh token was granted (Unix seconds), for OAUTH_REFRESH_TOKEN_DAYS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_in_at: Option<i64>,
 }
 
 impl TokenCache {
+    /// Whether this token may be used where `expected` (GOOGLE_*_ACCOUNT) is configured; tokens
+    /// saved before the account was recorded are given the benefit of the doubt
+    pub fn belongs_to(&self, expected: Option<&str>) -> bool {
+        match (expected, &self.account) {
+            (Some(expected), Some(account)) => account.eq_ignore_ascii_case(expected),
+            _ => true,
+        }
+    }
+
     /// Check if token is expired or close to expiring (within 5 minutes)
     pub fn is_expired(&self) -> bool {
         if let Some(expires_at) = self.expires_at {
@@ -39,9 +89,7 @@ impl TokenCache {
 
 /// Get the config directory path for token storage
 pub fn get_config_dir() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir()
-        .context("Could not determine config directory")?
-        .join("invoice-agent");
+    let config_dir = config_dir_path().context("Could not determine config directory")?;
 
     fs::create_dir_all(&config_dir)
         .context("Failed to create config directory")?;
@@ -49,12 +97,31 @@ pub fn get_config_dir() -> Result<PathBuf> {
     Ok(config_dir)
 }
 
+/// Where the config directory is, without creating it
+pub fn config_dir_path() -> Option<PathBuf> {
+    crate::config::shared::config_dir_override().or_else(|| Some(dirs::config_dir()?.join("invoice-agent")))
+}
+
+/// Get the token directory for a profile (batch mode keeps each tenant's tokens apart)
+pub fn get_token_dir(profile: Option<&str>) -> Result<PathBuf> {
+    let config_dir = get_config_dir()?;
+    let Some(name) = profile else {
+        return Ok(config_dir);
+    };
+
+    let profile_dir = config_dir.join("profiles").join(name);
+    fs::create_dir_all(&profile_dir)
+        .context("Failed to create profile token directory")?;
+
+    Ok(profile_dir)
+}
+
 /// Save token to file
-pub fn save_token(token_path: &PathBuf, token: &TokenCache) -> Result<()> {
+pub fn save_token(token_path: &Path, token: &TokenCache) -> Result<()> {
     let json = serde_json::to_string_pretty(token)
         .context("Failed to serialize token")?;
 
-    fs::write(token_path, json)
+    crate::config::shared::update(token_path, |_| Ok(json))
         .context("Failed to write token file")?;
 
     info!("Token saved to {}", token_path.display());
@@ -72,6 +139,50 @@ pub fn load_token(token_path: &PathBuf) -> Result<TokenCache> {
     Ok(token)
 }
 
+/// The account recorded in the token cached at `token_path`
+pub fn cached_account(token_path: &PathBuf) -> Option<String> {
+    load_token(token_path).ok().and_then(|token| token.account)
+}
+
+/// Fail a sign-in made with another Google account than the one configured for the service,
+/// before its token is saved. Easy to do by accident when the browser is signed in to both.
+pub fn check_account(service: &str, key: &str, account: Option<&str>, expected: Option<&str>) -> Result<()> {
+    if let (Some(account), Some(expected)) = (account, expected)
+        && !account.eq_ignore_ascii_case(expected)
+    {
+        anyhow::bail!("Signed in to {} with the wrong Google account: {} instead of {} ({})", service, account, expected, key);
+    }
+    Ok(())
+}
+
+/// What looks off about the accounts the Gmail and Drive tokens belong to: a token of another
+/// account than the configured one, or both in one account although Drive has an OAuth client of
+/// its own, which usually means the browser picked the same account twice
+pub fn account_warnings(config: &Config, gmail: Option<&str>, drive: Option<&str>) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for (service, key, account, expected) in [
+        ("Gmail", "GOOGLE_GMAIL_ACCOUNT", gmail, config.gmail_account.as_deref()),
+        ("Google Drive", "GOOGLE_DRIVE_ACCOUNT", drive, config.drive_account.as_deref()),
+    ] {
+        if let (Some(account), Some(expected)) = (account, expected)
+            && !account.eq_ignore_ascii_case(expected)
+        {
+            warnings.push(format!("{} is signed in as {}, not {} ({}); the next run signs in again", service, account, expected, key));
+        }
+    }
+    if let (Some(gmail), Some(drive)) = (gmail, drive)
+        && gmail.eq_ignore_ascii_case(drive)
+        && config.drive_client_id != config.gmail_client_id
+        && config.drive_account.is_none()
+    {
+        warnings.push(format!(
+            "Gmail and Google Drive are both signed in as {} although Drive has its own OAuth client; to archive to another account, run `invoice-pilot auth drive` and choose it",
+            gmail
+        ));
+    }
+    warnings
+}
+
 /// Create OAuth2 client
 pub fn create_oauth_client(
     client_id: String,
@@ -93,6 +204,8 @@ pub async fn perform_oauth_flow(
     client: &BasicClient,
     scopes: Vec<String>,
     url_sender: Option<(tokio::sync::mpsc::UnboundedSender<String>, &str)>,
+    login_hint: Option<&str>,
+    paste: Option<Paste>,
 ) -> Result<(StandardTokenResponse<oauth2::EmptyExtraTokenFields, BasicTokenType>, String)> {
     let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
 
@@ -104,6 +217,10 @@ pub async fn perform_oauth_flow(
     for scope in scopes {
         auth_request = auth_request.add_scope(Scope::new(scope));
     }
+    // Preselects the configured account on Google's account chooser
+    if let Some(hint) = login_hint {
+        auth_request = auth_request.add_extra_param("login_hint", hint);
+    }
 
     let (auth_url, csrf_token) = auth_request.url();
 
@@ -120,7 +237,7 @@ pub async fn perform_oauth_flow(
     info!("Authorization URL ready: {}", auth_url_str);
 
     // Send URL and browser status to TUI if sender is provided
-    if let Some((sender, prefix)) = url_sender {
+    if let Some((sender, prefix)) = &url_sender {
         // Always send the URL first so user can manually open it
         let _ = sender.send(format!("__{}AUTH_URL__:{}", prefix, auth_url_str));
 
@@ -130,51 +247,22 @@ pub async fn perform_oauth_flow(
         }
     }
 
-    // Start local server to receive callback
-    let listener = TcpListener::bind("127.0.0.1:8080")
-        .context("Failed to bind to port 8080. Is another instance running?")?;
-
-    // Wait for connection
-    let (mut stream, _) = listener.accept()
-        .context("Failed to accept connection")?;
+    let code = if let Some(paste) = paste {
+        tokio::time::timeout(CALLBACK_TIMEOUT, wait_for_pasted(&auth_url_str, &csrf_token, paste, url_sender.as_ref()))
+            .await
+            .map_err(|_| AuthTimedOut)??
+    } else {
+        let page = CallbackPage::from_env()?;
 
-    // Read the HTTP request
-    let mut reader = BufReader::new(&stream);
-    let mut request_line = String::new();
-    reader.read_line(&mut request_line)?;
+        // Start local server to receive callback; dropping the flow (a cancelled task) closes it
+        let listener = TcpListener::bind("127.0.0.1:8080")
+            .await
+            .context("Failed to bind to port 8080. Is another instance running?")?;
 
-    // Extract code and state from request
-    let redirect_url = request_line
-        .split_whitespace()
-        .nth(1)
-        .context("Invalid HTTP request")?;
-
-    let url = url::Url::parse(&format!("http://localhost{}", redirect_url))?;
-
-    let code = url
-        .query_pairs()
-        .find(|(key, _)| key == "code")
-        .map(|(_, value)| AuthorizationCode::new(value.into_owned()))
-        .context("Authorization code not found in callback")?;
-
-    let state = url
-        .query_pairs()
-        .find(|(key, _)| key == "state")
-        .map(|(_, value)| CsrfToken::new(value.into_owned()))
-        .context("State not found in callback")?;
-
-    // Verify CSRF token
-    if state.secret() != csrf_token.secret() {
-        anyhow::bail!("CSRF token mismatch");
-    }
-
-    // Send success response to browser
-    let response = "HTTP/1.1 200 OK\r\n\r\n\
-        <html><body>\
-        <h1>✓ Authorization successful!</h1>\
-        <p>You can close this window and return to the terminal.</p>\
-        </body></html>";
-    stream.write_all(response.as_bytes())?;
+        tokio::time::timeout(CALLBACK_TIMEOUT, wait_for_callback(&listener, &csrf_token, &page))
+            .await
+            .map_err(|_| AuthTimedOut)??
+    };
 
     // Exchange code for token
     let token = client
@@ -182,11 +270,173 @@ pub async fn perform_oauth_flow(
         .set_pkce_verifier(pkce_verifier)
         .request_async(async_http_client)
         .await
+        .map_err(token_request_error)
         .context("Failed to exchange authorization code for token")?;
 
     Ok((token, auth_url_str))
 }
 
+/// Serve the redirect until it brings an authorization code, answering the browser with the
+/// callback page. Other requests (the browser asking for a favicon) are turned away and waited past.
+async fn wait_for_callback(listener: &TcpListener, csrf_token: &CsrfToken, page: &CallbackPage) -> Result<AuthorizationCode> {
+    loop {
+        let (mut stream, _) = listener.accept()
+            .await
+            .context("Failed to accept connection")?;
+
+        // Read the HTTP request
+        let mut request_line = String::new();
+        BufReader::new(&mut stream).read_line(&mut request_line).await?;
+
+        // Extract code and state from request
+        let redirect_url = request_line
+            .split_whitespace()
+            .nth(1)
+            .context("Invalid HTTP request")?;
+
+        let url = url::Url::parse(&format!("http://localhost{}", redirect_url))?;
+        let param = |name: &str| url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned());
+
+        // Google redirects with `error` when consent is declined ("Deny") or fails
+        if let Some(error) = param("error") {
+            let described = param("error_description").map_or(error.clone(), |description| format!("{}: {}", error, description));
+            if error == "access_denied" {
+                stream.write_all(page.failure(
+                    "Access was declined",
+                    "Invoice Pilot was not given access to your account. If that was a mistake, start the sign-in again from the terminal.",
+                    None,
+                ).as_bytes()).await?;
+                anyhow::bail!("Access was declined in the browser; start the sign-in again to grant it");
+            }
+            stream.write_all(page.failure("Authorization failed", "Google could not complete the sign-in.", Some(&described)).as_bytes()).await?;
+            anyhow::bail!("Authorization failed: {}", described);
+        }
+
+        let Some(code) = param("code").map(AuthorizationCode::new) else {
+            stream.write_all(callback_page::not_found().as_bytes()).await?;
+            continue;
+        };
+
+        // Verify CSRF token
+        if param("state").as_deref() != Some(csrf_token.secret().as_str()) {
+            stream.write_all(page.failure(
+                "This sign-in link is out of date",
+                "It doesn't belong to the sign-in waiting in the terminal (an older link, or one opened twice). Start the sign-in again from the terminal.",
+                None,
+            ).as_bytes()).await?;
+            anyhow::bail!("CSRF token mismatch");
+        }
+
+        stream.write_all(page.success().as_bytes()).await?;
+        return Ok(code);
+    }
+}
+
+/// The code in what the user pasted: the address the browser ended up on, or the bare code.
+/// None when there's no code in it, so the user can paste again.
+fn parse_pasted(text: &str, csrf_token: &CsrfToken) -> Result<Option<AuthorizationCode>> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    if !text.contains("://") {
+        // A bare code has no spaces or query syntax
+        let bare = !text.contains(char::is_whitespace) && !text.contains(['?', '&', '=']);
+        return Ok(bare.then(|| AuthorizationCode::new(text.to_string())));
+    }
+
+    let Ok(url) = url::Url::parse(text) else {
+        return Ok(None);
+    };
+    let param = |name: &str| url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned());
+    if let Some(error) = param("error") {
+        if error == "access_denied" {
+            anyhow::bail!("Access was declined in the browser; start the sign-in again to grant it");
+        }
+        anyhow::bail!("Authorization failed: {}", error);
+    }
+    let Some(code) = param("code") else {
+        return Ok(None);
+    };
+    if param("state").as_deref() != Some(csrf_token.secret().as_str()) {
+        anyhow::bail!("CSRF token mismatch: the pasted address belongs to another sign-in");
+    }
+    Ok(Some(AuthorizationCode::new(code)))
+}
+
+/// Wait for the user to paste the redirect address, in the TUI when it started the flow or on
+/// the terminal otherwise
+async fn wait_for_pasted(auth_url: &str, csrf_token: &CsrfToken, paste: Paste, tui: Option<&(mpsc::UnboundedSender<String>, &str)>) -> Result<AuthorizationCode> {
+    const NO_CODE: &str = "That doesn't contain an authorization code; paste the whole address from the browser's address bar";
+
+    if let (Paste::Tui(mut pasted), Some((sender, prefix))) = (paste, tui) {
+        let _ = sender.send(format!("__{}AUTH_PASTE__:", prefix));
+        while let Some(text) = pasted.recv().await {
+            if let Some(code) = parse_pasted(&text, csrf_token)? {
+                return Ok(code);
+            }
+            let _ = sender.send(format!("__{}AUTH_PASTE__:{}", prefix, NO_CODE));
+        }
+        anyhow::bail!("The sign-in was cancelled");
+    }
+
+    println!("Open this address in a browser on any machine:\n\n{}\n", auth_url);
+    println!("After you allow access, the browser is sent to {} and shows an error page.", REDIRECT_URI);
+    println!("Copy the whole address from its address bar and paste it here:");
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if let Some(code) = parse_pasted(&line, csrf_token)? {
+            return Ok(code);
+        }
+        println!("{}:", NO_CODE);
+    }
+    anyhow::bail!("No authorization code was pasted")
+}
+
+/// Withdraw the grant behind the token cached at `token_path`, so Google stops honouring it
+/// (deleting the file alone leaves the app authorized). False when there was nothing to revoke.
+pub async fn revoke_cached_token(token_path: &PathBuf) -> Result<bool> {
+    if !token_path.exists() {
+        return Ok(false);
+    }
+    let token = load_token(token_path)?;
+    revoke_at(GOOGLE_REVOKE_URL, token.refresh_token.as_deref().unwrap_or(&token.access_token)).await
+}
+
+/// Revoking the refresh token withdraws the whole grant, access tokens included. A token Google
+/// no longer knows was revoked already.
+async fn revoke_at(url: &str, token: &str) -> Result<bool> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .form(&[("token", token)])
+        .send()
+        .await
+        .context("Failed to reach Google's revoke endpoint")?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(true);
+    }
+    let error_text = response.text().await.unwrap_or_default();
+    if status == reqwest::StatusCode::BAD_REQUEST && error_text.contains("invalid_token") {
+        return Ok(false);
+    }
+    anyhow::bail!("Google revoke error ({}): {}", status, error_text)
+}
+
+/// Keep what Google answered (`invalid_grant: Token has been expired or revoked.`), which the
+/// token error alone only calls "Server returned error response"
+fn token_request_error<RE, T>(error: RequestTokenError<RE, T>) -> anyhow::Error
+where
+    RE: std::error::Error + Send + Sync + 'static,
+    T: oauth2::ErrorResponse + std::fmt::Display + Send + Sync + 'static,
+{
+    match error {
+        RequestTokenError::ServerResponse(response) => anyhow::anyhow!("Google answered {}", response),
+        other => anyhow::Error::new(other),
+    }
+}
+
 /// Refresh an expired token
 pub async fn refresh_token(
     client: &BasicClient,
@@ -198,6 +448,7 @@ pub async fn refresh_token(
         .exchange_refresh_token(&oauth2::RefreshToken::new(refresh_token.to_string()))
         .request_async(async_http_client)
         .await
+        .map_err(token_request_error)
         .context("Failed to refresh token")?;
 
     info!("Token refreshed successfully");
@@ -215,6 +466,8 @@ mod tests {
             access_token: "test".to_string(),
             refresh_token: Some("refresh".to_string()),
             expires_at: Some(chrono::Utc::now().timestamp() + 3600),
+            account: None,
+            signed_in_at: None,
         };
         assert!(!token.is_expired());
 
@@ -223,6 +476,8 @@ mod tests {
             access_token: "test".to_string(),
             refresh_token: Some("refresh".to_string()),
             expires_at: Some(chrono::Utc::now().timestamp() - 100),
+            account: None,
+            signed_in_at: None,
         };
         assert!(token.is_expired());
 
@@ -231,8 +486,104 @@ mod tests {
             access_token: "test".to_string(),
             refresh_token: Some("refresh".to_string()),
             expires_at: Some(chrono::Utc::now().timestamp() + 200),
+            account: None,
+            signed_in_at: None,
         };
         assert!(token.is_expired());
+
+        // Cross-account setups: a token is only reused for the account it was issued to
+        let token = TokenCache { account: Some("Billing@Acme.com".to_string()), ..token };
+        assert!(token.belongs_to(Some("billing@acme.com")));
+        assert!(token.belongs_to(None));
+        assert!(!token.belongs_to(Some("me@gmail.com")));
+        assert!(check_account("Gmail", "GOOGLE_GMAIL_ACCOUNT", Some("me@gmail.com"), Some("billing@acme.com")).is_err());
+        assert!(check_account("Gmail", "GOOGLE_GMAIL_ACCOUNT", None, Some("billing@acme.com")).is_ok());
+    }
+
+    #[test]
+    fn test_account_warnings() {
+        let mut config = Config::for_test(&[]);
+        config.gmail_client_id = "shared.apps.googleusercontent.com".to_string();
+        config.drive_client_id = config.gmail_client_id.clone();
+        assert!(account_warnings(&config, Some("me@gmail.com"), Some("me@gmail.com")).is_empty());
+        assert!(account_warnings(&config, Some("me@gmail.com"), Some("finance@acme.com")).is_empty());
+
+        // A Drive client of its own hints at a second account
+        config.drive_client_id = "drive.apps.googleusercontent.com".to_string();
+        let warnings = account_warnings(&config, Some("me@gmail.com"), Some("Me@gmail.com"));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("both signed in as me@gmail.com"));
+
+        config.drive_account = Some("finance@acme.com".to_string());
+        let warnings = account_warnings(&config, Some("me@gmail.com"), Some("me@gmail.com"));
+        assert_eq!(warnings, vec!["Google Drive is signed in as me@gmail.com, not finance@acme.com (GOOGLE_DRIVE_ACCOUNT); the next run signs in again"]);
+        assert!(account_warnings(&config, None, Some("finance@acme.com")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_callback_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let csrf_token = CsrfToken::new("state-1".to_string());
+        let page = CallbackPage::default();
+
+        let browser = tokio::spawn(async move {
+            for path in ["/favicon.ico", "/?state=state-1&code=code-1"] {
+                let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+                stream.write_all(format!("GET {} HTTP/1.1\r\n\r\n", path).as_bytes()).await.unwrap();
+                let mut response = String::new();
+                tokio::io::AsyncReadExt::read_to_string(&mut stream, &mut response).await.unwrap();
+            }
+        });
+        let code = wait_for_callback(&listener, &csrf_token, &page).await.unwrap();
+        assert_eq!(code.secret(), "code-1");
+        browser.await.unwrap();
+
+        // Nobody comes back
+        let waited = tokio::time::timeout(Duration::from_millis(50), wait_for_callback(&listener, &csrf_token, &page)).await;
+
+        // "Deny" on the consent screen ends the flow instead of leaving it waiting
+        let browser = tokio::spawn(async move {
+            let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+            stream.write_all(b"GET /?error=access_denied&state=state-1 HTTP/1.1\r\n\r\n").await.unwrap();
+            let mut response = String::new();
+            tokio::io::AsyncReadExt::read_to_string(&mut stream, &mut response).await.unwrap();
+            response
+        });
+        let denied = wait_for_callback(&listener, &csrf_token, &page).await.unwrap_err();
+        assert!(denied.to_string().starts_with("Access was declined"));
+        assert!(browser.await.unwrap().contains("Access was declined"));
+        assert!(waited.is_err());
+    }
+
+    #[test]
+    fn test_parse_pasted() {
+        let csrf_token = CsrfToken::new("state-1".to_string());
+        let code = |text: &str| parse_pasted(text, &csrf_token).map(|code| code.map(|code| code.secret().clone()));
+        assert_eq!(code("  http://localhost:8080/?state=state-1&code=4/0Ab&scope=x \n").unwrap().as_deref(), Some("4/0Ab"));
+        assert_eq!(code("4/0AbCdE").unwrap().as_deref(), Some("4/0AbCdE"));
+        assert_eq!(code("http://localhost:8080/").unwrap(), None);
+        assert_eq!(code("not a code").unwrap(), None);
+        assert!(code("http://localhost:8080/?state=other&code=4/0Ab").is_err());
+        assert!(code("http://localhost:8080/?error=access_denied&state=state-1").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_revoke() {
+        let mut server = mockito::Server::new_async().await;
+        let revoked = server.mock("POST", "/revoke").match_body("token=refresh-1").with_status(200).create_async().await;
+        let unknown = server
+            .mock("POST", "/revoke")
+            .match_body("token=gone")
+            .with_status(400)
+            .with_body(r#"{"error": "invalid_token", "error_description": "Token expired or revoked"}"#)
+            .create_async()
+            .await;
+        let url = format!("{}/revoke", server.url());
+        assert!(revoke_at(&url, "refresh-1").await.unwrap());
+        assert!(!revoke_at(&url, "gone").await.unwrap());
+        revoked.assert_async().await;
+        unknown.assert_async().await;
     }
 
     #[test]
@@ -0,0 +1,97 @@
+//! Pre-flight token checks. Before a run starts, each OAuth token it will use is looked up with
+//! Google's tokeninfo endpoint, so a revoked token, or one granted a narrower scope than the run
+//! needs, stops the run before any mail is searched, saying which account to authorize again.
+
+use crate::process::outcome::FailureKind;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+const TOKENINFO_URL: &str = "https://oauth2.googleapis.com/tokeninfo";
+
+/// Scopes that include one a run checks for (GMAIL_SCOPE, DRIVE_SCOPE): a token granted any of
+/// them will do
+const BROADER_SCOPES: &[(&str, &[&str])] = &[
+    (
+        "https://www.googleapis.com/auth/gmail.readonly",
+        &["https://www.googleapis.com/auth/gmail.modify", "https://mail.google.com/"],
+    ),
+    ("https://www.googleapis.com/auth/drive.file", &["https://www.googleapis.com/auth/drive"]),
+];
+
+#[derive(Debug, Deserialize)]
+struct TokenInfo {
+    /// Space-separated granted scopes
+    #[serde(default)]
+    scope: String,
+}
+
+/// Whether the granted scopes include `needed`, directly or through a broader scope
+pub fn covers(granted: &[&str], needed: &str) -> bool {
+    let broader = BROADER_SCOPES.iter().find(|(scope, _)| *scope == needed).map_or(&[][..], |(_, broader)| *broader);
+    granted.iter().any(|scope| *scope == needed || broader.contains(scope))
+}
+
+/// Short name of a scope for messages (`gmail.readonly`)
+fn scope_name(scope: &str) -> &str {
+    scope.trim_end_matches('/').rsplit('/').next().unwrap_or(scope)
+}
+
+/// Check that `access_token` is still accepted by Google and grants `needed`. Rejected tokens
+/// and missing scopes are authentication failures naming `reauth`, the command that fixes them.
+pub async fn check_token(access_token: &str, needed: &str, account: &str, reauth: &str) -> Result<()> {
+    let response = reqwest::Client::new()
+        .get(TOKENINFO_URL)
+        .query(&[("access_token", access_token)])
+        .send()
+        .await
+        .context("Failed to reach Google's tokeninfo endpoint")?;
+
+    // tokeninfo answers 400 for tokens it doesn't know: expired, revoked or from another client
+    if response.status() == reqwest::StatusCode::BAD_REQUEST {
+        return Err(anyhow::anyhow!(
+            "The {} token was rejected by Google (revoked or expired); re-run `{}` to sign in again",
+            account,
+            reauth
+        ))
+        .context(FailureKind::Auth);
+    }
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        anyhow::bail!("Google tokeninfo error ({}): {}", status, error_text);
+    }
+
+    let info: TokenInfo = response.json().await.context("Failed to parse tokeninfo response")?;
+    let granted: Vec<&str> = info.scope.split_whitespace().collect();
+    if !covers(&granted, needed) {
+        let granted = if granted.is_empty() { "none".to_string() } else { granted.iter().map(|scope| scope_name(scope)).collect::<Vec<_>>().join(", ") };
+        return Err(anyhow::anyhow!(
+            "The {} token doesn't grant {} (granted: {}); re-run `{}` to grant {}",
+            account,
+            scope_name(needed),
+            granted,
+            reauth,
+            scope_name(needed)
+        ))
+        .context(FailureKind::Auth);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_coverage() {
+        let readonly = "https://www.googleapis.com/auth/gmail.readonly";
+        let drive_file = "https://www.googleapis.com/auth/drive.file";
+        assert!(covers(&[readonly], readonly));
+        assert!(covers(&["https://www.googleapis.com/auth/gmail.modify"], readonly));
+        assert!(covers(&["openid", "https://www.googleapis.com/auth/drive"], drive_file));
+        assert!(!covers(&["https://www.googleapis.com/auth/drive.readonly"], drive_file));
+        assert!(!covers(&[readonly], "https://www.googleapis.com/auth/gmail.send"));
+        assert_eq!(scope_name(readonly), "gmail.readonly");
+        assert_eq!(scope_name("https://mail.google.com/"), "mail.google.com");
+    }
+}
@@ -0,0 +1,150 @@
+//! Moving a signed-in setup to another machine (`auth export` / `auth import`). The cached OAuth
+//! tokens of the default account and every profile are zipped and encrypted with a passphrase
+//! (age's scrypt recipient, so `age -d` opens a bundle too), letting a headless server start
+//! with the tokens signed in on a laptop instead of going through the browser again.
+
+use anyhow::{Context, Result};
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+
+/// Token caches end in `_token.json` (`gmail_token.json`, `drive_token.json`, ...)
+const TOKEN_SUFFIX: &str = "_token.json";
+
+/// Whether `path`, relative to the config directory, is a token cache a bundle may hold: at the
+/// top or in `profiles/<name>/`, and nothing that could point outside the config directory
+fn is_token_path(path: &str) -> bool {
+    let parts: Vec<&str> = path.split('/').collect();
+    let named = |part: &str| !part.is_empty() && part != "." && part != ".." && !part.contains('\\');
+    let file = match parts.as_slice() {
+        [file] => file,
+        ["profiles", profile, file] if named(profile) => file,
+        _ => return false,
+    };
+    named(file) && file.ends_with(TOKEN_SUFFIX)
+}
+
+/// The token caches under `config_dir`, as paths relative to it
+pub fn token_files(config_dir: &Path) -> Result<Vec<String>> {
+    let mut dirs = vec![(config_dir.to_path_buf(), String::new())];
+    let profiles = config_dir.join("profiles");
+    if profiles.is_dir() {
+        for entry in std::fs::read_dir(&profiles).context("Failed to list profile token directories")? {
+            let entry = entry?;
+            if entry.path().is_dir() {
+                dirs.push((entry.path(), format!("profiles/{}/", entry.file_name().to_string_lossy())));
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    for (dir, prefix) in dirs {
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("Failed to list {}", dir.display()))? {
+            let entry = entry?;
+            let path = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if entry.path().is_file() && is_token_path(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Zip the token caches under `config_dir` and encrypt them with `passphrase`
+pub fn bundle(config_dir: &Path, passphrase: &str) -> Result<(Vec<u8>, Vec<String>)> {
+    let files = token_files(config_dir)?;
+    if files.is_empty() {
+        anyhow::bail!("No tokens to export in {}; sign in first (e.g. invoice-pilot auth gmail)", config_dir.display());
+    }
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for file in &files {
+        let data = std::fs::read(config_dir.join(file)).with_context(|| format!("Failed to read {}", file))?;
+        zip.start_file(file.as_str(), options)?;
+        zip.write_all(&data)?;
+    }
+    let archive = zip.finish()?.into_inner();
+
+    let encryptor = age::Encryptor::with_user_passphrase(age::secrecy::SecretString::from(passphrase.to_string()));
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(&archive)?;
+    writer.finish()?;
+    Ok((encrypted, files))
+}
+
+/// Decrypt a bundle and return its token caches, by path relative to the config directory
+pub fn unbundle(encrypted: &[u8], passphrase: &str) -> Result<Vec<(String, Vec<u8>)>> {
+    let decryptor = age::Decryptor::new(encrypted).map_err(|e| anyhow::anyhow!("Not a token bundle: {}", e))?;
+    let identity = age::scrypt::Identity::new(age::secrecy::SecretString::from(passphrase.to_string()));
+    let mut reader = decryptor
+        .decrypt(std::iter::once(&identity as &dyn age::Identity))
+        .map_err(|e| anyhow::anyhow!("Could not decrypt the token bundle (wrong passphrase?): {}", e))?;
+    let mut archive = Vec::new();
+    reader.read_to_end(&mut archive)?;
+
+    let mut zip = zip::ZipArchive::new(Cursor::new(archive)).context("The token bundle is not a zip archive")?;
+    let mut files = Vec::new();
+    for index in 0..zip.len() {
+        let mut file = zip.by_index(index)?;
+        if !is_token_path(file.name()) {
+            anyhow::bail!("The token bundle holds {}, which is not a token cache", file.name());
+        }
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        files.push((file.name().to_string(), data));
+    }
+    Ok(files)
+}
+
+/// Write the caches of a bundle under `config_dir`. Tokens already there are only replaced with
+/// `force`, so importing can't sign a configured machine out of its own accounts by accident.
+pub fn restore(config_dir: &Path, files: &[(String, Vec<u8>)], force: bool) -> Result<()> {
+    let existing: Vec<&str> = files.iter().map(|(path, _)| path.as_str()).filter(|path| config_dir.join(path).exists()).collect();
+    if !existing.is_empty() && !force {
+        anyhow::bail!("Tokens already exist for {}; re-run with --force to replace them", existing.join(", "));
+    }
+
+    for (path, data) in files {
+        let target = config_dir.join(path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).context("Failed to create profile token directory")?;
+        }
+        std::fs::write(&target, data).with_context(|| format!("Failed to write {}", target.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_roundtrip() {
+        assert!(is_token_path("gmail_token.json"));
+        assert!(is_token_path("profiles/acme/drive_token.json"));
+        assert!(!is_token_path("feedback.json"));
+        assert!(!is_token_path("../gmail_token.json"));
+        assert!(!is_token_path("profiles/../drive_token.json"));
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let (laptop, server) = (root.join("laptop"), root.join("server"));
+        std::fs::create_dir_all(laptop.join("profiles/acme")).unwrap();
+        std::fs::write(laptop.join("gmail_token.json"), b"{\"access_token\":\"a\"}").unwrap();
+        std::fs::write(laptop.join("profiles/acme/drive_token.json"), b"{\"access_token\":\"b\"}").unwrap();
+        std::fs::write(laptop.join("vendor_aliases.json"), b"{}").unwrap();
+
+        let (encrypted, exported) = bundle(&laptop, "correct horse").unwrap();
+        assert_eq!(exported, vec!["gmail_token.json", "profiles/acme/drive_token.json"]);
+        assert!(unbundle(&encrypted, "wrong horse").is_err());
+
+        let files = unbundle(&encrypted, "correct horse").unwrap();
+        restore(&server, &files, false).unwrap();
+        assert_eq!(std::fs::read(server.join("profiles/acme/drive_token.json")).unwrap(), b"{\"access_token\":\"b\"}");
+        assert!(restore(&server, &files, false).is_err());
+        restore(&server, &files, true).unwrap();
+    }
+}
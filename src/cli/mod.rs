@@ -1 +1,59 @@
 pub mod args;
+pub mod progress;
+
+use anyhow::Result;
+use tokio::sync::mpsc;
+
+/// Starts a yes/no question sent through a run's progress lines (see `confirm_after`)
+pub const CONFIRM_MARKER: &str = "__CONFIRM__:";
+
+/// Ask an open question on the terminal, returning the lowercased answer (None when empty or
+/// when stdin is not a terminal)
+pub fn choice_prompt(prompt: &str) -> Result<Option<String>> {
+    use std::io::{IsTerminal, Write};
+
+    if !std::io::stdin().is_terminal() {
+        return Ok(None);
+    }
+
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(Some(answer.trim().to_lowercase()).filter(|answer| !answer.is_empty()))
+}
+
+/// Ask a yes/no question on the terminal; anything but "y"/"yes" declines.
+/// Refuses to guess when stdin is not a terminal, so unattended runs must pass --yes.
+pub fn confirm_prompt(prompt: &str) -> Result<bool> {
+    use std::io::Write;
+
+    ensure_terminal()?;
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    read_yes()
+}
+
+/// Ask a yes/no question like `confirm_prompt` in the middle of a run: the progress printer
+/// behind `tx` prints it, so it comes after the lines sent before it
+pub fn confirm_after(tx: &mpsc::UnboundedSender<String>, prompt: &str) -> Result<bool> {
+    ensure_terminal()?;
+    tx.send(format!("{}{}", CONFIRM_MARKER, prompt))?;
+    read_yes()
+}
+
+fn ensure_terminal() -> Result<()> {
+    use std::io::IsTerminal;
+
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("Confirmation required but stdin is not a terminal; re-run with --yes to proceed unattended");
+    }
+    Ok(())
+}
+
+fn read_yes() -> Result<bool> {
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
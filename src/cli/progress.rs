@@ -0,0 +1,193 @@
+//! Progress bars for CLI runs on a terminal: the search, fetch, download and upload phases each
+//! get a bar with counts and rate, and the per-message and per-file lines are folded into them.
+//! Off a terminal (cron, pipes, CI) every progress line is printed as before.
+
+use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
+use std::fmt::Write;
+use std::io::IsTerminal;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::process::outcome::{Step, STEP_MARKER};
+
+const COUNTED_TEMPLATE: &str = "{prefix:>10.bold} [{bar:30.cyan/blue}] {pos}/{len} {rate:>7} eta {eta:>3} {wide_msg}";
+const SPINNER_TEMPLATE: &str = "{prefix:>10.bold} {spinner} {elapsed} {wide_msg}";
+
+#[derive(Clone)]
+pub struct Progress {
+    /// None when stdout is not a terminal
+    multi: Option<MultiProgress>,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self { multi: std::io::stdout().is_terminal().then(MultiProgress::new) }
+    }
+
+    /// A bar for a phase: counted up to `total`, a spinner without one. Hidden off a terminal.
+    pub fn bar(&self, phase: &str, total: Option<u64>) -> ProgressBar {
+        let Some(multi) = &self.multi else {
+            return ProgressBar::hidden();
+        };
+        let bar = match total {
+            Some(total) => ProgressBar::new(total).with_style(style(COUNTED_TEMPLATE)),
+            None => {
+                let spinner = ProgressBar::new_spinner().with_style(style(SPINNER_TEMPLATE));
+                spinner.enable_steady_tick(Duration::from_millis(120));
+                spinner
+            }
+        };
+        bar.set_prefix(phase.to_string());
+        multi.add(bar)
+    }
+
+    /// Finish a bar with a last message and let it scroll away with the output after it
+    pub fn finish(&self, bar: &ProgressBar, message: String) {
+        bar.finish_with_message(message);
+        if let Some(multi) = &self.multi {
+            multi.remove(bar);
+        }
+    }
+
+    /// Run `print` with the bars cleared off the screen, so its output stays above them
+    pub fn suspend<R>(&self, print: impl FnOnce() -> R) -> R {
+        match &self.multi {
+            Some(multi) => multi.suspend(print),
+            None => print(),
+        }
+    }
+
+    pub fn println(&self, line: &str) {
+        self.suspend(|| println!("{}", line));
+    }
+
+    /// Print the pipeline's progress lines as they arrive (internal markers are skipped). On a
+    /// terminal, the `__STEP__:` markers move the download and upload bars and the message and
+    /// file lines next to them show on the bars instead of scrolling by; stage lines, warnings and
+    /// failures are still printed.
+    pub fn spawn_printer(&self) -> (mpsc::UnboundedSender<String>, JoinHandle<()>) {
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        let progress = self.clone();
+        let printer = tokio::spawn(async move {
+            let mut bars = PipelineBars::default();
+            while let Some(line) = rx.recv().await {
+                if line.starts_with("__RESULTS__:") {
+                    continue;
+                }
+                if let Some(prompt) = line.strip_prefix(super::CONFIRM_MARKER) {
+                    progress.suspend(|| {
+                        print!("{}", prompt);
+                        let _ = std::io::Write::flush(&mut std::io::stdout());
+                    });
+                    continue;
+                }
+                if progress.multi.is_none() {
+                    if !line.starts_with(STEP_MARKER) {
+                        println!("{}", line);
+                    }
+                    continue;
+                }
+                bars.note(&progress, &line);
+            }
+            bars.finish(&progress);
+        });
+        (tx, printer)
+    }
+}
+
+fn style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template)
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+        .with_key("rate", |state: &ProgressState, out: &mut dyn Write| {
+            let _ = write!(out, "{:.1}/s", state.per_sec());
+        })
+        .progress_chars("=> ")
+}
+
+/// What a progress line of the pipeline means for the bars
+#[derive(Debug, PartialEq)]
+enum Line {
+    /// A `__STEP__:` marker, which moves the bars
+    Step(Step),
+    /// Per-file detail the bars stand for
+    Detail,
+    /// Anything else is printed
+    Print,
+}
+
+fn classify(line: &str) -> Line {
+    if let Some(step) = Step::parse(line) {
+        return Line::Step(step);
+    }
+    // Indented lines are detail; the ones worth a look (warnings, failures, alerts) are printed
+    let notable = ['⚠', '✗', '☣', '💸'].iter().any(|mark| line.trim().starts_with(*mark));
+    if line.starts_with(' ') && !notable {
+        return Line::Detail;
+    }
+    Line::Print
+}
+
+#[derive(Default)]
+struct PipelineBars {
+    download: Option<ProgressBar>,
+    upload: Option<ProgressBar>,
+}
+
+impl PipelineBars {
+    fn note(&mut self, progress: &Progress, line: &str) {
+        match classify(line) {
+            Line::Step(Step::Message(current, total)) => {
+                let bar = self.download.get_or_insert_with(|| progress.bar("Download", Some(total)));
+                bar.set_position(current.saturating_sub(1));
+            }
+            Line::Step(Step::Downloaded(count)) => {
+                if let Some(bar) = self.download.take() {
+                    progress.finish(&bar, format!("{} attachment(s)", count));
+                }
+            }
+            Line::Step(Step::UploadStarted(files)) => {
+                self.upload = Some(progress.bar("Upload", Some(files.max(1))));
+            }
+            Line::Step(Step::FileDone { .. }) => {
+                if let Some(bar) = &self.upload {
+                    // Files renamed or merged on the way may outnumber the downloads
+                    if bar.position() + 1 > bar.length().unwrap_or(0) {
+                        bar.inc_length(1);
+                    }
+                    bar.inc(1);
+                }
+            }
+            Line::Detail => {
+                if let Some(bar) = self.upload.as_ref().or(self.download.as_ref()) {
+                    bar.set_message(line.trim().to_string());
+                }
+            }
+            Line::Print => progress.println(line),
+        }
+    }
+
+    fn finish(self, progress: &Progress) {
+        for bar in [self.download, self.upload].into_iter().flatten() {
+            bar.set_length(bar.position());
+            progress.finish(&bar, "done".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_progress_lines() {
+        assert_eq!(classify("__STEP__:message=3/40"), Line::Step(Step::Message(3, 40)));
+        assert_eq!(classify("__STEP__:file=failed"), Line::Step(Step::FileDone { failed: true }));
+        // The lines next to the steps no longer move the bars
+        assert_eq!(classify("  Processing message 3/40"), Line::Detail);
+        assert_eq!(classify("⬆️ Uploading to Google Drive..."), Line::Print);
+        assert_eq!(classify("   ✓ Uploaded: aws-invoice.pdf (ID: 1a2b)"), Line::Detail);
+        assert_eq!(classify("   ✗ Failed to copy /tmp/a.pdf: disk full"), Line::Print);
+        assert_eq!(classify("      ✓ 15: aws-invoice.pdf (📄 General)"), Line::Detail);
+        assert_eq!(classify("Billing month detected: March"), Line::Print);
+    }
+}
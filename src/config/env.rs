@@ -1,80 +1,926 @@
+use crate::auth::client_secret::ClientSecret;
+use crate::process::compress::PDF_QUALITIES;
+use crate::process::budget::{self, Budget};
+use crate::process::encrypt;
+use crate::process::fiscal::{self, FiscalCalendar};
+use crate::process::retention::RetentionPolicy;
+use crate::process::sanitize;
+use super::keywords;
+use crate::mail::imap::TlsMode;
+use crate::mail::jmap::{self, JmapSettings};
+use crate::mail::proton::BridgeSettings;
+use crate::storage::gcs::GcsSettings;
+use crate::storage::sftp::SftpSettings;
+use crate::mail::search::{Exclusions, IngestQueue};
+use crate::scheduler::runner::{Holiday, ScheduleDay};
+use crate::mail::vendors::{self, VendorAlias};
 use anyhow::{Context, Result};
-use chrono::{Datelike, Local, NaiveDate};
+use chrono::{Datelike, Local, NaiveDate, NaiveTime};
+use chrono_tz::Tz;
 use log::info;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
+use std::path::{Path, PathBuf};
+
+/// Days before its due date an unpaid invoice is reminded of, by default
+pub const DEFAULT_REMINDER_DAYS: i64 = 7;
+
+/// Default location of the recorded fixtures used by mock mode: the checkout the binary was
+/// built from, so it doesn't depend on the working directory
+const DEFAULT_MOCK_FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/fixtures");
+
+/// Command-line switches that apply to every configuration a command loads
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Flags {
+    /// `--mock`: use the recorded fixtures whatever MOCK_MODE says
+    pub mock: bool,
+    /// `--refresh`: ignore cached message metadata and fetch every message again
+    pub refresh: bool,
+    /// `--paste-code`: finish sign-ins from a pasted redirect address, like OAUTH_PASTE_CODE
+    pub paste_code: bool,
+    /// `--read-only`: READ_ONLY=true whatever the environment says
+    pub read_only: bool,
+}
+
+pub fn read_only_refusal(action: &str) -> anyhow::Error {
+    anyhow::anyhow!("{} is not allowed in read-only mode (READ_ONLY / --read-only)", action)
+}
+
+/// What to do with documents dated far outside the requested range
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum DocumentDateCheck {
+    Off,
+    Warn,
+    Skip,
+}
+
+/// What to do with an invoice already archived under another file (same vendor, number and amount)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum DuplicateAction {
+    Off,
+    Flag,
+    Skip,
+}
+
+impl DuplicateAction {
+    fn parse(value: &str, name: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "flag" => Ok(Self::Flag),
+            "skip" => Ok(Self::Skip),
+            other => anyhow::bail!("{} must be off, flag or skip (got '{}')", name, other),
+        }
+    }
+}
+
+/// Where messages are read from (MAIL_SOURCE)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum MailSourceKind {
+    Gmail,
+    ProtonBridge,
+    Jmap,
+    Maildir,
+}
+
+impl MailSourceKind {
+    fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "gmail" => Ok(Self::Gmail),
+            "proton" | "proton-bridge" => Ok(Self::ProtonBridge),
+            "jmap" | "fastmail" => Ok(Self::Jmap),
+            "maildir" => Ok(Self::Maildir),
+            other => anyhow::bail!("MAIL_SOURCE must be gmail, proton-bridge, jmap or maildir (got '{}')", other),
+        }
+    }
+}
+
+/// Where archived documents are written (STORAGE_BACKEND, and STORAGE_MIRROR for the copy)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum StorageKind {
+    Drive,
+    Sftp,
+    Gcs,
+    Local,
+}
+
+impl StorageKind {
+    fn parse(key: &str, value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "drive" | "google-drive" => Ok(Self::Drive),
+            "sftp" => Ok(Self::Sftp),
+            "gcs" | "cloud-storage" => Ok(Self::Gcs),
+            "local" => Ok(Self::Local),
+            other => anyhow::bail!("{} must be drive, sftp, gcs or local (got '{}')", key, other),
+        }
+    }
+
+    /// Name shown in progress messages
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Drive => "Google Drive",
+            Self::Sftp => "SFTP server",
+            Self::Gcs => "Google Cloud Storage",
+            Self::Local => "local folder",
+        }
+    }
+}
+
+/// How often the archive digest is sent
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum DigestCadence {
+    Weekly,
+    Monthly,
+}
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
+    // Mailbox the invoices are read from
+    pub mail_source: MailSourceKind,
+    // Local ProtonMail Bridge connection, when MAIL_SOURCE=proton-bridge
+    #[serde(skip)]
+    pub proton_bridge: Option<BridgeSettings>,
+    // JMAP server (Fastmail by default), when MAIL_SOURCE=jmap
+    #[serde(skip)]
+    pub jmap: Option<JmapSettings>,
+    // Local Maildir tree, when MAIL_SOURCE=maildir
+    pub maildir_path: Option<PathBuf>,
+
     // Gmail Account credentials
     pub gmail_client_id: String,
     pub gmail_client_secret: String,
+    // Mailbox to read when it isn't the signed-in account's own, e.g. billing@company.com
+    pub gmail_user: Option<String>,
+    // Google account Gmail must be signed in as; Drive may be signed in as another (GOOGLE_DRIVE_ACCOUNT)
+    pub gmail_account: Option<String>,
+    // Service account key with domain-wide delegation, used instead of OAuth to read GMAIL_USER
+    pub google_service_account_key: Option<PathBuf>,
+    // Workspace mailboxes processed by `run-workspace`, each into its own Drive subfolder
+    pub workspace_users: Vec<String>,
 
     // Drive Account credentials
+    // Where documents are archived; folders keep the Drive layout on every backend
+    pub storage_backend: StorageKind,
+    // Second storage every document is also copied to
+    pub storage_mirror: Option<StorageKind>,
+    // SFTP server, when sftp is the backend or the mirror
+    #[serde(skip)]
+    pub sftp: Option<SftpSettings>,
+    // Cloud Storage bucket, when gcs is the backend or the mirror
+    #[serde(skip)]
+    pub gcs: Option<GcsSettings>,
+    // Archive directory, when local is the backend or the mirror
+    pub local_archive_dir: Option<PathBuf>,
     pub drive_client_id: String,
     pub drive_client_secret: String,
+    // Google account Drive must be signed in as, e.g. the company's when mail is read from a personal one
+    pub drive_account: Option<String>,
+    // May hold {year}, {fiscal_year} and {fiscal_period}, filled in per billing month
     pub drive_folder_path: String,
+    // Month the fiscal year starts in, for {fiscal_year}, {fiscal_period} and `report --fiscal-year`
+    #[serde(skip)]
+    pub fiscal_calendar: FiscalCalendar,
 
     // Scheduling (only required for scheduled mode)
-    pub fetch_invoices_day: Option<u8>,
+    #[serde(skip)]
+    pub fetch_invoices_day: Option<ScheduleDay>,
+    // Days off skipped by business-day schedules
+    #[serde(skip)]
+    pub holidays: Vec<Holiday>,
+    // Local time the timer or cron job starts `scheduled`, for the TUI's next-run countdown
+    pub schedule_time: NaiveTime,
+    // Scheduled runs start up to this many minutes late, so a fleet doesn't hit Google at once
+    pub schedule_jitter_minutes: u64,
+    // Retries of a scheduled run that failed for a transient reason, the delay doubling each time
+    pub schedule_retries: u32,
+    pub schedule_retry_delay_minutes: u64,
+    // A run with no progress for this long counts as stuck (0: never); scheduled runs then give up
+    pub heartbeat_stale_minutes: u64,
+    // Days a Google refresh token lasts after signing in (7 while the OAuth app is in Testing; 0: no limit)
+    pub oauth_refresh_token_days: u32,
+    // Also upload each run's JSON record (runs/<timestamp>.json) to the archive's `_meta` folder
+    pub upload_run_artifacts: bool,
 
     // Keywords to search for in emails
     pub target_keywords: Vec<String>,
 
+    // Search exclusions: negative keywords and mailbox categories (e.g. promotions, social)
+    pub exclude_keywords: Vec<String>,
+    pub exclude_categories: Vec<String>,
+    // Also search spam and trash (opt-in; files found there are flagged in the report)
+    pub include_spam: bool,
+    pub include_trash: bool,
+
+    // Only download attachments whose filename matches one of these (empty = all)
+    pub attachment_name_patterns: Vec<String>,
+    /// Canonical vendor names and their sender variants (VENDOR_ALIASES)
+    pub vendor_aliases: Vec<VendorAlias>,
+
+    // Sanity check of each document's own date against the requested range
+    pub document_date_check: DocumentDateCheck,
+    pub document_date_tolerance_days: i64,
+    // Same invoice resent as a new PDF, found by reading every PDF's text (off unless asked for)
+    pub semantic_duplicates: DuplicateAction,
+    /// Per-vendor overrides of `semantic_duplicates`, matched against the vendor's filename prefix
+    pub semantic_duplicate_vendors: Vec<(String, DuplicateAction)>,
+
+    // Label or alias whose messages are archived as they arrive, whatever their keywords (`ingest`)
+    #[serde(skip)]
+    pub ingest_queue: Option<IngestQueue>,
+    pub ingest_lookback_days: u64,
+
+    // Keyword queries run at the same time
+    pub search_concurrency: usize,
+
+    // Messages whose attachments are downloaded at the same time
+    pub download_concurrency: usize,
+
+    // Files of a folder saved, scanned, compressed, transformed and encrypted at the same time
+    pub transform_concurrency: usize,
+
+    // Prepared folders waiting for upload before preparing the next one pauses
+    pub upload_queue: usize,
+
+    // Lower the requests in flight per API on rate limits and remember where they settle
+    pub adaptive_concurrency: bool,
+
+    // Warn before a run whose keywords are estimated to match more messages than this (0 = never)
+    pub search_warn_messages: u64,
+    // Ask before uploading more than this many MB in manual mode, unless --yes (0 = never)
+    pub upload_confirm_mb: u64,
+    // Upload speed (Mbit/s) for upload time estimates; the speed measured on earlier runs otherwise
+    pub upload_speed_mbps: Option<f64>,
+    // Cap set by `manual --limit N --newest-first`: at most this many messages, newest first
+    pub message_limit: Option<usize>,
+    pub newest_first: bool,
+
+    // Timezone of the mailbox, used to decide which day a message arrived on (None = local time)
+    #[serde(skip)]
+    pub mailbox_timezone: Option<Tz>,
+
     // Date range for filtering emails
     pub start_date: NaiveDate,
     pub end_date: NaiveDate,
 
     // Debug logging
     pub debug_logs_enabled: bool,
+
+    // Mock mode: Gmail and Drive are backed by local fixtures instead of Google APIs
+    pub mock_mode: bool,
+    pub mock_fixtures_dir: PathBuf,
+    pub mock_drive_dir: PathBuf,
+
+    // Hook commands run through the shell after runs and uploads
+    pub on_run_success: Option<String>,
+    pub on_run_failure: Option<String>,
+    pub on_file_uploaded: Option<String>,
+    pub pre_upload_transform: Option<String>,
+
+    // Spoofing/phishing heuristics; suspicious mail is filed under the review folder
+    pub phishing_checks: bool,
+    pub review_folder: String,
+
+    // Virus scanning before upload: clamd socket (path or host:port) or an external scanner command
+    pub clamd_socket: Option<String>,
+    pub virus_scan_command: Option<String>,
+    pub quarantine_dir: PathBuf,
+
+    // Where runs with failures write failures-<run>.json for `retry --from`
+    pub failure_report_dir: PathBuf,
+
+    // PDF compression before upload (Ghostscript preset; None = off)
+    pub pdf_compression: Option<String>,
+    pub pdf_compression_skip_senders: Vec<String>,
+    pub ghostscript_path: String,
+
+    // File credit notes and refunds under a `Credit Notes` subfolder of their institution folder
+    pub credit_notes_subfolder: bool,
+    // Make file and folder names safe for a Windows sync client before uploading
+    pub filename_sanitize: sanitize::Strictness,
+
+    // Decrypt PGP/S-MIME encrypted attachments before archiving
+    pub decrypt_attachments: bool,
+    pub gpg_homedir: Option<PathBuf>,
+    pub gpg_passphrase_file: Option<PathBuf>,
+    pub smime_key_file: Option<PathBuf>,
+    pub smime_cert_file: Option<PathBuf>,
+
+    // age public keys; when set every file is encrypted to <file>.age before upload
+    pub encryption_recipients: Vec<String>,
+
+    // WASM classification/routing plugins, run in order (requires the `plugins` feature)
+    pub wasm_plugins: Vec<PathBuf>,
+
+    // Per-sender processing rules (RULES_FILE, or rules.toml when it exists)
+    pub rules_file: Option<PathBuf>,
+
+    // Column mappings of banks' CSV statements (STATEMENT_FORMATS_FILE, or statements.toml next to
+    // the profile's tokens or in the config directory, when it exists)
+    pub statement_formats_file: Option<PathBuf>,
+
+    // Notification sinks and the events they subscribe to (NOTIFICATIONS_FILE, or notifications.toml when it exists)
+    pub notifications_file: Option<PathBuf>,
+
+    // Accountant handoff for the `package` command
+    pub accountant_email: Option<String>,
+    pub handoff_drive_folder: Option<String>,
+
+    // Retention rules for the Drive archive (`archive maintain`)
+    #[serde(skip)]
+    pub retention: RetentionPolicy,
+
+    // Archive digest (`digest`): cadence, recipient and hook command
+    pub digest_cadence: DigestCadence,
+    pub digest_email: Option<String>,
+    pub on_digest: Option<String>,
+
+    // Monthly budgets per category or vendor, checked after each run
+    #[serde(skip)]
+    pub budgets: Vec<Budget>,
+    pub on_budget_alert: Option<String>,
+
+    // Reminders of unpaid invoices (`payables --remind` and the digest): days ahead and hook command
+    pub due_reminder_days: i64,
+    pub on_payment_due: Option<String>,
+
+    // Bearer token the web dashboard (`serve`) requires when set
+    #[cfg_attr(not(feature = "dashboard"), allow(dead_code))]
+    pub dashboard_token: Option<String>,
+
+    // Server certificate, key and client CA for the gRPC control API's mutual TLS (`grpc`)
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub grpc_tls_cert: Option<PathBuf>,
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub grpc_tls_key: Option<PathBuf>,
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub grpc_client_ca: Option<PathBuf>,
+
+    // Searches, listings and reports only: nothing is uploaded, moved or changed (READ_ONLY, `--read-only`)
+    pub read_only: bool,
+
+    // Ignore cached message metadata and fetch every message from the mailbox again (`--refresh`)
+    pub refresh: bool,
+
+    // Finish sign-ins from a pasted redirect address instead of the localhost callback
+    // (OAUTH_PASTE_CODE, `--paste-code`), for SSH sessions without port forwarding
+    pub paste_code: bool,
+
+    // Ask on the terminal before an upload over UPLOAD_CONFIRM_MB (manual runs without `--yes`)
+    pub confirm_uploads: bool,
+
+    // Profile name when loaded for a tenant in batch mode (None = default profile)
+    pub profile: Option<String>,
 }
 
 impl Config {
     /// Load configuration from environment variables
     pub fn from_env() -> Result<Self> {
-        // Load .env file from multiple possible locations
-        // Priority: 1. Current directory, 2. docker/.env, 3. Parent directory
+        Self::load(false)
+    }
+
+    /// Load configuration, forcing mock mode on when `force_mock` is set (e.g. `--mock`)
+    pub fn load(force_mock: bool) -> Result<Self> {
+        Self::load_with(Flags { mock: force_mock, ..Flags::default() })
+    }
+
+    /// Load configuration with the command line's switches applied
+    pub fn load_with(flags: Flags) -> Result<Self> {
+        Self::load_dotenv();
+        Self::build(flags, None, |key| env::var(key).ok())
+    }
+
+    /// Mock-mode configuration built from `vars` alone, so tests see neither `.env` nor the
+    /// process environment
+    #[cfg(test)]
+    pub(crate) fn for_test(vars: &[(&str, &str)]) -> Self {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        Self::build(Flags { mock: true, ..Flags::default() }, None, |key| vars.get(key).map(|v| v.to_string()))
+            .expect("test configuration")
+    }
+
+    /// Load a named profile: values in the profile's env file override the base environment
+    pub fn load_profile(name: &str, path: &Path, flags: Flags) -> Result<Self> {
+        Self::load_dotenv();
+
+        let overrides = dotenvy::from_path_iter(path)
+            .with_context(|| format!("Failed to read profile {}", path.display()))?
+            .collect::<Result<HashMap<String, String>, _>>()
+            .with_context(|| format!("Failed to parse profile {}", path.display()))?;
+
+        Self::build(flags, Some(name.to_string()), |key| {
+            overrides.get(key).cloned().or_else(|| env::var(key).ok())
+        })
+    }
+
+    /// ProtonMail Bridge settings (PROTON_BRIDGE_*), with the Bridge's default ports
+    fn proton_bridge(var: &impl Fn(&str) -> Option<String>, credential: &impl Fn(&str) -> Result<String>) -> Result<BridgeSettings> {
+        let port = |key: &str, default: u16| -> Result<u16> {
+            var(key)
+                .map(|s| s.trim().parse().with_context(|| format!("{} must be a port number", key)))
+                .transpose()
+                .map(|port| port.unwrap_or(default))
+        };
+        Ok(BridgeSettings {
+            host: var("PROTON_BRIDGE_HOST").filter(|s| !s.trim().is_empty()).unwrap_or_else(|| "127.0.0.1".to_string()),
+            imap_port: port("PROTON_BRIDGE_IMAP_PORT", 1143)?,
+            smtp_port: port("PROTON_BRIDGE_SMTP_PORT", 1025)?,
+            security: var("PROTON_BRIDGE_SECURITY")
+                .map(|s| TlsMode::parse(&s, "PROTON_BRIDGE_SECURITY"))
+                .transpose()?
+                .unwrap_or(TlsMode::StartTls),
+            username: credential("PROTON_BRIDGE_USERNAME")?,
+            password: credential("PROTON_BRIDGE_PASSWORD")?,
+            cert: var("PROTON_BRIDGE_CERT").filter(|s| !s.trim().is_empty()).map(PathBuf::from),
+            mailbox: var("PROTON_BRIDGE_MAILBOX").filter(|s| !s.trim().is_empty()).unwrap_or_else(|| "All Mail".to_string()),
+        })
+    }
+
+    /// A retention age in whole years (RETENTION_*_AFTER_YEARS), unset when empty
+    fn retention_years(var: &impl Fn(&str) -> Option<String>, key: &str) -> Result<Option<u32>> {
+        var(key)
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| s.trim().parse::<u32>().ok().filter(|years| *years > 0).with_context(|| format!("{} must be a number of years", key)))
+            .transpose()
+    }
+
+    /// SFTP storage settings (SFTP_*)
+    fn sftp(var: &impl Fn(&str) -> Option<String>, credential: &impl Fn(&str) -> Result<String>) -> Result<SftpSettings> {
+        let path = |key: &str| var(key).filter(|s| !s.trim().is_empty()).map(PathBuf::from);
+        Ok(SftpSettings {
+            host: credential("SFTP_HOST")?,
+            port: var("SFTP_PORT")
+                .map(|s| s.trim().parse().context("SFTP_PORT must be a port number"))
+                .transpose()?
+                .unwrap_or(22),
+            username: credential("SFTP_USERNAME")?,
+            key: path("SFTP_KEY"),
+            password: var("SFTP_PASSWORD").filter(|s| !s.is_empty()),
+            known_hosts: path("SFTP_KNOWN_HOSTS"),
+            remote_dir: var("SFTP_REMOTE_DIR").map(|s| s.trim().to_string()).unwrap_or_default(),
+        })
+    }
+
+    /// TARGET_KEYWORDS_TO_FETCH_AND_DOWNLOAD merged with the KEYWORD_LANGUAGES packs.
+    /// The built-in default only applies when neither is set.
+    fn target_keywords(var: &impl Fn(&str) -> Option<String>) -> Result<Vec<String>> {
+        let languages = var("KEYWORD_LANGUAGES").unwrap_or_default();
+        let user = var("TARGET_KEYWORDS_TO_FETCH_AND_DOWNLOAD").unwrap_or_else(|| {
+            if languages.trim().is_empty() { "invoice,invoices,fatura,faturas".to_string() } else { String::new() }
+        });
+
+        let user = user
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        Ok(keywords::merge(user, keywords::pack_keywords(&languages)?))
+    }
+
+    /// Exclusions applied to every keyword search
+    pub fn search_exclusions(&self) -> Exclusions {
+        Exclusions {
+            keywords: self.exclude_keywords.clone(),
+            categories: self.exclude_categories.clone(),
+            include_spam: self.include_spam,
+            include_trash: self.include_trash,
+        }
+    }
+
+    /// Duplicate handling for a vendor (its filename prefix, e.g. "hetzner-online-gmbh")
+    pub fn duplicate_action(&self, vendor: &str) -> DuplicateAction {
+        self.semantic_duplicate_vendors
+            .iter()
+            .find(|(name, _)| vendor.contains(name.as_str()))
+            .map_or(self.semantic_duplicates, |(_, action)| *action)
+    }
+
+    /// Whether any vendor has semantic duplicate detection enabled
+    pub fn semantic_duplicates_enabled(&self) -> bool {
+        self.semantic_duplicates != DuplicateAction::Off
+            || self.semantic_duplicate_vendors.iter().any(|(_, action)| *action != DuplicateAction::Off)
+    }
+
+    /// Flagged attachments are kept under the user's data directory until reviewed
+    fn default_quarantine_dir() -> PathBuf {
+        dirs::data_local_dir()
+            .unwrap_or_else(env::temp_dir)
+            .join("invoice-agent")
+            .join("quarantine")
+    }
+
+    /// Failure reports sit next to the quarantine, under the user's data directory
+    fn default_failure_report_dir() -> PathBuf {
+        dirs::data_local_dir()
+            .unwrap_or_else(env::temp_dir)
+            .join("invoice-agent")
+            .join("failures")
+    }
+
+    /// Load .env file from multiple possible locations
+    /// Priority: 1. Current directory, 2. docker/.env, 3. Parent directory
+    pub fn load_dotenv() {
         if dotenvy::dotenv().is_err() {
             if dotenvy::from_path("docker/.env").is_err() {
                 dotenvy::from_path("../.env").ok();
             }
         }
+    }
 
+    /// Build configuration from a key lookup (process environment, optionally overlaid by a profile)
+    fn build(flags: Flags, profile: Option<String>, var: impl Fn(&str) -> Option<String>) -> Result<Self> {
         // Parse date range
         let (start_date, end_date) = Self::parse_date_range()?;
 
+        // Google credentials are not needed when running against fixtures
+        let mock_mode = flags.mock || var("MOCK_MODE").is_some_and(|v| is_truthy(&v));
+        let credential = |key: &str| -> Result<String> {
+            match var(key) {
+                Some(value) => Ok(value),
+                None if mock_mode => Ok(format!("mock-{}", key.to_lowercase())),
+                None => anyhow::bail!("{} not set in .env", key),
+            }
+        };
+
+        // OAuth clients set in .env win; otherwise both come from the Cloud console's client_secret.json.
+        // `keys` are tried in order, so Drive can fall back to the Gmail client.
+        let client_secret = var("GOOGLE_CLIENT_SECRET_FILE")
+            .filter(|s| !s.trim().is_empty())
+            .map(|path| ClientSecret::load(Path::new(path.trim())))
+            .transpose()
+            .context("GOOGLE_CLIENT_SECRET_FILE is invalid")?;
+        let google_credential = |keys: &[&str]| -> Result<String> {
+            let from_file = client_secret.as_ref().map(|secret| match keys[0].ends_with("_CLIENT_ID") {
+                true => secret.client_id.clone(),
+                false => secret.client_secret.clone(),
+            });
+            match keys.iter().find_map(|key| var(key).filter(|s| !s.trim().is_empty())).or(from_file) {
+                Some(value) => Ok(value),
+                None if mock_mode => credential(keys[0]),
+                None => anyhow::bail!(
+                    "{} not set in .env (or set GOOGLE_CLIENT_SECRET_FILE to the client_secret.json from the Cloud console)",
+                    keys.join(" or ")
+                ),
+            }
+        };
+
+        let mail_source = var("MAIL_SOURCE")
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| MailSourceKind::parse(&s))
+            .transpose()?
+            .unwrap_or(MailSourceKind::Gmail);
+        // Gmail credentials are only needed when Gmail is the mail source
+        let gmail_credential = |key: &str| match mail_source {
+            MailSourceKind::Gmail => google_credential(&[key]),
+            _ => Ok(var(key).unwrap_or_default()),
+        };
+
+        let storage_backend = var("STORAGE_BACKEND")
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| StorageKind::parse("STORAGE_BACKEND", &s))
+            .transpose()?
+            .unwrap_or(StorageKind::Drive);
+        let storage_mirror = var("STORAGE_MIRROR")
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| StorageKind::parse("STORAGE_MIRROR", &s))
+            .transpose()?;
+        let uses_storage = |kind: StorageKind| storage_backend == kind || storage_mirror == Some(kind);
+        let gcs_service_account_key = var("GCS_SERVICE_ACCOUNT_KEY").filter(|s| !s.trim().is_empty()).map(PathBuf::from);
+        // The Drive OAuth client is needed for Drive, and for Cloud Storage without a service account
+        let needs_drive_client = uses_storage(StorageKind::Drive) || uses_storage(StorageKind::Gcs) && gcs_service_account_key.is_none();
+        // A client ID from one source with the secret from another would only fail at sign-in
+        for account in ["GMAIL", "DRIVE"] {
+            let [id, secret] = ["ID", "SECRET"].map(|part| var(&format!("GOOGLE_{}_CLIENT_{}", account, part)).is_some_and(|s| !s.trim().is_empty()));
+            if id != secret {
+                anyhow::bail!("Set both GOOGLE_{0}_CLIENT_ID and GOOGLE_{0}_CLIENT_SECRET, or neither", account);
+            }
+        }
+        // One OAuth client can serve both APIs: unset Drive credentials default to the Gmail ones,
+        // while each account still signs in and keeps its own token
+        let drive_credential = |key: &str, gmail_key: &str| match needs_drive_client {
+            true => google_credential(&[key, gmail_key]),
+            false => Ok(var(key).unwrap_or_default()),
+        };
+
         let config = Config {
-            gmail_client_id: env::var("GOOGLE_GMAIL_CLIENT_ID")
-                .context("GOOGLE_GMAIL_CLIENT_ID not set in .env")?,
-            gmail_client_secret: env::var("GOOGLE_GMAIL_CLIENT_SECRET")
-                .context("GOOGLE_GMAIL_CLIENT_SECRET not set in .env")?,
-            drive_client_id: env::var("GOOGLE_DRIVE_CLIENT_ID")
-                .context("GOOGLE_DRIVE_CLIENT_ID not set in .env")?,
-            drive_client_secret: env::var("GOOGLE_DRIVE_CLIENT_SECRET")
-                .context("GOOGLE_DRIVE_CLIENT_SECRET not set in .env")?,
-            drive_folder_path: env::var("GOOGLE_DRIVE_FOLDER_LOCATION")
-                .context("GOOGLE_DRIVE_FOLDER_LOCATION not set in .env")?,
-            fetch_invoices_day: env::var("FETCH_INVOICES_DAY")
-                .ok()
-                .map(|s| s.parse().context("FETCH_INVOICES_DAY must be a number between 1-31"))
+            mail_source,
+            proton_bridge: match mail_source {
+                MailSourceKind::ProtonBridge => Some(Self::proton_bridge(&var, &credential)?),
+                _ => None,
+            },
+            jmap: match mail_source {
+                MailSourceKind::Jmap => Some(JmapSettings {
+                    session_url: var("JMAP_SESSION_URL")
+                        .filter(|s| !s.trim().is_empty())
+                        .unwrap_or_else(|| jmap::FASTMAIL_SESSION_URL.to_string()),
+                    token: credential("JMAP_TOKEN")?,
+                }),
+                _ => None,
+            },
+            maildir_path: match mail_source {
+                MailSourceKind::Maildir => Some(PathBuf::from(
+                    var("MAILDIR_PATH").filter(|s| !s.trim().is_empty()).context("MAILDIR_PATH not set in .env")?,
+                )),
+                _ => None,
+            },
+            gmail_client_id: gmail_credential("GOOGLE_GMAIL_CLIENT_ID")?,
+            gmail_client_secret: gmail_credential("GOOGLE_GMAIL_CLIENT_SECRET")?,
+            gmail_account: var("GOOGLE_GMAIL_ACCOUNT").map(|s| s.trim().to_string()).filter(|s| !s.is_empty()),
+            gmail_user: var("GMAIL_USER").map(|s| s.trim().to_string()).filter(|s| !s.is_empty() && s != "me"),
+            google_service_account_key: var("GOOGLE_SERVICE_ACCOUNT_KEY").filter(|s| !s.trim().is_empty()).map(PathBuf::from),
+            workspace_users: var("WORKSPACE_USERS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            storage_backend,
+            storage_mirror,
+            sftp: if uses_storage(StorageKind::Sftp) { Some(Self::sftp(&var, &credential)?) } else { None },
+            gcs: if uses_storage(StorageKind::Gcs) {
+                Some(GcsSettings {
+                    bucket: credential("GCS_BUCKET")?,
+                    prefix: var("GCS_PREFIX").map(|s| s.trim().to_string()).unwrap_or_default(),
+                    service_account_key: gcs_service_account_key.clone(),
+                    object_metadata: var("GCS_OBJECT_METADATA").is_some_and(|s| s.trim().eq_ignore_ascii_case("true")),
+                })
+            } else {
+                None
+            },
+            local_archive_dir: if uses_storage(StorageKind::Local) {
+                Some(PathBuf::from(
+                    var("LOCAL_ARCHIVE_DIR").filter(|s| !s.trim().is_empty()).context("LOCAL_ARCHIVE_DIR not set in .env")?,
+                ))
+            } else {
+                None
+            },
+            drive_client_id: drive_credential("GOOGLE_DRIVE_CLIENT_ID", "GOOGLE_GMAIL_CLIENT_ID")?,
+            drive_client_secret: drive_credential("GOOGLE_DRIVE_CLIENT_SECRET", "GOOGLE_GMAIL_CLIENT_SECRET")?,
+            drive_account: var("GOOGLE_DRIVE_ACCOUNT").map(|s| s.trim().to_string()).filter(|s| !s.is_empty()),
+            drive_folder_path: match var("GOOGLE_DRIVE_FOLDER_LOCATION") {
+                Some(path) => path,
+                None if mock_mode => "billing/mock".to_string(),
+                None => anyhow::bail!("GOOGLE_DRIVE_FOLDER_LOCATION not set in .env"),
+            },
+            fiscal_calendar: match var("FISCAL_YEAR_START").filter(|s| !s.trim().is_empty()) {
+                Some(month) => FiscalCalendar::parse(&month)
+                    .with_context(|| format!("FISCAL_YEAR_START must be a month, 1-12 or its name (got '{}')", month))?,
+                None => FiscalCalendar::default(),
+            },
+            fetch_invoices_day: var("FETCH_INVOICES_DAY")
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| ScheduleDay::parse(&s).context("Invalid FETCH_INVOICES_DAY"))
                 .transpose()?,
-            target_keywords: env::var("TARGET_KEYWORDS_TO_FETCH_AND_DOWNLOAD")
-                .unwrap_or_else(|_| "invoice,invoices,fatura,faturas".to_string())
+            holidays: var("HOLIDAYS")
+                .unwrap_or_default()
+                .split(',')
+                .filter(|s| !s.trim().is_empty())
+                .map(Holiday::parse)
+                .collect::<Result<_>>()?,
+            schedule_time: var("SCHEDULE_TIME")
+                .map(|s| NaiveTime::parse_from_str(s.trim(), "%H:%M").context("SCHEDULE_TIME must look like 09:00"))
+                .transpose()?
+                .unwrap_or_else(|| NaiveTime::from_hms_opt(9, 0, 0).unwrap_or_default()),
+            schedule_jitter_minutes: var("SCHEDULE_JITTER_MINUTES")
+                .map(|s| s.parse().context("SCHEDULE_JITTER_MINUTES must be a number of minutes"))
+                .transpose()?
+                .unwrap_or(0),
+            schedule_retries: var("SCHEDULE_RETRIES")
+                .map(|s| s.parse().context("SCHEDULE_RETRIES must be a number"))
+                .transpose()?
+                .unwrap_or(0),
+            schedule_retry_delay_minutes: var("SCHEDULE_RETRY_DELAY_MINUTES")
+                .map(|s| s.parse().context("SCHEDULE_RETRY_DELAY_MINUTES must be a number of minutes"))
+                .transpose()?
+                .unwrap_or(30),
+            upload_run_artifacts: var("RUN_ARTIFACTS_UPLOAD").is_some_and(|v| is_truthy(&v)),
+            heartbeat_stale_minutes: var("HEARTBEAT_STALE_MINUTES")
+                .map(|s| s.parse().context("HEARTBEAT_STALE_MINUTES must be a number of minutes"))
+                .transpose()?
+                .unwrap_or(30),
+            oauth_refresh_token_days: var("OAUTH_REFRESH_TOKEN_DAYS")
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| s.trim().parse().context("OAUTH_REFRESH_TOKEN_DAYS must be a number of days"))
+                .transpose()?
+                .unwrap_or(0),
+            target_keywords: Self::target_keywords(&var)?,
+            exclude_keywords: var("EXCLUDE_KEYWORDS")
+                .unwrap_or_default()
                 .split(',')
                 .map(|s| s.trim().to_string())
                 .filter(|s| !s.is_empty())
                 .collect(),
+            exclude_categories: var("EXCLUDE_CATEGORIES")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            include_spam: var("INCLUDE_SPAM").is_some_and(|v| is_truthy(&v)),
+            include_trash: var("INCLUDE_TRASH").is_some_and(|v| is_truthy(&v)),
+            attachment_name_patterns: var("ATTACHMENT_NAME_PATTERNS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            vendor_aliases: vendors::parse_aliases(&var("VENDOR_ALIASES").unwrap_or_default())
+                .context("Invalid VENDOR_ALIASES")?,
+            document_date_check: match var("DOCUMENT_DATE_CHECK").map(|s| s.trim().to_lowercase()).as_deref() {
+                None | Some("" | "off") => DocumentDateCheck::Off,
+                Some("warn") => DocumentDateCheck::Warn,
+                Some("skip") => DocumentDateCheck::Skip,
+                Some(other) => anyhow::bail!("DOCUMENT_DATE_CHECK must be off, warn or skip (got '{}')", other),
+            },
+            document_date_tolerance_days: var("DOCUMENT_DATE_TOLERANCE_DAYS")
+                .map(|s| s.parse().context("DOCUMENT_DATE_TOLERANCE_DAYS must be a number of days"))
+                .transpose()?
+                .unwrap_or(31),
+            semantic_duplicates: var("SEMANTIC_DUPLICATES")
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| DuplicateAction::parse(&s, "SEMANTIC_DUPLICATES"))
+                .transpose()?
+                .unwrap_or(DuplicateAction::Off),
+            semantic_duplicate_vendors: var("SEMANTIC_DUPLICATES_VENDORS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|entry| {
+                    let (vendor, action) = entry
+                        .split_once('=')
+                        .with_context(|| format!("SEMANTIC_DUPLICATES_VENDORS entry '{}' must look like vendor=skip", entry))?;
+                    Ok((vendor.trim().to_lowercase(), DuplicateAction::parse(action, "SEMANTIC_DUPLICATES_VENDORS")?))
+                })
+                .collect::<Result<_>>()?,
+            ingest_queue: var("INGEST_QUEUE").filter(|s| !s.trim().is_empty()).map(|s| IngestQueue::parse(&s)),
+            ingest_lookback_days: var("INGEST_LOOKBACK_DAYS")
+                .map(|s| s.parse().context("INGEST_LOOKBACK_DAYS must be a number of days"))
+                .transpose()?
+                .unwrap_or(30),
+            search_concurrency: var("SEARCH_CONCURRENCY")
+                .map(|s| s.parse().context("SEARCH_CONCURRENCY must be a positive number"))
+                .transpose()?
+                .unwrap_or(4),
+            download_concurrency: var("DOWNLOAD_CONCURRENCY")
+                .map(|s| s.parse().context("DOWNLOAD_CONCURRENCY must be a positive number"))
+                .transpose()?
+                .unwrap_or(4),
+            transform_concurrency: var("TRANSFORM_CONCURRENCY")
+                .map(|s| s.parse().context("TRANSFORM_CONCURRENCY must be a positive number"))
+                .transpose()?
+                .unwrap_or(2),
+            adaptive_concurrency: var("ADAPTIVE_CONCURRENCY").is_none_or(|v| is_truthy(&v)),
+            upload_queue: var("UPLOAD_QUEUE")
+                .map(|s| s.parse().context("UPLOAD_QUEUE must be a positive number"))
+                .transpose()?
+                .unwrap_or(2),
+            search_warn_messages: var("SEARCH_WARN_MESSAGES")
+                .map(|s| s.parse().context("SEARCH_WARN_MESSAGES must be a number of messages"))
+                .transpose()?
+                .unwrap_or(1000),
+            upload_confirm_mb: var("UPLOAD_CONFIRM_MB")
+                .map(|s| s.parse().context("UPLOAD_CONFIRM_MB must be a number of megabytes"))
+                .transpose()?
+                .unwrap_or(500),
+            upload_speed_mbps: var("UPLOAD_SPEED_MBPS")
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| s.trim().parse::<f64>().ok().filter(|mbps| *mbps > 0.0).context("UPLOAD_SPEED_MBPS must be a positive number of Mbit/s"))
+                .transpose()?,
+            message_limit: None,
+            newest_first: false,
+            mailbox_timezone: var("MAILBOX_TIMEZONE")
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| s.trim().parse::<Tz>().map_err(|_| anyhow::anyhow!("MAILBOX_TIMEZONE '{}' is not a valid IANA timezone (e.g. Europe/Lisbon)", s)))
+                .transpose()?,
             start_date,
             end_date,
-            debug_logs_enabled: env::var("DEBUG_LOGS_ENABLED")
-                .unwrap_or_else(|_| "false".to_string())
+            debug_logs_enabled: var("DEBUG_LOGS_ENABLED")
+                .unwrap_or_else(|| "false".to_string())
                 .to_lowercase() == "true",
+            mock_mode,
+            mock_fixtures_dir: var("MOCK_FIXTURES_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_MOCK_FIXTURES_DIR)),
+            mock_drive_dir: var("MOCK_DRIVE_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| std::env::temp_dir().join("invoice-agent-mock-drive")),
+            on_run_success: var("ON_RUN_SUCCESS").filter(|s| !s.trim().is_empty()),
+            on_run_failure: var("ON_RUN_FAILURE").filter(|s| !s.trim().is_empty()),
+            on_file_uploaded: var("ON_FILE_UPLOADED").filter(|s| !s.trim().is_empty()),
+            pre_upload_transform: var("PRE_UPLOAD_TRANSFORM").filter(|s| !s.trim().is_empty()),
+            phishing_checks: var("PHISHING_CHECKS").is_none_or(|v| is_truthy(&v)),
+            review_folder: var("REVIEW_FOLDER")
+                .filter(|s| !s.trim().is_empty())
+                .unwrap_or_else(|| "Review".to_string()),
+            clamd_socket: var("CLAMD_SOCKET").filter(|s| !s.trim().is_empty()),
+            virus_scan_command: var("VIRUS_SCAN_COMMAND").filter(|s| !s.trim().is_empty()),
+            quarantine_dir: var("QUARANTINE_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(Self::default_quarantine_dir),
+            failure_report_dir: var("FAILURE_REPORT_DIR")
+                .filter(|s| !s.trim().is_empty())
+                .map(PathBuf::from)
+                .unwrap_or_else(Self::default_failure_report_dir),
+            pdf_compression: var("PDF_COMPRESSION")
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty() && s != "off"),
+            pdf_compression_skip_senders: var("PDF_COMPRESSION_SKIP_SENDERS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            ghostscript_path: var("GHOSTSCRIPT_PATH").unwrap_or_else(|| "gs".to_string()),
+            credit_notes_subfolder: var("CREDIT_NOTES_SUBFOLDER").is_some_and(|v| is_truthy(&v)),
+            filename_sanitize: sanitize::Strictness::parse(&var("FILENAME_SANITIZE").unwrap_or_default())?,
+            decrypt_attachments: var("DECRYPT_ATTACHMENTS").is_some_and(|v| is_truthy(&v)),
+            gpg_homedir: var("GPG_HOMEDIR").filter(|s| !s.trim().is_empty()).map(PathBuf::from),
+            gpg_passphrase_file: var("GPG_PASSPHRASE_FILE").filter(|s| !s.trim().is_empty()).map(PathBuf::from),
+            smime_key_file: var("SMIME_KEY_FILE").filter(|s| !s.trim().is_empty()).map(PathBuf::from),
+            smime_cert_file: var("SMIME_CERT_FILE").filter(|s| !s.trim().is_empty()).map(PathBuf::from),
+            encryption_recipients: var("ENCRYPTION_RECIPIENTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            wasm_plugins: var("WASM_PLUGINS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(PathBuf::from)
+                .collect(),
+            rules_file: match var("RULES_FILE").filter(|s| !s.trim().is_empty()) {
+                Some(path) => Some(PathBuf::from(path)),
+                None => Some(PathBuf::from("rules.toml")).filter(|path| path.exists()),
+            },
+            statement_formats_file: match var("STATEMENT_FORMATS_FILE").filter(|s| !s.trim().is_empty()) {
+                Some(path) => Some(PathBuf::from(path)),
+                None => {
+                    let profile_dir = profile.as_deref().and_then(|name| Some(crate::auth::oauth::config_dir_path()?.join("profiles").join(name)));
+                    profile_dir.into_iter().chain(crate::auth::oauth::config_dir_path()).map(|dir| dir.join("statements.toml")).find(|path| path.exists())
+                }
+            },
+            notifications_file: match var("NOTIFICATIONS_FILE").filter(|s| !s.trim().is_empty()) {
+                Some(path) => Some(PathBuf::from(path)),
+                None => Some(PathBuf::from("notifications.toml")).filter(|path| path.exists()),
+            },
+            accountant_email: var("ACCOUNTANT_EMAIL").filter(|s| !s.trim().is_empty()),
+            handoff_drive_folder: var("HANDOFF_DRIVE_FOLDER").filter(|s| !s.trim().is_empty()),
+            retention: RetentionPolicy {
+                compress_after_years: Self::retention_years(&var, "RETENTION_COMPRESS_AFTER_YEARS")?,
+                cold_storage_after_years: Self::retention_years(&var, "RETENTION_COLD_STORAGE_AFTER_YEARS")?,
+                cold_storage_folder: var("RETENTION_COLD_STORAGE_FOLDER").map(|s| s.trim().trim_matches('/').to_string()).filter(|s| !s.is_empty()),
+                delete_after_years: Self::retention_years(&var, "RETENTION_DELETE_AFTER_YEARS")?,
+            },
+            digest_cadence: match var("DIGEST_CADENCE").map(|s| s.trim().to_lowercase()).as_deref() {
+                None | Some("" | "weekly") => DigestCadence::Weekly,
+                Some("monthly") => DigestCadence::Monthly,
+                Some(other) => anyhow::bail!("DIGEST_CADENCE must be weekly or monthly (got '{}')", other),
+            },
+            digest_email: var("DIGEST_EMAIL").filter(|s| !s.trim().is_empty()),
+            on_digest: var("ON_DIGEST").filter(|s| !s.trim().is_empty()),
+            budgets: budget::parse_budgets(&var("BUDGETS").unwrap_or_default()).context("Invalid BUDGETS")?,
+            on_budget_alert: var("ON_BUDGET_ALERT").filter(|s| !s.trim().is_empty()),
+            due_reminder_days: match var("DUE_REMINDER_DAYS").filter(|s| !s.trim().is_empty()) {
+                Some(days) => days.trim().parse().ok().filter(|days: &i64| *days >= 0)
+                    .with_context(|| format!("DUE_REMINDER_DAYS must be a number of days (got '{}')", days))?,
+                None => DEFAULT_REMINDER_DAYS,
+            },
+            on_payment_due: var("ON_PAYMENT_DUE").filter(|s| !s.trim().is_empty()),
+            dashboard_token: var("DASHBOARD_TOKEN").map(|s| s.trim().to_string()).filter(|s| !s.is_empty()),
+            grpc_tls_cert: var("GRPC_TLS_CERT").filter(|s| !s.trim().is_empty()).map(PathBuf::from),
+            grpc_tls_key: var("GRPC_TLS_KEY").filter(|s| !s.trim().is_empty()).map(PathBuf::from),
+            grpc_client_ca: var("GRPC_CLIENT_CA").filter(|s| !s.trim().is_empty()).map(PathBuf::from),
+            read_only: flags.read_only || var("READ_ONLY").is_some_and(|v| is_truthy(&v)),
+            refresh: flags.refresh,
+            paste_code: flags.paste_code || var("OAUTH_PASTE_CODE").is_some_and(|v| is_truthy(&v)),
+            confirm_uploads: false,
+            profile,
         };
 
         config.validate()?;
         Ok(config)
     }
 
+    /// Fail with why when read-only mode forbids `action` (e.g. "Starting a run")
+    pub fn ensure_writable(&self, action: &str) -> Result<()> {
+        if self.read_only {
+            return Err(read_only_refusal(action));
+        }
+        Ok(())
+    }
+
+    /// Scratch directory for downloaded attachments, separate per profile so tenants never collide
+    pub fn temp_dir(&self) -> PathBuf {
+        let base = std::env::temp_dir().join("invoice-agent");
+        match &self.profile {
+            Some(name) => base.join(name),
+            None => base,
+        }
+    }
+
     /// Parse date range using smart defaults
     /// Default: 1st of last month to today
     /// Example: If today is 2024-10-15, defaults to 2024-09-01 to 2024-10-15 (45 days)
@@ -97,26 +943,111 @@ impl Config {
 
     /// Validate configuration values
     fn validate(&self) -> Result<()> {
-        if let Some(day) = self.fetch_invoices_day {
-            if day < 1 || day > 31 {
-                anyhow::bail!("FETCH_INVOICES_DAY must be between 1 and 31");
+        if self.mail_source == MailSourceKind::Gmail && self.gmail_client_id.is_empty() {
+            anyhow::bail!("GOOGLE_GMAIL_CLIENT_ID cannot be empty");
+        }
+
+        if self.google_service_account_key.is_some() && self.gmail_user.is_none() {
+            anyhow::bail!("GOOGLE_SERVICE_ACCOUNT_KEY needs GMAIL_USER, the Workspace mailbox to read");
+        }
+
+        for (key, account) in [("GOOGLE_GMAIL_ACCOUNT", &self.gmail_account), ("GOOGLE_DRIVE_ACCOUNT", &self.drive_account)] {
+            if account.as_ref().is_some_and(|account| !account.contains('@')) {
+                anyhow::bail!("{} must be the email address of a Google account", key);
             }
         }
 
-        if self.gmail_client_id.is_empty() {
-            anyhow::bail!("GOOGLE_GMAIL_CLIENT_ID cannot be empty");
+        if let Some(user) = self.workspace_users.iter().find(|user| !user.contains('@') || user.contains('/')) {
+            anyhow::bail!("WORKSPACE_USERS entry '{}' is not an email address", user);
         }
 
-        if self.drive_client_id.is_empty() {
+        if self.storage_mirror == Some(self.storage_backend) {
+            anyhow::bail!("STORAGE_MIRROR must be a different storage than STORAGE_BACKEND");
+        }
+
+        let needs_drive_client = std::iter::once(self.storage_backend).chain(self.storage_mirror).any(|kind| match kind {
+            StorageKind::Drive => true,
+            StorageKind::Gcs => self.gcs.as_ref().is_some_and(|gcs| gcs.service_account_key.is_none()),
+            StorageKind::Sftp | StorageKind::Local => false,
+        });
+        if needs_drive_client && self.drive_client_id.is_empty() {
             anyhow::bail!("GOOGLE_DRIVE_CLIENT_ID cannot be empty");
         }
 
+        // JMAP searches every mailbox, spam and trash included, without telling them apart
+        if self.mail_source == MailSourceKind::Jmap && (self.include_spam || self.include_trash) {
+            anyhow::bail!("INCLUDE_SPAM and INCLUDE_TRASH are not supported with MAIL_SOURCE=jmap");
+        }
+
         if self.target_keywords.is_empty() {
             anyhow::bail!("TARGET_KEYWORDS_TO_FETCH_AND_DOWNLOAD must contain at least one keyword");
         }
 
+        if self.search_concurrency == 0 {
+            anyhow::bail!("SEARCH_CONCURRENCY must be at least 1");
+        }
+
+        for (name, value) in [("DOWNLOAD_CONCURRENCY", self.download_concurrency), ("TRANSFORM_CONCURRENCY", self.transform_concurrency), ("UPLOAD_QUEUE", self.upload_queue)] {
+            if value == 0 {
+                anyhow::bail!("{} must be at least 1", name);
+            }
+        }
+
+        if let Some(quality) = &self.pdf_compression && !PDF_QUALITIES.contains(&quality.as_str()) {
+            anyhow::bail!("PDF_COMPRESSION must be one of off, {}", PDF_QUALITIES.join(", "));
+        }
+
+        fiscal::check_template(&self.drive_folder_path).context("GOOGLE_DRIVE_FOLDER_LOCATION is invalid")?;
+
+        encrypt::parse_recipients(&self.encryption_recipients).context("ENCRYPTION_RECIPIENTS is invalid")?;
+
+        let retention = &self.retention;
+        if retention.cold_storage_after_years.is_some() && retention.cold_storage_folder.is_none() {
+            anyhow::bail!("RETENTION_COLD_STORAGE_AFTER_YEARS needs RETENTION_COLD_STORAGE_FOLDER, the Drive folder months are moved to");
+        }
+        if let Some(delete_after) = retention.delete_after_years
+            && [retention.compress_after_years, retention.cold_storage_after_years].into_iter().flatten().any(|years| years >= delete_after)
+        {
+            anyhow::bail!("RETENTION_DELETE_AFTER_YEARS must be longer than the compression and cold storage ages");
+        }
+
         Ok(())
     }
 }
 
+/// Interpret a boolean flag value ("true"/"1"/"yes", case-insensitive)
+fn is_truthy(value: &str) -> bool {
+    matches!(value.trim().to_lowercase().as_str(), "true" | "1" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drive_client_defaults_to_gmail_client() {
+        let build = |pairs: &[(&str, &str)]| {
+            let vars: HashMap<String, String> = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            Config::build(Flags::default(), None, |key| vars.get(key).cloned())
+        };
+        let gmail = [
+            ("GOOGLE_GMAIL_CLIENT_ID", "shared.apps.googleusercontent.com"),
+            ("GOOGLE_GMAIL_CLIENT_SECRET", "shared-secret"),
+            ("GOOGLE_DRIVE_FOLDER_LOCATION", "billing"),
+        ];
+
+        let config = build(&gmail).unwrap();
+        assert_eq!(config.drive_client_id, "shared.apps.googleusercontent.com");
+        assert_eq!(config.drive_client_secret, "shared-secret");
+
+        // Mail and storage in different Google accounts, each with its own client
+        let split = [gmail.as_slice(), &[("GOOGLE_DRIVE_CLIENT_ID", "drive.apps.googleusercontent.com"), ("GOOGLE_DRIVE_CLIENT_SECRET", "drive-secret")]].concat();
+        let config = build(&split).unwrap();
+        assert_eq!(config.gmail_client_id, "shared.apps.googleusercontent.com");
+        assert_eq!(config.drive_client_id, "drive.apps.googleusercontent.com");
+        assert_eq!(config.drive_client_secret, "drive-secret");
 
+        let half = [gmail.as_slice(), &[("GOOGLE_DRIVE_CLIENT_ID", "drive.apps.googleusercontent.com")]].concat();
+        assert!(build(&half).is_err());
+    }
+}
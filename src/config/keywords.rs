@@ -0,0 +1,56 @@
+use anyhow::Result;
+
+/// Built-in search keywords per language, selected with KEYWORD_LANGUAGES
+const KEYWORD_PACKS: &[(&str, &[&str])] = &[
+    ("en", &["invoice", "invoices", "receipt", "receipts", "statement", "bill"]),
+    ("pt", &["fatura", "faturas", "recibo", "recibos", "extrato", "\"nota fiscal\""]),
+    ("es", &["factura", "facturas", "recibo", "recibos", "extracto", "movimientos"]),
+    ("de", &["rechnung", "rechnungen", "quittung", "beleg", "kontoauszug"]),
+    ("fr", &["facture", "factures", "reçu", "relevé", "\"avis d'échéance\""]),
+    ("it", &["fattura", "fatture", "ricevuta", "ricevute", "\"estratto conto\""]),
+];
+
+/// Language codes that have a keyword pack
+pub fn languages() -> Vec<&'static str> {
+    KEYWORD_PACKS.iter().map(|(code, _)| *code).collect()
+}
+
+/// Keywords for a comma-separated language list (e.g. "en,pt,de")
+pub fn pack_keywords(languages: &str) -> Result<Vec<String>> {
+    let mut keywords = Vec::new();
+    for code in languages.split(',').map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()) {
+        let Some((_, pack)) = KEYWORD_PACKS.iter().find(|(lang, _)| *lang == code) else {
+            anyhow::bail!("KEYWORD_LANGUAGES: unknown language '{}' (available: {})", code, self::languages().join(", "));
+        };
+        keywords.extend(pack.iter().map(|k| k.to_string()));
+    }
+    Ok(keywords)
+}
+
+/// User keywords first, then pack keywords, without case-insensitive duplicates
+pub fn merge(user: Vec<String>, packs: Vec<String>) -> Vec<String> {
+    let mut merged: Vec<String> = Vec::new();
+    for keyword in user.into_iter().chain(packs) {
+        if !merged.iter().any(|k| k.eq_ignore_ascii_case(&keyword)) {
+            merged.push(keyword);
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packs_merge_with_user_keywords() {
+        let packs = pack_keywords("pt, DE").unwrap();
+        assert!(packs.contains(&"extrato".to_string()));
+        assert!(packs.contains(&"rechnung".to_string()));
+        assert!(pack_keywords("xx").is_err());
+
+        let merged = merge(vec!["Fatura".to_string(), "hetzner".to_string()], packs);
+        assert_eq!(&merged[..3], &["Fatura", "hetzner", "faturas"]);
+        assert_eq!(merged.iter().filter(|k| k.eq_ignore_ascii_case("fatura")).count(), 1);
+    }
+}
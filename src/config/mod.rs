@@ -1 +1,4 @@
 pub mod env;
+pub mod keywords;
+pub mod profiles;
+pub mod shared;
@@ -0,0 +1,60 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Default directory holding one `<name>.env` file per tenant profile
+pub const DEFAULT_PROFILES_DIR: &str = "profiles";
+
+/// A tenant profile: its name and the env file overriding the base configuration
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Find every `<name>.env` profile in the directory, sorted by name
+pub fn discover(dir: &Path) -> Result<Vec<Profile>> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read profiles directory {}", dir.display()))?;
+
+    let mut profiles = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("env") {
+            continue;
+        }
+
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+
+        // Profile names become token and temp directory names
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            anyhow::bail!("Invalid profile name '{}': use letters, digits, '-' or '_'", name);
+        }
+
+        profiles.push(Profile { name: name.to_string(), path: path.clone() });
+    }
+
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(profiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover_only_env_files_sorted() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("globex.env"), "").unwrap();
+        std::fs::write(dir.join("acme.env"), "").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let names: Vec<String> = discover(dir).unwrap().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, vec!["acme", "globex"]);
+
+        std::fs::write(dir.join("bad name.env"), "").unwrap();
+        assert!(discover(dir).is_err());
+    }
+}
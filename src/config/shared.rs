@@ -0,0 +1,94 @@
+//! Config and state shared by a team. INVOICE_PILOT_CONFIG_DIR moves the config directory (the
+//! cached tokens and the JSON state kept next to them) to a location several machines can reach,
+//! such as a network drive two bookkeepers both mount. Writes there take a lock and are merged
+//! with what is already on disk, so two machines saving at once don't drop each other's changes.
+//! The dedup registry (invoice records) and the run history live in Postgres, so a shared setup
+//! needs DATABASE_URL instead of each machine keeping its own.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_DIR_VAR: &str = "INVOICE_PILOT_CONFIG_DIR";
+
+/// The config directory set in INVOICE_PILOT_CONFIG_DIR, if any
+pub fn config_dir_override() -> Option<PathBuf> {
+    std::env::var(CONFIG_DIR_VAR).ok().filter(|dir| !dir.trim().is_empty()).map(PathBuf::from)
+}
+
+/// Whether the config directory is shared (set through INVOICE_PILOT_CONFIG_DIR)
+pub fn is_shared() -> bool {
+    config_dir_override().is_some()
+}
+
+/// Rewrite `path` while holding an exclusive lock on `<path>.lock`: `update` gets the current
+/// contents (None when the file doesn't exist yet) and returns the new ones, which replace the
+/// file in one rename so nobody reads it half written
+pub fn update(path: &Path, update: impl FnOnce(Option<String>) -> Result<String>) -> Result<()> {
+    let name = path.file_name().context("State path has no file name")?.to_string_lossy().into_owned();
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_file_name(format!("{}.lock", name)))
+        .with_context(|| format!("Failed to open the lock of {}", path.display()))?;
+    lock.lock().with_context(|| format!("Failed to lock {}", path.display()))?;
+
+    let current = match fs::read_to_string(path) {
+        Ok(contents) => Some(contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let contents = update(current)?;
+
+    let temp = path.with_file_name(format!(".{}.tmp-{}", name, std::process::id()));
+    fs::write(&temp, contents).with_context(|| format!("Failed to write {}", temp.display()))?;
+    fs::rename(&temp, path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// `update` for JSON state: `merge` folds this machine's changes into what is on disk
+pub fn update_json<T: Default + Serialize + DeserializeOwned>(path: &Path, merge: impl FnOnce(&mut T)) -> Result<()> {
+    update(path, |current| {
+        let mut state = match current {
+            Some(json) => serde_json::from_str(&json).with_context(|| format!("Failed to parse {}", path.display()))?,
+            None => T::default(),
+        };
+        merge(&mut state);
+        Ok(serde_json::to_string_pretty(&state)?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_update_merges_with_disk() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("ingested.json");
+
+        let handles: Vec<_> = (0..8)
+            .map(|machine| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    update_json(&path, |state: &mut BTreeMap<String, u32>| {
+                        state.insert(format!("machine-{}", machine), machine);
+                    })
+                    .unwrap()
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let state: BTreeMap<String, u32> = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(state.len(), 8);
+        assert!(fs::read_dir(dir).unwrap().all(|entry| !entry.unwrap().file_name().to_string_lossy().contains(".tmp-")));
+    }
+}
@@ -1,10 +1,17 @@
 use anyhow::{Context, Result};
-use chrono::Utc;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
 use sqlx::{postgres::PgPoolOptions, Postgres, Pool, Row as _};
+use std::collections::HashMap;
 use std::env;
+use std::time::Duration;
+use crate::extract::statement::Transaction;
 
 pub type DbPool = Pool<Postgres>;
 
+/// How long optional features wait for the database before running without it
+const OPTIONAL_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub async fn init_pool() -> Result<DbPool> {
     // Load .env file from multiple locations (same as config does)
     if dotenvy::dotenv().is_err() {
@@ -57,9 +64,299 @@ async fn create_tables(pool: &DbPool) -> Result<()> {
     .await
     .context("Failed to create index on activity_logs")?;
 
+    // Cached message metadata (headers + attachment listing) keyed by mail source, profile and message id
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS message_cache (
+            source TEXT NOT NULL,
+            profile TEXT NOT NULL DEFAULT '',
+            message_id TEXT NOT NULL,
+            metadata TEXT NOT NULL,
+            fetched_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (source, profile, message_id)
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create message_cache table")?;
+
+    // Every archived document with the invoice fields extracted from it
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS invoice_documents (
+            id SERIAL PRIMARY KEY,
+            profile TEXT NOT NULL DEFAULT '',
+            vendor TEXT NOT NULL,
+            invoice_number TEXT,
+            amount_cents BIGINT,
+            currency TEXT,
+            filename TEXT NOT NULL,
+            folder TEXT NOT NULL,
+            file_id TEXT NOT NULL,
+            uploaded_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create invoice_documents table")?;
+
+    sqlx::query(
+        r#"
+        ALTER TABLE invoice_documents
+            ADD COLUMN IF NOT EXISTS billing_month DATE,
+            ADD COLUMN IF NOT EXISTS web_link TEXT
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to add archive columns to invoice_documents")?;
+
+    // Extracted document text, searchable together with the vendor and filename. The 'simple'
+    // configuration keeps words unstemmed, since invoices arrive in many languages
+    sqlx::query(
+        r#"
+        ALTER TABLE invoice_documents
+            ADD COLUMN IF NOT EXISTS content TEXT,
+            ADD COLUMN IF NOT EXISTS content_tsv tsvector GENERATED ALWAYS AS (
+                to_tsvector('simple', vendor || ' ' || filename || ' ' || coalesce(content, ''))
+            ) STORED
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to add full-text columns to invoice_documents")?;
+
+    sqlx::query(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_invoice_documents_content ON invoice_documents USING GIN (content_tsv)
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create full-text index on invoice_documents")?;
+
+    sqlx::query(
+        r#"
+        ALTER TABLE invoice_documents ADD COLUMN IF NOT EXISTS category TEXT
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to add category column to invoice_documents")?;
+
+    // What `audit` checks the stored file against, and where it can be fetched again
+    sqlx::query(
+        r#"
+        ALTER TABLE invoice_documents
+            ADD COLUMN IF NOT EXISTS message_id TEXT,
+            ADD COLUMN IF NOT EXISTS size_bytes BIGINT,
+            ADD COLUMN IF NOT EXISTS sha256 TEXT
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to add file columns to invoice_documents")?;
+
+    // Accounts payable: when payment is due and whether it was made (`payables`)
+    sqlx::query(
+        r#"
+        ALTER TABLE invoice_documents
+            ADD COLUMN IF NOT EXISTS due_date DATE,
+            ADD COLUMN IF NOT EXISTS paid BOOLEAN NOT NULL DEFAULT FALSE
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to add payment columns to invoice_documents")?;
+
+    // Transactions read from archived CSV and CAMT.053 bank statements, for `reconcile --month`.
+    // Identical rows of one statement are told apart by `occurrence`, so a statement overlapping
+    // an earlier one adds only the transactions that are new
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS bank_transactions (
+            id SERIAL PRIMARY KEY,
+            profile TEXT NOT NULL DEFAULT '',
+            bank TEXT NOT NULL,
+            booked_on DATE NOT NULL,
+            amount_cents BIGINT NOT NULL,
+            currency TEXT NOT NULL DEFAULT '',
+            description TEXT NOT NULL,
+            occurrence INTEGER NOT NULL DEFAULT 0,
+            file_id TEXT NOT NULL,
+            recorded_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (profile, bank, booked_on, amount_cents, currency, description, occurrence)
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create bank_transactions table")?;
+
+    // One row per pipeline run (CLI, TUI, batch tenant or dashboard)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS runs (
+            id SERIAL PRIMARY KEY,
+            profile TEXT NOT NULL DEFAULT '',
+            start_date DATE NOT NULL,
+            end_date DATE NOT NULL,
+            started_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            finished_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            processed INTEGER NOT NULL,
+            uploaded INTEGER NOT NULL,
+            skipped INTEGER NOT NULL,
+            failed INTEGER NOT NULL,
+            billing_month TEXT,
+            folder TEXT,
+            error TEXT
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create runs table")?;
+
+    sqlx::query(
+        r#"
+        ALTER TABLE runs
+            ADD COLUMN IF NOT EXISTS scheduled BOOLEAN NOT NULL DEFAULT FALSE,
+            ADD COLUMN IF NOT EXISTS attempt INTEGER NOT NULL DEFAULT 1
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to add schedule columns to runs")?;
+
+    sqlx::query(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_runs_started_at ON runs(profile, started_at DESC)
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create index on runs")?;
+
+    // Progress of runs still going, written every few seconds by the run itself
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS heartbeats (
+            id SERIAL PRIMARY KEY,
+            profile TEXT NOT NULL DEFAULT '',
+            command TEXT NOT NULL,
+            pid INTEGER NOT NULL,
+            started_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            beat_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            progress_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            stage TEXT NOT NULL DEFAULT '',
+            detail TEXT NOT NULL DEFAULT '',
+            items_completed INTEGER NOT NULL DEFAULT 0,
+            items_total INTEGER,
+            finished_at TIMESTAMP WITH TIME ZONE
+        )
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create heartbeats table")?;
+
+    sqlx::query(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_invoice_documents_fingerprint ON invoice_documents(profile, vendor, invoice_number, amount_cents)
+        "#
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create index on invoice_documents")?;
+
     Ok(())
 }
 
+/// A table `db export` copies: the columns telling its rows apart, so an import never adds a row
+/// twice, and the order rows are written in (ids are assigned again on import)
+pub struct BackupTable {
+    pub name: &'static str,
+    pub key: &'static [&'static str],
+    pub order: &'static str,
+}
+
+/// The accumulated state; activity logs and heartbeats are left out
+pub const BACKUP_TABLES: &[BackupTable] = &[
+    BackupTable { name: "invoice_documents", key: &["profile", "file_id", "filename"], order: "id" },
+    BackupTable { name: "runs", key: &["profile", "started_at"], order: "id" },
+    BackupTable {
+        name: "bank_transactions",
+        key: &["profile", "bank", "booked_on", "amount_cents", "currency", "description", "occurrence"],
+        order: "id",
+    },
+    BackupTable { name: "message_cache", key: &["source", "profile", "message_id"], order: "fetched_at" },
+];
+
+/// Every row of a backup table as a JSON object, without its id and generated columns
+pub async fn export_rows(pool: &DbPool, table: &BackupTable) -> Result<Vec<String>> {
+    let rows = sqlx::query(&format!(
+        "SELECT (to_jsonb(t) - 'id' - 'content_tsv')::text AS row FROM {} t ORDER BY {}",
+        table.name, table.order
+    ))
+    .fetch_all(pool)
+    .await
+    .with_context(|| format!("Failed to export {}", table.name))?;
+
+    Ok(rows.into_iter().map(|row| row.get("row")).collect())
+}
+
+/// Columns an imported row may set: all but the id and generated columns
+pub async fn importable_columns(pool: &DbPool, table: &BackupTable) -> Result<Vec<String>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT column_name::text AS name
+        FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = $1 AND is_generated = 'NEVER' AND column_name <> 'id'
+        "#
+    )
+    .bind(table.name)
+    .fetch_all(pool)
+    .await
+    .with_context(|| format!("Failed to read the columns of {}", table.name))?;
+
+    Ok(rows.into_iter().map(|row| row.get("name")).collect())
+}
+
+/// Insert an exported row unless one with the same key is already there. `columns` are the
+/// row's own, checked against `importable_columns`. Returns whether it was added.
+pub async fn import_row(pool: &DbPool, table: &BackupTable, columns: &[&str], row: &str) -> Result<bool> {
+    let columns = columns.join(", ");
+    let same_key = table.key.iter().map(|column| format!("t.{0} IS NOT DISTINCT FROM r.{0}", column)).collect::<Vec<_>>().join(" AND ");
+    let result = sqlx::query(&format!(
+        "INSERT INTO {0} ({1}) SELECT {1} FROM jsonb_populate_record(NULL::{0}, $1::jsonb) r WHERE NOT EXISTS (SELECT 1 FROM {0} t WHERE {2})",
+        table.name, columns, same_key
+    ))
+    .bind(row)
+    .execute(pool)
+    .await
+    .with_context(|| format!("Failed to import into {}", table.name))?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Connect when a database is configured and answers quickly; features that only enrich a run
+/// (message cache, invoice metadata) carry on without one
+pub async fn connect_optional(purpose: &str) -> Option<DbPool> {
+    match tokio::time::timeout(OPTIONAL_CONNECT_TIMEOUT, init_pool()).await {
+        Ok(Ok(pool)) => Some(pool),
+        Ok(Err(e)) => {
+            log::info!("{} disabled: {:#}", purpose, e);
+            None
+        }
+        Err(_) => {
+            log::info!("{} disabled: database connection timed out", purpose);
+            None
+        }
+    }
+}
+
 pub async fn save_log(pool: &DbPool, message: &str) -> Result<()> {
     sqlx::query(
         r#"
@@ -97,3 +394,959 @@ pub async fn load_logs(pool: &DbPool) -> Result<Vec<String>> {
     Ok(messages)
 }
 
+
+pub async fn load_cached_message(pool: &DbPool, source: &str, profile: &str, message_id: &str) -> Result<Option<String>> {
+    let row = sqlx::query(
+        r#"
+        SELECT metadata
+        FROM message_cache
+        WHERE source = $1 AND profile = $2 AND message_id = $3
+        "#
+    )
+    .bind(source)
+    .bind(profile)
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await
+    .context("Failed to load cached message")?;
+
+    Ok(row.map(|row| row.get::<String, _>("metadata")))
+}
+
+/// Cached metadata of a message from whichever mail source fetched it
+pub async fn load_cached_message_any_source(pool: &DbPool, profile: &str, message_id: &str) -> Result<Option<String>> {
+    let row = sqlx::query(
+        r#"
+        SELECT metadata
+        FROM message_cache
+        WHERE profile = $1 AND message_id = $2
+        ORDER BY fetched_at DESC
+        LIMIT 1
+        "#
+    )
+    .bind(profile)
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await
+    .context("Failed to load cached message")?;
+
+    Ok(row.map(|row| row.get::<String, _>("metadata")))
+}
+
+pub async fn save_cached_message(pool: &DbPool, source: &str, profile: &str, message_id: &str, metadata: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO message_cache (source, profile, message_id, metadata, fetched_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (source, profile, message_id)
+        DO UPDATE SET metadata = EXCLUDED.metadata, fetched_at = EXCLUDED.fetched_at
+        "#
+    )
+    .bind(source)
+    .bind(profile)
+    .bind(message_id)
+    .bind(metadata)
+    .bind(Utc::now())
+    .execute(pool)
+    .await
+    .context("Failed to cache message")?;
+
+    Ok(())
+}
+
+/// An archived document as recorded in `invoice_documents`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InvoiceDocument {
+    pub profile: String,
+    pub vendor: String,
+    pub invoice_number: Option<String>,
+    pub amount_cents: Option<i64>,
+    pub currency: Option<String>,
+    pub filename: String,
+    pub folder: String,
+    pub file_id: String,
+    /// First day of the billing month the document was filed under
+    pub billing_month: Option<NaiveDate>,
+    pub web_link: Option<String>,
+    /// Text extracted from the document, indexed for full-text search
+    #[serde(skip)]
+    pub content: Option<String>,
+    /// Passage around the matched words, filled in by full-text searches
+    pub snippet: Option<String>,
+    /// Category set by a sender rule; see `category()`
+    pub rule_category: Option<String>,
+    /// Message the attachment came from
+    #[serde(skip)]
+    pub message_id: Option<String>,
+    /// Size and SHA-256 (hex) of the file as uploaded
+    #[serde(skip)]
+    pub size_bytes: Option<i64>,
+    #[serde(skip)]
+    pub sha256: Option<String>,
+    /// Date payment is due by, when the document states one
+    pub due_date: Option<NaiveDate>,
+    /// Marked paid with `payables --paid` or in the TUI
+    pub paid: bool,
+}
+
+impl InvoiceDocument {
+    /// Extracted total with its currency, or "-" when none was found
+    pub fn display_amount(&self) -> String {
+        match (self.amount_cents, &self.currency) {
+            (Some(cents), Some(currency)) => format!("{} {}", crate::extract::invoice::format_amount(cents), currency),
+            (Some(cents), None) => crate::extract::invoice::format_amount(cents),
+            (None, _) => "-".to_string(),
+        }
+    }
+
+    /// Category of the document: the one its sender rule set, otherwise the institution folder
+    /// under the month (`billing/March/Revolut` -> `Revolut`, also for its `Credit Notes`
+    /// subfolder), "General" for files in the month folder itself. Folders of a templated
+    /// GOOGLE_DRIVE_FOLDER_LOCATION (`billing/{fiscal_year}`) are skipped up to the month.
+    pub fn category(&self, drive_folder_path: &str) -> String {
+        if let Some(category) = &self.rule_category {
+            return category.clone();
+        }
+        let Some(rest) = self.folder.strip_prefix(crate::process::fiscal::fixed_root(drive_folder_path).trim_end_matches('/')) else {
+            return "General".to_string();
+        };
+        let mut segments = rest.trim_start_matches('/').split('/');
+        // Skip the folders down to the month's
+        match self.billing_month.map(crate::process::jobs::month_name) {
+            Some(month) if drive_folder_path.contains('{') => {
+                segments.find(|segment| *segment == month);
+            }
+            _ => {
+                segments.next();
+            }
+        }
+        Some(segments.collect::<Vec<_>>().join("/"))
+            .map(|category| category.trim_end_matches(crate::extract::invoice::CREDIT_NOTES_FOLDER).trim_end_matches('/').to_string())
+            .filter(|category| !category.is_empty())
+            .unwrap_or_else(|| "General".to_string())
+    }
+}
+
+/// Filters for `search_invoice_documents`; None matches everything
+#[derive(Debug, Clone, Default)]
+pub struct InvoiceQuery {
+    /// Substring of the vendor prefix or the folder (institution) name, case-insensitive
+    pub vendor: Option<String>,
+    pub billing_month: Option<NaiveDate>,
+    pub min_amount_cents: Option<i64>,
+    pub max_amount_cents: Option<i64>,
+    /// Words or phrases in the document text (web search syntax: "quoted phrase", -excluded, or)
+    pub text: Option<String>,
+    pub limit: i64,
+}
+
+pub async fn save_invoice_document(pool: &DbPool, document: &InvoiceDocument) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO invoice_documents (profile, vendor, invoice_number, amount_cents, currency, filename, folder, file_id, billing_month, web_link, content, category, message_id, size_bytes, sha256, due_date, paid)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
+        "#
+    )
+    .bind(&document.profile)
+    .bind(&document.vendor)
+    .bind(&document.invoice_number)
+    .bind(document.amount_cents)
+    .bind(&document.currency)
+    .bind(&document.filename)
+    .bind(&document.folder)
+    .bind(&document.file_id)
+    .bind(document.billing_month)
+    .bind(&document.web_link)
+    // Postgres text cannot hold NUL, which some PDF extractions contain
+    .bind(document.content.as_ref().map(|content| content.replace('\0', "")))
+    .bind(&document.rule_category)
+    .bind(&document.message_id)
+    .bind(document.size_bytes)
+    .bind(&document.sha256)
+    .bind(document.due_date)
+    .bind(document.paid)
+    .execute(pool)
+    .await
+    .context("Failed to record invoice document")?;
+
+    Ok(())
+}
+
+/// An earlier archived document with the same vendor, invoice number and amount, as (filename, folder)
+pub async fn find_invoice_duplicate(pool: &DbPool, profile: &str, vendor: &str, invoice_number: &str, amount_cents: i64) -> Result<Option<(String, String)>> {
+    let row = sqlx::query(
+        r#"
+        SELECT filename, folder
+        FROM invoice_documents
+        WHERE profile = $1 AND vendor = $2 AND invoice_number = $3 AND amount_cents = $4
+        ORDER BY uploaded_at
+        LIMIT 1
+        "#
+    )
+    .bind(profile)
+    .bind(vendor)
+    .bind(invoice_number)
+    .bind(amount_cents)
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up invoice duplicates")?;
+
+    Ok(row.map(|row| (row.get::<String, _>("filename"), row.get::<String, _>("folder"))))
+}
+
+/// Archived documents of a profile matching the query: best text match first, then newest billing month.
+/// Text also matches with whitespace ignored, so an IBAN or reference is found however it was grouped
+pub async fn search_invoice_documents(pool: &DbPool, profile: &str, query: &InvoiceQuery) -> Result<Vec<InvoiceDocument>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT vendor, invoice_number, amount_cents, currency, filename, folder, file_id, billing_month, web_link, category, due_date, paid,
+            CASE WHEN $6::TEXT IS NULL THEN NULL
+                ELSE ts_headline('simple', coalesce(content, ''), websearch_to_tsquery('simple', $6), 'MaxWords=16, MinWords=6, StartSel=[, StopSel=]')
+            END AS snippet
+        FROM invoice_documents
+        WHERE profile = $1
+          AND ($2::TEXT IS NULL OR vendor ILIKE '%' || $2 || '%' OR folder ILIKE '%' || $2 || '%')
+          AND ($3::DATE IS NULL OR billing_month = $3)
+          AND ($4::BIGINT IS NULL OR amount_cents >= $4)
+          AND ($5::BIGINT IS NULL OR amount_cents <= $5)
+          AND ($6::TEXT IS NULL
+            OR content_tsv @@ websearch_to_tsquery('simple', $6)
+            OR regexp_replace(content, '\s+', '', 'g') ILIKE '%' || regexp_replace($6, '\s+', '', 'g') || '%')
+        ORDER BY
+            CASE WHEN $6::TEXT IS NULL THEN 0 ELSE ts_rank(content_tsv, websearch_to_tsquery('simple', $6)) END DESC,
+            billing_month DESC NULLS LAST,
+            uploaded_at DESC
+        LIMIT $7
+        "#
+    )
+    .bind(profile)
+    .bind(&query.vendor)
+    .bind(query.billing_month)
+    .bind(query.min_amount_cents)
+    .bind(query.max_amount_cents)
+    .bind(&query.text)
+    .bind(query.limit)
+    .fetch_all(pool)
+    .await
+    .context("Failed to search invoice documents")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| InvoiceDocument {
+            profile: profile.to_string(),
+            vendor: row.get("vendor"),
+            invoice_number: row.get("invoice_number"),
+            amount_cents: row.get("amount_cents"),
+            currency: row.get("currency"),
+            filename: row.get("filename"),
+            folder: row.get("folder"),
+            file_id: row.get("file_id"),
+            billing_month: row.get("billing_month"),
+            web_link: row.get("web_link"),
+            content: None,
+            snippet: row
+                .get::<Option<String>, _>("snippet")
+                .map(|snippet| snippet.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|snippet| !snippet.is_empty()),
+            rule_category: row.get("category"),
+            due_date: row.get("due_date"),
+            paid: row.get("paid"),
+            ..Default::default()
+        })
+        .collect())
+}
+
+/// Documents and their summed amounts for one billing month, vendor and currency
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveTotal {
+    pub billing_month: Option<NaiveDate>,
+    pub vendor: String,
+    pub currency: Option<String>,
+    pub documents: i64,
+    /// Sum of the amounts that could be extracted
+    pub total_cents: Option<i64>,
+}
+
+/// Totals per billing month and vendor, newest month first; `query.text` and `query.limit`
+/// are honoured, the amount bounds apply to the documents being summed
+pub async fn summarize_invoice_documents(pool: &DbPool, profile: &str, query: &InvoiceQuery) -> Result<Vec<ArchiveTotal>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT billing_month, vendor, currency, COUNT(*) AS documents, SUM(amount_cents)::BIGINT AS total_cents
+        FROM invoice_documents
+        WHERE profile = $1
+          AND ($2::TEXT IS NULL OR vendor ILIKE '%' || $2 || '%' OR folder ILIKE '%' || $2 || '%')
+          AND ($3::DATE IS NULL OR billing_month = $3)
+          AND ($4::BIGINT IS NULL OR amount_cents >= $4)
+          AND ($5::BIGINT IS NULL OR amount_cents <= $5)
+          AND ($6::TEXT IS NULL OR content_tsv @@ websearch_to_tsquery('simple', $6))
+        GROUP BY billing_month, vendor, currency
+        ORDER BY billing_month DESC NULLS LAST, vendor, currency
+        LIMIT $7
+        "#
+    )
+    .bind(profile)
+    .bind(&query.vendor)
+    .bind(query.billing_month)
+    .bind(query.min_amount_cents)
+    .bind(query.max_amount_cents)
+    .bind(&query.text)
+    .bind(query.limit)
+    .fetch_all(pool)
+    .await
+    .context("Failed to summarize invoice documents")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ArchiveTotal {
+            billing_month: row.get("billing_month"),
+            vendor: row.get("vendor"),
+            currency: row.get("currency"),
+            documents: row.get("documents"),
+            total_cents: row.get("total_cents"),
+        })
+        .collect())
+}
+
+/// Documents uploaded since a point in time, oldest first
+pub async fn invoice_documents_since(pool: &DbPool, profile: &str, since: DateTime<Utc>) -> Result<Vec<InvoiceDocument>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT vendor, invoice_number, amount_cents, currency, filename, folder, file_id, billing_month, web_link, category
+        FROM invoice_documents
+        WHERE profile = $1 AND uploaded_at >= $2
+        ORDER BY uploaded_at
+        "#
+    )
+    .bind(profile)
+    .bind(since)
+    .fetch_all(pool)
+    .await
+    .context("Failed to load recent invoice documents")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| InvoiceDocument {
+            profile: profile.to_string(),
+            vendor: row.get("vendor"),
+            invoice_number: row.get("invoice_number"),
+            amount_cents: row.get("amount_cents"),
+            currency: row.get("currency"),
+            filename: row.get("filename"),
+            folder: row.get("folder"),
+            file_id: row.get("file_id"),
+            billing_month: row.get("billing_month"),
+            web_link: row.get("web_link"),
+            content: None,
+            snippet: None,
+            rule_category: row.get("category"),
+            ..Default::default()
+        })
+        .collect())
+}
+
+/// Record the transactions of a bank statement archived as `file_id`; returns how many were new
+pub async fn save_transactions(pool: &DbPool, profile: &str, bank: &str, file_id: &str, transactions: &[Transaction]) -> Result<u64> {
+    let mut occurrences: HashMap<&Transaction, i32> = HashMap::new();
+    let mut recorded = 0;
+    let mut tx = pool.begin().await.context("Failed to start recording transactions")?;
+    for transaction in transactions {
+        let occurrence = occurrences.entry(transaction).or_default();
+        let result = sqlx::query(
+            r#"
+            INSERT INTO bank_transactions (profile, bank, booked_on, amount_cents, currency, description, occurrence, file_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .bind(profile)
+        .bind(bank)
+        .bind(transaction.date)
+        .bind(transaction.amount_cents)
+        .bind(transaction.currency.as_deref().unwrap_or_default())
+        .bind(&transaction.description)
+        .bind(*occurrence)
+        .bind(file_id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to record transaction")?;
+        *occurrence += 1;
+        recorded += result.rows_affected();
+    }
+    tx.commit().await.context("Failed to record transactions")?;
+    Ok(recorded)
+}
+
+/// Recorded transactions booked from `from` to `to` (inclusive), of one bank when given, oldest first
+pub async fn load_transactions(pool: &DbPool, profile: &str, bank: Option<&str>, from: NaiveDate, to: NaiveDate) -> Result<Vec<Transaction>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT booked_on, amount_cents, currency, description
+        FROM bank_transactions
+        WHERE profile = $1 AND ($2::TEXT IS NULL OR bank ILIKE $2) AND booked_on BETWEEN $3 AND $4
+        ORDER BY booked_on, id
+        "#
+    )
+    .bind(profile)
+    .bind(bank)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .context("Failed to load transactions")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Transaction {
+            date: row.get("booked_on"),
+            amount_cents: row.get("amount_cents"),
+            currency: Some(row.get::<String, _>("currency")).filter(|currency| !currency.is_empty()),
+            description: row.get("description"),
+        })
+        .collect())
+}
+
+/// Documents filed under the billing months from `from` to `to` (inclusive) that an amount was found in
+pub async fn invoice_documents_between(pool: &DbPool, profile: &str, from: NaiveDate, to: NaiveDate) -> Result<Vec<InvoiceDocument>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT vendor, invoice_number, amount_cents, currency, filename, folder, file_id, billing_month, web_link, category, due_date, paid
+        FROM invoice_documents
+        WHERE profile = $1 AND billing_month BETWEEN $2 AND $3 AND amount_cents IS NOT NULL
+        ORDER BY billing_month, uploaded_at
+        "#
+    )
+    .bind(profile)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .context("Failed to load invoice documents")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| InvoiceDocument {
+            profile: profile.to_string(),
+            vendor: row.get("vendor"),
+            invoice_number: row.get("invoice_number"),
+            amount_cents: row.get("amount_cents"),
+            currency: row.get("currency"),
+            filename: row.get("filename"),
+            folder: row.get("folder"),
+            file_id: row.get("file_id"),
+            billing_month: row.get("billing_month"),
+            web_link: row.get("web_link"),
+            rule_category: row.get("category"),
+            due_date: row.get("due_date"),
+            paid: row.get("paid"),
+            ..Default::default()
+        })
+        .collect())
+}
+
+/// Every document filed under one billing month, with the message it came from, oldest first
+pub async fn month_invoice_documents(pool: &DbPool, profile: &str, billing_month: NaiveDate) -> Result<Vec<InvoiceDocument>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT vendor, invoice_number, amount_cents, currency, filename, folder, file_id, billing_month, web_link, category, message_id, due_date, paid
+        FROM invoice_documents
+        WHERE profile = $1 AND billing_month = $2
+        ORDER BY id
+        "#
+    )
+    .bind(profile)
+    .bind(billing_month)
+    .fetch_all(pool)
+    .await
+    .context("Failed to load invoice documents")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| InvoiceDocument {
+            profile: profile.to_string(),
+            vendor: row.get("vendor"),
+            invoice_number: row.get("invoice_number"),
+            amount_cents: row.get("amount_cents"),
+            currency: row.get("currency"),
+            filename: row.get("filename"),
+            folder: row.get("folder"),
+            file_id: row.get("file_id"),
+            billing_month: row.get("billing_month"),
+            web_link: row.get("web_link"),
+            rule_category: row.get("category"),
+            message_id: row.get("message_id"),
+            due_date: row.get("due_date"),
+            paid: row.get("paid"),
+            ..Default::default()
+        })
+        .collect())
+}
+
+/// Unpaid documents due by `due_by` (inclusive), soonest due first
+pub async fn unpaid_invoice_documents(pool: &DbPool, profile: &str, due_by: NaiveDate) -> Result<Vec<InvoiceDocument>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT vendor, invoice_number, amount_cents, currency, filename, folder, file_id, billing_month, web_link, category, due_date
+        FROM invoice_documents
+        WHERE profile = $1 AND NOT paid AND due_date <= $2
+        ORDER BY due_date, vendor
+        "#
+    )
+    .bind(profile)
+    .bind(due_by)
+    .fetch_all(pool)
+    .await
+    .context("Failed to load unpaid invoice documents")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| InvoiceDocument {
+            profile: profile.to_string(),
+            vendor: row.get("vendor"),
+            invoice_number: row.get("invoice_number"),
+            amount_cents: row.get("amount_cents"),
+            currency: row.get("currency"),
+            filename: row.get("filename"),
+            folder: row.get("folder"),
+            file_id: row.get("file_id"),
+            billing_month: row.get("billing_month"),
+            web_link: row.get("web_link"),
+            rule_category: row.get("category"),
+            due_date: row.get("due_date"),
+            ..Default::default()
+        })
+        .collect())
+}
+
+/// Mark the document uploaded as `file_id` paid or unpaid; false when no such document was recorded
+pub async fn set_invoice_paid(pool: &DbPool, profile: &str, file_id: &str, paid: bool) -> Result<bool> {
+    let result = sqlx::query(
+        r#"
+        UPDATE invoice_documents SET paid = $3 WHERE profile = $1 AND file_id = $2
+        "#
+    )
+    .bind(profile)
+    .bind(file_id)
+    .bind(paid)
+    .execute(pool)
+    .await
+    .context("Failed to update the payment status")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Distinct (vendor, billing month) pairs archived between two billing months (inclusive)
+pub async fn vendor_months(pool: &DbPool, profile: &str, from: NaiveDate, to: NaiveDate) -> Result<Vec<(String, NaiveDate)>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT DISTINCT vendor, billing_month
+        FROM invoice_documents
+        WHERE profile = $1 AND billing_month BETWEEN $2 AND $3
+        "#
+    )
+    .bind(profile)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .context("Failed to load vendor history")?;
+
+    Ok(rows.into_iter().map(|row| (row.get("vendor"), row.get("billing_month"))).collect())
+}
+
+/// Totals per billing month, vendor and currency for the months from `from` to `to`
+pub async fn vendor_month_totals(pool: &DbPool, profile: &str, from: NaiveDate, to: NaiveDate) -> Result<Vec<ArchiveTotal>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT billing_month, vendor, currency, COUNT(*) AS documents, SUM(amount_cents)::BIGINT AS total_cents
+        FROM invoice_documents
+        WHERE profile = $1 AND billing_month BETWEEN $2 AND $3
+        GROUP BY billing_month, vendor, currency
+        "#
+    )
+    .bind(profile)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+    .context("Failed to load vendor totals")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ArchiveTotal {
+            billing_month: row.get("billing_month"),
+            vendor: row.get("vendor"),
+            currency: row.get("currency"),
+            documents: row.get("documents"),
+            total_cents: row.get("total_cents"),
+        })
+        .collect())
+}
+
+/// Point the documents filed in a folder or below it at where `archive maintain` put them:
+/// `new_folder` replaces that folder at the front of their path, and the ZIP now holding them,
+/// as (file id, link), replaces their own file. Returns the number of documents changed.
+pub async fn relocate_invoice_documents(pool: &DbPool, profile: &str, folder: &str, new_folder: &str, archive: Option<(&str, &str)>) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        UPDATE invoice_documents
+        SET folder = $3 || substr(folder, length($2) + 1),
+            file_id = coalesce($4, file_id),
+            web_link = coalesce($5, web_link)
+        WHERE profile = $1 AND (folder = $2 OR left(folder, length($2) + 1) = $2 || '/')
+        "#
+    )
+    .bind(profile)
+    .bind(folder)
+    .bind(new_folder)
+    .bind(archive.map(|(file_id, _)| file_id))
+    .bind(archive.map(|(_, link)| link))
+    .execute(pool)
+    .await
+    .context("Failed to update archived document locations")?;
+
+    Ok(result.rows_affected())
+}
+
+/// Forget the documents filed in a folder or below it, extracted text included, once their
+/// files are deleted. Returns the number of documents removed.
+pub async fn delete_invoice_documents(pool: &DbPool, profile: &str, folder: &str) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM invoice_documents
+        WHERE profile = $1 AND (folder = $2 OR left(folder, length($2) + 1) = $2 || '/')
+        "#
+    )
+    .bind(profile)
+    .bind(folder)
+    .execute(pool)
+    .await
+    .context("Failed to delete archived documents")?;
+
+    Ok(result.rows_affected())
+}
+
+/// An archived file as `audit` checks it
+#[derive(Debug, Clone)]
+pub struct ArchivedFile {
+    pub id: i32,
+    pub filename: String,
+    pub folder: String,
+    pub file_id: String,
+    pub message_id: Option<String>,
+    pub size_bytes: Option<i64>,
+    pub sha256: Option<String>,
+}
+
+/// Every file archived for a profile, or only those filed under one billing month, oldest first
+pub async fn archived_files(pool: &DbPool, profile: &str, billing_month: Option<NaiveDate>) -> Result<Vec<ArchivedFile>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, filename, folder, file_id, message_id, size_bytes, sha256
+        FROM invoice_documents
+        WHERE profile = $1 AND ($2::date IS NULL OR billing_month = $2)
+        ORDER BY id
+        "#
+    )
+    .bind(profile)
+    .bind(billing_month)
+    .fetch_all(pool)
+    .await
+    .context("Failed to load archived documents")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ArchivedFile {
+            id: row.get("id"),
+            filename: row.get("filename"),
+            folder: row.get("folder"),
+            file_id: row.get("file_id"),
+            message_id: row.get("message_id"),
+            size_bytes: row.get("size_bytes"),
+            sha256: row.get("sha256"),
+        })
+        .collect())
+}
+
+/// Point an archived document at a new copy of its file
+pub async fn update_archived_file(pool: &DbPool, id: i32, file_id: &str, web_link: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE invoice_documents SET file_id = $2, web_link = $3 WHERE id = $1
+        "#
+    )
+    .bind(id)
+    .bind(file_id)
+    .bind(web_link)
+    .execute(pool)
+    .await
+    .context("Failed to update archived document")?;
+
+    Ok(())
+}
+
+/// Record the new place of a file `reorganize` moved or renamed
+pub async fn relocate_archived_file(pool: &DbPool, profile: &str, file_id: &str, new_file_id: &str, folder: &str, filename: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE invoice_documents SET file_id = $3, folder = $4, filename = $5 WHERE profile = $1 AND file_id = $2
+        "#
+    )
+    .bind(profile)
+    .bind(file_id)
+    .bind(new_file_id)
+    .bind(folder)
+    .bind(filename)
+    .execute(pool)
+    .await
+    .context("Failed to update archived document")?;
+
+    Ok(())
+}
+
+/// Forget a single archived document once its file is gone
+pub async fn delete_archived_file(pool: &DbPool, id: i32) -> Result<()> {
+    sqlx::query(
+        r#"
+        DELETE FROM invoice_documents WHERE id = $1
+        "#
+    )
+    .bind(id)
+    .execute(pool)
+    .await
+    .context("Failed to delete archived document")?;
+
+    Ok(())
+}
+
+/// A finished pipeline run as recorded in `runs`
+#[derive(Debug, Clone, Serialize)]
+pub struct RunRecord {
+    pub profile: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub processed: i32,
+    pub uploaded: i32,
+    pub skipped: i32,
+    pub failed: i32,
+    pub billing_month: Option<String>,
+    pub folder: Option<String>,
+    /// Why the run stopped, when it did not complete
+    pub error: Option<String>,
+    /// Started by the `scheduled` command rather than by hand
+    pub scheduled: bool,
+    /// Try number of a scheduled run retried after a failure (SCHEDULE_RETRIES), 1 otherwise
+    pub attempt: i32,
+}
+
+pub async fn save_run(pool: &DbPool, run: &RunRecord) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO runs (profile, start_date, end_date, started_at, finished_at, processed, uploaded, skipped, failed, billing_month, folder, error, scheduled, attempt)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        "#
+    )
+    .bind(&run.profile)
+    .bind(run.start_date)
+    .bind(run.end_date)
+    .bind(run.started_at)
+    .bind(run.finished_at)
+    .bind(run.processed)
+    .bind(run.uploaded)
+    .bind(run.skipped)
+    .bind(run.failed)
+    .bind(&run.billing_month)
+    .bind(&run.folder)
+    .bind(&run.error)
+    .bind(run.scheduled)
+    .bind(run.attempt)
+    .execute(pool)
+    .await
+    .context("Failed to record run")?;
+
+    Ok(())
+}
+
+/// Most recent runs of a profile, newest first
+pub async fn load_runs(pool: &DbPool, profile: &str, limit: i64) -> Result<Vec<RunRecord>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT profile, start_date, end_date, started_at, finished_at, processed, uploaded, skipped, failed, billing_month, folder, error, scheduled, attempt
+        FROM runs
+        WHERE profile = $1
+        ORDER BY started_at DESC
+        LIMIT $2
+        "#
+    )
+    .bind(profile)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .context("Failed to load run history")?;
+
+    Ok(rows.iter().map(run_record).collect())
+}
+
+/// Latest run of a profile started by the `scheduled` command
+pub async fn last_scheduled_run(pool: &DbPool, profile: &str) -> Result<Option<RunRecord>> {
+    let row = sqlx::query(
+        r#"
+        SELECT profile, start_date, end_date, started_at, finished_at, processed, uploaded, skipped, failed, billing_month, folder, error, scheduled, attempt
+        FROM runs
+        WHERE profile = $1 AND scheduled
+        ORDER BY started_at DESC
+        LIMIT 1
+        "#
+    )
+    .bind(profile)
+    .fetch_optional(pool)
+    .await
+    .context("Failed to load the last scheduled run")?;
+
+    Ok(row.as_ref().map(run_record))
+}
+
+/// Where a running pipeline run is, as last written by the run
+#[derive(Debug, Clone, Serialize)]
+pub struct HeartbeatRecord {
+    pub id: i32,
+    pub profile: String,
+    /// What started the run (`scheduled`, `manual`, `run`)
+    pub command: String,
+    pub pid: i32,
+    pub started_at: DateTime<Utc>,
+    /// Last time the run was heard from
+    pub beat_at: DateTime<Utc>,
+    /// Last time the run moved on (a new stage, message or progress line)
+    pub progress_at: DateTime<Utc>,
+    pub stage: String,
+    /// Latest progress line within the stage
+    pub detail: String,
+    pub items_completed: i32,
+    pub items_total: Option<i32>,
+}
+
+/// Record a run that just started; returns the id its beats update
+pub async fn start_heartbeat(pool: &DbPool, profile: &str, command: &str, pid: u32) -> Result<i32> {
+    let id = sqlx::query_scalar(
+        r#"
+        INSERT INTO heartbeats (profile, command, pid, started_at, beat_at, progress_at)
+        VALUES ($1, $2, $3, NOW(), NOW(), NOW())
+        RETURNING id
+        "#
+    )
+    .bind(profile)
+    .bind(command)
+    .bind(pid as i32)
+    .fetch_one(pool)
+    .await
+    .context("Failed to record run heartbeat")?;
+
+    Ok(id)
+}
+
+pub async fn save_heartbeat(pool: &DbPool, heartbeat: &HeartbeatRecord) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE heartbeats
+        SET beat_at = NOW(), progress_at = $2, stage = $3, detail = $4, items_completed = $5, items_total = $6
+        WHERE id = $1
+        "#
+    )
+    .bind(heartbeat.id)
+    .bind(heartbeat.progress_at)
+    .bind(&heartbeat.stage)
+    .bind(&heartbeat.detail)
+    .bind(heartbeat.items_completed)
+    .bind(heartbeat.items_total)
+    .execute(pool)
+    .await
+    .context("Failed to update run heartbeat")?;
+
+    Ok(())
+}
+
+/// Mark a run as over, whether it finished or was given up on
+pub async fn finish_heartbeat(pool: &DbPool, id: i32) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE heartbeats SET finished_at = NOW() WHERE id = $1
+        "#
+    )
+    .bind(id)
+    .execute(pool)
+    .await
+    .context("Failed to finish run heartbeat")?;
+
+    Ok(())
+}
+
+/// Delete heartbeats of runs that ended (or last beat) more than `keep_days` days ago
+pub async fn prune_heartbeats(pool: &DbPool, keep_days: i32) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM heartbeats
+        WHERE COALESCE(finished_at, beat_at) < NOW() - make_interval(days => $1)
+        "#
+    )
+    .bind(keep_days)
+    .execute(pool)
+    .await
+    .context("Failed to prune run heartbeats")?;
+
+    Ok(result.rows_affected())
+}
+
+/// Runs of a profile that have not finished, newest first
+pub async fn live_heartbeats(pool: &DbPool, profile: &str) -> Result<Vec<HeartbeatRecord>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, profile, command, pid, started_at, beat_at, progress_at, stage, detail, items_completed, items_total
+        FROM heartbeats
+        WHERE profile = $1 AND finished_at IS NULL
+        ORDER BY started_at DESC
+        "#
+    )
+    .bind(profile)
+    .fetch_all(pool)
+    .await
+    .context("Failed to load run heartbeats")?;
+
+    Ok(rows
+        .iter()
+        .map(|row| HeartbeatRecord {
+            id: row.get("id"),
+            profile: row.get("profile"),
+            command: row.get("command"),
+            pid: row.get("pid"),
+            started_at: row.get("started_at"),
+            beat_at: row.get("beat_at"),
+            progress_at: row.get("progress_at"),
+            stage: row.get("stage"),
+            detail: row.get("detail"),
+            items_completed: row.get("items_completed"),
+            items_total: row.get("items_total"),
+        })
+        .collect())
+}
+
+fn run_record(row: &sqlx::postgres::PgRow) -> RunRecord {
+    RunRecord {
+        profile: row.get("profile"),
+        start_date: row.get("start_date"),
+        end_date: row.get("end_date"),
+        started_at: row.get("started_at"),
+        finished_at: row.get("finished_at"),
+        processed: row.get("processed"),
+        uploaded: row.get("uploaded"),
+        skipped: row.get("skipped"),
+        failed: row.get("failed"),
+        billing_month: row.get("billing_month"),
+        folder: row.get("folder"),
+        error: row.get("error"),
+        scheduled: row.get("scheduled"),
+        attempt: row.get("attempt"),
+    }
+}
@@ -1,5 +1,6 @@
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
 pub const DRIVE_API_BASE: &str = "https://www.googleapis.com/drive/v3";
 pub const DRIVE_UPLOAD_BASE: &str = "https://www.googleapis.com/upload/drive/v3";
@@ -8,6 +9,7 @@ pub const DRIVE_UPLOAD_BASE: &str = "https://www.googleapis.com/upload/drive/v3"
 pub struct DriveClient {
     client: Client,
     access_token: String,
+    mock_root: Option<PathBuf>,
 }
 
 impl DriveClient {
@@ -15,9 +17,25 @@ impl DriveClient {
         Self {
             client: Client::new(),
             access_token,
+            mock_root: None,
         }
     }
 
+    /// Create a client that writes folders and uploads into a local directory
+    /// instead of Google Drive. Folder and file IDs are their local paths.
+    pub fn mock(root: &Path) -> Self {
+        Self {
+            client: Client::new(),
+            access_token: "mock".to_string(),
+            mock_root: Some(root.to_path_buf()),
+        }
+    }
+
+    /// Local directory standing in for Drive when running in mock mode
+    pub fn mock_root(&self) -> Option<&Path> {
+        self.mock_root.as_deref()
+    }
+
     pub fn client(&self) -> &Client {
         &self.client
     }
@@ -30,6 +48,9 @@ impl DriveClient {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct FileListResponse {
     pub files: Option<Vec<FileInfo>>,
+    // Set when the listing holds more than the page returned
+    #[serde(rename = "nextPageToken", default, skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -38,6 +59,14 @@ pub struct FileInfo {
     pub name: String,
     #[serde(rename = "mimeType")]
     pub mime_type: Option<String>,
+    // Drive reports sizes as decimal strings
+    pub size: Option<String>,
+    /// Only reported for files stored in Drive (not Google Docs)
+    #[serde(rename = "sha256Checksum")]
+    pub sha256: Option<String>,
+    /// RFC 3339 timestamp, so creation order is string order
+    #[serde(rename = "createdTime")]
+    pub created_time: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -51,9 +80,26 @@ pub struct FileMetadata {
 #[derive(Debug, Deserialize)]
 pub struct UploadedFile {
     pub id: String,
-    #[allow(dead_code)]
     pub name: String,
     #[serde(rename = "webViewLink")]
-    #[allow(dead_code)]
     pub web_view_link: Option<String>,
+    // Set when the file already existed and the upload was skipped
+    #[serde(skip)]
+    pub duplicate: bool,
+}
+
+impl UploadedFile {
+    /// Link to open the file: Drive's web view, or the local path for mock uploads
+    pub fn link(&self) -> String {
+        self.web_view_link.clone().unwrap_or_else(|| file_link(&self.id))
+    }
+}
+
+/// Link to a file by id when Drive did not return one
+pub fn file_link(file_id: &str) -> String {
+    if std::path::Path::new(file_id).is_absolute() {
+        format!("file://{}", file_id)
+    } else {
+        format!("https://drive.google.com/file/d/{}/view", file_id)
+    }
 }
@@ -0,0 +1,35 @@
+use anyhow::{Context, Result};
+use super::client::{DriveClient, DRIVE_API_BASE};
+use crate::process::outcome::ApiError;
+use crate::process::telemetry;
+
+/// Download the content of a file stored in Drive
+pub async fn download_file(
+    client: &DriveClient,
+    file_id: &str,
+) -> Result<Vec<u8>> {
+    if client.mock_root().is_some() {
+        return std::fs::read(file_id).context("Failed to read file from mock Drive folder");
+    }
+
+    let url = format!("{}/files/{}", DRIVE_API_BASE, file_id);
+
+    let request = client.client()
+        .get(&url)
+        .bearer_auth(client.access_token())
+        .query(&[("alt", "media")]);
+    let response = telemetry::send("Drive", request)
+        .await
+        .context("Failed to download file")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(ApiError::new("Drive", status, error_text).into());
+    }
+
+    let data = response.bytes().await
+        .context("Failed to read downloaded file")?;
+
+    Ok(data.to_vec())
+}
@@ -1,7 +1,29 @@
 use anyhow::{Context, Result};
-use super::client::{DriveClient, DRIVE_API_BASE, FileListResponse, FileMetadata};
+use super::client::{DriveClient, DRIVE_API_BASE, FileInfo, FileListResponse, FileMetadata};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+use crate::process::outcome::ApiError;
+use crate::process::telemetry;
 
-const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+pub const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+
+/// Attempts at creating a folder while Drive answers with a rate limit
+const CREATE_ATTEMPTS: u32 = 5;
+
+/// One lock per folder (parent id and name) being looked up or created, shared by every client in
+/// the process so concurrent uploads never create the same folder twice
+static FOLDER_LOCKS: LazyLock<Mutex<HashMap<(String, String), FolderLock>>> = LazyLock::new(Default::default);
+
+type FolderLock = Arc<tokio::sync::Mutex<()>>;
+
+fn folder_lock(parent_id: &str, folder_name: &str) -> FolderLock {
+    let mut locks = FOLDER_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    // Locks only the map still holds are for finished lookups; dropping them keeps a long-running
+    // process from keeping one per folder it ever touched
+    locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+    locks.entry((parent_id.to_string(), folder_name.to_string())).or_default().clone()
+}
 
 /// Find or create a folder by path (e.g., "billing/all-expenses/2025")
 pub async fn find_or_create_folder(
@@ -14,6 +36,12 @@ pub async fn find_or_create_folder(
         anyhow::bail!("Folder path cannot be empty");
     }
 
+    if let Some(root) = client.mock_root() {
+        let dir = parts.iter().fold(root.to_path_buf(), |dir, part| dir.join(part));
+        std::fs::create_dir_all(&dir).context("Failed to create mock Drive folder")?;
+        return Ok(dir.to_string_lossy().to_string());
+    }
+
     let mut parent_id = "root".to_string();
 
     for part in parts {
@@ -23,12 +51,398 @@ pub async fn find_or_create_folder(
     Ok(parent_id)
 }
 
-/// Find or create a single folder within a parent
+/// Look up an existing folder by path without creating anything
+pub async fn find_folder_by_path(
+    client: &DriveClient,
+    folder_path: &str,
+) -> Result<Option<String>> {
+    let parts: Vec<&str> = folder_path.split('/').filter(|s| !s.is_empty()).collect();
+
+    if let Some(root) = client.mock_root() {
+        let dir = parts.iter().fold(root.to_path_buf(), |dir, part| dir.join(part));
+        return Ok(dir.is_dir().then(|| dir.to_string_lossy().to_string()));
+    }
+
+    let mut parent_id = "root".to_string();
+
+    // Where a name is duplicated the oldest folder is followed, as when creating; merging is
+    // left to `find_or_create_folder` so lookups never change anything
+    for part in parts {
+        match find_folders(client, part, &parent_id).await?.first() {
+            Some(folder) => parent_id = folder.id.clone(),
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(parent_id))
+}
+
+/// List the files and subfolders directly inside a folder
+pub async fn list_folder(
+    client: &DriveClient,
+    folder_id: &str,
+) -> Result<Vec<FileInfo>> {
+    if client.mock_root().is_some() {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(folder_id).context("Failed to read mock Drive folder")? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            files.push(FileInfo {
+                id: entry.path().to_string_lossy().to_string(),
+                name: entry.file_name().to_string_lossy().to_string(),
+                mime_type: metadata.is_dir().then(|| FOLDER_MIME_TYPE.to_string()),
+                size: metadata.is_file().then(|| metadata.len().to_string()),
+                sha256: metadata.is_file().then(|| std::fs::read(entry.path()).map(|data| super::upload::sha256_hex(&data))).transpose()?,
+                created_time: None,
+            });
+        }
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        return Ok(files);
+    }
+
+    let query = format!("'{}' in parents and trashed=false", folder_id);
+    let url = format!("{}/files", DRIVE_API_BASE);
+
+    let request = client.client()
+        .get(&url)
+        .bearer_auth(client.access_token())
+        .query(&[
+            ("q", query.as_str()),
+            ("fields", "files(id, name, mimeType, size, sha256Checksum, createdTime)"),
+            ("orderBy", "name"),
+            ("pageSize", "1000"),
+        ]);
+    let response = telemetry::send("Drive", request)
+        .await
+        .context("Failed to list folder")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(ApiError::new("Drive", status, error_text).into());
+    }
+
+    let result: FileListResponse = response.json().await
+        .context("Failed to parse folder listing")?;
+
+    Ok(result.files.unwrap_or_default())
+}
+
+/// The files (not subfolders) directly inside a folder, ids and names only, or None when there
+/// are more than `max`: a single listing request for callers that would otherwise query file by file
+pub async fn list_files_up_to(
+    client: &DriveClient,
+    folder_id: &str,
+    max: usize,
+) -> Result<Option<Vec<FileInfo>>> {
+    let query = format!("'{}' in parents and trashed=false and mimeType != '{}'", folder_id, FOLDER_MIME_TYPE);
+    let url = format!("{}/files", DRIVE_API_BASE);
+
+    let request = client.client()
+        .get(&url)
+        .bearer_auth(client.access_token())
+        .query(&[("q", query.as_str()), ("fields", "nextPageToken, files(id, name)"), ("pageSize", &max.to_string())]);
+    let response = telemetry::send("Drive", request)
+        .await
+        .context("Failed to list folder")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(ApiError::new("Drive", status, error_text).into());
+    }
+
+    let result: FileListResponse = response.json().await
+        .context("Failed to parse folder listing")?;
+
+    Ok(result.next_page_token.is_none().then(|| result.files.unwrap_or_default()))
+}
+
+/// Look up a file by id, None when it was deleted or is in the trash
+pub async fn file_info(
+    client: &DriveClient,
+    file_id: &str,
+) -> Result<Option<FileInfo>> {
+    if client.mock_root().is_some() {
+        let path = std::path::Path::new(file_id);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let data = std::fs::read(path).context("Failed to read mock Drive file")?;
+        return Ok(Some(FileInfo {
+            id: file_id.to_string(),
+            name: path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+            mime_type: None,
+            size: Some(data.len().to_string()),
+            sha256: Some(super::upload::sha256_hex(&data)),
+            created_time: None,
+        }));
+    }
+
+    #[derive(serde::Deserialize)]
+    struct FileState {
+        #[serde(flatten)]
+        info: FileInfo,
+        #[serde(default)]
+        trashed: bool,
+    }
+
+    let url = format!("{}/files/{}", DRIVE_API_BASE, file_id);
+
+    let request = client.client()
+        .get(&url)
+        .bearer_auth(client.access_token())
+        .query(&[("fields", "id, name, mimeType, size, sha256Checksum, trashed")]);
+    let response = telemetry::send("Drive", request)
+        .await
+        .context("Failed to look up file")?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(ApiError::new("Drive", status, error_text).into());
+    }
+
+    let state: FileState = response.json().await
+        .context("Failed to parse file metadata")?;
+
+    Ok((!state.trashed).then_some(state.info))
+}
+
+/// Move a file or folder (with everything in it) from one parent folder to another
+pub async fn move_item(
+    client: &DriveClient,
+    item_id: &str,
+    from_parent_id: &str,
+    to_parent_id: &str,
+) -> Result<()> {
+    if client.mock_root().is_some() {
+        let name = std::path::Path::new(item_id).file_name().context("Invalid mock Drive path")?;
+        std::fs::rename(item_id, std::path::Path::new(to_parent_id).join(name)).context("Failed to move mock Drive item")?;
+        return Ok(());
+    }
+
+    let url = format!("{}/files/{}", DRIVE_API_BASE, item_id);
+
+    let request = client.client()
+        .patch(&url)
+        .bearer_auth(client.access_token())
+        .query(&[("addParents", to_parent_id), ("removeParents", from_parent_id), ("fields", "id")])
+        .json(&serde_json::json!({}));
+    let response = telemetry::send("Drive", request)
+        .await
+        .context("Failed to move item")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(ApiError::new("Drive", status, error_text).into());
+    }
+
+    Ok(())
+}
+
+/// Move a file to another folder under a new name in one request. Returns the file's id, which
+/// Drive keeps (the mock Drive's ids are paths, so there it changes).
+pub async fn relocate_item(
+    client: &DriveClient,
+    item_id: &str,
+    from_parent_id: &str,
+    to_parent_id: &str,
+    name: &str,
+) -> Result<String> {
+    if client.mock_root().is_some() {
+        let target = std::path::Path::new(to_parent_id).join(name);
+        std::fs::rename(item_id, &target).context("Failed to move mock Drive item")?;
+        return Ok(target.to_string_lossy().to_string());
+    }
+
+    let url = format!("{}/files/{}", DRIVE_API_BASE, item_id);
+    let mut request = client.client()
+        .patch(&url)
+        .bearer_auth(client.access_token());
+    if from_parent_id != to_parent_id {
+        request = request.query(&[("addParents", to_parent_id), ("removeParents", from_parent_id)]);
+    }
+
+    request = request
+        .query(&[("fields", "id")])
+        .json(&serde_json::json!({ "name": name }));
+    let response = telemetry::send("Drive", request)
+        .await
+        .context("Failed to move item")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(ApiError::new("Drive", status, error_text).into());
+    }
+
+    Ok(item_id.to_string())
+}
+
+/// Move a file or folder to the Drive trash, where it stays restorable for 30 days
+pub async fn trash_item(
+    client: &DriveClient,
+    item_id: &str,
+) -> Result<()> {
+    if client.mock_root().is_some() {
+        let path = std::path::Path::new(item_id);
+        let result = if path.is_dir() { std::fs::remove_dir_all(path) } else { std::fs::remove_file(path) };
+        return result.context("Failed to delete mock Drive item");
+    }
+
+    let url = format!("{}/files/{}", DRIVE_API_BASE, item_id);
+
+    let request = client.client()
+        .patch(&url)
+        .bearer_auth(client.access_token())
+        .query(&[("fields", "id")])
+        .json(&serde_json::json!({ "trashed": true }));
+    let response = telemetry::send("Drive", request)
+        .await
+        .context("Failed to trash item")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(ApiError::new("Drive", status, error_text).into());
+    }
+
+    Ok(())
+}
+
+/// Who a folder belongs to and whether the signed-in account may add files to it
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderAccess {
+    #[serde(default)]
+    pub capabilities: FolderCapabilities,
+    /// Absent for folders in a shared drive, which the drive owns
+    #[serde(default)]
+    pub owners: Vec<DriveUser>,
+    /// Who shared the folder with this account, when someone did
+    pub sharing_user: Option<DriveUser>,
+    pub drive_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderCapabilities {
+    #[serde(default)]
+    pub can_add_children: bool,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriveUser {
+    #[serde(default)]
+    pub display_name: String,
+    pub email_address: Option<String>,
+}
+
+impl DriveUser {
+    /// "Jane Doe <jane@example.com>"
+    fn describe(&self) -> String {
+        match &self.email_address {
+            Some(email) if !self.display_name.is_empty() => format!("{} <{}>", self.display_name, email),
+            Some(email) => email.clone(),
+            None => self.display_name.clone(),
+        }
+    }
+}
+
+impl FolderAccess {
+    /// Err naming the folder's owner when the account can only view `folder_path`
+    pub fn check(&self, folder_path: &str) -> Result<()> {
+        if self.capabilities.can_add_children {
+            return Ok(());
+        }
+        let owner = match (self.owners.first(), &self.drive_id, &self.sharing_user) {
+            (Some(owner), _, _) => format!("owned by {}", owner.describe()),
+            (None, Some(_), _) => "in a shared drive; ask one of its managers".to_string(),
+            (None, None, Some(sharer)) => format!("shared by {}", sharer.describe()),
+            (None, None, None) => "owner unknown".to_string(),
+        };
+        anyhow::bail!(
+            "The Drive account can't add files to '{}' ({}): it only has view or comment access. \
+             Ask for Editor access, or point GOOGLE_DRIVE_FOLDER_LOCATION at a folder you can write to",
+            folder_path,
+            owner
+        )
+    }
+}
+
+/// Sharing details of an existing folder, to check uploads will be allowed before any are made
+pub async fn folder_access(client: &DriveClient, folder_id: &str) -> Result<FolderAccess> {
+    let url = format!("{}/files/{}", DRIVE_API_BASE, folder_id);
+
+    let request = client.client()
+        .get(&url)
+        .bearer_auth(client.access_token())
+        .query(&[
+            ("fields", "capabilities(canAddChildren), owners(displayName, emailAddress), sharingUser(displayName, emailAddress), driveId"),
+            ("supportsAllDrives", "true"),
+        ]);
+    let response = telemetry::send("Drive", request)
+        .await
+        .context("Failed to look up folder permissions")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(ApiError::new("Drive", status, error_text).into());
+    }
+
+    response.json().await.context("Failed to parse folder permissions")
+}
+
+/// Link that opens a folder in the browser: Drive's webViewLink, or a file:// link to the mock
+/// Drive directory
+pub async fn folder_link(client: &DriveClient, folder_id: &str) -> Result<String> {
+    #[derive(serde::Deserialize)]
+    struct Link {
+        #[serde(rename = "webViewLink")]
+        web_view_link: Option<String>,
+    }
+
+    if client.mock_root().is_some() {
+        return Ok(format!("file://{}", folder_id));
+    }
+
+    let url = format!("{}/files/{}", DRIVE_API_BASE, folder_id);
+    let request = client.client()
+        .get(&url)
+        .bearer_auth(client.access_token())
+        .query(&[("fields", "webViewLink"), ("supportsAllDrives", "true")]);
+    let response = telemetry::send("Drive", request)
+        .await
+        .context("Failed to look up the folder link")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(ApiError::new("Drive", status, error_text).into());
+    }
+
+    let link: Link = response.json().await.context("Failed to parse the folder link")?;
+    Ok(link.web_view_link.unwrap_or_else(|| format!("https://drive.google.com/drive/folders/{}", folder_id)))
+}
+
+/// Find or create a single folder within a parent. Drive allows several folders with the same
+/// name, so creation is serialized per folder, the parent is checked again after creating (another
+/// process may have done the same) and duplicates are merged into the oldest folder.
 async fn find_or_create_single_folder(
     client: &DriveClient,
     folder_name: &str,
     parent_id: &str,
 ) -> Result<String> {
+    let lock = folder_lock(parent_id, folder_name);
+    let _guard = lock.lock().await;
+
     log::info!("Looking for folder '{}' in parent '{}'", folder_name, parent_id);
 
     // Try to find existing folder
@@ -39,15 +453,59 @@ async fn find_or_create_single_folder(
 
     // Create new folder
     log::info!("Folder '{}' not found, creating new one", folder_name);
-    create_folder(client, folder_name, parent_id).await
+    let created = create_folder(client, folder_name, parent_id).await?;
+
+    // Settle on whichever folder is oldest should one have appeared meanwhile; a folder Drive
+    // reported as conflicting must show up here
+    match find_folder(client, folder_name, parent_id).await? {
+        Some(folder_id) => Ok(folder_id),
+        None => created.with_context(|| format!("Drive reported a conflict creating folder '{}' but it can't be found", folder_name)),
+    }
 }
 
-/// Search for a folder by name within a parent
+/// Search for a folder by name within a parent, merging any duplicates into the oldest one
 async fn find_folder(
     client: &DriveClient,
     folder_name: &str,
     parent_id: &str,
 ) -> Result<Option<String>> {
+    let folders = find_folders(client, folder_name, parent_id).await?;
+    let Some((oldest, duplicates)) = folders.split_first() else {
+        return Ok(None);
+    };
+    if !duplicates.is_empty() {
+        merge_duplicate_folders(client, &oldest.id, duplicates).await?;
+    }
+    Ok(Some(oldest.id.clone()))
+}
+
+/// Move everything in `duplicates` into `keep` and trash the emptied duplicates. Subfolders
+/// that now share a name are merged the next time they are looked up.
+async fn merge_duplicate_folders(
+    client: &DriveClient,
+    keep: &str,
+    duplicates: &[FileInfo],
+) -> Result<()> {
+    for duplicate in duplicates {
+        log::warn!("Merging duplicate folder '{}' ({}) into {}", duplicate.name, duplicate.id, keep);
+        for item in list_folder(client, &duplicate.id).await? {
+            move_item(client, &item.id, &duplicate.id, keep).await
+                .with_context(|| format!("Failed to merge '{}' from duplicate folder '{}'", item.name, duplicate.name))?;
+        }
+        // A listing is capped, so only a folder seen empty is removed
+        if list_folder(client, &duplicate.id).await?.is_empty() {
+            trash_item(client, &duplicate.id).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Folders with a name within a parent, oldest first
+async fn find_folders(
+    client: &DriveClient,
+    folder_name: &str,
+    parent_id: &str,
+) -> Result<Vec<FileInfo>> {
     let query = format!(
         "name='{}' and '{}' in parents and mimeType='{}' and trashed=false",
         folder_name.replace("'", "\\'"),
@@ -59,40 +517,45 @@ async fn find_folder(
 
     let url = format!("{}/files", DRIVE_API_BASE);
 
-    let response = client.client()
+    let request = client.client()
         .get(&url)
         .bearer_auth(client.access_token())
-        .query(&[("q", &query), ("fields", &"files(id, name)".to_string())])
-        .send()
+        .query(&[("q", query.as_str()), ("fields", "files(id, name)"), ("orderBy", "createdTime")]);
+    let response = telemetry::send("Drive", request)
         .await
         .context("Failed to search for folder")?;
 
     if !response.status().is_success() {
         let status = response.status();
         let error_text = response.text().await.unwrap_or_default();
-        anyhow::bail!("Drive API error ({}): {}", status, error_text);
+        return Err(ApiError::new("Drive", status, error_text).into());
     }
 
     let result: FileListResponse = response.json().await
         .context("Failed to parse folder search response")?;
 
-    let folder_id = result.files.and_then(|files| {
-        log::debug!("Found {} folders matching search", files.len());
-        files.first().map(|f| {
-            log::debug!("Using folder: name='{}', id='{}'", f.name, f.id);
-            f.id.clone()
-        })
-    });
+    let folders = result.files.unwrap_or_default();
+    log::debug!("Found {} folders matching search", folders.len());
+    if let Some(f) = folders.first() {
+        log::debug!("Using folder: name='{}', id='{}'", f.name, f.id);
+    }
+
+    Ok(folders)
+}
 
-    Ok(folder_id)
+/// Whether Drive turned a request down for going too fast (403 with a rate-limit reason, or 429)
+fn is_rate_limited(status: reqwest::StatusCode, error_text: &str) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || (status == reqwest::StatusCode::FORBIDDEN && (error_text.contains("rateLimitExceeded") || error_text.contains("userRateLimitExceeded")))
 }
 
-/// Create a new folder
+/// Create a new folder, backing off while Drive rate-limits. None when Drive reports a conflict,
+/// left to the caller to resolve by looking the folder up.
 async fn create_folder(
     client: &DriveClient,
     folder_name: &str,
     parent_id: &str,
-) -> Result<String> {
+) -> Result<Option<String>> {
     let url = format!("{}/files", DRIVE_API_BASE);
 
     let metadata = FileMetadata {
@@ -101,19 +564,33 @@ async fn create_folder(
         mime_type: Some(FOLDER_MIME_TYPE.to_string()),
     };
 
-    let response = client.client()
-        .post(&url)
-        .bearer_auth(client.access_token())
-        .json(&metadata)
-        .send()
-        .await
-        .context("Failed to create folder")?;
+    let mut attempt = 0;
+    let response = loop {
+        let request = client.client()
+            .post(&url)
+            .bearer_auth(client.access_token())
+            .json(&metadata);
+        let response = telemetry::send("Drive", request)
+            .await
+            .context("Failed to create folder")?;
 
-    if !response.status().is_success() {
         let status = response.status();
+        if status.is_success() {
+            break response;
+        }
+        if status == reqwest::StatusCode::CONFLICT {
+            log::warn!("Drive reported a conflict creating folder '{}'", folder_name);
+            return Ok(None);
+        }
         let error_text = response.text().await.unwrap_or_default();
-        anyhow::bail!("Drive API error ({}): {}", status, error_text);
-    }
+        attempt += 1;
+        if !is_rate_limited(status, &error_text) || attempt >= CREATE_ATTEMPTS {
+            return Err(ApiError::new("Drive", status, error_text).into());
+        }
+        let delay = Duration::from_secs(1 << attempt);
+        log::warn!("Drive rate limit creating folder '{}', retrying in {}s", folder_name, delay.as_secs());
+        tokio::time::sleep(delay).await;
+    };
 
     let created: serde_json::Value = response.json().await
         .context("Failed to parse folder creation response")?;
@@ -122,12 +599,54 @@ async fn create_folder(
         .context("Folder ID not found in response")?
         .to_string();
 
-    Ok(folder_id)
+    Ok(Some(folder_id))
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
 
+    #[test]
+    fn test_folder_locks_are_evicted_when_released() {
+        let held = folder_lock("evict-test", "2025");
+        drop(folder_lock("evict-test", "March"));
+        let _next = folder_lock("evict-test", "April");
+
+        let locks = FOLDER_LOCKS.lock().unwrap();
+        let key = |name: &str| ("evict-test".to_string(), name.to_string());
+        assert!(locks.contains_key(&key("2025")));
+        assert!(!locks.contains_key(&key("March")));
+        drop(held);
+    }
+
+    #[test]
+    fn test_rate_limit_and_locks() {
+        let forbidden = reqwest::StatusCode::FORBIDDEN;
+        assert!(is_rate_limited(forbidden, r#"{"error":{"errors":[{"reason":"userRateLimitExceeded"}]}}"#));
+        assert!(is_rate_limited(reqwest::StatusCode::TOO_MANY_REQUESTS, ""));
+        assert!(!is_rate_limited(forbidden, r#"{"error":{"errors":[{"reason":"insufficientPermissions"}]}}"#));
+
+        // The same folder always gets the same lock
+        assert!(Arc::ptr_eq(&folder_lock("root", "billing"), &folder_lock("root", "billing")));
+        assert!(!Arc::ptr_eq(&folder_lock("root", "billing"), &folder_lock("root", "2025")));
+    }
+
+    #[test]
+    fn test_folder_access_check() {
+        let access: FolderAccess = serde_json::from_str(
+            r#"{"name": "billing", "capabilities": {"canAddChildren": false},
+                "owners": [{"displayName": "Jane Doe", "emailAddress": "jane@example.com"}]}"#,
+        )
+        .unwrap();
+        let error = access.check("billing").unwrap_err().to_string();
+        assert!(error.contains("'billing' (owned by Jane Doe <jane@example.com>)"), "{}", error);
+
+        let shared_drive: FolderAccess = serde_json::from_str(r#"{"name": "billing", "driveId": "0AB", "capabilities": {"canAddChildren": false}}"#).unwrap();
+        assert!(shared_drive.check("billing").unwrap_err().to_string().contains("shared drive"));
+
+        let editor: FolderAccess = serde_json::from_str(r#"{"name": "billing", "capabilities": {"canAddChildren": true}}"#).unwrap();
+        assert!(editor.check("billing").is_ok());
+    }
 
     #[test]
     fn test_folder_path_parsing() {
@@ -1,3 +1,4 @@
 pub mod client;
+pub mod download;
 pub mod folder;
 pub mod upload;
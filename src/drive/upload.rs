@@ -1,8 +1,60 @@
 use anyhow::{Context, Result};
 use reqwest::multipart::{Form, Part};
+use std::collections::HashMap;
 use std::path::Path;
 use tokio::sync::mpsc;
 use super::client::{DriveClient, DRIVE_UPLOAD_BASE, FileMetadata, UploadedFile, FileListResponse, DRIVE_API_BASE};
+use crate::process::outcome::{ApiError, Step};
+use crate::process::telemetry;
+
+/// Folders holding more files than this (one page of a listing) are checked for duplicates with
+/// a query per file instead of being listed
+const LISTED_MAX_FILES: usize = 1000;
+
+/// How an upload finds a file of the same name already in the folder
+enum Duplicates {
+    /// A search per file
+    Query,
+    /// Against the folder's files (name to id), listed once for a whole batch
+    Listed(HashMap<String, String>),
+}
+
+impl Duplicates {
+    /// List the folder's files once, unless it holds too many or can't be listed (or in mock mode,
+    /// where a lookup is a file check)
+    async fn for_folder(client: &DriveClient, folder_id: &str) -> Self {
+        if client.mock_root().is_some() {
+            return Duplicates::Query;
+        }
+        match super::folder::list_files_up_to(client, folder_id, LISTED_MAX_FILES).await {
+            Ok(Some(files)) => Duplicates::Listed(files.into_iter().map(|file| (file.name, file.id)).collect()),
+            Ok(None) => Duplicates::Query,
+            Err(e) => {
+                log::debug!("Listing folder {} for duplicate checks failed, searching per file: {:#}", folder_id, e);
+                Duplicates::Query
+            }
+        }
+    }
+
+    async fn find(&self, client: &DriveClient, filename: &str, folder_id: &str) -> Result<Option<UploadedFile>> {
+        match self {
+            Duplicates::Query => find_file_in_folder(client, filename, folder_id).await,
+            Duplicates::Listed(files) => Ok(files.get(filename).map(|id| UploadedFile {
+                id: id.clone(),
+                name: filename.to_string(),
+                web_view_link: None,
+                duplicate: true,
+            })),
+        }
+    }
+
+    /// Note a file uploaded to the folder since it was listed
+    fn add(&mut self, uploaded: &UploadedFile) {
+        if let Duplicates::Listed(files) = self {
+            files.insert(uploaded.name.clone(), uploaded.id.clone());
+        }
+    }
+}
 
 /// Upload a file to Google Drive
 pub async fn upload_file(
@@ -11,6 +63,17 @@ pub async fn upload_file(
     folder_id: &str,
     skip_duplicates: bool,
     tx: Option<&mpsc::UnboundedSender<String>>,
+) -> Result<UploadedFile> {
+    upload_checked(client, file_path, folder_id, skip_duplicates.then_some(&Duplicates::Query), tx).await
+}
+
+/// Upload a file, unless `duplicates` finds one of the same name in the folder
+async fn upload_checked(
+    client: &DriveClient,
+    file_path: &Path,
+    folder_id: &str,
+    duplicates: Option<&Duplicates>,
+    tx: Option<&mpsc::UnboundedSender<String>>,
 ) -> Result<UploadedFile> {
     let filename = file_path.file_name()
         .context("Invalid file path")?
@@ -18,26 +81,41 @@ pub async fn upload_file(
         .to_string();
 
     // Check for duplicates if requested
-    if skip_duplicates {
-        if let Some(existing_file) = find_file_in_folder(client, &filename, folder_id).await? {
-            if let Some(tx) = tx {
-                let _ = tx.send(format!("   ⚠ Skipping duplicate: {} (already exists)", filename));
-            }
-            return Ok(existing_file);
+    if let Some(duplicates) = duplicates && let Some(existing_file) = duplicates.find(client, &filename, folder_id).await? {
+        if let Some(tx) = tx {
+            let _ = tx.send(format!("   ⚠ Skipping duplicate: {} (already exists)", filename));
+            let _ = tx.send(Step::FileDone { failed: false }.marker());
         }
+        return Ok(existing_file);
     }
 
     if let Some(tx) = tx {
         let _ = tx.send(format!("   ↑ Uploading: {}...", filename));
     }
 
+    if client.mock_root().is_some() {
+        let target = Path::new(folder_id).join(&filename);
+        std::fs::copy(file_path, &target).context("Failed to copy file into mock Drive folder")?;
+        if let Some(tx) = tx {
+            let _ = tx.send(format!("   ✓ Uploaded: {} (mock: {})", filename, target.display()));
+            let _ = tx.send(Step::FileDone { failed: false }.marker());
+        }
+        return Ok(UploadedFile {
+            id: target.to_string_lossy().to_string(),
+            name: filename,
+            web_view_link: None,
+            duplicate: false,
+        });
+    }
+
     let file_data = std::fs::read(file_path)
         .context("Failed to read file")?;
 
+    let mime_type = mime_type_for(&filename);
     let metadata = FileMetadata {
         name: filename.clone(),
         parents: Some(vec![folder_id.to_string()]),
-        mime_type: Some("application/pdf".to_string()),
+        mime_type: Some(mime_type.to_string()),
     };
 
     let metadata_json = serde_json::to_string(&metadata)
@@ -49,26 +127,26 @@ pub async fn upload_file(
 
     let file_part = Part::bytes(file_data)
         .file_name(filename.clone())
-        .mime_str("application/pdf")?;
+        .mime_str(mime_type)?;
 
     let form = Form::new()
         .part("metadata", metadata_part)
         .part("file", file_part);
 
-    let url = format!("{}/files?uploadType=multipart", DRIVE_UPLOAD_BASE);
+    let url = format!("{}/files?uploadType=multipart&fields=id,name,webViewLink", DRIVE_UPLOAD_BASE);
 
-    let response = client.client()
+    let request = client.client()
         .post(&url)
         .bearer_auth(client.access_token())
-        .multipart(form)
-        .send()
+        .multipart(form);
+    let response = telemetry::send("Drive", request)
         .await
         .context("Failed to upload file")?;
 
     if !response.status().is_success() {
         let status = response.status();
         let error_text = response.text().await.unwrap_or_default();
-        anyhow::bail!("Drive API error ({}): {}", status, error_text);
+        return Err(ApiError::new("Drive", status, error_text).into());
     }
 
     let uploaded: UploadedFile = response.json().await
@@ -76,17 +154,50 @@ pub async fn upload_file(
 
     if let Some(tx) = tx {
         let _ = tx.send(format!("   ✓ Uploaded: {} (ID: {})", filename, uploaded.id));
+        let _ = tx.send(Step::FileDone { failed: false }.marker());
     }
     Ok(uploaded)
 }
 
+/// Hex SHA-256 of file contents, the form Drive reports as `sha256Checksum`
+pub fn sha256_hex(data: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Pick the upload MIME type from the file extension (attachments are mostly PDFs)
+pub fn mime_type_for(filename: &str) -> &'static str {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    match extension.as_deref() {
+        Some("zip") => "application/zip",
+        Some("csv") => "text/csv",
+        Some("age") => "application/octet-stream",
+        _ => "application/pdf",
+    }
+}
+
 /// Find a file by name in a specific folder
 async fn find_file_in_folder(
     client: &DriveClient,
     filename: &str,
     folder_id: &str,
 ) -> Result<Option<UploadedFile>> {
+    if client.mock_root().is_some() {
+        let target = Path::new(folder_id).join(filename);
+        return Ok(target.exists().then(|| UploadedFile {
+            id: target.to_string_lossy().to_string(),
+            name: filename.to_string(),
+            web_view_link: None,
+            duplicate: true,
+        }));
+    }
+
     let query = format!(
+
         "name='{}' and '{}' in parents and trashed=false",
         filename.replace("'", "\\'"),
         folder_id
@@ -94,11 +205,11 @@ async fn find_file_in_folder(
 
     let url = format!("{}/files", DRIVE_API_BASE);
 
-    let response = client.client()
+    let request = client.client()
         .get(&url)
         .bearer_auth(client.access_token())
-        .query(&[("q", &query), ("fields", &"files(id, name, webViewLink)".to_string())])
-        .send()
+        .query(&[("q", &query), ("fields", &"files(id, name, webViewLink)".to_string())]);
+    let response = telemetry::send("Drive", request)
         .await
         .context("Failed to search for file")?;
 
@@ -109,41 +220,78 @@ async fn find_file_in_folder(
     let result: FileListResponse = response.json().await
         .context("Failed to parse file search response")?;
 
-    if let Some(files) = result.files {
-        if let Some(file) = files.first() {
-            return Ok(Some(UploadedFile {
-                id: file.id.clone(),
-                name: file.name.clone(),
-                web_view_link: None,
-            }));
-        }
+    if let Some(files) = result.files && let Some(file) = files.first() {
+        return Ok(Some(UploadedFile {
+            id: file.id.clone(),
+            name: file.name.clone(),
+            web_view_link: None,
+            duplicate: true,
+        }));
     }
 
     Ok(None)
 }
 
-/// Upload multiple files and return summary
+/// Upload multiple files and return summary. The folder is listed once for the duplicate checks
+/// rather than searched per file.
 pub async fn upload_files(
     client: &DriveClient,
     file_paths: &[std::path::PathBuf],
     folder_id: &str,
     tx: Option<&mpsc::UnboundedSender<String>>,
 ) -> Result<UploadSummary> {
+    let mut summary = UploadSummary::default();
+    let mut duplicates = Duplicates::for_folder(client, folder_id).await;
+
     for file_path in file_paths {
-        match upload_file(client, file_path, folder_id, true, tx).await {
-            Ok(_) => {
-                // File uploaded successfully
-            },
+        match upload_checked(client, file_path, folder_id, Some(&duplicates), tx).await {
+            Ok(uploaded) if uploaded.duplicate => summary.skipped += 1,
+            Ok(uploaded) => {
+                duplicates.add(&uploaded);
+                summary.uploaded += 1;
+                summary.uploaded_files.push((file_path.clone(), uploaded));
+            }
             Err(e) => {
+                summary.failed += 1;
                 if let Some(tx) = tx {
                     let _ = tx.send(format!("   ✗ Failed to upload {}: {}", file_path.display(), e));
+                    let _ = tx.send(Step::FileDone { failed: true }.marker());
                 }
+                summary.failed_files.push((file_path.clone(), e));
             }
         }
     }
 
-    Ok(UploadSummary {})
+    Ok(summary)
+}
+
+#[derive(Debug, Default)]
+pub struct UploadSummary {
+    pub uploaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    // Local path and Drive file for every new upload
+    pub uploaded_files: Vec<(std::path::PathBuf, UploadedFile)>,
+    // Local path and error for every failed upload
+    pub failed_files: Vec<(std::path::PathBuf, anyhow::Error)>,
 }
 
-#[derive(Debug, Clone)]
-pub struct UploadSummary {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_listed_duplicates() {
+        let client = DriveClient::new("token".to_string());
+        let mut duplicates = Duplicates::Listed(HashMap::from([("2025-03-aws.pdf".to_string(), "F1".to_string())]));
+
+        let existing = duplicates.find(&client, "2025-03-aws.pdf", "FOLDER").await.unwrap().unwrap();
+        assert_eq!((existing.id.as_str(), existing.duplicate), ("F1", true));
+        assert!(duplicates.find(&client, "2025-03-hetzner.pdf", "FOLDER").await.unwrap().is_none());
+
+        // A file uploaded earlier in the batch counts as already there
+        let uploaded = UploadedFile { id: "F2".to_string(), name: "2025-03-hetzner.pdf".to_string(), web_view_link: None, duplicate: false };
+        duplicates.add(&uploaded);
+        assert_eq!(duplicates.find(&client, "2025-03-hetzner.pdf", "FOLDER").await.unwrap().unwrap().id, "F2");
+    }
+}
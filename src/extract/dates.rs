@@ -0,0 +1,174 @@
+use chrono::NaiveDate;
+
+/// Labels that usually precede the issue date, in the languages of the keyword packs
+const DATE_LABELS: &[&str] = &[
+    "invoice date", "date of issue", "issue date", "issued on", "rechnungsdatum", "datum",
+    "fecha de emisión", "fecha", "data de emissão", "data da fatura", "date de facture",
+    "data fattura", "date", "data",
+];
+
+/// Labels that precede the date payment is due by
+const DUE_LABELS: &[&str] = &[
+    "due date", "payment due", "amount due by", "due by", "due on", "pay by", "payable by", "fälligkeitsdatum",
+    "fällig am", "zahlbar bis", "fecha de vencimiento", "vencimiento", "data de vencimento", "vencimento",
+    "date d'échéance", "échéance", "data di scadenza", "scadenza",
+];
+
+/// How far after a label the date may start (in bytes)
+const LABEL_DISTANCE: usize = 40;
+
+const MONTHS: &[(&str, u32)] = &[
+    ("january", 1), ("february", 2), ("march", 3), ("april", 4), ("may", 5), ("june", 6),
+    ("july", 7), ("august", 8), ("september", 9), ("october", 10), ("november", 11), ("december", 12),
+    ("janeiro", 1), ("fevereiro", 2), ("março", 3), ("abril", 4), ("maio", 5), ("junho", 6),
+    ("julho", 7), ("agosto", 8), ("setembro", 9), ("outubro", 10), ("novembro", 11), ("dezembro", 12),
+    ("enero", 1), ("febrero", 2), ("marzo", 3), ("mayo", 5), ("junio", 6), ("julio", 7),
+    ("septiembre", 9), ("octubre", 10), ("noviembre", 11), ("diciembre", 12),
+    ("januar", 1), ("februar", 2), ("märz", 3), ("juni", 6), ("juli", 7), ("oktober", 10), ("dezember", 12),
+    ("janvier", 1), ("février", 2), ("mars", 3), ("avril", 4), ("mai", 5), ("juin", 6), ("juillet", 7),
+    ("août", 8), ("septembre", 9), ("octobre", 10), ("novembre", 11), ("décembre", 12),
+    ("gennaio", 1), ("febbraio", 2), ("aprile", 4), ("maggio", 5), ("giugno", 6), ("luglio", 7),
+    ("settembre", 9), ("ottobre", 10), ("dicembre", 12),
+    ("jan", 1), ("feb", 2), ("mar", 3), ("apr", 4), ("jun", 6), ("jul", 7), ("aug", 8),
+    ("sep", 9), ("sept", 9), ("oct", 10), ("nov", 11), ("dec", 12),
+];
+
+/// The document's own date: the first date right after a label such as "Invoice date",
+/// otherwise the first date in the text
+pub fn document_date(text: &str) -> Option<NaiveDate> {
+    let text = text.to_lowercase();
+    let dates = find_dates(&text);
+    labelled_date(&text, &dates, DATE_LABELS).or_else(|| dates.first().map(|(_, date)| *date))
+}
+
+/// The date payment is due by, when a label such as "Due date" or "Fällig am" precedes one
+pub fn due_date(text: &str) -> Option<NaiveDate> {
+    let text = text.to_lowercase();
+    labelled_date(&text, &find_dates(&text), DUE_LABELS)
+}
+
+/// The date right after the label appearing first in `text`
+fn labelled_date(text: &str, dates: &[(usize, NaiveDate)], labels: &[&str]) -> Option<NaiveDate> {
+    labels
+        .iter()
+        .flat_map(|label| text.match_indices(label).map(|(index, _)| index + label.len()))
+        .filter_map(|label_end| {
+            dates
+                .iter()
+                .find(|(offset, _)| *offset >= label_end && *offset - label_end <= LABEL_DISTANCE)
+                .map(|(_, date)| (label_end, *date))
+        })
+        .min_by_key(|(label_end, _)| *label_end)
+        .map(|(_, date)| date)
+}
+
+/// Every date in lowercase text with its byte offset, in order of appearance
+pub fn find_dates(text: &str) -> Vec<(usize, NaiveDate)> {
+    let mut dates = numeric_dates(text);
+    dates.extend(named_month_dates(text));
+    dates.sort_by_key(|(offset, _)| *offset);
+    dates
+}
+
+/// 2025-03-07, 2025/03/07, 07.03.2025, 07/03/2025 (day first unless that's impossible), 20250307
+fn numeric_dates(text: &str) -> Vec<(usize, NaiveDate)> {
+    let mut dates = Vec::new();
+    for (offset, token) in runs(text, |c| c.is_ascii_digit() || matches!(c, '-' | '.' | '/')) {
+        let token = token.trim_matches(|c: char| !c.is_ascii_digit());
+        let parts: Vec<&str> = token.split(['-', '.', '/']).collect();
+
+        let date = match parts.as_slice() {
+            [compact] if compact.len() == 8 => ymd(&compact[..4], &compact[4..6], &compact[6..]),
+            [y, m, d] if y.len() == 4 => ymd(y, m, d),
+            [a, b, y] if y.len() == 4 && a.len() <= 2 && b.len() <= 2 => {
+                ymd(y, b, a).or_else(|| ymd(y, a, b))
+            }
+            _ => None,
+        };
+        if let Some(date) = date {
+            dates.push((offset, date));
+        }
+    }
+    dates
+}
+
+/// 7 March 2025, March 7, 2025, 7. März 2025, 7 de março de 2025
+fn named_month_dates(text: &str) -> Vec<(usize, NaiveDate)> {
+    let words: Vec<(usize, &str)> = runs(text, char::is_alphanumeric)
+        .into_iter()
+        .filter(|(_, word)| !matches!(*word, "de" | "of" | "the"))
+        .collect();
+
+    let month = |word: &str| MONTHS.iter().find(|(name, _)| *name == word).map(|(_, m)| *m);
+    let number = |word: &str| word.parse::<u32>().ok();
+
+    words
+        .windows(3)
+        .filter_map(|window| {
+            let [(offset, first), (_, second), (_, third)] = window else { return None };
+            let year = number(third).filter(|_| third.len() == 4)? as i32;
+            let (day, month) = match (number(first), month(second), month(first), number(second)) {
+                (Some(day), Some(month), _, _) => (day, month),
+                (_, _, Some(month), Some(day)) => (day, month),
+                _ => return None,
+            };
+            NaiveDate::from_ymd_opt(year, month, day).map(|date| (*offset, date))
+        })
+        .collect()
+}
+
+fn ymd(year: &str, month: &str, day: &str) -> Option<NaiveDate> {
+    let year: i32 = year.parse().ok()?;
+    if !(1990..=2100).contains(&year) {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year, month.parse().ok()?, day.parse().ok()?)
+}
+
+/// Maximal runs of characters matching `keep`, with their byte offsets
+fn runs(text: &str, keep: impl Fn(char) -> bool) -> Vec<(usize, &str)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices() {
+        match (keep(c), start) {
+            (true, None) => start = Some(index),
+            (false, Some(s)) => {
+                runs.push((s, &text[s..index]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        runs.push((s, &text[s..]));
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(y, m, d)
+    }
+
+    #[test]
+    fn test_document_date() {
+        assert_eq!(document_date("Hetzner Online GmbH\nInvoice R0012345678\nDate: 2025-03-07\nTotal: EUR 49.99"), date(2025, 3, 7));
+        assert_eq!(document_date("Period 01.02.2025 - 28.02.2025\nRechnungsdatum: 03.03.2025"), date(2025, 3, 3));
+        assert_eq!(document_date("Invoice date March 4, 2025"), date(2025, 3, 4));
+        assert_eq!(document_date("Emitida em 7 de março de 2025"), date(2025, 3, 7));
+        assert_eq!(document_date("Paid 03/15/2025"), date(2025, 3, 15));
+        assert_eq!(document_date("revolut-account-statement_2025-02-01_2025-02-28.pdf"), date(2025, 2, 1));
+        assert_eq!(document_date("Total 1.234.567 EUR, page 1/2"), None);
+    }
+
+    #[test]
+    fn test_due_date() {
+        assert_eq!(due_date("Invoice date: 2025-03-04\nDue date: 2025-04-03\nTotal: EUR 231.40"), date(2025, 4, 3));
+        assert_eq!(due_date("Rechnungsdatum 03.03.2025, zahlbar bis 17.03.2025"), date(2025, 3, 17));
+        assert_eq!(due_date("Data de vencimento: 10 de abril de 2025"), date(2025, 4, 10));
+        assert_eq!(due_date("Hetzner Online GmbH\nDate: 2025-03-07\nTotal: EUR 49.99"), None);
+    }
+}
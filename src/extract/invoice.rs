@@ -0,0 +1,200 @@
+use chrono::NaiveDate;
+
+/// Labels that precede an invoice number; the bare words come last and need the number right after them
+const NUMBER_LABELS: &[&str] = &[
+    "invoice number", "invoice no", "invoice nr", "invoice #", "invoice id", "receipt number", "receipt no",
+    "receipt #", "document number", "número de factura", "numero de factura", "factura n", "número da fatura",
+    "numero da fatura", "fatura n", "rechnungsnummer", "rechnung nr", "numéro de facture", "facture n",
+    "numero fattura", "fattura n",
+];
+const BARE_NUMBER_LABELS: &[&str] = &["invoice", "factura", "fatura", "rechnung", "facture", "fattura", "receipt"];
+
+/// Labels that precede the amount to pay, most specific first
+const AMOUNT_LABELS: &[&str] = &[
+    "amount due", "total due", "balance due", "grand total", "total amount", "total a pagar", "importe total",
+    "valor total", "gesamtbetrag", "rechnungsbetrag", "montant total", "total ttc", "importo totale", "totale",
+    "total",
+];
+
+/// Titles of credit notes and refunds; only looked for in the document's heading, since invoices
+/// mention refunds in their terms
+const CREDIT_NOTE_LABELS: &[&str] = &[
+    "credit note", "credit memo", "refund receipt", "refund confirmation", "gutschrift", "rechnungskorrektur",
+    "stornorechnung", "nota de crédito", "nota de credito", "factura rectificativa", "note de crédit",
+    "facture d'avoir", "nota di credito",
+];
+
+/// Subfolder of the institution folder (or the month folder) credit notes are filed in with
+/// CREDIT_NOTES_SUBFOLDER
+pub const CREDIT_NOTES_FOLDER: &str = "Credit Notes";
+
+/// How much of the start of a document counts as its heading (in characters)
+const HEADING_LENGTH: usize = 300;
+
+/// How far after a label the value may start (in bytes)
+const LABEL_DISTANCE: usize = 40;
+
+const CURRENCIES: &[(&str, &str)] = &[
+    ("€", "EUR"), ("eur", "EUR"), ("$", "USD"), ("usd", "USD"), ("£", "GBP"), ("gbp", "GBP"),
+    ("chf", "CHF"), ("r$", "BRL"), ("brl", "BRL"), ("pln", "PLN"), ("sek", "SEK"), ("dkk", "DKK"), ("nok", "NOK"),
+];
+
+/// What identifies an invoice regardless of the file it arrived in
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InvoiceFields {
+    /// Uppercase, separators removed: "eur-inv-2025/0311" -> "EURINV20250311"
+    pub number: Option<String>,
+    pub amount_cents: Option<i64>,
+    pub currency: Option<String>,
+    pub due_date: Option<NaiveDate>,
+    /// A credit note or refund, titled as one or with a negative total; its amount is negative
+    pub credit_note: bool,
+}
+
+pub fn invoice_fields(text: &str) -> InvoiceFields {
+    let text = text.to_lowercase();
+    let amount = labelled_amount(&text);
+    let heading: String = text.chars().take(HEADING_LENGTH).collect();
+    let credit_note = amount.as_ref().is_some_and(|(cents, _)| *cents < 0)
+        || CREDIT_NOTE_LABELS.iter().any(|label| word_matches(&heading, label).next().is_some());
+    InvoiceFields {
+        number: invoice_number(&text),
+        // Credit notes often print the amount credited without a sign
+        amount_cents: amount.as_ref().map(|(cents, _)| if credit_note { -cents.abs() } else { *cents }),
+        currency: amount.and_then(|(_, currency)| currency),
+        due_date: super::dates::due_date(&text),
+        credit_note,
+    }
+}
+
+/// Format cents as "1234.56" ("-0.50" for negative amounts)
+pub fn format_amount(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+fn invoice_number(text: &str) -> Option<String> {
+    let labelled = NUMBER_LABELS.iter().find_map(|label| {
+        word_matches(text, label).find_map(|end| {
+            let rest = &text[end..];
+            // Skip "no.", ":", "º" and similar between the label and the number
+            let start = rest.find(|c: char| c.is_ascii_alphanumeric())?;
+            (start <= LABEL_DISTANCE).then(|| number_token(&rest[start..])).flatten()
+        })
+    });
+
+    labelled.or_else(|| {
+        BARE_NUMBER_LABELS.iter().find_map(|label| {
+            word_matches(text, label).find_map(|end| {
+                let rest = text[end..].trim_start_matches([' ', ':', '#']);
+                number_token(rest)
+            })
+        })
+    })
+}
+
+/// A reference like R0012345678 or EUR-INV-2025-0311 at the start of `text`
+fn number_token(text: &str) -> Option<String> {
+    let token: String = text
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '/' | '_' | '.'))
+        .collect();
+    let normalized: String = token.chars().filter(char::is_ascii_alphanumeric).collect::<String>().to_uppercase();
+    (normalized.len() >= 3 && normalized.chars().any(|c| c.is_ascii_digit())).then_some(normalized)
+}
+
+fn labelled_amount(text: &str) -> Option<(i64, Option<String>)> {
+    AMOUNT_LABELS.iter().find_map(|label| {
+        word_matches(text, label).find_map(|end| {
+            let window: String = text[end..].chars().take(LABEL_DISTANCE).collect();
+            let start = window.find(|c: char| c.is_ascii_digit())?;
+            // "subtotal" and "total items: 3" are not what we're after
+            let cents = parse_amount(&window[start..])?;
+            // "-49.99", "eur -49.99", "-€49.99", "−49,99"
+            let negative = window[..start]
+                .trim_end_matches(|c: char| c.is_whitespace() || matches!(c, '€' | '$' | '£'))
+                .ends_with(['-', '−']);
+            let cents = if negative { -cents } else { cents };
+            let currency = CURRENCIES
+                .iter()
+                .find(|(symbol, _)| window.contains(symbol))
+                .map(|(_, code)| code.to_string());
+            Some((cents, currency))
+        })
+    })
+}
+
+/// "1.234,56", "1,234.56", "231.40", "49" -> cents; amounts without decimals are whole units
+fn parse_amount(text: &str) -> Option<i64> {
+    let raw: String = text
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '\'' | ' '))
+        .collect();
+    let raw = raw.trim_end_matches(['.', ',', ' ']);
+    let digits: String = raw.chars().filter(char::is_ascii_digit).collect();
+    if digits.is_empty() {
+        return None;
+    }
+
+    let has_decimals = raw
+        .rfind(['.', ','])
+        .is_some_and(|pos| raw[pos + 1..].len() == 2 && raw[pos + 1..].chars().all(|c| c.is_ascii_digit()));
+    let value: i64 = digits.parse().ok()?;
+    if !has_decimals && !raw.contains(['.', ',', ' ', '\'']) && value < 10 {
+        // A lone digit ("total items: 3") is not an amount
+        return None;
+    }
+    Some(if has_decimals { value } else { value * 100 })
+}
+
+/// End offsets of `word` where it stands as whole words in `text`
+fn word_matches<'a>(text: &'a str, word: &'a str) -> impl Iterator<Item = usize> + 'a {
+    text.match_indices(word).filter_map(move |(start, _)| {
+        let end = start + word.len();
+        let before = text[..start].chars().next_back();
+        let after = text[end..].chars().next();
+        let boundary = |c: Option<char>| !c.is_some_and(char::is_alphanumeric);
+        // "invoice #" ends in punctuation, so anything may follow it
+        let after_ok = !word.ends_with(char::is_alphanumeric) || boundary(after);
+        (boundary(before) && after_ok).then_some(end)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invoice_fields() {
+        let aws = invoice_fields("Amazon Web Services EMEA SARL\nInvoice Number: EUR-INV-2025-0311\nInvoice Date: 2025-03-04\nSubtotal: EUR 200.00\nTotal: EUR 231.40");
+        assert_eq!(aws, InvoiceFields { number: Some("EURINV20250311".to_string()), amount_cents: Some(23140), currency: Some("EUR".to_string()), due_date: None, credit_note: false });
+
+        let hetzner = invoice_fields("Hetzner Online GmbH\nInvoice R0012345678\nDate: 2025-03-07\nTotal: EUR 49.99");
+        assert_eq!(hetzner.number.as_deref(), Some("R0012345678"));
+        assert_eq!(hetzner.amount_cents, Some(4999));
+
+        let german = invoice_fields("Rechnungsnummer: 2025/0042\nGesamtbetrag 1.234,56 €");
+        assert_eq!((german.number.as_deref(), german.amount_cents), (Some("20250042"), Some(123456)));
+
+        let statement = invoice_fields("Revolut Ltd\nEUR Statement\nOpening balance EUR 1,250.00");
+        assert_eq!((statement.number, statement.amount_cents), (None, None));
+        assert_eq!(format_amount(123456), "1234.56");
+    }
+
+    #[test]
+    fn test_credit_notes() {
+        let titled = invoice_fields("Hetzner Online GmbH\nCredit Note R0012345679\nDate: 2025-03-20\nTotal: EUR 12.50");
+        assert!(titled.credit_note);
+        assert_eq!(titled.amount_cents, Some(-1250));
+
+        let signed = invoice_fields("Amazon Web Services EMEA SARL\nInvoice Number: EUR-CN-2025-0042\nTotal: EUR -231.40");
+        assert!(signed.credit_note);
+        assert_eq!(signed.amount_cents, Some(-23140));
+        assert_eq!(invoice_fields("Gutschrift Nr. 2025/0042\nGesamtbetrag −1.234,56 €").amount_cents, Some(-123456));
+
+        // Refund terms further down an invoice don't make it a credit note
+        let terms = format!("Invoice R001\nTotal: EUR 49.99\n{}Refunds are issued as a credit note.", "Line item\n".repeat(40));
+        assert!(!invoice_fields(&terms).credit_note);
+        assert_eq!(format_amount(-50), "-0.50");
+    }
+}
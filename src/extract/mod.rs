@@ -0,0 +1,48 @@
+//! Content extraction from downloaded documents (text, dates, invoice fields, statement transactions)
+
+pub mod dates;
+pub mod invoice;
+pub mod statement;
+
+use log::warn;
+use std::path::Path;
+
+/// Plain text of a document, or None when the type isn't supported or extraction failed.
+/// Only PDFs are supported; scanned PDFs without a text layer yield empty text.
+pub fn document_text(filename: &str, data: &[u8]) -> Option<String> {
+    let is_pdf = Path::new(filename)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"));
+    if !is_pdf {
+        return None;
+    }
+
+    // The PDF parser panics on some malformed files; treat that like any other failure
+    match std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(data)) {
+        Ok(Ok(text)) => Some(text),
+        Ok(Err(e)) => {
+            warn!("Could not extract text from {}: {}", filename, e);
+            None
+        }
+        Err(_) => {
+            warn!("Could not extract text from {}: parser panicked", filename);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_text_from_fixture_pdf() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures/gmail/attachments/att-hetzner-invoice.pdf");
+        let text = document_text("invoice.pdf", &std::fs::read(path).unwrap()).unwrap();
+
+        assert!(text.contains("Invoice R0012345678"));
+        assert_eq!(dates::document_date(&text), chrono::NaiveDate::from_ymd_opt(2025, 3, 7));
+        assert_eq!(document_text("notes.txt", b"plain"), None);
+        assert_eq!(document_text("broken.pdf", b"not a pdf"), None);
+    }
+}
@@ -0,0 +1,530 @@
+//! Bank statement transactions from CSV exports, CAMT.053 XML and PDF text. CSV columns are found
+//! by their header names, or by a bank's mapping in the statement formats file
+//! (STATEMENT_FORMATS_FILE, `statements.toml` in the config directory or the profile's by default):
+//!
+//! ```toml
+//! [[bank]]
+//! name = "Sparkasse"
+//! separator = ";"
+//! date = "Buchungstag"
+//! amount = "Betrag"
+//! description = ["Beguenstigter/Zahlungspflichtiger", "Verwendungszweck"]
+//! currency = "Waehrung"
+//! date_format = "%d.%m.%y"
+//! ```
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use super::dates::find_dates;
+
+/// Header names of the columns a CSV statement is read from, lowercase
+const DATE_HEADERS: &[&str] = &[
+    "date", "booking date", "transaction date", "completed date", "value date", "buchungstag", "buchungsdatum",
+    "valutadatum", "fecha", "fecha operación", "data", "data movimento", "date opération", "data operazione",
+];
+const AMOUNT_HEADERS: &[&str] = &["amount", "betrag", "importe", "valor", "montant", "importo"];
+const DEBIT_HEADERS: &[&str] = &["debit", "paid out", "money out", "soll", "cargo", "débito", "débit", "addebiti"];
+const CREDIT_HEADERS: &[&str] = &["credit", "paid in", "money in", "haben", "abono", "crédito", "crédit", "accrediti"];
+const DESCRIPTION_HEADERS: &[&str] = &[
+    "description", "payee", "merchant", "counterparty", "name", "beguenstigter/zahlungspflichtiger",
+    "empfänger", "verwendungszweck", "concepto", "descrição", "libellé", "descrizione", "reference",
+];
+const CURRENCY_HEADERS: &[&str] = &["currency", "währung", "moneda", "moeda", "devise"];
+
+/// One booked transaction of a bank statement
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Transaction {
+    pub date: NaiveDate,
+    /// Negative for money going out (payments), positive for money coming in (refunds)
+    pub amount_cents: i64,
+    pub currency: Option<String>,
+    pub description: String,
+}
+
+/// Columns of one bank's CSV exports, by header name
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnMapping {
+    /// Institution folder the bank's statements are filed under, also accepted by `reconcile --bank`
+    pub name: String,
+    pub date: String,
+    /// Signed amount; banks with separate columns set `debit` and `credit` instead
+    pub amount: Option<String>,
+    pub debit: Option<String>,
+    pub credit: Option<String>,
+    /// Columns joined into the description
+    #[serde(default)]
+    pub description: Vec<String>,
+    pub currency: Option<String>,
+    /// Field separator; guessed from the header line otherwise
+    pub separator: Option<char>,
+    /// chrono format of the dates, e.g. "%d.%m.%y"; common formats are recognized otherwise
+    pub date_format: Option<String>,
+}
+
+/// Column mappings per bank
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatementFormats {
+    #[serde(default, rename = "bank")]
+    banks: Vec<ColumnMapping>,
+}
+
+impl StatementFormats {
+    /// Load the statement formats file; no file configured means header names are guessed
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read statement formats file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid statement formats file {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut formats: Self = toml::from_str(text)?;
+        for mapping in &mut formats.banks {
+            anyhow::ensure!(!mapping.name.trim().is_empty(), "every bank needs a name");
+            anyhow::ensure!(
+                mapping.amount.is_some() || mapping.debit.is_some(),
+                "bank {} needs an amount column, or debit and credit columns",
+                mapping.name
+            );
+            // Headers are compared in lowercase
+            for column in [&mut mapping.amount, &mut mapping.debit, &mut mapping.credit, &mut mapping.currency].into_iter().flatten() {
+                *column = column.trim().to_lowercase();
+            }
+            mapping.date = mapping.date.trim().to_lowercase();
+            mapping.description.iter_mut().for_each(|column| *column = column.trim().to_lowercase());
+        }
+        Ok(formats)
+    }
+
+    /// The mapping of a bank, by name (case-insensitive)
+    pub fn for_bank(&self, bank: &str) -> Option<&ColumnMapping> {
+        self.banks.iter().find(|mapping| mapping.name.eq_ignore_ascii_case(bank.trim()))
+    }
+}
+
+/// Whether a file is a statement transactions can be read from when it is archived (CSV, or XML
+/// that is a CAMT.053 statement; PDFs are archived as documents only)
+pub fn is_statement_export(filename: &str, data: &[u8]) -> bool {
+    match extension(filename).as_str() {
+        "csv" => true,
+        "xml" => XmlElement::parse(&decode(data)).is_ok_and(|document| document.find("BkToCstmrStmt").is_some()),
+        _ => false,
+    }
+}
+
+fn extension(filename: &str) -> String {
+    Path::new(filename).extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+/// Transactions of a CSV, CAMT.053 or PDF statement, CSVs read with the bank's column mapping when
+/// it has one. PDFs are read line by line: a line with a date and a signed amount is a transaction,
+/// so statements without signs yield only incoming money or none.
+pub fn parse_statement(filename: &str, data: &[u8], mapping: Option<&ColumnMapping>) -> Result<Vec<Transaction>> {
+    match extension(filename).as_str() {
+        "csv" | "txt" => parse_csv(&decode(data), mapping),
+        "xml" => parse_camt053(&decode(data)),
+        "pdf" => {
+            let text = super::document_text(filename, data).with_context(|| format!("Could not read the text of {}", filename))?;
+            Ok(parse_text(&text))
+        }
+        other => anyhow::bail!("{}: unsupported statement type '{}' (CSV, CAMT.053 XML or PDF)", filename, other),
+    }
+}
+
+/// UTF-8, or Windows-1252 as many banks still export
+fn decode(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(text) => text.trim_start_matches('\u{feff}').to_string(),
+        Err(_) => encoding_rs::WINDOWS_1252.decode(data).0.into_owned(),
+    }
+}
+
+/// Columns of a CSV statement, found by their header names
+#[derive(Debug, Default)]
+struct Columns {
+    date: usize,
+    amount: Option<usize>,
+    debit: Option<usize>,
+    credit: Option<usize>,
+    description: Vec<usize>,
+    currency: Option<usize>,
+    date_format: Option<String>,
+}
+
+impl Columns {
+    fn detect(header: &[String], mapping: Option<&ColumnMapping>) -> Option<Self> {
+        let cells: Vec<String> = header.iter().map(|cell| cell.trim().to_lowercase()).collect();
+        let find = |names: &[&str]| cells.iter().position(|cell| names.contains(&cell.as_str()));
+        let named = |name: &Option<String>| name.as_deref().and_then(|name| find(&[name]));
+        let columns = match mapping {
+            Some(mapping) => Self {
+                date: find(&[mapping.date.as_str()])?,
+                amount: named(&mapping.amount),
+                debit: named(&mapping.debit),
+                credit: named(&mapping.credit),
+                description: mapping.description.iter().filter_map(|name| find(&[name.as_str()])).collect(),
+                currency: named(&mapping.currency),
+                date_format: mapping.date_format.clone(),
+            },
+            None => Self {
+                date: find(DATE_HEADERS)?,
+                amount: find(AMOUNT_HEADERS),
+                debit: find(DEBIT_HEADERS),
+                credit: find(CREDIT_HEADERS),
+                description: cells
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, cell)| DESCRIPTION_HEADERS.contains(&cell.as_str()))
+                    .map(|(index, _)| index)
+                    .collect(),
+                currency: find(CURRENCY_HEADERS),
+                date_format: None,
+            },
+        };
+        (columns.amount.is_some() || columns.debit.is_some()).then_some(columns)
+    }
+
+    fn transaction(&self, row: &[String]) -> Option<Transaction> {
+        let cell = |index: usize| row.get(index).map(|cell| cell.trim()).unwrap_or_default();
+        let date = match &self.date_format {
+            Some(format) => NaiveDate::parse_from_str(cell(self.date), format).ok()?,
+            None => find_dates(&cell(self.date).to_lowercase()).first()?.1,
+        };
+        let amount_cents = match self.amount {
+            Some(index) => parse_signed_amount(cell(index))?,
+            // Separate columns: debits are money out whatever their sign
+            None => match self.debit.and_then(|index| parse_signed_amount(cell(index))).filter(|cents| *cents != 0) {
+                Some(debit) => -debit.abs(),
+                None => self.credit.and_then(|index| parse_signed_amount(cell(index)))?.abs(),
+            },
+        };
+        Some(Transaction {
+            date,
+            amount_cents,
+            currency: self.currency.map(|index| cell(index).to_uppercase()).filter(|currency| !currency.is_empty()),
+            description: self.description.iter().map(|index| cell(*index)).filter(|text| !text.is_empty()).collect::<Vec<_>>().join(" "),
+        })
+    }
+}
+
+/// Transactions of a CSV export: the header is the first line naming a date and an amount (or
+/// debit) column, so preamble lines before it are skipped
+pub fn parse_csv(text: &str, mapping: Option<&ColumnMapping>) -> Result<Vec<Transaction>> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let (separator, columns) = lines
+        .by_ref()
+        .find_map(|line| {
+            let separator = mapping.and_then(|mapping| mapping.separator).unwrap_or_else(|| separator(line));
+            Columns::detect(&split_csv_line(line, separator), mapping).map(|columns| (separator, columns))
+        })
+        .with_context(|| match mapping {
+            Some(mapping) => format!("The CSV header has no '{}' column and amount column of {}'s format", mapping.date, mapping.name),
+            None => "No date and amount columns found in the CSV header".to_string(),
+        })?;
+
+    Ok(lines.filter_map(|line| columns.transaction(&split_csv_line(line, separator))).collect())
+}
+
+/// The most frequent of `;`, tab and `,` in the header line
+fn separator(line: &str) -> char {
+    [';', '\t', ',']
+        .into_iter()
+        .max_by_key(|separator| line.matches(*separator).count())
+        .unwrap_or(',')
+}
+
+/// Fields of one CSV line; quoted fields may contain the separator and doubled quotes
+fn split_csv_line(line: &str, separator: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Booked entries of a CAMT.053 (ISO 20022 bank-to-customer statement) document. The description
+/// is the counterparty's name followed by the remittance information.
+pub fn parse_camt053(xml: &str) -> Result<Vec<Transaction>> {
+    let document = XmlElement::parse(xml).context("Not a readable XML document")?;
+    anyhow::ensure!(document.find("BkToCstmrStmt").is_some(), "Not a CAMT.053 statement (no BkToCstmrStmt element)");
+
+    let mut transactions = Vec::new();
+    for statement in document.find_all("Stmt") {
+        for entry in statement.find_all("Ntry") {
+            // Pending entries aren't booked yet: <Sts>PDNG</Sts>, or <Sts><Cd>PDNG</Cd></Sts> from version 8
+            if entry.text_of("Sts").is_some_and(|status| status.contains("PDNG")) {
+                continue;
+            }
+            let Some(amount) = entry.find("Amt") else {
+                continue;
+            };
+            let Some(cents) = parse_signed_amount(&amount.text) else {
+                continue;
+            };
+            let booked = entry.text_of("BookgDt").or_else(|| entry.text_of("ValDt")).unwrap_or_default();
+            let Some((_, date)) = find_dates(&booked).first().copied() else {
+                continue;
+            };
+            let debit = entry.text_of("CdtDbtInd").is_some_and(|indicator| indicator == "DBIT");
+
+            // Who was paid for debits, who paid for credits
+            let party = entry.find(if debit { "Cdtr" } else { "Dbtr" }).and_then(|party| party.text_of("Nm"));
+            let remittance = entry.find_all("Ustrd").into_iter().map(|text| text.text.trim().to_string());
+            let mut description: Vec<String> = party.into_iter().chain(remittance).filter(|text| !text.is_empty()).collect();
+            if description.is_empty() {
+                description.extend(entry.text_of("AddtlNtryInf"));
+            }
+
+            transactions.push(Transaction {
+                date,
+                amount_cents: if debit { -cents.abs() } else { cents.abs() },
+                currency: amount.attribute("Ccy"),
+                description: description.join(" "),
+            });
+        }
+    }
+    Ok(transactions)
+}
+
+/// An XML element read with quick-xml, by its name without namespace prefix. `text` is all the
+/// text inside it, that of nested elements included.
+#[derive(Debug, Default)]
+struct XmlElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    text: String,
+    children: Vec<XmlElement>,
+}
+
+impl XmlElement {
+    /// The whole document, under an unnamed root
+    fn parse(xml: &str) -> Result<Self> {
+        use quick_xml::events::Event;
+
+        let mut reader = quick_xml::Reader::from_str(xml);
+        let mut open = vec![Self::default()];
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => open.push(Self::open(&start)?),
+                Event::Empty(start) => {
+                    let element = Self::open(&start)?;
+                    open.last_mut().context("Unbalanced XML")?.children.push(element);
+                }
+                Event::End(_) => {
+                    let element = open.pop().context("Unbalanced XML")?;
+                    let parent = open.last_mut().context("Unbalanced XML")?;
+                    parent.text.push_str(&element.text);
+                    parent.children.push(element);
+                }
+                Event::Text(text) => open.last_mut().context("Unbalanced XML")?.text.push_str(&text.into_inner()),
+                Event::CData(data) => open.last_mut().context("Unbalanced XML")?.text.push_str(&data.into_inner()),
+                Event::GeneralRef(reference) => {
+                    let resolved = match reference.resolve_char_ref()? {
+                        Some(c) => c.to_string(),
+                        None => quick_xml::escape::resolve_predefined_entity(&reference.into_inner()).unwrap_or_default().to_string(),
+                    };
+                    open.last_mut().context("Unbalanced XML")?.text.push_str(&resolved);
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        anyhow::ensure!(open.len() == 1, "Unclosed XML elements");
+        open.pop().context("Unbalanced XML")
+    }
+
+    fn open(start: &quick_xml::events::BytesStart) -> Result<Self> {
+        let mut attributes = Vec::new();
+        for attribute in start.attributes() {
+            let attribute = attribute?;
+            let value = attribute.normalized_value(quick_xml::XmlVersion::default())?.into_owned();
+            attributes.push((attribute.key.local_name().as_ref().to_string(), value));
+        }
+        Ok(Self { name: start.local_name().as_ref().to_string(), attributes, ..Self::default() })
+    }
+
+    /// The first `<name>` element inside this one, at any depth
+    fn find(&self, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find_map(|child| if child.name == name { Some(child) } else { child.find(name) })
+    }
+
+    /// Every `<name>` element inside this one, at any depth (not looking inside the ones found)
+    fn find_all(&self, name: &str) -> Vec<&XmlElement> {
+        self.children
+            .iter()
+            .flat_map(|child| if child.name == name { vec![child] } else { child.find_all(name) })
+            .collect()
+    }
+
+    /// Trimmed text of the first `<name>` element
+    fn text_of(&self, name: &str) -> Option<String> {
+        self.find(name).map(|element| element.text.trim().to_string())
+    }
+
+    fn attribute(&self, name: &str) -> Option<String> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone())
+    }
+}
+
+/// Transactions of a statement's text: lines starting with a date that hold a signed amount
+pub fn parse_text(text: &str) -> Vec<Transaction> {
+    text.lines()
+        .filter_map(|line| {
+            let lower = line.trim().to_lowercase();
+            let (offset, date) = *find_dates(&lower).first()?;
+            if offset > 0 {
+                return None;
+            }
+            let rest = lower.split_once(char::is_whitespace).map(|(_, rest)| rest).unwrap_or_default();
+            let tokens: Vec<&str> = rest.split_whitespace().collect();
+            let index = tokens.iter().position(|token| token.starts_with(['-', '−', '+']) && parse_signed_amount(token).is_some())?;
+            Some(Transaction {
+                date,
+                amount_cents: parse_signed_amount(tokens[index])?,
+                currency: None,
+                description: line.split_whitespace().skip(1).take(index).collect::<Vec<_>>().join(" "),
+            })
+        })
+        .collect()
+}
+
+/// "-49.99", "1.234,56", "(12.50)", "49,99-", "€ -8.00" -> cents
+pub fn parse_signed_amount(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let negative = text.starts_with(['-', '−', '(']) || text.ends_with(['-', '−', ')'])
+        || text.trim_start_matches(|c: char| !c.is_ascii_digit() && !matches!(c, '-' | '−')).starts_with(['-', '−']);
+    let number: String = text.chars().filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',')).collect();
+    if !number.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    // The last separator is the decimal one when one or two digits follow it
+    let (units, decimals) = match number.rfind(['.', ',']) {
+        Some(pos) if (1..=2).contains(&(number.len() - pos - 1)) => (&number[..pos], &number[pos + 1..]),
+        _ => (number.as_str(), ""),
+    };
+    let units: i64 = units.chars().filter(char::is_ascii_digit).collect::<String>().parse().unwrap_or(0);
+    let decimals: i64 = format!("{:0<2}", decimals).parse().ok()?;
+    let cents = units.checked_mul(100)?.checked_add(decimals)?;
+    Some(if negative { -cents } else { cents })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_statements() {
+        let revolut = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance\n\
+            CARD_PAYMENT,Current,2025-03-04 10:12:01,2025-03-05 09:00:00,Hetzner Online,-49.99,0.00,EUR,COMPLETED,900.01\n\
+            TOPUP,Current,2025-03-06 08:00:00,2025-03-06 08:00:00,\"Top-up by *1234, thanks\",200.00,0.00,EUR,COMPLETED,1100.01\n";
+        let transactions = parse_csv(revolut, None).unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].date, NaiveDate::from_ymd_opt(2025, 3, 5).unwrap());
+        assert_eq!((transactions[0].amount_cents, transactions[0].currency.as_deref()), (-4999, Some("EUR")));
+        assert_eq!(transactions[1].description, "Top-up by *1234, thanks");
+
+        let german = "Kontoauszug Girokonto\nBuchungstag;Verwendungszweck;Soll;Haben;Währung\n04.03.2025;AWS EMEA;231,40;;EUR\n07.03.2025;Erstattung;;12,50;EUR\n";
+        let transactions = parse_csv(german, None).unwrap();
+        assert_eq!(transactions.iter().map(|t| t.amount_cents).collect::<Vec<_>>(), vec![-23140, 1250]);
+        assert_eq!(transactions[0].description, "AWS EMEA");
+
+        assert!(parse_csv("Name,Email\nHetzner,billing@hetzner.com\n", None).is_err());
+    }
+
+    #[test]
+    fn test_column_mapping() {
+        let formats = StatementFormats::parse(
+            "[[bank]]\nname = \"Sparkasse\"\nseparator = \";\"\ndate = \"Buchungstag\"\namount = \"Betrag\"\ndescription = [\"Beguenstigter/Zahlungspflichtiger\", \"Verwendungszweck\"]\ncurrency = \"Waehrung\"\ndate_format = \"%d.%m.%y\"\n",
+        )
+        .unwrap();
+        let sparkasse = formats.for_bank("sparkasse").unwrap();
+        let csv = "\"Auftragskonto\";\"Buchungstag\";\"Valutadatum\";\"Verwendungszweck\";\"Beguenstigter/Zahlungspflichtiger\";\"Betrag\";\"Waehrung\"\n\
+            \"DE89370400440532013000\";\"05.03.25\";\"05.03.25\";\"Rechnung R0012345678\";\"Hetzner Online GmbH\";\"-49,99\";\"EUR\"\n";
+        let transactions = parse_csv(csv, Some(sparkasse)).unwrap();
+        assert_eq!(transactions[0].date, NaiveDate::from_ymd_opt(2025, 3, 5).unwrap());
+        assert_eq!(transactions[0].description, "Hetzner Online GmbH Rechnung R0012345678");
+        assert_eq!(transactions[0].amount_cents, -4999);
+
+        assert!(StatementFormats::parse("[[bank]]\nname = \"N26\"\ndate = \"Date\"\n").is_err());
+        assert!(formats.for_bank("N26").is_none());
+    }
+
+    #[test]
+    fn test_parse_camt053() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <Stmt>
+      <Acct><Id><IBAN>DE89370400440532013000</IBAN></Id><Ccy>EUR</Ccy></Acct>
+      <Ntry>
+        <Amt Ccy="EUR">49.99</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2025-03-05</Dt></BookgDt>
+        <NtryDtls><TxDtls>
+          <RltdPties><Cdtr><Nm>Hetzner Online GmbH</Nm></Cdtr></RltdPties>
+          <RmtInf><Ustrd>R0012345678</Ustrd></RmtInf>
+        </TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">12.50</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts><Cd>BOOK</Cd></Sts>
+        <BookgDt><DtTm>2025-03-20T10:00:00</DtTm></BookgDt>
+        <AddtlNtryInf>Refund AT&amp;T</AddtlNtryInf>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">9.00</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>PDNG</Sts>
+        <BookgDt><Dt>2025-03-30</Dt></BookgDt>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>"#;
+        let transactions = parse_camt053(xml).unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0], Transaction {
+            date: NaiveDate::from_ymd_opt(2025, 3, 5).unwrap(),
+            amount_cents: -4999,
+            currency: Some("EUR".to_string()),
+            description: "Hetzner Online GmbH R0012345678".to_string(),
+        });
+        assert_eq!((transactions[1].amount_cents, transactions[1].description.as_str()), (1250, "Refund AT&T"));
+        assert!(parse_camt053("<Document><Other/></Document>").is_err());
+        assert!(is_statement_export("camt.xml", xml.as_bytes()));
+        assert!(!is_statement_export("invoice.xml", b"<Invoice><ID>R1</ID></Invoice>"));
+    }
+
+    #[test]
+    fn test_parse_statement_text() {
+        let text = "Revolut Ltd\nEUR Statement\n05/03/2025 Hetzner Online -49.99 900.01\n06/03/2025 Top-up +200.00 1100.01\nClosing balance EUR 968.61";
+        let transactions = parse_text(text);
+        assert_eq!(transactions.len(), 2);
+        assert_eq!((transactions[0].amount_cents, transactions[0].description.as_str()), (-4999, "Hetzner Online"));
+        assert_eq!(transactions[1].amount_cents, 20000);
+
+        assert_eq!(parse_signed_amount("1.234,56"), Some(123456));
+        assert_eq!(parse_signed_amount("(12.5)"), Some(-1250));
+        assert_eq!(parse_signed_amount("€ -8"), Some(-800));
+        assert_eq!(parse_signed_amount("EUR"), None);
+    }
+}
@@ -1,146 +1,109 @@
 use anyhow::{Context, Result};
 use base64::prelude::*;
-use std::path::PathBuf;
-use super::client::{GmailClient, GMAIL_API_BASE, Message, Attachment, MessagePart};
-
-#[derive(Debug, Clone)]
-pub struct InvoiceAttachment {
-    pub filename: String,
-    pub data: Vec<u8>,
-    #[allow(dead_code)]
-    pub message_id: String,
-}
-
-#[derive(Debug, Clone)]
-pub struct InvoiceAttachmentWithBank {
-    pub attachment: InvoiceAttachment,
-    pub bank_name: Option<String>,
-}
+use chrono::{DateTime, Utc};
+use super::client::{GmailClient, Message, Attachment, MessagePart, read_fixture};
+use crate::mail::{mime, AttachmentRef, MailFolder, MailMessage};
+use crate::process::telemetry;
+
+/// Fetch a full message (headers and MIME structure)
+pub async fn fetch_message(client: &GmailClient, message_id: &str) -> Result<Message> {
+    if let Some(fixtures) = client.fixtures() {
+        return read_fixture(&fixtures.join("messages").join(format!("{}.json", message_id)));
+    }
 
-/// Get message and extract all attachments
-pub async fn get_message_attachments(
-    client: &GmailClient,
-    message_id: &str,
-) -> Result<Vec<InvoiceAttachmentWithBank>> {
-    let url = format!("{}/users/me/messages/{}", GMAIL_API_BASE, message_id);
+    let url = format!("{}/messages/{}", client.user_url(), message_id);
 
-    let response = client.client()
+    let request = client.client()
         .get(&url)
-        .bearer_auth(client.access_token())
-        .send()
+        .bearer_auth(client.access_token());
+    let response = telemetry::send("Gmail", request)
         .await
         .context("Failed to fetch message")?;
 
     if !response.status().is_success() {
         let status = response.status();
         let error_text = response.text().await.unwrap_or_default();
-        anyhow::bail!("Gmail API error ({}): {}", status, error_text);
+        return Err(client.api_error(status, &error_text));
     }
 
     let message: Message = response.json().await
         .context("Failed to parse message")?;
 
-    // Extract sender name and detect bank from headers
-    let sender_name = extract_sender_name(&message);
-    let sender_prefix = sanitize_sender_name(&sender_name);
-    let bank_name = detect_bank_name(&message);
+    Ok(message)
+}
 
-    let mut attachments = Vec::new();
+/// Convert a Gmail API message into the source-agnostic representation
+pub fn to_mail_message(message: Message) -> MailMessage {
+    let header = |name: &str| {
+        message.payload.as_ref()
+            .and_then(|p| p.headers.as_ref())
+            .and_then(|headers| headers.iter().find(|h| h.name.eq_ignore_ascii_case(name)))
+            .map(|h| h.value.clone())
+            .unwrap_or_default()
+    };
 
-    if let Some(payload) = message.payload {
-        find_attachments(&payload, &mut attachments);
+    let mut attachments = Vec::new();
+    if let Some(payload) = &message.payload {
+        find_attachments(payload, &mut attachments);
     }
 
-    // Skip silently if no attachments
-
-    // Download attachment data
-    let mut result = Vec::new();
-    for (filename, attachment_id) in attachments {
-        match download_attachment(client, message_id, &attachment_id).await {
-            Ok(data) => {
-                // Prepend sender name to filename
-                let new_filename = if !sender_prefix.is_empty() {
-                    format!("{}-{}", sender_prefix, filename)
-                } else {
-                    filename.clone()
-                };
-
-                let attachment_with_bank = InvoiceAttachmentWithBank {
-                    attachment: InvoiceAttachment {
-                        filename: new_filename.clone(),
-                        data,
-                        message_id: message_id.to_string(),
-                    },
-                    bank_name: bank_name.clone(),
-                };
-
-                result.push(attachment_with_bank);
-            }
-            Err(_e) => {
-                // Skip error logging
-            }
-        }
+    MailMessage {
+        id: message.id.clone(),
+        from: header("From"),
+        subject: header("Subject"),
+        received_at: message.internal_date.as_deref()
+            .and_then(|ms| ms.parse::<i64>().ok())
+            .and_then(DateTime::<Utc>::from_timestamp_millis),
+        body: message.payload.as_ref().map(body_text).unwrap_or_default(),
+        headers: message.payload.as_ref()
+            .and_then(|p| p.headers.as_ref())
+            .map(|headers| headers.iter().map(|h| (h.name.clone(), h.value.clone())).collect())
+            .unwrap_or_default(),
+        attachments,
+        folder: MailFolder::from_gmail_labels(&message.label_ids),
     }
-
-    Ok(result)
 }
 
-/// Extract sender name from message headers
-fn extract_sender_name(message: &Message) -> String {
-    if let Some(payload) = &message.payload {
-        if let Some(headers) = &payload.headers {
-            for header in headers {
-                if header.name.to_lowercase() == "from" {
-                    // Extract name from "Name <email@example.com>" format
-                    let from = &header.value;
-
-                    // Try to extract the name part before the email
-                    if let Some(name_end) = from.find('<') {
-                        let name = from[..name_end].trim();
-                        if !name.is_empty() {
-                            // Remove quotes if present
-                            return name.trim_matches('"').to_string();
-                        }
-                    }
-
-                    // If no angle bracket, try to extract from email
-                    if let Some(at_pos) = from.find('@') {
-                        return from[..at_pos].to_string();
-                    }
+/// Readable text of a MIME tree: text/plain and text/html parts that aren't attachments.
+/// Of a multipart/alternative only the plain text version is used when there is one.
+fn body_text(part: &MessagePart) -> String {
+    if part.filename.as_deref().is_some_and(|name| !name.is_empty()) {
+        return String::new();
+    }
 
-                    return from.clone();
-                }
+    let mime_type = part.mime_type.as_deref().unwrap_or_default().to_ascii_lowercase();
+    if let Some(parts) = &part.parts {
+        let texts: Vec<String> = parts.iter().map(body_text).filter(|text| !text.trim().is_empty()).collect();
+        if mime_type == "multipart/alternative" {
+            let plain = parts.iter().position(|p| p.mime_type.as_deref().is_some_and(|m| m.eq_ignore_ascii_case("text/plain")));
+            if let Some(text) = plain.map(|index| body_text(&parts[index])).filter(|text| !text.trim().is_empty()) {
+                return text;
             }
+            return texts.into_iter().next().unwrap_or_default();
         }
+        return texts.join("\n");
     }
-    String::new()
-}
 
-/// Sanitize sender name for use in filename
-/// "LangFuse GmbH" -> "langfuse-gmbh"
-fn sanitize_sender_name(name: &str) -> String {
-    name.to_lowercase()
-        .chars()
-        .map(|c| {
-            if c.is_alphanumeric() {
-                c
-            } else if c.is_whitespace() {
-                '-'
-            } else {
-                // Remove special characters
-                '\0'
-            }
-        })
-        .filter(|&c| c != '\0')
-        .collect::<String>()
-        .split('-')
-        .filter(|s| !s.is_empty())
-        .collect::<Vec<_>>()
-        .join("-")
+    if mime_type != "text/plain" && mime_type != "text/html" {
+        return String::new();
+    }
+    let Some(data) = part.body.as_ref().and_then(|b| b.data.as_deref()) else {
+        return String::new();
+    };
+    let Ok(bytes) = BASE64_URL_SAFE_NO_PAD.decode(data.trim_end_matches('=')) else {
+        return String::new();
+    };
+
+    let content_type = part.headers.as_ref()
+        .and_then(|headers| headers.iter().find(|h| h.name.eq_ignore_ascii_case("Content-Type")))
+        .map(|h| h.value.as_str())
+        .unwrap_or_default();
+    // The API has already undone the Content-Transfer-Encoding; only the charset remains
+    mime::part_text(&mime_type, content_type, None, &bytes)
 }
 
 /// Recursively find all attachments in message parts
-fn find_attachments(part: &MessagePart, attachments: &mut Vec<(String, String)>) {
+fn find_attachments(part: &MessagePart, attachments: &mut Vec<AttachmentRef>) {
     // Check if this part is an attachment
     // An attachment has a filename and an attachment_id
     if let Some(filename) = &part.filename {
@@ -148,7 +111,12 @@ fn find_attachments(part: &MessagePart, attachments: &mut Vec<(String, String)>)
             if let Some(body) = &part.body {
                 if let Some(attachment_id) = &body.attachment_id {
                     // This is an attachment - add it regardless of mime type
-                    attachments.push((filename.clone(), attachment_id.clone()));
+                    attachments.push(AttachmentRef {
+                        filename: filename.clone(),
+                        attachment_id: attachment_id.clone(),
+                        mime_type: part.mime_type.clone(),
+                        size: body.size.map(u64::from),
+                    });
                 }
             }
         }
@@ -163,27 +131,32 @@ fn find_attachments(part: &MessagePart, attachments: &mut Vec<(String, String)>)
 }
 
 /// Download attachment data
-async fn download_attachment(
+pub async fn download_attachment(
     client: &GmailClient,
     message_id: &str,
     attachment_id: &str,
 ) -> Result<Vec<u8>> {
+    // Mock mode serves the sample file whose name (without extension) is the attachment id
+    if let Some(fixtures) = client.fixtures() {
+        return read_fixture_attachment(&fixtures.join("attachments"), attachment_id);
+    }
+
     let url = format!(
-        "{}/users/me/messages/{}/attachments/{}",
-        GMAIL_API_BASE, message_id, attachment_id
+        "{}/messages/{}/attachments/{}",
+        client.user_url(), message_id, attachment_id
     );
 
-    let response = client.client()
+    let request = client.client()
         .get(&url)
-        .bearer_auth(client.access_token())
-        .send()
+        .bearer_auth(client.access_token());
+    let response = telemetry::send("Gmail", request)
         .await
         .context("Failed to download attachment")?;
 
     if !response.status().is_success() {
         let status = response.status();
         let error_text = response.text().await.unwrap_or_default();
-        anyhow::bail!("Gmail API error ({}): {}", status, error_text);
+        return Err(client.api_error(status, &error_text));
     }
 
     let attachment: Attachment = response.json().await
@@ -204,133 +177,14 @@ async fn download_attachment(
     Ok(data)
 }
 
-/// Save attachment to temp directory
-pub fn save_attachment_to_temp(attachment: &InvoiceAttachment) -> Result<PathBuf> {
-    let temp_dir = std::env::temp_dir().join("invoice-agent");
-    std::fs::create_dir_all(&temp_dir)
-        .context("Failed to create temp directory")?;
-
-    let file_path = temp_dir.join(&attachment.filename);
-    std::fs::write(&file_path, &attachment.data)
-        .context("Failed to write attachment to temp file")?;
-
-    Ok(file_path)
-}
-
-/// Detect bank name from message headers and content
-fn detect_bank_name(message: &Message) -> Option<String> {
-    let search_text = extract_search_text(message);
-    detect_bank_from_text(&search_text).map(|name| {
-        // Convert to title case for consistent folder naming
-        name.split_whitespace()
-            .map(|word| {
-                if word.len() > 0 {
-                    format!("{}{}", word.chars().next().unwrap().to_uppercase(), word[1..].to_lowercase())
-                } else {
-                    word.to_string()
-                }
-            })
-            .collect::<Vec<_>>()
-            .join(" ")
-    })
-}
-
-/// Extract searchable text from message (headers + body)
-fn extract_search_text(message: &Message) -> String {
-    let mut text = String::new();
-    
-    // Extract from headers
-    if let Some(payload) = &message.payload {
-        if let Some(headers) = &payload.headers {
-            for header in headers {
-                if header.name.to_lowercase() == "from" || header.name.to_lowercase() == "subject" {
-                    text.push_str(&header.value);
-                    text.push(' ');
-                }
-            }
-        }
-        
-        // Extract from body if available
-        if let Some(body) = &payload.body {
-            if let Some(data) = &body.data {
-                // Try to decode and extract text from body
-                // This is a simplified version - in production you'd want proper MIME parsing
-                text.push_str(data);
-            }
-        }
-    }
-    
-    text.to_lowercase()
-}
-
-/// Detect bank name from text using predefined patterns
-fn detect_bank_from_text(text: &str) -> Option<String> {
-    // Common European digital and physical banks (including those without 'bank' in name)
-    let bank_patterns = vec![
-        // Digital banks
-        "wise", "revolut", "nubank", "bunq", "monzo", "starling", "chime", "venmo",
-        "paypal", "wise", "transferwise", "wise.com", "revolut.com", "nubank.com.br",
-        
-        // Traditional banks with 'bank' in name
-        "santander", "bbva", "caixabank", "ing", "deutsche bank", "commerzbank",
-        "hsbc", "barclays", "lloyds", "rbs", "natwest", "barclays", "standard chartered",
-        "bnp paribas", "societe generale", "credit agricole", "dexia", "fortis",
-        "kbc", "rabobank", "abn amro", "ing", "asn", "triodos", "moneco",
-        
-        // Spanish banks
-        "banco santander", "bbva", "caixa bank", "la caixa", "bankinter", "sabadell",
-        "popular", "galicia", "santanderrio", "macro", "hipotecario", "provincia",
-        
-        // Portuguese banks
-        "bcp", "bpi", "caixa geral de depósitos", "millennium bcp", "banco espírito santo",
-        
-        // Italian banks
-        "intesa sanpaolo", "unicredit", "banco popolare", "monte dei paschi", "mediolanum",
-        
-        // French banks
-        "societe generale", "bnp paribas", "credit agricole", "lcl", "bpce", "caisse d'epargne",
-        
-        // German banks
-        "deutsche bank", "commerzbank", "hypovereinsbank", "sparkasse", "volksbank",
-        
-        // Dutch banks
-        "ing", "rabobank", "abn amro", "asn bank", "triodos bank", "moneco bank",
-        
-        // Polish banks
-        "pkobp", "ing", "millennium", "bnp paribas", "santander", "bank millennium",
-        
-        // Czech banks
-        "csob", "kb", "unicredit", "raiffeisen", "moneta", "fio",
-        
-        // Austrian banks
-        "erste bank", "raiffeisen", "bank austria", "volksbank", "sparkasse",
-        
-        // Swiss banks
-        "ubs", "credit suisse", "zkb", "ubs", "postfinance", "raiffeisen",
-        
-        // Nordic banks
-        "nordea", "dnb", "handelsbanken", "seb", "swedbank", "sampo",
-        
-        // Other European digital services
-        "wise", "transferwise", "revolut", "n26", "bunq", "monzo", "starling",
-        "tidal", "october", "bunq", "mollie", "adyen", "stripe", "paypal",
-        
-        // Brokerages and trading platforms
-        "interactive brokers", "ibkr", "charles schwab", "etrade", "td ameritrade", " fidelity",
-        "robinhood", "webull", "coinbase", "binance", "kraken", "coinbase pro", "binance us",
-        
-        // Banks with 'banco' in name (Spanish/Portuguese)
-        "banco", "banco santander", "banco do brasil", "banco itaú", "banco bradesco",
-        
-        // Generic bank indicators
-        "bank", "banco", "financial", "fintech", "fiscal", "tributary",
-    ];
-    
-    for pattern in bank_patterns {
-        if text.contains(&pattern.to_lowercase()) {
-            return Some(pattern.to_string());
+/// Find and read a sample attachment file in mock mode
+fn read_fixture_attachment(dir: &std::path::Path, attachment_id: &str) -> Result<Vec<u8>> {
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read fixture directory {}", dir.display()))? {
+        let path = entry?.path();
+        if path.file_stem().and_then(|s| s.to_str()) == Some(attachment_id) {
+            return std::fs::read(&path)
+                .with_context(|| format!("Failed to read fixture {}", path.display()));
         }
     }
-    
-    None
+    anyhow::bail!("No fixture attachment found for id {}", attachment_id)
 }
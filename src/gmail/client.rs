@@ -1,5 +1,9 @@
+use anyhow::{Context, Result};
 use reqwest::Client;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use crate::process::outcome::ApiError;
 
 pub const GMAIL_API_BASE: &str = "https://gmail.googleapis.com/gmail/v1";
 
@@ -7,6 +11,8 @@ pub const GMAIL_API_BASE: &str = "https://gmail.googleapis.com/gmail/v1";
 pub struct GmailClient {
     client: Client,
     access_token: String,
+    fixtures: Option<PathBuf>,
+    user_id: String,
 }
 
 impl GmailClient {
@@ -14,9 +20,32 @@ impl GmailClient {
         Self {
             client: Client::new(),
             access_token,
+            fixtures: None,
+            user_id: "me".to_string(),
         }
     }
 
+    /// Read another mailbox than the signed-in account's (GMAIL_USER)
+    pub fn with_user(mut self, user_id: &str) -> Self {
+        self.user_id = user_id.to_string();
+        self
+    }
+
+    /// Create a client that replays recorded responses from `<fixtures_dir>/gmail`
+    pub fn mock(fixtures_dir: &Path) -> Self {
+        Self {
+            client: Client::new(),
+            access_token: "mock".to_string(),
+            fixtures: Some(fixtures_dir.join("gmail")),
+            user_id: "me".to_string(),
+        }
+    }
+
+    /// Fixture directory when running in mock mode
+    pub fn fixtures(&self) -> Option<&Path> {
+        self.fixtures.as_deref()
+    }
+
     pub fn client(&self) -> &Client {
         &self.client
     }
@@ -24,11 +53,32 @@ impl GmailClient {
     pub fn access_token(&self) -> &str {
         &self.access_token
     }
+
+    /// API URL of the mailbox, `.../users/me` unless another user was selected
+    pub fn user_url(&self) -> String {
+        format!("{}/users/{}", GMAIL_API_BASE, self.user_id)
+    }
+
+    /// Error for a failed API call. A 403 on another user's mailbox usually means the account
+    /// was given access through Gmail delegation, which the API does not honour.
+    pub fn api_error(&self, status: reqwest::StatusCode, error_text: &str) -> anyhow::Error {
+        if status == reqwest::StatusCode::FORBIDDEN && self.user_id != "me" {
+            let hint = format!(
+                "{}\nThe signed-in account can't open {} through the API (mailbox delegation only works in the Gmail web app). \
+                 Use GOOGLE_SERVICE_ACCOUNT_KEY with domain-wide delegation, or a separate profile signed in as {}",
+                error_text, self.user_id, self.user_id
+            );
+            return ApiError::new("Gmail", status, hint).into();
+        }
+        ApiError::new("Gmail", status, error_text).into()
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct MessageListResponse {
     pub messages: Option<Vec<MessageInfo>>,
+    #[serde(rename = "nextPageToken")]
+    pub next_page_token: Option<String>,
     #[serde(rename = "resultSizeEstimate")]
     pub result_size_estimate: Option<u32>,
 }
@@ -43,6 +93,10 @@ pub struct MessageInfo {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Message {
     pub id: String,
+    #[serde(rename = "internalDate")]
+    pub internal_date: Option<String>,
+    #[serde(rename = "labelIds", default)]
+    pub label_ids: Vec<String>,
     pub payload: Option<MessagePart>,
 }
 
@@ -75,3 +129,11 @@ pub struct Attachment {
     pub data: String,
     pub size: u32,
 }
+
+/// Read a recorded JSON response from the fixture directory
+pub fn read_fixture<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read fixture {}", path.display()))?;
+    serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse fixture {}", path.display()))
+}
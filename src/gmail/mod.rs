@@ -1,3 +1,5 @@
 pub mod client;
 pub mod search;
 pub mod attachment;
+pub mod send;
+pub mod source;
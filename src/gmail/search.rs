@@ -1,87 +1,217 @@
 use anyhow::{Context, Result};
-use chrono::{Datelike, NaiveDate};
-use super::client::{GmailClient, GMAIL_API_BASE, MessageListResponse};
+use chrono::{Datelike, Days, NaiveDate};
+use crate::mail::search::{Exclusions, IngestQueue};
+use crate::process::telemetry;
+use crate::mail::MailMessage;
+use super::attachment;
+use super::client::{GmailClient, Message, MessageListResponse, read_fixture};
 
-/// Search Gmail for invoice emails within a date range
-pub async fn search_invoices(
+/// Largest page Gmail returns for a message list
+const MAX_PAGE_SIZE: usize = 500;
+
+/// Search Gmail for messages with attachments matching one keyword within a date range
+pub async fn search_keyword(
     client: &GmailClient,
     start_date: NaiveDate,
     end_date: NaiveDate,
-    keywords: &[String],
+    keyword: &str,
+    exclusions: &Exclusions,
+    max_results: Option<usize>,
 ) -> Result<Vec<String>> {
-    // Silently search - detailed progress sent via UI
-    let keywords_to_search = if keywords.is_empty() {
-        vec!["invoice".to_string(), "invoices".to_string(), "fatura".to_string(), "faturas".to_string(), "statement".to_string(), "bank".to_string()]
-    } else {
-        keywords.to_vec()
-    };
+    let mut query = build_search_query_single(start_date, end_date, keyword);
+    query.push_str(&build_exclusions(exclusions));
+    query.push_str(&build_folder_scope(exclusions));
+    search_with_query(client, &query, max_results).await
+}
+
+/// Gmail's estimate of how many messages `search_keyword` would find, from a single
+/// one-result request
+pub async fn estimate_keyword(
+    client: &GmailClient,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    keyword: &str,
+    exclusions: &Exclusions,
+) -> Result<u64> {
+    let mut query = build_search_query_single(start_date, end_date, keyword);
+    query.push_str(&build_exclusions(exclusions));
+    query.push_str(&build_folder_scope(exclusions));
+    let result = list_page(client, &query, 1, None).await?;
+    Ok(u64::from(result.result_size_estimate.unwrap_or_default()))
+}
 
-    let mut all_message_ids = std::collections::HashSet::new();
+/// Search Gmail for messages with attachments in the ingestion queue since a date
+pub async fn search_queue(client: &GmailClient, queue: &IngestQueue, since: NaiveDate) -> Result<Vec<String>> {
+    search_with_query(client, &build_queue_query(queue, since), None).await
+}
 
-    // Search for each keyword separately to maximize results
-    for keyword in &keywords_to_search {
-        let query = build_search_query_single(start_date, end_date, keyword);
+/// Perform a single search query, following result pages until `max_results` ids are found
+async fn search_with_query(client: &GmailClient, query: &str, max_results: Option<usize>) -> Result<Vec<String>> {
+    let page_size = max_results.unwrap_or(MAX_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let mut message_ids = Vec::new();
+    let mut page_token: Option<String> = None;
 
-        match search_with_query(client, &query).await {
-            Ok(message_ids) => {
-                for id in message_ids {
-                    all_message_ids.insert(id);
-                }
-            }
-            Err(_e) => {
-                // Silently skip failed searches
-            }
+    loop {
+        let result = list_page(client, query, page_size, page_token.as_deref()).await?;
+        message_ids.extend(result.messages.unwrap_or_default().into_iter().map(|m| m.id));
+
+        if let Some(max_results) = max_results && message_ids.len() >= max_results {
+            message_ids.truncate(max_results);
+            break;
+        }
+        match result.next_page_token {
+            Some(token) => page_token = Some(token),
+            None => break,
         }
     }
 
-    let final_results: Vec<String> = all_message_ids.into_iter().collect();
-    Ok(final_results)
+    Ok(message_ids)
 }
 
-/// Perform a single search query
-async fn search_with_query(client: &GmailClient, query: &str) -> Result<Vec<String>> {
-    let url = format!("{}/users/me/messages", GMAIL_API_BASE);
+/// Fetch one page of a message list
+async fn list_page(client: &GmailClient, query: &str, page_size: usize, page_token: Option<&str>) -> Result<MessageListResponse> {
+    // Mock mode replays the recorded messages that answer the query
+    if let Some(fixtures) = client.fixtures() {
+        let mut result: MessageListResponse = read_fixture(&fixtures.join("messages.json"))?;
+        if let Some(messages) = &mut result.messages {
+            let mut matching = Vec::new();
+            for entry in messages.drain(..) {
+                let message: Message = read_fixture(&fixtures.join("messages").join(format!("{}.json", entry.id)))?;
+                if fixture_matches(&attachment::to_mail_message(message), query) {
+                    matching.push(entry);
+                }
+            }
+            *messages = matching;
+            result.result_size_estimate = Some(messages.len() as u32);
+            messages.truncate(page_size);
+        }
+        result.next_page_token = None;
+        return Ok(result);
+    }
 
-    let response = client.client()
+    let url = format!("{}/messages", client.user_url());
+    let mut request = client.client()
         .get(&url)
         .bearer_auth(client.access_token())
-        .query(&[("q", query)])
-        .send()
-        .await
-        .context("Failed to search Gmail")?;
+        .query(&[("q", query), ("maxResults", &page_size.to_string())]);
+    if let Some(token) = page_token {
+        request = request.query(&[("pageToken", token)]);
+    }
+
+    let response = telemetry::send("Gmail", request).await.context("Failed to search Gmail")?;
 
     if !response.status().is_success() {
         let status = response.status();
         let error_text = response.text().await.unwrap_or_default();
-        anyhow::bail!("Gmail API error ({}): {}", status, error_text);
+        return Err(client.api_error(status, &error_text));
     }
 
-    let result: MessageListResponse = response.json().await
-        .context("Failed to parse Gmail search response")?;
+    response.json().await.context("Failed to parse Gmail search response")
+}
 
-    let message_ids: Vec<String> = result.messages
-        .unwrap_or_default()
-        .into_iter()
-        .map(|m| m.id)
-        .collect();
+/// Whether a recorded message answers a Gmail query. Words and `from:`/`subject:`/`filename:`
+/// terms must match and `-` ones must not; `after:` and `before:` bound the day it arrived.
+/// Other operators (`has:`, `label:`, `in:`, `category:`, ...) aren't recorded and pass.
+fn fixture_matches(message: &MailMessage, query: &str) -> bool {
+    let received = message.received_at.map(|at| at.date_naive());
+    let contains = |text: &str, term: &str| text.to_lowercase().contains(term);
 
-    Ok(message_ids)
+    query_terms(query).iter().all(|term| {
+        let (negated, term) = match term.strip_prefix('-') {
+            Some(term) => (true, term),
+            None => (false, term.as_str()),
+        };
+        let matched = match term.split_once(':') {
+            Some(("after", date)) => query_date(date).zip(received).map(|(date, received)| received >= date),
+            Some(("before", date)) => query_date(date).zip(received).map(|(date, received)| received < date),
+            Some(("from", value)) => Some(contains(&message.from, value)),
+            Some(("subject", value)) => Some(contains(&message.subject, value)),
+            Some(("filename", value)) => Some(message.attachments.iter().any(|a| contains(&a.filename, value))),
+            Some(_) => None,
+            None => Some(message.mentions(term)),
+        };
+        matched.is_none_or(|matched| matched != negated)
+    })
+}
+
+/// Split a query into lowercase terms, keeping quoted phrases together (quotes dropped)
+fn query_terms(query: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !term.is_empty() {
+                    terms.push(std::mem::take(&mut term));
+                }
+            }
+            c => term.extend(c.to_lowercase()),
+        }
+    }
+    if !term.is_empty() {
+        terms.push(term);
+    }
+    terms
+}
+
+/// Parse a query date such as `2024/9/1`
+fn query_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y/%m/%d").ok()
 }
 
-/// Build Gmail search query for a single keyword
+/// Build Gmail search query for a single keyword over an inclusive date range
+/// (Gmail's `before:` is exclusive, so it points at the day after `end_date`)
 fn build_search_query_single(start_date: NaiveDate, end_date: NaiveDate, keyword: &str) -> String {
+    let before = end_date + Days::new(1);
     format!(
         "{} has:attachment after:{}/{}/{} before:{}/{}/{}",
         keyword,
         start_date.year(),
         start_date.month(),
         start_date.day(),
-        end_date.year(),
-        end_date.month(),
-        end_date.day()
+        before.year(),
+        before.month(),
+        before.day()
     )
 }
 
+/// Build Gmail search query for the ingestion queue: `label:"invoice-inbox" has:attachment after:2024/9/1`.
+/// Aliases match on `deliveredto:`, which keeps the plus part of the address.
+fn build_queue_query(queue: &IngestQueue, since: NaiveDate) -> String {
+    let filter = match queue {
+        IngestQueue::Label(label) => format!("label:\"{}\"", label.trim_matches('"')),
+        IngestQueue::Address(address) => format!("deliveredto:{}", address),
+    };
+    format!("{} has:attachment after:{}/{}/{}", filter, since.year(), since.month(), since.day())
+}
+
+/// Negative terms appended to every query: ` -proforma -"pro forma" -category:promotions`
+fn build_exclusions(exclusions: &Exclusions) -> String {
+    let keywords = exclusions.keywords.iter().map(|keyword| {
+        if keyword.contains(char::is_whitespace) {
+            format!(" -\"{}\"", keyword.trim_matches('"'))
+        } else {
+            format!(" -{}", keyword)
+        }
+    });
+    let categories = exclusions.categories.iter().map(|category| format!(" -category:{}", category));
+    keywords.chain(categories).collect()
+}
+
+/// Folders the query covers. Gmail leaves out spam and trash unless told `in:anywhere`; drafts
+/// and chats are never wanted.
+fn build_folder_scope(exclusions: &Exclusions) -> String {
+    let scope = match (exclusions.include_spam, exclusions.include_trash) {
+        (false, false) => "",
+        (true, false) => " in:anywhere -in:trash",
+        (false, true) => " in:anywhere -in:spam",
+        (true, true) => " in:anywhere",
+    };
+    format!("{} -in:chats -in:drafts", scope)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -97,7 +227,54 @@ mod tests {
         assert!(query.contains("invoice"));
         assert!(query.contains("has:attachment"));
         assert!(query.contains("after:2024/9/1"));
-        assert!(query.contains("before:2024/10/12"));
+        assert!(query.contains("before:2024/10/13")); // exclusive, so the end date is included
         assert!(!query.contains("OR")); // Should be single keyword only
     }
+
+    #[test]
+    fn test_build_queue_query() {
+        let since = NaiveDate::from_ymd_opt(2024, 9, 1).unwrap();
+
+        let query = build_queue_query(&IngestQueue::parse("Invoice Inbox"), since);
+        assert_eq!(query, "label:\"Invoice Inbox\" has:attachment after:2024/9/1");
+
+        let query = build_queue_query(&IngestQueue::parse("Invoices+Inbox@example.com"), since);
+        assert_eq!(query, "deliveredto:invoices+inbox@example.com has:attachment after:2024/9/1");
+    }
+
+    #[test]
+    fn test_build_exclusions() {
+        let exclusions = Exclusions {
+            keywords: vec!["proforma".to_string(), "pro forma".to_string()],
+            categories: vec!["promotions".to_string(), "social".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(build_exclusions(&exclusions), " -proforma -\"pro forma\" -category:promotions -category:social");
+        assert_eq!(build_exclusions(&Exclusions::default()), "");
+
+        assert_eq!(build_folder_scope(&Exclusions::default()), " -in:chats -in:drafts");
+        let spam = Exclusions { include_spam: true, ..Default::default() };
+        assert_eq!(build_folder_scope(&spam), " in:anywhere -in:trash -in:chats -in:drafts");
+    }
+
+    #[tokio::test]
+    async fn test_mock_search_filters_fixtures_by_query() {
+        let client = GmailClient::mock(&std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures"));
+        let march = (NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(), NaiveDate::from_ymd_opt(2025, 3, 31).unwrap());
+        let search = |keyword: &'static str, exclusions: Exclusions, (start, end): (NaiveDate, NaiveDate)| {
+            let client = &client;
+            async move { search_keyword(client, start, end, keyword, &exclusions, None).await.unwrap() }
+        };
+
+        assert_eq!(search("invoice", Exclusions::default(), march).await, vec!["18e0a1f2c3d4e5f6", "18e0b7c8d9e0f1a2"]);
+        assert_eq!(search("statement", Exclusions::default(), march).await, vec!["18e0c3d4e5f6a7b8"]);
+
+        let hetzner = Exclusions { keywords: vec!["hetzner".to_string()], ..Default::default() };
+        assert_eq!(search("invoice", hetzner, march).await, vec!["18e0a1f2c3d4e5f6"]);
+
+        // `before:` is exclusive and the Hetzner invoice arrived on 7 March
+        let early_march = (march.0, NaiveDate::from_ymd_opt(2025, 3, 6).unwrap());
+        assert_eq!(search("invoice", Exclusions::default(), early_march).await, vec!["18e0a1f2c3d4e5f6"]);
+    }
 }
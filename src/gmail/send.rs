@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use base64::prelude::*;
+use super::client::{GmailClient, GMAIL_API_BASE};
+use crate::auth;
+use crate::config::env::Config;
+use crate::process::outcome::{ApiError, FailureKind};
+use crate::process::telemetry;
+
+/// Build an RFC 2822 message with only a plain text body
+pub fn build_text_message(to: &str, subject: &str, body: &str) -> String {
+    let body = body.replace("\r\n", "\n").replace('\n', "\r\n");
+    format!(
+        "To: {to}\r\n\
+         Subject: {subject}\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: text/plain; charset=\"UTF-8\"\r\n\
+         Content-Transfer-Encoding: 8bit\r\n\
+         \r\n\
+         {body}\r\n"
+    )
+}
+
+/// Build an RFC 2822 message with a plain text body and a single attachment
+pub fn build_mime_message(
+    to: &str,
+    subject: &str,
+    body: &str,
+    attachment_name: &str,
+    attachment_mime_type: &str,
+    attachment: &[u8],
+) -> String {
+    let boundary = format!("invoice-agent-{:016x}", rand::random::<u64>());
+
+    // Wrap base64 at 76 characters as required for MIME bodies
+    let encoded = BASE64_STANDARD.encode(attachment);
+    let wrapped = encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|line| String::from_utf8_lossy(line).to_string())
+        .collect::<Vec<_>>()
+        .join("\r\n");
+
+    format!(
+        "To: {to}\r\n\
+         Subject: {subject}\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/mixed; boundary=\"{boundary}\"\r\n\
+         \r\n\
+         --{boundary}\r\n\
+         Content-Type: text/plain; charset=\"UTF-8\"\r\n\
+         \r\n\
+         {body}\r\n\
+         --{boundary}\r\n\
+         Content-Type: {attachment_mime_type}; name=\"{attachment_name}\"\r\n\
+         Content-Disposition: attachment; filename=\"{attachment_name}\"\r\n\
+         Content-Transfer-Encoding: base64\r\n\
+         \r\n\
+         {wrapped}\r\n\
+         --{boundary}--\r\n"
+    )
+}
+
+/// Send a raw message with the profile's Gmail send permission (asked for once, stored apart
+/// from the read-only token), or through the ProtonMail Bridge when that is the mail source.
+/// Mock mode writes it to `<mock drive dir>/outbox/<outbox_name>` instead.
+pub async fn send_from_profile(config: &Config, raw: &str, outbox_name: &str) -> Result<()> {
+    if config.mock_mode {
+        let outbox = config.mock_drive_dir.join("outbox");
+        std::fs::create_dir_all(&outbox).context("Failed to create mock outbox")?;
+        std::fs::write(outbox.join(outbox_name), raw).context("Failed to write mock email")?;
+        return Ok(());
+    }
+
+    if let Some(bridge) = &config.proton_bridge {
+        return crate::mail::proton::send_message(bridge, raw).await;
+    }
+
+    let send_token = auth::gmail_auth::get_gmail_send_token(
+        config.gmail_client_id.clone(),
+        config.gmail_client_secret.clone(),
+        config.gmail_account.as_deref(),
+        config.profile.as_deref(),
+        config.paste_code,
+    )
+    .await
+    .context(FailureKind::Auth)?;
+    send_message(&GmailClient::new(send_token), raw).await
+}
+
+/// Send a raw RFC 2822 message from the authenticated account
+pub async fn send_message(client: &GmailClient, raw: &str) -> Result<()> {
+    let url = format!("{}/users/me/messages/send", GMAIL_API_BASE);
+
+    let request = client.client()
+        .post(&url)
+        .bearer_auth(client.access_token())
+        .json(&serde_json::json!({ "raw": BASE64_URL_SAFE_NO_PAD.encode(raw.as_bytes()) }));
+    let response = telemetry::send("Gmail", request)
+        .await
+        .context("Failed to send message")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(ApiError::new("Gmail", status, error_text).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_mime_message_with_attachment() {
+        let raw = build_mime_message(
+            "accountant@example.com",
+            "Invoices March 2025",
+            "Please find attached.",
+            "invoices-2025-03.zip",
+            "application/zip",
+            b"PK\x03\x04",
+        );
+
+        assert!(raw.starts_with("To: accountant@example.com\r\n"));
+        assert!(raw.contains("Content-Disposition: attachment; filename=\"invoices-2025-03.zip\""));
+        assert!(raw.contains(&BASE64_STANDARD.encode(b"PK\x03\x04")));
+        assert!(raw.trim_end().ends_with("--"));
+    }
+
+    #[test]
+    fn test_build_text_message() {
+        let raw = build_text_message("me@example.com", "Weekly invoice digest", "Archived: 3\n  hetzner");
+        assert!(raw.contains("Subject: Weekly invoice digest\r\n"));
+        assert!(raw.ends_with("\r\n\r\nArchived: 3\r\n  hetzner\r\n"));
+    }
+}
@@ -0,0 +1,42 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use super::client::GmailClient;
+use super::{attachment, search};
+use crate::mail::search::{Exclusions, IngestQueue};
+use crate::mail::{MailMessage, MailSource};
+
+#[async_trait]
+impl MailSource for GmailClient {
+    fn name(&self) -> &'static str {
+        "Gmail"
+    }
+
+    async fn search_keyword(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        keyword: &str,
+        exclusions: &Exclusions,
+        max_results: Option<usize>,
+    ) -> Result<Vec<String>> {
+        search::search_keyword(self, start_date, end_date, keyword, exclusions, max_results).await
+    }
+
+    async fn estimate_keyword(&self, start_date: NaiveDate, end_date: NaiveDate, keyword: &str, exclusions: &Exclusions) -> Result<Option<u64>> {
+        search::estimate_keyword(self, start_date, end_date, keyword, exclusions).await.map(Some)
+    }
+
+    async fn search_queue(&self, queue: &IngestQueue, since: NaiveDate) -> Result<Vec<String>> {
+        search::search_queue(self, queue, since).await
+    }
+
+    async fn fetch_message(&self, message_id: &str) -> Result<MailMessage> {
+        let message = attachment::fetch_message(self, message_id).await?;
+        Ok(attachment::to_mail_message(message))
+    }
+
+    async fn download_attachment(&self, message_id: &str, attachment_id: &str) -> Result<Vec<u8>> {
+        attachment::download_attachment(self, message_id, attachment_id).await
+    }
+}
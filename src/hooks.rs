@@ -0,0 +1,256 @@
+use crate::config::env::Config;
+use crate::drive::client::UploadedFile;
+use crate::notify::{self, Notification};
+use crate::process::budget::BudgetAlert;
+use crate::process::digest::Digest;
+use crate::process::outcome::RunSummary;
+use crate::process::payables::Payables;
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+/// A stuck hook is killed after this long so it can't hang the run
+const HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Run ON_RUN_SUCCESS or ON_RUN_FAILURE once a pipeline run has finished, and notify the sinks
+/// subscribed to `run_finished` or `run_failed`. A run with failed files counts as a failure.
+pub async fn run_finished(
+    config: &Config,
+    result: &Result<RunSummary>,
+    tx: Option<&mpsc::UnboundedSender<String>>,
+) {
+    notify::send(config, &Notification::run(config, result), tx).await;
+
+    let succeeded = matches!(result, Ok(summary) if summary.failed == 0);
+    let (name, command) = if succeeded {
+        ("ON_RUN_SUCCESS", &config.on_run_success)
+    } else {
+        ("ON_RUN_FAILURE", &config.on_run_failure)
+    };
+    let Some(command) = command else {
+        return;
+    };
+
+    let empty = RunSummary::default();
+    let summary = result.as_ref().unwrap_or(&empty);
+    let error = result.as_ref().err().map(|e| format!("{:#}", e)).unwrap_or_default();
+
+    let payload = json!({
+        "event": if succeeded { "run_success" } else { "run_failure" },
+        "profile": config.profile,
+        "processed": summary.processed,
+        "uploaded": summary.uploaded,
+        "skipped": summary.skipped,
+        "failed": summary.failed,
+        "quarantined": summary.quarantined,
+        "month": summary.billing_month,
+        "folder": summary.folder,
+        "folder_link": summary.folder_link,
+        "budget_alerts": summary.budget_alerts,
+        "error": error,
+    });
+
+    if let Err(e) = run_hook(command, &payload).await {
+        report(tx, format!("⚠ {} hook failed: {:#}", name, e));
+    }
+}
+
+/// Run ON_FILE_UPLOADED for a file that was just uploaded (skipped duplicates don't trigger it)
+pub async fn file_uploaded(
+    config: &Config,
+    path: &Path,
+    uploaded: &UploadedFile,
+    folder: &str,
+    institution: &str,
+    tx: Option<&mpsc::UnboundedSender<String>>,
+) {
+    let Some(command) = &config.on_file_uploaded else {
+        return;
+    };
+
+    let payload = json!({
+        "event": "file_uploaded",
+        "profile": config.profile,
+        "file_name": uploaded.name,
+        "file_path": path.to_string_lossy(),
+        "drive_file_id": uploaded.id,
+        "folder": folder,
+        "institution": institution,
+    });
+
+    if let Err(e) = run_hook(command, &payload).await {
+        report(tx, format!("   ⚠ ON_FILE_UPLOADED hook failed for {}: {:#}", uploaded.name, e));
+    }
+}
+
+/// Run ON_BUDGET_ALERT for a budget a run pushed over its monthly limit
+pub async fn budget_exceeded(config: &Config, alert: &BudgetAlert, tx: Option<&mpsc::UnboundedSender<String>>) {
+    let Some(command) = &config.on_budget_alert else {
+        return;
+    };
+
+    let payload = json!({
+        "event": "budget_exceeded",
+        "profile": config.profile,
+        "budget": alert.budget,
+        "month": alert.month.format("%Y-%m").to_string(),
+        "currency": alert.currency,
+        "spent_cents": alert.spent_cents,
+        "limit_cents": alert.limit_cents,
+        "message": alert.to_string(),
+    });
+
+    if let Err(e) = run_hook(command, &payload).await {
+        report(tx, format!("⚠ ON_BUDGET_ALERT hook failed for {}: {:#}", alert.budget, e));
+    }
+}
+
+/// Run ON_DIGEST with a digest; the rendered text is in `text`, the figures alongside it
+pub async fn digest(config: &Config, digest: &Digest) -> Result<()> {
+    let Some(command) = &config.on_digest else {
+        return Ok(());
+    };
+
+    let mut payload = json!({ "event": "digest", "text": digest.render() });
+    if let (Some(fields), Value::Object(figures)) = (payload.as_object_mut(), serde_json::to_value(digest)?) {
+        fields.extend(figures);
+    }
+    run_hook(command, &payload).await.context("ON_DIGEST hook failed")?;
+    Ok(())
+}
+
+/// Run ON_PAYMENT_DUE with the unpaid invoices past due or due soon; the rendered list is in `text`
+pub async fn payment_due(config: &Config, payables: &Payables) -> Result<()> {
+    let Some(command) = &config.on_payment_due else {
+        return Ok(());
+    };
+
+    let mut payload = json!({
+        "event": "payment_due",
+        "profile": config.profile,
+        "message": payables.subject(),
+        "text": payables.render(),
+    });
+    if let (Some(fields), Value::Object(figures)) = (payload.as_object_mut(), serde_json::to_value(payables)?) {
+        fields.extend(figures);
+    }
+    run_hook(command, &payload).await.context("ON_PAYMENT_DUE hook failed")?;
+    Ok(())
+}
+
+/// Run PRE_UPLOAD_TRANSFORM on a downloaded attachment and return the path to upload.
+/// The command receives the file path and prints the (possibly new) path as its last stdout line;
+/// printing nothing keeps the original file.
+pub async fn transform_file(config: &Config, path: &Path, institution: &str) -> Result<PathBuf> {
+    let Some(command) = &config.pre_upload_transform else {
+        return Ok(path.to_path_buf());
+    };
+
+    let payload = json!({
+        "event": "pre_upload",
+        "profile": config.profile,
+        "file_name": path.file_name().map(|n| n.to_string_lossy().to_string()),
+        "file_path": path.to_string_lossy(),
+        "institution": institution,
+    });
+
+    let stdout = run_hook(command, &payload).await
+        .context("PRE_UPLOAD_TRANSFORM failed")?;
+
+    let Some(output) = stdout.lines().map(str::trim).rfind(|line| !line.is_empty()) else {
+        return Ok(path.to_path_buf());
+    };
+
+    let output = PathBuf::from(output);
+    if !output.is_file() {
+        anyhow::bail!("PRE_UPLOAD_TRANSFORM returned {}, which is not a file", output.display());
+    }
+
+    Ok(output)
+}
+
+/// Run a hook command through the shell and return its stdout. The payload is written to stdin
+/// as JSON and each field is also exported as an `INVOICE_AGENT_<FIELD>` environment variable.
+async fn run_hook(command: &str, payload: &Value) -> Result<String> {
+    let mut cmd = shell_command(command);
+    if let Some(fields) = payload.as_object() {
+        for (key, value) in fields {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Null => String::new(),
+                other => other.to_string(),
+            };
+            cmd.env(format!("INVOICE_AGENT_{}", key.to_uppercase()), value);
+        }
+    }
+
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start `{}`", command))?;
+
+    // Hooks that ignore stdin close the pipe early; that's not an error
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(payload.to_string().as_bytes()).await;
+    }
+
+    let output = tokio::time::timeout(HOOK_TIMEOUT, child.wait_with_output())
+        .await
+        .with_context(|| format!("`{}` timed out after {}s", command, HOOK_TIMEOUT.as_secs()))??;
+
+    if !output.status.success() {
+        anyhow::bail!("`{}` exited with {}", command, output.status);
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+pub fn report(tx: Option<&mpsc::UnboundedSender<String>>, message: String) {
+    match tx {
+        Some(tx) => {
+            let _ = tx.send(message);
+        }
+        None => eprintln!("{}", message),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_hook_passes_env_and_stdin() {
+        let temp = tempfile::tempdir().unwrap();
+        let out = temp.path().join("hook");
+        let command = format!("cat > {0}.json && echo \"$INVOICE_AGENT_FILE_NAME\" > {0}.env", out.display());
+
+        let payload = json!({ "event": "file_uploaded", "file_name": "aws-invoice.pdf" });
+        run_hook(&command, &payload).await.unwrap();
+
+        let stdin: Value = serde_json::from_str(&std::fs::read_to_string(out.with_extension("json")).unwrap()).unwrap();
+        assert_eq!(stdin["file_name"], "aws-invoice.pdf");
+        assert_eq!(std::fs::read_to_string(out.with_extension("env")).unwrap().trim(), "aws-invoice.pdf");
+
+        assert!(run_hook("exit 3", &payload).await.is_err());
+        assert_eq!(run_hook("echo one; echo two", &payload).await.unwrap(), "one\ntwo\n");
+    }
+}
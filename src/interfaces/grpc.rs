@@ -0,0 +1,240 @@
+//! gRPC control API for fleet deployments: a central orchestrator triggers runs, streams their
+//! progress and queries the archive of each instance. Service definition: proto/invoicepilot.proto.
+//!
+//! Outside localhost the server only starts with mutual TLS (GRPC_TLS_CERT, GRPC_TLS_KEY and
+//! GRPC_CLIENT_CA), so only clients holding a certificate from that CA get in.
+
+pub mod proto {
+    tonic::include_proto!("invoicepilot.v1");
+}
+
+use crate::config::env::Config;
+use crate::db::{self, DbPool, InvoiceQuery};
+use crate::process::outcome::FailureKind;
+use crate::process::tracker::{self, LiveRun, RunTracker};
+use crate::scheduler::runner;
+use anyhow::{Context, Result};
+use futures_util::stream::{self, Stream};
+use proto::invoice_pilot_server::{InvoicePilot, InvoicePilotServer};
+use proto::{
+    run_event, ArchiveQuery, GetRunStatusRequest, QueryArchiveResponse, RunEvent, RunStatus, SearchInvoicesResponse,
+    StartRunRequest, StartRunResponse, WatchRunRequest,
+};
+use std::pin::Pin;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
+use tonic::{Request, Response, Status};
+
+struct ControlService {
+    config: Config,
+    pool: Option<DbPool>,
+    tracker: RunTracker,
+}
+
+impl ControlService {
+    fn pool(&self) -> Result<&DbPool, Status> {
+        self.pool.as_ref().ok_or_else(|| Status::unavailable("The invoice archive needs the database (set DATABASE_URL)"))
+    }
+
+    fn profile(&self) -> &str {
+        self.config.profile.as_deref().unwrap_or_default()
+    }
+}
+
+#[tonic::async_trait]
+impl InvoicePilot for ControlService {
+    async fn start_run(&self, request: Request<StartRunRequest>) -> Result<Response<StartRunResponse>, Status> {
+        self.config.ensure_writable("Starting a run").map_err(|e| Status::permission_denied(e.to_string()))?;
+        let request = request.into_inner();
+        let (start_date, end_date) = if request.start_date.is_empty() && request.end_date.is_empty() {
+            runner::get_previous_month_range()
+        } else {
+            runner::parse_date_range(&format!("{}:{}", request.start_date, request.end_date))
+                .map_err(|e| Status::invalid_argument(e.to_string()))?
+        };
+        if !self.tracker.start(self.config.clone(), start_date, end_date) {
+            return Err(Status::failed_precondition("A run is already in progress"));
+        }
+        Ok(Response::new(StartRunResponse {
+            start_date: start_date.to_string(),
+            end_date: end_date.to_string(),
+        }))
+    }
+
+    async fn get_run_status(&self, _request: Request<GetRunStatusRequest>) -> Result<Response<RunStatus>, Status> {
+        Ok(Response::new(run_status(self.tracker.snapshot())))
+    }
+
+    type WatchRunStream = Pin<Box<dyn Stream<Item = Result<RunEvent, Status>> + Send>>;
+
+    async fn watch_run(&self, _request: Request<WatchRunRequest>) -> Result<Response<Self::WatchRunStream>, Status> {
+        // Subscribe before looking, so a run finishing in between still sends its final status
+        let events = self.tracker.subscribe();
+        let snapshot = self.tracker.snapshot();
+        if !snapshot.as_ref().is_some_and(|run| run.running) {
+            let finished = RunEvent { event: Some(run_event::Event::Finished(run_status(snapshot))) };
+            return Ok(Response::new(Box::pin(stream::iter([Ok(finished)]))));
+        }
+
+        let stream = stream::unfold(Some(events), |events| async move {
+            let mut events = events?;
+            let event = match events.recv().await {
+                Ok(tracker::RunEvent::Progress(line)) => run_event::Event::Progress(line),
+                Ok(tracker::RunEvent::Finished(run)) => {
+                    let finished = RunEvent { event: Some(run_event::Event::Finished(run_status(Some(run)))) };
+                    return Some((Ok(finished), None));
+                }
+                Err(RecvError::Lagged(missed)) => run_event::Event::Progress(format!("… {} progress lines skipped", missed)),
+                Err(RecvError::Closed) => return None,
+            };
+            Some((Ok(RunEvent { event: Some(event) }), Some(events)))
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn search_invoices(&self, request: Request<ArchiveQuery>) -> Result<Response<SearchInvoicesResponse>, Status> {
+        let query = invoice_query(request.into_inner())?;
+        let documents = db::search_invoice_documents(self.pool()?, self.profile(), &query)
+            .await
+            .map_err(|e| Status::internal(format!("{:#}", e)))?;
+        Ok(Response::new(SearchInvoicesResponse {
+            documents: documents
+                .into_iter()
+                .map(|document| proto::InvoiceDocument {
+                    vendor: document.vendor,
+                    invoice_number: document.invoice_number.unwrap_or_default(),
+                    amount_cents: document.amount_cents,
+                    currency: document.currency.unwrap_or_default(),
+                    filename: document.filename,
+                    folder: document.folder,
+                    file_id: document.file_id,
+                    billing_month: document.billing_month.map(|month| month.to_string()).unwrap_or_default(),
+                    web_link: document.web_link.unwrap_or_default(),
+                    snippet: document.snippet.unwrap_or_default(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn query_archive(&self, request: Request<ArchiveQuery>) -> Result<Response<QueryArchiveResponse>, Status> {
+        let query = invoice_query(request.into_inner())?;
+        let totals = db::summarize_invoice_documents(self.pool()?, self.profile(), &query)
+            .await
+            .map_err(|e| Status::internal(format!("{:#}", e)))?;
+        Ok(Response::new(QueryArchiveResponse {
+            totals: totals
+                .into_iter()
+                .map(|total| proto::ArchiveTotal {
+                    billing_month: total.billing_month.map(|month| month.to_string()).unwrap_or_default(),
+                    vendor: total.vendor,
+                    currency: total.currency.unwrap_or_default(),
+                    documents: total.documents,
+                    total_cents: total.total_cents,
+                })
+                .collect(),
+        }))
+    }
+}
+
+fn invoice_query(query: ArchiveQuery) -> Result<InvoiceQuery, Status> {
+    let non_empty = |value: String| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+    let billing_month = non_empty(query.month)
+        .map(|month| runner::parse_month(&month))
+        .transpose()
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+    Ok(InvoiceQuery {
+        vendor: non_empty(query.vendor),
+        billing_month,
+        min_amount_cents: query.min_amount.map(|amount| (amount * 100.0).round() as i64),
+        max_amount_cents: query.max_amount.map(|amount| (amount * 100.0).round() as i64),
+        text: non_empty(query.text),
+        limit: if query.limit == 0 { 50 } else { i64::from(query.limit.min(200)) },
+    })
+}
+
+fn run_status(run: Option<LiveRun>) -> RunStatus {
+    let Some(run) = run else {
+        return RunStatus::default();
+    };
+    RunStatus {
+        has_run: true,
+        running: run.running,
+        start_date: run.start_date.to_string(),
+        end_date: run.end_date.to_string(),
+        started_at: run.started_at.to_rfc3339(),
+        uploaded: run.uploaded as u32,
+        skipped: run.skipped as u32,
+        failed: run.failed as u32,
+        error: run.error.unwrap_or_default(),
+        log: run.log,
+    }
+}
+
+/// Serve the control API until the process is stopped
+pub async fn serve(config: Config, bind: &str) -> Result<()> {
+    let address = tokio::net::lookup_host(bind)
+        .await
+        .with_context(|| format!("Invalid gRPC address {}", bind))?
+        .next()
+        .with_context(|| format!("Invalid gRPC address {}", bind))?;
+
+    let mut server = Server::builder();
+    match (&config.grpc_tls_cert, &config.grpc_tls_key, &config.grpc_client_ca) {
+        (Some(cert), Some(key), Some(ca)) => {
+            let read = |path: &std::path::PathBuf| std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()));
+            let tls = ServerTlsConfig::new()
+                .identity(Identity::from_pem(read(cert)?, read(key)?))
+                .client_ca_root(Certificate::from_pem(read(ca)?));
+            server = server.tls_config(tls).context("Invalid gRPC TLS configuration")?;
+            println!("🔒 Mutual TLS: clients need a certificate signed by {}", ca.display());
+        }
+        (None, None, None) if address.ip().is_loopback() => {
+            println!("⚠ Plaintext gRPC on localhost; set GRPC_TLS_CERT, GRPC_TLS_KEY and GRPC_CLIENT_CA for mutual TLS");
+        }
+        (None, None, None) => {
+            return Err(anyhow::anyhow!("Refusing to serve gRPC on {} without mutual TLS: set GRPC_TLS_CERT, GRPC_TLS_KEY and GRPC_CLIENT_CA", address))
+                .context(FailureKind::Config);
+        }
+        _ => {
+            return Err(anyhow::anyhow!("Mutual TLS needs all of GRPC_TLS_CERT, GRPC_TLS_KEY and GRPC_CLIENT_CA")).context(FailureKind::Config);
+        }
+    }
+
+    let pool = db::connect_optional("gRPC archive queries").await;
+    let service = ControlService { config, pool, tracker: RunTracker::default() };
+    println!("📡 gRPC control API listening on {}", address);
+    server
+        .add_service(InvoicePilotServer::new(service))
+        .serve(address)
+        .await
+        .context("gRPC server failed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    fn service() -> ControlService {
+        ControlService { config: Config::for_test(&[]), pool: None, tracker: RunTracker::default() }
+    }
+
+    #[tokio::test]
+    async fn test_control_service_without_run() {
+        let service = service();
+
+        let status = service.start_run(Request::new(StartRunRequest { start_date: "2025-03-31".to_string(), end_date: "2025-03-01".to_string() })).await;
+        assert_eq!(status.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        // Nothing running: the watch ends right away with the (empty) last status
+        let events: Vec<_> = service.watch_run(Request::new(WatchRunRequest {})).await.unwrap().into_inner().collect().await;
+        assert_eq!(events.len(), 1);
+        let Some(run_event::Event::Finished(status)) = events[0].as_ref().unwrap().event.clone() else {
+            panic!("expected the final status");
+        };
+        assert!(!status.has_run);
+
+        let search = service.search_invoices(Request::new(ArchiveQuery::default())).await;
+        assert_eq!(search.unwrap_err().code(), tonic::Code::Unavailable);
+    }
+}
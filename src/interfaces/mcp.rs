@@ -0,0 +1,304 @@
+//! MCP (Model Context Protocol) server so AI assistants can search the archive and start runs.
+//!
+//! Speaks JSON-RPC 2.0, newline-delimited over stdio (`mcp` command) or one message per POST to
+//! `/mcp` on the web dashboard. Tools: `search_invoices`, `query_archive`, `run_fetch` and
+//! `get_run_status`. Tool failures are reported as tool results with `isError`, protocol
+//! problems as JSON-RPC errors.
+
+use crate::config::env::Config;
+use crate::db::{self, DbPool, InvoiceQuery};
+use crate::process::tracker::RunTracker;
+use crate::scheduler::runner;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+/// Protocol revisions this server speaks, newest first
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// Progress lines of the current run returned by get_run_status
+const LOG_TAIL: usize = 50;
+
+/// Past runs returned by get_run_status
+const RECENT_RUNS: i64 = 10;
+
+/// Filters shared by search_invoices and query_archive
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArchiveArgs {
+    query: Option<String>,
+    vendor: Option<String>,
+    month: Option<String>,
+    min_amount: Option<f64>,
+    max_amount: Option<f64>,
+    limit: Option<i64>,
+}
+
+impl ArchiveArgs {
+    fn into_query(self) -> Result<InvoiceQuery> {
+        let non_empty = |value: Option<String>| value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        Ok(InvoiceQuery {
+            vendor: non_empty(self.vendor),
+            billing_month: non_empty(self.month).map(|month| runner::parse_month(&month)).transpose()?,
+            min_amount_cents: self.min_amount.map(|amount| (amount * 100.0).round() as i64),
+            max_amount_cents: self.max_amount.map(|amount| (amount * 100.0).round() as i64),
+            text: non_empty(self.query),
+            limit: self.limit.unwrap_or(50).clamp(1, 200),
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RunArgs {
+    date_range: Option<String>,
+}
+
+pub struct McpServer {
+    config: Config,
+    pool: Option<DbPool>,
+    tracker: RunTracker,
+}
+
+impl McpServer {
+    pub fn new(config: Config, pool: Option<DbPool>, tracker: RunTracker) -> Self {
+        Self { config, pool, tracker }
+    }
+
+    /// Answer one JSON-RPC message; notifications and client responses get no answer
+    pub async fn handle_message(&self, message: &str) -> Option<Value> {
+        let request: Value = match serde_json::from_str(message) {
+            Ok(request) => request,
+            Err(e) => return Some(error_response(Value::Null, -32700, &format!("Parse error: {}", e))),
+        };
+        let method = request.get("method").and_then(Value::as_str)?;
+        let id = request.get("id").cloned();
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+        let result = match method {
+            "initialize" => Ok(initialize(&params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tools(self.config.read_only) })),
+            "tools/call" => self.call_tool(&params).await,
+            _ => Err((-32601, format!("Method not found: {}", method))),
+        };
+
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, &message),
+        })
+    }
+
+    async fn call_tool(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let name = params.get("name").and_then(Value::as_str).unwrap_or_default();
+        let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+        let outcome = match name {
+            "search_invoices" => self.search_invoices(arguments).await,
+            "query_archive" => self.query_archive(arguments).await,
+            "run_fetch" => self.run_fetch(arguments),
+            "get_run_status" => self.get_run_status().await,
+            _ => return Err((-32602, format!("Unknown tool: {}", name))),
+        };
+
+        Ok(match outcome {
+            Ok(value) => json!({
+                "content": [{ "type": "text", "text": serde_json::to_string_pretty(&value).unwrap_or_default() }],
+                "isError": false,
+            }),
+            Err(e) => json!({
+                "content": [{ "type": "text", "text": format!("{:#}", e) }],
+                "isError": true,
+            }),
+        })
+    }
+
+    fn pool(&self) -> Result<&DbPool> {
+        self.pool.as_ref().context("The invoice archive needs the database (set DATABASE_URL)")
+    }
+
+    fn profile(&self) -> &str {
+        self.config.profile.as_deref().unwrap_or_default()
+    }
+
+    async fn search_invoices(&self, arguments: Value) -> Result<Value> {
+        let args: ArchiveArgs = serde_json::from_value(arguments).context("Invalid arguments")?;
+        let documents = db::search_invoice_documents(self.pool()?, self.profile(), &args.into_query()?).await?;
+        Ok(serde_json::to_value(documents)?)
+    }
+
+    async fn query_archive(&self, arguments: Value) -> Result<Value> {
+        let args: ArchiveArgs = serde_json::from_value(arguments).context("Invalid arguments")?;
+        let totals = db::summarize_invoice_documents(self.pool()?, self.profile(), &args.into_query()?).await?;
+        Ok(serde_json::to_value(totals)?)
+    }
+
+    fn run_fetch(&self, arguments: Value) -> Result<Value> {
+        self.config.ensure_writable("Starting a run")?;
+        let args: RunArgs = serde_json::from_value(arguments).context("Invalid arguments")?;
+        let (start_date, end_date) = match args.date_range {
+            Some(range) => runner::parse_date_range(&range)?,
+            None => runner::get_previous_month_range(),
+        };
+        if !self.tracker.start(self.config.clone(), start_date, end_date) {
+            anyhow::bail!("A run is already in progress; check get_run_status");
+        }
+        Ok(json!({
+            "started": true,
+            "start_date": start_date,
+            "end_date": end_date,
+            "next": "Call get_run_status until running is false",
+        }))
+    }
+
+    async fn get_run_status(&self) -> Result<Value> {
+        let current = self.tracker.snapshot().map(|mut run| {
+            let skip = run.log.len().saturating_sub(LOG_TAIL);
+            run.log.drain(..skip);
+            run
+        });
+        let recent_runs = match &self.pool {
+            Some(pool) => json!(db::load_runs(pool, self.profile(), RECENT_RUNS).await?),
+            None => Value::Null,
+        };
+        Ok(json!({ "current": current, "recent_runs": recent_runs }))
+    }
+}
+
+/// Serve MCP over stdin/stdout until the client closes stdin
+pub async fn serve_stdio(config: Config) -> Result<()> {
+    let pool = db::connect_optional("MCP archive tools").await;
+    let server = McpServer::new(config, pool, RunTracker::default());
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = server.handle_message(&line).await {
+            stdout.write_all(format!("{}\n", response).as_bytes()).await?;
+            stdout.flush().await?;
+        }
+    }
+    Ok(())
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(Value::as_str);
+    let version = requested
+        .filter(|version| PROTOCOL_VERSIONS.contains(version))
+        .unwrap_or(PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": {} },
+        "serverInfo": { "name": "invoice-pilot", "version": env!("CARGO_PKG_VERSION") },
+        "instructions": "Archived invoices are recorded as they are uploaded to Google Drive. Amounts are in cents of the document currency. run_fetch starts a background run; poll get_run_status for progress.",
+    })
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// The tools offered; read-only mode (READ_ONLY) leaves out `run_fetch`
+fn tools(read_only: bool) -> Value {
+    let filters = json!({
+        "query": { "type": "string", "description": "Words or phrases in the document text (web search syntax), e.g. \"domain renewal\" or an IBAN" },
+        "vendor": { "type": "string", "description": "Part of the vendor or institution name" },
+        "month": { "type": "string", "description": "Billing month, YYYY-MM" },
+        "min_amount": { "type": "number", "description": "Only invoices of at least this total" },
+        "max_amount": { "type": "number", "description": "Only invoices of at most this total" },
+        "limit": { "type": "integer", "minimum": 1, "maximum": 200, "description": "Maximum number of rows (default 50)" },
+    });
+    json!([
+        {
+            "name": "search_invoices",
+            "description": "Find archived invoices and statements. Returns vendor, invoice number, amount_cents, currency, filename, Drive folder, billing month, web link and, for text queries, the matching passage.",
+            "inputSchema": { "type": "object", "properties": filters, "additionalProperties": false },
+        },
+        {
+            "name": "query_archive",
+            "description": "Totals of archived documents per billing month, vendor and currency (document count and summed amount_cents), with the same filters as search_invoices.",
+            "inputSchema": { "type": "object", "properties": filters, "additionalProperties": false },
+        },
+        {
+            "name": "run_fetch",
+            "description": "Start fetching invoices from the mailbox and uploading them to Drive in the background. One run at a time.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "date_range": { "type": "string", "description": "YYYY-MM-DD:YYYY-MM-DD; defaults to the previous month" },
+                },
+                "additionalProperties": false,
+            },
+        },
+        {
+            "name": "get_run_status",
+            "description": "Progress of the run started by run_fetch (latest log lines, uploaded/skipped/failed counts, error) and the most recent recorded runs.",
+            "inputSchema": { "type": "object", "properties": {}, "additionalProperties": false },
+        },
+    ])
+    .as_array()
+    .into_iter()
+    .flatten()
+    .filter(|tool| !(read_only && tool["name"] == "run_fetch"))
+    .cloned()
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> McpServer {
+        McpServer::new(Config::for_test(&[]), None, RunTracker::default())
+    }
+
+    #[tokio::test]
+    async fn test_handle_message() {
+        let server = server();
+
+        let response = server
+            .handle_message(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-03-26"}}"#)
+            .await
+            .unwrap();
+        assert_eq!(response["result"]["protocolVersion"], "2025-03-26");
+
+        // Notifications get no answer
+        assert!(server.handle_message(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#).await.is_none());
+
+        let response = server.handle_message(r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#).await.unwrap();
+        assert_eq!(response["result"]["tools"].as_array().unwrap().len(), 4);
+
+        // Without a database the archive tools fail as tool results, not protocol errors
+        let response = server
+            .handle_message(r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"search_invoices","arguments":{"query":"hosting"}}}"#)
+            .await
+            .unwrap();
+        assert_eq!(response["result"]["isError"], true);
+
+        let response = server
+            .handle_message(r#"{"jsonrpc":"2.0","id":4,"method":"tools/call","params":{"name":"nope"}}"#)
+            .await
+            .unwrap();
+        assert_eq!(response["error"]["code"], -32602);
+
+        let response = server.handle_message("not json").await.unwrap();
+        assert_eq!(response["error"]["code"], -32700);
+
+        // Read-only mode neither offers nor starts runs
+        let mut config = Config::for_test(&[]);
+        config.read_only = true;
+        let server = McpServer::new(config, None, RunTracker::default());
+        let response = server.handle_message(r#"{"jsonrpc":"2.0","id":5,"method":"tools/list"}"#).await.unwrap();
+        assert!(response["result"]["tools"].as_array().unwrap().iter().all(|tool| tool["name"] != "run_fetch"));
+        let response = server
+            .handle_message(r#"{"jsonrpc":"2.0","id":6,"method":"tools/call","params":{"name":"run_fetch","arguments":{}}}"#)
+            .await
+            .unwrap();
+        assert_eq!(response["result"]["isError"], true);
+        assert!(server.tracker.snapshot().is_none());
+    }
+}
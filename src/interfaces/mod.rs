@@ -1,2 +1,7 @@
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod mcp;
 pub mod tui;
 pub mod ui;
+#[cfg(feature = "dashboard")]
+pub mod web;
@@ -1,5 +1,9 @@
 use crate::app::{App, AuthStatus, FocusedPanel, PopupState};
+use crate::auth::failure::Service;
+use crate::config::env::Flags;
+use crate::process::feedback::{Correction, Feedback};
 use crate::process::jobs;
+use crate::process::outcome::STEP_MARKER;
 use crate::interfaces::ui::draw;
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
@@ -7,10 +11,14 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::prelude::*;
-use std::{io, time::Duration};
+use base64::Engine as _;
+use std::{
+    io::{self, Write as _},
+    time::Duration,
+};
 use tokio::sync::mpsc;
 
-pub async fn run_tui() -> Result<(), Box<dyn std::error::Error>> {
+pub async fn run_tui(flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -20,6 +28,7 @@ pub async fn run_tui() -> Result<(), Box<dyn std::error::Error>> {
 
     // Create app and run it
     let mut app = App::new();
+    app.flags = flags;
 
     // Initialize database connection
     match crate::db::init_pool().await {
@@ -42,6 +51,9 @@ pub async fn run_tui() -> Result<(), Box<dyn std::error::Error>> {
         app.open_popup(PopupState::SetupGuide);
     } else {
         app.add_progress_message("Configuration loaded successfully".to_string());
+        if let Err(e) = app.load_last_scheduled_run().await {
+            app.add_progress_message(format!("Could not load the last scheduled run: {:#}", e));
+        }
         // Validate existing authentication tokens
         app.validate_existing_tokens();
     }
@@ -71,6 +83,14 @@ async fn run_app<B: Backend>(
 ) -> io::Result<()> {
     let (tx, mut rx) = mpsc::unbounded_channel::<String>();
 
+    // Refresh cached tokens before they expire while the app is left open
+    let keepalive = app
+        .config
+        .as_ref()
+        .filter(|config| !config.mock_mode)
+        .and_then(|config| crate::auth::keepalive::kept_tokens(config).ok().map(|tokens| (tokens, config.clone())))
+        .map(|(tokens, config)| crate::auth::keepalive::spawn(tokens, config, Some(tx.clone())));
+
     loop {
         terminal.draw(|f| draw(f, app))?;
 
@@ -79,6 +99,15 @@ async fn run_app<B: Backend>(
 
         // Handle async processing updates
         while let Ok(message) = rx.try_recv() {
+            // Sign-ins record their failures before reporting back
+            if message.contains("_AUTH_") {
+                app.reload_auth_failures();
+                app.reload_auth_accounts();
+            }
+            // Steps are for the CLI progress bars; the lines next to them say the same
+            if message.starts_with(STEP_MARKER) {
+                continue;
+            }
             if message == "__PROCESSING_COMPLETE__" {
                 app.set_processing(false);
                 app.processing_step = None;
@@ -101,6 +130,21 @@ async fn run_app<B: Backend>(
                 app.auth_popup_success = true;
                 // Keep popup open to show success and allow user options
                 // Don't auto-start Drive auth for refreshed tokens - let user do it manually
+            } else if let Some(service) = message.strip_prefix("__").and_then(|m| m.strip_suffix("_AUTH_KEPT_FRESH__")) {
+                let (status, label) = match service {
+                    "GMAIL" => (Some(&mut app.gmail_auth_status), "Gmail"),
+                    "DRIVE" => (Some(&mut app.drive_auth_status), "Google Drive"),
+                    _ => (None, "Cloud Storage"),
+                };
+                // A sign-in under way decides the status itself
+                if let Some(status) = status
+                    && *status != crate::app::AuthStatus::Authenticating
+                {
+                    *status = crate::app::AuthStatus::Authenticated;
+                }
+                app.add_progress_message(format!("{} token refreshed in the background", label));
+            } else if let Some(error) = message.strip_prefix("__GCS_AUTH_ERROR__:") {
+                app.add_progress_message(format!("Cloud Storage authentication failed: {}", error));
             } else if message.starts_with("__GMAIL_AUTH_ERROR__:") {
                 let error = message.strip_prefix("__GMAIL_AUTH_ERROR__:").unwrap_or("Unknown error");
                 app.gmail_auth_status = crate::app::AuthStatus::Error(error.to_string());
@@ -108,6 +152,17 @@ async fn run_app<B: Backend>(
                 if matches!(app.popup_state, PopupState::GmailAuthUrl) {
                     app.close_popup();
                 }
+            } else if message == "__GMAIL_AUTH_TIMEOUT__" {
+                app.gmail_auth_status = crate::app::AuthStatus::TimedOut;
+                app.add_progress_message("Gmail auth timed out: no response from the browser. Press Enter to try again".to_string());
+                if matches!(app.popup_state, PopupState::GmailAuthUrl) {
+                    app.close_popup();
+                }
+            } else if let Some(error) = message.strip_prefix("__GMAIL_AUTH_PASTE__:").or_else(|| message.strip_prefix("__DRIVE_AUTH_PASTE__:")) {
+                app.auth_paste_input = Some(String::new());
+                if !error.is_empty() {
+                    app.error_message = Some(error.to_string());
+                }
             } else if message.starts_with("__GMAIL_AUTH_URL__:") {
                 let url = message.strip_prefix("__GMAIL_AUTH_URL__:").unwrap_or("");
                 app.auth_url = Some(url.to_string());
@@ -134,6 +189,12 @@ async fn run_app<B: Backend>(
                 if matches!(app.popup_state, PopupState::DriveAuthUrl) {
                     app.close_popup();
                 }
+            } else if message == "__DRIVE_AUTH_TIMEOUT__" {
+                app.drive_auth_status = crate::app::AuthStatus::TimedOut;
+                app.add_progress_message("Drive auth timed out: no response from the browser. Press Enter to try again".to_string());
+                if matches!(app.popup_state, PopupState::DriveAuthUrl) {
+                    app.close_popup();
+                }
             } else if message.starts_with("__DRIVE_AUTH_URL__:") {
                 let url = message.strip_prefix("__DRIVE_AUTH_URL__:").unwrap_or("");
                 app.auth_url = Some(url.to_string());
@@ -146,6 +207,12 @@ async fn run_app<B: Backend>(
             } else if message.starts_with("__RESULTS__:") {
                 // Parse results: processed=5,uploaded=4,failed=1,month=October,folder=Invoices/October
                 let results_str = message.strip_prefix("__RESULTS__:").unwrap_or("");
+                // The folder link comes last and may hold ',' and '=' itself
+                let (results_str, link) = match results_str.split_once(",link=") {
+                    Some((results, link)) => (results, Some(link.to_string())),
+                    None => (results_str, None),
+                };
+                app.drive_folder_link = link;
                 for part in results_str.split(',') {
                     let kv: Vec<&str> = part.split('=').collect();
                     if kv.len() == 2 {
@@ -217,11 +284,14 @@ async fn run_app<B: Backend>(
                                 }
                             } else {
                                 // Handle popup confirmation
-                                handle_popup_confirm(app, &tx);
+                                handle_popup_confirm(app, &tx).await;
                             }
                         }
                         KeyCode::Esc => {
                             if app.is_popup_open() {
+                                if matches!(app.popup_state, PopupState::GmailAuthUrl | PopupState::DriveAuthUrl) {
+                                    app.cancel_auth();
+                                }
                                 app.close_popup();
                             } else {
                                 break; // Quit
@@ -232,6 +302,11 @@ async fn run_app<B: Backend>(
                                 break; // Quit
                             }
                         }
+                        KeyCode::Char('p') | KeyCode::Char('P')
+                            if matches!(app.popup_state, PopupState::ArchiveSearch) && !app.archive_editing =>
+                        {
+                            toggle_selected_paid(app).await;
+                        }
                         KeyCode::Char('?') => {
                             if !app.is_popup_open() {
                                 if app.config.is_none() {
@@ -251,7 +326,7 @@ async fn run_app<B: Backend>(
                                     FocusedPanel::Manual => handle_manual_input(app, key.code),
                                     FocusedPanel::Auth => handle_auth_input(app, key.code, tx.clone()),
                                     FocusedPanel::Scheduled => handle_scheduled_input(app, key.code),
-                                    FocusedPanel::Logs => {} // Logs panel is read-only
+                                    FocusedPanel::Logs => handle_logs_input(app, key.code),
                                 }
                             }
                         }
@@ -261,6 +336,9 @@ async fn run_app<B: Backend>(
         }
     }
 
+    if let Some(keepalive) = keepalive {
+        keepalive.abort();
+    }
     Ok(())
 }
 
@@ -318,6 +396,141 @@ fn handle_manual_input(app: &mut App, key_code: KeyCode) {
     }
 }
 
+fn handle_logs_input(app: &mut App, key_code: KeyCode) {
+    match key_code {
+        KeyCode::Char('f') | KeyCode::Char('F') => open_corrections(app),
+        KeyCode::Char('s') | KeyCode::Char('S') => {
+            if app.db_pool.is_none() {
+                app.set_error("Archive search needs the invoice database (set DATABASE_URL)".to_string());
+                return;
+            }
+            app.archive_results = None;
+            app.archive_selected = 0;
+            app.archive_editing = true;
+            app.open_popup(PopupState::ArchiveSearch);
+        }
+        _ => {}
+    }
+}
+
+/// Full-text search over the archived documents for the typed query
+async fn search_archive(app: &mut App) {
+    let Some(pool) = app.db_pool.clone() else {
+        return;
+    };
+    let text = app.archive_query.trim().to_string();
+    if text.is_empty() {
+        return;
+    }
+    let profile = app.config.as_ref().and_then(|c| c.profile.clone()).unwrap_or_default();
+    let query = crate::db::InvoiceQuery { text: Some(text), limit: 50, ..Default::default() };
+    match crate::db::search_invoice_documents(&pool, &profile, &query).await {
+        Ok(documents) => {
+            app.archive_editing = documents.is_empty();
+            app.archive_results = Some(documents);
+            app.archive_selected = 0;
+        }
+        Err(e) => app.set_error(format!("Archive search failed: {:#}", e)),
+    }
+}
+
+/// Mark the document selected in the archive search paid, or unpaid again
+async fn toggle_selected_paid(app: &mut App) {
+    let Some(pool) = app.db_pool.clone() else {
+        return;
+    };
+    let profile = app.config.as_ref().and_then(|c| c.profile.clone()).unwrap_or_default();
+    let Some(document) = app.archive_results.as_mut().and_then(|results| results.get_mut(app.archive_selected)) else {
+        return;
+    };
+    let paid = !document.paid;
+    match crate::db::set_invoice_paid(&pool, &profile, &document.file_id, paid).await {
+        Ok(_) => {
+            document.paid = paid;
+            let message = format!("Marked {} {}", document.filename, if paid { "paid" } else { "unpaid" });
+            app.add_progress_message(message);
+        }
+        Err(e) => app.set_error(format!("Could not update the payment status: {:#}", e)),
+    }
+}
+
+/// Name and link of the file selected in the archive search or corrections popup
+fn selected_file_link(app: &App) -> Option<(String, String)> {
+    match app.popup_state {
+        PopupState::ArchiveSearch => {
+            let document = app.archive_results.as_ref()?.get(app.archive_selected)?;
+            let link = document.web_link.clone().unwrap_or_else(|| crate::drive::client::file_link(&document.file_id));
+            Some((document.filename.clone(), link))
+        }
+        PopupState::Corrections => {
+            let file = app.processed_files.get(app.processed_selected)?;
+            Some((file.filename.clone(), crate::drive::client::file_link(&file.file_id)))
+        }
+        _ => None,
+    }
+}
+
+fn open_selected_file(app: &mut App) {
+    let Some((filename, link)) = selected_file_link(app) else {
+        return;
+    };
+    match webbrowser::open(&link) {
+        Ok(()) => app.add_progress_message(format!("Opened {} in the browser", filename)),
+        Err(e) => app.set_error(format!("Could not open the browser ({}) - link: {}", e, link)),
+    }
+}
+
+/// Copy the selected file's link through the terminal (OSC 52), which also works over SSH
+fn copy_selected_link(app: &mut App) {
+    let Some((filename, link)) = selected_file_link(app) else {
+        return;
+    };
+    let sequence = format!("\x1b]52;c;{}\x07", base64::engine::general_purpose::STANDARD.encode(&link));
+    let mut stdout = io::stdout();
+    match stdout.write_all(sequence.as_bytes()).and_then(|()| stdout.flush()) {
+        Ok(()) => app.add_progress_message(format!("Copied link to {}: {}", filename, link)),
+        Err(e) => app.set_error(format!("Could not copy the link ({}) - link: {}", e, link)),
+    }
+}
+
+/// Show recently processed files so a misclassified one can be corrected
+fn open_corrections(app: &mut App) {
+    let Some(config) = app.config.as_ref() else {
+        app.set_error("Configuration not loaded - cannot load processed files".to_string());
+        return;
+    };
+    match Feedback::load(config) {
+        Ok(feedback) => {
+            app.processed_files = feedback.recent().cloned().collect();
+            app.processed_selected = 0;
+            app.correction_folder_input = None;
+            app.open_popup(PopupState::Corrections);
+        }
+        Err(e) => app.set_error(format!("Could not load processed files: {:#}", e)),
+    }
+}
+
+/// Persist a correction for the selected file's sender
+fn save_correction(app: &mut App, correction: Correction) {
+    let (Some(config), Some(file)) = (app.config.as_ref(), app.processed_files.get(app.processed_selected)) else {
+        return;
+    };
+    if let Err(e) = config.ensure_writable("Saving a correction") {
+        app.set_error(e.to_string());
+        return;
+    }
+    let file_id = file.file_id.clone();
+    let result = Feedback::load(config).and_then(|mut feedback| {
+        let file = feedback.correct(&file_id, Some(correction.clone()))?.clone();
+        feedback.save()?;
+        Ok(file)
+    });
+    match result {
+        Ok(file) => app.add_progress_message(format!("Correction saved: {} ({}) - {}", file.filename, file.sender, correction)),
+        Err(e) => app.set_error(format!("Could not save correction: {:#}", e)),
+    }
+}
+
 fn handle_scheduled_input(app: &mut App, key_code: KeyCode) {
     match key_code {
         KeyCode::Enter => {
@@ -340,6 +553,11 @@ fn start_scheduled_processing(app: &mut App, tx: mpsc::UnboundedSender<String>)
     app.set_processing(true);
     app.add_progress_message("Starting scheduled invoice processing...".to_string());
 
+    let Some(config) = app.config.clone() else {
+        app.set_error("Configuration not loaded - cannot process".to_string());
+        return;
+    };
+
     // Get previous month date range for scheduled processing
     let (start_date, end_date) = crate::scheduler::runner::get_previous_month_range();
 
@@ -348,7 +566,7 @@ fn start_scheduled_processing(app: &mut App, tx: mpsc::UnboundedSender<String>)
     // Spawn processing task
     let tx_clone = tx.clone();
     tokio::spawn(async move {
-        let result = jobs::run_manual_processing(start_date, end_date, &tx_clone).await;
+        let result = jobs::run_manual_processing(config, start_date, end_date, &tx_clone).await;
         // Send completion signal
         if let Err(e) = result {
             let _ = tx.send(format!("Scheduled processing error: {}", e));
@@ -366,13 +584,37 @@ fn handle_auth_input(app: &mut App, key_code: KeyCode, tx: mpsc::UnboundedSender
             start_drive_auth(app, tx.clone());
         }
         KeyCode::Char('r') | KeyCode::Char('R') | KeyCode::Char('c') | KeyCode::Char('C') => {
+            if let Some(Err(e)) = app.config.as_ref().map(|config| config.ensure_writable("Clearing tokens")) {
+                app.set_error(e.to_string());
+                return;
+            }
             app.gmail_auth_status = crate::app::AuthStatus::NotAuthenticated;
             app.drive_auth_status = crate::app::AuthStatus::NotAuthenticated;
             app.scheduled_job_logged = false; // Reset logging flag when auth is cleared
-            // Clear tokens
-            let _ = crate::auth::gmail_auth::clear_gmail_token();
-            let _ = crate::auth::drive_auth::clear_drive_token();
-            app.add_progress_message("All authentication tokens cleared".to_string());
+            // Revoke the grants with Google like `auth reset`, then clear the tokens
+            tokio::spawn(async move {
+                let revoked = [
+                    ("Gmail", crate::auth::gmail_auth::revoke_gmail_token().await),
+                    ("Google Drive", crate::auth::drive_auth::revoke_drive_token().await),
+                ];
+                for (account, revoked) in revoked {
+                    match revoked {
+                        Ok(true) => {
+                            let _ = tx.send(format!("Revoked the {} grant with Google", account));
+                        }
+                        Ok(false) => {}
+                        Err(e) => {
+                            let _ = tx.send(format!(
+                                "⚠ Couldn't revoke the {} grant ({:#}); remove access at https://myaccount.google.com/permissions",
+                                account, e
+                            ));
+                        }
+                    }
+                }
+                let _ = crate::auth::gmail_auth::clear_gmail_token();
+                let _ = crate::auth::drive_auth::clear_drive_token();
+                let _ = tx.send("All authentication tokens cleared".to_string());
+            });
         }
         _ => {}
     }
@@ -422,10 +664,8 @@ fn handle_popup_input(app: &mut App, key_code: KeyCode) {
         }
         PopupState::ScheduleConfig => {
             match key_code {
-                KeyCode::Char(c) => {
-                    if c.is_ascii_digit() && app.schedule_input.len() < 2 {
-                        app.schedule_input.push(c);
-                    }
+                KeyCode::Char(c) if (c.is_ascii_alphanumeric() || c == ' ' || c == '-') && app.schedule_input.len() < 24 => {
+                    app.schedule_input.push(c);
                 }
                 KeyCode::Backspace => {
                     app.schedule_input.pop();
@@ -438,7 +678,12 @@ fn handle_popup_input(app: &mut App, key_code: KeyCode) {
             if app.auth_popup_success {
                 match key_code {
                     KeyCode::Char('c') | KeyCode::Char('C') => {
-                        // Clear tokens based on which service
+                        if let Some(Err(e)) = app.config.as_ref().map(|config| config.ensure_writable("Clearing tokens")) {
+                            app.close_popup();
+                            app.set_error(e.to_string());
+                            return;
+                        }
+                        // Clear the local tokens based on which service (the grant stays with Google)
                         match app.popup_state {
                             PopupState::GmailAuthUrl => {
                                 let _ = crate::auth::gmail_auth::clear_gmail_token();
@@ -465,6 +710,67 @@ fn handle_popup_input(app: &mut App, key_code: KeyCode) {
                         app.close_popup();
                     }
                 }
+            } else if let Some(pasted) = app.auth_paste_input.as_mut() {
+                match key_code {
+                    KeyCode::Char(c) => pasted.push(c),
+                    KeyCode::Backspace => {
+                        pasted.pop();
+                    }
+                    _ => {}
+                }
+            }
+        }
+        PopupState::Corrections => {
+            if let Some(input) = app.correction_folder_input.as_mut() {
+                match key_code {
+                    KeyCode::Char(c) => input.push(c),
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    _ => {}
+                }
+                return;
+            }
+            match key_code {
+                KeyCode::Down if app.processed_selected + 1 < app.processed_files.len() => {
+                    app.processed_selected += 1;
+                }
+                KeyCode::Up if app.processed_selected > 0 => {
+                    app.processed_selected -= 1;
+                }
+                KeyCode::Char('n') | KeyCode::Char('N') => save_correction(app, Correction::NotInvoice),
+                KeyCode::Char('o') | KeyCode::Char('O') => open_selected_file(app),
+                KeyCode::Char('l') | KeyCode::Char('L') => copy_selected_link(app),
+                KeyCode::Char('w') | KeyCode::Char('W') if !app.processed_files.is_empty() => {
+                    app.correction_folder_input = Some(String::new());
+                }
+                _ => {}
+            }
+        }
+        PopupState::ArchiveSearch => {
+            let results = app.archive_results.as_ref().map_or(0, Vec::len);
+            if app.archive_editing {
+                match key_code {
+                    KeyCode::Char(c) => app.archive_query.push(c),
+                    KeyCode::Backspace => {
+                        app.archive_query.pop();
+                    }
+                    KeyCode::Down if results > 0 => app.archive_editing = false,
+                    _ => {}
+                }
+                return;
+            }
+            match key_code {
+                KeyCode::Down if app.archive_selected + 1 < results => {
+                    app.archive_selected += 1;
+                }
+                KeyCode::Up if app.archive_selected > 0 => {
+                    app.archive_selected -= 1;
+                }
+                KeyCode::Char('o') | KeyCode::Char('O') => open_selected_file(app),
+                KeyCode::Char('l') | KeyCode::Char('L') => copy_selected_link(app),
+                KeyCode::Char('/') | KeyCode::Backspace => app.archive_editing = true,
+                _ => {}
             }
         }
         PopupState::DetailedLogs => {
@@ -495,7 +801,7 @@ fn handle_popup_input(app: &mut App, key_code: KeyCode) {
     }
 }
 
-fn handle_popup_confirm(app: &mut App, tx: &mpsc::UnboundedSender<String>) {
+async fn handle_popup_confirm(app: &mut App, tx: &mpsc::UnboundedSender<String>) {
     match app.popup_state {
         PopupState::DateInput => {
             // Validate dates
@@ -514,21 +820,22 @@ fn handle_popup_confirm(app: &mut App, tx: &mpsc::UnboundedSender<String>) {
             }
         }
         PopupState::ScheduleConfig => {
-            if let Ok(day) = app.schedule_input.parse::<u32>() {
-                if day >= 1 && day <= 31 {
+            match crate::scheduler::runner::ScheduleDay::parse(&app.schedule_input) {
+                Ok(day) => {
                     app.fetch_invoices_day = Some(day);
                     app.scheduled_job_logged = false; // Reset logging flag when schedule changes
                     app.close_popup();
-                    app.add_progress_message(format!("Scheduled processing set for day {} of each month", day));
-                } else {
-                    app.set_error("Day must be between 1 and 31".to_string());
+                    app.add_progress_message(format!("Scheduled processing set for {} of each month", day));
                 }
-            } else {
-                app.set_error("Please enter a valid day number".to_string());
+                Err(e) => app.set_error(e.to_string()),
             }
         }
         PopupState::ProcessingConfirm => {
             app.close_popup();
+            if let Some(Err(e)) = app.config.as_ref().map(|config| config.ensure_writable("Starting a run")) {
+                app.set_error(e.to_string());
+                return;
+            }
             // Start processing based on current panel
             match app.focused_panel {
                 FocusedPanel::Manual => {
@@ -550,8 +857,27 @@ fn handle_popup_confirm(app: &mut App, tx: &mpsc::UnboundedSender<String>) {
         PopupState::DetailedLogs => {
             app.close_popup();
         }
+        PopupState::Corrections => {
+            // Enter finishes typing the corrected folder
+            if let Some(folder) = app.correction_folder_input.take() {
+                let folder = folder.trim().to_string();
+                if !folder.is_empty() {
+                    save_correction(app, Correction::Folder(folder));
+                }
+            }
+        }
+        PopupState::ArchiveSearch => {
+            search_archive(app).await;
+        }
         PopupState::GmailAuthUrl | PopupState::DriveAuthUrl => {
-            // Auth URL popups are closed automatically when auth completes
+            // Auth URL popups are closed automatically when auth completes; Enter only submits a
+            // pasted redirect address
+            if let Some(pasted) = app.auth_paste_input.take() {
+                app.error_message = None;
+                if app.auth_paste.as_ref().is_none_or(|sender| sender.send(pasted).is_err()) {
+                    app.error_message = Some("No sign-in is waiting for a code; start it again".to_string());
+                }
+            }
         }
         PopupState::None => {} // Should not happen
     }
@@ -560,25 +886,42 @@ fn handle_popup_confirm(app: &mut App, tx: &mpsc::UnboundedSender<String>) {
 fn start_gmail_auth(app: &mut App, tx: mpsc::UnboundedSender<String>) {
     let config = app.config.clone();
     if let Some(config) = config {
+        if config.mock_mode {
+            app.gmail_auth_status = AuthStatus::Authenticated;
+            app.add_progress_message("Mock mode: Gmail authentication skipped".to_string());
+            return;
+        }
+        app.cancel_auth(); // Only one flow can listen for the callback
         app.gmail_auth_status = AuthStatus::Authenticating;
         app.auth_popup_success = false; // Reset success flag
         app.open_popup(PopupState::GmailAuthUrl);
         let tx_clone = tx.clone();
+        let pasted = config.paste_code.then(|| {
+            let (sender, pasted) = mpsc::unbounded_channel();
+            app.auth_paste = Some(sender);
+            pasted
+        });
 
-        tokio::spawn(async move {
-            match crate::auth::gmail_auth::get_gmail_token_with_url(
+        app.auth_task = Some(tokio::spawn(async move {
+            let signed_in = crate::auth::gmail_auth::get_gmail_token_with_url(
                 config.gmail_client_id,
                 config.gmail_client_secret,
+                config.gmail_account.as_deref(),
                 tx_clone.clone(),
-            ).await {
+                pasted,
+            ).await;
+            match crate::auth::failure::track(config.profile.as_deref(), Service::Gmail, signed_in) {
                 Ok(_) => {
                     let _ = tx_clone.send("__GMAIL_AUTH_SUCCESS__".to_string());
                 }
+                Err(e) if e.is::<crate::auth::oauth::AuthTimedOut>() => {
+                    let _ = tx_clone.send("__GMAIL_AUTH_TIMEOUT__".to_string());
+                }
                 Err(e) => {
                     let _ = tx_clone.send(format!("__GMAIL_AUTH_ERROR__:{}", e));
                 }
             }
-        });
+        }));
     } else {
         app.set_error("Configuration not loaded - cannot authenticate".to_string());
     }
@@ -587,25 +930,43 @@ fn start_gmail_auth(app: &mut App, tx: mpsc::UnboundedSender<String>) {
 fn start_drive_auth(app: &mut App, tx: mpsc::UnboundedSender<String>) {
     let config = app.config.clone();
     if let Some(config) = config {
+        if config.mock_mode {
+            app.drive_auth_status = AuthStatus::Authenticated;
+            app.add_progress_message("Mock mode: Drive authentication skipped".to_string());
+            return;
+        }
+        app.cancel_auth(); // Only one flow can listen for the callback
         app.drive_auth_status = AuthStatus::Authenticating;
+
         app.auth_popup_success = false; // Reset success flag
         app.open_popup(PopupState::DriveAuthUrl);
         let tx_clone = tx.clone();
+        let pasted = config.paste_code.then(|| {
+            let (sender, pasted) = mpsc::unbounded_channel();
+            app.auth_paste = Some(sender);
+            pasted
+        });
 
-        tokio::spawn(async move {
-            match crate::auth::drive_auth::get_drive_token_with_url(
+        app.auth_task = Some(tokio::spawn(async move {
+            let signed_in = crate::auth::drive_auth::get_drive_token_with_url(
                 config.drive_client_id,
                 config.drive_client_secret,
+                config.drive_account.as_deref(),
                 tx_clone.clone(),
-            ).await {
+                pasted,
+            ).await;
+            match crate::auth::failure::track(config.profile.as_deref(), Service::Drive, signed_in) {
                 Ok(_) => {
                     let _ = tx_clone.send("__DRIVE_AUTH_SUCCESS__".to_string());
                 }
+                Err(e) if e.is::<crate::auth::oauth::AuthTimedOut>() => {
+                    let _ = tx_clone.send("__DRIVE_AUTH_TIMEOUT__".to_string());
+                }
                 Err(e) => {
                     let _ = tx_clone.send(format!("__DRIVE_AUTH_ERROR__:{}", e));
                 }
             }
-        });
+        }));
     } else {
         app.set_error("Configuration not loaded - cannot authenticate".to_string());
     }
@@ -619,6 +980,11 @@ fn start_immediate_manual_processing(app: &mut App, tx: mpsc::UnboundedSender<St
     app.set_processing(true);
     app.add_progress_message("Starting manual invoice processing...".to_string());
 
+    let Some(config) = app.config.clone() else {
+        app.set_error("Configuration not loaded - cannot process".to_string());
+        return;
+    };
+
     // Get previous month date range for immediate manual processing
     let (start_date, end_date) = crate::scheduler::runner::get_previous_month_range();
 
@@ -627,7 +993,7 @@ fn start_immediate_manual_processing(app: &mut App, tx: mpsc::UnboundedSender<St
     // Spawn processing task
     let tx_clone = tx.clone();
     tokio::spawn(async move {
-        let result = jobs::run_manual_processing(start_date, end_date, &tx_clone).await;
+        let result = jobs::run_manual_processing(config, start_date, end_date, &tx_clone).await;
         // Send completion signal
         if let Err(e) = result {
             let _ = tx.send(format!("Manual processing error: {}", e));
@@ -9,6 +9,7 @@ use ratatui::{
 };
 
 use crate::app::{App, AuthStatus, FocusedPanel, PopupState};
+use crate::auth::failure::{AuthErrorKind, Service};
 use log::info;
 
 
@@ -26,7 +27,7 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
         .split(size);
 
     // Title
-    let title = draw_title();
+    let title = draw_title(app.flags.mock);
     frame.render_widget(title, chunks[0]);
 
     // Dashboard with multiple panels
@@ -44,8 +45,13 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
     }
 }
 
-fn draw_title() -> Paragraph<'static> {
-    Paragraph::new("🚀 Invoice Pilot - Interactive Mode")
+fn draw_title(mock_mode: bool) -> Paragraph<'static> {
+    let title = if mock_mode {
+        "🚀 Invoice Pilot - Interactive Mode (Mock)"
+    } else {
+        "🚀 Invoice Pilot - Interactive Mode"
+    };
+    Paragraph::new(title)
         .style(Style::default().fg(Color::Rgb(255, 255, 0)).add_modifier(Modifier::BOLD))
         .alignment(Alignment::Left)
         .block(Block::default().borders(Borders::ALL).border_style(Style::default().fg(Color::Rgb(255, 255, 0))))
@@ -72,9 +78,9 @@ fn draw_footer(app: &App) -> Paragraph<'static> {
                     "Enter: Run | R: Reset | Type: Input Dates"
                 }
             }
-            FocusedPanel::Auth => "G: Gmail Auth | D: Drive Auth | C/R: Clear All",
+            FocusedPanel::Auth => "G: Gmail Auth | D: Drive Auth | C/R: Revoke & Clear All",
             FocusedPanel::Scheduled => "Enter: Configure Schedule | S: Manual Trigger",
-            FocusedPanel::Logs => "Read-only",
+            FocusedPanel::Logs => "Enter: View Logs | F: Correct Processed Files | S: Search Archive",
         }
     );
 
@@ -220,17 +226,21 @@ fn draw_manual_panel(frame: &mut Frame, app: &mut App, area: Rect) {
         frame.render_widget(progress, chunks[1]);
     } else if app.total_processed > 0 {
         // Show results summary
-        let summary_text = format!(
+        let mut summary_text = format!(
             "✅ Complete\n\n{} processed\n{} uploaded\n{} failed\n\nFolder: {}",
             app.total_processed,
             app.total_uploaded,
             app.total_failed,
             app.drive_folder.as_deref().unwrap_or("N/A")
         );
+        if let Some(link) = &app.drive_folder_link {
+            summary_text.push_str(&format!("\n{}", link));
+        }
 
         let summary = Paragraph::new(summary_text)
             .style(Style::default().fg(Color::Green))
             .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
             .block(Block::default().borders(Borders::ALL).title("Results"));
         frame.render_widget(summary, chunks[1]);
     } else {
@@ -282,16 +292,34 @@ fn draw_auth_panel(frame: &mut Frame, app: &mut App, area: Rect) {
     frame.render_widget(panel_block, area);
 
     // Gmail status with animated progress bar
-    let gmail_widget = create_auth_progress_bar("Gmail", &app.gmail_auth_status, app.animation_counter, false);
+    let gmail_widget = create_auth_progress_bar(&account_title("Gmail", app.gmail_account.as_deref()), &app.gmail_auth_status, app.animation_counter, false);
     frame.render_widget(gmail_widget, chunks[1]); // Updated from chunks[0]
 
     // Drive status with animated progress bar
-    let drive_widget = create_auth_progress_bar("Google Drive", &app.drive_auth_status, app.animation_counter, true);
+    let drive_widget = create_auth_progress_bar(&account_title("Google Drive", app.drive_account.as_deref()), &app.drive_auth_status, app.animation_counter, true);
     frame.render_widget(drive_widget, chunks[3]); // Updated from chunks[1]
 
-    // Empty space
-    let empty = Paragraph::new("");
-    frame.render_widget(empty, chunks[4]); // Updated from chunks[2]
+    // What to do about failed sign-ins
+    let mut lines = Vec::new();
+    for (service, status, key) in [(Service::Gmail, &app.gmail_auth_status, "G"), (Service::Drive, &app.drive_auth_status, "D")] {
+        if !matches!(status, AuthStatus::Error(_) | AuthStatus::TimedOut) {
+            continue;
+        }
+        let kind = match (status, app.auth_failures.get(service.key())) {
+            (_, Some(failure)) => failure.kind,
+            (AuthStatus::Error(message), None) => AuthErrorKind::classify(message),
+            _ => AuthErrorKind::TimedOut,
+        };
+        lines.push(Line::from(Span::styled(format!("{}: {}", service.label(), kind.label()), Style::default().fg(Color::Red))));
+        lines.push(Line::from(Span::styled(kind.remedy(&format!("press {}", key)), Style::default().fg(Color::Gray))));
+    }
+    if let Some(config) = &app.config {
+        for warning in crate::auth::oauth::account_warnings(config, app.gmail_account.as_deref(), app.drive_account.as_deref()) {
+            lines.push(Line::from(Span::styled(warning, Style::default().fg(Color::Yellow))));
+        }
+    }
+    let remedies = Paragraph::new(lines).wrap(Wrap { trim: true });
+    frame.render_widget(remedies, chunks[4]);
 }
 
 fn draw_scheduled_panel(frame: &mut Frame, app: &mut App, area: Rect) {
@@ -324,8 +352,8 @@ fn draw_scheduled_panel(frame: &mut Frame, app: &mut App, area: Rect) {
 
     if auth_ok && configured && !app.scheduled_job_logged {
         if let Some(day) = app.fetch_invoices_day {
-            app.add_progress_message(format!("🔄 Automatic job scheduled: Will run on day {} of each month when triggered", day));
-            info!("Scheduled job configured: Will run on day {} of each month", day);
+            app.add_progress_message(format!("🔄 Automatic job scheduled: Will run on {} of each month when triggered", day));
+            info!("Scheduled job configured: Will run on {} of each month", day);
             app.scheduled_job_logged = true;
         }
     }
@@ -381,6 +409,8 @@ fn draw_popup(frame: &mut Frame, app: &mut App) {
         PopupState::Help => draw_help_popup(frame),
         PopupState::SetupGuide => draw_setup_guide_popup(frame),
         PopupState::DetailedLogs => draw_detailed_logs_popup(frame, app),
+        PopupState::Corrections => draw_corrections_popup(frame, app),
+        PopupState::ArchiveSearch => draw_archive_search_popup(frame, app),
         PopupState::None => {} // Should not happen
     }
 }
@@ -481,14 +511,14 @@ fn draw_schedule_config_popup(frame: &mut Frame, app: &mut App) {
     frame.render_widget(title, chunks[0]);
 
     // Instructions
-    let instructions = Paragraph::new("Enter the day of month (1-31) to run invoice processing automatically.")
+    let instructions = Paragraph::new("Enter the day of month (1-31), an Nth weekday (last friday) or an Nth business day (first business day) to run invoice processing automatically.")
         .style(Style::default().fg(Color::Gray))
         .alignment(Alignment::Center)
         .wrap(Wrap { trim: true });
     frame.render_widget(instructions, chunks[1]);
 
     // Day input
-    let day_input = Paragraph::new(format!("Day: {}", app.schedule_input))
+    let day_input = Paragraph::new(format!("Run on: {}", app.schedule_input))
         .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
         .alignment(Alignment::Center);
     frame.render_widget(day_input, chunks[2]);
@@ -584,7 +614,8 @@ SHORTCUTS:
 • Manual Panel: Enter to run processing, R to reset
 • Auth Panel: G for Gmail auth, D for Drive auth, R to reset
 • Scheduled Panel: S for manual trigger, Enter to configure
-• Log Panel: Read-only activity feed"#;
+• Log Panel: Enter to view all logs, F to mark processed files as not an invoice or wrong vendor/bank, S to search archived documents by their contents
+• Search/Correction Lists: O to open the selected file in the browser, L to copy its link; P marks a search result paid or unpaid"#;
 
     let content = Paragraph::new(help_text)
         .style(Style::default().fg(Color::White))
@@ -661,6 +692,169 @@ fn draw_detailed_logs_popup(frame: &mut Frame, app: &App) {
     frame.render_widget(help, chunks[2]);
 }
 
+fn draw_corrections_popup(frame: &mut Frame, app: &App) {
+    let area = centered_rect(70, 60, frame.area());
+    create_colored_background(frame, area, Color::Rgb(80, 40, 100)); // Dark purple background
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(2), // Title
+            Constraint::Min(1),    // Processed files
+            Constraint::Length(2), // Help text
+        ])
+        .split(area);
+
+    let title = Paragraph::new("✎ Correct Processed Files")
+        .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+        .alignment(Alignment::Center);
+    frame.render_widget(title, chunks[0]);
+
+    let list_area = Rect {
+        x: chunks[1].x + 2,
+        y: chunks[1].y,
+        width: chunks[1].width.saturating_sub(4),
+        height: chunks[1].height,
+    };
+
+    if app.processed_files.is_empty() {
+        let empty = Paragraph::new("No processed files recorded yet - files appear here after a run uploads them.")
+            .style(Style::default().fg(Color::Gray))
+            .wrap(Wrap { trim: true });
+        frame.render_widget(empty, list_area);
+    } else {
+        // Keep the selected file visible
+        let height = list_area.height as usize;
+        let first = app.processed_selected.saturating_sub(height.saturating_sub(1));
+        let lines: Vec<Line> = app.processed_files.iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .map(|(idx, file)| {
+                let text = format!("{} {}  ({} - {})", if idx == app.processed_selected { "▶" } else { " " }, file.filename, file.folder, file.sender);
+                let style = if idx == app.processed_selected {
+                    Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(Color::White)
+                };
+                Line::from(Span::styled(text, style))
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines), list_area);
+    }
+
+    let help = match &app.correction_folder_input {
+        Some(folder) => format!("Correct folder: {}▏ | Enter Save | Esc Close", folder),
+        None => "↑/↓ Select | N Not an invoice | W Wrong vendor/bank (set folder) | O Open | L Copy link | Esc Close".to_string(),
+    };
+    let help = Paragraph::new(help)
+        .style(Style::default().fg(Color::Gray))
+        .alignment(Alignment::Center);
+    frame.render_widget(help, chunks[2]);
+}
+
+fn draw_archive_search_popup(frame: &mut Frame, app: &App) {
+    let area = centered_rect(80, 70, frame.area());
+    create_colored_background(frame, area, Color::Rgb(80, 40, 100)); // Dark purple background
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(2), // Title
+            Constraint::Length(2), // Query
+            Constraint::Min(1),    // Results
+            Constraint::Length(3), // Snippet of the selected result
+            Constraint::Length(2), // Help text
+        ])
+        .split(area);
+
+    let title = Paragraph::new("🔎 Search Archived Documents")
+        .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+        .alignment(Alignment::Center);
+    frame.render_widget(title, chunks[0]);
+
+    let inset = |chunk: Rect| Rect {
+        x: chunk.x + 2,
+        y: chunk.y,
+        width: chunk.width.saturating_sub(4),
+        height: chunk.height,
+    };
+
+    let cursor = if app.archive_editing { "▏" } else { "" };
+    let query = Paragraph::new(format!("Search: {}{}", app.archive_query, cursor))
+        .style(Style::default().fg(Color::Yellow));
+    frame.render_widget(query, inset(chunks[1]));
+
+    let list_area = inset(chunks[2]);
+    match &app.archive_results {
+        None => {
+            let hint = Paragraph::new("Type words from the documents (\"domain renewal\", an IBAN, a project name) and press Enter.")
+                .style(Style::default().fg(Color::Gray))
+                .wrap(Wrap { trim: true });
+            frame.render_widget(hint, list_area);
+        }
+        Some(results) if results.is_empty() => {
+            let empty = Paragraph::new("No archived documents match.")
+                .style(Style::default().fg(Color::Gray));
+            frame.render_widget(empty, list_area);
+        }
+        Some(results) => {
+            // Keep the selected document visible
+            let height = list_area.height as usize;
+            let first = app.archive_selected.saturating_sub(height.saturating_sub(1));
+            let lines: Vec<Line> = results.iter()
+                .enumerate()
+                .skip(first)
+                .take(height)
+                .map(|(idx, document)| {
+                    let month = document.billing_month.map(|m| m.format("%Y-%m").to_string()).unwrap_or_else(|| "-------".to_string());
+                    let payment = match (document.paid, document.due_date) {
+                        (true, _) => "  paid".to_string(),
+                        (false, Some(due)) => format!("  due {}", due),
+                        (false, None) => String::new(),
+                    };
+                    let text = format!(
+                        "{} {}  {}  {}  {}{}",
+                        if idx == app.archive_selected { "▶" } else { " " },
+                        month,
+                        document.vendor,
+                        document.display_amount(),
+                        document.filename,
+                        payment
+                    );
+                    let style = if idx == app.archive_selected {
+                        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default().fg(Color::White)
+                    };
+                    Line::from(Span::styled(text, style))
+                })
+                .collect();
+            frame.render_widget(Paragraph::new(lines), list_area);
+        }
+    }
+
+    let snippet = app.archive_results.as_ref()
+        .and_then(|results| results.get(app.archive_selected))
+        .and_then(|document| document.snippet.as_deref())
+        .map(|snippet| format!("…{}…", snippet))
+        .unwrap_or_default();
+    let snippet = Paragraph::new(snippet)
+        .style(Style::default().fg(Color::Gray))
+        .wrap(Wrap { trim: true });
+    frame.render_widget(snippet, inset(chunks[3]));
+
+    let help = if app.archive_editing {
+        "Type to edit | Enter Search | ↓ Results | Esc Close"
+    } else {
+        "↑/↓ Select | O Open in browser | L Copy link | P Paid/unpaid | / Edit search | Esc Close"
+    };
+    let help = Paragraph::new(help)
+        .style(Style::default().fg(Color::Gray))
+        .alignment(Alignment::Center);
+    frame.render_widget(help, chunks[4]);
+}
+
 fn draw_setup_guide_popup(frame: &mut Frame) {
     let area = centered_rect(85, 70, frame.area());
     create_colored_background(frame, area, Color::Rgb(150, 0, 150)); // Dark Magenta
@@ -700,6 +894,10 @@ GOOGLE_DRIVE_CLIENT_ID=your-drive-client-id.apps.googleusercontent.com
 GOOGLE_DRIVE_CLIENT_SECRET=your-drive-client-secret
 GOOGLE_DRIVE_FOLDER_LOCATION=billing/all-expenses/2025
 
+The Drive client ID and secret may be left out to reuse the Gmail OAuth client.
+Or, in place of the four client variables, the downloaded OAuth client file:
+GOOGLE_CLIENT_SECRET_FILE=/path/to/client_secret.json
+
 🔗 GOOGLE CLOUD CONSOLE SETUP:
 1. Go to https://console.cloud.google.com/
 2. Create a new project or select existing one
@@ -741,7 +939,7 @@ fn draw_specific_auth_url_popup(frame: &mut Frame, app: &mut App, service_name:
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(3), // Title
-            Constraint::Length(8), // Content (URL or success message)
+            Constraint::Min(8),    // Content (URL, pasted address or success message)
             Constraint::Length(3), // Instructions
             Constraint::Length(3), // Controls
         ])
@@ -762,6 +960,9 @@ fn draw_specific_auth_url_popup(frame: &mut Frame, app: &mut App, service_name:
     // Content display
     let content_text = if app.auth_popup_success {
         format!("{} authentication completed successfully!\n\nYour tokens are cached and ready to use.", service_name)
+    } else if let (Some(url), Some(pasted)) = (&app.auth_url, &app.auth_paste_input) {
+        let error = app.error_message.as_ref().map(|error| format!("\n\n{}", error)).unwrap_or_default();
+        format!("{} Authorization URL:\n\n{}\n\nPaste the address the browser was sent to:\n> {}▏{}", service_name, url, pasted, error)
     } else if let Some(url) = &app.auth_url {
         format!("{} Authorization URL:\n\n{}", service_name, url)
     } else {
@@ -777,7 +978,9 @@ fn draw_specific_auth_url_popup(frame: &mut Frame, app: &mut App, service_name:
 
     // Instructions
     let instructions = if app.auth_popup_success {
-        format!("Your {} authentication is active.\nYou can now close this popup or clear tokens if needed.", service_name)
+        format!("Your {} authentication is active.\nYou can now close this popup or clear the local tokens if needed\n(the grant stays with Google; C/R in the auth panel revokes it).", service_name)
+    } else if app.auth_paste_input.is_some() {
+        format!("Open the URL in a browser on any machine and allow {} access.\nThe browser then lands on a localhost error page: copy the whole address from its address bar.", service_name)
     } else {
         format!("INSTRUCTIONS:\n- Copy the URL above\n- Open it in your web browser\n- Complete the Google OAuth flow for {}\n- Return here when done\n- The app will detect completion automatically", service_name)
     };
@@ -790,9 +993,11 @@ fn draw_specific_auth_url_popup(frame: &mut Frame, app: &mut App, service_name:
 
     // Controls
     let controls_text = if app.auth_popup_success {
-        "Any Key: Close | C: Clear Tokens"
+        "Any Key: Close | C: Clear Local Tokens"
+    } else if app.auth_paste_input.is_some() {
+        "Enter: Submit | Esc: Cancel"
     } else if app.auth_url.is_some() {
-        "Esc: Close | Complete authorization in browser, then return here"
+        "Esc: Cancel | Complete authorization in browser, then return here"
     } else {
         "Esc: Cancel | Waiting for authorization URL..."
     };
@@ -863,10 +1068,15 @@ fn create_calendar_lines(app: &App, area: Rect) -> Vec<Line<'static>> {
     }.pred_opt().unwrap();
 
     let first_weekday = first_of_month.weekday().num_days_from_sunday();
+    let holidays = app.config.as_ref().map(|c| c.holidays.as_slice()).unwrap_or_default();
+    let scheduled_date = app.fetch_invoices_day.and_then(|schedule| schedule.date_in(current_year, current_month, holidays));
     let mut day = 1;
 
+    // Next and last run go below the calendar, after a blank line
+    let status_lines = schedule_status_lines(app, panel_width, left_padding, bg_color);
+
     // Calculate available space for weeks with padding between them
-    let available_height = area.height.saturating_sub(4) as usize;
+    let available_height = area.height.saturating_sub(4 + status_lines.len() as u16 + 1) as usize;
     let num_weeks = 6; // Standard calendar weeks
     let spacing_per_week = if num_weeks > 0 { available_height / num_weeks } else { 1 };
 
@@ -884,7 +1094,7 @@ fn create_calendar_lines(app: &App, area: Rect) -> Vec<Line<'static>> {
             if (week_count == 0 && weekday < first_weekday) || day > last_of_month.day() {
                 week_spans.push(Span::styled(format!("{:<width$}", "", width = col_width), Style::default().bg(bg_color)));
             } else {
-                let is_scheduled = app.fetch_invoices_day.map_or(false, |d| d == day as u32);
+                let is_scheduled = scheduled_date.is_some_and(|date| date.day() == day);
                 let is_today = now.day() == day && now.month() == current_month && now.year() == current_year;
 
                 let style = if is_scheduled && is_today {
@@ -929,9 +1139,68 @@ fn create_calendar_lines(app: &App, area: Rect) -> Vec<Line<'static>> {
         lines.push(Line::from(blank_line));
     }
 
+    lines.push(Line::from(vec![Span::styled(" ".repeat(panel_width), Style::default().bg(bg_color))]));
+    lines.extend(status_lines);
     lines
 }
 
+/// "Next run: Mar 2, 09:00 (in 6d 4h)" and the outcome of the last scheduled run
+fn schedule_status_lines(app: &App, panel_width: usize, left_padding: usize, bg_color: Color) -> Vec<Line<'static>> {
+    use crate::scheduler::runner;
+
+    let now = chrono::Local::now().naive_local();
+    let schedule_time = app.config.as_ref().map(|c| c.schedule_time).unwrap_or_default();
+    let holidays = app.config.as_ref().map(|c| c.holidays.as_slice()).unwrap_or_default();
+    let next = match app.fetch_invoices_day.and_then(|schedule| runner::next_run(&schedule, holidays, schedule_time, now)) {
+        Some(next) => (
+            format!("Next run: {} (in {})", next.format("%b %-d, %H:%M"), runner::format_countdown(next - now)),
+            Color::Yellow,
+        ),
+        None => ("Next run: not scheduled (press Enter to set a day)".to_string(), Color::Gray),
+    };
+
+    let last = match (&app.last_scheduled_run, &app.db_pool) {
+        (Some(run), _) => {
+            let mut started = run.started_at.with_timezone(&chrono::Local).format("%b %-d, %H:%M").to_string();
+            if run.attempt > 1 {
+                started.push_str(&format!(" (attempt {})", run.attempt));
+            }
+            match &run.error {
+                Some(error) => (format!("Last run: {} ✗ {}", started, error), Color::Red),
+                None => (
+                    format!("Last run: {} ✓ {} uploaded, {} skipped, {} failed", started, run.uploaded, run.skipped, run.failed),
+                    if run.failed > 0 { Color::Yellow } else { Color::Green },
+                ),
+            }
+        }
+        (None, Some(_)) => ("Last run: none recorded yet".to_string(), Color::Gray),
+        (None, None) => ("Last run: run history needs DATABASE_URL".to_string(), Color::Gray),
+    };
+
+    let text_width = panel_width.saturating_sub(left_padding);
+    [next, last]
+        .into_iter()
+        .map(|(text, color)| {
+            let text: String = text.chars().take(text_width).collect();
+            let fill = text_width.saturating_sub(text.chars().count());
+            Line::from(vec![
+                Span::styled(" ".repeat(left_padding), Style::default().bg(bg_color)),
+                Span::styled(text, Style::default().fg(color).bg(bg_color)),
+                Span::styled(" ".repeat(fill), Style::default().bg(bg_color)),
+            ])
+        })
+        .collect()
+}
+
+/// "Gmail · me@gmail.com" once the signed-in account is known, so a mailbox and a Drive in
+/// different accounts can be told apart
+fn account_title(service: &str, account: Option<&str>) -> String {
+    match account {
+        Some(account) => format!("{} · {}", service, account),
+        None => service.to_string(),
+    }
+}
+
 fn create_auth_progress_bar(title: &str, status: &AuthStatus, animation_counter: u32, is_drive: bool) -> Paragraph<'static> {
     let border_color = match status {
         AuthStatus::Authenticated => Color::Green,
@@ -947,6 +1216,7 @@ fn create_auth_progress_bar(title: &str, status: &AuthStatus, animation_counter:
             ("Authenticating...", Color::Yellow, progress)
         }
         AuthStatus::Authenticated => ("Authenticated", Color::Green, 1.0),
+        AuthStatus::TimedOut => ("Timed Out", Color::Yellow, 0.0),
         AuthStatus::Error(_) => ("Error", Color::Red, 0.0),
     };
 
@@ -0,0 +1,203 @@
+//! Web dashboard for the `serve` command: run history, archive search and a "run now" form.
+//!
+//! The page is a single HTML file baked into the binary; everything else is a small JSON API
+//! under `/api` plus the MCP endpoint at `/mcp`, guarded by DASHBOARD_TOKEN when it is set.
+
+use crate::config::env::Config;
+use crate::db::{self, DbPool, HeartbeatRecord, InvoiceDocument, InvoiceQuery, RunRecord};
+use crate::interfaces::mcp::McpServer;
+use crate::process::heartbeat::{self, Health};
+use crate::process::tracker::{LiveRun, RunTracker};
+use crate::scheduler::runner;
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const INDEX_HTML: &str = include_str!("index.html");
+
+/// How many past runs the history shows
+const HISTORY_LIMIT: i64 = 25;
+
+struct Dashboard {
+    config: Config,
+    pool: Option<DbPool>,
+    tracker: RunTracker,
+    /// Shares the tracker, so a run started by an assistant shows up here and vice versa
+    mcp: McpServer,
+}
+
+#[derive(Serialize)]
+struct RunsResponse {
+    current: Option<LiveRun>,
+    history: Vec<RunRecord>,
+    /// Runs in progress anywhere (scheduled, CLI, other servers), from their heartbeats
+    live: Vec<LiveHeartbeat>,
+    /// Why the history is empty or incomplete
+    notice: Option<String>,
+    /// Range a "run now" defaults to (the previous month, like scheduled runs)
+    default_start: NaiveDate,
+    default_end: NaiveDate,
+    /// Read-only mode (READ_ONLY): the run button is hidden
+    read_only: bool,
+}
+
+#[derive(Serialize)]
+struct LiveHeartbeat {
+    #[serde(flatten)]
+    heartbeat: HeartbeatRecord,
+    health: Health,
+    /// Stage, count and latest line
+    progress: String,
+}
+
+#[derive(Deserialize)]
+struct RunRequest {
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    q: Option<String>,
+    vendor: Option<String>,
+    month: Option<String>,
+    limit: Option<i64>,
+}
+
+type ApiError = (StatusCode, String);
+
+/// Serve the dashboard until the process is stopped
+pub async fn serve(config: Config, bind: &str) -> Result<()> {
+    let pool = db::connect_optional("Dashboard run history and archive search").await;
+    if pool.is_none() {
+        println!("ℹ No database (DATABASE_URL): run history and archive search are unavailable");
+    }
+
+    let unguarded = config.dashboard_token.is_none();
+    let tracker = RunTracker::default();
+    let state = Arc::new(Dashboard {
+        mcp: McpServer::new(config.clone(), pool.clone(), tracker.clone()),
+        config,
+        pool,
+        tracker,
+    });
+    let api = Router::new()
+        .route("/api/runs", get(runs).post(start_run))
+        .route("/api/search", get(search))
+        .route("/mcp", post(mcp))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token));
+    let app = Router::new()
+        .route("/", get(|| async { Html(INDEX_HTML) }))
+        .merge(api)
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(bind)
+        .await
+        .with_context(|| format!("Failed to listen on {}", bind))?;
+    let address = listener.local_addr()?;
+    if !address.ip().is_loopback() && unguarded {
+        println!("⚠ DASHBOARD_TOKEN is not set: anyone who can reach {} can start runs", address);
+    }
+    println!("🌐 Dashboard running at http://{}", address);
+    axum::serve(listener, app).await.context("Dashboard server failed")
+}
+
+/// Accept the token as `Authorization: Bearer <token>` or `?token=<token>`
+async fn require_token(State(state): State<Arc<Dashboard>>, request: Request, next: Next) -> Response {
+    let Some(expected) = &state.config.dashboard_token else {
+        return next.run(request).await;
+    };
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string);
+    let query = request.uri().query().and_then(|query| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == "token")
+            .map(|(_, value)| value.into_owned())
+    });
+    if bearer.or(query).as_deref() == Some(expected.as_str()) {
+        next.run(request).await
+    } else {
+        (StatusCode::UNAUTHORIZED, "Missing or wrong dashboard token").into_response()
+    }
+}
+
+async fn runs(State(state): State<Arc<Dashboard>>) -> Json<RunsResponse> {
+    let current = state.tracker.snapshot();
+    let profile = state.config.profile.as_deref().unwrap_or_default();
+    let (history, live, notice) = match &state.pool {
+        Some(pool) => match (db::load_runs(pool, profile, HISTORY_LIMIT).await, db::live_heartbeats(pool, profile).await) {
+            (Ok(history), Ok(live)) => (history, live, None),
+            (Err(e), _) | (_, Err(e)) => (Vec::new(), Vec::new(), Some(format!("{:#}", e))),
+        },
+        None => (Vec::new(), Vec::new(), Some("Run history needs the database (set DATABASE_URL)".to_string())),
+    };
+    let stale = heartbeat::stale_limit(&state.config);
+    let now = chrono::Utc::now();
+    let live = live
+        .into_iter()
+        .map(|heartbeat| LiveHeartbeat {
+            health: stale.map_or(Health::Alive, |stale| Health::of(&heartbeat, stale, now)),
+            progress: heartbeat::describe(&heartbeat),
+            heartbeat,
+        })
+        .collect();
+    let (default_start, default_end) = runner::get_previous_month_range();
+    Json(RunsResponse { current, history, live, notice, default_start, default_end, read_only: state.config.read_only })
+}
+
+/// Start a run in the background; only one dashboard run at a time
+async fn start_run(State(state): State<Arc<Dashboard>>, Json(request): Json<RunRequest>) -> Result<StatusCode, ApiError> {
+    if let Err(e) = state.config.ensure_writable("Starting a run") {
+        return Err((StatusCode::FORBIDDEN, e.to_string()));
+    }
+    if request.end_date < request.start_date {
+        return Err((StatusCode::BAD_REQUEST, "End date must be after start date".to_string()));
+    }
+    if !state.tracker.start(state.config.clone(), request.start_date, request.end_date) {
+        return Err((StatusCode::CONFLICT, "A run is already in progress".to_string()));
+    }
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn search(State(state): State<Arc<Dashboard>>, Query(params): Query<SearchParams>) -> Result<Json<Vec<InvoiceDocument>>, ApiError> {
+    let Some(pool) = &state.pool else {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, "Archive search needs the database (set DATABASE_URL)".to_string()));
+    };
+    let non_empty = |value: Option<String>| value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    let billing_month = non_empty(params.month)
+        .map(|month| runner::parse_month(&month))
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let query = InvoiceQuery {
+        vendor: non_empty(params.vendor),
+        billing_month,
+        text: non_empty(params.q),
+        limit: params.limit.unwrap_or(50).clamp(1, 200),
+        ..Default::default()
+    };
+    db::search_invoice_documents(pool, state.config.profile.as_deref().unwrap_or_default(), &query)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))
+}
+
+/// MCP over HTTP: one JSON-RPC message per POST, answered with JSON (202 for notifications)
+async fn mcp(State(state): State<Arc<Dashboard>>, body: String) -> Response {
+    match state.mcp.handle_message(&body).await {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
@@ -0,0 +1,402 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use super::{MailMessage, MailSource};
+use super::vendors::VendorAliases;
+
+/// Longest filename kept, in bytes: filesystems stop at 255, and the upload and encryption
+/// suffixes (`.part`, `.age`) need room after it
+const MAX_FILENAME_BYTES: usize = 200;
+
+#[derive(Debug, Clone)]
+pub struct InvoiceAttachment {
+    pub filename: String,
+    pub data: Vec<u8>,
+    pub message_id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct InvoiceAttachmentWithBank {
+    pub attachment: InvoiceAttachment,
+    pub bank_name: Option<String>,
+    /// Sender's filename prefix (canonical slug for aliased vendors)
+    pub vendor: String,
+    /// Document text, once extracted for checks that read the content
+    pub text: Option<String>,
+    /// Category set by a sender rule (rules.toml); the folder is the category otherwise
+    pub category: Option<String>,
+}
+
+/// Filename prefix and institution folder for a message's attachments, from the sender and the
+/// bank detected in its headers; an aliased vendor wins for both
+pub fn message_sender(message: &MailMessage, vendors: &mut VendorAliases) -> (String, Option<String>) {
+    match vendors.resolve(&message.from) {
+        Some(vendor) => (vendor.slug(), Some(vendor.name.clone())),
+        None => (
+            sender_prefix(&message.from),
+            detect_bank_name(message).map(|bank| vendors.canonical(&bank).map(str::to_string).unwrap_or(bank)),
+        ),
+    }
+}
+
+/// Download all attachments of an already fetched message, named and filed as `message_sender` says
+pub async fn download_message_attachments(
+    source: &dyn MailSource,
+    message: &MailMessage,
+    (sender_prefix, bank_name): &(String, Option<String>),
+) -> Result<Vec<InvoiceAttachmentWithBank>> {
+    let message_id = message.id.as_str();
+
+    // Skip silently if no attachments
+
+    // Download attachment data
+    let mut result = Vec::new();
+    for attachment in &message.attachments {
+        match super::download::download_with_retries(source, message_id, attachment).await {
+            Ok(data) => {
+                // Prepend sender name to filename
+                let new_filename = if !sender_prefix.is_empty() {
+                    format!("{}-{}", sender_prefix, attachment.filename)
+                } else {
+                    attachment.filename.clone()
+                };
+                let new_filename = fit_filename(&new_filename, &data);
+
+                let attachment_with_bank = InvoiceAttachmentWithBank {
+                    attachment: InvoiceAttachment {
+                        filename: new_filename.clone(),
+                        data,
+                        message_id: message_id.to_string(),
+                    },
+                    bank_name: bank_name.clone(),
+                    vendor: sender_prefix.clone(),
+                    text: None,
+                    category: None,
+                };
+
+                result.push(attachment_with_bank);
+            }
+            Err(e) => {
+                log::warn!("Skipping an attachment of message {}: {:#}", message_id, e);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Drop attachments whose filename matches none of the patterns (case-insensitive substrings;
+/// `{date}` matches names containing a date such as 2025-03 or 20250301).
+/// Returns the names that were dropped; an empty pattern list keeps everything.
+pub fn retain_matching_names(message: &mut MailMessage, patterns: &[String]) -> Vec<String> {
+    if patterns.is_empty() {
+        return Vec::new();
+    }
+
+    let mut dropped = Vec::new();
+    message.attachments.retain(|attachment| {
+        let keep = name_matches(&attachment.filename, patterns);
+        if !keep {
+            dropped.push(attachment.filename.clone());
+        }
+        keep
+    });
+    dropped
+}
+
+fn name_matches(filename: &str, patterns: &[String]) -> bool {
+    let name = filename.to_lowercase();
+    patterns.iter().any(|pattern| match pattern.as_str() {
+        "{date}" => contains_date(&name),
+        pattern => name.contains(pattern),
+    })
+}
+
+/// Whether a filename contains something date-like: 20250301, 2025-03(-01), 03.2025, 01_03_2025
+fn contains_date(name: &str) -> bool {
+    let runs: Vec<&str> = name.split(|c: char| !c.is_ascii_digit()).filter(|r| !r.is_empty()).collect();
+    let is_year = |r: &str| r.len() == 4 && (r.starts_with("19") || r.starts_with("20"));
+    let is_month = |r: &str| r.len() <= 2 && r.parse::<u8>().is_ok_and(|m| (1..=12).contains(&m));
+
+    let compact = runs.iter().any(|r| r.len() == 8 && is_year(&r[..4]) && is_month(&r[4..6]));
+    let separated = runs.windows(2).any(|pair| {
+        (is_year(pair[0]) && is_month(pair[1])) || (is_month(pair[0]) && is_year(pair[1]))
+    });
+    compact || separated
+}
+
+/// Extract sender name from a From header value
+fn extract_sender_name(from: &str) -> String {
+    // Extract name from "Name <email@example.com>" format
+    // Try to extract the name part before the email
+    if let Some(name_end) = from.find('<') {
+        let name = from[..name_end].trim();
+        if !name.is_empty() {
+            // Remove quotes if present
+            return name.trim_matches('"').to_string();
+        }
+    }
+
+    // If no angle bracket, try to extract from email
+    if let Some(at_pos) = from.find('@') {
+        return from[..at_pos].to_string();
+    }
+
+    from.to_string()
+}
+
+/// Filename prefix for a sender: the sanitized display name, or the sender's domain
+/// when nothing usable is left of the name
+fn sender_prefix(from: &str) -> String {
+    let prefix = sanitize_sender_name(&extract_sender_name(from));
+    if !prefix.is_empty() {
+        return prefix;
+    }
+
+    from.rsplit_once('@')
+        .map(|(_, domain)| super::phishing::domain_root(domain.trim_end_matches(['>', '"', ' '])))
+        .map(|root| sanitize_sender_name(&root))
+        .unwrap_or_default()
+}
+
+/// Sanitize sender name for use in filename, transliterating to ASCII
+/// "LangFuse GmbH" -> "langfuse-gmbh", "Müller & Søn" -> "muller-son"
+pub(super) fn sanitize_sender_name(name: &str) -> String {
+    deunicode::deunicode_with_tofu(name, "")
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c
+            } else if c.is_whitespace() {
+                '-'
+            } else {
+                // Remove special characters
+                '\0'
+            }
+        })
+        .filter(|&c| c != '\0')
+        .collect::<String>()
+        .split('-')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Shorten an overlong filename, keeping its extension and adding the first characters of the
+/// content's SHA-256 so shortened names that started alike stay apart
+/// ("<180 characters>.pdf" -> "<170 characters>-1a2b3c4d.pdf")
+pub fn fit_filename(filename: &str, data: &[u8]) -> String {
+    if filename.len() <= MAX_FILENAME_BYTES {
+        return filename.to_string();
+    }
+    let (stem, ext) = match filename.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.len() <= 10 => (stem, format!(".{}", ext)),
+        _ => (filename, String::new()),
+    };
+    let hash = &crate::drive::upload::sha256_hex(data)[..8];
+    let mut keep = MAX_FILENAME_BYTES - hash.len() - 1 - ext.len();
+    while !stem.is_char_boundary(keep) {
+        keep -= 1;
+    }
+    format!("{}-{}{}", stem[..keep].trim_end_matches(['-', '_', ' ', '.']), hash, ext)
+}
+
+/// Save attachment to a subdirectory of the temp directory for its message, so same-named
+/// attachments of different emails don't overwrite each other, under its name shortened to what
+/// filesystems take
+pub fn save_attachment_to_temp(attachment: &InvoiceAttachment, temp_dir: &Path) -> Result<PathBuf> {
+    // Message ids can hold characters paths can't (`<...@...>` Message-IDs)
+    let message_dir = temp_dir.join(&crate::drive::upload::sha256_hex(attachment.message_id.as_bytes())[..16]);
+    std::fs::create_dir_all(&message_dir)
+        .context("Failed to create temp directory")?;
+
+    let file_path = message_dir.join(fit_filename(&attachment.filename, &attachment.data));
+    std::fs::write(&file_path, &attachment.data)
+        .context("Failed to write attachment to temp file")?;
+
+    Ok(file_path)
+}
+
+/// Detect bank name from message headers and content
+fn detect_bank_name(message: &MailMessage) -> Option<String> {
+    let search_text = extract_search_text(message);
+    detect_bank_from_text(&search_text).map(|name| {
+        // Convert to title case for consistent folder naming
+        name.split_whitespace()
+            .map(|word| {
+                if word.len() > 0 {
+                    format!("{}{}", word.chars().next().unwrap().to_uppercase(), word[1..].to_lowercase())
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    })
+}
+
+/// Extract searchable text from message (headers + body)
+fn extract_search_text(message: &MailMessage) -> String {
+    let mut text = String::new();
+
+    // Extract from headers
+    text.push_str(&message.from);
+    text.push(' ');
+    text.push_str(&message.subject);
+    text.push(' ');
+
+    // Decoded text of the body parts (see gmail::attachment::body_text)
+    text.push_str(&message.body);
+
+    text.to_lowercase()
+}
+
+/// Detect bank name from text using predefined patterns
+fn detect_bank_from_text(text: &str) -> Option<String> {
+    // Common European digital and physical banks (including those without 'bank' in name)
+    let bank_patterns = vec![
+        // Digital banks
+        "wise", "revolut", "nubank", "bunq", "monzo", "starling", "chime", "venmo",
+        "paypal", "wise", "transferwise", "wise.com", "revolut.com", "nubank.com.br",
+        
+        // Traditional banks with 'bank' in name
+        "santander", "bbva", "caixabank", "ing", "deutsche bank", "commerzbank",
+        "hsbc", "barclays", "lloyds", "rbs", "natwest", "barclays", "standard chartered",
+        "bnp paribas", "societe generale", "credit agricole", "dexia", "fortis",
+        "kbc", "rabobank", "abn amro", "ing", "asn", "triodos", "moneco",
+        
+        // Spanish banks
+        "banco santander", "bbva", "caixa bank", "la caixa", "bankinter", "sabadell",
+        "popular", "galicia", "santanderrio", "macro", "hipotecario", "provincia",
+        
+        // Portuguese banks
+        "bcp", "bpi", "caixa geral de depósitos", "millennium bcp", "banco espírito santo",
+        
+        // Italian banks
+        "intesa sanpaolo", "unicredit", "banco popolare", "monte dei paschi", "mediolanum",
+        
+        // French banks
+        "societe generale", "bnp paribas", "credit agricole", "lcl", "bpce", "caisse d'epargne",
+        
+        // German banks
+        "deutsche bank", "commerzbank", "hypovereinsbank", "sparkasse", "volksbank",
+        
+        // Dutch banks
+        "ing", "rabobank", "abn amro", "asn bank", "triodos bank", "moneco bank",
+        
+        // Polish banks
+        "pkobp", "ing", "millennium", "bnp paribas", "santander", "bank millennium",
+        
+        // Czech banks
+        "csob", "kb", "unicredit", "raiffeisen", "moneta", "fio",
+        
+        // Austrian banks
+        "erste bank", "raiffeisen", "bank austria", "volksbank", "sparkasse",
+        
+        // Swiss banks
+        "ubs", "credit suisse", "zkb", "ubs", "postfinance", "raiffeisen",
+        
+        // Nordic banks
+        "nordea", "dnb", "handelsbanken", "seb", "swedbank", "sampo",
+        
+        // Other European digital services
+        "wise", "transferwise", "revolut", "n26", "bunq", "monzo", "starling",
+        "tidal", "october", "bunq", "mollie", "adyen", "stripe", "paypal",
+        
+        // Brokerages and trading platforms
+        "interactive brokers", "ibkr", "charles schwab", "etrade", "td ameritrade", " fidelity",
+        "robinhood", "webull", "coinbase", "binance", "kraken", "coinbase pro", "binance us",
+        
+        // Banks with 'banco' in name (Spanish/Portuguese)
+        "banco", "banco santander", "banco do brasil", "banco itaú", "banco bradesco",
+        
+        // Generic bank indicators
+        "bank", "banco", "financial", "fintech", "fiscal", "tributary",
+    ];
+    
+    for pattern in bank_patterns {
+        if contains_word(text, &pattern.trim().to_lowercase()) {
+            return Some(pattern.trim().to_string());
+        }
+    }
+    
+    None
+}
+
+/// Whether `pattern` occurs in `text` as whole words ("ing" matches "ING Bank", not "greetings")
+fn contains_word(text: &str, pattern: &str) -> bool {
+    text.match_indices(pattern).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + pattern.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mail::AttachmentRef;
+
+    #[test]
+    fn test_retain_matching_names() {
+        let patterns = vec!["invoice".to_string(), "statement".to_string(), "{date}".to_string()];
+        let mut message = MailMessage {
+            attachments: ["Invoice-42.pdf", "spring-brochure.pdf", "account-statement.pdf", "R_2025-03.pdf", "scan_20250301.pdf", "logo.png", "page-12-3.pdf"]
+                .iter()
+                .map(|name| AttachmentRef { filename: name.to_string(), ..Default::default() })
+                .collect(),
+            ..Default::default()
+        };
+
+        let dropped = retain_matching_names(&mut message, &patterns);
+        assert_eq!(dropped, vec!["spring-brochure.pdf", "logo.png", "page-12-3.pdf"]);
+        assert_eq!(message.attachments.len(), 4);
+        assert!(retain_matching_names(&mut message, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_fit_filename() {
+        assert_eq!(fit_filename("aws-invoice.pdf", b"%PDF"), "aws-invoice.pdf");
+
+        let long = format!("vendor-{}.pdf", "ä".repeat(150));
+        let fitted = fit_filename(&long, b"%PDF");
+        assert!(fitted.len() <= MAX_FILENAME_BYTES);
+        assert!(fitted.starts_with("vendor-ää") && fitted.ends_with(&format!("-{}.pdf", &crate::drive::upload::sha256_hex(b"%PDF")[..8])));
+        assert_ne!(fit_filename(&long, b"other"), fitted);
+    }
+
+    #[test]
+    fn test_save_attachment_to_temp_per_message() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let attachment = |message_id: &str, data: &[u8]| InvoiceAttachment { filename: "invoice.pdf".to_string(), data: data.to_vec(), message_id: message_id.to_string() };
+
+        let first = save_attachment_to_temp(&attachment("<a@mail.example>", b"first"), dir).unwrap();
+        let second = save_attachment_to_temp(&attachment("<b@mail.example>", b"second"), dir).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(std::fs::read(&first).unwrap(), b"first");
+        assert_eq!(second.file_name().unwrap(), "invoice.pdf");
+    }
+
+    #[test]
+    fn test_sender_prefix_transliterates() {
+        assert_eq!(sanitize_sender_name("LangFuse GmbH"), "langfuse-gmbh");
+        assert_eq!(sanitize_sender_name("Müller GmbH"), "muller-gmbh");
+        assert_eq!(sanitize_sender_name("Café Ñandú & Søn"), "cafe-nandu-son");
+        assert_eq!(sanitize_sender_name("Straße AG"), "strasse-ag");
+
+        let japanese = sender_prefix("株式会社 <billing@example.co.jp>");
+        assert!(!japanese.is_empty() && japanese.is_ascii());
+
+        // Nothing usable in the display name: fall back to the sender domain
+        assert_eq!(sender_prefix("\"***\" <billing@vendor.co.jp>"), "vendor");
+    }
+
+    #[test]
+    fn test_detect_bank_from_text_matches_whole_words() {
+        assert_eq!(detect_bank_from_text("greetings from amazon web services"), None);
+        assert_eq!(detect_bank_from_text("otherwise unrelated"), None);
+        assert_eq!(detect_bank_from_text("your ing bank statement"), Some("ing".to_string()));
+        assert_eq!(detect_bank_from_text("no-reply@revolut.com"), Some("revolut".to_string()));
+    }
+}
@@ -0,0 +1,117 @@
+use super::search::{Exclusions, IngestQueue};
+use super::{MailMessage, MailSource};
+use crate::db::{self, DbPool};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use log::warn;
+
+/// Wraps a mail source so message metadata (headers and attachment listings) is
+/// read from and written to the database instead of being fetched on every run
+pub struct CachedSource {
+    inner: Box<dyn MailSource>,
+    pool: DbPool,
+    profile: String,
+    /// Set by `--refresh`: ignore cached metadata and fetch every message again
+    refresh: bool,
+}
+
+impl CachedSource {
+    pub fn new(inner: Box<dyn MailSource>, pool: DbPool, profile: Option<&str>, refresh: bool) -> Self {
+        Self {
+            inner,
+            pool,
+            profile: profile.unwrap_or_default().to_string(),
+            refresh,
+        }
+    }
+
+    async fn cached(&self, message_id: &str) -> Option<MailMessage> {
+        if self.refresh {
+            return None;
+        }
+
+        match db::load_cached_message(&self.pool, self.inner.name(), &self.profile, message_id).await {
+            Ok(cached) => cached.and_then(|json| serde_json::from_str(&json).ok()),
+            Err(e) => {
+                warn!("Message cache lookup failed: {:#}", e);
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MailSource for CachedSource {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn search_keyword(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        keyword: &str,
+        exclusions: &Exclusions,
+        max_results: Option<usize>,
+    ) -> Result<Vec<String>> {
+        self.inner.search_keyword(start_date, end_date, keyword, exclusions, max_results).await
+    }
+
+    async fn estimate_keyword(&self, start_date: NaiveDate, end_date: NaiveDate, keyword: &str, exclusions: &Exclusions) -> Result<Option<u64>> {
+        self.inner.estimate_keyword(start_date, end_date, keyword, exclusions).await
+    }
+
+    async fn search_queue(&self, queue: &IngestQueue, since: NaiveDate) -> Result<Vec<String>> {
+        self.inner.search_queue(queue, since).await
+    }
+
+    async fn fetch_message(&self, message_id: &str) -> Result<MailMessage> {
+        if let Some(message) = self.cached(message_id).await {
+            return Ok(message);
+        }
+
+        let message = self.inner.fetch_message(message_id).await?;
+        let saved = match serde_json::to_string(&message) {
+            Ok(json) => db::save_cached_message(&self.pool, self.inner.name(), &self.profile, message_id, &json).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = saved {
+            warn!("Failed to cache message {}: {:#}", message_id, e);
+        }
+        Ok(message)
+    }
+
+    async fn download_attachment(&self, message_id: &str, attachment_id: &str) -> Result<Vec<u8>> {
+        self.inner.download_attachment(message_id, attachment_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mail::AttachmentRef;
+
+    #[test]
+    fn test_cached_metadata_round_trips() {
+        let message = MailMessage {
+            id: "18e0a1f2c3d4e5f6".to_string(),
+            from: "Hetzner <billing@hetzner.com>".to_string(),
+            received_at: chrono::DateTime::from_timestamp_millis(1741075200000),
+            headers: vec![("Authentication-Results".to_string(), "spf=pass".to_string())],
+            attachments: vec![AttachmentRef {
+                filename: "invoice.pdf".to_string(),
+                attachment_id: "att-1".to_string(),
+                size: Some(2048),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let cached: MailMessage = serde_json::from_str(&serde_json::to_string(&message).unwrap()).unwrap();
+        assert_eq!(cached.received_at, message.received_at);
+        assert_eq!(cached.headers, message.headers);
+        assert_eq!(cached.attachments[0].attachment_id, "att-1");
+        assert_eq!(cached.attachments[0].size, Some(2048));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::config::env::Config;
+use super::attachment::InvoiceAttachment;
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// How an attachment was encrypted by the sender
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encryption {
+    Pgp,
+    Smime,
+}
+
+impl Encryption {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encryption::Pgp => "PGP",
+            Encryption::Smime => "S/MIME",
+        }
+    }
+}
+
+/// Recognise encrypted attachments by extension (and the ASCII armor header for `.asc`)
+pub fn detect(filename: &str, data: &[u8]) -> Option<Encryption> {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    match extension.as_deref() {
+        Some("pgp" | "gpg") => Some(Encryption::Pgp),
+        Some("asc") if data.starts_with(b"-----BEGIN PGP MESSAGE-----") => Some(Encryption::Pgp),
+        Some("p7m") => Some(Encryption::Smime),
+        _ => None,
+    }
+}
+
+/// Replace an encrypted attachment's data and name with the decrypted content.
+/// Returns the encryption that was removed, or None for attachments that aren't encrypted.
+pub async fn decrypt_attachment(config: &Config, attachment: &mut InvoiceAttachment) -> Result<Option<Encryption>> {
+    let Some(encryption) = detect(&attachment.filename, &attachment.data) else {
+        return Ok(None);
+    };
+
+    let decrypted = match encryption {
+        Encryption::Pgp => decrypt_pgp(config, &attachment.data).await?,
+        Encryption::Smime => decrypt_smime(config, &attachment.data).await?,
+    };
+    if decrypted.is_empty() {
+        anyhow::bail!("decryption produced no data");
+    }
+
+    attachment.filename = decrypted_filename(&attachment.filename, &decrypted);
+    attachment.data = decrypted;
+    Ok(Some(encryption))
+}
+
+/// Decrypt with gpg using the keyring in GPG_HOMEDIR (or the default keyring)
+async fn decrypt_pgp(config: &Config, data: &[u8]) -> Result<Vec<u8>> {
+    let mut cmd = Command::new("gpg");
+    cmd.args(["--batch", "--quiet", "--no-tty"]);
+    if let Some(homedir) = &config.gpg_homedir {
+        cmd.arg("--homedir").arg(homedir);
+    }
+    if let Some(passphrase_file) = &config.gpg_passphrase_file {
+        cmd.args(["--pinentry-mode", "loopback", "--passphrase-file"]).arg(passphrase_file);
+    }
+    cmd.arg("--decrypt");
+
+    run_with_stdin(cmd, data).await.context("gpg could not decrypt the attachment")
+}
+
+/// Decrypt with `openssl smime` using SMIME_KEY_FILE (and SMIME_CERT_FILE when set)
+async fn decrypt_smime(config: &Config, data: &[u8]) -> Result<Vec<u8>> {
+    let key = config.smime_key_file.as_ref()
+        .context("S/MIME attachment found but SMIME_KEY_FILE is not set")?;
+
+    let mut cmd = Command::new("openssl");
+    cmd.args(["smime", "-decrypt", "-inform", "DER", "-inkey"]).arg(key);
+    if let Some(cert) = &config.smime_cert_file {
+        cmd.arg("-recip").arg(cert);
+    }
+
+    run_with_stdin(cmd, data).await.context("openssl could not decrypt the attachment")
+}
+
+async fn run_with_stdin(mut cmd: Command, data: &[u8]) -> Result<Vec<u8>> {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    // Feed stdin while reading the output so large attachments can't deadlock on full pipes
+    let mut stdin = child.stdin.take().context("failed to open stdin")?;
+    let input = data.to_vec();
+    let writer = tokio::spawn(async move {
+        let _ = stdin.write_all(&input).await;
+    });
+    let output = child.wait_with_output().await?;
+    let _ = writer.await;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{} ({})", output.status, stderr.trim());
+    }
+
+    Ok(output.stdout)
+}
+
+/// Drop the encryption extension: `invoice.pdf.gpg` -> `invoice.pdf`. S/MIME wraps a whole
+/// MIME entity, which is kept as `.eml` so it opens in any mail client.
+fn decrypted_filename(filename: &str, decrypted: &[u8]) -> String {
+    let stem = Path::new(filename)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| filename.to_string());
+
+    let is_mime = decrypted.starts_with(b"Content-") || decrypted.starts_with(b"MIME-Version");
+    if is_mime && Path::new(&stem).extension().is_none() {
+        format!("{}.eml", stem)
+    } else {
+        stem
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_and_rename() {
+        assert_eq!(detect("invoice.pdf.gpg", b"\x85\x01"), Some(Encryption::Pgp));
+        assert_eq!(detect("invoice.pdf.asc", b"-----BEGIN PGP MESSAGE-----\n"), Some(Encryption::Pgp));
+        assert_eq!(detect("key.asc", b"-----BEGIN PGP PUBLIC KEY BLOCK-----\n"), None);
+        assert_eq!(detect("smime.p7m", b"0\x80"), Some(Encryption::Smime));
+        assert_eq!(detect("invoice.pdf", b"%PDF"), None);
+
+        assert_eq!(decrypted_filename("acme-invoice.pdf.gpg", b"%PDF-1.4"), "acme-invoice.pdf");
+        assert_eq!(decrypted_filename("acme-smime.p7m", b"Content-Type: multipart/mixed"), "acme-smime.eml");
+    }
+}
@@ -0,0 +1,142 @@
+//! Attachment downloads that survive a flaky connection. Each attachment is retried on its own,
+//! with backoff, when its transfer fails for a reason worth retrying (a dropped connection, a
+//! timeout, a rate limit, a server error), so one bad transfer doesn't cost the rest of the
+//! message. Sources serving attachments over plain HTTP (JMAP) also pick a transfer cut off
+//! partway back up with a Range request instead of starting over.
+
+use super::{AttachmentRef, MailSource};
+use crate::process::outcome::categorize;
+use crate::process::telemetry;
+use crate::scheduler::runner::retry_delay;
+use anyhow::{Context, Result};
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// Tries per attachment, the first one included
+const DOWNLOAD_ATTEMPTS: u32 = 4;
+
+/// Wait before the first retry, doubled for each one after it
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Download an attachment, retrying transient failures
+pub async fn download_with_retries(source: &dyn MailSource, message_id: &str, attachment: &AttachmentRef) -> Result<Vec<u8>> {
+    let mut attempt = 1;
+    loop {
+        match source.download_attachment(message_id, &attachment.attachment_id).await {
+            Ok(data) => return Ok(data),
+            Err(e) if attempt < DOWNLOAD_ATTEMPTS && is_transient(&e) => {
+                let delay = retry_delay(RETRY_DELAY, attempt);
+                log::warn!(
+                    "Downloading {} failed, retrying in {}s ({}/{}): {:#}",
+                    attachment.filename,
+                    delay.as_secs(),
+                    attempt + 1,
+                    DOWNLOAD_ATTEMPTS,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                telemetry::retried(source.name());
+                attempt += 1;
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to download {}", attachment.filename)),
+        }
+    }
+}
+
+/// Whether a failed download may go through when tried again: the connection dropped or timed
+/// out, or the server answered 429, 5xx or a rate-limit 403. Missing attachments, refused
+/// tokens and the like fail the same way every time.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    categorize(error).is_retriable()
+}
+
+/// GET `url` with `token`, counted towards `api`, resuming a body cut off partway from where it stopped. A resume is
+/// only tried after a transfer that got further than the one before, so a server that keeps
+/// dropping the connection straight away is left to `download_with_retries`.
+pub async fn ranged_get(api: &str, client: &reqwest::Client, url: &str, token: &str) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    loop {
+        let before = data.len();
+        match fetch_from(api, client, url, token, &mut data).await {
+            Ok(()) => return Ok(data),
+            Err(e) if data.len() > before && is_transient(&e) => {
+                log::warn!("Attachment transfer cut off after {} bytes, resuming: {:#}", data.len(), e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Append the body of `url` to `data`, from byte `data.len()` on. A server that ignores the range
+/// sends the whole file again, which replaces what was there.
+async fn fetch_from(api: &str, client: &reqwest::Client, url: &str, token: &str, data: &mut Vec<u8>) -> Result<()> {
+    let mut request = client.get(url).bearer_auth(token);
+    if !data.is_empty() {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", data.len()));
+    }
+    let slot = telemetry::slot(api).await;
+    let mut response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            telemetry::count(api, None, &[], 0, 0);
+            return Err(e).context("Failed to download attachment");
+        }
+    };
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        telemetry::count(api, Some(status), error_text.as_bytes(), 0, error_text.len() as u64);
+        telemetry::observe(slot.as_ref(), status, error_text.as_bytes());
+        anyhow::bail!("Attachment download failed ({}): {}", status, error_text);
+    }
+    telemetry::observe(slot.as_ref(), status, &[]);
+    if status != StatusCode::PARTIAL_CONTENT {
+        data.clear();
+    }
+    // Counted once the transfer ends, cut off or not, with what arrived
+    let start = data.len();
+    let transfer = async {
+        while let Some(chunk) = response.chunk().await? {
+            data.extend_from_slice(&chunk);
+        }
+        Ok::<_, reqwest::Error>(())
+    }
+    .await;
+    telemetry::count(api, Some(status), &[], 0, (data.len() - start) as u64);
+    transfer.context("Attachment transfer interrupted")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+
+    #[tokio::test]
+    async fn test_transient_errors_and_resume() {
+        assert!(is_transient(&anyhow::anyhow!("Gmail API error (503 Service Unavailable): backend error")));
+        assert!(is_transient(&anyhow::anyhow!("Attachment download failed (429 Too Many Requests): slow down")));
+        assert!(is_transient(&anyhow::anyhow!("Gmail API error (403 Forbidden): userRateLimitExceeded")));
+        assert!(!is_transient(&anyhow::anyhow!("Gmail API error (404 Not Found): attachment gone")));
+        assert!(!is_transient(&anyhow::anyhow!("Attachment B1 not found in message M1 (of 3)")));
+        let reset = anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::ConnectionReset)).context("IMAP fetch failed");
+        assert!(is_transient(&reset));
+
+        let mut server = mockito::Server::new_async().await;
+        let client = reqwest::Client::new();
+        let url = format!("{}/blob/B1", server.url());
+        let _rest = server
+            .mock("GET", "/blob/B1")
+            .match_header("range", "bytes=4-")
+            .with_status(206)
+            .with_body("-1.4\n")
+            .create_async()
+            .await;
+        let _whole = server.mock("GET", "/blob/B1").match_header("range", Matcher::Missing).with_body("%PDF-1.4\n").create_async().await;
+
+        let mut data = b"%PDF".to_vec();
+        fetch_from("JMAP", &client, &url, "token", &mut data).await.unwrap();
+        assert_eq!(data, b"%PDF-1.4\n");
+        assert_eq!(ranged_get("JMAP", &client, &url, "token").await.unwrap(), b"%PDF-1.4\n");
+    }
+}
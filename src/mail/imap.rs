@@ -0,0 +1,320 @@
+//! Minimal IMAP4rev1 client: what a mail source needs to search a mailbox and fetch whole
+//! messages read-only, over implicit TLS or STARTTLS.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::ClientConfig;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+
+/// How a mail server connection is secured
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsMode {
+    /// TLS from the first byte (IMAPS, SMTPS)
+    Implicit,
+    /// Plain connection upgraded with STARTTLS before logging in
+    StartTls,
+}
+
+impl TlsMode {
+    pub fn parse(value: &str, name: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "ssl" | "tls" => Ok(Self::Implicit),
+            "starttls" => Ok(Self::StartTls),
+            other => anyhow::bail!("{} must be starttls or ssl (got '{}')", name, other),
+        }
+    }
+}
+
+/// A connection the clients can read and write, plain or TLS
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// Start TLS on a connected socket
+pub async fn upgrade(tcp: TcpStream, host: &str, tls: Arc<ClientConfig>) -> Result<TlsStream<TcpStream>> {
+    let server_name = ServerName::try_from(host.to_string()).with_context(|| format!("Invalid server name {}", host))?;
+    TlsConnector::from(tls)
+        .connect(server_name, tcp)
+        .await
+        .with_context(|| format!("TLS handshake with {} failed", host))
+}
+
+/// Criteria of a UID SEARCH: `SINCE 1-Mar-2025 BEFORE 1-Apr-2025 TEXT "invoice" NOT TEXT "proforma"`
+#[derive(Debug, Default)]
+pub struct Search<'a> {
+    pub since: Option<NaiveDate>,
+    /// Exclusive, like IMAP's BEFORE
+    pub before: Option<NaiveDate>,
+    pub text: Option<&'a str>,
+    pub to: Option<&'a str>,
+    pub not_text: &'a [String],
+}
+
+/// A command argument: sent as is, or as a string (quoted, or a literal when not plain ASCII)
+enum Arg<'a> {
+    Atom(String),
+    Str(&'a str),
+}
+
+/// One untagged response, with its literals cut out of the text (`{123}` stays in place)
+struct Response {
+    text: String,
+    literals: Vec<Vec<u8>>,
+}
+
+pub struct ImapSession {
+    stream: BufReader<Box<dyn Stream>>,
+    next_tag: u32,
+}
+
+impl ImapSession {
+    /// Connect and read the server greeting
+    pub async fn connect(host: &str, port: u16, mode: TlsMode, tls: Arc<ClientConfig>) -> Result<Self> {
+        let mut tcp = TcpStream::connect((host, port))
+            .await
+            .with_context(|| format!("Failed to connect to IMAP server {}:{}", host, port))?;
+        let stream: Box<dyn Stream> = match mode {
+            TlsMode::Implicit => Box::new(upgrade(tcp, host, tls).await?),
+            TlsMode::StartTls => {
+                starttls(&mut tcp).await?;
+                Box::new(upgrade(tcp, host, tls).await?)
+            }
+        };
+
+        let mut session = Self::from_stream(stream);
+        if mode == TlsMode::Implicit {
+            session.read_greeting().await?;
+        }
+        Ok(session)
+    }
+
+    fn from_stream(stream: Box<dyn Stream>) -> Self {
+        Self { stream: BufReader::new(stream), next_tag: 1 }
+    }
+
+    async fn read_greeting(&mut self) -> Result<()> {
+        let (line, _) = self.read_response().await?;
+        anyhow::ensure!(line.starts_with("* OK") || line.starts_with("* PREAUTH"), "Unexpected IMAP greeting: {}", line.trim());
+        Ok(())
+    }
+
+    pub async fn login(&mut self, username: &str, password: &str) -> Result<()> {
+        self.run(vec![Arg::Atom("LOGIN".into()), Arg::Str(username), Arg::Str(password)])
+            .await
+            .context("IMAP login failed")?;
+        Ok(())
+    }
+
+    /// Open a mailbox read-only
+    pub async fn examine(&mut self, mailbox: &str) -> Result<()> {
+        self.run(vec![Arg::Atom("EXAMINE".into()), Arg::Str(mailbox)])
+            .await
+            .with_context(|| format!("Failed to open mailbox '{}'", mailbox))?;
+        Ok(())
+    }
+
+    /// UIDs of the messages in the open mailbox matching the criteria, in ascending order
+    pub async fn uid_search(&mut self, search: &Search<'_>) -> Result<Vec<u32>> {
+        let mut args = vec![Arg::Atom("UID".into()), Arg::Atom("SEARCH".into())];
+        let strings = search.text.into_iter().chain(search.to).chain(search.not_text.iter().map(String::as_str));
+        if strings.clone().any(|s| !s.is_ascii()) {
+            args.push(Arg::Atom("CHARSET UTF-8".into()));
+        }
+        if let Some(since) = search.since {
+            args.push(Arg::Atom(format!("SINCE {}", imap_date(since))));
+        }
+        if let Some(before) = search.before {
+            args.push(Arg::Atom(format!("BEFORE {}", imap_date(before))));
+        }
+        if let Some(text) = search.text {
+            args.extend([Arg::Atom("TEXT".into()), Arg::Str(text)]);
+        }
+        if let Some(to) = search.to {
+            args.extend([Arg::Atom("TO".into()), Arg::Str(to)]);
+        }
+        for excluded in search.not_text {
+            args.extend([Arg::Atom("NOT TEXT".into()), Arg::Str(excluded)]);
+        }
+        if args.len() == 2 {
+            args.push(Arg::Atom("ALL".into()));
+        }
+
+        let mut uids: Vec<u32> = self
+            .run(args)
+            .await
+            .context("IMAP search failed")?
+            .iter()
+            .filter_map(|response| response.text.strip_prefix("* SEARCH"))
+            .flat_map(|numbers| numbers.split_whitespace().filter_map(|n| n.parse().ok()).collect::<Vec<_>>())
+            .collect();
+        uids.sort_unstable();
+        Ok(uids)
+    }
+
+    /// The raw message and its arrival time (INTERNALDATE), or None when the UID is gone
+    pub async fn uid_fetch(&mut self, uid: u32) -> Result<Option<(Vec<u8>, Option<DateTime<Utc>>)>> {
+        let responses = self
+            .run(vec![Arg::Atom(format!("UID FETCH {} (INTERNALDATE BODY.PEEK[])", uid))])
+            .await
+            .with_context(|| format!("Failed to fetch message {}", uid))?;
+
+        Ok(responses
+            .into_iter()
+            .filter(|response| response.text.starts_with("* ") && response.text.contains(" FETCH "))
+            .find_map(|mut response| {
+                let received_at = response.text.split_once("INTERNALDATE \"").and_then(|(_, rest)| rest.split_once('"')).and_then(|(date, _)| {
+                    DateTime::parse_from_str(date.trim(), "%d-%b-%Y %H:%M:%S %z").ok().map(|date| date.with_timezone(&Utc))
+                });
+                let raw = response.literals.pop()?;
+                Some((raw, received_at))
+            }))
+    }
+
+    /// Send a tagged command and collect the untagged responses until its completion.
+    /// A NO or BAD completion is an error carrying the server's text.
+    async fn run(&mut self, args: Vec<Arg<'_>>) -> Result<Vec<Response>> {
+        let tag = format!("a{}", self.next_tag);
+        self.next_tag += 1;
+
+        self.stream.write_all(tag.as_bytes()).await?;
+        for arg in args {
+            self.stream.write_all(b" ").await?;
+            match arg {
+                Arg::Atom(atom) => self.stream.write_all(atom.as_bytes()).await?,
+                Arg::Str(value) if value.is_ascii() && !value.contains(['\r', '\n']) => {
+                    let quoted = format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+                    self.stream.write_all(quoted.as_bytes()).await?;
+                }
+                Arg::Str(value) => {
+                    // Synchronizing literal: wait for the server's go-ahead before the bytes
+                    self.stream.write_all(format!("{{{}}}\r\n", value.len()).as_bytes()).await?;
+                    self.stream.flush().await?;
+                    loop {
+                        let (line, _) = self.read_response().await?;
+                        if line.starts_with('+') {
+                            break;
+                        }
+                        anyhow::ensure!(!line.starts_with(&tag), "{}", line.trim());
+                    }
+                    self.stream.write_all(value.as_bytes()).await?;
+                }
+            }
+        }
+        self.stream.write_all(b"\r\n").await?;
+        self.stream.flush().await?;
+
+        let mut responses = Vec::new();
+        loop {
+            let (text, literals) = self.read_response().await?;
+            if let Some(status) = text.strip_prefix(&tag) {
+                let status = status.trim();
+                anyhow::ensure!(status.starts_with("OK"), "{}", status);
+                return Ok(responses);
+            }
+            responses.push(Response { text, literals });
+        }
+    }
+
+    /// Read one response line, following `{n}` literals
+    async fn read_response(&mut self) -> Result<(String, Vec<Vec<u8>>)> {
+        let mut text = String::new();
+        let mut literals = Vec::new();
+        loop {
+            let mut line = Vec::new();
+            let read = self.stream.read_until(b'\n', &mut line).await?;
+            anyhow::ensure!(read > 0, "IMAP server closed the connection");
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            text.push_str(line);
+
+            let Some(length) = line
+                .strip_suffix('}')
+                .and_then(|rest| rest.rsplit_once('{'))
+                .and_then(|(_, length)| length.trim_end_matches('+').parse::<usize>().ok())
+            else {
+                return Ok((text, literals));
+            };
+            let mut literal = vec![0; length];
+            self.stream.read_exact(&mut literal).await?;
+            literals.push(literal);
+        }
+    }
+}
+
+/// The plaintext part of a STARTTLS connection: greeting, STARTTLS, OK
+async fn starttls(tcp: &mut TcpStream) -> Result<()> {
+    let mut reader = BufReader::new(tcp);
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    anyhow::ensure!(line.starts_with("* OK"), "Unexpected IMAP greeting: {}", line.trim());
+
+    reader.get_mut().write_all(b"a0 STARTTLS\r\n").await?;
+    loop {
+        line.clear();
+        anyhow::ensure!(reader.read_line(&mut line).await? > 0, "IMAP server closed the connection during STARTTLS");
+        if let Some(status) = line.strip_prefix("a0 ") {
+            anyhow::ensure!(status.starts_with("OK"), "IMAP server refused STARTTLS: {}", status.trim());
+            return Ok(());
+        }
+    }
+}
+
+/// IMAP date format: `1-Mar-2025`
+fn imap_date(date: NaiveDate) -> String {
+    date.format("%-d-%b-%Y").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_search_and_fetch() {
+        let (client, server) = tokio::io::duplex(4096);
+        let server = tokio::spawn(async move {
+            let mut server = BufReader::new(server);
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            assert_eq!(line, "a1 UID SEARCH CHARSET UTF-8 SINCE 1-Mar-2025 BEFORE 1-Apr-2025 TEXT {6}\r\n");
+            server.write_all(b"+ go ahead\r\n").await.unwrap();
+            line.clear();
+            server.read_line(&mut line).await.unwrap();
+            assert_eq!(line, "março NOT TEXT \"proforma\"\r\n");
+            server.write_all(b"* SEARCH 9 3\r\na1 OK SEARCH completed\r\n").await.unwrap();
+
+            line.clear();
+            server.read_line(&mut line).await.unwrap();
+            assert_eq!(line, "a2 UID FETCH 9 (INTERNALDATE BODY.PEEK[])\r\n");
+            server
+                .write_all(b"* 2 FETCH (UID 9 INTERNALDATE \" 4-Mar-2025 09:12:45 +0100\" BODY[] {7}\r\nSubject)\r\na2 OK FETCH completed\r\n")
+                .await
+                .unwrap();
+
+            line.clear();
+            server.read_line(&mut line).await.unwrap();
+            server.write_all(b"a3 NO [NONEXISTENT] Unknown Mailbox: Labels/x\r\n").await.unwrap();
+        });
+
+        let mut session = ImapSession::from_stream(Box::new(client));
+        let search = Search {
+            since: NaiveDate::from_ymd_opt(2025, 3, 1),
+            before: NaiveDate::from_ymd_opt(2025, 4, 1),
+            text: Some("março"),
+            not_text: &["proforma".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(session.uid_search(&search).await.unwrap(), vec![3, 9]);
+
+        let (raw, received_at) = session.uid_fetch(9).await.unwrap().unwrap();
+        assert_eq!(raw, b"Subject");
+        assert_eq!(received_at.unwrap().to_rfc3339(), "2025-03-04T08:12:45+00:00");
+
+        let error = session.examine("Labels/x").await.unwrap_err();
+        assert!(format!("{:#}", error).contains("Unknown Mailbox"));
+        server.await.unwrap();
+    }
+}
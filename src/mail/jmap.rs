@@ -0,0 +1,341 @@
+//! JMAP mail source (RFC 8620/8621), e.g. Fastmail. The server's Email/query filter maps onto
+//! the keyword search directly: date range, full-text keyword, has-attachment and exclusions.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{Days, NaiveDate};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use super::search::{Exclusions, IngestQueue};
+use super::{mime, AttachmentRef, MailFolder, MailMessage, MailSource};
+use crate::process::outcome::ApiError;
+use crate::process::telemetry;
+
+/// Fastmail's session endpoint, used when JMAP_SESSION_URL is unset
+pub const FASTMAIL_SESSION_URL: &str = "https://api.fastmail.com/jmap/session";
+
+const MAIL_CAPABILITY: &str = "urn:ietf:params:jmap:mail";
+
+/// Ids requested per Email/query page
+const PAGE_SIZE: usize = 100;
+
+/// Connection settings of a JMAP server (JMAP_*)
+#[derive(Debug, Clone)]
+pub struct JmapSettings {
+    pub session_url: String,
+    /// API token (Fastmail: Settings → Privacy & Security → API tokens, read-only mail scope)
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Session {
+    api_url: String,
+    download_url: String,
+    primary_accounts: std::collections::HashMap<String, String>,
+}
+
+pub struct JmapSource {
+    client: Client,
+    token: String,
+    api_url: String,
+    download_url: String,
+    account_id: String,
+}
+
+impl JmapSource {
+    /// Fetch the session resource to find the API and download URLs and the mail account
+    pub async fn connect(settings: &JmapSettings) -> Result<Self> {
+        let client = Client::new();
+        let request = client
+            .get(&settings.session_url)
+            .bearer_auth(&settings.token);
+        let response = telemetry::send("JMAP", request)
+            .await
+            .with_context(|| format!("Failed to reach JMAP server {}", settings.session_url))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            anyhow::bail!("JMAP session request failed ({}): {}", status, error_text);
+        }
+
+        let session: Session = response.json().await.context("Failed to parse JMAP session")?;
+        let account_id = session
+            .primary_accounts
+            .get(MAIL_CAPABILITY)
+            .cloned()
+            .context("The JMAP token has no mail account (check its scopes)")?;
+        Ok(Self {
+            client,
+            token: settings.token.clone(),
+            api_url: session.api_url,
+            download_url: session.download_url,
+            account_id,
+        })
+    }
+
+    /// Make one method call and return its arguments
+    async fn call(&self, method: &str, mut arguments: Value) -> Result<Value> {
+        arguments["accountId"] = json!(self.account_id);
+        let body = json!({
+            "using": ["urn:ietf:params:jmap:core", MAIL_CAPABILITY],
+            "methodCalls": [[method, arguments, "0"]],
+        });
+        let request = self
+            .client
+            .post(&self.api_url)
+            .bearer_auth(&self.token)
+            .json(&body);
+        let response = telemetry::send("JMAP", request)
+            .await
+            .with_context(|| format!("JMAP {} request failed", method))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(ApiError::new("JMAP", status, error_text).into());
+        }
+
+        let mut body: Value = response.json().await.context("Failed to parse JMAP response")?;
+        let response = &mut body["methodResponses"][0];
+        let arguments = response[1].take();
+        if response[0].as_str() != Some(method) {
+            anyhow::bail!("JMAP {} failed: {}", method, arguments);
+        }
+        Ok(arguments)
+    }
+
+    /// Ids of the emails matching a filter, newest first, following pages until `max_results`
+    async fn query(&self, filter: Value, max_results: Option<usize>) -> Result<Vec<String>> {
+        let mut ids: Vec<String> = Vec::new();
+        loop {
+            let limit = max_results.map_or(PAGE_SIZE, |max| (max - ids.len()).min(PAGE_SIZE));
+            let result = self
+                .call(
+                    "Email/query",
+                    json!({
+                        "filter": filter,
+                        "sort": [{ "property": "receivedAt", "isAscending": false }],
+                        "position": ids.len(),
+                        "limit": limit,
+                    }),
+                )
+                .await?;
+            let page: Vec<String> = serde_json::from_value(result["ids"].clone()).context("Invalid Email/query ids")?;
+            let done = page.len() < limit;
+            ids.extend(page);
+            if done || max_results.is_some_and(|max| ids.len() >= max) {
+                return Ok(ids);
+            }
+        }
+    }
+
+    async fn mailbox_id(&self, name: &str) -> Result<String> {
+        let result = self.call("Mailbox/query", json!({ "filter": { "name": name } })).await?;
+        result["ids"][0].as_str().map(str::to_string).with_context(|| format!("No JMAP mailbox named '{}'", name))
+    }
+}
+
+/// Email/query filter for one keyword within an inclusive date range. Messages that match any of
+/// the excluded keywords are left out (`NOT` over the list means none of them may match).
+fn keyword_filter(start_date: NaiveDate, end_date: NaiveDate, keyword: &str, exclusions: &Exclusions) -> Value {
+    let before = end_date.checked_add_days(Days::new(1)).unwrap_or(end_date);
+    let mut conditions = vec![json!({
+        "after": format!("{}T00:00:00Z", start_date),
+        "before": format!("{}T00:00:00Z", before),
+        "text": keyword,
+        "hasAttachment": true,
+    })];
+    if !exclusions.keywords.is_empty() {
+        let excluded: Vec<Value> = exclusions.keywords.iter().map(|text| json!({ "text": text })).collect();
+        conditions.push(json!({ "operator": "NOT", "conditions": excluded }));
+    }
+    json!({ "operator": "AND", "conditions": conditions })
+}
+
+/// Convert an Email/get object into the source-agnostic representation
+fn to_mail_message(email: &Value) -> MailMessage {
+    let from = email["from"][0].as_object().map(|from| {
+        let address = from.get("email").and_then(Value::as_str).unwrap_or_default();
+        match from.get("name").and_then(Value::as_str).filter(|name| !name.is_empty()) {
+            Some(name) => format!("{} <{}>", name, address),
+            None => address.to_string(),
+        }
+    });
+
+    let body = email["textBody"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|part| {
+            let text = email["bodyValues"][part["partId"].as_str()?]["value"].as_str()?;
+            Some(if part["type"].as_str() == Some("text/html") { mime::html_to_text(text) } else { text.to_string() })
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let attachments = email["attachments"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|part| {
+            Some(AttachmentRef {
+                filename: part["name"].as_str().filter(|name| !name.is_empty())?.to_string(),
+                attachment_id: part["blobId"].as_str()?.to_string(),
+                mime_type: part["type"].as_str().map(str::to_string),
+                size: part["size"].as_u64(),
+            })
+        })
+        .collect();
+
+    MailMessage {
+        id: email["id"].as_str().unwrap_or_default().to_string(),
+        from: from.unwrap_or_default(),
+        subject: email["subject"].as_str().unwrap_or_default().to_string(),
+        received_at: email["receivedAt"].as_str().and_then(|date| chrono::DateTime::parse_from_rfc3339(date).ok()).map(|date| date.to_utc()),
+        body,
+        headers: email["headers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|header| Some((header["name"].as_str()?.to_string(), mime::decode_header(header["value"].as_str()?.trim()))))
+            .collect(),
+        attachments,
+        folder: if email["keywords"]["$draft"].as_bool() == Some(true) { MailFolder::Drafts } else { MailFolder::Mail },
+    }
+}
+
+#[async_trait]
+impl MailSource for JmapSource {
+    fn name(&self) -> &'static str {
+        "JMAP"
+    }
+
+    async fn search_keyword(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        keyword: &str,
+        exclusions: &Exclusions,
+        max_results: Option<usize>,
+    ) -> Result<Vec<String>> {
+        self.query(keyword_filter(start_date, end_date, keyword, exclusions), max_results).await
+    }
+
+    async fn estimate_keyword(&self, start_date: NaiveDate, end_date: NaiveDate, keyword: &str, exclusions: &Exclusions) -> Result<Option<u64>> {
+        let result = self
+            .call(
+                "Email/query",
+                json!({ "filter": keyword_filter(start_date, end_date, keyword, exclusions), "limit": 1, "calculateTotal": true }),
+            )
+            .await?;
+        Ok(result["total"].as_u64())
+    }
+
+    async fn search_queue(&self, queue: &IngestQueue, since: NaiveDate) -> Result<Vec<String>> {
+        let after = format!("{}T00:00:00Z", since);
+        let filter = match queue {
+            IngestQueue::Label(label) => json!({ "inMailbox": self.mailbox_id(label).await?, "after": after, "hasAttachment": true }),
+            IngestQueue::Address(address) => json!({ "to": address, "after": after, "hasAttachment": true }),
+        };
+        self.query(filter, None).await
+    }
+
+    async fn fetch_message(&self, message_id: &str) -> Result<MailMessage> {
+        let result = self
+            .call(
+                "Email/get",
+                json!({
+                    "ids": [message_id],
+                    "properties": ["id", "from", "subject", "receivedAt", "keywords", "headers", "textBody", "bodyValues", "attachments"],
+                    "fetchTextBodyValues": true,
+                }),
+            )
+            .await?;
+        let email = result["list"].get(0).with_context(|| format!("Message {} no longer exists", message_id))?;
+        Ok(to_mail_message(email))
+    }
+
+    async fn download_attachment(&self, _message_id: &str, attachment_id: &str) -> Result<Vec<u8>> {
+        let url = self
+            .download_url
+            .replace("{accountId}", &self.account_id)
+            .replace("{blobId}", attachment_id)
+            .replace("{name}", "attachment")
+            .replace("{type}", "application%2Foctet-stream");
+        super::download::ranged_get(self.name(), &self.client, &url, &self.token).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+
+    #[tokio::test]
+    async fn test_search_fetch_and_download() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let _session = server
+            .mock("GET", "/jmap/session")
+            .match_header("authorization", "Bearer fm-token")
+            .with_body(
+                json!({
+                    "apiUrl": format!("{}/jmap/api", url),
+                    "downloadUrl": format!("{}/download/{{accountId}}/{{blobId}}/{{name}}?type={{type}}", url),
+                    "primaryAccounts": { MAIL_CAPABILITY: "u123" },
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let _query = server
+            .mock("POST", "/jmap/api")
+            .match_body(Matcher::PartialJson(json!({ "methodCalls": [["Email/query", {
+                "accountId": "u123",
+                "filter": { "operator": "AND", "conditions": [
+                    { "after": "2025-03-01T00:00:00Z", "before": "2025-04-01T00:00:00Z", "text": "invoice", "hasAttachment": true },
+                    { "operator": "NOT", "conditions": [{ "text": "proforma" }] },
+                ]},
+            }, "0"]] })))
+            .with_body(json!({ "methodResponses": [["Email/query", { "ids": ["M1", "M2"] }, "0"]] }).to_string())
+            .create_async()
+            .await;
+        let _get = server
+            .mock("POST", "/jmap/api")
+            .match_body(Matcher::Regex("Email/get".to_string()))
+            .with_body(
+                json!({ "methodResponses": [["Email/get", { "list": [{
+                    "id": "M1",
+                    "from": [{ "name": "Fastmail", "email": "billing@fastmail.com" }],
+                    "subject": "Your invoice",
+                    "receivedAt": "2025-03-04T09:00:00Z",
+                    "headers": [{ "name": "Subject", "value": " Your invoice" }],
+                    "textBody": [{ "partId": "1", "type": "text/plain" }],
+                    "bodyValues": { "1": { "value": "Thanks for your payment" } },
+                    "attachments": [{ "partId": "2", "blobId": "B9", "name": "invoice.pdf", "type": "application/pdf", "size": 9 }],
+                }]}, "0"]] })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let _download = server
+            .mock("GET", "/download/u123/B9/attachment")
+            .match_query(Matcher::Any)
+            .with_body("%PDF-1.4\n")
+            .create_async()
+            .await;
+
+        let settings = JmapSettings { session_url: format!("{}/jmap/session", url), token: "fm-token".to_string() };
+        let source = JmapSource::connect(&settings).await.unwrap();
+        let march = (NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(), NaiveDate::from_ymd_opt(2025, 3, 31).unwrap());
+        let exclusions = Exclusions { keywords: vec!["proforma".to_string()], ..Default::default() };
+        assert_eq!(source.search_keyword(march.0, march.1, "invoice", &exclusions, None).await.unwrap(), vec!["M1", "M2"]);
+
+        let message = source.fetch_message("M1").await.unwrap();
+        assert_eq!(message.from, "Fastmail <billing@fastmail.com>");
+        assert_eq!(message.body, "Thanks for your payment");
+        assert_eq!(message.attachments[0].attachment_id, "B9");
+        assert_eq!(source.download_attachment("M1", "B9").await.unwrap(), b"%PDF-1.4\n");
+    }
+}
@@ -0,0 +1,282 @@
+//! Local Maildir source, for mail mirrored by mbsync, offlineimap and the like. Searching reads
+//! the messages themselves, applying the same date, keyword, attachment and exclusion filters
+//! as a server-side search.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use super::search::{Exclusions, IngestQueue};
+use super::{rfc822, MailFolder, MailMessage, MailSource};
+
+/// Mail source scanning a Maildir tree. Both layouts are read: Maildir++ (`.Folder` next to
+/// the inbox's `cur`/`new`) and one Maildir per directory (`INBOX/cur`, `Archive/2024/cur`).
+/// Message ids are `<folder>/<unique name>`, the file name without its flags, which change as
+/// the message is read. The tree is read on blocking threads, so a large mailbox doesn't hold
+/// up the runtime.
+#[derive(Clone)]
+pub struct MaildirSource {
+    root: PathBuf,
+    /// Messages parsed while searching, by id
+    parsed: Arc<Mutex<HashMap<String, MailMessage>>>,
+}
+
+/// A message file found in the tree
+struct Entry {
+    id: String,
+    path: PathBuf,
+    modified: Option<DateTime<Utc>>,
+}
+
+impl MaildirSource {
+    pub fn open(root: &Path) -> Result<Self> {
+        anyhow::ensure!(root.is_dir(), "MAILDIR_PATH {} is not a directory", root.display());
+        Ok(Self { root: root.to_path_buf(), parsed: Arc::new(Mutex::new(HashMap::new())) })
+    }
+
+    /// Run `read` on a blocking thread
+    async fn blocking<T: Send + 'static>(&self, read: impl FnOnce(&Self) -> Result<T> + Send + 'static) -> Result<T> {
+        let source = self.clone();
+        tokio::task::spawn_blocking(move || read(&source)).await.context("Reading the Maildir stopped")?
+    }
+
+    /// Maildir folders under the root, as paths relative to it ("" is the root itself)
+    fn folders(&self) -> Vec<String> {
+        let mut folders = Vec::new();
+        let mut pending = vec![String::new()];
+        while let Some(folder) = pending.pop() {
+            let dir = self.root.join(&folder);
+            if dir.join("cur").is_dir() || dir.join("new").is_dir() {
+                folders.push(folder.clone());
+            }
+            let Ok(children) = std::fs::read_dir(&dir) else { continue };
+            for child in children.flatten() {
+                let name = child.file_name().to_string_lossy().into_owned();
+                if matches!(name.as_str(), "cur" | "new" | "tmp") || !child.path().is_dir() {
+                    continue;
+                }
+                pending.push(if folder.is_empty() { name } else { format!("{}/{}", folder, name) });
+            }
+        }
+        folders.sort();
+        folders
+    }
+
+    /// Message files of a folder, from `new` and `cur`
+    fn entries(&self, folder: &str) -> Vec<Entry> {
+        let mut entries = Vec::new();
+        for sub in ["new", "cur"] {
+            let Ok(files) = std::fs::read_dir(self.root.join(folder).join(sub)) else { continue };
+            for file in files.flatten() {
+                let name = file.file_name().to_string_lossy().into_owned();
+                if name.starts_with('.') {
+                    continue;
+                }
+                let unique = name.split(':').next().unwrap_or(&name);
+                entries.push(Entry {
+                    id: if folder.is_empty() { unique.to_string() } else { format!("{}/{}", folder, unique) },
+                    path: file.path(),
+                    modified: file.metadata().and_then(|m| m.modified()).ok().map(DateTime::<Utc>::from),
+                });
+            }
+        }
+        entries
+    }
+
+    /// Path of a message by id, wherever its flags have moved it since
+    fn locate(&self, message_id: &str) -> Result<PathBuf> {
+        let (folder, unique) = message_id.rsplit_once('/').unwrap_or(("", message_id));
+        self.entries(folder)
+            .into_iter()
+            .find(|entry| entry.id == message_id)
+            .map(|entry| entry.path)
+            .with_context(|| format!("Message {} no longer exists in the Maildir ({})", message_id, unique))
+    }
+
+    fn read(&self, id: &str, path: &Path, modified: Option<DateTime<Utc>>) -> Result<rfc822::ParsedMessage> {
+        let raw = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut parsed = rfc822::parse(id, &raw, None);
+        parsed.message.folder = id.rsplit_once('/').map_or(MailFolder::Mail, |(folder, _)| MailFolder::from_folder_name(folder));
+        // No Date header: the file time is the best guess
+        parsed.message.received_at = parsed.message.received_at.or(modified);
+        Ok(parsed)
+    }
+
+    /// Messages with attachments in `folders` received from `since` up to `until` (inclusive)
+    /// and passing `filter`, newest first, with copies in several folders listed once
+    fn scan(&self, folders: &[String], since: NaiveDate, until: Option<NaiveDate>, filter: impl Fn(&MailMessage) -> bool) -> Result<Vec<String>> {
+        // Files are written when the message arrives or is synced, never before it was sent,
+        // so older files can be skipped without reading them (a day's margin for timezones)
+        let oldest = since.checked_sub_days(Days::new(1)).unwrap_or(since);
+
+        let mut found: Vec<(Option<DateTime<Utc>>, String)> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut parsed = self.parsed.lock().expect("maildir cache lock poisoned");
+        for folder in folders {
+            for entry in self.entries(folder) {
+                if entry.modified.is_some_and(|modified| modified.date_naive() < oldest) {
+                    continue;
+                }
+                let message = match parsed.get(&entry.id) {
+                    Some(message) => message,
+                    None => match self.read(&entry.id, &entry.path, entry.modified) {
+                        Ok(read) => parsed.entry(entry.id.clone()).or_insert(read.message),
+                        Err(e) => {
+                            log::warn!("Skipping {}: {:#}", entry.path.display(), e);
+                            continue;
+                        }
+                    },
+                };
+
+                let in_range = message
+                    .received_date(None)
+                    .is_none_or(|date| date >= since && until.is_none_or(|until| date <= until));
+                if !in_range || message.attachments.is_empty() || !filter(message) {
+                    continue;
+                }
+                let message_id_header = message.header_values("Message-ID").next().map(str::to_string);
+                if message_id_header.is_some_and(|header| !seen.insert(header)) {
+                    continue;
+                }
+                found.push((message.received_at, entry.id));
+            }
+        }
+
+        found.sort_by_key(|(received_at, _)| std::cmp::Reverse(*received_at));
+        Ok(found.into_iter().map(|(_, id)| id).collect())
+    }
+}
+
+#[async_trait]
+impl MailSource for MaildirSource {
+    fn name(&self) -> &'static str {
+        "Maildir"
+    }
+
+    async fn search_keyword(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        keyword: &str,
+        exclusions: &Exclusions,
+        max_results: Option<usize>,
+    ) -> Result<Vec<String>> {
+        let (keyword, exclusions) = (keyword.to_string(), exclusions.clone());
+        let mut ids = self
+            .blocking(move |source| {
+                // Like Gmail, spam and trash only when asked, drafts never
+                let folders: Vec<String> = source
+                    .folders()
+                    .into_iter()
+                    .filter(|folder| match MailFolder::from_folder_name(folder) {
+                        MailFolder::Mail => true,
+                        MailFolder::Spam => exclusions.include_spam,
+                        MailFolder::Trash => exclusions.include_trash,
+                        MailFolder::Drafts | MailFolder::Chats => false,
+                    })
+                    .collect();
+                source.scan(&folders, start_date, Some(end_date), |message| {
+                    message.mentions(&keyword) && !exclusions.keywords.iter().any(|term| message.mentions(term))
+                })
+            })
+            .await?;
+        if let Some(max_results) = max_results {
+            ids.truncate(max_results);
+        }
+        Ok(ids)
+    }
+
+    async fn search_queue(&self, queue: &IngestQueue, since: NaiveDate) -> Result<Vec<String>> {
+        let queue = queue.clone();
+        self.blocking(move |source| match &queue {
+            // A label is a folder: `invoice-inbox/` or Maildir++ `.invoice-inbox/`
+            IngestQueue::Label(label) => {
+                let folders: Vec<String> = source
+                    .folders()
+                    .into_iter()
+                    .filter(|folder| folder.eq_ignore_ascii_case(label) || folder.eq_ignore_ascii_case(&format!(".{}", label)))
+                    .collect();
+                anyhow::ensure!(!folders.is_empty(), "No Maildir folder named '{}' in {}", label, source.root.display());
+                source.scan(&folders, since, None, |_| true)
+            }
+            IngestQueue::Address(address) => source.scan(&source.folders(), since, None, |message| {
+                ["To", "Cc", "Delivered-To"]
+                    .iter()
+                    .flat_map(|name| message.header_values(name))
+                    .any(|value| value.to_lowercase().contains(address.as_str()))
+            }),
+        })
+        .await
+    }
+
+    async fn fetch_message(&self, message_id: &str) -> Result<MailMessage> {
+        if let Some(message) = self.parsed.lock().expect("maildir cache lock poisoned").get(message_id) {
+            return Ok(message.clone());
+        }
+        let message_id = message_id.to_string();
+        self.blocking(move |source| {
+            let path = source.locate(&message_id)?;
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok().map(DateTime::<Utc>::from);
+            Ok(source.read(&message_id, &path, modified)?.message)
+        })
+        .await
+    }
+
+    async fn download_attachment(&self, message_id: &str, attachment_id: &str) -> Result<Vec<u8>> {
+        let (message_id, attachment_id) = (message_id.to_string(), attachment_id.to_string());
+        self.blocking(move |source| {
+            let parsed = source.read(&message_id, &source.locate(&message_id)?, None)?;
+            let index = parsed.message.attachments.iter().position(|attachment| attachment.attachment_id == attachment_id);
+            index
+                .and_then(|index| parsed.attachments.into_iter().nth(index))
+                .with_context(|| format!("Attachment {} not found in message {}", attachment_id, message_id))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deliver(dir: &Path, name: &str, date: &str, subject: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        let raw = format!(
+            "From: billing@vendor.com\r\nTo: me@example.com\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@vendor.com>\r\n\
+Content-Type: multipart/mixed; boundary=b\r\n\r\n--b\r\nContent-Type: text/plain\r\n\r\nSee attached\r\n\
+--b\r\nContent-Type: application/pdf; name=\"doc.pdf\"\r\nContent-Transfer-Encoding: base64\r\n\r\nJVBERi0xLjQK\r\n--b--\r\n",
+            subject, date, subject.replace(' ', "-")
+        );
+        std::fs::write(dir.join(name), raw).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_search_across_folders() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        deliver(&root.join("INBOX/cur"), "1741000000.1.host:2,S", "Tue, 4 Mar 2025 09:00:00 +0000", "March invoice");
+        deliver(&root.join("INBOX/new"), "1741500000.2.host", "Mon, 10 Mar 2025 09:00:00 +0000", "Proforma invoice");
+        deliver(&root.join("Archive/cur"), "1741000000.3.host:2,S", "Tue, 4 Mar 2025 09:00:00 +0000", "March invoice");
+        deliver(&root.join("Archive/cur"), "1738000000.4.host:2,S", "Mon, 3 Feb 2025 09:00:00 +0000", "February invoice");
+        deliver(&root.join(".Trash/cur"), "1741600000.5.host:2,S", "Tue, 11 Mar 2025 09:00:00 +0000", "Old invoice");
+
+        let source = MaildirSource::open(root).unwrap();
+        let exclusions = Exclusions { keywords: vec!["proforma".to_string()], ..Default::default() };
+        let march = (NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(), NaiveDate::from_ymd_opt(2025, 3, 31).unwrap());
+        let ids = source.search_keyword(march.0, march.1, "INVOICE", &exclusions, None).await.unwrap();
+        // The copy in Archive is the same message (Message-ID), Trash is skipped
+        assert_eq!(ids, vec!["Archive/1741000000.3.host"]);
+
+        let message = source.fetch_message(&ids[0]).await.unwrap();
+        assert_eq!(message.subject, "March invoice");
+        assert_eq!(source.download_attachment(&ids[0], "0").await.unwrap(), b"%PDF-1.4\n");
+
+        // Only when asked, and marked as found there
+        let with_trash = Exclusions { include_trash: true, ..exclusions };
+        let ids = source.search_keyword(march.0, march.1, "INVOICE", &with_trash, None).await.unwrap();
+        assert_eq!(ids, vec![".Trash/1741600000.5.host", "Archive/1741000000.3.host"]);
+        assert_eq!(source.fetch_message(&ids[0]).await.unwrap().folder, MailFolder::Trash);
+    }
+}
@@ -0,0 +1,214 @@
+use base64::prelude::*;
+
+/// Readable text of one MIME body part: undo the transfer encoding, decode the charset and
+/// strip markup from HTML. `transfer_encoding` is None when the source already decoded it.
+pub fn part_text(mime_type: &str, content_type: &str, transfer_encoding: Option<&str>, data: &[u8]) -> String {
+    let bytes = decode_transfer_encoding(transfer_encoding, data);
+    let text = decode_charset(&bytes, charset(content_type));
+    if mime_type.eq_ignore_ascii_case("text/html") {
+        html_to_text(&text)
+    } else {
+        text
+    }
+}
+
+/// Undo a Content-Transfer-Encoding (base64 or quoted-printable; anything else is kept as is)
+pub fn decode_transfer_encoding(transfer_encoding: Option<&str>, data: &[u8]) -> Vec<u8> {
+    match transfer_encoding.map(|e| e.trim().to_ascii_lowercase()).as_deref() {
+        Some("quoted-printable") => decode_quoted_printable(data),
+        Some("base64") => {
+            let compact: Vec<u8> = data.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+            BASE64_STANDARD.decode(&compact).unwrap_or_else(|_| data.to_vec())
+        }
+        _ => data.to_vec(),
+    }
+}
+
+/// Decode the RFC 2047 encoded words of a header value (`=?UTF-8?Q?Fatura_n=C2=BA_12?=`).
+/// Whitespace between two adjacent encoded words is dropped, as the RFC requires.
+pub fn decode_header(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = rest.split_at(start);
+        match decode_encoded_word(candidate) {
+            Some((text, len)) => {
+                if !(after_word && before.trim().is_empty()) {
+                    out.push_str(before);
+                }
+                out.push_str(&text);
+                rest = &candidate[len..];
+                after_word = true;
+            }
+            None => {
+                out.push_str(before);
+                out.push_str("=?");
+                rest = &candidate[2..];
+                after_word = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Decode one `=?charset?encoding?text?=` word at the start of `word`, with its length
+fn decode_encoded_word(word: &str) -> Option<(String, usize)> {
+    let inner = word.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let end = inner.find("?=")?;
+    let text = &inner[..end];
+    if text.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let bytes = match encoding {
+        "B" | "b" => BASE64_STANDARD.decode(text.trim_end_matches('=')).or_else(|_| BASE64_STANDARD_NO_PAD.decode(text.trim_end_matches('='))).ok()?,
+        "Q" | "q" => decode_quoted_printable(text.replace('_', " ").as_bytes()),
+        _ => return None,
+    };
+    // RFC 2231 allows a language after the charset: `UTF-8*pt`
+    let charset = charset.split('*').next().unwrap_or(charset);
+    Some((decode_charset(&bytes, Some(charset)), word.len() - inner[end + 2..].len()))
+}
+
+/// `charset` parameter of a Content-Type header value
+fn charset(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim().eq_ignore_ascii_case("charset").then(|| value.trim().trim_matches('"'))
+    })
+}
+
+fn decode_charset(bytes: &[u8], charset: Option<&str>) -> String {
+    let encoding = charset
+        .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode(bytes).0.into_owned()
+}
+
+/// Decode quoted-printable (RFC 2045): `=XX` escapes and `=` soft line breaks
+fn decode_quoted_printable(data: &[u8]) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] != b'=' {
+            out.push(data[i]);
+            i += 1;
+            continue;
+        }
+        match (data.get(i + 1).copied(), data.get(i + 2).copied()) {
+            (Some(b'\r'), Some(b'\n')) => i += 3,
+            (Some(b'\n'), _) => i += 2,
+            (Some(high), Some(low)) if hex(high).is_some() && hex(low).is_some() => {
+                out.push(hex(high).unwrap() << 4 | hex(low).unwrap());
+                i += 3;
+            }
+            // Malformed escape: keep it as is
+            _ => {
+                out.push(b'=');
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Plain text of an HTML body: scripts, styles and tags removed, entities decoded, whitespace collapsed
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let lower = html.to_ascii_lowercase();
+    let mut i = 0;
+    while i < html.len() {
+        if html[i..].starts_with('<') {
+            // Skip the content of script and style elements entirely
+            let skip_until = ["script", "style", "head"].iter().find_map(|tag| {
+                let opens = lower[i + 1..].starts_with(tag)
+                    && lower[i + 1 + tag.len()..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace());
+                opens.then(|| format!("</{}", tag))
+            });
+            let end = match skip_until {
+                Some(closing) => lower[i..].find(&closing).map(|pos| i + pos + closing.len()),
+                None => Some(i),
+            };
+            i = match end.and_then(|from| lower[from..].find('>').map(|pos| from + pos + 1)) {
+                Some(next) => next,
+                None => html.len(),
+            };
+            text.push(' ');
+        } else {
+            let next = html[i..].find('<').map_or(html.len(), |pos| i + pos);
+            text.push_str(&decode_entities(&html[i..next]));
+            i = next;
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            }?;
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_part_text() {
+        let qp = b"Extrato banc=C3=A1rio do Millennium BCP =\r\ndispon=C3=ADvel";
+        assert_eq!(part_text("text/plain", "text/plain; charset=\"UTF-8\"", Some("quoted-printable"), qp), "Extrato bancário do Millennium BCP disponível");
+
+        let latin1 = b"Caixa Geral de Dep\xf3sitos";
+        assert_eq!(part_text("text/plain", "text/plain; charset=ISO-8859-1", None, latin1), "Caixa Geral de Depósitos");
+
+        let base64 = b"WW91ciBSZXZvbHV0\r\nIHN0YXRlbWVudA==";
+        assert_eq!(part_text("text/plain", "text/plain", Some("base64"), base64), "Your Revolut statement");
+
+        let html = "<html><head><title>x</title><style>p { color: red }</style></head>\
+            <body><p>Your <b>Wise</b>&nbsp;statement &amp; receipt&#33;</p><script>var bank = 1;</script></body></html>";
+        assert_eq!(html_to_text(html), "Your Wise statement & receipt!");
+        assert_eq!(html_to_text("Q&A &bogus;"), "Q&A &bogus;");
+
+        assert_eq!(decode_header("=?UTF-8?Q?Fatura_n=C2=BA?= =?UTF-8?B?IDEyMw==?= (mar\u{e7}o)"), "Fatura nº 123 (março)");
+        assert_eq!(decode_header("=?ISO-8859-1?Q?Caixa_Geral_de_Dep=F3sitos?= <x@cgd.pt>"), "Caixa Geral de Depósitos <x@cgd.pt>");
+        assert_eq!(decode_header("Plain =?bogus subject"), "Plain =?bogus subject");
+    }
+}
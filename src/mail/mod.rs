@@ -0,0 +1,261 @@
+pub mod attachment;
+pub mod cache;
+pub mod decrypt;
+pub mod download;
+pub mod imap;
+pub mod jmap;
+pub mod maildir;
+pub mod mime;
+pub mod phishing;
+pub mod proton;
+pub mod rfc822;
+pub mod search;
+pub mod smtp;
+pub mod vendors;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use crate::auth;
+use crate::config::env::Config;
+use crate::gmail;
+use crate::process::outcome::FailureKind;
+use serde::{Deserialize, Serialize};
+
+/// A mailbox the pipeline can pull invoices from (Gmail, ProtonMail Bridge, JMAP or a local Maildir today; Outlook, mbox later)
+#[async_trait]
+pub trait MailSource: Send + Sync {
+    /// Short human readable name used in progress messages
+    fn name(&self) -> &'static str;
+
+    /// Find ids of messages matching a single keyword, and none of the exclusions, within the
+    /// date range, newest first (all result pages, or the first `max_results` ids). Keywords are
+    /// merged by `search::search_keywords`.
+    async fn search_keyword(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        keyword: &str,
+        exclusions: &search::Exclusions,
+        max_results: Option<usize>,
+    ) -> Result<Vec<String>>;
+
+    /// Rough number of messages `search_keyword` would find, when the mailbox can tell without
+    /// listing them (None otherwise)
+    async fn estimate_keyword(
+        &self,
+        _start_date: NaiveDate,
+        _end_date: NaiveDate,
+        _keyword: &str,
+        _exclusions: &search::Exclusions,
+    ) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Find ids of messages with attachments in the ingestion queue that arrived on or after
+    /// `since` (all result pages), whatever their subject or sender
+    async fn search_queue(&self, queue: &search::IngestQueue, since: NaiveDate) -> Result<Vec<String>>;
+
+    /// Fetch a message's headers, body and attachment list
+    async fn fetch_message(&self, message_id: &str) -> Result<MailMessage>;
+
+    /// Download the raw bytes of a single attachment
+    async fn download_attachment(&self, message_id: &str, attachment_id: &str) -> Result<Vec<u8>>;
+}
+
+/// Source-agnostic view of an email message
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MailMessage {
+    pub id: String,
+    pub from: String,
+    pub subject: String,
+    pub received_at: Option<DateTime<Utc>>,
+    /// Readable text of the text/plain and text/html parts (HTML stripped)
+    pub body: String,
+    /// All top-level headers in message order (names as sent)
+    pub headers: Vec<(String, String)>,
+    pub attachments: Vec<AttachmentRef>,
+    /// Folder the message was found in
+    #[serde(default)]
+    pub folder: MailFolder,
+}
+
+/// Mailbox folders keyword runs treat apart: spam and trash are only searched when asked
+/// (INCLUDE_SPAM, INCLUDE_TRASH), drafts and chats never
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum MailFolder {
+    /// Anywhere else: the inbox, archived mail, labels and user folders
+    #[default]
+    Mail,
+    Spam,
+    Trash,
+    Drafts,
+    Chats,
+}
+
+impl MailFolder {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Mail => "Mail",
+            Self::Spam => "Spam",
+            Self::Trash => "Trash",
+            Self::Drafts => "Drafts",
+            Self::Chats => "Chats",
+        }
+    }
+
+    /// Drafts and chats are never archived, whatever a search returned
+    pub fn is_excluded(self) -> bool {
+        matches!(self, Self::Drafts | Self::Chats)
+    }
+
+    /// Folder of a Gmail message from its system labels
+    pub fn from_gmail_labels(labels: &[String]) -> Self {
+        let has = |label: &str| labels.iter().any(|l| l == label);
+        if has("CHAT") {
+            Self::Chats
+        } else if has("DRAFT") {
+            Self::Drafts
+        } else if has("SPAM") {
+            Self::Spam
+        } else if has("TRASH") {
+            Self::Trash
+        } else {
+            Self::Mail
+        }
+    }
+
+    /// Folder of a mailbox or Maildir folder by its name (`Spam`, `INBOX.Junk`, `.Trash`)
+    pub fn from_folder_name(name: &str) -> Self {
+        let name = name.rsplit(['/', '.']).next().unwrap_or(name).to_lowercase();
+        match name.as_str() {
+            "spam" | "junk" => Self::Spam,
+            "trash" | "deleted messages" | "deleted items" | "bin" => Self::Trash,
+            "drafts" | "draft" => Self::Drafts,
+            "chats" => Self::Chats,
+            _ => Self::Mail,
+        }
+    }
+}
+
+impl MailMessage {
+    /// Whether the message arrived within the inclusive date range, in the mailbox timezone
+    /// (the machine's local timezone when None). Messages without a receive time pass.
+    pub fn received_within(&self, start_date: NaiveDate, end_date: NaiveDate, timezone: Option<Tz>) -> bool {
+        self.received_date(timezone).is_none_or(|date| (start_date..=end_date).contains(&date))
+    }
+
+    /// Whether a term occurs in the sender, subject, body or attachment names (case-insensitive)
+    pub fn mentions(&self, term: &str) -> bool {
+        let term = term.to_lowercase();
+        [&self.from, &self.subject, &self.body]
+            .into_iter()
+            .chain(self.attachments.iter().map(|attachment| &attachment.filename))
+            .any(|text| text.to_lowercase().contains(&term))
+    }
+
+    /// Calendar date the message arrived on in `timezone` (local time when unset)
+    pub fn received_date(&self, timezone: Option<Tz>) -> Option<NaiveDate> {
+        let received_at = self.received_at?;
+        Some(match timezone {
+            Some(tz) => received_at.with_timezone(&tz).date_naive(),
+            None => received_at.with_timezone(&chrono::Local).date_naive(),
+        })
+    }
+
+    /// Values of every header with this name (case-insensitive)
+    pub fn header_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// An attachment listed on a message, downloaded on demand
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AttachmentRef {
+    pub filename: String,
+    pub attachment_id: String,
+    #[allow(dead_code)]
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+}
+
+/// Build the mail source selected by the configuration, authenticating if needed
+pub async fn connect(config: &Config) -> Result<Box<dyn MailSource>> {
+    if config.mock_mode {
+        return Ok(Box::new(gmail::client::GmailClient::mock(&config.mock_fixtures_dir)));
+    }
+
+    if let Some(bridge) = &config.proton_bridge {
+        let source = proton::ProtonBridgeSource::new(bridge.clone()).context(FailureKind::Config)?;
+        return Ok(with_cache(Box::new(source), config).await);
+    }
+
+    if let Some(settings) = &config.jmap {
+        let source = jmap::JmapSource::connect(settings).await.context(FailureKind::Auth)?;
+        return Ok(with_cache(Box::new(source), config).await);
+    }
+
+    // Local files: nothing to gain from the metadata cache
+    if let Some(path) = &config.maildir_path {
+        return Ok(Box::new(maildir::MaildirSource::open(path).context(FailureKind::Config)?));
+    }
+
+    let gmail_token = match (&config.google_service_account_key, &config.gmail_user) {
+        (Some(key), Some(user)) => auth::gmail_auth::get_delegated_gmail_token(key, user).await.context(FailureKind::Auth)?,
+        _ => {
+            let signed_in = async {
+                let gmail_token = auth::gmail_auth::get_gmail_token_for_profile(
+                    config.gmail_client_id.clone(),
+                    config.gmail_client_secret.clone(),
+                    config.gmail_account.as_deref(),
+                    config.profile.as_deref(),
+                    config.paste_code,
+                )
+                .await
+                .context(FailureKind::Auth)?;
+                // Service account tokens are minted for the scope they ask for; a cached OAuth
+                // token may have been revoked or granted less
+                auth::preflight::check_token(&gmail_token, auth::gmail_auth::GMAIL_SCOPE, "Gmail", "invoice-pilot auth gmail").await?;
+                anyhow::Ok(gmail_token)
+            };
+            auth::failure::track(config.profile.as_deref(), auth::failure::Service::Gmail, signed_in.await)?
+        }
+    };
+
+    let mut client = gmail::client::GmailClient::new(gmail_token);
+    if let Some(user) = &config.gmail_user {
+        client = client.with_user(user);
+    }
+    Ok(with_cache(Box::new(client), config).await)
+}
+
+/// Cache message metadata in the database when one is configured and reachable
+async fn with_cache(source: Box<dyn MailSource>, config: &Config) -> Box<dyn MailSource> {
+    match crate::db::connect_optional("Message metadata cache").await {
+        Some(pool) => Box::new(cache::CachedSource::new(source, pool, config.profile.as_deref(), config.refresh)),
+        None => source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_received_within_uses_mailbox_timezone() {
+        // 2025-03-31 23:30 UTC is already April 1st in Lisbon summer time
+        let message = MailMessage {
+            received_at: DateTime::parse_from_rfc3339("2025-03-31T23:30:00Z").ok().map(|d| d.with_timezone(&Utc)),
+            ..Default::default()
+        };
+        let march = (NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(), NaiveDate::from_ymd_opt(2025, 3, 31).unwrap());
+
+        assert!(message.received_within(march.0, march.1, Some(chrono_tz::UTC)));
+        assert!(!message.received_within(march.0, march.1, Some(chrono_tz::Europe::Lisbon)));
+        assert!(MailMessage::default().received_within(march.0, march.1, None));
+    }
+}
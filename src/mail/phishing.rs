@@ -0,0 +1,176 @@
+use super::MailMessage;
+
+/// Vendors that are commonly impersonated: (name in display names, registrable domain labels they send from)
+const KNOWN_VENDORS: &[(&str, &[&str])] = &[
+    ("amazon", &["amazon", "amazonaws"]), ("aws", &["amazon", "amazonaws"]), ("paypal", &["paypal"]),
+    ("stripe", &["stripe"]), ("apple", &["apple", "icloud"]), ("google", &["google", "googlemail"]),
+    ("microsoft", &["microsoft", "office", "outlook"]), ("adobe", &["adobe"]), ("docusign", &["docusign"]),
+    ("dropbox", &["dropbox"]), ("github", &["github"]), ("hetzner", &["hetzner"]),
+    ("digitalocean", &["digitalocean"]), ("revolut", &["revolut"]), ("wise", &["wise", "transferwise"]),
+    ("n26", &["n26"]), ("coinbase", &["coinbase"]), ("binance", &["binance"]),
+    ("santander", &["santander"]), ("bbva", &["bbva"]),
+];
+
+/// Second-level labels that sit under a country code (example.co.uk, example.com.br)
+const GENERIC_SLDS: &[&str] = &["co", "com", "net", "org", "gov", "ac", "edu"];
+
+/// Reasons a message looks spoofed or phishy; empty when nothing suspicious was found
+pub fn assess(message: &MailMessage) -> Vec<String> {
+    let mut reasons = Vec::new();
+
+    for results in message.header_values("Authentication-Results") {
+        reasons.extend(failed_authentication(results));
+    }
+
+    let (display_name, address) = split_from(&message.from);
+    let Some(domain) = address.rsplit_once('@').map(|(_, d)| d.to_lowercase()) else {
+        return reasons;
+    };
+    let root = domain_root(&domain);
+
+    if domain.split('.').any(|label| label.starts_with("xn--")) {
+        reasons.push(format!("internationalized (punycode) sender domain {}", domain));
+    }
+
+    // "PayPal <billing@paypa1-support.com>": the display name claims a vendor the domain doesn't belong to
+    let name_words = words(&display_name);
+    for (vendor, vendor_roots) in KNOWN_VENDORS {
+        if name_words.iter().any(|w| w == vendor) && !vendor_roots.contains(&root.as_str()) {
+            reasons.push(format!("display name mentions {} but the sender domain is {}", vendor, domain));
+            break;
+        }
+    }
+
+    // An address in the display name that differs from the real sender
+    if let Some((_, shown_domain)) = display_name.rsplit_once('@') {
+        let shown_domain = shown_domain.trim_end_matches(['>', '"', ' ']).to_lowercase();
+        if !shown_domain.is_empty() && shown_domain != domain {
+            reasons.push(format!("display name shows {} but mail comes from {}", shown_domain, domain));
+        }
+    }
+
+    if let Some(vendor_root) = lookalike_of(&root) {
+        reasons.push(format!("sender domain {} looks like {}", domain, vendor_root));
+    }
+
+    reasons.dedup();
+    reasons
+}
+
+/// SPF/DKIM/DMARC verdicts that failed in an Authentication-Results header
+fn failed_authentication(results: &str) -> Vec<String> {
+    let results = results.to_lowercase();
+    ["spf", "dkim", "dmarc"]
+        .iter()
+        .filter_map(|method| {
+            let verdict = results
+                .split([';', ' ', '\n', '\t'])
+                .find_map(|token| token.trim().strip_prefix(&format!("{}=", method)))?;
+            matches!(verdict, "fail" | "softfail" | "permerror")
+                .then(|| format!("{} {}", method.to_uppercase(), verdict))
+        })
+        .collect()
+}
+
+/// Split `"Name" <user@domain>` into the display name and the address
+fn split_from(from: &str) -> (String, String) {
+    match (from.rfind('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => (
+            from[..start].trim().trim_matches('"').to_string(),
+            from[start + 1..end].trim().to_string(),
+        ),
+        _ => (String::new(), from.trim().to_string()),
+    }
+}
+
+/// Registrable label of a domain: `billing.paypal.co.uk` -> `paypal`
+pub(super) fn domain_root(domain: &str) -> String {
+    let labels: Vec<&str> = domain.split('.').filter(|l| !l.is_empty()).collect();
+    let index = match labels.len() {
+        0 => return String::new(),
+        1 => 0,
+        n if n >= 3 && GENERIC_SLDS.contains(&labels[n - 2]) => n - 3,
+        n => n - 2,
+    };
+    labels[index].to_string()
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Known vendor this domain label imitates: homoglyph swaps (paypa1, rnicrosoft) or one-letter
+/// typos (amazom), alone or as one word of a hyphenated label (paypa1-billing). A genuine vendor
+/// name beside other words (amazon-payments) takes both to be flagged.
+fn lookalike_of(root: &str) -> Option<&'static str> {
+    let vendor_roots = || KNOWN_VENDORS.iter().flat_map(|(_, roots)| roots.iter().copied());
+
+    std::iter::once(root).chain(root.split('-')).find_map(|label| {
+        if vendor_roots().any(|vendor_root| vendor_root == label) {
+            return None;
+        }
+        let normalized = label
+            .replace("rn", "m")
+            .replace("vv", "w")
+            .replace('0', "o")
+            .replace('1', "l");
+        vendor_roots().find(|vendor_root| {
+            normalized == *vendor_root || (vendor_root.len() >= 5 && edit_distance(label, vendor_root) == 1)
+        })
+    })
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            current.push((previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(from: &str, auth: Option<&str>) -> MailMessage {
+        MailMessage {
+            from: from.to_string(),
+            headers: auth.map(|a| vec![("Authentication-Results".to_string(), a.to_string())]).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_legitimate_senders_pass() {
+        assert!(assess(&message("Amazon Web Services <no-reply@aws.amazon.com>", Some("mx.google.com; dkim=pass; spf=pass; dmarc=pass"))).is_empty());
+        assert!(assess(&message("PayPal <service@paypal.co.uk>", None)).is_empty());
+        assert!(assess(&message("Otherwise Ltd <billing@otherwise.io>", None)).is_empty());
+        assert!(assess(&message("billing@hetzner.com", None)).is_empty());
+        assert!(assess(&message("AWS Marketplace <no-reply@marketplace.amazonaws.com>", None)).is_empty());
+    }
+
+    #[test]
+    fn test_flags_spoofing_signals() {
+        let reasons = assess(&message("PayPal <billing@paypa1.com>", Some("mx.google.com; spf=softfail smtp.mailfrom=paypa1.com; dkim=none")));
+        assert!(reasons.contains(&"SPF softfail".to_string()));
+        assert!(reasons.iter().any(|r| r.starts_with("display name mentions paypal")));
+        assert!(reasons.contains(&"sender domain paypa1.com looks like paypal".to_string()));
+
+        assert!(!assess(&message("Billing <invoices@paypa1-billing.com>", None)).is_empty());
+        // A vendor's name next to other words is not a lookalike by itself
+        assert!(assess(&message("Billing <invoices@amazon-payments-center.com>", None)).is_empty());
+        assert!(!assess(&message("Stripe <receipts@strlpe.com>", None)).is_empty());
+        assert!(!assess(&message("\"accounts@stripe.com\" <x@evil.example>", None)).is_empty());
+        assert_eq!(domain_root("billing.paypal.co.uk"), "paypal");
+    }
+}
@@ -0,0 +1,385 @@
+//! ProtonMail Bridge source: reads the mailbox over the Bridge's local IMAP server and sends
+//! through its SMTP server. The Bridge presents a self-signed certificate, so instead of the
+//! system roots its exported certificate is pinned (PROTON_BRIDGE_CERT); on this machine any
+//! certificate is accepted when none is configured, since the traffic never leaves it.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{Days, NaiveDate};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_rustls::rustls::{self, ClientConfig, DigitallySignedStruct, SignatureScheme};
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use super::imap::{ImapSession, Search, TlsMode};
+use super::search::{Exclusions, IngestQueue};
+use super::{rfc822, smtp, MailFolder, MailMessage, MailSource};
+
+/// Attachments decoded but not downloaded yet are dropped, oldest first, beyond this size;
+/// downloading one of them fetches its message again
+const MAX_DECODED_BYTES: usize = 128 * 1024 * 1024;
+
+/// Connection settings of a local ProtonMail Bridge (PROTON_BRIDGE_*)
+#[derive(Clone)]
+pub struct BridgeSettings {
+    pub host: String,
+    pub imap_port: u16,
+    pub smtp_port: u16,
+    pub security: TlsMode,
+    /// Proton address, as shown in the Bridge's mailbox details
+    pub username: String,
+    /// Password generated by the Bridge (not the Proton account password)
+    pub password: String,
+    /// Certificate exported from the Bridge settings, pinned when set
+    pub cert: Option<PathBuf>,
+    /// Folder searched by keyword runs, "All Mail" by default
+    pub mailbox: String,
+}
+
+impl std::fmt::Debug for BridgeSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BridgeSettings")
+            .field("host", &self.host)
+            .field("imap_port", &self.imap_port)
+            .field("smtp_port", &self.smtp_port)
+            .field("security", &self.security)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("cert", &self.cert)
+            .field("mailbox", &self.mailbox)
+            .finish()
+    }
+}
+
+impl BridgeSettings {
+    /// TLS configuration trusting exactly the Bridge's certificate
+    fn tls_config(&self) -> Result<Arc<ClientConfig>> {
+        let pinned = match &self.cert {
+            Some(path) => Some(
+                CertificateDer::from_pem_file(path)
+                    .with_context(|| format!("Failed to read the Bridge certificate {}", path.display()))?,
+            ),
+            None => {
+                let loopback = self.host == "localhost" || self.host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+                anyhow::ensure!(
+                    loopback,
+                    "PROTON_BRIDGE_CERT is required when the Bridge runs on another machine ({}); export the certificate from the Bridge settings",
+                    self.host
+                );
+                None
+            }
+        };
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .context("Failed to set up TLS")?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier { pinned, provider }))
+            .with_no_client_auth();
+        Ok(Arc::new(config))
+    }
+}
+
+/// Accepts the pinned certificate only (or any certificate when none is pinned), while still
+/// checking that the server holds its key
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    pinned: Option<CertificateDer<'static>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match &self.pinned {
+            Some(pinned) if pinned.as_ref() != end_entity.as_ref() => Err(rustls::Error::General(
+                "the server certificate is not the pinned Bridge certificate (PROTON_BRIDGE_CERT)".to_string(),
+            )),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Mail source reading a Proton mailbox through the Bridge. Message ids are
+/// `<folder>/<uid>` so messages found in a label folder can be fetched later.
+pub struct ProtonBridgeSource {
+    settings: BridgeSettings,
+    tls: Arc<ClientConfig>,
+    session: Mutex<Option<Session>>,
+    /// Attachments decoded by `fetch_message`, until downloaded
+    attachments: Mutex<Decoded>,
+}
+
+/// Decoded attachments by message and attachment id, at most `limit` bytes of them
+struct Decoded {
+    limit: usize,
+    data: HashMap<(String, String), Vec<u8>>,
+    order: VecDeque<(String, String)>,
+    bytes: usize,
+}
+
+impl Decoded {
+    fn new(limit: usize) -> Self {
+        Self { limit, data: HashMap::new(), order: VecDeque::new(), bytes: 0 }
+    }
+
+    fn insert(&mut self, key: (String, String), data: Vec<u8>) {
+        self.bytes += data.len();
+        if let Some(replaced) = self.data.insert(key.clone(), data) {
+            self.bytes -= replaced.len();
+        } else {
+            self.order.push_back(key);
+        }
+        while self.bytes > self.limit
+            && let Some(oldest) = self.order.pop_front()
+        {
+            if let Some(dropped) = self.data.remove(&oldest) {
+                self.bytes -= dropped.len();
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &(String, String)) -> Option<Vec<u8>> {
+        let data = self.data.remove(key)?;
+        self.bytes -= data.len();
+        self.order.retain(|queued| queued != key);
+        Some(data)
+    }
+}
+
+struct Session {
+    imap: ImapSession,
+    mailbox: Option<String>,
+}
+
+impl ProtonBridgeSource {
+    pub fn new(settings: BridgeSettings) -> Result<Self> {
+        let tls = settings.tls_config()?;
+        Ok(Self {
+            settings,
+            tls,
+            session: Mutex::new(None),
+            attachments: Mutex::new(Decoded::new(MAX_DECODED_BYTES)),
+        })
+    }
+
+    /// Run `f` on a logged-in session with `mailbox` open, reconnecting after a failure
+    async fn with_mailbox<T>(
+        &self,
+        mailbox: &str,
+        f: impl AsyncFnOnce(&mut ImapSession) -> Result<T>,
+    ) -> Result<T> {
+        let mut guard = self.session.lock().await;
+        if guard.is_none() {
+            let mut imap = ImapSession::connect(&self.settings.host, self.settings.imap_port, self.settings.security, self.tls.clone())
+                .await
+                .context("Failed to connect to ProtonMail Bridge")?;
+            imap.login(&self.settings.username, &self.settings.password).await?;
+            *guard = Some(Session { imap, mailbox: None });
+        }
+
+        let session = guard.as_mut().expect("session was just opened");
+        let result = async {
+            if session.mailbox.as_deref() != Some(mailbox) {
+                session.mailbox = None;
+                session.imap.examine(mailbox).await?;
+                session.mailbox = Some(mailbox.to_string());
+            }
+            f(&mut session.imap).await
+        }
+        .await;
+
+        // Start over with a fresh connection next time rather than reuse one in an unknown state
+        if result.is_err() {
+            *guard = None;
+        }
+        result
+    }
+
+    async fn search(&self, mailbox: &str, search: &Search<'_>, max_results: Option<usize>) -> Result<Vec<String>> {
+        let mut uids = self.with_mailbox(mailbox, async |imap| imap.uid_search(search).await).await?;
+        // Newest first, like Gmail (UIDs grow with arrival)
+        uids.reverse();
+        if let Some(max_results) = max_results {
+            uids.truncate(max_results);
+        }
+        Ok(uids.into_iter().map(|uid| format!("{}/{}", mailbox, uid)).collect())
+    }
+}
+
+/// Split a message id into its folder and UID
+fn split_id(message_id: &str) -> Result<(&str, u32)> {
+    message_id
+        .rsplit_once('/')
+        .and_then(|(mailbox, uid)| Some((mailbox, uid.parse().ok()?)))
+        .with_context(|| format!("Invalid Bridge message id '{}'", message_id))
+}
+
+#[async_trait]
+impl MailSource for ProtonBridgeSource {
+    fn name(&self) -> &'static str {
+        "ProtonMail Bridge"
+    }
+
+    async fn search_keyword(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        keyword: &str,
+        exclusions: &Exclusions,
+        max_results: Option<usize>,
+    ) -> Result<Vec<String>> {
+        let search = Search {
+            since: Some(start_date),
+            before: end_date.checked_add_days(Days::new(1)),
+            text: Some(keyword),
+            not_text: &exclusions.keywords,
+            ..Default::default()
+        };
+        let mut ids = self.search(&self.settings.mailbox, &search, max_results).await?;
+        // The Bridge keeps spam and trash in folders of their own
+        for (included, mailbox) in [(exclusions.include_spam, "Spam"), (exclusions.include_trash, "Trash")] {
+            if included {
+                ids.extend(self.search(mailbox, &search, max_results).await?);
+            }
+        }
+        if let Some(max_results) = max_results {
+            ids.truncate(max_results);
+        }
+        Ok(ids)
+    }
+
+    async fn estimate_keyword(&self, start_date: NaiveDate, end_date: NaiveDate, keyword: &str, exclusions: &Exclusions) -> Result<Option<u64>> {
+        // The Bridge searches its local index, so counting is as cheap as estimating
+        let found = self.search_keyword(start_date, end_date, keyword, exclusions, None).await?;
+        Ok(Some(found.len() as u64))
+    }
+
+    async fn search_queue(&self, queue: &IngestQueue, since: NaiveDate) -> Result<Vec<String>> {
+        match queue {
+            // Proton labels appear as folders under Labels/
+            IngestQueue::Label(label) => {
+                let folder = if label.contains('/') { label.clone() } else { format!("Labels/{}", label) };
+                self.search(&folder, &Search { since: Some(since), ..Default::default() }, None).await
+            }
+            IngestQueue::Address(address) => {
+                let search = Search { since: Some(since), to: Some(address), ..Default::default() };
+                self.search(&self.settings.mailbox, &search, None).await
+            }
+        }
+    }
+
+    async fn fetch_message(&self, message_id: &str) -> Result<MailMessage> {
+        let (mailbox, uid) = split_id(message_id)?;
+        let (raw, received_at) = self
+            .with_mailbox(mailbox, async |imap| imap.uid_fetch(uid).await)
+            .await?
+            .with_context(|| format!("Message {} no longer exists", message_id))?;
+
+        let mut parsed = rfc822::parse(message_id, &raw, received_at);
+        parsed.message.folder = MailFolder::from_folder_name(mailbox);
+        let mut attachments = self.attachments.lock().await;
+        for (attachment, data) in parsed.message.attachments.iter().zip(parsed.attachments) {
+            attachments.insert((message_id.to_string(), attachment.attachment_id.clone()), data);
+        }
+        Ok(parsed.message)
+    }
+
+    async fn download_attachment(&self, message_id: &str, attachment_id: &str) -> Result<Vec<u8>> {
+        let key = (message_id.to_string(), attachment_id.to_string());
+        if let Some(data) = self.attachments.lock().await.remove(&key) {
+            return Ok(data);
+        }
+
+        // Listed from the metadata cache in an earlier run: fetch the message again
+        self.fetch_message(message_id).await?;
+        self.attachments
+            .lock()
+            .await
+            .remove(&key)
+            .with_context(|| format!("Attachment {} not found in message {}", attachment_id, message_id))
+    }
+}
+
+/// Send a raw message (To and Subject headers, as built by `gmail::send`) through the Bridge,
+/// from the Bridge account
+pub async fn send_message(settings: &BridgeSettings, raw: &str) -> Result<()> {
+    let recipients: Vec<String> = raw
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.strip_prefix("To:"))
+        .flat_map(|to| to.split(','))
+        .map(|address| address.trim().rsplit_once('<').map_or(address.trim(), |(_, rest)| rest.trim_end_matches('>')).to_string())
+        .filter(|address| !address.is_empty())
+        .collect();
+    let message = format!(
+        "From: {}\r\nDate: {}\r\n{}",
+        settings.username,
+        chrono::Utc::now().to_rfc2822(),
+        raw
+    );
+
+    let submission = smtp::Submission {
+        host: &settings.host,
+        port: settings.smtp_port,
+        mode: settings.security,
+        tls: settings.tls_config()?,
+        username: &settings.username,
+        password: &settings.password,
+    };
+    smtp::send(&submission, &settings.username, &recipients, &message).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoded_attachments_are_bounded() {
+        let key = |id: &str| ("INBOX/1".to_string(), id.to_string());
+        let mut decoded = Decoded::new(10);
+        decoded.insert(key("0"), vec![0; 4]);
+        decoded.insert(key("1"), vec![1; 4]);
+        decoded.insert(key("2"), vec![2; 4]);
+        // The oldest goes once the limit is passed
+        assert_eq!(decoded.remove(&key("0")), None);
+        assert_eq!(decoded.remove(&key("1")), Some(vec![1; 4]));
+        assert_eq!((decoded.bytes, decoded.order.len()), (4, 1));
+
+        let settings = BridgeSettings {
+            host: "127.0.0.1".to_string(),
+            imap_port: 1143,
+            smtp_port: 1025,
+            security: TlsMode::StartTls,
+            username: "me@proton.me".to_string(),
+            password: "bridge-secret".to_string(),
+            cert: None,
+            mailbox: "All Mail".to_string(),
+        };
+        assert!(!format!("{:?}", settings).contains("bridge-secret"));
+    }
+}
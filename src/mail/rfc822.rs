@@ -0,0 +1,307 @@
+//! Parsing of raw RFC 822 messages, for sources that hand over whole messages (IMAP, Maildir)
+//! rather than an already decoded MIME tree like the Gmail API.
+
+use chrono::{DateTime, Utc};
+use super::{mime, AttachmentRef, MailFolder, MailMessage};
+
+/// A parsed message and the decoded data of its attachments, in the order of
+/// `message.attachments` (whose ids are their positions: "0", "1", ...)
+pub struct ParsedMessage {
+    pub message: MailMessage,
+    pub attachments: Vec<Vec<u8>>,
+}
+
+/// Parse a raw message. `received_at` is the mailbox's arrival time (IMAP INTERNALDATE, file
+/// time); the Date header is used when there is none.
+pub fn parse(id: &str, raw: &[u8], received_at: Option<DateTime<Utc>>) -> ParsedMessage {
+    let (headers, body) = split_entity(raw);
+    let header = |name: &str| find_header(&headers, name).map(mime::decode_header).unwrap_or_default();
+
+    let mut attachments = Vec::new();
+    let text = walk(&headers, body, &mut attachments);
+    let (refs, data) = attachments.into_iter().unzip();
+
+    ParsedMessage {
+        message: MailMessage {
+            id: id.to_string(),
+            from: header("From"),
+            subject: header("Subject"),
+            received_at: received_at.or_else(|| find_header(&headers, "Date").and_then(parse_date)),
+            body: text,
+            headers: headers.iter().map(|(name, value)| (name.clone(), mime::decode_header(value))).collect(),
+            attachments: refs,
+            folder: MailFolder::default(),
+        },
+        attachments: data,
+    }
+}
+
+/// Date header value, e.g. `Tue, 4 Mar 2025 09:12:45 +0000 (UTC)`
+pub fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.split(" (").next().unwrap_or(value).trim();
+    DateTime::parse_from_rfc2822(value).ok().map(|date| date.with_timezone(&Utc))
+}
+
+/// Readable text of an entity, collecting its attachments (parts with a filename).
+/// Of a multipart/alternative only the plain text version is used when there is one.
+fn walk(headers: &[(String, String)], body: &[u8], attachments: &mut Vec<(AttachmentRef, Vec<u8>)>) -> String {
+    let content_type = find_header(headers, "Content-Type").unwrap_or("text/plain");
+    let mime_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    let transfer_encoding = find_header(headers, "Content-Transfer-Encoding");
+
+    if mime_type.starts_with("multipart/") {
+        let Some(boundary) = param(content_type, "boundary") else {
+            return String::new();
+        };
+        let parts: Vec<_> = split_multipart(body, &boundary).into_iter().map(split_entity).collect();
+        let texts: Vec<(bool, String)> = parts
+            .iter()
+            .map(|(part_headers, part_body)| {
+                let plain = find_header(part_headers, "Content-Type").is_none_or(|t| t.trim().to_ascii_lowercase().starts_with("text/plain"));
+                (plain, walk(part_headers, part_body, attachments))
+            })
+            .filter(|(_, text)| !text.trim().is_empty())
+            .collect();
+        if mime_type == "multipart/alternative" {
+            let plain = texts.iter().find(|(plain, _)| *plain).or(texts.first());
+            return plain.map(|(_, text)| text.clone()).unwrap_or_default();
+        }
+        return texts.into_iter().map(|(_, text)| text).collect::<Vec<_>>().join("\n");
+    }
+
+    let filename = find_header(headers, "Content-Disposition")
+        .and_then(|disposition| param(disposition, "filename"))
+        .or_else(|| param(content_type, "name"))
+        .filter(|name| !name.trim().is_empty());
+    if let Some(filename) = filename {
+        let data = mime::decode_transfer_encoding(transfer_encoding, body);
+        let attachment = AttachmentRef {
+            filename: filename.trim().to_string(),
+            attachment_id: attachments.len().to_string(),
+            mime_type: Some(mime_type),
+            size: Some(data.len() as u64),
+        };
+        attachments.push((attachment, data));
+        return String::new();
+    }
+
+    if mime_type != "text/plain" && mime_type != "text/html" {
+        return String::new();
+    }
+    mime::part_text(&mime_type, content_type, transfer_encoding, body)
+}
+
+/// Split an entity into its unfolded headers and its body (after the first empty line)
+fn split_entity(data: &[u8]) -> (Vec<(String, String)>, &[u8]) {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let end = data[pos..].iter().position(|&b| b == b'\n').map_or(data.len(), |i| pos + i + 1);
+        let line = String::from_utf8_lossy(&data[pos..end]);
+        let line = line.trim_end_matches(['\r', '\n']);
+        pos = end;
+        if line.is_empty() {
+            break;
+        }
+
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    (headers, &data[pos.min(data.len())..])
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+}
+
+/// Bodies of the parts of a multipart entity. The line break before a delimiter belongs to it.
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut pos = 0;
+    while pos < body.len() {
+        let end = body[pos..].iter().position(|&b| b == b'\n').map_or(body.len(), |i| pos + i + 1);
+        let line = body[pos..end].trim_ascii_end();
+        if let Some(rest) = line.strip_prefix(delimiter.as_bytes())
+            && (rest.is_empty() || rest == b"--")
+        {
+            if let Some(start) = start {
+                parts.push(strip_line_break(&body[start..pos]));
+            }
+            if rest == b"--" {
+                return parts;
+            }
+            start = Some(end);
+        }
+        pos = end;
+    }
+    // No closing delimiter: keep what there is
+    if let Some(start) = start {
+        parts.push(&body[start..]);
+    }
+    parts
+}
+
+fn strip_line_break(data: &[u8]) -> &[u8] {
+    let data = data.strip_suffix(b"\n").unwrap_or(data);
+    data.strip_suffix(b"\r").unwrap_or(data)
+}
+
+/// A parameter of a structured header value (`attachment; filename="March.pdf"`), including
+/// RFC 2231 extended and continued values (`filename*=UTF-8''Fatura%20mar%C3%A7o.pdf`)
+fn param(value: &str, name: &str) -> Option<String> {
+    let params = split_params(value);
+    if let Some((_, value)) = params.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)) {
+        return Some(mime::decode_header(value));
+    }
+
+    // Extended: name*=, or continued: name*0=, name*1*=, ...
+    let mut segments: Vec<(usize, bool, &str)> = params
+        .iter()
+        .filter_map(|(key, value)| {
+            let rest = key.get(..name.len()).filter(|prefix| prefix.eq_ignore_ascii_case(name)).map(|_| &key[name.len()..])?;
+            let rest = rest.strip_prefix('*')?;
+            let (index, encoded) = match rest.strip_suffix('*') {
+                Some(index) => (index, true),
+                None if rest.is_empty() => ("", true),
+                None => (rest, false),
+            };
+            let index = if index.is_empty() { 0 } else { index.parse().ok()? };
+            Some((index, encoded, value.as_str()))
+        })
+        .collect();
+    if segments.is_empty() {
+        return None;
+    }
+    segments.sort_by_key(|(index, _, _)| *index);
+
+    let mut charset = None;
+    let mut bytes = Vec::new();
+    for (position, (_, encoded, value)) in segments.into_iter().enumerate() {
+        if !encoded {
+            bytes.extend_from_slice(value.as_bytes());
+            continue;
+        }
+        let mut value = value;
+        if position == 0
+            && let Some((declared, rest)) = value.split_once('\'')
+            && let Some((_language, rest)) = rest.split_once('\'')
+        {
+            charset = Some(declared);
+            value = rest;
+        }
+        bytes.extend(percent_decode(value));
+    }
+    let encoding = charset
+        .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    Some(encoding.decode(&bytes).0.into_owned())
+}
+
+/// `key=value` pairs after the first `;`, with quotes removed
+fn split_params(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut fields = Vec::new();
+    for c in value.chars() {
+        match c {
+            _ if escaped => {
+                current.push(c);
+                escaped = false;
+            }
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => fields.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    fields.push(current);
+
+    for field in fields.into_iter().skip(1) {
+        if let Some((key, value)) = field.split_once('=') {
+            params.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    params
+}
+
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = value.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_multipart_message() {
+        let raw = b"From: =?UTF-8?Q?M=C3=BCller_GmbH?= <billing@mueller.de>\r\n\
+Subject: Your invoice\r\n\
+\tfor March\r\n\
+Date: Tue, 4 Mar 2025 09:12:45 +0100 (CET)\r\n\
+Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+\r\n\
+preamble\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=inner\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/html; charset=utf-8\r\n\
+\r\n\
+<p>HTML version</p>\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+Fatura de mar=C3=A7o\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: application/pdf; name=\"ignored.pdf\"\r\n\
+Content-Disposition: attachment;\r\n\
+\tfilename*=UTF-8''Fatura%20mar%C3%A7o.pdf\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+JVBERi0xLjQK\r\n\
+--outer\r\n\
+Content-Type: text/csv; name=\"lines.csv\"\r\n\
+\r\n\
+a,b\r\n\
+--outer--\r\n";
+
+        let parsed = parse("INBOX/7", raw, None);
+        let message = &parsed.message;
+        assert_eq!(message.from, "Müller GmbH <billing@mueller.de>");
+        assert_eq!(message.subject, "Your invoice for March");
+        assert_eq!(message.received_at.unwrap().to_rfc3339(), "2025-03-04T08:12:45+00:00");
+        assert_eq!(message.body, "Fatura de março");
+
+        let names: Vec<&str> = message.attachments.iter().map(|a| a.filename.as_str()).collect();
+        assert_eq!(names, vec!["Fatura março.pdf", "lines.csv"]);
+        assert_eq!(message.attachments[1].attachment_id, "1");
+        assert_eq!(parsed.attachments[0], b"%PDF-1.4\n");
+        assert_eq!(parsed.attachments[1], b"a,b");
+    }
+}
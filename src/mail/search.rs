@@ -0,0 +1,269 @@
+use super::{MailMessage, MailSource};
+use chrono::{Days, NaiveDate};
+use futures_util::stream::{self, StreamExt};
+use std::collections::{HashMap, HashSet};
+
+/// Terms and mailbox categories every keyword query must not match
+#[derive(Debug, Clone, Default)]
+pub struct Exclusions {
+    /// Words or phrases such as "proforma", "quote", "newsletter"
+    pub keywords: Vec<String>,
+    /// Mailbox categories such as "promotions" or "social" (Gmail tabs)
+    pub categories: Vec<String>,
+    /// Search the spam and trash folders too, which are otherwise left out (INCLUDE_SPAM, INCLUDE_TRASH)
+    pub include_spam: bool,
+    pub include_trash: bool,
+}
+
+/// Where messages pushed into the archive by hand arrive (INGEST_QUEUE)
+#[derive(Debug, Clone, PartialEq)]
+pub enum IngestQueue {
+    /// Mailbox label, e.g. "invoice-inbox"
+    Label(String),
+    /// Address the messages are delivered to, e.g. an alias like invoices+inbox@example.com
+    Address(String),
+}
+
+impl IngestQueue {
+    /// Anything with an `@` is an address, the rest a label
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        if value.contains('@') {
+            Self::Address(value.to_lowercase())
+        } else {
+            Self::Label(value.to_string())
+        }
+    }
+}
+
+impl std::fmt::Display for IngestQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Label(label) => write!(f, "label {}", label),
+            Self::Address(address) => write!(f, "{}", address),
+        }
+    }
+}
+
+/// How many message ids one keyword returned, and how many no other keyword found
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeywordHits {
+    pub keyword: String,
+    pub hits: usize,
+    pub unique: usize,
+    pub error: Option<String>,
+}
+
+/// Merged result of searching every keyword
+#[derive(Debug, Clone, Default)]
+pub struct SearchResults {
+    /// Deduplicated ids, in keyword order then result order
+    pub message_ids: Vec<String>,
+    pub per_keyword: Vec<KeywordHits>,
+}
+
+impl SearchResults {
+    /// One line per keyword so users can prune keywords that never contribute
+    pub fn keyword_report(&self) -> Vec<String> {
+        self.per_keyword
+            .iter()
+            .map(|k| match &k.error {
+                Some(e) => format!("  ✗ {}: search failed: {}", k.keyword, e),
+                None if k.hits == 0 => format!("  · {}: no hits", k.keyword),
+                None => format!("  · {}: {} hit(s), {} unique", k.keyword, k.hits, k.unique),
+            })
+            .collect()
+    }
+}
+
+/// Rough match counts per keyword, reported before searching so a run that would take hours
+/// can be stopped or capped
+#[derive(Debug, Clone, Default)]
+pub struct SearchEstimate {
+    /// Keyword -> estimated matches (None when the source can't estimate or the request failed)
+    pub per_keyword: Vec<(String, Option<u64>)>,
+}
+
+impl SearchEstimate {
+    /// Sum over keywords; messages matching several keywords count once per keyword
+    pub fn total(&self) -> u64 {
+        self.per_keyword.iter().filter_map(|(_, estimate)| *estimate).sum()
+    }
+
+    /// "Estimated matches: invoice ~120, bank ~4200", or None when nothing could be estimated
+    pub fn summary(&self) -> Option<String> {
+        let estimates: Vec<String> = self
+            .per_keyword
+            .iter()
+            .filter_map(|(keyword, estimate)| estimate.map(|estimate| format!("{} ~{}", keyword, estimate)))
+            .collect();
+        (!estimates.is_empty()).then(|| format!("  Estimated matches: {}", estimates.join(", ")))
+    }
+
+    /// Warning when an uncapped run would go through more than `threshold` messages (0 = never)
+    pub fn warning(&self, threshold: u64, limit: Option<usize>) -> Option<String> {
+        if threshold == 0 || limit.is_some() || self.total() <= threshold {
+            return None;
+        }
+        let (keyword, estimate) = self.per_keyword.iter().max_by_key(|(_, estimate)| *estimate)?;
+        Some(format!(
+            "⚠ Up to ~{} messages match ('{}' alone ~{}); fetching them all may take hours. Narrow TARGET_KEYWORDS or the date range, or cap the run with `manual --limit N --newest-first`",
+            self.total(),
+            keyword,
+            estimate.unwrap_or_default()
+        ))
+    }
+}
+
+/// Estimate every keyword's matches (up to `concurrency` requests at a time), over the same
+/// padded range as `search_keywords`
+pub async fn estimate_keywords(
+    source: &dyn MailSource,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    keywords: &[String],
+    exclusions: &Exclusions,
+    concurrency: usize,
+) -> SearchEstimate {
+    let (start_date, end_date) = (start_date - Days::new(1), end_date + Days::new(1));
+    let mut estimates: Vec<(usize, String, Option<u64>)> = stream::iter(keywords.iter().cloned().enumerate())
+        .map(|(index, keyword)| async move {
+            let estimate = source.estimate_keyword(start_date, end_date, &keyword, exclusions).await.ok().flatten();
+            (index, keyword, estimate)
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    estimates.sort_by_key(|(index, _, _)| *index);
+    SearchEstimate { per_keyword: estimates.into_iter().map(|(_, keyword, estimate)| (keyword, estimate)).collect() }
+}
+
+/// Search each keyword separately (up to `concurrency` queries at a time) and merge the hits.
+/// With `max_results`, each keyword stops after its newest `max_results` messages.
+/// A failing keyword is reported in `per_keyword` instead of failing the whole search,
+/// unless every keyword failed.
+///
+/// The range is padded by a day on each side because mailbox servers interpret dates in their
+/// own timezone; callers post-filter fetched messages with `MailMessage::received_within`.
+pub async fn search_keywords(
+    source: &dyn MailSource,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    keywords: &[String],
+    exclusions: &Exclusions,
+    concurrency: usize,
+    max_results: Option<usize>,
+) -> anyhow::Result<SearchResults> {
+    let (start_date, end_date) = (start_date - Days::new(1), end_date + Days::new(1));
+    let mut results: Vec<(usize, anyhow::Result<Vec<String>>)> = stream::iter(keywords.iter().cloned().enumerate())
+        .map(|(index, keyword)| async move { (index, source.search_keyword(start_date, end_date, &keyword, exclusions, max_results).await) })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    results.sort_by_key(|(index, _)| *index);
+
+    if !results.is_empty() && results.iter().all(|(_, result)| result.is_err()) {
+        let (_, error) = results.swap_remove(0);
+        return Err(error.unwrap_err().context("Every keyword search failed"));
+    }
+
+    Ok(merge(keywords, results.into_iter().map(|(_, result)| result)))
+}
+
+/// Apply `manual --limit N --newest-first` to fetched messages: order them newest first when
+/// asked (messages without a receive time last), then keep the first `limit`. Returns how many
+/// were left out.
+pub fn cap_messages(messages: &mut Vec<MailMessage>, limit: Option<usize>, newest_first: bool) -> usize {
+    if newest_first {
+        messages.sort_by_key(|message| std::cmp::Reverse(message.received_at));
+    }
+    let before = messages.len();
+    messages.truncate(limit.unwrap_or(before));
+    before - messages.len()
+}
+
+/// Drop drafts and chats a search returned anyway. Returns how many were dropped.
+pub fn drop_drafts_and_chats(messages: &mut Vec<MailMessage>) -> usize {
+    let before = messages.len();
+    messages.retain(|message| !message.folder.is_excluded());
+    before - messages.len()
+}
+
+fn merge(keywords: &[String], results: impl Iterator<Item = anyhow::Result<Vec<String>>>) -> SearchResults {
+    let results: Vec<_> = results.collect();
+
+    // How many keywords found each message, to tell unique contributions apart
+    let mut found_by: HashMap<&str, usize> = HashMap::new();
+    for ids in results.iter().flatten() {
+        for id in ids.iter().collect::<HashSet<_>>() {
+            *found_by.entry(id.as_str()).or_default() += 1;
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut merged = SearchResults::default();
+    for (keyword, result) in keywords.iter().zip(&results) {
+        let hits = match result {
+            Ok(ids) => {
+                let ids: Vec<&String> = ids.iter().collect::<HashSet<_>>().into_iter().collect();
+                let unique = ids.iter().filter(|id| found_by[id.as_str()] == 1).count();
+                KeywordHits { keyword: keyword.clone(), hits: ids.len(), unique, error: None }
+            }
+            Err(e) => KeywordHits { keyword: keyword.clone(), hits: 0, unique: 0, error: Some(format!("{:#}", e)) },
+        };
+        merged.per_keyword.push(hits);
+
+        for id in result.iter().flatten() {
+            if seen.insert(id.clone()) {
+                merged.message_ids.push(id.clone());
+            }
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_counts_unique_contributions() {
+        let keywords: Vec<String> = ["invoice", "fatura", "newsletter", "bank"].iter().map(|s| s.to_string()).collect();
+        let ids = |ids: &[&str]| Ok(ids.iter().map(|s| s.to_string()).collect());
+        let results = vec![
+            ids(&["a", "b", "c"]),
+            ids(&["b", "d"]),
+            ids(&[]),
+            Err(anyhow::anyhow!("429 Too Many Requests")),
+        ];
+
+        let merged = merge(&keywords, results.into_iter());
+        assert_eq!(merged.message_ids, vec!["a", "b", "c", "d"]);
+        assert_eq!((merged.per_keyword[0].hits, merged.per_keyword[0].unique), (3, 2));
+        assert_eq!((merged.per_keyword[1].hits, merged.per_keyword[1].unique), (2, 1));
+        assert_eq!(merged.per_keyword[2].hits, 0);
+        assert!(merged.per_keyword[3].error.is_some());
+        assert_eq!(merged.keyword_report()[2], "  · newsletter: no hits");
+    }
+
+    #[test]
+    fn test_estimate_and_cap() {
+        let estimate = SearchEstimate {
+            per_keyword: vec![("invoice".to_string(), Some(120)), ("bank".to_string(), Some(4200)), ("fatura".to_string(), None)],
+        };
+        assert_eq!(estimate.summary().unwrap(), "  Estimated matches: invoice ~120, bank ~4200");
+        assert!(estimate.warning(1000, None).unwrap().contains("'bank' alone ~4200"));
+        assert!(estimate.warning(1000, Some(50)).is_none());
+        assert!(estimate.warning(0, None).is_none());
+        assert!(SearchEstimate::default().summary().is_none());
+
+        let received = |id: &str, day: Option<u32>| MailMessage {
+            id: id.to_string(),
+            received_at: day.map(|day| chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2025, 3, day, 9, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        let mut messages = vec![received("a", Some(2)), received("b", None), received("c", Some(9)), received("d", Some(5))];
+        assert_eq!(cap_messages(&mut messages, Some(2), true), 2);
+        assert_eq!(messages.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), vec!["c", "d"]);
+    }
+}
@@ -0,0 +1,101 @@
+//! Minimal SMTP submission client (AUTH PLAIN over implicit TLS or STARTTLS)
+
+use anyhow::{Context, Result};
+use base64::prelude::*;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::ClientConfig;
+use super::imap::{self, Stream, TlsMode};
+
+/// Where and as whom to submit a message
+pub struct Submission<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub mode: TlsMode,
+    pub tls: Arc<ClientConfig>,
+    pub username: &'a str,
+    pub password: &'a str,
+}
+
+/// Submit a raw RFC 2822 message from `from` to every `recipients` address
+pub async fn send(submission: &Submission<'_>, from: &str, recipients: &[String], raw: &str) -> Result<()> {
+    anyhow::ensure!(!recipients.is_empty(), "The message has no recipients");
+    let mut tcp = TcpStream::connect((submission.host, submission.port))
+        .await
+        .with_context(|| format!("Failed to connect to SMTP server {}:{}", submission.host, submission.port))?;
+
+    let stream: Box<dyn Stream> = match submission.mode {
+        TlsMode::Implicit => Box::new(imap::upgrade(tcp, submission.host, submission.tls.clone()).await?),
+        TlsMode::StartTls => {
+            let mut plain = BufReader::new(&mut tcp);
+            expect(&mut plain, 220).await?;
+            command(&mut plain, "EHLO invoice-pilot", 250).await?;
+            command(&mut plain, "STARTTLS", 220).await?;
+            Box::new(imap::upgrade(tcp, submission.host, submission.tls.clone()).await?)
+        }
+    };
+
+    let mut stream = BufReader::new(stream);
+    if submission.mode == TlsMode::Implicit {
+        expect(&mut stream, 220).await?;
+    }
+    command(&mut stream, "EHLO invoice-pilot", 250).await?;
+    let credentials = BASE64_STANDARD.encode(format!("\0{}\0{}", submission.username, submission.password));
+    command(&mut stream, &format!("AUTH PLAIN {}", credentials), 235).await.context("SMTP login failed")?;
+    command(&mut stream, &format!("MAIL FROM:<{}>", from), 250).await?;
+    for recipient in recipients {
+        command(&mut stream, &format!("RCPT TO:<{}>", recipient), 250)
+            .await
+            .with_context(|| format!("Recipient {} was refused", recipient))?;
+    }
+    command(&mut stream, "DATA", 354).await?;
+    stream.write_all(&dot_stuff(raw)).await?;
+    command(&mut stream, ".", 250).await.context("The message was not accepted")?;
+    let _ = command(&mut stream, "QUIT", 221).await;
+    Ok(())
+}
+
+/// Message body for DATA: CRLF line endings, lines starting with '.' doubled
+fn dot_stuff(raw: &str) -> Vec<u8> {
+    let mut out = String::with_capacity(raw.len() + 64);
+    for line in raw.replace("\r\n", "\n").trim_end_matches('\n').split('\n') {
+        if line.starts_with('.') {
+            out.push('.');
+        }
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+    out.into_bytes()
+}
+
+async fn command<S: Stream>(stream: &mut BufReader<S>, line: &str, code: u16) -> Result<()> {
+    stream.write_all(format!("{}\r\n", line).as_bytes()).await?;
+    stream.flush().await?;
+    expect(stream, code).await
+}
+
+/// Read a (possibly multi-line) reply and check its code
+async fn expect<S: Stream>(stream: &mut BufReader<S>, code: u16) -> Result<()> {
+    loop {
+        let mut line = String::new();
+        anyhow::ensure!(stream.read_line(&mut line).await? > 0, "SMTP server closed the connection");
+        // "250-SIZE" continues, "250 OK" ends the reply
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        let reply: u16 = line.get(..3).and_then(|c| c.parse().ok()).unwrap_or_default();
+        anyhow::ensure!(reply == code, "SMTP server replied: {}", line.trim());
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_stuff() {
+        assert_eq!(dot_stuff("Subject: x\n\n.hidden\nend\n"), b"Subject: x\r\n\r\n..hidden\r\nend\r\n");
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use super::attachment::sanitize_sender_name;
+use super::phishing::domain_root;
+use crate::auth::oauth::get_token_dir;
+use crate::config::shared;
+
+/// Learned sender domains, stored next to the profile's tokens
+const LEARNED_ALIASES_FILE: &str = "vendor_aliases.json";
+
+/// Platforms that send mail on behalf of many vendors; their domains are never learned
+const SHARED_SENDER_ROOTS: &[&str] = &[
+    "gmail", "googlemail", "outlook", "hotmail", "live", "yahoo", "icloud", "proton", "protonmail", "gmx",
+    "stripe", "paypal", "paddle", "chargebee", "quickbooks", "intuit", "xero", "zoho", "freshbooks",
+];
+
+/// A canonical vendor name and the sender variants that map to it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct VendorAlias {
+    pub name: String,
+    /// Lowercase substrings of the From header (display name or address)
+    pub patterns: Vec<String>,
+}
+
+impl VendorAlias {
+    /// Filename prefix for this vendor: "Amazon Web Services" -> "amazon-web-services"
+    pub fn slug(&self) -> String {
+        sanitize_sender_name(&self.name)
+    }
+}
+
+/// Parse `VENDOR_ALIASES`: `Name=variant|variant; Name=variant`
+pub fn parse_aliases(value: &str) -> Result<Vec<VendorAlias>> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (name, variants) = entry
+                .split_once('=')
+                .with_context(|| format!("vendor alias '{}' must look like Name=variant|variant", entry))?;
+            let name = name.trim().to_string();
+            // The canonical name always matches itself
+            let patterns: Vec<String> = std::iter::once(name.as_str())
+                .chain(variants.split('|'))
+                .map(|variant| variant.trim().to_lowercase())
+                .filter(|variant| !variant.is_empty())
+                .collect();
+            if name.contains('/') || sanitize_sender_name(&name).is_empty() {
+                anyhow::bail!("vendor alias '{}' needs a name usable in filenames and folders", entry);
+            }
+            Ok(VendorAlias { name, patterns })
+        })
+        .collect()
+}
+
+/// Configured aliases plus the sender domains learned from them, so a vendor writing from a
+/// new display name ("AWS Notifications") still lands under its canonical name
+#[derive(Debug, Default)]
+pub struct VendorAliases {
+    aliases: Vec<VendorAlias>,
+    /// Sender domain -> canonical vendor name
+    learned: BTreeMap<String, String>,
+    path: Option<PathBuf>,
+    newly_learned: usize,
+}
+
+impl VendorAliases {
+    /// Load the configured aliases and the profile's learned domains (nothing when no aliases are configured)
+    pub fn load(aliases: &[VendorAlias], profile: Option<&str>) -> Result<Self> {
+        if aliases.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let path = get_token_dir(profile)?.join(LEARNED_ALIASES_FILE);
+        let learned = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(Self { aliases: aliases.to_vec(), learned, path: Some(path), newly_learned: 0 })
+    }
+
+    /// Canonical vendor for a From header, learning its domain when a configured alias matches
+    pub fn resolve(&mut self, from: &str) -> Option<&VendorAlias> {
+        let from_lower = from.to_lowercase();
+        let domain = sender_domain(&from_lower);
+
+        if let Some(index) = self.aliases.iter().position(|alias| alias.patterns.iter().any(|p| from_lower.contains(p.as_str()))) {
+            if let Some(domain) = domain.filter(|d| !SHARED_SENDER_ROOTS.contains(&domain_root(d).as_str()))
+                && self.learned.get(&domain) != Some(&self.aliases[index].name)
+            {
+                self.learned.insert(domain, self.aliases[index].name.clone());
+                self.newly_learned += 1;
+            }
+            return Some(&self.aliases[index]);
+        }
+
+        let name = self.learned.get(&domain?)?;
+        self.aliases.iter().find(|alias| &alias.name == name)
+    }
+
+    /// Canonical name for a detected institution ("Transferwise" -> "Wise"), if one is aliased
+    pub fn canonical(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.aliases
+            .iter()
+            .find(|alias| alias.patterns.iter().any(|p| name == *p || name.contains(p.as_str())))
+            .map(|alias| alias.name.as_str())
+    }
+
+    /// Number of domains learned since loading
+    pub fn newly_learned(&self) -> usize {
+        self.newly_learned
+    }
+
+    /// Persist the learned domains when anything new was learned
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if self.newly_learned == 0 {
+            return Ok(());
+        }
+        shared::update_json(path, |on_disk: &mut BTreeMap<String, String>| on_disk.extend(self.learned.clone()))
+    }
+}
+
+fn sender_domain(from: &str) -> Option<String> {
+    let (_, domain) = from.rsplit_once('@')?;
+    let domain = domain.trim_end_matches(['>', '"', ' ']).trim();
+    (!domain.is_empty()).then(|| domain.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vendor_aliases() {
+        let aliases = parse_aliases("Amazon Web Services=AWS|amazon web services; Wise=TransferWise").unwrap();
+        assert_eq!(aliases[0].slug(), "amazon-web-services");
+        assert!(parse_aliases("no separator").is_err());
+
+        let mut vendors = VendorAliases { aliases, ..Default::default() };
+        for from in ["Amazon Web Services, Inc. <billing@aws.com>", "AWS Billing <invoices@aws.com>", "Amazon Web Services EMEA SARL <x@aws.com>"] {
+            assert_eq!(vendors.resolve(from).map(|a| a.name.as_str()), Some("Amazon Web Services"));
+        }
+
+        // Learned from the domain, not from the display name
+        assert_eq!(vendors.newly_learned(), 1);
+        assert_eq!(vendors.resolve("Notifications <no-reply@aws.com>").map(|a| a.name.as_str()), Some("Amazon Web Services"));
+
+        // Shared platforms are not learned
+        vendors.resolve("AWS via Stripe <receipts@stripe.com>");
+        assert!(vendors.resolve("Acme <receipts@stripe.com>").is_none());
+
+        assert_eq!(vendors.canonical("Transferwise"), Some("Wise"));
+        assert_eq!(vendors.canonical("Revolut"), None);
+    }
+}
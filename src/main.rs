@@ -4,26 +4,69 @@ mod cli;
 mod config;
 mod db;
 mod drive;
+mod extract;
 mod gmail;
+mod hooks;
+mod mail;
+mod notify;
+mod plugins;
 mod process;
 mod scheduler;
+mod storage;
 mod interfaces;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{Datelike, NaiveDate};
-use clap::{Parser, Subcommand};
-use config::env::Config;
+use clap::{Parser, Subcommand, ValueEnum};
+use config::env::{Config, Flags, StorageKind};
+use process::heartbeat::Heartbeat;
+use process::outcome::{FailureKind, RunSummary, Stage, STEP_MARKER};
 use std::fs;
+use std::process::ExitCode;
+use std::path::{Path, PathBuf};
 use log4rs;
 
 #[derive(Parser, Debug)]
 #[command(name = "invoice-pilot")]
 #[command(about = "Automated invoice fetcher from Gmail to Google Drive", long_about = None)]
 struct Cli {
+    /// Use recorded fixtures instead of Gmail and Google Drive (no credentials needed)
+    #[arg(long, global = true)]
+    mock: bool,
+
+    /// Percentage of failed files tolerated before exiting with code 2 (0 = any failure)
+    #[arg(long, global = true, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=100))]
+    fail_threshold: u8,
+
+    /// Ignore cached message metadata and fetch every message from the mailbox again
+    #[arg(long, global = true)]
+    refresh: bool,
+
+    /// Finish Google sign-ins by pasting the address the browser is sent to, for machines the
+    /// localhost callback can't reach (SSH without port forwarding)
+    #[arg(long, global = true)]
+    paste_code: bool,
+
+    /// Only allow searches, listings and reports: refuse anything that uploads, moves or changes
+    /// the archive, the mailbox or the recorded state (READ_ONLY=true does the same)
+    #[arg(long, global = true)]
+    read_only: bool,
+
+    /// How pipeline commands report the result on their last stdout line: the RESULT line
+    /// (text), or the whole run summary with its API usage as one JSON object (json)
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Run in interactive TUI mode (default)
@@ -33,9 +76,261 @@ enum Commands {
         /// Custom date range in format YYYY-MM-DD:YYYY-MM-DD
         #[arg(short, long)]
         date_range: Option<String>,
+        /// Skip the confirmation prompt before downloading and uploading
+        #[arg(short, long)]
+        yes: bool,
+        /// Process at most this many messages
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Process the newest messages first (with --limit, keep the newest ones)
+        #[arg(long)]
+        newest_first: bool,
     },
     /// Run in scheduled mode (legacy CLI mode)
     Scheduled,
+    /// Process every tenant profile (mailbox, folder, keywords) in one invocation
+    RunAll {
+        /// Custom date range in format YYYY-MM-DD:YYYY-MM-DD (defaults to each profile's range)
+        #[arg(short, long)]
+        date_range: Option<String>,
+        /// Directory containing one <name>.env file per profile
+        #[arg(long, default_value = config::profiles::DEFAULT_PROFILES_DIR)]
+        profiles_dir: PathBuf,
+        /// Number of profiles processed at the same time
+        #[arg(long, default_value_t = 1)]
+        concurrency: usize,
+    },
+    /// Process every Workspace user in WORKSPACE_USERS (domain-wide delegation) into per-user folders
+    RunWorkspace {
+        /// Custom date range in format YYYY-MM-DD:YYYY-MM-DD
+        #[arg(short, long)]
+        date_range: Option<String>,
+        /// Number of mailboxes processed at the same time
+        #[arg(long, default_value_t = 1)]
+        concurrency: usize,
+    },
+    /// Archive everything forwarded to the ingestion queue (INGEST_QUEUE label or alias), whatever its keywords
+    Ingest {
+        /// Keep polling the queue instead of exiting after one pass
+        #[arg(short, long)]
+        watch: bool,
+        /// Seconds between polls with --watch
+        #[arg(long, default_value_t = 60, requires = "watch")]
+        interval: u64,
+    },
+    /// Send the archive digest when DIGEST_CADENCE says it's due (run it daily from cron or a timer)
+    Digest {
+        /// Send now even if the last digest is recent
+        #[arg(long)]
+        now: bool,
+        /// Print the digest without sending it or marking it sent
+        #[arg(long, conflicts_with = "now")]
+        dry_run: bool,
+    },
+    /// Bundle a completed month (PDFs + CSV manifest) and hand it off to the accountant
+    Package {
+        /// Month to package in format YYYY-MM (defaults to the previous month)
+        #[arg(short, long)]
+        month: Option<String>,
+        /// Email the package to this address (overrides ACCOUNTANT_EMAIL)
+        #[arg(long)]
+        email: Option<String>,
+        /// Upload the package to this Drive folder (overrides HANDOFF_DRIVE_FOLDER)
+        #[arg(long, conflicts_with = "email")]
+        handoff_folder: Option<String>,
+    },
+    /// Maintain the Drive archive
+    Archive {
+        #[command(subcommand)]
+        action: ArchiveAction,
+    },
+    /// Back up or restore the invoice database and the state kept next to it
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+    /// Record PDFs archived in Drive by hand in the invoice database, so search, reports and duplicate checks cover them
+    IndexDrive {
+        /// Drive folder to walk, e.g. "Accounting/Invoices"
+        #[arg(long)]
+        folder: String,
+    },
+    /// Rename and move a past month's Drive files to match the current folder location, rules and sanitizing
+    Reorganize {
+        /// Billing month to reorganize (YYYY-MM)
+        #[arg(long)]
+        month: String,
+        /// Make the changes; without it the planned renames and moves are only listed
+        #[arg(long)]
+        apply: bool,
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Archive a past month again from the messages it was archived from, under the current naming and rules
+    Reprocess {
+        /// Billing month to replay (YYYY-MM)
+        #[arg(long)]
+        month: String,
+        /// Leave the old copies the new run replaces in place
+        #[arg(long)]
+        keep_old: bool,
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Show where a past month's documents would go under a proposed rules file, changing nothing
+    Simulate {
+        /// The proposed rules file (same format as RULES_FILE)
+        #[arg(long)]
+        rules: PathBuf,
+        /// Billing month to classify again (YYYY-MM)
+        #[arg(long)]
+        month: String,
+    },
+    /// Fetch and archive again the messages a failure report lists (runs with failures write one to FAILURE_REPORT_DIR)
+    Retry {
+        /// The report, failures-<run>.json
+        #[arg(long)]
+        from: PathBuf,
+        /// Directory holding <name>.env, for reports of a profile run by `run-all`
+        #[arg(long, default_value = config::profiles::DEFAULT_PROFILES_DIR)]
+        profiles_dir: PathBuf,
+    },
+    /// Merge duplicate same-name folders in the Drive archive into the oldest one
+    Tidy {
+        /// List the duplicate folders without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Skip the confirmation prompt
+        #[arg(short, long, conflicts_with = "dry_run")]
+        yes: bool,
+    },
+    /// Check the Drive archive against the invoice database: missing, changed and unrecorded files
+    Audit {
+        /// Also compare each file's size and checksum with the ones it was uploaded with
+        #[arg(long)]
+        verify: bool,
+        /// Upload missing files again from the messages they came from
+        #[arg(long)]
+        reupload: bool,
+    },
+    /// Decrypt .age files downloaded from the archive (see ENCRYPTION_RECIPIENTS)
+    Decrypt {
+        /// Encrypted files or directories (searched recursively for .age files)
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// age identity file with the private key (defaults to AGE_IDENTITY_FILE)
+        #[arg(short, long)]
+        identity: Option<PathBuf>,
+        /// Write decrypted files here instead of next to the encrypted ones
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Show runs in progress (alive, stuck or gone) and how the last run ended; exits 1 when a run is stuck
+    Status,
+    /// List the working directories crashed runs left behind, and upload or remove their files
+    Recover {
+        /// Upload the files they hold to the folders they were meant for
+        #[arg(long, conflicts_with = "clean")]
+        resume: bool,
+        /// Remove them
+        #[arg(long)]
+        clean: bool,
+    },
+    /// Search archived invoices (needs DATABASE_URL; documents are recorded as they are uploaded)
+    Search {
+        /// Words in the document text, e.g. "domain renewal" or an IBAN
+        text: Option<String>,
+        /// Vendor or institution name (part of it is enough)
+        #[arg(long)]
+        vendor: Option<String>,
+        /// Billing month in format YYYY-MM
+        #[arg(short, long)]
+        month: Option<String>,
+        /// Only invoices of at least this total
+        #[arg(long)]
+        min_amount: Option<f64>,
+        /// Only invoices of at most this total
+        #[arg(long)]
+        max_amount: Option<f64>,
+        /// Maximum number of results
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+    },
+    /// Report on the archived invoices (needs DATABASE_URL)
+    #[command(group(clap::ArgGroup::new("kind").required(true).args(["trends", "fiscal_year"])))]
+    Report {
+        /// Per-vendor totals of the month against the months before, flagging spikes, new and missing vendors
+        #[arg(long)]
+        trends: bool,
+        /// Totals per accounting period of a fiscal year (FISCAL_YEAR_START), e.g. 2025 or 2025-26 (defaults to the current one)
+        #[arg(long, value_name = "YEAR", num_args = 0..=1, default_missing_value = "")]
+        fiscal_year: Option<String>,
+        /// Month to report in format YYYY-MM (defaults to the current month)
+        #[arg(short, long)]
+        month: Option<String>,
+        /// Number of months before it to compare with
+        #[arg(long, default_value_t = process::trends::DEFAULT_WINDOW, value_parser = clap::value_parser!(u32).range(1..))]
+        window: u32,
+    },
+    /// List unpaid invoices past due or due soon, mark one paid or unpaid, or send the reminders (needs DATABASE_URL)
+    Payables {
+        /// Drive file id of the invoice to mark (with --paid or --unpaid)
+        file_id: Option<String>,
+        /// Mark the invoice paid
+        #[arg(long, requires = "file_id", conflicts_with = "unpaid")]
+        paid: bool,
+        /// Mark the invoice unpaid again
+        #[arg(long, requires = "file_id")]
+        unpaid: bool,
+        /// Email the reminder (DIGEST_EMAIL) and run ON_PAYMENT_DUE when something is due (run it daily from cron or a timer)
+        #[arg(long, conflicts_with = "file_id")]
+        remind: bool,
+        /// Days ahead that count as due soon (defaults to DUE_REMINDER_DAYS)
+        #[arg(long)]
+        days: Option<i64>,
+    },
+    /// Match bank statement transactions against the archived invoices and list payments with no invoice (needs DATABASE_URL)
+    Reconcile {
+        /// Statement exports (CSV, CAMT.053 XML or PDF)
+        #[arg(required_unless_present = "month")]
+        statements: Vec<PathBuf>,
+        /// Reconcile the transactions recorded from archived statements in this month (YYYY-MM) instead
+        #[arg(short, long, conflicts_with = "statements")]
+        month: Option<String>,
+        /// Bank whose column mapping reads the CSV files (STATEMENT_FORMATS_FILE); with --month, only its transactions
+        #[arg(long)]
+        bank: Option<String>,
+    },
+    /// Serve the web dashboard: run history, archive search and a run button (needs the `dashboard` feature)
+    Serve {
+        /// Address to listen on; use 0.0.0.0:8090 to reach it from other machines (set DASHBOARD_TOKEN)
+        #[arg(long, default_value = "127.0.0.1:8090")]
+        bind: String,
+    },
+    /// Serve the gRPC control API for orchestrators: runs, progress streaming, archive queries (needs the `grpc` feature)
+    Grpc {
+        /// Address to listen on; anything beyond localhost requires mutual TLS (GRPC_TLS_*, GRPC_CLIENT_CA)
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        bind: String,
+    },
+    /// Run an MCP server on stdin/stdout so AI assistants can search the archive and start runs
+    Mcp,
+    /// Correct a processed file so future runs learn from it (lists recent files without arguments)
+    Correct {
+        /// Drive file id of the processed file
+        file_id: Option<String>,
+        /// Not an invoice: skip this sender's attachments from now on
+        #[arg(long, requires = "file_id", conflicts_with_all = ["folder", "clear"])]
+        not_invoice: bool,
+        /// Wrong vendor/bank: file this sender's attachments under this folder instead
+        #[arg(long, requires = "file_id", conflicts_with = "clear")]
+        folder: Option<String>,
+        /// Forget the correction made for this file's sender
+        #[arg(long, requires = "file_id")]
+        clear: bool,
+    },
     /// Manage authentication tokens (legacy CLI mode)
     Auth {
         #[command(subcommand)]
@@ -43,50 +338,317 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand, Debug)]
+enum ArchiveAction {
+    /// Apply the retention rules (RETENTION_*): zip old months, move them to cold storage or delete them
+    Maintain {
+        /// Show what the rules would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Skip the confirmation prompt
+        #[arg(short, long, conflicts_with = "dry_run")]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DbAction {
+    /// Write the invoice records, run history, bank transactions, message cache, learned vendors, corrections and rules to a JSON lines file
+    Export {
+        /// File to write, e.g. backup.jsonl
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Add the contents of a `db export` file to this database and config directory
+    Import {
+        /// File to read
+        file: PathBuf,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 enum AuthAction {
     /// Re-authenticate Gmail account
-    Gmail,
+    Gmail {
+        /// Revoke the current grant with Google first, so access is withdrawn before signing in again
+        #[arg(long)]
+        revoke: bool,
+    },
     /// Re-authenticate Google Drive account
-    Drive,
-    /// Clear all tokens (force re-authentication for both)
+    Drive {
+        /// Revoke the current grant with Google first, so access is withdrawn before signing in again
+        #[arg(long)]
+        revoke: bool,
+    },
+    /// Revoke the grants with Google and clear all tokens (force re-authentication for both)
     Reset,
+    /// Show the saved tokens and why signing in last failed, with how to fix it
+    Status,
+    /// Warn, and notify the `auth_expiring` sinks, when a sign-in won't last until the next scheduled run (run it daily from cron or a timer)
+    Check,
+    /// Save the cached tokens of every account to a passphrase-encrypted bundle
+    Export {
+        /// Bundle to write
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Restore the tokens of a bundle written by `auth export` (e.g. on a headless server)
+    Import {
+        /// Bundle to read
+        file: PathBuf,
+        /// Replace tokens that already exist on this machine
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     // Create logs directory if it doesn't exist
     fs::create_dir_all("src/data/logs").ok();
 
     let cli = Cli::parse();
+    let fail_threshold = cli.fail_threshold;
+    let format = cli.format;
+
+    // Pipeline commands always end with a machine-readable RESULT line (or JSON summary), even when they fail
+    let print_result = matches!(
+        cli.command,
+        Some(Commands::Manual { .. } | Commands::Scheduled | Commands::RunAll { .. })
+    );
+
+    // Exit codes: 0 success, 2 partial failure, 3 auth failure, 4 config error (1 for anything else)
+    let (result, api) = process::telemetry::measure(run(cli)).await;
+    let (summary, exit_code) = match result {
+        Ok(summary) => {
+            let summary = summary.unwrap_or_default();
+            if summary.exceeds_threshold(fail_threshold) {
+                if summary.failed > 0 {
+                    eprintln!(
+                        "\n✗ {} file(s) failed ({:.0}%, threshold {}%)",
+                        summary.failed,
+                        summary.failure_rate(),
+                        fail_threshold
+                    );
+                }
+                if let Some(mirror) = summary.mirror.as_ref().filter(|mirror| mirror.failed > 0) {
+                    eprintln!(
+                        "✗ {} file(s) failed to copy to the mirror, {} ({:.0}%, threshold {}%)",
+                        mirror.failed,
+                        mirror.name,
+                        mirror.failure_rate(),
+                        fail_threshold
+                    );
+                }
+            }
+            let exit_code = summary.exit_code(fail_threshold);
+            (summary, exit_code)
+        }
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            (RunSummary { api, ..RunSummary::default() }, process::outcome::exit_code_for_error(&e))
+        }
+    };
+
+    if print_result {
+        match format {
+            OutputFormat::Text => println!("{}", summary.result_line(exit_code)),
+            OutputFormat::Json => println!("{}", summary.json_line(exit_code)),
+        }
+    }
+
+    ExitCode::from(exit_code)
+}
+
+/// What a command would change, for read-only mode to refuse; None for searches, listings,
+/// reports, dry runs and the servers (which refuse starting runs themselves)
+fn changes(command: &Commands) -> Option<&'static str> {
+    Some(match command {
+        Commands::Manual { .. } | Commands::Scheduled | Commands::RunAll { .. } | Commands::RunWorkspace { .. } => "Archiving",
+        Commands::Ingest { .. } => "Ingesting",
+        Commands::Digest { dry_run: false, .. } => "Sending the digest",
+        Commands::Package { .. } => "Packaging a month",
+        Commands::Archive { action: ArchiveAction::Maintain { dry_run: false, .. } } => "Archive maintenance",
+        Commands::Db { action: DbAction::Import { .. } } => "Importing a database",
+        Commands::IndexDrive { .. } => "Indexing Drive",
+        Commands::Reorganize { apply: true, .. } => "Reorganizing a month",
+        Commands::Reprocess { .. } => "Reprocessing",
+        Commands::Retry { .. } => "Retrying failures",
+        Commands::Tidy { dry_run: false, .. } => "Merging folders",
+        Commands::Audit { reupload: true, .. } => "Re-uploading",
+        Commands::Recover { resume, clean } if *resume || *clean => "Recovering crashed runs",
+        Commands::Payables { file_id: Some(_), .. } | Commands::Payables { remind: true, .. } => "Changing payables",
+        Commands::Correct { file_id: Some(_), .. } => "Saving a correction",
+        Commands::Auth {
+            action:
+                AuthAction::Reset
+                | AuthAction::Gmail { revoke: true }
+                | AuthAction::Drive { revoke: true }
+                | AuthAction::Import { .. }
+                | AuthAction::Export { .. },
+        } => "Managing tokens",
+        _ => return None,
+    })
+}
+
+/// Run the selected command; pipeline commands return their summary for the exit-code policy
+async fn run(cli: Cli) -> Result<Option<RunSummary>> {
+    let flags = Flags { mock: cli.mock, refresh: cli.refresh, paste_code: cli.paste_code, read_only: cli.read_only };
+    let command = cli.command.unwrap_or(Commands::Tui);
+    if let Some(action) = changes(&command)
+        && (flags.read_only || Config::load_with(flags).is_ok_and(|config| config.read_only))
+    {
+        return Err(config::env::read_only_refusal(action)).context(FailureKind::Config);
+    }
 
-    match cli.command.unwrap_or(Commands::Tui) {
+    match command {
         Commands::Tui => {
             // For TUI mode, only log to file if debug logging is enabled
             // Never log to console to avoid interfering with TUI
-            if let Ok(config) = Config::from_env() {
+            if let Ok(config) = Config::load_with(flags) {
                 if config.debug_logs_enabled {
                     let _ = init_file_logging_only();
                 }
             }
             // Run the interactive TUI
-            if let Err(e) = interfaces::tui::run_tui().await {
+            if let Err(e) = interfaces::tui::run_tui(flags).await {
                 eprintln!("TUI error: {}", e);
                 std::process::exit(1);
             }
+            Ok(None)
         }
-        Commands::Manual { date_range } => {
-            run_manual(date_range).await?;
+        Commands::Manual { date_range, yes, limit, newest_first } => {
+            run_manual(date_range, yes, limit, newest_first, flags).await.map(Some)
         }
         Commands::Scheduled => {
-            run_scheduled_legacy().await?;
+            run_scheduled_legacy(flags).await.map(Some)
+        }
+        Commands::RunAll { date_range, profiles_dir, concurrency } => {
+            run_all_profiles(date_range, &profiles_dir, concurrency, flags).await.map(Some)
+        }
+        Commands::RunWorkspace { date_range, concurrency } => {
+            run_workspace(date_range, concurrency, flags).await.map(Some)
+        }
+        Commands::Ingest { watch, interval } => {
+            run_ingest(watch, interval, flags).await.map(Some)
+        }
+        Commands::Digest { now, dry_run } => {
+            run_digest(now, dry_run, flags).await?;
+            Ok(None)
+        }
+        Commands::Package { month, email, handoff_folder } => {
+            run_package(month, email, handoff_folder, flags).await?;
+            Ok(None)
+        }
+        Commands::Archive { action: ArchiveAction::Maintain { dry_run, yes } } => {
+            run_archive_maintain(dry_run, yes, flags).await?;
+            Ok(None)
+        }
+        Commands::Db { action } => {
+            run_db(action, flags).await?;
+            Ok(None)
+        }
+        Commands::IndexDrive { folder } => {
+            run_index_drive(&folder, flags).await?;
+            Ok(None)
+        }
+        Commands::Reorganize { month, apply, yes } => {
+            run_reorganize(&month, apply, yes, flags).await?;
+            Ok(None)
+        }
+        Commands::Reprocess { month, keep_old, yes } => {
+            run_reprocess(&month, keep_old, yes, flags).await.map(Some)
+        }
+        Commands::Simulate { rules, month } => {
+            run_simulate(&rules, &month, flags).await?;
+            Ok(None)
+        }
+        Commands::Retry { from, profiles_dir } => {
+            run_retry(&from, &profiles_dir, flags).await.map(Some)
+        }
+        Commands::Tidy { dry_run, yes } => {
+            run_tidy(dry_run, yes, flags).await?;
+            Ok(None)
+        }
+        Commands::Audit { verify, reupload } => {
+            run_audit(verify, reupload, flags).await?;
+            Ok(None)
+        }
+        Commands::Decrypt { paths, identity, output } => {
+            run_decrypt(&paths, identity, output.as_deref())?;
+            Ok(None)
+        }
+        Commands::Status => {
+            run_status(flags).await?;
+            Ok(None)
+        }
+        Commands::Recover { resume, clean } => {
+            let config = Config::load_with(flags).context(FailureKind::Config)?;
+            let action = match (resume, clean) {
+                (true, _) => Some(RecoverAction::Resume),
+                (_, true) => Some(RecoverAction::Clean),
+                _ => None,
+            };
+            let leftovers = process::workdir::leftovers(&config);
+            if leftovers.is_empty() {
+                println!("No files left behind by crashed runs");
+            } else {
+                print_leftovers(&leftovers);
+                recover_leftover_runs(&config, &leftovers, action).await?;
+            }
+            Ok(None)
+        }
+        Commands::Search { text, vendor, month, min_amount, max_amount, limit } => {
+            let query = db::InvoiceQuery {
+                vendor,
+                billing_month: month.as_deref().map(scheduler::runner::parse_month).transpose().context(FailureKind::Config)?,
+                min_amount_cents: min_amount.map(|amount| (amount * 100.0).round() as i64),
+                max_amount_cents: max_amount.map(|amount| (amount * 100.0).round() as i64),
+                text: text.filter(|text| !text.trim().is_empty()),
+                limit,
+            };
+            run_search(&query, flags).await?;
+            Ok(None)
+        }
+        Commands::Report { trends: _, fiscal_year: Some(year), .. } => {
+            run_fiscal_report(&year, flags).await?;
+            Ok(None)
+        }
+        Commands::Report { trends: _, fiscal_year: None, month, window } => {
+            run_trends(month.as_deref(), window, flags).await?;
+            Ok(None)
+        }
+        Commands::Payables { file_id, paid, unpaid, remind, days } => {
+            run_payables(file_id.as_deref(), paid, unpaid, remind, days, flags).await?;
+            Ok(None)
+        }
+        Commands::Reconcile { statements, month, bank } => {
+            run_reconcile(&statements, month.as_deref(), bank.as_deref(), flags).await?;
+            Ok(None)
+        }
+        Commands::Serve { bind } => {
+            run_serve(&bind, flags).await?;
+            Ok(None)
+        }
+        Commands::Grpc { bind } => {
+            run_grpc(&bind, flags).await?;
+            Ok(None)
+        }
+        Commands::Mcp => {
+            let config = Config::load_with(flags).context(FailureKind::Config)?;
+            auth::keepalive::spawn_for(&config);
+            interfaces::mcp::serve_stdio(config).await?;
+            Ok(None)
+        }
+        Commands::Correct { file_id, not_invoice, folder, clear } => {
+            run_correct(file_id.as_deref(), not_invoice, folder, clear, flags)?;
+            Ok(None)
         }
         Commands::Auth { action } => {
-            handle_auth_command(action).await?;
+            handle_auth_command(action, flags).await?;
+            Ok(None)
         }
     }
-
-    Ok(())
 }
 
 /// Initialize logging with only file output (no console) for TUI mode
@@ -107,16 +669,21 @@ fn init_file_logging_only() -> Result<()> {
     Ok(())
 }
 
-async fn run_manual(date_range: Option<String>) -> Result<()> {
+async fn run_manual(date_range: Option<String>, yes: bool, limit: Option<usize>, newest_first: bool, flags: Flags) -> Result<RunSummary> {
     println!("🚀 Invoice Agent - Manual Mode\n");
 
     // Load configuration
-    let config = Config::from_env()?;
+    let mut config = Config::load_with(flags).context(FailureKind::Config)?;
+    if limit == Some(0) {
+        return Err(anyhow::anyhow!("--limit must be at least 1")).context(FailureKind::Config);
+    }
+    config.message_limit = limit;
+    config.newest_first = newest_first;
 
     // Determine date range - prioritize CLI arg, then config (FILTER_BY_DATE or smart default)
     let (start_date, end_date) = if let Some(range_str) = date_range {
         println!("📅 Using CLI-provided date range");
-        scheduler::runner::parse_date_range(&range_str)?
+        scheduler::runner::parse_date_range(&range_str).context(FailureKind::Config)?
     } else {
         // Use dates from config (already parsed from FILTER_BY_DATE or defaults)
         (config.start_date, config.end_date)
@@ -124,233 +691,1751 @@ async fn run_manual(date_range: Option<String>) -> Result<()> {
 
     println!("📅 Date range: {} to {}\n", start_date, end_date);
 
-    // Execute the invoice fetching pipeline
-    fetch_and_upload_invoices(config, start_date, end_date).await?;
+    // Files a crashed run left behind: ask what to do with them, unless running unattended
+    let leftovers = process::workdir::leftovers(&config);
+    if !leftovers.is_empty() {
+        print_leftovers(&leftovers);
+        let action = if yes {
+            None
+        } else {
+            cli::choice_prompt("Upload them now [r], remove them [c], or keep them for later [K]? ")?
+                .and_then(|answer| match answer.as_str() {
+                    "r" | "resume" => Some(RecoverAction::Resume),
+                    "c" | "clean" => Some(RecoverAction::Clean),
+                    _ => None,
+                })
+        };
+        recover_leftover_runs(&config, &leftovers, action).await?;
+    }
+
+    // Execute the invoice fetching pipeline, asking before anything is downloaded
+    let started_at = chrono::Utc::now();
+    let heartbeat = Heartbeat::start(&config, "manual").await;
+    let (result, api) = process::telemetry::track(&config, fetch_and_upload_invoices(&config, start_date, end_date, !yes, &heartbeat)).await;
+    heartbeat.finish().await;
+    hooks::run_finished(&config, &result, None).await;
+    process::jobs::record_run(&config, start_date, end_date, started_at, &result, &api, None).await;
+    let summary = result?;
+
+    if summary.failed == 0 {
+        println!("\n✅ Manual run completed successfully!");
+    } else {
+        println!("\n⚠ Manual run completed with {} failure(s)", summary.failed);
+    }
+    Ok(summary)
+}
+
+#[derive(Clone, Copy)]
+enum RecoverAction {
+    Resume,
+    Clean,
+}
+
+fn print_leftovers(leftovers: &[process::workdir::Leftover]) {
+    println!("⚠ {} crashed run(s) left files behind:", leftovers.len());
+    for leftover in leftovers {
+        println!("  {}", leftover.describe());
+    }
+}
 
-    println!("\n✅ Manual run completed successfully!");
+/// Upload or remove the files of crashed runs; without an action they are kept, with a hint
+async fn recover_leftover_runs(config: &Config, leftovers: &[process::workdir::Leftover], action: Option<RecoverAction>) -> Result<()> {
+    match action {
+        None => println!("  Upload them with `recover --resume` or remove them with `recover --clean`\n"),
+        Some(RecoverAction::Clean) => {
+            for leftover in leftovers {
+                leftover.clean()?;
+                println!("🗑 Removed {}", leftover.path.display());
+            }
+        }
+        Some(RecoverAction::Resume) => {
+            let storage = storage::connect(config).await?;
+            let (tx, printer) = spawn_progress_printer();
+            let mut failed = 0;
+            for leftover in leftovers {
+                println!("⬆️ Resuming {}", leftover.describe());
+                let (uploaded, skipped, failures) = leftover.resume(storage.as_ref(), &tx).await?;
+                println!("  {} uploaded, {} already there, {} failed", uploaded, skipped, failures);
+                failed += failures;
+            }
+            drop(tx);
+            let _ = printer.await;
+            if failed > 0 {
+                anyhow::bail!("{} file(s) could not be uploaded; their directories were kept for another try", failed);
+            }
+        }
+    }
     Ok(())
 }
 
-async fn run_scheduled_legacy() -> Result<()> {
+async fn run_scheduled_legacy(flags: Flags) -> Result<RunSummary> {
     println!("⏰ Invoice Agent - Scheduled Mode\n");
 
     // Load configuration
-    let config = Config::from_env()?;
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+
+    let leftovers = process::workdir::leftovers(&config);
+    if !leftovers.is_empty() {
+        print_leftovers(&leftovers);
+        recover_leftover_runs(&config, &leftovers, None).await?;
+    }
 
     // Validate that FETCH_INVOICES_DAY is set for scheduled mode
     let fetch_invoices_day = config.fetch_invoices_day
-        .ok_or_else(|| anyhow::anyhow!("FETCH_INVOICES_DAY must be set in .env for scheduled mode"))?;
+        .ok_or_else(|| anyhow::anyhow!("FETCH_INVOICES_DAY must be set in .env for scheduled mode"))
+        .context(FailureKind::Config)?;
 
     // Check if we should run today
-    if !scheduler::runner::should_run_today(fetch_invoices_day) {
-        println!("ℹ Not scheduled to run today (runs on day {})", fetch_invoices_day);
+    if !scheduler::runner::should_run_today(&fetch_invoices_day, &config.holidays) {
+        println!("ℹ Not scheduled to run today (runs on {} of each month)", fetch_invoices_day);
         println!("Current day: {}", chrono::Utc::now().day());
-        return Ok(());
+        if !config.mock_mode {
+            // Days without a run are the chance to fix a sign-in the next run would fail on
+            match auth::expiry::check(&config, chrono::Utc::now()) {
+                Ok(warnings) => {
+                    for warning in &warnings {
+                        println!("⚠ {}: {} (run `{}`)", warning.service.label(), warning.message, warning.service.reauth_command());
+                    }
+                    auth::expiry::alert(&config, &warnings).await;
+                }
+                Err(e) => log::warn!("{:#}", e),
+            }
+        }
+        return Ok(RunSummary::default());
     }
 
     // Run directly (legacy mode always runs directly)
-    println!("✓ Today is day {} - running invoice fetch\n", fetch_invoices_day);
+    println!("✓ Today is {} of the month - running invoice fetch\n", fetch_invoices_day);
+
+    // Spread instances sharing the same schedule
+    if config.schedule_jitter_minutes > 0 {
+        let delay = scheduler::runner::jitter(config.schedule_jitter_minutes);
+        println!("⏳ Starting in {}m {}s (SCHEDULE_JITTER_MINUTES)\n", delay.as_secs() / 60, delay.as_secs() % 60);
+        tokio::time::sleep(delay).await;
+    }
 
     // Use previous month range
     let (start_date, end_date) = scheduler::runner::get_previous_month_range();
     println!("📅 Date range: {} to {}\n", start_date, end_date);
 
-    // Execute the invoice fetching pipeline
-    fetch_and_upload_invoices(config, start_date, end_date).await?;
+    // Earlier runs that died without finishing haven't been reported anywhere yet
+    match process::heartbeat::report_gone(&config).await {
+        Ok(0) => {}
+        Ok(gone) => println!("⚠ {} earlier run(s) stopped responding without finishing (reported to ON_RUN_FAILURE)\n", gone),
+        Err(e) => log::warn!("{:#}", e),
+    }
+
+    // Execute the invoice fetching pipeline (unattended, no confirmation), retrying transient
+    // failures; every attempt is recorded, the hooks only hear about the last one. An attempt
+    // that stops making progress for HEARTBEAT_STALE_MINUTES is given up as a transient failure.
+    let attempts = config.schedule_retries + 1;
+    let mut attempt = 1;
+    let result = loop {
+        let started_at = chrono::Utc::now();
+        let heartbeat = Heartbeat::start(&config, "scheduled").await;
+        let stale = process::heartbeat::stale_limit(&config);
+        let (result, api) = process::telemetry::track(&config, heartbeat.guard(stale, fetch_and_upload_invoices(&config, start_date, end_date, false, &heartbeat))).await;
+        heartbeat.finish().await;
+        process::jobs::record_run(&config, start_date, end_date, started_at, &result, &api, Some(attempt)).await;
+        match &result {
+            Err(e) if attempt < attempts && process::outcome::is_transient(e) => {
+                let delay = scheduler::runner::retry_delay(std::time::Duration::from_secs(config.schedule_retry_delay_minutes * 60), attempt);
+                println!("\n⚠ Attempt {}/{} failed: {:#}", attempt, attempts, e);
+                println!("🔁 Retrying in {} minute(s)\n", delay.as_secs() / 60);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            _ => break result,
+        }
+    };
+    hooks::run_finished(&config, &result, None).await;
+    let summary = result?;
 
-    println!("\n✅ Scheduled run completed successfully!");
-    Ok(())
+    if summary.failed == 0 {
+        println!("\n✅ Scheduled run completed successfully!");
+    } else {
+        println!("\n⚠ Scheduled run completed with {} failure(s)", summary.failed);
+    }
+    Ok(summary)
 }
 
-/// Determine the billing month from the date range
-/// If the range is primarily in one month, use that month
-/// Otherwise, use the end date's month
-fn determine_billing_month(start_date: NaiveDate, end_date: NaiveDate) -> String {
-    // If range spans multiple months, use the month that contains most of the range
-    let start_month = start_date.month();
-    let end_month = end_date.month();
+async fn run_all_profiles(date_range: Option<String>, profiles_dir: &Path, concurrency: usize, flags: Flags) -> Result<RunSummary> {
+    println!("🏢 Invoice Agent - Batch Mode\n");
 
-    if start_month == end_month {
-        // Same month - use it
-        format!("{}", chrono::Month::try_from(end_month as u8).unwrap().name())
-    } else {
-        // Different months - check if it's primarily last month billing
-        let days_in_end_month = (end_date - NaiveDate::from_ymd_opt(end_date.year(), end_date.month(), 1).unwrap()).num_days() + 1;
-        let total_days = (end_date - start_date).num_days() + 1;
+    let profiles = config::profiles::discover(profiles_dir).context(FailureKind::Config)?;
+    if profiles.is_empty() {
+        return Err(anyhow::anyhow!("No profiles found in {} (expected <name>.env files)", profiles_dir.display()))
+            .context(FailureKind::Config);
+    }
 
-        // If we're early in the end month (less than 15 days), it's likely previous month billing
-        if days_in_end_month < 15 && total_days > 20 {
-            format!("{}", chrono::Month::try_from(start_month as u8).unwrap().name())
-        } else {
-            format!("{}", chrono::Month::try_from(end_month as u8).unwrap().name())
+    let date_range = date_range
+        .map(|range_str| scheduler::runner::parse_date_range(&range_str))
+        .transpose()
+        .context(FailureKind::Config)?;
+
+    println!("📋 {} profile(s), up to {} at a time\n", profiles.len(), concurrency.max(1));
+
+    // Print tenant progress as it arrives
+    let (tx, printer) = spawn_progress_printer();
+
+    let reports = process::batch::run_all(profiles, date_range, concurrency, flags, &tx).await;
+    drop(tx);
+    let _ = printer.await;
+
+    println!("\n═══ Batch Report ═══");
+    Ok(print_batch_report(&reports, "profile(s)"))
+}
+
+async fn run_workspace(date_range: Option<String>, concurrency: usize, flags: Flags) -> Result<RunSummary> {
+    println!("🏢 Invoice Agent - Workspace Mode\n");
+
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    if config.workspace_users.is_empty() {
+        return Err(anyhow::anyhow!("WORKSPACE_USERS is empty; list the mailboxes to process (e.g. alice@acme.com, bob@acme.com)"))
+            .context(FailureKind::Config);
+    }
+    if config.google_service_account_key.is_none() && !config.mock_mode {
+        return Err(anyhow::anyhow!("Workspace mode reads mailboxes through a service account; set GOOGLE_SERVICE_ACCOUNT_KEY"))
+            .context(FailureKind::Config);
+    }
+
+    let date_range = date_range
+        .map(|range_str| scheduler::runner::parse_date_range(&range_str))
+        .transpose()
+        .context(FailureKind::Config)?;
+
+    println!(
+        "📋 {} mailbox(es) into {}/<user>, up to {} at a time\n",
+        config.workspace_users.len(),
+        config.drive_folder_path.trim_end_matches('/'),
+        concurrency.max(1)
+    );
+
+    let (tx, printer) = spawn_progress_printer();
+    let reports = process::batch::run_workspace(&config, date_range, concurrency, &tx).await;
+    drop(tx);
+    let _ = printer.await;
+
+    println!("\n═══ Workspace Report ═══");
+    Ok(print_batch_report(&reports, "mailbox(es)"))
+}
+
+/// Print one line per tenant (profile or mailbox) and return the combined summary
+fn print_batch_report(reports: &[process::batch::TenantReport], noun: &str) -> RunSummary {
+    let mut totals = RunSummary::default();
+    for report in reports {
+        match &report.error {
+            None => println!(
+                "✓ {:<20} {:>4} file(s), {} failed  {}",
+                report.profile,
+                report.summary.processed,
+                report.summary.failed,
+                report.summary.folder.as_deref().unwrap_or("-")
+            ),
+            Some(e) => println!("✗ {:<20} {}", report.profile, e),
+        }
+        totals.absorb(&report.summary);
+    }
+
+    for report in reports {
+        for alert in &report.summary.budget_alerts {
+            println!("💸 {}: over budget: {}", report.profile, alert);
         }
     }
+
+    print_mirror_line(&totals);
+    totals.failed_profiles = reports.iter().filter(|r| !r.succeeded()).count();
+    if totals.failed_profiles > 0 {
+        eprintln!("\n✗ {} of {} {} failed", totals.failed_profiles, reports.len(), noun);
+    } else {
+        println!("\n✅ All {} {} completed successfully!", reports.len(), noun);
+    }
+    totals
 }
 
-async fn fetch_and_upload_invoices(
-    config: Config,
-    start_date: NaiveDate,
-    end_date: NaiveDate,
-) -> Result<()> {
-    // 1. Authenticate with Gmail
-    println!("═══ Gmail Authentication ═══");
-    let gmail_token = auth::gmail_auth::get_gmail_token(
-        config.gmail_client_id.clone(),
-        config.gmail_client_secret.clone(),
-    )
-    .await?;
-    let gmail_client = gmail::client::GmailClient::new(gmail_token);
-
-    // 2. Authenticate with Drive
-    println!("\n═══ Google Drive Authentication ═══");
-    let drive_token = auth::drive_auth::get_drive_token(
-        config.drive_client_id.clone(),
-        config.drive_client_secret.clone(),
-    )
-    .await?;
-    let drive_client = drive::client::DriveClient::new(drive_token);
+async fn run_ingest(watch: bool, interval: u64, flags: Flags) -> Result<RunSummary> {
+    println!("📥 Invoice Agent - Ingestion Queue\n");
 
-    // 3. Search Gmail for invoices
-    println!("\n═══ Searching Gmail ═══");
-    let message_ids = gmail::search::search_invoices(&gmail_client, start_date, end_date, &config.target_keywords).await?;
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let queue = config
+        .ingest_queue
+        .clone()
+        .context("INGEST_QUEUE must be set in .env (a Gmail label or an alias such as invoices+inbox@example.com)")
+        .context(FailureKind::Config)?;
+    let mut ingested = process::ingest::IngestedMessages::load(&config).context(FailureKind::Config)?;
 
-    if message_ids.is_empty() {
-        println!("\nℹ No invoices found in the specified date range");
-        return Ok(());
+    let (tx, printer) = spawn_progress_printer();
+    if watch {
+        println!("👀 Watching {} every {}s (Ctrl+C to stop)", queue, interval.max(1));
     }
 
-    // 4. Download attachments
-    println!("\n═══ Downloading Attachments ═══");
-    let mut all_attachments = Vec::new();
+    let mut totals = RunSummary::default();
+    loop {
+        let started_at = chrono::Utc::now();
+        // Connected again on every pass: access tokens run out long before a watch does
+        let result = match process::jobs::connect(&config, &tx).await {
+            Ok((source, storage)) => process::ingest::ingest_queue(&config, source.as_ref(), storage.as_ref(), &mut ingested, &tx).await,
+            Err(e) => Err(e),
+        };
 
-    for (idx, message_id) in message_ids.iter().enumerate() {
-        println!("Processing message {}/{}: {}", idx + 1, message_ids.len(), message_id);
+        // Only passes that found something (or failed) are worth a hook call and a run history entry
+        if !result.as_ref().is_ok_and(|summary| summary.processed == 0 && summary.failed == 0) {
+            let today = started_at.with_timezone(&chrono::Local).date_naive();
+            let since = today - chrono::Days::new(config.ingest_lookback_days);
+            hooks::run_finished(&config, &result, Some(&tx)).await;
+            process::jobs::record_run(&config, since, today, started_at, &result, &process::telemetry::ApiUsage::new(), None).await;
+        }
 
-        match gmail::attachment::get_message_attachments(&gmail_client, message_id).await {
-            Ok(attachments) => {
-                all_attachments.extend(attachments);
-            }
+        match result {
+            Ok(summary) => totals.absorb(&summary),
+            Err(e) if watch => tx.send(format!("✗ Ingestion failed: {:#} (retrying in {}s)", e, interval.max(1)))?,
             Err(e) => {
-                eprintln!("   ✗ Failed to process message {}: {}", message_id, e);
+                drop(tx);
+                let _ = printer.await;
+                return Err(e);
             }
         }
+
+        if !watch {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(interval.max(1))).await;
+    }
+    drop(tx);
+    let _ = printer.await;
+
+    if totals.processed == 0 && totals.failed == 0 {
+        println!("ℹ Nothing new in {}", queue);
+    } else {
+        println!("\n═══ Summary ═══");
+        println!("Total files:    {}", totals.processed);
+        println!("Uploaded:       {}", totals.uploaded);
+        println!("Skipped:        {}", totals.skipped);
+        println!("Failed:         {}", totals.failed);
+        print_mirror_line(&totals);
+        for alert in &totals.budget_alerts {
+            println!("💸 Over budget: {}", alert);
+        }
     }
+    Ok(totals)
+}
 
-    if all_attachments.is_empty() {
-        println!("\nℹ No attachments found in messages");
+async fn run_digest(force: bool, dry_run: bool, flags: Flags) -> Result<()> {
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let mut state = process::digest::DigestState::load(&config).context(FailureKind::Config)?;
+    let now = chrono::Utc::now();
+    if !force && !dry_run && !state.is_due(config.digest_cadence, now) {
+        println!("ℹ Digest not due yet ({:?} cadence)", config.digest_cadence);
         return Ok(());
     }
 
-    println!("\n✓ Downloaded {} attachment(s)", all_attachments.len());
+    let pool = db::init_pool()
+        .await
+        .context("The digest needs the invoice database (set DATABASE_URL)")
+        .context(FailureKind::Config)?;
+    let digest = process::digest::Digest::build(&config, &pool, state.since(config.digest_cadence, now), now).await?;
+    let text = digest.render();
+    println!("{}", text);
+    if dry_run {
+        return Ok(());
+    }
 
-    // 5. Determine billing month and create monthly folder
-    let billing_month = determine_billing_month(start_date, end_date);
-    println!("📅 Billing month detected: {}", billing_month);
+    if config.digest_email.is_none() && config.on_digest.is_none() {
+        return Err(anyhow::anyhow!("Nowhere to send the digest: set DIGEST_EMAIL or ON_DIGEST in .env (or use --dry-run)"))
+            .context(FailureKind::Config);
+    }
+    if let Some(to) = &config.digest_email {
+        let raw = gmail::send::build_text_message(to, &digest.subject(), &text);
+        gmail::send::send_from_profile(&config, &raw, &format!("digest-{}.eml", now.format("%Y-%m-%d"))).await?;
+        println!("✓ Digest emailed to {}", to);
+    }
+    hooks::digest(&config, &digest).await?;
 
-    let monthly_folder_path = format!("{}/{}", config.drive_folder_path, billing_month);
-    let _monthly_folder_id = drive::folder::find_or_create_folder(&drive_client, &monthly_folder_path).await?;
+    // Only a delivered digest moves the period on, so a failed one is retried next time
+    state.mark_sent(now);
+    state.save()?;
+    Ok(())
+}
 
-    // 6. Group attachments by bank name and prepare for upload
-    println!("\n═══ Preparing Upload ═══");
-    
-    // Group attachments by bank name
-    let mut bank_groups: std::collections::HashMap<Option<String>, Vec<gmail::attachment::InvoiceAttachmentWithBank>> = std::collections::HashMap::new();
-    for attachment in &all_attachments {
-        bank_groups.entry(attachment.bank_name.clone()).or_insert_with(Vec::new).push(attachment.clone());
-    }
+async fn run_package(month: Option<String>, email: Option<String>, handoff_folder: Option<String>, flags: Flags) -> Result<()> {
+    println!("📦 Invoice Agent - Accountant Package\n");
 
-    let mut all_file_paths = Vec::new();
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
 
-    // 7. Upload files to bank-specific folders
-    println!("\n═══ Uploading to Google Drive ═══");
-    
-    for (bank_name, attachments) in bank_groups {
-        let bank_display_name = bank_name.as_deref().unwrap_or("General");
-        println!("\n🏦 Processing bank: {}", bank_display_name);
-        
-        // Create bank-specific folder
-        let bank_folder_path = if let Some(ref bank) = bank_name {
-            format!("{}/{}", monthly_folder_path, bank)
-        } else {
-            monthly_folder_path.clone()
-        };
-        
-        let bank_folder_id = drive::folder::find_or_create_folder(&drive_client, &bank_folder_path).await?;
-        
-        // Save attachments to temp directory for this bank
-        let mut file_paths = Vec::new();
-        for attachment in &attachments {
-            match gmail::attachment::save_attachment_to_temp(&attachment.attachment) {
-                Ok(path) => {
-                    file_paths.push(path.clone());
-                    all_file_paths.push(path);
-                }
-                Err(e) => {
-                    eprintln!("   ✗ Failed to save {}: {}", attachment.attachment.filename, e);
-                }
-            }
+    let (year, month) = match month {
+        Some(month_str) => {
+            let date = scheduler::runner::parse_month(&month_str).context(FailureKind::Config)?;
+            (date.year(), date.month())
+        }
+        None => {
+            let (start_date, _) = scheduler::runner::get_previous_month_range();
+            (start_date.year(), start_date.month())
         }
-        
-        // Upload files to bank-specific folder
-        drive::upload::upload_files(&drive_client, &file_paths, &bank_folder_id, None).await?;
+    };
 
-        println!("   ✓ Bank: {} - Files uploaded", bank_display_name);
-    }
+    // CLI flags win over .env; email is preferred when both are configured
+    let delivery = if let Some(to) = email {
+        process::package::Delivery::Email(to)
+    } else if let Some(folder) = handoff_folder {
+        process::package::Delivery::DriveFolder(folder)
+    } else if let Some(to) = config.accountant_email.clone() {
+        process::package::Delivery::Email(to)
+    } else if let Some(folder) = config.handoff_drive_folder.clone() {
+        process::package::Delivery::DriveFolder(folder)
+    } else {
+        return Err(anyhow::anyhow!("No delivery target: pass --email or --handoff-folder, or set ACCOUNTANT_EMAIL / HANDOFF_DRIVE_FOLDER in .env"))
+            .context(FailureKind::Config);
+    };
 
-    // 8. Cleanup temp files
-    println!("\n═══ Cleanup ═══");
-    for file_path in &all_file_paths {
-        if let Err(e) = std::fs::remove_file(file_path) {
-            eprintln!("   ⚠ Failed to remove temp file {}: {}", file_path.display(), e);
-        }
-    }
-    println!("✓ Temp files cleaned up");
+    let (tx, printer) = spawn_progress_printer();
+
+    let result = process::package::package_month(&config, year, month, delivery, &tx).await;
+    drop(tx);
+    let _ = printer.await;
+    let summary = result?;
 
-    // Print summary
     println!("\n═══ Summary ═══");
-    println!("Total files:    {}", all_file_paths.len());
-    println!("Monthly folder: {}", monthly_folder_path);
+    println!("Package:   {} ({} file(s), {} KB)", summary.archive_name, summary.files, summary.size_bytes / 1024);
+    println!("Delivered: {}", summary.delivered_to);
 
+    println!("\n✅ Package delivered successfully!");
     Ok(())
 }
 
+async fn run_archive_maintain(dry_run: bool, yes: bool, flags: Flags) -> Result<()> {
+    println!("🗄 Invoice Agent - Archive Maintenance\n");
 
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    if config.retention.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No retention rules: set RETENTION_COMPRESS_AFTER_YEARS, RETENTION_COLD_STORAGE_AFTER_YEARS or RETENTION_DELETE_AFTER_YEARS in .env"
+        ))
+        .context(FailureKind::Config);
+    }
+    if !flags.mock && config.storage_backend != StorageKind::Drive {
+        return Err(anyhow::anyhow!(
+            "archive maintain works on the Google Drive archive, but STORAGE_BACKEND is the {}",
+            config.storage_backend.label()
+        ))
+        .context(FailureKind::Config);
+    }
 
-async fn handle_auth_command(action: AuthAction) -> Result<()> {
-    match action {
-        AuthAction::Gmail => {
-            println!("🔄 Re-authenticating Gmail...\n");
-            auth::gmail_auth::clear_gmail_token()?;
+    let client = storage::drive_client(&config).await?;
+    let root = process::retention::managed_root(&config.drive_folder_path);
+    println!("Archive: {}", if root.is_empty() { "My Drive" } else { &root });
+    println!("Rules:   {}\n", config.retention.describe());
 
-            let config = Config::from_env()?;
-            auth::gmail_auth::get_gmail_token(
-                config.gmail_client_id,
-                config.gmail_client_secret,
-            )
-            .await?;
+    let plan = process::retention::plan(&client, &config.drive_folder_path, &config.retention, chrono::Local::now().date_naive()).await?;
+    for month in &plan.months {
+        println!("  {}", month.describe());
+    }
+    for path in &plan.undated {
+        println!("  ⚠ {}: not inside a year folder, so its age is unknown; skipped", path);
+    }
+    if plan.pending() == 0 {
+        println!("✓ Nothing is due");
+        return Ok(());
+    }
+    if dry_run {
+        println!("\nDry run: nothing was changed");
+        return Ok(());
+    }
+    let prompt = format!("\nApply these changes to {} month(s)? Deleted months go to the Drive trash. [y/N] ", plan.pending());
+    if !yes && !cli::confirm_prompt(&prompt)? {
+        anyhow::bail!("Aborted - nothing was changed");
+    }
 
-            println!("\n✅ Gmail re-authenticated successfully!");
-        }
-        AuthAction::Drive => {
-            println!("🔄 Re-authenticating Google Drive...\n");
-            auth::drive_auth::clear_drive_token()?;
+    let (tx, printer) = spawn_progress_printer();
+    let result = process::retention::apply(&config, &client, &plan, &tx).await;
+    drop(tx);
+    let _ = printer.await;
+    let summary = result?;
 
-            let config = Config::from_env()?;
-            auth::drive_auth::get_drive_token(
-                config.drive_client_id,
-                config.drive_client_secret,
-            )
-            .await?;
+    println!("\n═══ Summary ═══");
+    println!("Compressed: {} month(s) ({} KB of ZIPs)", summary.compressed, summary.archive_bytes / 1024);
+    println!("Moved:      {} month(s)", summary.moved);
+    println!("Deleted:    {} month(s)", summary.deleted);
+    if summary.failed > 0 {
+        anyhow::bail!("{} action(s) failed", summary.failed);
+    }
+    println!("\n✅ Archive maintenance completed successfully!");
+    Ok(())
+}
 
-            println!("\n✅ Google Drive re-authenticated successfully!");
+async fn run_db(action: DbAction, flags: Flags) -> Result<()> {
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let pool = db::init_pool()
+        .await
+        .context("Set DATABASE_URL to the invoice database")
+        .context(FailureKind::Config)?;
+
+    match action {
+        DbAction::Export { out } => {
+            println!("💾 Invoice Agent - Export Database\n");
+            let counts = process::backup::export(&config, &pool, &out).await?;
+            for (table, rows) in counts {
+                println!("{:<20}{} row(s)", format!("{}:", table), rows);
+            }
+            println!("\n✅ Exported to {}", out.display());
+            println!("ℹ The file holds document text and message metadata; keep it as private as the archive");
         }
-        AuthAction::Reset => {
-            println!("🔄 Resetting all authentication tokens...\n");
-            auth::gmail_auth::clear_gmail_token()?;
-            auth::drive_auth::clear_drive_token()?;
-            println!("\n✅ All tokens cleared! Run manual or scheduled mode to re-authenticate.");
+        DbAction::Import { file } => {
+            println!("💾 Invoice Agent - Import Database\n");
+            let records = process::backup::read(&file)?;
+            let summary = process::backup::import(&config, &pool, &records).await?;
+            for table in db::BACKUP_TABLES {
+                let added = summary.added.get(table.name).copied().unwrap_or_default();
+                let existing = summary.existing.get(table.name).copied().unwrap_or_default();
+                if added + existing > 0 {
+                    println!("{:<20}{} added, {} already there", format!("{}:", table.name), added, existing);
+                }
+            }
+            for path in &summary.written {
+                println!("✓ Wrote {}", path.display());
+            }
+            for path in &summary.kept {
+                println!("ℹ Kept {} (this machine already has one)", path.display());
+            }
+            if summary.rules_skipped {
+                println!("ℹ The export has a rules file: set RULES_FILE to where it should go and import again");
+            }
+            println!("\n✅ Imported {}", file.display());
         }
     }
+    Ok(())
+}
 
+async fn run_index_drive(folder: &str, flags: Flags) -> Result<()> {
+    println!("📇 Invoice Agent - Index Drive Archive\n");
+
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let pool = db::init_pool()
+        .await
+        .context("Indexing records documents in the invoice database (set DATABASE_URL)")
+        .context(FailureKind::Config)?;
+    let client = storage::drive_client(&config).await?;
+
+    let (tx, printer) = spawn_progress_printer();
+    let result = process::index::index_drive(&config, &client, &pool, folder.trim_matches('/'), &tx).await;
+    drop(tx);
+    let _ = printer.await;
+    let summary = result?;
+
+    println!("\n═══ Summary ═══");
+    println!("Indexed:        {}", summary.indexed);
+    println!("Known:          {} (already in the database)", summary.known);
+    println!("Skipped:        {} (not PDFs)", summary.skipped);
+    println!("Failed:         {}", summary.failed);
+    if summary.failed > 0 {
+        anyhow::bail!("{} file(s) could not be indexed", summary.failed);
+    }
     Ok(())
 }
+
+async fn run_reorganize(month: &str, apply: bool, yes: bool, flags: Flags) -> Result<()> {
+    println!("🗂 Invoice Agent - Reorganize Month\n");
+
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let month = scheduler::runner::parse_month(month).context(FailureKind::Config)?;
+    if !flags.mock && config.storage_backend != StorageKind::Drive {
+        return Err(anyhow::anyhow!(
+            "reorganize moves files in the Google Drive archive, but STORAGE_BACKEND is the {}",
+            config.storage_backend.label()
+        ))
+        .context(FailureKind::Config);
+    }
+    let rules = process::rules::Rules::load(config.rules_file.as_deref()).context(FailureKind::Config)?;
+    let pool = db::init_pool()
+        .await
+        .context("Reorganizing needs the invoice database (set DATABASE_URL)")
+        .context(FailureKind::Config)?;
+    let profile = config.profile.clone().unwrap_or_default();
+
+    let month_label = month.format("%B %Y").to_string();
+    let documents = db::month_invoice_documents(&pool, &profile, month).await?;
+    if documents.is_empty() {
+        anyhow::bail!("Nothing is archived under {}", month_label);
+    }
+    let mut messages = std::collections::HashMap::new();
+    for message_id in documents.iter().filter_map(|document| document.message_id.as_ref()) {
+        if let Some(message) = db::load_cached_message_any_source(&pool, &profile, message_id)
+            .await?
+            .and_then(|json| serde_json::from_str::<mail::MailMessage>(&json).ok())
+        {
+            messages.insert(message_id.clone(), message);
+        }
+    }
+    let mut vendors = mail::vendors::VendorAliases::load(&config.vendor_aliases, config.profile.as_deref()).context(FailureKind::Config)?;
+    let plan = process::reorganize::ReorganizePlan::new(&config, &rules, &mut vendors, &documents, &messages);
+
+    println!("Month:          {}", month_label);
+    println!("Documents:      {} ({} already in place)\n", documents.len(), plan.unchanged);
+    for relocation in &plan.relocations {
+        println!("  {}/{}", relocation.folder, relocation.filename);
+        println!("    → {}/{}", relocation.to_folder, relocation.to_filename);
+    }
+    for conflict in &plan.conflicts {
+        println!("  ⚠ {}: another document of the month goes there, left as it is", conflict);
+    }
+    if plan.left > 0 {
+        println!("ℹ {} document(s) outside a month folder or in a zipped month are left as they are", plan.left);
+    }
+    if plan.relocations.is_empty() {
+        println!("✓ {} already follows the current conventions", month_label);
+        return Ok(());
+    }
+    if !apply {
+        println!("\nDry run: nothing was changed (pass --apply to rename and move {} file(s))", plan.relocations.len());
+        return Ok(());
+    }
+    let prompt = format!("\nRename and move {} file(s) in Drive? [y/N] ", plan.relocations.len());
+    if !yes && !cli::confirm_prompt(&prompt)? {
+        anyhow::bail!("Aborted - nothing was changed");
+    }
+
+    let client = storage::drive_client(&config).await?;
+    let (tx, printer) = spawn_progress_printer();
+    let result = process::reorganize::reorganize(&client, &pool, &profile, &plan, &tx).await;
+    drop(tx);
+    let _ = printer.await;
+    let summary = result?;
+
+    println!("\n═══ Summary ═══");
+    println!("Moved:          {}", summary.moved);
+    if summary.taken > 0 {
+        println!("Left:           {} (a file of the same name is already there)", summary.taken);
+    }
+    println!("Failed:         {}", summary.failed);
+    if summary.failed > 0 {
+        anyhow::bail!("{} file(s) could not be moved", summary.failed);
+    }
+    Ok(())
+}
+
+async fn run_reprocess(month: &str, keep_old: bool, yes: bool, flags: Flags) -> Result<RunSummary> {
+    println!("🔁 Invoice Agent - Reprocess Month\n");
+
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let month = scheduler::runner::parse_month(month).context(FailureKind::Config)?;
+    if !flags.mock && config.storage_backend != StorageKind::Drive {
+        return Err(anyhow::anyhow!(
+            "reprocess replaces files in the Google Drive archive, but STORAGE_BACKEND is the {}",
+            config.storage_backend.label()
+        ))
+        .context(FailureKind::Config);
+    }
+    let pool = db::init_pool()
+        .await
+        .context("Reprocessing needs the invoice database (set DATABASE_URL)")
+        .context(FailureKind::Config)?;
+
+    let month_label = month.format("%B %Y").to_string();
+    let records = db::archived_files(&pool, &config.profile.clone().unwrap_or_default(), Some(month)).await?;
+    let Some(plan) = process::reprocess::ReprocessPlan::new(month, records) else {
+        anyhow::bail!("No document archived under {} names the message it came from; nothing to reprocess", month_label);
+    };
+    println!("Month:          {} (under {})", month_label, plan.folder_path);
+    println!("Documents:      {} from {} message(s)", plan.records.len(), plan.message_ids.len());
+    if plan.untraceable > 0 {
+        println!("ℹ {} document(s) were archived before their messages were recorded and are left as they are", plan.untraceable);
+    }
+    let prompt = if keep_old {
+        "\nFetch these messages again and archive them under the current rules? [y/N] "
+    } else {
+        "\nFetch these messages again and archive them under the current rules? Replaced copies go to the Drive trash. [y/N] "
+    };
+    if !yes && !cli::confirm_prompt(prompt)? {
+        anyhow::bail!("Aborted - nothing was changed");
+    }
+
+    let (tx, printer) = spawn_progress_printer();
+    let started_at = chrono::Utc::now();
+    let result = process::reprocess::reprocess(&config, &pool, &plan, keep_old, &tx).await;
+    drop(tx);
+    let _ = printer.await;
+    let run = result.as_ref().map(|summary| summary.run.clone()).map_err(|e| anyhow::anyhow!("{:#}", e));
+    let last_day = month.checked_add_months(chrono::Months::new(1)).and_then(|next| next.pred_opt()).unwrap_or(month);
+    process::jobs::record_run(&config, month, last_day, started_at, &run, &process::telemetry::ApiUsage::new(), None).await;
+    let summary = result?;
+
+    println!("\n═══ Summary ═══");
+    println!("Total files:    {}", summary.run.processed);
+    println!("Uploaded:       {}", summary.run.uploaded);
+    println!("Skipped:        {} (already in place)", summary.run.skipped);
+    println!("Failed:         {}", summary.run.failed);
+    if !keep_old {
+        println!("Replaced:       {} (old files moved to the trash)", summary.replaced);
+    }
+    print_mirror_line(&summary.run);
+    print_folder_link(&summary.run);
+    if !summary.kept.is_empty() {
+        println!("\n⚠ Kept: the new run archived these documents' messages with different contents; compare them by hand:");
+        for path in &summary.kept {
+            println!("  {}", path);
+        }
+    }
+    if summary.failed > 0 {
+        anyhow::bail!("{} replaced file(s) could not be moved to the trash", summary.failed);
+    }
+    Ok(summary.run)
+}
+
+async fn run_simulate(rules_path: &Path, month: &str, flags: Flags) -> Result<()> {
+    use process::simulate::Simulated;
+
+    println!("🧪 Invoice Agent - Simulate Rules\n");
+
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let month = scheduler::runner::parse_month(month).context(FailureKind::Config)?;
+    let rules = process::rules::Rules::load(Some(rules_path)).context(FailureKind::Config)?;
+    let pool = db::init_pool()
+        .await
+        .context("Simulating rules needs the invoice database (set DATABASE_URL)")
+        .context(FailureKind::Config)?;
+    let profile = config.profile.clone().unwrap_or_default();
+
+    let month_label = month.format("%B %Y").to_string();
+    let documents = db::month_invoice_documents(&pool, &profile, month).await?;
+    if documents.is_empty() {
+        anyhow::bail!("Nothing is archived under {}", month_label);
+    }
+    println!("Month:          {}", month_label);
+    println!("Rules:          {}", rules_path.display());
+    println!("Documents:      {}\n", documents.len());
+
+    let mut vendors = mail::vendors::VendorAliases::load(&config.vendor_aliases, config.profile.as_deref()).context(FailureKind::Config)?;
+    let (mut changed, mut skipped, mut unchanged, mut uncached) = (0, 0, 0, 0);
+    for document in &documents {
+        let message = match &document.message_id {
+            Some(message_id) => db::load_cached_message_any_source(&pool, &profile, message_id)
+                .await?
+                .and_then(|json| serde_json::from_str::<mail::MailMessage>(&json).ok()),
+            None => None,
+        };
+        let Some(message) = message else {
+            uncached += 1;
+            continue;
+        };
+        let path = format!("{}/{}", document.folder, document.filename);
+        match process::simulate::reclassify(&rules, &mut vendors, document, &message, config.mailbox_timezone) {
+            Simulated::Unchanged => unchanged += 1,
+            Simulated::Skipped { sender } => {
+                skipped += 1;
+                println!("  ⊘ {}: would be skipped ({})", path, sender);
+            }
+            Simulated::Changed { folder, filename, category, sender } => {
+                changed += 1;
+                println!("  → {}", path);
+                println!("      to {}/{}", folder, filename);
+                if category != document.rule_category {
+                    println!("      category: {} → {}", document.category(&config.drive_folder_path), category.as_deref().unwrap_or("from its folder"));
+                }
+                println!("      rule: {}", sender.as_deref().unwrap_or("none matches any more"));
+            }
+        }
+    }
+
+    println!("\n═══ Summary ═══");
+    println!("Would change:   {}", changed);
+    println!("Would skip:     {}", skipped);
+    println!("Unchanged:      {}", unchanged);
+    if uncached > 0 {
+        println!("Not simulated:  {} (archived before message IDs were recorded, or their messages aren't in the message cache; a run over the month's dates fills it)", uncached);
+    }
+    println!("\nNothing was changed. To apply the rules, point RULES_FILE at {} and run `invoice-pilot reprocess --month {}`.", rules_path.display(), month.format("%Y-%m"));
+    Ok(())
+}
+
+async fn run_status(flags: Flags) -> Result<()> {
+    use process::heartbeat::Health;
+
+    println!("📟 Invoice Agent - Status\n");
+
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let pool = db::init_pool()
+        .await
+        .context("Status reads the run heartbeats from the invoice database (set DATABASE_URL)")
+        .context(FailureKind::Config)?;
+    let profile = config.profile.clone().unwrap_or_default();
+    let stale = process::heartbeat::stale_limit(&config);
+    let now = chrono::Utc::now();
+    let ago = |at: chrono::DateTime<chrono::Utc>| scheduler::runner::format_countdown(now - at);
+
+    let live = db::live_heartbeats(&pool, &profile).await?;
+    if live.is_empty() {
+        println!("No run in progress");
+    }
+    let mut unhealthy = 0;
+    for heartbeat in &live {
+        let health = stale.map_or(Health::Alive, |stale| Health::of(heartbeat, stale, now));
+        let run = format!("{} run (pid {}) started {} ago", heartbeat.command, heartbeat.pid, ago(heartbeat.started_at));
+        match health {
+            Health::Alive => println!("● Running: {}, last progress {} ago", run, ago(heartbeat.progress_at)),
+            Health::Stuck => println!("⚠ Stuck: {}, no progress for {}", run, ago(heartbeat.progress_at)),
+            Health::Gone => println!("✗ Not responding: {}, last heard from {} ago", run, ago(heartbeat.beat_at)),
+        }
+        println!("  {}", process::heartbeat::describe(heartbeat));
+        if health != Health::Alive {
+            unhealthy += 1;
+        }
+    }
+
+    if let Some(last) = db::load_runs(&pool, &profile, 1).await?.first() {
+        println!(
+            "\nLast run:       {} ({} ago), {} uploaded, {} skipped, {} failed",
+            last.finished_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+            ago(last.finished_at),
+            last.uploaded,
+            last.skipped,
+            last.failed
+        );
+        if let Some(error) = &last.error {
+            println!("Error:          {}", error);
+        }
+    }
+
+    let gmail = auth::gmail_auth::signed_in_as(config.profile.as_deref());
+    let drive = auth::drive_auth::signed_in_as(config.profile.as_deref());
+    if gmail.is_some() || drive.is_some() {
+        let unknown = || "not signed in".to_string();
+        println!("\nAccounts:       Gmail: {} / Drive: {}", gmail.clone().unwrap_or_else(unknown), drive.clone().unwrap_or_else(unknown));
+    }
+    for warning in auth::oauth::account_warnings(&config, gmail.as_deref(), drive.as_deref()) {
+        println!("⚠ {}", warning);
+    }
+
+    if unhealthy > 0 {
+        anyhow::bail!("{} run(s) stuck or not responding", unhealthy);
+    }
+    Ok(())
+}
+
+async fn run_tidy(dry_run: bool, yes: bool, flags: Flags) -> Result<()> {
+    println!("🧹 Invoice Agent - Tidy Archive Folders\n");
+
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    if !flags.mock && config.storage_backend != StorageKind::Drive {
+        return Err(anyhow::anyhow!(
+            "tidy merges folders in the Google Drive archive, but STORAGE_BACKEND is the {}",
+            config.storage_backend.label()
+        ))
+        .context(FailureKind::Config);
+    }
+
+    let client = storage::drive_client(&config).await?;
+    let mut roots = vec![process::retention::managed_root(&config.drive_folder_path)];
+    if let Some(cold) = &config.retention.cold_storage_folder {
+        roots.push(cold.trim_matches('/').to_string());
+    }
+    let mut duplicates = Vec::new();
+    for root in &roots {
+        println!("Scanning {}...", if root.is_empty() { "My Drive" } else { root });
+        duplicates.extend(process::tidy::find_duplicates(&client, root).await?);
+    }
+    println!();
+    for set in &duplicates {
+        println!("  {}: {} duplicate folder(s) with {} file(s)", set.path, set.duplicates.len(), set.files);
+    }
+    if duplicates.is_empty() {
+        println!("✓ No duplicate folders");
+        return Ok(());
+    }
+    if dry_run {
+        println!("\nDry run: nothing was changed");
+        return Ok(());
+    }
+    let count: usize = duplicates.iter().map(|set| set.duplicates.len()).sum();
+    let prompt = format!("\nMerge {} duplicate folder(s) into the oldest of each name? Identical copies and emptied folders go to the Drive trash. [y/N] ", count);
+    if !yes && !cli::confirm_prompt(&prompt)? {
+        anyhow::bail!("Aborted - nothing was changed");
+    }
+
+    let pool = if config.mock_mode { None } else { db::connect_optional("Record updates").await };
+    let (tx, printer) = spawn_progress_printer();
+    let result = process::tidy::tidy(&client, pool.as_ref(), &config.profile.clone().unwrap_or_default(), &duplicates, &tx).await;
+    drop(tx);
+    let _ = printer.await;
+    let summary = result?;
+
+    println!("\n═══ Summary ═══");
+    println!("Merged:         {} folder(s)", summary.merged);
+    println!("Moved:          {} item(s)", summary.moved);
+    if summary.renamed > 0 {
+        println!("Renamed:        {} item(s) whose name was taken", summary.renamed);
+    }
+    println!("Identical:      {} file(s) trashed", summary.identical);
+    if summary.relinked > 0 {
+        println!("Relinked:       {} invoice record(s)", summary.relinked);
+    }
+    if summary.failed > 0 {
+        anyhow::bail!("{} folder(s) could not be merged", summary.failed);
+    }
+    println!("\n✅ Archive folders tidied");
+    Ok(())
+}
+
+async fn run_audit(verify: bool, reupload: bool, flags: Flags) -> Result<()> {
+    println!("🔎 Invoice Agent - Archive Audit\n");
+
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    if !flags.mock && config.storage_backend != StorageKind::Drive {
+        return Err(anyhow::anyhow!(
+            "audit checks the Google Drive archive, but STORAGE_BACKEND is the {}",
+            config.storage_backend.label()
+        ))
+        .context(FailureKind::Config);
+    }
+    let pool = db::init_pool()
+        .await
+        .context("The audit needs the invoice database (set DATABASE_URL)")
+        .context(FailureKind::Config)?;
+
+    let client = storage::drive_client(&config).await?;
+    let mut roots = vec![process::retention::managed_root(&config.drive_folder_path)];
+    if let Some(cold) = &config.retention.cold_storage_folder {
+        roots.push(cold.trim_matches('/').to_string());
+    }
+    let records = db::archived_files(&pool, &config.profile.clone().unwrap_or_default(), None).await?;
+    println!("Checking {} archived document(s) ({})...\n", records.len(), if verify { "presence, size and checksum" } else { "presence only" });
+    let report = process::audit::audit(&client, &roots, records, verify).await?;
+
+    if !report.missing.is_empty() {
+        println!("✗ Missing from Drive ({}):", report.missing.len());
+        for record in &report.missing {
+            println!("  {}/{}", record.folder, record.filename);
+        }
+    }
+    if !report.changed.is_empty() {
+        println!("✗ Changed in Drive ({}):", report.changed.len());
+        for (record, change) in &report.changed {
+            println!("  {}/{}: {}", record.folder, record.filename, change);
+        }
+    }
+    if report.unverifiable > 0 {
+        println!("ℹ {} document(s) were archived before sizes and checksums were recorded; checked for presence only", report.unverifiable);
+    }
+    if !report.untracked.is_empty() {
+        println!("ℹ Not in the invoice database ({}):", report.untracked.len());
+        for path in report.untracked.iter().take(20) {
+            println!("  {}", path);
+        }
+        if report.untracked.len() > 20 {
+            println!("  ... and {} more", report.untracked.len() - 20);
+        }
+    }
+
+    let mut restored = 0;
+    if reupload && !report.missing.is_empty() {
+        println!("\n═══ Re-uploading missing files ═══");
+        let source = mail::connect(&config).await?;
+        for record in &report.missing {
+            match process::audit::reupload(&client, source.as_ref(), record, &config.temp_dir()).await {
+                Ok(uploaded) => {
+                    db::update_archived_file(&pool, record.id, &uploaded.id, &uploaded.link()).await?;
+                    println!("  ✓ {}/{}", record.folder, record.filename);
+                    restored += 1;
+                }
+                Err(e) => println!("  ✗ {}/{}: {:#}", record.folder, record.filename, e),
+            }
+        }
+    }
+
+    let drifted = report.drifted() - restored;
+    if drifted > 0 {
+        anyhow::bail!("{} archived document(s) no longer match their records", drifted);
+    }
+    println!("\n✅ Every archived document is in place");
+    Ok(())
+}
+
+async fn fetch_and_upload_invoices(
+    config: &Config,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    confirm: bool,
+    heartbeat: &Heartbeat,
+) -> Result<RunSummary> {
+    let mut summary = RunSummary::default();
+
+    heartbeat.stage("Connecting");
+    let (source, storage) = if config.mock_mode {
+        println!("🧪 Mock mode: replaying fixtures from {}", config.mock_fixtures_dir.display());
+        println!("   Uploads are written to {}", config.mock_drive_dir.display());
+        (mail::connect(config).await?, storage::connect(config).await?)
+    } else {
+        // 1. Authenticate with Gmail
+        println!("═══ Gmail Authentication ═══");
+        let source = mail::connect(config).await?;
+
+        // 2. Authenticate with Drive (or connect to the SFTP server), and the mirror if any
+        match config.storage_backend {
+            StorageKind::Drive => println!("\n═══ Google Drive Authentication ═══"),
+            StorageKind::Sftp => println!("\n═══ SFTP Connection ═══"),
+            StorageKind::Gcs => println!("\n═══ Google Cloud Storage Authentication ═══"),
+            StorageKind::Local => println!("\n═══ Local Archive ═══"),
+        }
+        if let Some(mirror) = config.storage_mirror {
+            println!("  Mirroring every upload to the {}", mirror.label());
+        }
+        (source, storage::connect(config).await?)
+    };
+
+    // 3. Search Gmail for invoices
+    println!("\n═══ Searching {} ═══", source.name());
+    heartbeat.stage(&format!("Searching {}", source.name()));
+    if let Some(user) = &config.gmail_user {
+        println!("  Mailbox: {}", user);
+    }
+    let estimate = mail::search::estimate_keywords(source.as_ref(), start_date, end_date, &config.target_keywords, &config.search_exclusions(), config.search_concurrency).await;
+    if let Some(line) = estimate.summary() {
+        println!("{}", line);
+    }
+    if let Some(warning) = estimate.warning(config.search_warn_messages, config.message_limit) {
+        println!("{}", warning);
+        if confirm {
+            heartbeat.stage("Waiting for confirmation");
+        }
+        if confirm && !cli::confirm_prompt("Search and fetch them all anyway? [y/N] ")? {
+            anyhow::bail!("Aborted - nothing was downloaded or uploaded");
+        }
+    }
+
+    let progress = cli::progress::Progress::new();
+    let searching = progress.bar("Search", None);
+    searching.set_message(format!("{} keyword(s)", config.target_keywords.len()));
+    let search = mail::search::search_keywords(source.as_ref(), start_date, end_date, &config.target_keywords, &config.search_exclusions(), config.search_concurrency, config.message_limit).await;
+    progress.finish(&searching, match &search {
+        Ok(search) => format!("{} message(s)", search.message_ids.len()),
+        Err(_) => "failed".to_string(),
+    });
+    let search = search?;
+    for line in search.keyword_report() {
+        progress.println(&line);
+    }
+    let message_ids = search.message_ids;
+
+    if message_ids.is_empty() {
+        println!("\nℹ No invoices found in the specified date range");
+        return Ok(summary);
+    }
+
+    // 4. Fetch messages to estimate the download before committing to it
+    let mut messages = Vec::new();
+    let mut outside_range = 0;
+    heartbeat.stage("Fetching messages");
+    let fetching = progress.bar("Fetch", Some(message_ids.len() as u64));
+    for (idx, message_id) in message_ids.iter().enumerate() {
+        heartbeat.items(idx, message_ids.len());
+        match source.fetch_message(message_id).await {
+            Ok(message) if !message.received_within(start_date, end_date, config.mailbox_timezone) => outside_range += 1,
+            Ok(mut message) => {
+                for name in mail::attachment::retain_matching_names(&mut message, &config.attachment_name_patterns) {
+                    progress.println(&format!("   ⊘ Ignoring {}: name doesn't match ATTACHMENT_NAME_PATTERNS", name));
+                }
+                messages.push(message);
+            }
+            Err(e) => {
+                summary.fail(Stage::Fetch, Some(message_id), None, &e);
+                progress.suspend(|| eprintln!("   ✗ Failed to fetch message {} ({}/{}): {}", message_id, idx + 1, message_ids.len(), e));
+            }
+        }
+        fetching.inc(1);
+    }
+    progress.finish(&fetching, format!("{} message(s) in range", messages.len()));
+
+    if outside_range > 0 {
+        println!("   ℹ {} message(s) arrived outside the date range in the mailbox timezone and were ignored", outside_range);
+    }
+    let drafts = mail::search::drop_drafts_and_chats(&mut messages);
+    if drafts > 0 {
+        println!("   ℹ {} draft or chat message(s) ignored", drafts);
+    }
+    let left_out = mail::search::cap_messages(&mut messages, config.message_limit, config.newest_first);
+    if left_out > 0 {
+        println!("   ℹ {} message(s) left out by --limit", left_out);
+    }
+
+    if confirm {
+        let attachment_count: usize = messages.iter().map(|m| m.attachments.len()).sum();
+        let total_bytes: u64 = messages.iter()
+            .flat_map(|m| &m.attachments)
+            .filter_map(|a| a.size)
+            .sum();
+
+        let prompt = format!(
+            "\nFound {} messages, estimated {} attachments (~{}). Proceed? [y/N] ",
+            messages.len(),
+            attachment_count,
+            format_size(total_bytes)
+        );
+        heartbeat.stage("Waiting for confirmation");
+        if !cli::confirm_prompt(&prompt)? {
+            anyhow::bail!("Aborted - nothing was downloaded or uploaded");
+        }
+    }
+
+    // 5. Download, upload and clean up (shared with the TUI pipeline)
+    println!("\n═══ Downloading & Uploading ═══");
+    let (tx, printer) = progress.spawn_printer();
+    let (relay, forwarder) = heartbeat.relay(&tx);
+    let config = &Config { confirm_uploads: confirm, ..config.clone() };
+    let result = process::jobs::archive_messages(config, source.as_ref(), storage.as_ref(), &messages, start_date, end_date, &relay).await;
+    drop(relay);
+    let _ = forwarder.await;
+    drop(tx);
+    let _ = printer.await;
+
+    let archived = result?;
+    summary.processed = archived.processed;
+    summary.uploaded = archived.uploaded;
+    summary.skipped = archived.skipped;
+    summary.failed += archived.failed;
+    summary.failures.extend(archived.failures);
+    summary.quarantined = archived.quarantined;
+    summary.billing_month = archived.billing_month;
+    summary.folder = archived.folder;
+    summary.folder_link = archived.folder_link;
+    summary.budget_alerts = archived.budget_alerts;
+    summary.mirror = archived.mirror;
+    summary.from_spam = archived.from_spam;
+    summary.from_trash = archived.from_trash;
+
+    // Print summary
+    println!("\n═══ Summary ═══");
+    println!("Total files:    {}", summary.processed);
+    println!("Uploaded:       {}", summary.uploaded);
+    println!("Skipped:        {}", summary.skipped);
+    println!("Failed:         {}", summary.failed);
+    print_mirror_line(&summary);
+    if config.include_spam || config.include_trash {
+        println!("By folder:      {}", summary.folder_counts());
+    }
+    if summary.quarantined > 0 {
+        println!("☣ Quarantined:  {} (infected, not uploaded - see {})", summary.quarantined, config.quarantine_dir.display());
+    }
+    if let Some(folder) = &summary.folder {
+        println!("Monthly folder: {}", folder);
+    }
+    print_folder_link(&summary);
+    let usage = process::telemetry::snapshot();
+    if !usage.is_empty() {
+        println!("API usage:      {}", process::telemetry::describe(&usage));
+    }
+    for alert in &summary.budget_alerts {
+        println!("💸 Over budget: {}", alert);
+    }
+    process::failures::save_report(config, start_date, end_date, &summary, None);
+
+    Ok(summary)
+}
+
+async fn run_retry(from: &Path, profiles_dir: &Path, flags: Flags) -> Result<RunSummary> {
+    println!("🔁 Invoice Agent - Retry Failures\n");
+
+    let report = process::failures::FailureReport::load(from).context(FailureKind::Config)?;
+    let mut config = match &report.profile {
+        Some(name) => Config::load_profile(name, &profiles_dir.join(format!("{}.env", name)), flags),
+        None => Config::load_with(flags),
+    }
+    .context(FailureKind::Config)?;
+    report.restore(&mut config);
+    println!("Report:         {} ({} failure(s), {})", from.display(), report.failures.len(), report.created_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"));
+    println!("Date range:     {} to {}\n", report.start_date, report.end_date);
+
+    let (tx, printer) = spawn_progress_printer();
+    let started_at = chrono::Utc::now();
+    let result = async {
+        let (source, storage) = process::jobs::connect(&config, &tx).await?;
+        process::failures::retry(&config, source.as_ref(), storage.as_ref(), &report, &tx).await
+    }
+    .await;
+    drop(tx);
+    let _ = printer.await;
+    hooks::run_finished(&config, &result, None).await;
+    process::jobs::record_run(&config, report.start_date, report.end_date, started_at, &result, &process::telemetry::ApiUsage::new(), None).await;
+    let summary = result?;
+
+    println!("\n═══ Summary ═══");
+    println!("Total files:    {}", summary.processed);
+    println!("Uploaded:       {}", summary.uploaded);
+    println!("Skipped:        {} (already in place)", summary.skipped);
+    println!("Failed:         {}", summary.failed);
+    print_mirror_line(&summary);
+    print_folder_link(&summary);
+
+    // The report is done with once everything went through; a new one lists what failed again
+    if summary.failed == 0 {
+        std::fs::remove_file(from).with_context(|| format!("Failed to remove {}", from.display()))?;
+        println!("\n✅ Every failure was retried successfully; removed {}", from.display());
+    }
+    Ok(summary)
+}
+
+/// Print where the run's folder opens (Drive's web link), when the storage gave one
+fn print_folder_link(summary: &RunSummary) {
+    if let Some(link) = &summary.folder_link {
+        println!("Open:           {}", link);
+    }
+}
+
+/// Print the mirror's own counts under the summary, when uploads were mirrored
+fn print_mirror_line(summary: &RunSummary) {
+    if let Some(mirror) = &summary.mirror {
+        println!("Mirror:         {} copied, {} skipped, {} failed ({})", mirror.uploaded, mirror.skipped, mirror.failed, mirror.name);
+    }
+}
+
+/// Print pipeline progress messages as they arrive (internal markers are skipped)
+fn spawn_progress_printer() -> (tokio::sync::mpsc::UnboundedSender<String>, tokio::task::JoinHandle<()>) {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let printer = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            if !message.starts_with("__RESULTS__:") && !message.starts_with(STEP_MARKER) {
+                println!("{}", message);
+            }
+        }
+    });
+    (tx, printer)
+}
+
+/// The passphrase of a token bundle, from TOKENS_PASSPHRASE or typed without echo (twice when
+/// `confirm`, as a typo would lock the bundle)
+fn read_passphrase(prompt: &str, confirm: bool) -> Result<String> {
+    use std::io::IsTerminal;
+
+    Config::load_dotenv();
+    if let Ok(passphrase) = std::env::var("TOKENS_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("Passphrase required but stdin is not a terminal; set TOKENS_PASSPHRASE");
+    }
+
+    let passphrase = read_hidden(prompt)?;
+    if passphrase.is_empty() {
+        anyhow::bail!("The passphrase can't be empty");
+    }
+    if confirm && read_hidden("Repeat the passphrase: ")? != passphrase {
+        anyhow::bail!("The passphrases don't match");
+    }
+    Ok(passphrase)
+}
+
+fn read_hidden(prompt: &str) -> Result<String> {
+    use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+    use std::io::Write;
+
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    crossterm::terminal::enable_raw_mode()?;
+    let mut typed = String::new();
+    let read = loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Enter => break Ok(typed),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break Err(anyhow::anyhow!("Cancelled")),
+                KeyCode::Char(c) => typed.push(c),
+                KeyCode::Backspace => {
+                    typed.pop();
+                }
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(e) => break Err(e.into()),
+        }
+    };
+    crossterm::terminal::disable_raw_mode()?;
+    println!();
+    read
+}
+
+/// Decrypt `.age` files from the archive with the identity file
+fn run_decrypt(paths: &[PathBuf], identity: Option<PathBuf>, output: Option<&Path>) -> Result<()> {
+    Config::load_dotenv();
+    let identity = identity
+        .or_else(|| std::env::var("AGE_IDENTITY_FILE").ok().map(PathBuf::from))
+        .context("No identity file: pass --identity or set AGE_IDENTITY_FILE")
+        .context(FailureKind::Config)?;
+    let identities = process::encrypt::load_identities(&identity).context(FailureKind::Config)?;
+
+    if let Some(dir) = output {
+        fs::create_dir_all(dir).context("Failed to create output directory")?;
+    }
+
+    let files = process::encrypt::collect_encrypted_files(paths)?;
+    if files.is_empty() {
+        anyhow::bail!("No .age files found");
+    }
+
+    let mut failed = 0;
+    for file in &files {
+        match process::encrypt::decrypt_file(file, &identities, output) {
+            Ok(decrypted) => println!("✓ {}", decrypted.display()),
+            Err(e) => {
+                failed += 1;
+                eprintln!("✗ {:#}", e);
+            }
+        }
+    }
+
+    println!("\nDecrypted {} of {} file(s)", files.len() - failed, files.len());
+    if failed > 0 {
+        anyhow::bail!("{} file(s) could not be decrypted", failed);
+    }
+    Ok(())
+}
+
+async fn run_search(query: &db::InvoiceQuery, flags: Flags) -> Result<()> {
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let pool = db::init_pool()
+        .await
+        .context("Archive search needs the invoice database (set DATABASE_URL)")
+        .context(FailureKind::Config)?;
+
+    let documents = db::search_invoice_documents(&pool, config.profile.as_deref().unwrap_or_default(), query).await?;
+    if documents.is_empty() {
+        println!("No archived documents match.");
+        return Ok(());
+    }
+
+    for document in &documents {
+        let month = document.billing_month.map(|m| m.format("%Y-%m").to_string()).unwrap_or_else(|| "-------".to_string());
+        println!(
+            "{}  {:<24} {:<18} {:>14}  {}",
+            month,
+            document.vendor,
+            document.invoice_number.as_deref().unwrap_or("-"),
+            document.display_amount(),
+            document.filename
+        );
+        if let Some(snippet) = &document.snippet {
+            println!("         …{}…", snippet);
+        }
+        println!("         {}", document.web_link.as_deref().unwrap_or(&document.file_id));
+    }
+    println!("\n{} document(s)", documents.len());
+    Ok(())
+}
+
+async fn run_trends(month: Option<&str>, window: u32, flags: Flags) -> Result<()> {
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let month = match month {
+        Some(month) => scheduler::runner::parse_month(month).context(FailureKind::Config)?,
+        None => chrono::Local::now().date_naive().with_day(1).unwrap_or_default(),
+    };
+    let pool = db::init_pool()
+        .await
+        .context("Trend reports need the invoice database (set DATABASE_URL)")
+        .context(FailureKind::Config)?;
+
+    let report = process::trends::TrendReport::load(&pool, config.profile.as_deref().unwrap_or_default(), month, window).await?;
+    print!("{}", report.render());
+    Ok(())
+}
+
+async fn run_fiscal_report(year: &str, flags: Flags) -> Result<()> {
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let calendar = config.fiscal_calendar;
+    let start = match year.trim() {
+        "" => calendar.year_start(chrono::Local::now().date_naive()),
+        year => calendar
+            .named_year_start(year)
+            .with_context(|| format!("Fiscal year must look like 2025 or 2025-26 (got '{}')", year))
+            .context(FailureKind::Config)?,
+    };
+    let pool = db::init_pool()
+        .await
+        .context("Fiscal year reports need the invoice database (set DATABASE_URL)")
+        .context(FailureKind::Config)?;
+
+    let report = process::fiscal::FiscalReport::load(&pool, config.profile.as_deref().unwrap_or_default(), calendar, start).await?;
+    print!("{}", report.render());
+    Ok(())
+}
+
+async fn run_payables(file_id: Option<&str>, paid: bool, unpaid: bool, remind: bool, days: Option<i64>, flags: Flags) -> Result<()> {
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let pool = db::init_pool()
+        .await
+        .context("Payables need the invoice database (set DATABASE_URL)")
+        .context(FailureKind::Config)?;
+    let profile = config.profile.as_deref().unwrap_or_default();
+
+    if let Some(file_id) = file_id {
+        if !paid && !unpaid {
+            return Err(anyhow::anyhow!("Pass --paid or --unpaid to mark {}", file_id)).context(FailureKind::Config);
+        }
+        if !db::set_invoice_paid(&pool, profile, file_id, paid).await? {
+            anyhow::bail!("No archived document with file id {}", file_id);
+        }
+        println!("✓ Marked {} {}", file_id, if paid { "paid" } else { "unpaid" });
+        return Ok(());
+    }
+
+    let today = chrono::Local::now().date_naive();
+    let payables = process::payables::Payables::load(&pool, profile, today, days.unwrap_or(config.due_reminder_days)).await?;
+    print!("{}", payables.render());
+    if !remind || payables.is_empty() {
+        return Ok(());
+    }
+
+    if config.digest_email.is_none() && config.on_payment_due.is_none() {
+        return Err(anyhow::anyhow!("Nowhere to send the reminder: set DIGEST_EMAIL or ON_PAYMENT_DUE in .env"))
+            .context(FailureKind::Config);
+    }
+    if let Some(to) = &config.digest_email {
+        let raw = gmail::send::build_text_message(to, &payables.subject(), &payables.render());
+        gmail::send::send_from_profile(&config, &raw, &format!("payables-{}.eml", today)).await?;
+        println!("✓ Reminder emailed to {}", to);
+    }
+    hooks::payment_due(&config, &payables).await?;
+    Ok(())
+}
+
+async fn run_reconcile(statements: &[PathBuf], month: Option<&str>, bank: Option<&str>, flags: Flags) -> Result<()> {
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let profile = config.profile.as_deref().unwrap_or_default();
+    let pool = db::init_pool()
+        .await
+        .context("Reconciliation needs the invoice database (set DATABASE_URL)")
+        .context(FailureKind::Config)?;
+
+    let transactions = match month {
+        Some(month) => {
+            let start = scheduler::runner::parse_month(month).context(FailureKind::Config)?;
+            let end = start + chrono::Months::new(1) - chrono::Duration::days(1);
+            let transactions = db::load_transactions(&pool, profile, bank, start, end).await?;
+            println!("📄 {} transaction(s) recorded for {}", transactions.len(), start.format("%B %Y"));
+            transactions
+        }
+        None => {
+            let formats = extract::statement::StatementFormats::load(config.statement_formats_file.as_deref()).context(FailureKind::Config)?;
+            let mapping = match bank {
+                Some(bank) => Some(formats.for_bank(bank).with_context(|| format!("No column mapping for {} in the statement formats file", bank)).context(FailureKind::Config)?),
+                None => None,
+            };
+            let mut transactions = Vec::new();
+            for path in statements {
+                let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display())).context(FailureKind::Config)?;
+                let parsed = extract::statement::parse_statement(&path.to_string_lossy(), &data, mapping).context(FailureKind::Config)?;
+                println!("📄 {}: {} transaction(s)", path.display(), parsed.len());
+                transactions.extend(parsed);
+            }
+            transactions
+        }
+    };
+
+    let reconciliation = process::reconcile::Reconciliation::load(&pool, profile, transactions).await?;
+    println!("\n{}", reconciliation.render());
+    Ok(())
+}
+
+#[cfg(feature = "dashboard")]
+async fn run_serve(bind: &str, flags: Flags) -> Result<()> {
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    auth::keepalive::spawn_for(&config);
+    interfaces::web::serve(config, bind).await
+}
+
+#[cfg(not(feature = "dashboard"))]
+async fn run_serve(_bind: &str, _flags: Flags) -> Result<()> {
+    Err(anyhow::anyhow!("invoice-pilot was built without the `dashboard` feature; rebuild with `--features dashboard`"))
+        .context(FailureKind::Config)
+}
+
+#[cfg(feature = "grpc")]
+async fn run_grpc(bind: &str, flags: Flags) -> Result<()> {
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    auth::keepalive::spawn_for(&config);
+    interfaces::grpc::serve(config, bind).await
+}
+
+#[cfg(not(feature = "grpc"))]
+async fn run_grpc(_bind: &str, _flags: Flags) -> Result<()> {
+    Err(anyhow::anyhow!("invoice-pilot was built without the `grpc` feature; rebuild with `--features grpc`"))
+        .context(FailureKind::Config)
+}
+
+fn run_correct(file_id: Option<&str>, not_invoice: bool, folder: Option<String>, clear: bool, flags: Flags) -> Result<()> {
+    let config = Config::load_with(flags).context(FailureKind::Config)?;
+    let mut feedback = process::feedback::Feedback::load(&config).context(FailureKind::Config)?;
+
+    let Some(file_id) = file_id else {
+        println!("Recently processed files:");
+        for file in feedback.recent().take(20) {
+            println!("  {}  {}  ({}, from {})", file.file_id, file.filename, file.folder, file.sender);
+        }
+        println!("\nCorrections:");
+        for (sender, entry) in feedback.corrections() {
+            println!("  {}: {}", sender, entry.correction);
+        }
+        return Ok(());
+    };
+
+    let correction = match (not_invoice, folder) {
+        (true, _) => Some(process::feedback::Correction::NotInvoice),
+        (false, Some(folder)) => Some(process::feedback::Correction::Folder(folder)),
+        (false, None) if clear => None,
+        (false, None) => {
+            return Err(anyhow::anyhow!("Pass --not-invoice, --folder <NAME> or --clear")).context(FailureKind::Config);
+        }
+    };
+
+    let described = correction.as_ref().map_or("correction cleared".to_string(), |c| c.to_string());
+    let file = feedback.correct(file_id, correction)?;
+    println!("✓ {} (from {}): {}", file.filename, file.sender, described);
+    feedback.save()?;
+    println!("Future runs will apply this to every attachment from the same sender.");
+    Ok(())
+}
+
+/// Human readable size for the download estimate
+fn format_size(bytes: u64) -> String {
+    const MB: u64 = 1024 * 1024;
+    if bytes >= MB {
+        format!("{} MB", bytes.div_ceil(MB))
+    } else {
+        format!("{} KB", bytes.div_ceil(1024))
+    }
+}
+
+/// Report a revocation; one that fails still lets the local tokens be cleared
+fn print_revoked(account: &str, revoked: Result<bool>) {
+    match revoked {
+        Ok(true) => println!("  ✓ Revoked the {} grant with Google", account),
+        Ok(false) => println!("  ℹ No {} grant to revoke", account),
+        Err(e) => println!(
+            "  ⚠ Couldn't revoke the {} grant ({:#}); remove access at https://myaccount.google.com/permissions",
+            account, e
+        ),
+    }
+}
+
+async fn handle_auth_command(action: AuthAction, flags: Flags) -> Result<()> {
+    match action {
+        AuthAction::Gmail { revoke } => {
+            println!("🔄 Re-authenticating Gmail...\n");
+            if revoke {
+                print_revoked("Gmail", auth::gmail_auth::revoke_gmail_token().await);
+            }
+            auth::gmail_auth::clear_gmail_token()?;
+
+            let config = Config::from_env().context(FailureKind::Config)?;
+            let signed_in = auth::gmail_auth::get_gmail_token_for_profile(
+                config.gmail_client_id,
+                config.gmail_client_secret,
+                config.gmail_account.as_deref(),
+                config.profile.as_deref(),
+                config.paste_code,
+            )
+            .await;
+            auth::failure::track(config.profile.as_deref(), auth::failure::Service::Gmail, signed_in)?;
+
+            println!("\n✅ Gmail re-authenticated successfully!");
+        }
+        AuthAction::Drive { revoke } => {
+            println!("🔄 Re-authenticating Google Drive...\n");
+            if revoke {
+                print_revoked("Google Drive", auth::drive_auth::revoke_drive_token().await);
+            }
+            auth::drive_auth::clear_drive_token()?;
+
+            let config = Config::from_env().context(FailureKind::Config)?;
+            let signed_in = auth::drive_auth::get_drive_token_for_profile(
+                config.drive_client_id,
+                config.drive_client_secret,
+                config.drive_account.as_deref(),
+                config.profile.as_deref(),
+                config.paste_code,
+            )
+            .await;
+            auth::failure::track(config.profile.as_deref(), auth::failure::Service::Drive, signed_in)?;
+
+            println!("\n✅ Google Drive re-authenticated successfully!");
+        }
+        AuthAction::Reset => {
+            println!("🔄 Resetting all authentication tokens...\n");
+            print_revoked("Gmail", auth::gmail_auth::revoke_gmail_token().await);
+            print_revoked("Google Drive", auth::drive_auth::revoke_drive_token().await);
+            auth::gmail_auth::clear_gmail_token()?;
+            auth::drive_auth::clear_drive_token()?;
+            println!("\n✅ All tokens cleared! Run manual or scheduled mode to re-authenticate.");
+        }
+        AuthAction::Status => {
+            // Status works without a loadable .env, just without the configured accounts
+            let config = Config::from_env().ok();
+            let profile = config.as_ref().and_then(|config| config.profile.as_deref());
+            let config_dir = auth::oauth::get_token_dir(profile)?;
+            let failures = auth::failure::load(profile)?;
+            let mut accounts = Vec::new();
+            for (service, file) in [(auth::failure::Service::Gmail, "gmail_token.json"), (auth::failure::Service::Drive, "drive_token.json")] {
+                println!("{}", service.label());
+                let path = config_dir.join(file);
+                let account = path.exists().then(|| auth::oauth::cached_account(&path)).flatten();
+                let token = if !path.exists() {
+                    format!("not signed in (run `{}`)", service.reauth_command())
+                } else {
+                    match auth::oauth::load_token(&path) {
+                        Ok(token) if !token.is_expired() => match token.expires_at.and_then(|at| chrono::DateTime::from_timestamp(at, 0)) {
+                            Some(at) => format!("valid until {}", at.format("%Y-%m-%d %H:%M UTC")),
+                            None => "valid".to_string(),
+                        },
+                        Ok(token) if token.refresh_token.is_some() => "expired, refreshed on the next run".to_string(),
+                        Ok(_) => format!("expired and can't be refreshed (run `{}`)", service.reauth_command()),
+                        Err(e) => format!("unreadable ({:#})", e),
+                    }
+                };
+                println!("  Token:          {}", token);
+                match &account {
+                    Some(account) => println!("  Account:        {}", account),
+                    None if path.exists() => println!("  Account:        unknown (recorded once a run uses the token)"),
+                    None => {}
+                }
+                if let Some(config) = &config
+                    && let Ok(token) = auth::oauth::load_token(&path)
+                    && let Some(at) = auth::expiry::refresh_expires_at(&token, config.oauth_refresh_token_days)
+                {
+                    println!("  Sign-in ends:   {} (OAUTH_REFRESH_TOKEN_DAYS)", at.format("%Y-%m-%d %H:%M UTC"));
+                }
+                accounts.push(account);
+                match failures.get(service.key()) {
+                    Some(failure) => {
+                        println!("  Last error:     {} at {}", failure.kind.label(), failure.at.format("%Y-%m-%d %H:%M UTC"));
+                        println!("                  {}", failure.message);
+                        println!("  Fix:            {}", failure.kind.remedy(&format!("re-run `{}`", service.reauth_command())));
+                    }
+                    None => println!("  Last error:     none"),
+                }
+            }
+            if let [Some(mail), Some(storage)] = accounts.as_slice()
+                && !mail.eq_ignore_ascii_case(storage)
+            {
+                println!("\nCross-account: mail is read as {} and documents are stored as {}", mail, storage);
+            }
+            if let (Some(config), [gmail, drive]) = (&config, accounts.as_slice()) {
+                for warning in auth::oauth::account_warnings(config, gmail.as_deref(), drive.as_deref()) {
+                    println!("\n⚠ {}", warning);
+                }
+            }
+            if !failures.is_empty() {
+                return Err(anyhow::anyhow!("{} service(s) failed to sign in last time", failures.len())).context(FailureKind::Auth);
+            }
+        }
+        AuthAction::Check => {
+            let config = Config::load_with(flags).context(FailureKind::Config)?;
+            let now = chrono::Utc::now();
+            let warnings = auth::expiry::check(&config, now)?;
+            if warnings.is_empty() {
+                match auth::expiry::next_scheduled_run(&config, now) {
+                    Some(run) => println!("✓ The saved sign-ins are fine for the run on {}", run.with_timezone(&chrono::Local).format("%b %-d, %H:%M")),
+                    None => println!("✓ The saved sign-ins are fine"),
+                }
+                return Ok(());
+            }
+            for warning in &warnings {
+                println!("⚠ {}: {}", warning.service.label(), warning.message);
+                println!("  Fix: run `{}`", warning.service.reauth_command());
+            }
+            // Notifying the sinks sends something, which read-only mode doesn't
+            if config.read_only {
+                println!("(read-only: the auth_expiring sinks were not notified)");
+            } else {
+                auth::expiry::alert(&config, &warnings).await;
+            }
+            return Err(anyhow::anyhow!("{} sign-in(s) need attention", warnings.len())).context(FailureKind::Auth);
+        }
+        AuthAction::Export { out } => {
+            if out.exists() {
+                anyhow::bail!("{} already exists", out.display());
+            }
+            let passphrase = read_passphrase("Passphrase for the bundle: ", true)?;
+            let (bundle, files) = auth::transfer::bundle(&auth::oauth::get_config_dir()?, &passphrase)?;
+            fs::write(&out, bundle).with_context(|| format!("Failed to write {}", out.display()))?;
+            for file in &files {
+                println!("  ✓ {}", file);
+            }
+            println!("\n✅ Exported {} token(s) to {}", files.len(), out.display());
+            println!("   Copy it to the other machine and run `invoice-pilot auth import {}` there.", out.display());
+        }
+        AuthAction::Import { file, force } => {
+            let bundle = fs::read(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+            let passphrase = read_passphrase("Passphrase of the bundle: ", false)?;
+            let files = auth::transfer::unbundle(&bundle, &passphrase)?;
+            auth::transfer::restore(&auth::oauth::get_config_dir()?, &files, force)?;
+            for (path, _) in &files {
+                println!("  ✓ {}", path);
+            }
+            println!("\n✅ Imported {} token(s) from {}", files.len(), file.display());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes_of(args: &str) -> Option<&'static str> {
+        let cli = Cli::try_parse_from(std::iter::once("invoice-pilot").chain(args.split_whitespace())).expect("valid command line");
+        changes(&cli.command.expect("a command"))
+    }
+
+    #[test]
+    fn test_read_only_refuses_every_change() {
+        let changing = [
+            "manual",
+            "scheduled",
+            "run-all",
+            "run-workspace",
+            "ingest",
+            "digest",
+            "digest --now",
+            "package",
+            "archive maintain",
+            "db import export.json",
+            "index-drive --folder Invoices",
+            "reorganize --month 2024-03 --apply",
+            "reprocess --month 2024-03",
+            "retry --from run.json",
+            "tidy",
+            "audit --reupload",
+            "recover --resume",
+            "recover --clean",
+            "payables abc --paid",
+            "payables --remind",
+            "correct abc --not-invoice",
+            "auth reset",
+            "auth gmail --revoke",
+            "auth drive --revoke",
+            "auth export --out tokens.bundle",
+            "auth import tokens.bundle",
+        ];
+        for args in changing {
+            assert!(changes_of(args).is_some(), "`{}` changes something", args);
+        }
+
+        let looking = [
+            "digest --dry-run",
+            "archive maintain --dry-run",
+            "db export --out export.json",
+            "reorganize --month 2024-03",
+            "tidy --dry-run",
+            "audit --verify",
+            "recover",
+            "status",
+            "search aws",
+            "payables",
+            "correct",
+            "reconcile --month 2024-03",
+            "simulate --rules rules.toml --month 2024-03",
+            "auth gmail",
+            "auth drive",
+            "auth status",
+            "auth check",
+        ];
+        for args in looking {
+            assert_eq!(changes_of(args), None, "`{}` changes nothing", args);
+        }
+    }
+}
@@ -0,0 +1,522 @@
+//! Notification routing (NOTIFICATIONS_FILE, `notifications.toml` by default). Each sink
+//! subscribes to the events it cares about and may narrow them down further; an event goes to
+//! every sink that accepts it. Sinks are separate from the ON_* hooks, which keep working.
+//!
+//! ```toml
+//! [[sink]]
+//! kind = "slack"
+//! url = "https://hooks.slack.com/services/T000/B000/XXXX"
+//! events = ["run_failed", "auth_expiring"]
+//!
+//! [[sink]]
+//! kind = "email"
+//! to = "books@example.com"
+//! events = ["large_invoice", "missing_vendor"]
+//! min_amount = 500.00
+//! currency = "EUR"
+//!
+//! [[sink]]
+//! kind = "telegram"
+//! bot_token = "123456:ABC-DEF"
+//! chat_id = "-1001234567890"
+//! events = ["run_finished", "run_failed"]
+//! profiles = ["acme"]
+//!
+//! [[sink]]
+//! kind = "webhook"
+//! url = "https://example.com/invoice-events"
+//! events = ["large_invoice"]
+//! min_amount = 1000
+//! vendors = ["aws", "hetzner"]
+//!
+//! [[sink]]
+//! kind = "desktop"
+//! events = ["run_finished", "run_failed", "auth_expiring"]
+//! ```
+
+use crate::auth::oauth::get_token_dir;
+use crate::config::env::Config;
+use crate::config::shared;
+use crate::db::InvoiceDocument;
+use crate::gmail;
+use crate::hooks;
+use crate::process::outcome::RunSummary;
+use crate::process::trends::VendorTrend;
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+/// A sink that doesn't answer within this long is given up on
+const SINK_TIMEOUT: Duration = Duration::from_secs(30);
+
+const TELEGRAM_API_BASE: &str = "https://api.telegram.org";
+
+/// Vendors already notified missing, by month, stored next to the profile's tokens
+const MISSING_NOTIFIED_FILE: &str = "missing-vendors-notified.json";
+
+/// Months of missing-vendor notices kept
+const MISSING_NOTIFIED_MONTHS: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    /// A run finished without failed files
+    RunFinished,
+    /// A run failed, or finished with failed files
+    RunFailed,
+    /// A vendor billed in every one of the months before had nothing archived for a month that has ended
+    MissingVendor,
+    /// An archived invoice at or above the sink's `min_amount`
+    LargeInvoice,
+    /// A saved sign-in stopped refreshing and is about to expire
+    AuthExpiring,
+}
+
+impl Event {
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::RunFinished => "run_finished",
+            Event::RunFailed => "run_failed",
+            Event::MissingVendor => "missing_vendor",
+            Event::LargeInvoice => "large_invoice",
+            Event::AuthExpiring => "auth_expiring",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkKind {
+    Slack,
+    Email,
+    Webhook,
+    Telegram,
+    Desktop,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Router {
+    #[serde(default, rename = "sink")]
+    sinks: Vec<Sink>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sink {
+    pub kind: SinkKind,
+    pub events: Vec<Event>,
+    /// Incoming webhook of a Slack channel, or the endpoint of a webhook sink
+    pub url: Option<String>,
+    /// Recipient of an email sink, sent like the digest (Gmail send permission or ProtonMail Bridge)
+    pub to: Option<String>,
+    pub bot_token: Option<String>,
+    pub chat_id: Option<String>,
+    /// Only events of these profiles ("default" for the unnamed one)
+    #[serde(default)]
+    pub profiles: Vec<String>,
+    /// Only invoice events of vendors whose name contains one of these (case-insensitive)
+    #[serde(default)]
+    pub vendors: Vec<String>,
+    /// Only invoices of at least this amount; required for `large_invoice`
+    pub min_amount: Option<f64>,
+    /// Only invoices in this currency
+    pub currency: Option<String>,
+}
+
+/// An event ready to be sent: a subject and text for people, and its fields for webhooks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub event: Event,
+    pub profile: String,
+    pub subject: String,
+    pub text: String,
+    pub vendor: Option<String>,
+    pub amount_cents: Option<i64>,
+    pub currency: Option<String>,
+    pub fields: Value,
+}
+
+impl Router {
+    /// Load the notifications file; no file configured means no sinks
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read notifications file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid notifications file {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let router: Self = toml::from_str(text)?;
+        for (index, sink) in router.sinks.iter().enumerate() {
+            sink.check().with_context(|| format!("sink {} ({:?})", index + 1, sink.kind))?;
+        }
+        Ok(router)
+    }
+
+    /// Whether any sink takes `event`, so work only it needs can be skipped otherwise
+    pub fn subscribed(&self, event: Event) -> bool {
+        self.sinks.iter().any(|sink| sink.events.contains(&event))
+    }
+
+    /// Sinks that accept the notification, with their number in the file
+    pub fn routes<'a>(&'a self, notification: &'a Notification) -> impl Iterator<Item = (usize, &'a Sink)> + 'a {
+        self.sinks.iter().enumerate().filter(|(_, sink)| sink.accepts(notification)).map(|(index, sink)| (index + 1, sink))
+    }
+
+    /// Deliver the notification to every sink that accepts it. A failing sink is reported and
+    /// doesn't keep the others from getting it.
+    pub async fn send(&self, config: &Config, notification: &Notification, tx: Option<&mpsc::UnboundedSender<String>>) {
+        for (number, sink) in self.routes(notification) {
+            if let Err(e) = sink.deliver(config, notification).await {
+                hooks::report(tx, format!("⚠ Notification sink {} ({:?}) failed for {}: {:#}", number, sink.kind, notification.event.name(), e));
+            }
+        }
+    }
+}
+
+/// Load the notifications file of `config` and deliver the notification; an unreadable file is
+/// reported like a failing sink
+pub async fn send(config: &Config, notification: &Notification, tx: Option<&mpsc::UnboundedSender<String>>) {
+    match Router::load(config.notifications_file.as_deref()) {
+        Ok(router) => router.send(config, notification, tx).await,
+        Err(e) => hooks::report(tx, format!("⚠ Notifications not sent: {:#}", e)),
+    }
+}
+
+impl Sink {
+    fn check(&self) -> Result<()> {
+        anyhow::ensure!(!self.events.is_empty(), "`events` lists none of the events to send");
+        let missing = |field: &str| anyhow::anyhow!("a {:?} sink needs `{}`", self.kind, field);
+        match self.kind {
+            SinkKind::Slack | SinkKind::Webhook => {
+                self.url.as_ref().ok_or_else(|| missing("url"))?;
+            }
+            SinkKind::Email => {
+                self.to.as_ref().ok_or_else(|| missing("to"))?;
+            }
+            SinkKind::Telegram => {
+                self.bot_token.as_ref().ok_or_else(|| missing("bot_token"))?;
+                self.chat_id.as_ref().ok_or_else(|| missing("chat_id"))?;
+            }
+            SinkKind::Desktop => {}
+        }
+        if self.events.contains(&Event::LargeInvoice) && self.min_amount.is_none() {
+            anyhow::bail!("`large_invoice` needs `min_amount`, the smallest amount that counts as large");
+        }
+        Ok(())
+    }
+
+    /// Whether the sink takes the notification. Vendor, amount and currency filters only narrow
+    /// down events that have one, so a sink can mix invoice and run events.
+    pub fn accepts(&self, notification: &Notification) -> bool {
+        let vendor = |vendor: &String| {
+            let vendor = vendor.to_lowercase();
+            self.vendors.is_empty() || self.vendors.iter().any(|wanted| vendor.contains(&wanted.to_lowercase()))
+        };
+        let amount = |cents: &i64| self.min_amount.is_none_or(|min| *cents >= (min * 100.0).round() as i64);
+        let currency = |currency: &String| self.currency.as_ref().is_none_or(|wanted| wanted.eq_ignore_ascii_case(currency));
+
+        self.events.contains(&notification.event)
+            && (self.profiles.is_empty() || self.profiles.contains(&notification.profile))
+            && notification.vendor.as_ref().is_none_or(vendor)
+            && notification.amount_cents.as_ref().is_none_or(amount)
+            && notification.currency.as_ref().is_none_or(currency)
+    }
+
+    async fn deliver(&self, config: &Config, notification: &Notification) -> Result<()> {
+        let message = format!("{}\n\n{}", notification.subject, notification.text);
+        match self.kind {
+            SinkKind::Slack => {
+                let text = format!("*{}*\n{}", notification.subject, notification.text);
+                post(self.url.as_deref().unwrap_or_default(), &json!({ "text": text })).await
+            }
+            SinkKind::Webhook => post(self.url.as_deref().unwrap_or_default(), &notification.payload()).await,
+            SinkKind::Telegram => {
+                let url = format!("{}/bot{}/sendMessage", TELEGRAM_API_BASE, self.bot_token.as_deref().unwrap_or_default());
+                post(&url, &json!({ "chat_id": self.chat_id, "text": message })).await
+            }
+            SinkKind::Email => {
+                let raw = gmail::send::build_text_message(self.to.as_deref().unwrap_or_default(), &notification.subject, &notification.text);
+                let outbox_name = format!("notification-{}-{}.eml", notification.event.name(), chrono::Utc::now().format("%Y%m%dT%H%M%S"));
+                tokio::time::timeout(SINK_TIMEOUT, gmail::send::send_from_profile(config, &raw, &outbox_name))
+                    .await
+                    .context("timed out")?
+            }
+            SinkKind::Desktop => desktop(&notification.subject, &notification.text).await,
+        }
+    }
+}
+
+/// POST `body` as JSON. Errors name the host only: the path of a Slack webhook or a Telegram
+/// bot URL is a secret.
+async fn post(url: &str, body: &Value) -> Result<()> {
+    let host = host_of(url);
+    let response = reqwest::Client::new()
+        .post(url)
+        .timeout(SINK_TIMEOUT)
+        .json(body)
+        .send()
+        .await
+        .map_err(reqwest::Error::without_url)
+        .with_context(|| format!("Request to {} failed", host))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("{} answered {}: {}", host, status, body.trim());
+    }
+    Ok(())
+}
+
+/// "hooks.slack.com" for "https://hooks.slack.com/services/T000/B000/XXXX"
+fn host_of(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "the sink".to_string())
+}
+
+/// Note that `vendor` was notified missing for `month`; false when it already was, so a vendor
+/// is reported once per month rather than on every run until its invoice arrives
+pub fn first_missing_notice(config: &Config, month: NaiveDate, vendor: &str) -> Result<bool> {
+    let path = get_token_dir(config.profile.as_deref())?.join(MISSING_NOTIFIED_FILE);
+    mark_missing(&path, month, vendor)
+}
+
+fn mark_missing(path: &Path, month: NaiveDate, vendor: &str) -> Result<bool> {
+    let mut first = false;
+    shared::update_json(path, |notified: &mut BTreeMap<String, BTreeSet<String>>| {
+        first = notified.entry(month.format("%Y-%m").to_string()).or_default().insert(vendor.to_lowercase());
+        while notified.len() > MISSING_NOTIFIED_MONTHS {
+            notified.pop_first();
+        }
+    })
+    .context("Failed to record the missing-vendor notice")?;
+    Ok(first)
+}
+
+/// Show a desktop notification with notify-send (Linux) or osascript (macOS)
+async fn desktop(subject: &str, text: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!("display notification {:?} with title {:?}", text, subject));
+        command
+    } else if cfg!(windows) {
+        anyhow::bail!("desktop notifications aren't supported on Windows");
+    } else {
+        let mut command = Command::new("notify-send");
+        command.arg("--app-name=invoice-pilot").arg(subject).arg(text);
+        command
+    };
+    let status = tokio::time::timeout(SINK_TIMEOUT, command.kill_on_drop(true).status())
+        .await
+        .context("timed out")?
+        .context("Failed to start the desktop notifier")?;
+    anyhow::ensure!(status.success(), "the desktop notifier exited with {}", status);
+    Ok(())
+}
+
+fn profile_name(config: &Config) -> String {
+    config.profile.clone().unwrap_or_else(|| "default".to_string())
+}
+
+impl Notification {
+    /// JSON body of a webhook sink: the event, subject and text, then the event's own fields
+    pub fn payload(&self) -> Value {
+        let mut payload = json!({
+            "event": self.event.name(),
+            "profile": self.profile,
+            "subject": self.subject,
+            "text": self.text,
+        });
+        if let (Some(payload), Value::Object(fields)) = (payload.as_object_mut(), &self.fields) {
+            payload.extend(fields.clone());
+        }
+        payload
+    }
+
+    /// `run_finished`, or `run_failed` for a run that failed or has failed files
+    pub fn run(config: &Config, result: &Result<RunSummary>) -> Self {
+        let profile = profile_name(config);
+        let (event, subject, mut text) = match result {
+            Ok(summary) => {
+                let counts = format!("{} uploaded, {} skipped, {} failed", summary.uploaded, summary.skipped, summary.failed);
+                let month = summary.billing_month.as_deref().map(|month| format!(" for {}", month)).unwrap_or_default();
+                let event = if summary.failed == 0 { Event::RunFinished } else { Event::RunFailed };
+                let outcome = if summary.failed == 0 { "finished" } else { "finished with failures" };
+                (event, format!("Invoice run{} {} ({})", month, outcome, profile), counts)
+            }
+            Err(e) => (Event::RunFailed, format!("Invoice run failed ({})", profile), format!("{:#}", e)),
+        };
+        let empty = RunSummary::default();
+        let summary = result.as_ref().unwrap_or(&empty);
+        if let Some(folder) = summary.folder_link.as_ref().or(summary.folder.as_ref()) {
+            text.push_str(&format!("\nFolder: {}", folder));
+        }
+        for alert in &summary.budget_alerts {
+            text.push_str(&format!("\nOver budget: {}", alert));
+        }
+        Self {
+            event,
+            profile,
+            subject,
+            text,
+            vendor: None,
+            amount_cents: None,
+            currency: None,
+            fields: json!({
+                "processed": summary.processed,
+                "uploaded": summary.uploaded,
+                "skipped": summary.skipped,
+                "failed": summary.failed,
+                "month": summary.billing_month,
+                "folder": summary.folder,
+                "folder_link": summary.folder_link,
+                "error": result.as_ref().err().map(|e| format!("{:#}", e)),
+            }),
+        }
+    }
+
+    /// `large_invoice` for an archived invoice; sinks decide what is large with `min_amount`
+    pub fn large_invoice(config: &Config, document: &InvoiceDocument) -> Self {
+        let mut text = format!("{} archived {} in {}", document.vendor, document.filename, document.folder);
+        if let Some(link) = &document.web_link {
+            text.push_str(&format!("\n{}", link));
+        }
+        Self {
+            event: Event::LargeInvoice,
+            profile: profile_name(config),
+            subject: format!("Large invoice: {} {}", document.vendor, document.display_amount()),
+            text,
+            vendor: Some(document.vendor.clone()),
+            amount_cents: document.amount_cents,
+            currency: document.currency.clone(),
+            fields: json!({
+                "vendor": document.vendor,
+                "amount_cents": document.amount_cents,
+                "currency": document.currency,
+                "invoice_number": document.invoice_number,
+                "file_name": document.filename,
+                "folder": document.folder,
+                "file_id": document.file_id,
+                "web_link": document.web_link,
+                "due_date": document.due_date,
+            }),
+        }
+    }
+
+    /// `missing_vendor` for a regular vendor with nothing archived for `month`
+    pub fn missing_vendor(config: &Config, month: NaiveDate, trend: &VendorTrend) -> Self {
+        let month_label = month.format("%B %Y").to_string();
+        Self {
+            event: Event::MissingVendor,
+            profile: profile_name(config),
+            subject: format!("No invoice from {} for {}", trend.vendor, month_label),
+            text: format!(
+                "{} was billed in each of the {} month(s) before {}, but nothing has been archived for it.",
+                trend.vendor, trend.months_billed, month_label
+            ),
+            vendor: Some(trend.vendor.clone()),
+            amount_cents: None,
+            currency: None,
+            fields: json!({
+                "vendor": trend.vendor,
+                "month": month.format("%Y-%m").to_string(),
+                "months_billed": trend.months_billed,
+                "usual_cents": trend.usual_cents,
+            }),
+        }
+    }
+
+    /// `auth_expiring` for a saved sign-in that can't be refreshed or won't last until the next
+    /// run; `reauth` is the command that fixes it
+    pub fn auth_expiring(config: &Config, service: &str, problem: &str, reauth: &str) -> Self {
+        Self {
+            event: Event::AuthExpiring,
+            profile: profile_name(config),
+            subject: format!("{} sign-in is about to expire ({})", service, profile_name(config)),
+            text: format!("{}\nRun `{}` to sign in again.", problem, reauth),
+            vendor: None,
+            amount_cents: None,
+            currency: None,
+            fields: json!({ "service": service, "problem": problem, "reauth": reauth }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_events_by_sink_filters() {
+        let router = Router::parse(
+            r#"
+            [[sink]]
+            kind = "slack"
+            url = "https://hooks.slack.com/services/T/B/X"
+            events = ["run_failed", "large_invoice"]
+            min_amount = 500
+
+            [[sink]]
+            kind = "email"
+            to = "books@example.com"
+            events = ["large_invoice", "missing_vendor"]
+            min_amount = 100.50
+            currency = "eur"
+            vendors = ["aws"]
+            profiles = ["acme"]
+
+            [[sink]]
+            kind = "desktop"
+            events = ["run_finished"]
+            "#,
+        )
+        .unwrap();
+        let notification = |event, vendor: Option<&str>, amount_cents, currency: Option<&str>| Notification {
+            event,
+            profile: "acme".to_string(),
+            subject: String::new(),
+            text: String::new(),
+            vendor: vendor.map(str::to_string),
+            amount_cents,
+            currency: currency.map(str::to_string),
+            fields: Value::Null,
+        };
+        let routed = |notification: Notification| router.routes(&notification).map(|(number, _)| number).collect::<Vec<_>>();
+
+        assert_eq!(routed(notification(Event::LargeInvoice, Some("AWS EMEA"), Some(60_000), Some("EUR"))), vec![1, 2]);
+        assert_eq!(routed(notification(Event::LargeInvoice, Some("AWS EMEA"), Some(20_000), Some("EUR"))), vec![2]);
+        assert_eq!(routed(notification(Event::LargeInvoice, Some("AWS EMEA"), Some(10_049), Some("EUR"))), Vec::<usize>::new());
+        assert_eq!(routed(notification(Event::LargeInvoice, Some("Hetzner"), Some(60_000), Some("EUR"))), vec![1]);
+        assert_eq!(routed(notification(Event::LargeInvoice, Some("AWS"), Some(60_000), Some("USD"))), vec![1]);
+        assert_eq!(routed(notification(Event::MissingVendor, Some("aws.com"), None, None)), vec![2]);
+        assert_eq!(routed(Notification { profile: "default".to_string(), ..notification(Event::MissingVendor, Some("AWS"), None, None) }), Vec::<usize>::new());
+        assert_eq!(routed(notification(Event::RunFailed, None, None, None)), vec![1]);
+        assert_eq!(routed(notification(Event::RunFinished, None, None, None)), vec![3]);
+        assert!(router.subscribed(Event::MissingVendor) && !router.subscribed(Event::AuthExpiring));
+
+        // Sinks need somewhere to send to, and `large_invoice` a threshold
+        for invalid in [
+            "[[sink]]\nkind = \"slack\"\nevents = [\"run_failed\"]",
+            "[[sink]]\nkind = \"telegram\"\nbot_token = \"1:A\"\nevents = [\"run_failed\"]",
+            "[[sink]]\nkind = \"desktop\"\nevents = [\"large_invoice\"]",
+            "[[sink]]\nkind = \"desktop\"\nevents = []",
+            "[[sink]]\nkind = \"desktop\"\nevents = [\"invoice_paid\"]",
+            "[[sink]]\nkind = \"pager\"\nevents = [\"run_failed\"]",
+        ] {
+            assert!(Router::parse(invalid).is_err(), "{}", invalid);
+        }
+
+        // Errors never show a webhook's secret path or a bot token
+        assert_eq!(host_of("https://hooks.slack.com/services/T/B/X"), "hooks.slack.com");
+        assert_eq!(host_of(&format!("{}/bot123:ABC/sendMessage", TELEGRAM_API_BASE)), "api.telegram.org");
+    }
+}
@@ -0,0 +1,156 @@
+//! Classification and routing plugins.
+//!
+//! A plugin is a WebAssembly module that sees each downloaded attachment (message headers,
+//! filename, detected institution and message text) and can override where it goes. Plugins
+//! need the `plugins` cargo feature and are listed in WASM_PLUGINS.
+//!
+//! Plugin ABI: the module imports nothing and exports `memory`, `alloc(len: i32) -> i32`
+//! and `route(ptr: i32, len: i32) -> i64`. The host writes the JSON input into the buffer
+//! returned by `alloc`, calls `route`, and reads the JSON decision from the returned
+//! `(ptr << 32) | len`. A zero length means "no opinion".
+
+#[cfg(feature = "plugins")]
+mod wasm;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// What a plugin gets to see for each attachment
+#[derive(Debug, Serialize)]
+pub struct PluginInput<'a> {
+    pub from: &'a str,
+    pub subject: &'a str,
+    pub filename: &'a str,
+    pub institution: Option<&'a str>,
+    pub text: &'a str,
+}
+
+/// A plugin's routing decision; unset fields keep the built-in behaviour
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct RoutingDecision {
+    /// Folder path relative to the monthly folder (e.g. "Taxes/VAT")
+    pub folder: Option<String>,
+    /// New file name
+    pub filename: Option<String>,
+    /// Category label; used as the folder when no folder is given
+    pub category: Option<String>,
+    /// Do not upload this attachment
+    #[serde(default)]
+    pub skip: bool,
+}
+
+impl RoutingDecision {
+    /// Combine decisions from consecutive plugins: later plugins override earlier fields,
+    /// and any plugin can skip
+    #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+    fn merge(&mut self, other: RoutingDecision) {
+        if other.folder.is_some() {
+            self.folder = other.folder;
+        }
+        if other.filename.is_some() {
+            self.filename = other.filename;
+        }
+        if other.category.is_some() {
+            self.category = other.category;
+        }
+        self.skip |= other.skip;
+    }
+
+    /// Target folder relative to the monthly folder, stripped of `.`/`..` components
+    pub fn target_folder(&self) -> Option<String> {
+        let folder = self.folder.as_deref().or(self.category.as_deref())?;
+        let parts: Vec<&str> = folder
+            .split(['/', '\\'])
+            .map(str::trim)
+            .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+            .collect();
+        (!parts.is_empty()).then(|| parts.join("/"))
+    }
+
+    /// New file name, reduced to its last path component
+    pub fn target_filename(&self) -> Option<String> {
+        let name = self.filename.as_deref()?.rsplit(['/', '\\']).next()?.trim();
+        (!name.is_empty() && name != "." && name != "..").then(|| name.to_string())
+    }
+}
+
+/// The plugins configured for a run, in WASM_PLUGINS order
+pub struct Plugins {
+    #[cfg(feature = "plugins")]
+    runtime: Option<wasm::WasmRuntime>,
+}
+
+impl Plugins {
+    /// Compile the given plugin modules
+    #[cfg(feature = "plugins")]
+    pub fn load(paths: &[PathBuf]) -> Result<Self> {
+        let runtime = if paths.is_empty() {
+            None
+        } else {
+            Some(wasm::WasmRuntime::load(paths)?)
+        };
+        Ok(Self { runtime })
+    }
+
+    /// Compile the given plugin modules
+    #[cfg(not(feature = "plugins"))]
+    pub fn load(paths: &[PathBuf]) -> Result<Self> {
+        if !paths.is_empty() {
+            anyhow::bail!("WASM_PLUGINS is set but invoice-pilot was built without the `plugins` feature");
+        }
+        Ok(Self {})
+    }
+
+    pub fn is_empty(&self) -> bool {
+        #[cfg(feature = "plugins")]
+        return self.runtime.is_none();
+        #[cfg(not(feature = "plugins"))]
+        true
+    }
+
+    /// Run every plugin on an attachment and merge their decisions
+    #[cfg(feature = "plugins")]
+    pub fn route(&self, input: &PluginInput) -> Result<RoutingDecision> {
+        let mut decision = RoutingDecision::default();
+        if let Some(runtime) = &self.runtime {
+            let input = serde_json::to_vec(input)?;
+            for result in runtime.route(&input) {
+                decision.merge(result?);
+            }
+        }
+        Ok(decision)
+    }
+
+    /// Run every plugin on an attachment and merge their decisions
+    #[cfg(not(feature = "plugins"))]
+    pub fn route(&self, _input: &PluginInput) -> Result<RoutingDecision> {
+        Ok(RoutingDecision::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_and_sanitize_decisions() {
+        let mut decision: RoutingDecision = serde_json::from_str(r#"{"category": "Utilities"}"#).unwrap();
+        assert_eq!(decision.target_folder().as_deref(), Some("Utilities"));
+
+        decision.merge(RoutingDecision {
+            folder: Some("../Taxes/./VAT/".to_string()),
+            filename: Some("../../etc/q1-vat.pdf".to_string()),
+            ..Default::default()
+        });
+        decision.merge(RoutingDecision::default());
+
+        assert_eq!(decision.target_folder().as_deref(), Some("Taxes/VAT"));
+        assert_eq!(decision.target_filename().as_deref(), Some("q1-vat.pdf"));
+        assert_eq!(decision.category.as_deref(), Some("Utilities"));
+        assert!(!decision.skip);
+
+        decision.merge(RoutingDecision { skip: true, ..Default::default() });
+        assert!(decision.skip);
+    }
+}
@@ -0,0 +1,100 @@
+use super::RoutingDecision;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use wasmtime::{Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// Execution budget per call so a looping plugin can't hang the run
+const FUEL_LIMIT: u64 = 1_000_000_000;
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+pub struct WasmRuntime {
+    engine: Engine,
+    plugins: Vec<(PathBuf, Module)>,
+}
+
+impl WasmRuntime {
+    pub fn load(paths: &[PathBuf]) -> Result<Self> {
+        let mut engine_config = wasmtime::Config::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config)?;
+
+        let plugins = paths
+            .iter()
+            .map(|path| {
+                let module = Module::from_file(&engine, path)
+                    .with_context(|| format!("Failed to load plugin {}", path.display()))?;
+                Ok((path.clone(), module))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { engine, plugins })
+    }
+
+    /// Run each plugin on the JSON input; every call gets a fresh instance
+    pub fn route<'a>(&'a self, input: &'a [u8]) -> impl Iterator<Item = Result<RoutingDecision>> + 'a {
+        self.plugins.iter().map(move |(path, module)| {
+            self.call(module, input)
+                .with_context(|| format!("Plugin {} failed", display_name(path)))
+        })
+    }
+
+    fn call(&self, module: &Module, input: &[u8]) -> Result<RoutingDecision> {
+        let limits = StoreLimitsBuilder::new().memory_size(MEMORY_LIMIT).build();
+        let mut store: Store<StoreLimits> = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL_LIMIT)?;
+
+        let instance = Instance::new(&mut store, module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .context("plugin does not export `memory`")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let route = instance.get_typed_func::<(i32, i32), i64>(&mut store, "route")?;
+
+        let len = i32::try_from(input.len()).context("plugin input too large")?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as u32 as usize, input)?;
+
+        let packed = route.call(&mut store, (ptr, len))? as u64;
+        let (out_ptr, out_len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        if out_len == 0 {
+            return Ok(RoutingDecision::default());
+        }
+
+        let mut output = vec![0; out_len];
+        memory.read(&store, out_ptr, &mut output)?;
+        serde_json::from_slice(&output).context("plugin returned an invalid routing decision")
+    }
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_through_wat_plugin() {
+        let decision = r#"{"folder":"Taxes","skip":false}"#;
+        let packed = (1024u64 << 32) | decision.len() as u64;
+        let wat = format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (data (i32.const 1024) "{}")
+                (func (export "alloc") (param i32) (result i32) i32.const 0)
+                (func (export "route") (param i32 i32) (result i64) i64.const {}))"#,
+            decision.replace('"', "\\\""),
+            packed
+        );
+
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("plugin.wat");
+        std::fs::write(&path, wat).unwrap();
+        let runtime = WasmRuntime::load(std::slice::from_ref(&path)).unwrap();
+        let results: Vec<_> = runtime.route(b"{}").collect::<Result<_>>().unwrap();
+
+        assert_eq!(results, vec![RoutingDecision { folder: Some("Taxes".to_string()), ..Default::default() }]);
+    }
+}
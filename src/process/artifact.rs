@@ -0,0 +1,145 @@
+//! A JSON record of every finished run, written to `runs/<timestamp>.json` next to the profile's
+//! tokens. It holds the whole structured result (counts, mirror copies, budget alerts, the error
+//! that stopped the run), so scripts and dashboards can read run outcomes without the database.
+//! With RUN_ARTIFACTS_UPLOAD=true it is also uploaded to the archive's `_meta` folder.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use std::path::PathBuf;
+use crate::auth::oauth::get_token_dir;
+use crate::config::env::Config;
+use crate::db::RunRecord;
+use crate::process::fiscal;
+use crate::process::outcome::RunSummary;
+use crate::storage;
+
+/// Bumped when a field changes meaning or goes away; new fields may appear at any time
+const ARTIFACT_VERSION: u32 = 1;
+
+/// Records live in this directory of the token directory
+const RUNS_DIR: &str = "runs";
+
+/// Archive folder (under GOOGLE_DRIVE_FOLDER_LOCATION) records are uploaded to
+const META_FOLDER: &str = "_meta";
+
+#[derive(Debug, Serialize)]
+struct RunArtifact<'a> {
+    version: u32,
+    profile: Option<&'a str>,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    started_at: DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+    /// `success`, `partial` (some files failed) or `failure` (the run stopped)
+    status: &'static str,
+    error: Option<&'a str>,
+    scheduled: bool,
+    attempt: i32,
+    summary: &'a RunSummary,
+}
+
+impl<'a> RunArtifact<'a> {
+    fn new(run: &'a RunRecord, summary: &'a RunSummary) -> Self {
+        let status = match (&run.error, summary.failed) {
+            (Some(_), _) => "failure",
+            (None, 0) => "success",
+            (None, _) => "partial",
+        };
+        Self {
+            version: ARTIFACT_VERSION,
+            profile: Some(run.profile.as_str()).filter(|profile| !profile.is_empty()),
+            start_date: run.start_date,
+            end_date: run.end_date,
+            started_at: run.started_at,
+            finished_at: run.finished_at,
+            status,
+            error: run.error.as_deref(),
+            scheduled: run.scheduled,
+            attempt: run.attempt,
+            summary,
+        }
+    }
+}
+
+/// Write the record of a finished run (and upload it when RUN_ARTIFACTS_UPLOAD is set). Failures
+/// are logged: the run itself already happened.
+pub async fn save(config: &Config, run: &RunRecord, summary: &RunSummary) {
+    let path = match write(config, run, summary) {
+        Ok(path) => path,
+        Err(e) => {
+            log::warn!("Failed to save the run record: {:#}", e);
+            return;
+        }
+    };
+    if config.upload_run_artifacts
+        && let Err(e) = upload(config, &path).await
+    {
+        log::warn!("Failed to upload the run record: {:#}", e);
+    }
+}
+
+fn write(config: &Config, run: &RunRecord, summary: &RunSummary) -> Result<PathBuf> {
+    let dir = get_token_dir(config.profile.as_deref())?.join(RUNS_DIR);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(file_name(run.started_at, config.profile.as_deref(), config.gmail_user.as_deref()));
+    let json = serde_json::to_string_pretty(&RunArtifact::new(run, summary))?;
+    std::fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// `2025-03-01T09-00-00Z-acme-1a2b3c4d.json`: sorts by start time, is a valid name on every
+/// filesystem, and stays apart from the records of other profiles and mailboxes uploaded to the
+/// same `_meta` folder, even of runs started the same second
+fn file_name(started_at: DateTime<Utc>, profile: Option<&str>, user: Option<&str>) -> String {
+    let owner: String = profile
+        .into_iter()
+        .chain(user)
+        .flat_map(|part| std::iter::once('-').chain(part.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '-' })))
+        .collect();
+    format!("{}{}-{:08x}.json", started_at.format("%Y-%m-%dT%H-%M-%SZ"), owner, rand::random::<u32>())
+}
+
+async fn upload(config: &Config, path: &PathBuf) -> Result<()> {
+    let storage = storage::connect(config).await?;
+    let folder = storage.find_or_create_folder(&format!("{}/{}", fiscal::fixed_root(&config.drive_folder_path).trim_end_matches('/'), META_FOLDER)).await?;
+    storage.upload_files(std::slice::from_ref(path), &folder, None).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_artifact_fields() {
+        let started_at = DateTime::parse_from_rfc3339("2025-03-01T09:00:00Z").unwrap().with_timezone(&Utc);
+        let run = RunRecord {
+            profile: String::new(),
+            start_date: NaiveDate::from_ymd_opt(2025, 2, 1).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(2025, 2, 28).unwrap(),
+            started_at,
+            finished_at: started_at,
+            processed: 3,
+            uploaded: 2,
+            skipped: 0,
+            failed: 1,
+            billing_month: Some("February 2025".to_string()),
+            folder: None,
+            error: None,
+            scheduled: true,
+            attempt: 2,
+        };
+        let summary = RunSummary { processed: 3, uploaded: 2, failed: 1, budget_alerts: vec!["hetzner".to_string()], ..Default::default() };
+
+        let json = serde_json::to_value(RunArtifact::new(&run, &summary)).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["status"], "partial");
+        assert!(json["profile"].is_null());
+        assert_eq!(json["start_date"], "2025-02-01");
+        assert_eq!(json["summary"]["budget_alerts"][0], "hetzner");
+        let name = file_name(started_at, Some("acme"), Some("ana@example.com"));
+        assert!(name.starts_with("2025-03-01T09-00-00Z-acme-ana-example.com-") && name.ends_with(".json"));
+        assert_ne!(file_name(started_at, None, None), file_name(started_at, None, None));
+    }
+}
@@ -0,0 +1,173 @@
+//! Archive audit (`audit`): checks the invoice database against the Drive archive. Every recorded
+//! document should still have its file; with `--verify` the file must also have the size and
+//! checksum it was uploaded with. Files in the archive folders that no record points at are
+//! listed too.
+
+use crate::db::ArchivedFile;
+use crate::drive;
+use crate::drive::client::{DriveClient, FileInfo, UploadedFile};
+use crate::mail::MailSource;
+use crate::process::retention;
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+#[derive(Debug, Default)]
+pub struct AuditReport {
+    /// Records whose file was deleted or trashed
+    pub missing: Vec<ArchivedFile>,
+    /// Records whose file no longer matches, with what changed
+    pub changed: Vec<(ArchivedFile, String)>,
+    /// Records archived before sizes and checksums were kept, only checked for presence
+    pub unverifiable: usize,
+    /// Files in the archive folders that no record points at, by path
+    pub untracked: Vec<String>,
+}
+
+impl AuditReport {
+    pub fn drifted(&self) -> usize {
+        self.missing.len() + self.changed.len()
+    }
+}
+
+/// What changed in a record's file, None when it still matches. A file that `archive maintain`
+/// zipped together with the rest of its month only has to exist.
+fn compare(record: &ArchivedFile, file: &FileInfo, verify: bool) -> Option<String> {
+    if file.name != record.filename && file.name.ends_with(".zip") && !record.filename.ends_with(".zip") {
+        return None;
+    }
+    if file.name != record.filename {
+        return Some(format!("renamed to {}", file.name));
+    }
+    if !verify {
+        return None;
+    }
+    let size = file.size.as_deref().and_then(|size| size.parse::<i64>().ok());
+    if let (Some(recorded), Some(size)) = (record.size_bytes, size)
+        && recorded != size
+    {
+        return Some(format!("size {} → {} bytes", recorded, size));
+    }
+    match (&record.sha256, &file.sha256) {
+        (Some(recorded), Some(sha256)) if recorded != sha256 => Some("contents changed (checksum differs)".to_string()),
+        _ => None,
+    }
+}
+
+/// Check `records` against the files under the managed folder (and the cold-storage folder).
+/// Files found there are matched by id; the others are looked up one by one, since a file moved
+/// elsewhere in Drive is still archived.
+pub async fn audit(
+    client: &DriveClient,
+    roots: &[String],
+    records: Vec<ArchivedFile>,
+    verify: bool,
+) -> Result<AuditReport> {
+    let mut files: HashMap<String, (String, FileInfo)> = HashMap::new();
+    for root in roots {
+        let Some(root_id) = drive::folder::find_folder_by_path(client, root).await? else {
+            continue;
+        };
+        for (path, file) in retention::list_files(client, &root_id).await? {
+            let path = if root.is_empty() { path } else { format!("{}/{}", root, path) };
+            files.insert(file.id.clone(), (path, file));
+        }
+    }
+
+    let mut report = AuditReport::default();
+    let referenced: HashSet<String> = records.iter().map(|record| record.file_id.clone()).collect();
+    for record in records {
+        let file = match files.get(&record.file_id) {
+            Some((_, file)) => Some(file.clone()),
+            None => drive::folder::file_info(client, &record.file_id)
+                .await
+                .with_context(|| format!("Failed to look up {}/{}", record.folder, record.filename))?,
+        };
+        let Some(file) = file else {
+            report.missing.push(record);
+            continue;
+        };
+        if verify && (record.size_bytes.is_none() || record.sha256.is_none()) {
+            report.unverifiable += 1;
+        }
+        if let Some(change) = compare(&record, &file, verify) {
+            report.changed.push((record, change));
+        }
+    }
+
+    report.untracked = files
+        .into_iter()
+        .filter(|(id, _)| !referenced.contains(id))
+        .map(|(_, (path, _))| path)
+        .collect();
+    report.untracked.sort();
+    Ok(report)
+}
+
+/// Fetch a missing file again from the message it came from and upload it to its folder. Only an
+/// attachment with the recorded checksum will do: a file that was compressed, transformed or
+/// encrypted before upload can't be rebuilt from the mail alone.
+pub async fn reupload(client: &DriveClient, source: &dyn MailSource, record: &ArchivedFile, temp_dir: &Path) -> Result<UploadedFile> {
+    let message_id = record.message_id.as_deref().context("its message was not recorded")?;
+    let sha256 = record.sha256.as_deref().context("its checksum was not recorded")?;
+    let message = source.fetch_message(message_id).await.context("Failed to fetch its message")?;
+
+    for attachment in &message.attachments {
+        let data = crate::mail::download::download_with_retries(source, message_id, attachment).await?;
+        if drive::upload::sha256_hex(&data) != sha256 {
+            continue;
+        }
+
+        // Under its archived name, which may differ from the attachment's
+        let dir = temp_dir.join(format!("audit-{}", record.id));
+        std::fs::create_dir_all(&dir).context("Failed to create temp directory")?;
+        let path = dir.join(&record.filename);
+        std::fs::write(&path, &data).context("Failed to write temp file")?;
+        let uploaded = async {
+            let folder_id = drive::folder::find_or_create_folder(client, &record.folder).await?;
+            drive::upload::upload_file(client, &path, &folder_id, false, None).await
+        }
+        .await;
+        let _ = std::fs::remove_dir_all(&dir);
+        return uploaded;
+    }
+    anyhow::bail!("no attachment of its message matches the archived file (it was changed before upload); reprocess its month instead")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_audit_finds_drift() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let month = root.join("billing/2024/March");
+        std::fs::create_dir_all(&month).unwrap();
+        for name in ["kept.pdf", "edited.pdf", "manual.pdf"] {
+            std::fs::write(month.join(name), b"%PDF-1.4\n").unwrap();
+        }
+        let record = |id: i32, name: &str| ArchivedFile {
+            id,
+            filename: name.to_string(),
+            folder: "billing/2024/March".to_string(),
+            file_id: month.join(name).to_string_lossy().to_string(),
+            message_id: Some(format!("msg-{}", id)),
+            size_bytes: Some(9),
+            sha256: Some(drive::upload::sha256_hex(b"%PDF-1.4\n")),
+        };
+        let records = vec![record(1, "kept.pdf"), record(2, "edited.pdf"), record(3, "deleted.pdf"), ArchivedFile { sha256: None, ..record(4, "kept.pdf") }];
+        std::fs::write(month.join("edited.pdf"), b"%PDF-1.4\n%edited\n").unwrap();
+
+        let client = DriveClient::mock(root);
+        let report = audit(&client, &["billing".to_string()], records.clone(), true).await.unwrap();
+        assert_eq!(report.missing.iter().map(|r| r.id).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(report.changed.iter().map(|(r, change)| (r.id, change.as_str())).collect::<Vec<_>>(), vec![(2, "size 9 → 17 bytes")]);
+        assert_eq!(report.unverifiable, 1);
+        assert_eq!(report.untracked, vec!["billing/2024/March/manual.pdf"]);
+
+        // Presence only
+        let report = audit(&client, &["billing".to_string()], records, false).await.unwrap();
+        assert_eq!((report.missing.len(), report.changed.len()), (1, 0));
+    }
+}
@@ -0,0 +1,229 @@
+//! Moving the accumulated state to another machine or database (`db export` / `db import`). The
+//! invoice records, run history, bank transactions and message cache are written as JSON lines,
+//! one row per line, together with the profile's learned vendor aliases, corrections and rules
+//! file. Importing adds what the target doesn't have yet, so it can also merge two setups.
+
+use crate::auth::oauth::get_token_dir;
+use crate::config::env::Config;
+use crate::db::{self, BackupTable, DbPool};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Version of the export format, raised when a change makes old readers misread it
+const FORMAT: u32 = 1;
+
+/// JSON state kept next to the tokens that travels with an export
+const STATE_FILES: &[&str] = &["vendor_aliases.json", "feedback.json"];
+
+/// One line of an export
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Record {
+    /// Always the first line
+    Header { format: u32, exported_at: DateTime<Utc>, profile: Option<String> },
+    Row { table: String, row: serde_json::Value },
+    /// A JSON state file of the exported profile
+    State { file: String, contents: serde_json::Value },
+    /// The rules file (RULES_FILE)
+    Rules { contents: String },
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Rows added and rows already there, by table
+    pub added: BTreeMap<String, usize>,
+    pub existing: BTreeMap<String, usize>,
+    /// State and rules files written, and those kept because this machine already has them
+    pub written: Vec<PathBuf>,
+    pub kept: Vec<PathBuf>,
+    /// The export has a rules file but RULES_FILE isn't set, so it wasn't written anywhere
+    pub rules_skipped: bool,
+}
+
+fn table(name: &str) -> Option<&'static BackupTable> {
+    db::BACKUP_TABLES.iter().find(|table| table.name == name)
+}
+
+/// Write every backup table, the profile's state files and the rules file to `out`. Returns the
+/// rows written by table.
+pub async fn export(config: &Config, pool: &DbPool, out: &Path) -> Result<Vec<(&'static str, usize)>> {
+    let file = create_private(out).with_context(|| format!("Failed to create {}", out.display()))?;
+    let mut writer = std::io::BufWriter::new(file);
+    let mut write = |record: &Record| -> Result<()> {
+        serde_json::to_writer(&mut writer, record)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    };
+
+    write(&Record::Header { format: FORMAT, exported_at: Utc::now(), profile: config.profile.clone() })?;
+    let mut counts = Vec::new();
+    for table in db::BACKUP_TABLES {
+        let rows = db::export_rows(pool, table).await?;
+        counts.push((table.name, rows.len()));
+        for row in rows {
+            write(&Record::Row { table: table.name.to_string(), row: serde_json::from_str(&row)? })?;
+        }
+    }
+
+    let dir = get_token_dir(config.profile.as_deref())?;
+    for name in STATE_FILES {
+        let path = dir.join(name);
+        if let Ok(json) = std::fs::read_to_string(&path) {
+            let contents = serde_json::from_str(&json).with_context(|| format!("Failed to parse {}", path.display()))?;
+            write(&Record::State { file: name.to_string(), contents })?;
+        }
+    }
+    if let Some(path) = &config.rules_file
+        && let Ok(contents) = std::fs::read_to_string(path)
+    {
+        write(&Record::Rules { contents })?;
+    }
+
+    writer.flush().with_context(|| format!("Failed to write {}", out.display()))?;
+    Ok(counts)
+}
+
+/// Create (or truncate) `out` readable by its owner only: an export holds document text and
+/// message metadata
+fn create_private(out: &Path) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let file = options.open(out)?;
+    // The mode only applies to a new file; an existing one is narrowed too
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    Ok(file)
+}
+
+/// Read an export line by line, checking it starts with a header this version understands
+pub fn read(path: &Path) -> Result<Vec<Record>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut records = Vec::new();
+    for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(&line).with_context(|| format!("{} line {} is not an export record", path.display(), index + 1))?;
+        match (&record, records.is_empty()) {
+            (Record::Header { format, .. }, true) if *format > FORMAT => {
+                anyhow::bail!("{} was written by a newer version (format {}); update invoice-pilot first", path.display(), format)
+            }
+            (Record::Header { .. }, true) => {}
+            (_, true) => anyhow::bail!("{} is not a `db export` file (no header)", path.display()),
+            (Record::Row { table: name, .. }, false) if table(name).is_none() => {
+                anyhow::bail!("{} line {} is for an unknown table {}", path.display(), index + 1, name)
+            }
+            (Record::State { file, .. }, false) if !STATE_FILES.contains(&file.as_str()) => {
+                anyhow::bail!("{} line {} is for an unknown state file {}", path.display(), index + 1, file)
+            }
+            _ => {}
+        }
+        records.push(record);
+    }
+    if records.is_empty() {
+        anyhow::bail!("{} is empty", path.display());
+    }
+    Ok(records)
+}
+
+/// Add the records to the database and the profile's config directory. State and rules files
+/// are only written where this machine has none, the rules file only to RULES_FILE.
+pub async fn import(config: &Config, pool: &DbPool, records: &[Record]) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let mut columns: HashMap<&str, Vec<String>> = HashMap::new();
+    let dir = get_token_dir(config.profile.as_deref())?;
+
+    for record in records {
+        match record {
+            Record::Header { .. } => {}
+            Record::Row { table: name, row } => {
+                let table = table(name).context("Unknown table")?;
+                if !columns.contains_key(table.name) {
+                    columns.insert(table.name, db::importable_columns(pool, table).await?);
+                }
+                let known = &columns[table.name];
+                let object = row.as_object().with_context(|| format!("A {} row is not a JSON object", name))?;
+                let row_columns: Vec<&str> = object.keys().map(String::as_str).filter(|column| known.iter().any(|k| k == column)).collect();
+                let counts = if db::import_row(pool, table, &row_columns, &row.to_string()).await? {
+                    &mut summary.added
+                } else {
+                    &mut summary.existing
+                };
+                *counts.entry(name.clone()).or_default() += 1;
+            }
+            Record::State { file, contents } => {
+                write_if_missing(&dir.join(file), &serde_json::to_string_pretty(contents)?, &mut summary)?;
+            }
+            Record::Rules { contents } => match &config.rules_file {
+                Some(path) => write_if_missing(path, contents, &mut summary)?,
+                None => summary.rules_skipped = true,
+            },
+        }
+    }
+    Ok(summary)
+}
+
+fn write_if_missing(path: &Path, contents: &str, summary: &mut ImportSummary) -> Result<()> {
+    if path.exists() {
+        summary.kept.push(path.to_path_buf());
+        return Ok(());
+    }
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+    summary.written.push(path.to_path_buf());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_checks_export_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.jsonl");
+        let header = r#"{"kind":"header","format":1,"exported_at":"2025-03-01T10:00:00Z","profile":null}"#;
+
+        let lines = [
+            header,
+            r#"{"kind":"row","table":"runs","row":{"profile":"","uploaded":3}}"#,
+            "",
+            r#"{"kind":"state","file":"vendor_aliases.json","contents":{"aws.com":"AWS"}}"#,
+            r#"{"kind":"rules","contents":"[[rule]]\nsender = \"aws.com\"\n"}"#,
+        ];
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let records = read(&path).unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[1], Record::Row { table: "runs".to_string(), row: serde_json::json!({"profile": "", "uploaded": 3}) });
+
+        // Only known tables and state files, after a header of a format this version reads
+        for lines in [
+            vec![header, r#"{"kind":"row","table":"pg_authid","row":{}}"#],
+            vec![header, r#"{"kind":"state","file":"../../.bashrc","contents":{}}"#],
+            vec![r#"{"kind":"row","table":"runs","row":{}}"#],
+            vec![r#"{"kind":"header","format":2,"exported_at":"2025-03-01T10:00:00Z","profile":null}"#],
+        ] {
+            std::fs::write(&path, lines.join("\n")).unwrap();
+            assert!(read(&path).is_err(), "{:?}", lines);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_export_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("backup.jsonl");
+        std::fs::write(&out, "old").unwrap();
+        std::fs::set_permissions(&out, std::fs::Permissions::from_mode(0o644)).unwrap();
+        drop(create_private(&out).unwrap());
+        let metadata = std::fs::metadata(&out).unwrap();
+        assert_eq!((metadata.permissions().mode() & 0o777, metadata.len()), (0o600, 0));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::config::env::{Config, Flags};
+use crate::config::profiles::Profile;
+use crate::process::jobs;
+use crate::process::outcome::{RunSummary, STEP_MARKER};
+use chrono::NaiveDate;
+use futures_util::stream::{self, StreamExt};
+use tokio::sync::mpsc;
+
+/// Outcome of a single tenant in a batch run
+#[derive(Debug, Clone)]
+pub struct TenantReport {
+    pub profile: String,
+    pub summary: RunSummary,
+    pub error: Option<String>,
+}
+
+impl TenantReport {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Process every profile with at most `concurrency` tenants in flight.
+/// A failing tenant is recorded in its report and never stops the others.
+pub async fn run_all(
+    profiles: Vec<Profile>,
+    date_range: Option<(NaiveDate, NaiveDate)>,
+    concurrency: usize,
+    flags: Flags,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Vec<TenantReport> {
+    let mut reports: Vec<TenantReport> = stream::iter(profiles)
+        .map(|profile| run_tenant(profile, date_range, flags, tx))
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+
+    reports.sort_by(|a, b| a.profile.cmp(&b.profile));
+    reports
+}
+
+/// Process each Workspace user's mailbox (WORKSPACE_USERS, read through domain-wide
+/// delegation) into its own subfolder of the Drive folder, e.g. `billing/alice@acme.com/March`,
+/// with at most `concurrency` users in flight. Reports are named after the users.
+pub async fn run_workspace(
+    config: &Config,
+    date_range: Option<(NaiveDate, NaiveDate)>,
+    concurrency: usize,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Vec<TenantReport> {
+    let (start_date, end_date) = date_range.unwrap_or((config.start_date, config.end_date));
+    let mut reports: Vec<TenantReport> = stream::iter(&config.workspace_users)
+        .map(|user| {
+            let mut user_config = config.clone();
+            user_config.gmail_user = Some(user.clone());
+            user_config.drive_folder_path = format!("{}/{}", config.drive_folder_path.trim_end_matches('/'), user);
+            run_prefixed(user.clone(), user_config, start_date, end_date, tx)
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+
+    reports.sort_by(|a, b| a.profile.cmp(&b.profile));
+    reports
+}
+
+/// Run the pipeline for one tenant, prefixing its progress messages with the profile name
+async fn run_tenant(
+    profile: Profile,
+    date_range: Option<(NaiveDate, NaiveDate)>,
+    flags: Flags,
+    tx: &mpsc::UnboundedSender<String>,
+) -> TenantReport {
+    let mut report = TenantReport {
+        profile: profile.name.clone(),
+        summary: RunSummary::default(),
+        error: None,
+    };
+
+    let config = match Config::load_profile(&profile.name, &profile.path, flags) {
+        Ok(config) => config,
+        Err(e) => {
+            let _ = tx.send(format!("[{}] ✗ Invalid configuration: {:#}", profile.name, e));
+            report.error = Some(format!("{:#}", e));
+            return report;
+        }
+    };
+
+    let (start_date, end_date) = date_range.unwrap_or((config.start_date, config.end_date));
+    run_prefixed(profile.name, config, start_date, end_date, tx).await
+}
+
+/// Run the pipeline with one configuration, prefixing its progress messages with `name`
+async fn run_prefixed(
+    name: String,
+    config: Config,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    tx: &mpsc::UnboundedSender<String>,
+) -> TenantReport {
+    let mut report = TenantReport {
+        profile: name.clone(),
+        summary: RunSummary::default(),
+        error: None,
+    };
+    let _ = tx.send(format!("[{}] 📅 Date range: {} to {}", name, start_date, end_date));
+
+    let (tenant_tx, mut tenant_rx) = mpsc::unbounded_channel();
+    let run = async move {
+        jobs::run_manual_processing(config, start_date, end_date, &tenant_tx).await
+        // tenant_tx is dropped here, which ends the forwarding loop below
+    };
+    let forward = async {
+        while let Some(message) = tenant_rx.recv().await {
+            if !message.starts_with("__RESULTS__:") && !message.starts_with(STEP_MARKER) {
+                let _ = tx.send(format!("[{}] {}", name, message));
+            }
+        }
+    };
+
+    let (result, ()) = tokio::join!(run, forward);
+    match result {
+        Ok(summary) => report.summary = summary,
+        Err(e) => {
+            let _ = tx.send(format!("[{}] ✗ Processing failed: {:#}", name, e));
+            report.error = Some(format!("{:#}", e));
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn test_run_all_isolates_failing_tenant() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let profiles_dir = root.join("profiles");
+        let drive_dir = root.join("drive");
+        std::fs::create_dir_all(&profiles_dir).unwrap();
+
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures");
+        for name in ["acme", "globex"] {
+            std::fs::write(
+                profiles_dir.join(format!("{}.env", name)),
+                format!(
+                    "GOOGLE_DRIVE_FOLDER_LOCATION=clients/{}\nMOCK_FIXTURES_DIR={}\nMOCK_DRIVE_DIR={}\n",
+                    name,
+                    fixtures.display(),
+                    drive_dir.display()
+                ),
+            )
+            .unwrap();
+        }
+        std::fs::write(profiles_dir.join("broken.env"), "FETCH_INVOICES_DAY=99\n").unwrap();
+
+        let profiles = crate::config::profiles::discover(&profiles_dir).unwrap();
+        let range = (
+            NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2025, 3, 31).unwrap(),
+        );
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let reports = run_all(profiles, Some(range), 2, Flags { mock: true, ..Flags::default() }, &tx).await;
+
+        let names: Vec<&str> = reports.iter().map(|r| r.profile.as_str()).collect();
+        assert_eq!(names, vec!["acme", "broken", "globex"]);
+        assert!(!reports[1].succeeded());
+        for report in [&reports[0], &reports[2]] {
+            assert!(report.succeeded(), "{:?}", report.error);
+            // The two invoices; the Revolut statement matches no default keyword
+            assert_eq!(report.summary.processed, 2);
+        }
+        assert!(drive_dir.join("clients/acme/March").is_dir());
+        assert!(drive_dir.join("clients/globex/March").is_dir());
+    }
+
+    #[tokio::test]
+    async fn test_run_workspace_uses_per_user_folders() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let drive_dir = root.join("drive");
+
+        let env_file = root.join("workspace.env");
+        std::fs::write(
+            &env_file,
+            format!(
+                "GOOGLE_DRIVE_FOLDER_LOCATION=company/billing/\nWORKSPACE_USERS=\"Bob@acme.com, alice@acme.com\"\nMOCK_FIXTURES_DIR={}\nMOCK_DRIVE_DIR={}\n",
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures").display(),
+                drive_dir.display()
+            ),
+        )
+        .unwrap();
+        let config = Config::load_profile("workspace", &env_file, Flags { mock: true, ..Flags::default() }).unwrap();
+        let range = (
+            NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2025, 3, 31).unwrap(),
+        );
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let reports = run_workspace(&config, Some(range), 2, &tx).await;
+
+        let names: Vec<&str> = reports.iter().map(|r| r.profile.as_str()).collect();
+        assert_eq!(names, vec!["alice@acme.com", "bob@acme.com"]);
+        for report in &reports {
+            assert!(report.succeeded(), "{:?}", report.error);
+            // The two invoices; the Revolut statement matches no default keyword
+            assert_eq!(report.summary.processed, 2);
+        }
+        assert!(drive_dir.join("company/billing/alice@acme.com/March").is_dir());
+        assert!(drive_dir.join("company/billing/bob@acme.com/March").is_dir());
+    }
+}
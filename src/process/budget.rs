@@ -0,0 +1,189 @@
+//! Monthly budgets per category or vendor (BUDGETS). After a run, each billing month it touched
+//! is summed from the archive and budgets that are over their limit raise an alert.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use crate::db::{self, DbPool, InvoiceDocument, InvoiceQuery};
+use crate::extract::invoice::format_amount;
+
+/// Documents of one month loaded from the archive to sum a budget
+const MAX_MONTH_DOCUMENTS: i64 = 10_000;
+
+/// Monthly spending limit for a category (institution folder) or a vendor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Budget {
+    /// Lowercase category name, or part of the vendor's filename prefix
+    pub name: String,
+    pub limit_cents: i64,
+    /// Only amounts in this currency count; None compares every currency to the limit separately
+    pub currency: Option<String>,
+}
+
+impl Budget {
+    fn applies_to(&self, document: &InvoiceDocument, drive_folder_path: &str) -> bool {
+        document.category(drive_folder_path).to_lowercase() == self.name || document.vendor.contains(self.name.as_str())
+    }
+}
+
+/// A budget its month's documents went over
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetAlert {
+    pub budget: String,
+    pub month: NaiveDate,
+    pub currency: String,
+    pub spent_cents: i64,
+    pub limit_cents: i64,
+}
+
+impl std::fmt::Display for BudgetAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let currency = if self.currency.is_empty() { String::new() } else { format!(" {}", self.currency) };
+        write!(
+            f,
+            "{} spent {}{} in {} (budget {}{})",
+            self.budget,
+            format_amount(self.spent_cents),
+            currency,
+            self.month.format("%B %Y"),
+            format_amount(self.limit_cents),
+            currency
+        )
+    }
+}
+
+/// Parse `hetzner=100, Revolut=1500 EUR, hosting=250.50` into budgets
+pub fn parse_budgets(value: &str) -> Result<Vec<Budget>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (name, limit) = entry
+                .split_once('=')
+                .with_context(|| format!("BUDGETS entry '{}' must look like vendor=100 or Category=100 EUR", entry))?;
+            let mut limit = limit.split_whitespace();
+            let amount: f64 = limit
+                .next()
+                .and_then(|amount| amount.parse().ok())
+                .filter(|amount: &f64| *amount >= 0.0)
+                .with_context(|| format!("BUDGETS entry '{}' needs a positive amount", entry))?;
+            Ok(Budget {
+                name: name.trim().to_lowercase(),
+                limit_cents: (amount * 100.0).round() as i64,
+                currency: limit.next().map(str::to_uppercase),
+            })
+        })
+        .collect()
+}
+
+/// Check the budgets of every month this run archived documents for. Months are summed from the
+/// archive when it is available (this run's documents are already in it), otherwise from this
+/// run's documents alone. Only budgets this run added spend to are reported, so a month that is
+/// already over budget doesn't alert on every run.
+pub async fn check_run(
+    budgets: &[Budget],
+    drive_folder_path: &str,
+    pool: Option<&DbPool>,
+    run_documents: &[InvoiceDocument],
+) -> Result<Vec<BudgetAlert>> {
+    if budgets.is_empty() || run_documents.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let Some(pool) = pool else {
+        return Ok(over_budget(budgets, drive_folder_path, run_documents, run_documents));
+    };
+
+    let months: BTreeSet<NaiveDate> = run_documents.iter().filter_map(|document| document.billing_month).collect();
+    let profile = run_documents[0].profile.as_str();
+    let mut documents = Vec::new();
+    for month in months {
+        let query = InvoiceQuery { billing_month: Some(month), limit: MAX_MONTH_DOCUMENTS, ..Default::default() };
+        documents.extend(db::search_invoice_documents(pool, profile, &query).await?);
+    }
+    Ok(over_budget(budgets, drive_folder_path, &documents, run_documents))
+}
+
+fn over_budget(
+    budgets: &[Budget],
+    drive_folder_path: &str,
+    documents: &[InvoiceDocument],
+    run_documents: &[InvoiceDocument],
+) -> Vec<BudgetAlert> {
+    let new_files: HashSet<&str> = run_documents.iter().map(|document| document.file_id.as_str()).collect();
+
+    // (budget, month, currency) -> (spent, whether this run added to it)
+    let mut spent: BTreeMap<(usize, NaiveDate, String), (i64, bool)> = BTreeMap::new();
+    for document in documents {
+        let (Some(cents), Some(month)) = (document.amount_cents, document.billing_month) else {
+            continue;
+        };
+        let currency = document.currency.clone().unwrap_or_default().to_uppercase();
+        for (index, budget) in budgets.iter().enumerate() {
+            if budget.currency.as_ref().is_some_and(|wanted| *wanted != currency) || !budget.applies_to(document, drive_folder_path) {
+                continue;
+            }
+            let entry = spent.entry((index, month, currency.clone())).or_default();
+            entry.0 += cents;
+            entry.1 |= new_files.contains(document.file_id.as_str());
+        }
+    }
+
+    spent
+        .into_iter()
+        .filter(|((index, _, _), (cents, added))| *added && *cents > budgets[*index].limit_cents)
+        .map(|((index, month, currency), (spent_cents, _))| BudgetAlert {
+            budget: budgets[index].name.clone(),
+            month,
+            currency,
+            spent_cents,
+            limit_cents: budgets[index].limit_cents,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(file_id: &str, vendor: &str, folder: &str, cents: i64, currency: &str) -> InvoiceDocument {
+        InvoiceDocument {
+            vendor: vendor.to_string(),
+            folder: folder.to_string(),
+            file_id: file_id.to_string(),
+            amount_cents: Some(cents),
+            currency: Some(currency.to_string()),
+            billing_month: NaiveDate::from_ymd_opt(2025, 3, 1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_over_budget() {
+        let budgets = parse_budgets("hetzner=100, Revolut=50 EUR").unwrap();
+        assert_eq!(budgets[1], Budget { name: "revolut".to_string(), limit_cents: 5000, currency: Some("EUR".to_string()) });
+        assert!(parse_budgets("hetzner").is_err());
+
+        let earlier = document("f1", "hetzner-online-gmbh", "billing/March", 8000, "EUR");
+        let new = document("f2", "hetzner-online-gmbh", "billing/March", 4000, "EUR");
+        let revolut = document("f3", "revolut", "billing/March/Revolut", 6000, "USD");
+        let alerts = over_budget(&budgets, "billing", &[earlier.clone(), new.clone(), revolut.clone()], &[new.clone(), revolut]);
+
+        // Revolut's budget is in EUR, so the USD statement doesn't count toward it
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].to_string(), "hetzner spent 120.00 EUR in March 2025 (budget 100.00 EUR)");
+
+        // Nothing new for hetzner in this run: no repeated alert
+        assert!(over_budget(&budgets, "billing", &[earlier, new], &[]).is_empty());
+
+        // A credit note in the Revolut folder nets the month's spend back under its budget
+        let statement = document("f4", "revolut", "billing/March/Revolut", 6000, "EUR");
+        let refund = document("f5", "revolut", "billing/March/Revolut/Credit Notes", -2000, "EUR");
+        assert_eq!(refund.category("billing"), "Revolut");
+        assert_eq!(document("f6", "revolut", "billing/FY2024-25/P12/March/Revolut", 100, "EUR").category("billing/FY{fiscal_year}/{fiscal_period}"), "Revolut");
+        assert_eq!(document("f7", "aws", "billing/FY2024-25/P12/March", 100, "EUR").category("billing/FY{fiscal_year}/{fiscal_period}"), "General");
+        assert!(over_budget(&budgets, "billing", &[statement.clone(), refund.clone()], &[statement, refund]).is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+use crate::config::env::Config;
+use anyhow::{Context, Result};
+use std::path::Path;
+use tokio::process::Command;
+
+/// Ghostscript -dPDFSETTINGS presets accepted by PDF_COMPRESSION
+pub const PDF_QUALITIES: [&str; 4] = ["screen", "ebook", "printer", "prepress"];
+
+/// Size of a PDF before and after compression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Compressed {
+    pub before: u64,
+    pub after: u64,
+}
+
+impl Compressed {
+    pub fn saved(&self) -> u64 {
+        self.before.saturating_sub(self.after)
+    }
+}
+
+/// Compress a PDF in place with Ghostscript when PDF_COMPRESSION is set.
+/// Returns None when the file was left untouched: compression off, not a PDF, the sender
+/// opted out, or the result was not smaller than the original.
+pub async fn compress_pdf(config: &Config, path: &Path, sender: &str) -> Result<Option<Compressed>> {
+    let Some(quality) = &config.pdf_compression else {
+        return Ok(None);
+    };
+    let is_pdf = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"));
+    if !is_pdf || is_opted_out(&config.pdf_compression_skip_senders, sender) {
+        return Ok(None);
+    }
+
+    let before = std::fs::metadata(path)?.len();
+    let output = path.with_extension("compressed.pdf");
+
+    let status = Command::new(&config.ghostscript_path)
+        .arg("-sDEVICE=pdfwrite")
+        .arg("-dCompatibilityLevel=1.4")
+        .arg(format!("-dPDFSETTINGS=/{}", quality))
+        .args(["-dNOPAUSE", "-dQUIET", "-dBATCH"])
+        .arg(format!("-sOutputFile={}", output.display()))
+        .arg(path)
+        .kill_on_drop(true)
+        .status()
+        .await
+        .with_context(|| format!("Failed to run {}", config.ghostscript_path))?;
+
+    if !status.success() {
+        let _ = std::fs::remove_file(&output);
+        anyhow::bail!("{} exited with {}", config.ghostscript_path, status);
+    }
+
+    let after = std::fs::metadata(&output)?.len();
+    if after == 0 || after >= before {
+        std::fs::remove_file(&output)?;
+        return Ok(None);
+    }
+
+    std::fs::rename(&output, path).context("Failed to replace PDF with compressed copy")?;
+    Ok(Some(Compressed { before, after }))
+}
+
+/// Senders listed in PDF_COMPRESSION_SKIP_SENDERS keep their documents pristine
+fn is_opted_out(skip_senders: &[String], sender: &str) -> bool {
+    let sender = sender.to_lowercase();
+    skip_senders.iter().any(|skip| sender.contains(skip.as_str()))
+}
+
+/// Human readable byte count for savings reports
+pub fn format_bytes(bytes: u64) -> String {
+    const MB: f64 = 1024.0 * 1024.0;
+    if bytes as f64 >= MB {
+        format!("{:.1} MB", bytes as f64 / MB)
+    } else {
+        format!("{} KB", bytes.div_ceil(1024))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sender_opt_out() {
+        let skip = vec!["notary".to_string(), "@tax.gov".to_string()];
+        assert!(is_opted_out(&skip, "Notary Office <office@notary.example>"));
+        assert!(is_opted_out(&skip, "Revenue <no-reply@TAX.gov>"));
+        assert!(!is_opted_out(&skip, "Hetzner <billing@hetzner.com>"));
+        assert_eq!(format_bytes(1536), "2 KB");
+        assert_eq!(format_bytes(20 * 1024 * 1024), "20.0 MB");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_compress_replaces_only_when_smaller() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+
+        // Fake ghostscript: writes a tiny file to -sOutputFile
+        let gs = dir.join("gs");
+        std::fs::write(&gs, "#!/bin/sh\nfor a in \"$@\"; do case $a in -sOutputFile=*) printf small > \"${a#-sOutputFile=}\";; esac; done\n").unwrap();
+        std::fs::set_permissions(&gs, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut config = Config::for_test(&[]);
+        config.pdf_compression = Some("ebook".to_string());
+        config.pdf_compression_skip_senders = vec!["notary".to_string()];
+        config.ghostscript_path = gs.to_string_lossy().to_string();
+
+        let pdf = dir.join("scan.pdf");
+        std::fs::write(&pdf, vec![b'x'; 4096]).unwrap();
+        let result = compress_pdf(&config, &pdf, "Notary <a@notary.example>").await.unwrap();
+        assert_eq!(result, None);
+
+        let result = compress_pdf(&config, &pdf, "Bank <a@bank.example>").await.unwrap();
+        assert_eq!(result, Some(Compressed { before: 4096, after: 5 }));
+        assert_eq!(std::fs::read(&pdf).unwrap(), b"small");
+
+        // Already small: the "compressed" copy is not smaller, so the original stays
+        let result = compress_pdf(&config, &pdf, "Bank <a@bank.example>").await.unwrap();
+        assert_eq!(result, None);
+        assert!(!dir.join("scan.compressed.pdf").exists());
+    }
+}
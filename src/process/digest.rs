@@ -0,0 +1,263 @@
+//! Archive digest sent by `digest` every week or month (DIGEST_CADENCE): what was archived since
+//! the last digest, spend per category (institution folder) and regular vendors whose invoice for
+//! last month never arrived. Delivered by email (DIGEST_EMAIL) and/or the ON_DIGEST hook.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use crate::auth::oauth::get_token_dir;
+use crate::config::env::{Config, DigestCadence};
+use crate::config::shared;
+use crate::db::{self, DbPool};
+use crate::extract::invoice::format_amount;
+use crate::process::payables::Payables;
+
+/// When the last digest went out, stored next to the profile's tokens
+const DIGEST_FILE: &str = "digest.json";
+
+/// Months in a row a vendor must have been archived to be expected the next month
+const EXPECTED_MONTHS: u32 = 3;
+
+/// Schedule state of the digest
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DigestState {
+    last_sent: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl DigestState {
+    /// Load the profile's digest state. Mock runs keep it in memory only.
+    pub fn load(config: &Config) -> Result<Self> {
+        if config.mock_mode {
+            return Ok(Self::default());
+        }
+
+        let path = get_token_dir(config.profile.as_deref())?.join(DIGEST_FILE);
+        let mut state = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        state.path = Some(path);
+        Ok(state)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        shared::update_json(path, |on_disk: &mut Self| on_disk.last_sent = on_disk.last_sent.max(self.last_sent))
+    }
+
+    /// Weekly digests go out 7 days apart (an hour early is fine, so a daily timer doesn't slip
+    /// a day), monthly ones once per calendar month
+    pub fn is_due(&self, cadence: DigestCadence, now: DateTime<Utc>) -> bool {
+        let Some(last_sent) = self.last_sent else {
+            return true;
+        };
+        match cadence {
+            DigestCadence::Weekly => now - last_sent >= chrono::Duration::days(7) - chrono::Duration::hours(1),
+            DigestCadence::Monthly => {
+                let (last, now) = (last_sent.with_timezone(&Local), now.with_timezone(&Local));
+                (last.year(), last.month()) != (now.year(), now.month())
+            }
+        }
+    }
+
+    /// Start of the digest period: the last digest, or one period back for the first one
+    pub fn since(&self, cadence: DigestCadence, now: DateTime<Utc>) -> DateTime<Utc> {
+        self.last_sent.unwrap_or_else(|| match cadence {
+            DigestCadence::Weekly => now - chrono::Duration::days(7),
+            DigestCadence::Monthly => now.checked_sub_months(Months::new(1)).unwrap_or(now),
+        })
+    }
+
+    pub fn mark_sent(&mut self, at: DateTime<Utc>) {
+        self.last_sent = Some(at);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VendorCount {
+    pub vendor: String,
+    pub documents: usize,
+}
+
+/// Documents and extracted totals of one category (the institution folder, "General" otherwise)
+#[derive(Debug, Clone, Serialize)]
+pub struct CategorySpend {
+    pub category: String,
+    pub documents: usize,
+    /// Currency -> summed amount of the documents an amount was found in
+    pub totals_cents: BTreeMap<String, i64>,
+    pub without_amount: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Digest {
+    pub profile: Option<String>,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub documents: usize,
+    pub vendors: Vec<VendorCount>,
+    pub categories: Vec<CategorySpend>,
+    /// Billing month checked for missing invoices (the month before `until`)
+    pub checked_month: NaiveDate,
+    /// Vendors archived each of the months before `checked_month` but not in it
+    pub missing_vendors: Vec<String>,
+    /// Unpaid invoices past due or due within DUE_REMINDER_DAYS
+    pub payables: Payables,
+}
+
+impl Digest {
+    /// Gather the digest for documents uploaded from `since` to now
+    pub async fn build(config: &Config, pool: &DbPool, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Self> {
+        let profile = config.profile.as_deref().unwrap_or_default();
+        let documents = db::invoice_documents_since(pool, profile, since).await?;
+
+        let mut vendors: BTreeMap<String, usize> = BTreeMap::new();
+        let mut categories: BTreeMap<String, CategorySpend> = BTreeMap::new();
+        for document in &documents {
+            *vendors.entry(document.vendor.clone()).or_default() += 1;
+
+            let category = document.category(&config.drive_folder_path);
+            let spend = categories.entry(category.clone()).or_insert_with(|| CategorySpend {
+                category,
+                documents: 0,
+                totals_cents: BTreeMap::new(),
+                without_amount: 0,
+            });
+            spend.documents += 1;
+            match document.amount_cents {
+                Some(cents) => *spend.totals_cents.entry(document.currency.clone().unwrap_or_default()).or_default() += cents,
+                None => spend.without_amount += 1,
+            }
+        }
+
+        let this_month = until.with_timezone(&Local).date_naive().with_day(1).context("Invalid date")?;
+        let checked_month = this_month - Months::new(1);
+        let history = db::vendor_months(pool, profile, checked_month - Months::new(EXPECTED_MONTHS), checked_month).await?;
+
+        let today = until.with_timezone(&Local).date_naive();
+        let payables = Payables::load(pool, profile, today, config.due_reminder_days).await?;
+
+        let mut vendors: Vec<VendorCount> = vendors.into_iter().map(|(vendor, documents)| VendorCount { vendor, documents }).collect();
+        vendors.sort_by(|a, b| b.documents.cmp(&a.documents).then_with(|| a.vendor.cmp(&b.vendor)));
+        Ok(Self {
+            profile: config.profile.clone(),
+            since,
+            until,
+            documents: documents.len(),
+            vendors,
+            categories: categories.into_values().collect(),
+            checked_month,
+            missing_vendors: missing_vendors(&history, checked_month),
+            payables,
+        })
+    }
+
+    pub fn subject(&self) -> String {
+        format!("Invoice digest {} to {}", self.since.with_timezone(&Local).format("%Y-%m-%d"), self.until.with_timezone(&Local).format("%Y-%m-%d"))
+    }
+
+    /// Plain text body for email and hooks
+    pub fn render(&self) -> String {
+        let mut text = self.subject();
+        if let Some(profile) = &self.profile {
+            text.push_str(&format!(" ({})", profile));
+        }
+        text.push_str("\n\n");
+
+        if self.documents == 0 {
+            text.push_str("Nothing was archived in this period.\n");
+        } else {
+            text.push_str(&format!("Archived {} document(s) from {} vendor(s):\n", self.documents, self.vendors.len()));
+            for vendor in &self.vendors {
+                text.push_str(&format!("  {:<32} {:>3}\n", vendor.vendor, vendor.documents));
+            }
+
+            text.push_str("\nSpend by category:\n");
+            for spend in &self.categories {
+                let mut totals: Vec<String> = spend
+                    .totals_cents
+                    .iter()
+                    .map(|(currency, cents)| format!("{} {}", format_amount(*cents), currency).trim_end().to_string())
+                    .collect();
+                if spend.without_amount > 0 {
+                    totals.push(format!("{} without an amount", spend.without_amount));
+                }
+                text.push_str(&format!("  {:<20} {}\n", spend.category, totals.join(", ")));
+            }
+        }
+
+        let month = self.checked_month.format("%B %Y");
+        if self.missing_vendors.is_empty() {
+            text.push_str(&format!("\nNo regular vendor is missing an invoice for {}.\n", month));
+        } else {
+            text.push_str(&format!("\nRegular vendors with no invoice for {} yet:\n", month));
+            for vendor in &self.missing_vendors {
+                text.push_str(&format!("  - {}\n", vendor));
+            }
+        }
+
+        if !self.payables.is_empty() {
+            text.push_str(&format!("\n{}\n\n{}", self.payables.subject(), self.payables.render()));
+        }
+        text
+    }
+}
+
+/// Vendors archived in each of the `EXPECTED_MONTHS` months before `month` but not in `month`
+fn missing_vendors(history: &[(String, NaiveDate)], month: NaiveDate) -> Vec<String> {
+    let seen: BTreeSet<(&str, NaiveDate)> = history.iter().map(|(vendor, month)| (vendor.as_str(), *month)).collect();
+    let vendors: BTreeSet<&str> = history.iter().map(|(vendor, _)| vendor.as_str()).collect();
+    vendors
+        .into_iter()
+        .filter(|vendor| !seen.contains(&(*vendor, month)))
+        .filter(|vendor| (1..=EXPECTED_MONTHS).all(|back| seen.contains(&(*vendor, month - Months::new(back)))))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_vendors() {
+        let month = |m| NaiveDate::from_ymd_opt(2025, m, 1).unwrap();
+        let history: Vec<(String, NaiveDate)> = [
+            ("hetzner", 1), ("hetzner", 2), ("hetzner", 3),
+            ("aws", 1), ("aws", 2), ("aws", 3), ("aws", 4),
+            ("github", 2), ("github", 3),
+        ]
+        .iter()
+        .map(|(vendor, m)| (vendor.to_string(), month(*m)))
+        .collect();
+
+        // Only hetzner was there every month before April and is missing in April
+        assert_eq!(missing_vendors(&history, month(4)), vec!["hetzner".to_string()]);
+    }
+
+    #[test]
+    fn test_digest_due() {
+        let now = Utc::now();
+        let mut state = DigestState::default();
+        assert!(state.is_due(DigestCadence::Weekly, now));
+
+        state.mark_sent(now - chrono::Duration::days(3));
+        assert!(!state.is_due(DigestCadence::Weekly, now));
+        assert_eq!(state.since(DigestCadence::Weekly, now), now - chrono::Duration::days(3));
+
+        state.mark_sent(now - chrono::Duration::days(7) + chrono::Duration::minutes(5));
+        assert!(state.is_due(DigestCadence::Weekly, now));
+
+        state.mark_sent(now);
+        assert!(!state.is_due(DigestCadence::Monthly, now));
+        state.mark_sent(now - chrono::Duration::days(40));
+        assert!(state.is_due(DigestCadence::Monthly, now));
+    }
+}
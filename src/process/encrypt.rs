@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Extension appended to encrypted files (`statement.pdf` -> `statement.pdf.age`)
+pub const ENCRYPTED_EXTENSION: &str = "age";
+
+/// Parse the age public keys (`age1...`) from ENCRYPTION_RECIPIENTS
+pub fn parse_recipients(keys: &[String]) -> Result<Vec<age::x25519::Recipient>> {
+    keys.iter()
+        .map(|key| {
+            key.parse::<age::x25519::Recipient>()
+                .map_err(|e| anyhow::anyhow!("Invalid age recipient {}: {}", key, e))
+        })
+        .collect()
+}
+
+/// Encrypt a file to `<file>.age` next to it and return the new path
+pub fn encrypt_file(path: &Path, recipients: &[age::x25519::Recipient]) -> Result<PathBuf> {
+    let data = std::fs::read(path).context("Failed to read file to encrypt")?;
+    let encryptor = age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))?;
+
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(&data)?;
+    writer.finish()?;
+
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", ENCRYPTED_EXTENSION));
+    let output = PathBuf::from(name);
+    std::fs::write(&output, encrypted).context("Failed to write encrypted file")?;
+    Ok(output)
+}
+
+/// Load the private keys from an age identity file (as written by `age-keygen`)
+pub fn load_identities(path: &Path) -> Result<Vec<Box<dyn age::Identity>>> {
+    age::IdentityFile::from_file(path.to_string_lossy().to_string())
+        .with_context(|| format!("Failed to read identity file {}", path.display()))?
+        .into_identities()
+        .map_err(|e| anyhow::anyhow!("Invalid identity file {}: {}", path.display(), e))
+}
+
+/// Decrypt a `.age` file, writing the original next to it or into `output_dir`
+pub fn decrypt_file(path: &Path, identities: &[Box<dyn age::Identity>], output_dir: Option<&Path>) -> Result<PathBuf> {
+    let is_encrypted = path.extension().is_some_and(|ext| ext == ENCRYPTED_EXTENSION);
+    let (Some(stem), true) = (path.file_stem(), is_encrypted) else {
+        anyhow::bail!("{} is not a .{} file", path.display(), ENCRYPTED_EXTENSION);
+    };
+    let output = output_dir.unwrap_or_else(|| path.parent().unwrap_or(Path::new("."))).join(stem);
+    if output.exists() {
+        anyhow::bail!("{} already exists", output.display());
+    }
+
+    let encrypted = std::fs::read(path)?;
+    let decryptor = age::Decryptor::new(&encrypted[..])
+        .map_err(|e| anyhow::anyhow!("{} is not a valid age file: {}", path.display(), e))?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|i| i.as_ref() as &dyn age::Identity))
+        .map_err(|e| anyhow::anyhow!("Could not decrypt {}: {}", path.display(), e))?;
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    std::fs::write(&output, data).context("Failed to write decrypted file")?;
+    Ok(output)
+}
+
+/// Expand the given paths into files to decrypt; directories are walked recursively for `.age` files
+pub fn collect_encrypted_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            walk_encrypted_files(path, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+fn walk_encrypted_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            walk_encrypted_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == ENCRYPTED_EXTENSION) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let restored = dir.join("restored");
+        std::fs::create_dir_all(&restored).unwrap();
+
+        let identity = age::x25519::Identity::generate();
+        let recipients = parse_recipients(&[identity.to_public().to_string()]).unwrap();
+        assert!(parse_recipients(&["not-a-key".to_string()]).is_err());
+
+        let original = dir.join("statement.pdf");
+        std::fs::write(&original, b"%PDF-1.4 secret").unwrap();
+        let encrypted = encrypt_file(&original, &recipients).unwrap();
+        assert_eq!(encrypted, dir.join("statement.pdf.age"));
+        assert_ne!(std::fs::read(&encrypted).unwrap(), b"%PDF-1.4 secret");
+
+        let identity_file = dir.join("key.txt");
+        std::fs::write(&identity_file, identity.to_string().expose_secret()).unwrap();
+        let identities = load_identities(&identity_file).unwrap();
+
+        assert_eq!(collect_encrypted_files(&[dir.to_path_buf()]).unwrap(), vec![encrypted.clone()]);
+        let decrypted = decrypt_file(&encrypted, &identities, Some(&restored)).unwrap();
+        assert_eq!(std::fs::read(decrypted).unwrap(), b"%PDF-1.4 secret");
+        assert!(decrypt_file(&original, &identities, Some(&restored)).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+//! How much a run is about to upload and how long that should take. Before the attachments are
+//! downloaded, their total size from the message metadata is shown with an ETA from the upload
+//! speed set in UPLOAD_SPEED_MBPS, or else the speed measured on the last upload large enough to
+//! tell. Manual runs ask before uploading more than UPLOAD_CONFIRM_MB unless `--yes` is given.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use crate::auth::oauth::get_token_dir;
+use crate::config::env::Config;
+use crate::config::shared;
+use crate::process::compress::format_bytes;
+use tokio::sync::mpsc;
+
+/// Last measured upload speed, stored next to the profile's tokens
+const THROUGHPUT_FILE: &str = "throughput.json";
+
+/// Smaller uploads are mostly request latency and say little about the connection
+const MIN_MEASURED_BYTES: u64 = 1024 * 1024;
+
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Throughput {
+    bytes_per_second: f64,
+    measured_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadEstimate {
+    pub files: usize,
+    pub bytes: u64,
+    pub bytes_per_second: Option<f64>,
+    /// Whether the speed was measured on an earlier run rather than set in UPLOAD_SPEED_MBPS
+    pub measured: bool,
+}
+
+impl UploadEstimate {
+    /// Estimate for uploading `files` totalling `bytes` with the configured or last measured speed
+    pub fn new(config: &Config, files: usize, bytes: u64) -> Self {
+        let (bytes_per_second, measured) = match config.upload_speed_mbps {
+            Some(mbps) => (Some(mbps * 1_000_000.0 / 8.0), false),
+            None => (load_throughput(config).map(|throughput| throughput.bytes_per_second), true),
+        };
+        Self { files, bytes, bytes_per_second: bytes_per_second.filter(|speed| *speed > 0.0), measured }
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        self.bytes_per_second.map(|speed| Duration::from_secs_f64(self.bytes as f64 / speed))
+    }
+
+    /// "📦 Uploading 12 file(s), 38.4 MB (~1m 20s at 4.0 Mbit/s, measured on an earlier run)"
+    pub fn summary(&self) -> String {
+        let size = format!("📦 Uploading {} file(s), {}", self.files, format_bytes(self.bytes));
+        match (self.eta(), self.bytes_per_second) {
+            (Some(eta), Some(speed)) => format!(
+                "{} (~{} at {:.1} Mbit/s{})",
+                size,
+                format_eta(eta),
+                speed * 8.0 / 1_000_000.0,
+                if self.measured { ", measured on an earlier run" } else { "" }
+            ),
+            _ => size,
+        }
+    }
+
+    /// Whether the upload is larger than UPLOAD_CONFIRM_MB (0 never asks)
+    pub fn needs_confirmation(&self, config: &Config) -> bool {
+        config.upload_confirm_mb > 0 && self.bytes > config.upload_confirm_mb * MB
+    }
+
+    /// Whether to go ahead: asks on the terminal, after the progress lines sent to `tx` so far,
+    /// when the upload needs confirmation and the run is interactive, yes otherwise
+    pub fn confirm(&self, config: &Config, tx: &mpsc::UnboundedSender<String>) -> Result<bool> {
+        if !config.confirm_uploads || !self.needs_confirmation(config) {
+            return Ok(true);
+        }
+        crate::cli::confirm_after(tx, &format!(
+            "\nUpload {}? That is more than UPLOAD_CONFIRM_MB ({} MB). [y/N] ",
+            format_bytes(self.bytes),
+            config.upload_confirm_mb
+        ))
+    }
+}
+
+/// "45s", "3m 20s", "1h 5m"
+fn format_eta(eta: Duration) -> String {
+    let seconds = eta.as_secs().max(1);
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, seconds) => format!("{}s", seconds),
+        (0, minutes, seconds) => format!("{}m {}s", minutes, seconds),
+        (hours, minutes, _) => format!("{}h {}m", hours, minutes),
+    }
+}
+
+fn load_throughput(config: &Config) -> Option<Throughput> {
+    if config.mock_mode {
+        return None;
+    }
+    let path = get_token_dir(config.profile.as_deref()).ok()?.join(THROUGHPUT_FILE);
+    serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+/// Remember the speed of an upload of `bytes` that took `elapsed`, for later estimates. Uploads
+/// too small to tell are ignored, as are mock runs.
+pub fn record_throughput(config: &Config, bytes: u64, elapsed: Duration) -> Result<()> {
+    if config.mock_mode || bytes < MIN_MEASURED_BYTES || elapsed.is_zero() {
+        return Ok(());
+    }
+    let path = get_token_dir(config.profile.as_deref())?.join(THROUGHPUT_FILE);
+    shared::update_json(&path, |throughput: &mut Throughput| {
+        throughput.bytes_per_second = bytes as f64 / elapsed.as_secs_f64();
+        throughput.measured_at = Some(Utc::now());
+    })
+    .context("Failed to save the measured upload speed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        let mut config = Config::for_test(&[]);
+        config.upload_speed_mbps = Some(8.0);
+        config.upload_confirm_mb = 50;
+
+        let estimate = UploadEstimate::new(&config, 12, 80 * MB);
+        assert!(!estimate.measured);
+        assert_eq!(estimate.eta().map(|eta| eta.as_secs()), Some(83));
+        assert_eq!(estimate.summary(), "📦 Uploading 12 file(s), 80.0 MB (~1m 23s at 8.0 Mbit/s)");
+        assert!(estimate.needs_confirmation(&config));
+        assert!(!UploadEstimate::new(&config, 1, 2 * MB).needs_confirmation(&config));
+
+        // Mock runs have no measured speed to go on
+        config.upload_speed_mbps = None;
+        assert_eq!(UploadEstimate::new(&config, 1, 2 * MB).summary(), "📦 Uploading 1 file(s), 2.0 MB");
+        assert_eq!(format_eta(Duration::from_secs(3900)), "1h 5m");
+    }
+}
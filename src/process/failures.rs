@@ -0,0 +1,210 @@
+//! Failure reports: a run in which messages or files failed writes `failures-<run>.json` with
+//! each failure's stage, message, attachment and error, and `retry --from <file>` fetches just
+//! those messages again and runs them through the pipeline.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use crate::config::env::Config;
+use crate::mail::MailSource;
+use crate::process::jobs;
+use crate::process::outcome::{ErrorCategory, Failure, RunSummary, Stage};
+use crate::storage::Storage;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailureReport {
+    pub profile: Option<String>,
+    /// Mailbox and archive folder of the run when they differ per user (`run-workspace`), for
+    /// `retry` to read and upload to the same ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gmail_user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drive_folder_path: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// Date range of the run, which billing months are worked out from again on retry
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub failures: Vec<Failure>,
+}
+
+impl FailureReport {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("{} is not a failure report", path.display()))
+    }
+
+    /// Messages to fetch again, each once, in the order they failed. Messages found deleted the
+    /// first time are left out.
+    pub fn message_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = Vec::new();
+        for id in self.failures.iter().filter(|failure| failure.category != ErrorCategory::NotFound).filter_map(|failure| failure.message_id.as_deref()) {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        ids
+    }
+
+    /// Point `config` at the mailbox and folder the failed run used
+    pub fn restore(&self, config: &mut Config) {
+        if let Some(user) = &self.gmail_user {
+            config.gmail_user = Some(user.clone());
+        }
+        if let Some(folder) = &self.drive_folder_path {
+            config.drive_folder_path = folder.clone();
+        }
+    }
+
+    /// "failures-20250301T090000", with the profile and the mailbox in front for tenants and
+    /// workspace users (without the extension, which `save_report` adds)
+    fn file_stem(&self) -> String {
+        let run = self.created_at.with_timezone(&chrono::Local).format("%Y%m%dT%H%M%S");
+        let owner: Vec<String> = self
+            .profile
+            .iter()
+            .chain(&self.gmail_user)
+            .map(|part| part.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '-' }).collect())
+            .collect();
+        if owner.is_empty() {
+            format!("failures-{}", run)
+        } else {
+            format!("failures-{}-{}", owner.join("-"), run)
+        }
+    }
+}
+
+/// Write the failure report of a run that had failures into FAILURE_REPORT_DIR. A report that
+/// can't be written is a warning, not a reason to fail the run.
+pub fn save_report(config: &Config, start_date: NaiveDate, end_date: NaiveDate, summary: &RunSummary, tx: Option<&mpsc::UnboundedSender<String>>) -> Option<PathBuf> {
+    if summary.failures.is_empty() {
+        return None;
+    }
+    let report = FailureReport {
+        profile: config.profile.clone(),
+        gmail_user: config.gmail_user.clone(),
+        drive_folder_path: Some(config.drive_folder_path.clone()),
+        created_at: Utc::now(),
+        start_date,
+        end_date,
+        failures: summary.failures.clone(),
+    };
+    let stem = report.file_stem();
+    let mut path = config.failure_report_dir.join(format!("{}.json", stem));
+    let written = std::fs::create_dir_all(&config.failure_report_dir).map_err(anyhow::Error::from).and_then(|_| {
+        let json = serde_json::to_string_pretty(&report)?;
+        // A report written the same second (another user of the same mailbox) is never replaced
+        for n in 2.. {
+            match std::fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => return Ok(std::io::Write::write_all(&mut file, json.as_bytes())?),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => path = config.failure_report_dir.join(format!("{}-{}.json", stem, n)),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        unreachable!()
+    });
+    match written {
+        Ok(()) => {
+            notify(tx, format!("📝 {} failure(s) written to {} (retry with `invoice-pilot retry --from {}`)", report.failures.len(), path.display(), path.display()));
+            Some(path)
+        }
+        Err(e) => {
+            notify(tx, format!("⚠ Could not write the failure report {}: {:#}", path.display(), e));
+            None
+        }
+    }
+}
+
+/// Fetch the messages named in a failure report and archive them again. Files that made it the
+/// first time are skipped as already uploaded; what fails again goes into a new report.
+pub async fn retry(config: &Config, source: &dyn MailSource, storage: &dyn Storage, report: &FailureReport, tx: &mpsc::UnboundedSender<String>) -> Result<RunSummary> {
+    let message_ids = report.message_ids();
+    let without_message = report.failures.iter().filter(|failure| failure.message_id.is_none()).count();
+    if without_message > 0 {
+        tx.send(format!("  ℹ {} failure(s) name no message and can't be retried", without_message))?;
+    }
+    let gone = report.failures.iter().filter(|failure| failure.category == ErrorCategory::NotFound).count();
+    if gone > 0 {
+        tx.send(format!("  ℹ {} failure(s) were for messages or files that no longer exist and are not retried", gone))?;
+    }
+    tx.send(format!("🔁 Retrying {} message(s) from {} to {}...", message_ids.len(), report.start_date, report.end_date))?;
+
+    let mut messages = Vec::new();
+    let mut fetch_failures = Vec::new();
+    for message_id in message_ids {
+        match source.fetch_message(message_id).await {
+            Ok(message) => messages.push(message),
+            Err(e) => {
+                tx.send(format!("      ✗ Failed to fetch message {}: {}", message_id, e))?;
+                fetch_failures.push((message_id, e));
+            }
+        }
+    }
+
+    let mut summary = if messages.is_empty() {
+        RunSummary::default()
+    } else {
+        jobs::archive_messages(config, source, storage, &messages, report.start_date, report.end_date, tx).await?
+    };
+    for (message_id, error) in fetch_failures {
+        summary.fail(Stage::Fetch, Some(message_id), None, &error);
+    }
+    save_report(config, report.start_date, report.end_date, &summary, Some(tx));
+    Ok(summary)
+}
+
+fn notify(tx: Option<&mpsc::UnboundedSender<String>>, message: String) {
+    match tx {
+        Some(tx) => {
+            let _ = tx.send(message);
+        }
+        None => eprintln!("{}", message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::outcome::ApiError;
+
+    #[test]
+    fn test_failure_report_roundtrip() {
+        let mut config = Config::for_test(&[]);
+        config.profile = Some("acme".to_string());
+        let temp = tempfile::tempdir().unwrap();
+        config.failure_report_dir = temp.path().to_path_buf();
+        let day = |d| NaiveDate::from_ymd_opt(2025, 3, d).unwrap();
+
+        assert_eq!(save_report(&config, day(1), day(31), &RunSummary::default(), None), None);
+
+        let mut summary = RunSummary::default();
+        let status = |code| reqwest::StatusCode::from_u16(code).unwrap();
+        summary.fail(Stage::Fetch, Some("m1"), None, &ApiError::new("Gmail", status(503), "backend error").into());
+        summary.fail(Stage::Upload, Some("m2"), Some("aws-invoice.pdf"), &anyhow::anyhow!("quota exceeded"));
+        summary.fail(Stage::Prepare, Some("m1"), Some("scan.pdf"), &anyhow::anyhow!("Virus scan failed"));
+        summary.fail(Stage::Fetch, Some("m3"), None, &ApiError::new("Gmail", status(404), "Requested entity was not found.").into());
+        let path = save_report(&config, day(1), day(31), &summary, None).unwrap();
+        assert!(path.file_name().unwrap().to_string_lossy().starts_with("failures-acme-2"));
+
+        // Workspace users of one profile get reports of their own, which retry reads from and
+        // uploads to the same places
+        config.gmail_user = Some("ana@example.com".to_string());
+        config.drive_folder_path = "billing/ana@example.com".to_string();
+        let first = save_report(&config, day(1), day(31), &summary, None).unwrap();
+        let second = save_report(&config, day(1), day(31), &summary, None).unwrap();
+        assert_ne!(first, second);
+        assert!(first.file_name().unwrap().to_string_lossy().starts_with("failures-acme-ana-example.com-2"));
+        let mut base = Config { gmail_user: None, drive_folder_path: "billing".to_string(), ..config.clone() };
+        FailureReport::load(&second).unwrap().restore(&mut base);
+        assert_eq!((base.gmail_user.as_deref(), base.drive_folder_path.as_str()), (Some("ana@example.com"), "billing/ana@example.com"));
+
+        let report = FailureReport::load(&path).unwrap();
+        assert_eq!((report.start_date, report.end_date), (day(1), day(31)));
+        assert_eq!(report.failures, summary.failures);
+        assert_eq!(report.message_ids(), vec!["m1", "m2"]);
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert!(json.contains("\"stage\": \"upload\""));
+        assert!(json.contains("\"category\": \"transient\""));
+    }
+}
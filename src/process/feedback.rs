@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use crate::auth::oauth::get_token_dir;
+use crate::config::env::Config;
+use crate::config::shared;
+
+/// Processed files and corrections, stored next to the profile's tokens
+const FEEDBACK_FILE: &str = "feedback.json";
+
+/// How many uploaded files are remembered for `correct`
+const MAX_PROCESSED_FILES: usize = 500;
+
+/// A file the pipeline uploaded, kept so it can be corrected later by its Drive file id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessedFile {
+    pub file_id: String,
+    pub filename: String,
+    /// From header of the message the attachment came from
+    pub sender: String,
+    pub folder: String,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+/// What the user said was wrong with a processed file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Correction {
+    /// Not an invoice: skip this sender's attachments from now on
+    NotInvoice,
+    /// Wrong vendor/bank: file this sender's attachments under this folder instead
+    Folder(String),
+}
+
+impl std::fmt::Display for Correction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Correction::NotInvoice => write!(f, "not an invoice"),
+            Correction::Folder(folder) => write!(f, "file under {}", folder),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SenderCorrection {
+    pub correction: Correction,
+    /// The file the correction was made on
+    pub file_id: String,
+    pub corrected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Feedback {
+    processed: Vec<ProcessedFile>,
+    /// Sender address -> correction applied to future runs
+    corrections: BTreeMap<String, SenderCorrection>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Senders corrected (or cleared) since loading
+    #[serde(skip)]
+    corrected: BTreeSet<String>,
+}
+
+impl Feedback {
+    /// Load the profile's feedback. Mock runs keep it in memory only.
+    pub fn load(config: &Config) -> Result<Self> {
+        if config.mock_mode {
+            return Ok(Self::default());
+        }
+
+        let path = get_token_dir(config.profile.as_deref())?.join(FEEDBACK_FILE);
+        let mut feedback = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        feedback.path = Some(path);
+        Ok(feedback)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // Merged with what's on disk: another machine sharing the config directory may have
+        // uploaded or corrected files meanwhile
+        shared::update_json(path, |on_disk: &mut Self| {
+            on_disk.processed.retain(|file| !self.processed.iter().any(|ours| ours.file_id == file.file_id));
+            on_disk.processed.extend(self.processed.iter().cloned());
+            on_disk.processed.sort_by_key(|file| file.uploaded_at);
+            let excess = on_disk.processed.len().saturating_sub(MAX_PROCESSED_FILES);
+            on_disk.processed.drain(..excess);
+
+            for sender in &self.corrected {
+                match self.corrections.get(sender) {
+                    Some(correction) => on_disk.corrections.insert(sender.clone(), correction.clone()),
+                    None => on_disk.corrections.remove(sender),
+                };
+            }
+        })
+    }
+
+    /// Remember an uploaded file (the most recent upload of a file id wins)
+    pub fn record_upload(&mut self, file: ProcessedFile) {
+        self.processed.retain(|existing| existing.file_id != file.file_id);
+        self.processed.push(file);
+        let excess = self.processed.len().saturating_sub(MAX_PROCESSED_FILES);
+        self.processed.drain(..excess);
+    }
+
+    /// Most recently uploaded files first
+    pub fn recent(&self) -> impl Iterator<Item = &ProcessedFile> {
+        self.processed.iter().rev()
+    }
+
+    /// Record a correction for the sender of a processed file; None clears it
+    pub fn correct(&mut self, file_id: &str, correction: Option<Correction>) -> Result<&ProcessedFile> {
+        let file = self
+            .processed
+            .iter()
+            .rev()
+            .find(|file| file.file_id == file_id)
+            .with_context(|| format!("No processed file with id {} (run `correct` without arguments to list them)", file_id))?;
+        let sender = sender_address(&file.sender);
+        self.corrected.insert(sender.clone());
+
+        match correction {
+            Some(correction) => {
+                let entry = SenderCorrection { correction, file_id: file_id.to_string(), corrected_at: Utc::now() };
+                self.corrections.insert(sender, entry);
+            }
+            None => {
+                self.corrections.remove(&sender);
+            }
+        }
+        Ok(file)
+    }
+
+    /// Correction learned for the sender of a message, if any
+    pub fn correction_for(&self, from: &str) -> Option<&Correction> {
+        self.corrections.get(&sender_address(from)).map(|entry| &entry.correction)
+    }
+
+    pub fn corrections(&self) -> impl Iterator<Item = (&String, &SenderCorrection)> {
+        self.corrections.iter()
+    }
+}
+
+/// Lowercase address of a From header: `"Acme" <Billing@Acme.com>` -> `billing@acme.com`
+fn sender_address(from: &str) -> String {
+    let address = match (from.rfind('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &from[start + 1..end],
+        _ => from,
+    };
+    address.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corrections_apply_to_sender() {
+        let mut feedback = Feedback::default();
+        let file = |id: &str, sender: &str| ProcessedFile {
+            file_id: id.to_string(),
+            filename: format!("{}.pdf", id),
+            sender: sender.to_string(),
+            folder: "billing/March".to_string(),
+            uploaded_at: Utc::now(),
+        };
+        feedback.record_upload(file("f1", "Newsletter <News@Shop.example>"));
+        feedback.record_upload(file("f2", "Wise <noreply@wise.com>"));
+
+        feedback.correct("f1", Some(Correction::NotInvoice)).unwrap();
+        feedback.correct("f2", Some(Correction::Folder("Wise".to_string()))).unwrap();
+        assert!(feedback.correct("missing", Some(Correction::NotInvoice)).is_err());
+
+        assert_eq!(feedback.correction_for("\"Shop\" <news@shop.example>"), Some(&Correction::NotInvoice));
+        assert_eq!(feedback.correction_for("noreply@wise.com"), Some(&Correction::Folder("Wise".to_string())));
+        assert_eq!(feedback.correction_for("other@wise.com"), None);
+
+        feedback.correct("f1", None).unwrap();
+        assert_eq!(feedback.correction_for("news@shop.example"), None);
+        assert_eq!(feedback.recent().next().map(|f| f.file_id.as_str()), Some("f2"));
+    }
+}
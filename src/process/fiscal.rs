@@ -0,0 +1,191 @@
+//! Fiscal years (FISCAL_YEAR_START): GOOGLE_DRIVE_FOLDER_LOCATION may hold `{year}`,
+//! `{fiscal_year}` and `{fiscal_period}`, filled in per billing month, and `report --fiscal-year`
+//! totals the archive by accounting period instead of calendar month.
+
+use anyhow::Result;
+use chrono::{Datelike, Months, NaiveDate};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use crate::db::{self, DbPool};
+use crate::extract::invoice::format_amount;
+
+/// Placeholders GOOGLE_DRIVE_FOLDER_LOCATION may contain
+const PLACEHOLDERS: &[&str] = &["year", "fiscal_year", "fiscal_period"];
+
+/// Accounting calendar: a fiscal year runs twelve months from its start month
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct FiscalCalendar {
+    /// 1 = January (calendar years), 4 = April (April–March), ...
+    pub start_month: u32,
+}
+
+impl Default for FiscalCalendar {
+    fn default() -> Self {
+        Self { start_month: 1 }
+    }
+}
+
+impl FiscalCalendar {
+    /// "4", "04", "April" or "apr"
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let start_month = match value.parse::<u32>() {
+            Ok(month) => month,
+            Err(_) => value.parse::<chrono::Month>().ok()?.number_from_month(),
+        };
+        (1..=12).contains(&start_month).then_some(Self { start_month })
+    }
+
+    /// First day of the fiscal year `date` falls in
+    pub fn year_start(&self, date: NaiveDate) -> NaiveDate {
+        let year = if date.month() >= self.start_month { date.year() } else { date.year() - 1 };
+        NaiveDate::from_ymd_opt(year, self.start_month, 1).unwrap_or(date)
+    }
+
+    /// First day of the fiscal year named `year` ("2025" or "2025-26" is the one starting in 2025)
+    pub fn named_year_start(&self, year: &str) -> Option<NaiveDate> {
+        let start = year.split_once('-').map_or(year, |(start, _)| start).trim().parse().ok()?;
+        NaiveDate::from_ymd_opt(start, self.start_month, 1)
+    }
+
+    /// "2025" for calendar years, "2025-26" for a year starting in April 2025
+    pub fn fiscal_year(&self, date: NaiveDate) -> String {
+        let start = self.year_start(date).year();
+        if self.start_month == 1 {
+            start.to_string()
+        } else {
+            format!("{}-{:02}", start, (start + 1) % 100)
+        }
+    }
+
+    /// Month of the fiscal year, "P01" for its first month to "P12"
+    pub fn fiscal_period(&self, date: NaiveDate) -> String {
+        format!("P{:02}", (date.month() + 12 - self.start_month) % 12 + 1)
+    }
+
+    /// Fill in the placeholders of a folder path for a billing month
+    pub fn render(&self, path: &str, month: NaiveDate) -> String {
+        path.replace("{year}", &month.year().to_string())
+            .replace("{fiscal_year}", &self.fiscal_year(month))
+            .replace("{fiscal_period}", &self.fiscal_period(month))
+    }
+}
+
+/// Unknown `{...}` placeholders in a folder path
+pub fn check_template(path: &str) -> Result<()> {
+    for part in path.split('{').skip(1) {
+        let name = part.split_once('}').map(|(name, _)| name).unwrap_or(part);
+        if !PLACEHOLDERS.contains(&name) {
+            anyhow::bail!("Unknown placeholder {{{}}} (use {})", name, PLACEHOLDERS.iter().map(|p| format!("{{{}}}", p)).collect::<Vec<_>>().join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// The folders of a path before its first placeholder ("billing/FY{fiscal_year}" → "billing"),
+/// which every month shares
+pub fn fixed_root(path: &str) -> String {
+    path.split('/').take_while(|segment| !segment.contains('{')).collect::<Vec<_>>().join("/")
+}
+
+/// Totals of one accounting period
+#[derive(Debug, Clone, Serialize)]
+pub struct PeriodTotal {
+    /// "P01"
+    pub period: String,
+    pub month: NaiveDate,
+    pub documents: i64,
+    /// Currency ("" when unknown) -> total of the documents an amount was found in
+    pub totals_cents: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FiscalReport {
+    /// "2025-26"
+    pub year: String,
+    pub start: NaiveDate,
+    /// All twelve periods, empty ones included
+    pub periods: Vec<PeriodTotal>,
+}
+
+impl FiscalReport {
+    /// Totals of the fiscal year starting at `start`
+    pub async fn load(pool: &DbPool, profile: &str, calendar: FiscalCalendar, start: NaiveDate) -> Result<Self> {
+        let totals = db::vendor_month_totals(pool, profile, start, start + Months::new(11)).await?;
+        Ok(Self::build(calendar, start, totals))
+    }
+
+    fn build(calendar: FiscalCalendar, start: NaiveDate, totals: Vec<db::ArchiveTotal>) -> Self {
+        let mut periods: Vec<PeriodTotal> = (0..12)
+            .map(|offset| start + Months::new(offset))
+            .map(|month| PeriodTotal { period: calendar.fiscal_period(month), month, documents: 0, totals_cents: BTreeMap::new() })
+            .collect();
+        for total in totals {
+            let Some(period) = periods.iter_mut().find(|period| Some(period.month) == total.billing_month) else {
+                continue;
+            };
+            period.documents += total.documents;
+            if let Some(cents) = total.total_cents {
+                *period.totals_cents.entry(total.currency.unwrap_or_default()).or_default() += cents;
+            }
+        }
+        Self { year: calendar.fiscal_year(start), start, periods }
+    }
+
+    /// Plain text table for the terminal
+    pub fn render(&self) -> String {
+        let mut text = format!("Fiscal year {} (from {})\n\n", self.year, self.start.format("%B %Y"));
+        let mut year_cents: BTreeMap<&str, i64> = BTreeMap::new();
+        for period in &self.periods {
+            for (currency, cents) in &period.totals_cents {
+                *year_cents.entry(currency).or_default() += cents;
+            }
+            text.push_str(&format!(
+                "  {}  {:<15} {:>4} document(s)  {}\n",
+                period.period,
+                period.month.format("%B %Y").to_string(),
+                period.documents,
+                amounts(period.totals_cents.iter().map(|(currency, cents)| (currency.as_str(), *cents)))
+            ));
+        }
+        let documents: i64 = self.periods.iter().map(|period| period.documents).sum();
+        text.push_str(&format!("\n  Total {:>21} document(s)  {}\n", documents, amounts(year_cents.into_iter())));
+        text
+    }
+}
+
+/// "49.99 EUR, 12.00 USD", "-" when nothing was found
+fn amounts<'a>(totals: impl Iterator<Item = (&'a str, i64)>) -> String {
+    let amounts: Vec<String> = totals.map(|(currency, cents)| format!("{} {}", format_amount(cents), currency).trim_end().to_string()).collect();
+    if amounts.is_empty() { "-".to_string() } else { amounts.join(", ") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fiscal_calendar() {
+        let day = |y, m| NaiveDate::from_ymd_opt(y, m, 1).unwrap();
+        let april = FiscalCalendar::parse("April").unwrap();
+        assert_eq!(FiscalCalendar::parse("4"), Some(april));
+        assert_eq!(FiscalCalendar::parse("13"), None);
+
+        assert_eq!(april.fiscal_year(day(2026, 3)), "2025-26");
+        assert_eq!(april.fiscal_period(day(2026, 3)), "P12");
+        assert_eq!(april.fiscal_period(day(2025, 4)), "P01");
+        assert_eq!(april.named_year_start("2025-26"), Some(day(2025, 4)));
+        assert_eq!(april.render("billing/FY{fiscal_year}/{fiscal_period}", day(2025, 5)), "billing/FY2025-26/P02");
+        assert_eq!(FiscalCalendar::default().render("billing/{fiscal_year}", day(2025, 5)), "billing/2025");
+
+        assert!(check_template("billing/{year}").is_ok());
+        assert!(check_template("billing/{month}").is_err());
+        assert_eq!(fixed_root("billing/FY{fiscal_year}/x"), "billing");
+
+        let total = |month, cents| db::ArchiveTotal { billing_month: Some(month), vendor: "aws".to_string(), currency: Some("EUR".to_string()), documents: 1, total_cents: Some(cents) };
+        let report = FiscalReport::build(april, day(2025, 4), vec![total(day(2025, 4), 1000), total(day(2026, 3), 2500), total(day(2026, 4), 9900)]);
+        assert_eq!(report.periods[0].totals_cents["EUR"], 1000);
+        assert_eq!(report.periods[11].totals_cents["EUR"], 2500);
+        assert!(report.render().contains("Total                     2 document(s)  35.00 EUR"));
+    }
+}
@@ -0,0 +1,293 @@
+//! Run heartbeats. A running pipeline writes its stage and progress to the `heartbeats` table
+//! every few seconds, so `status` and the dashboard can tell a slow run from a stuck one: a run
+//! that keeps beating but stops moving is stuck somewhere (a stalled download), one that stops
+//! beating is gone. Scheduled runs also give up once they stop moving for
+//! HEARTBEAT_STALE_MINUTES, which takes the usual failure path (retries, ON_RUN_FAILURE).
+
+use crate::config::env::Config;
+use crate::db::{self, DbPool, HeartbeatRecord};
+use crate::hooks;
+use crate::process::outcome::RunSummary;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// How often a running pipeline writes its heartbeat
+const BEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Days the heartbeats of past runs are kept before a new run deletes them
+const HEARTBEAT_KEEP_DAYS: i32 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    /// Beating and moving on, however slowly
+    Alive,
+    /// Beating, but no progress for longer than the stale limit
+    Stuck,
+    /// Not heard from for longer than the stale limit: the process died or is frozen
+    Gone,
+}
+
+impl Health {
+    pub fn of(heartbeat: &HeartbeatRecord, stale: Duration, now: DateTime<Utc>) -> Self {
+        let older_than_stale = |at: DateTime<Utc>| (now - at).to_std().is_ok_and(|age| age > stale);
+        if older_than_stale(heartbeat.beat_at) {
+            Health::Gone
+        } else if older_than_stale(heartbeat.progress_at) {
+            Health::Stuck
+        } else {
+            Health::Alive
+        }
+    }
+}
+
+/// The stale limit from HEARTBEAT_STALE_MINUTES, None when stuck runs aren't detected
+pub fn stale_limit(config: &Config) -> Option<Duration> {
+    (config.heartbeat_stale_minutes > 0).then(|| Duration::from_secs(config.heartbeat_stale_minutes * 60))
+}
+
+/// A run's live progress, written to the database while the run lasts (when there is one; mock
+/// runs are never recorded)
+pub struct Heartbeat {
+    progress: Arc<Mutex<HeartbeatRecord>>,
+    recorded: Option<(DbPool, JoinHandle<()>)>,
+}
+
+impl Heartbeat {
+    pub async fn start(config: &Config, command: &str) -> Self {
+        let now = Utc::now();
+        let mut progress = HeartbeatRecord {
+            id: 0,
+            profile: config.profile.clone().unwrap_or_default(),
+            command: command.to_string(),
+            pid: std::process::id() as i32,
+            started_at: now,
+            beat_at: now,
+            progress_at: now,
+            stage: "Starting".to_string(),
+            detail: String::new(),
+            items_completed: 0,
+            items_total: None,
+        };
+
+        let pool = if config.mock_mode { None } else { db::connect_optional("Run heartbeat").await };
+        let mut recorded = None;
+        if let Some(pool) = pool {
+            match db::start_heartbeat(&pool, &progress.profile, command, std::process::id()).await {
+                Ok(id) => progress.id = id,
+                Err(e) => log::warn!("{:#}", e),
+            }
+            if let Err(e) = db::prune_heartbeats(&pool, HEARTBEAT_KEEP_DAYS).await {
+                log::warn!("{:#}", e);
+            }
+            if progress.id != 0 {
+                recorded = Some(pool);
+            }
+        }
+
+        let progress = Arc::new(Mutex::new(progress));
+        let recorded = recorded.map(|pool| {
+            let beating = tokio::spawn(beat(pool.clone(), progress.clone()));
+            (pool, beating)
+        });
+        Self { progress, recorded }
+    }
+
+    /// Move on to a new stage
+    pub fn stage(&self, stage: &str) {
+        update(&self.progress, |progress| {
+            progress.stage = stage.to_string();
+            progress.detail.clear();
+            progress.items_total = None;
+        });
+    }
+
+    /// Items of the current stage done so far
+    pub fn items(&self, completed: usize, total: usize) {
+        update(&self.progress, |progress| {
+            progress.items_completed = completed as i32;
+            progress.items_total = Some(total as i32);
+        });
+    }
+
+    /// A sender that notes every progress line sent through it before passing it on to `tx`.
+    /// The forwarding task ends once the returned sender is dropped.
+    pub fn relay(&self, tx: &mpsc::UnboundedSender<String>) -> (mpsc::UnboundedSender<String>, JoinHandle<()>) {
+        let (relay, mut rx) = mpsc::unbounded_channel::<String>();
+        let progress = self.progress.clone();
+        let tx = tx.clone();
+        let forwarder = tokio::spawn(async move {
+            while let Some(line) = rx.recv().await {
+                note_line(&progress, &line);
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        (relay, forwarder)
+    }
+
+    /// Resolves once the run has made no progress for `stale`, with where it stopped
+    pub async fn stalled(&self, stale: Duration) -> String {
+        loop {
+            let (progress_at, described) = {
+                let progress = self.progress.lock().unwrap_or_else(|e| e.into_inner());
+                (progress.progress_at, describe(&progress))
+            };
+            let idle = (Utc::now() - progress_at).to_std().unwrap_or_default();
+            if idle >= stale {
+                return described;
+            }
+            tokio::time::sleep((stale - idle).min(BEAT_INTERVAL)).await;
+        }
+    }
+
+    /// Run `run` and give it up once it stops moving for the stale limit
+    pub async fn guard<T>(&self, stale: Option<Duration>, run: impl Future<Output = Result<T>>) -> Result<T> {
+        let Some(stale) = stale else {
+            return run.await;
+        };
+        tokio::select! {
+            result = run => result,
+            stopped = self.stalled(stale) => Err(anyhow::anyhow!(
+                "Run stalled: no progress for {} minute(s) ({})",
+                stale.as_secs() / 60,
+                stopped
+            )),
+        }
+    }
+
+    /// The run is over: stop beating and mark it finished
+    pub async fn finish(self) {
+        if let Some((pool, beating)) = self.recorded {
+            beating.abort();
+            let id = self.progress.lock().unwrap_or_else(|e| e.into_inner()).id;
+            if let Err(e) = db::finish_heartbeat(&pool, id).await {
+                log::warn!("{:#}", e);
+            }
+        }
+    }
+}
+
+fn update(progress: &Mutex<HeartbeatRecord>, change: impl FnOnce(&mut HeartbeatRecord)) {
+    let mut progress = progress.lock().unwrap_or_else(|e| e.into_inner());
+    change(&mut progress);
+    progress.progress_at = Utc::now();
+}
+
+/// Unindented progress lines start a stage, indented ones are its detail, and
+/// "Processing message 3/40" counts messages
+fn note_line(progress: &Mutex<HeartbeatRecord>, line: &str) {
+    // Internal markers for the TUI (e.g. __RESULTS__) are not progress
+    if line.starts_with("__") || line.trim().is_empty() {
+        return;
+    }
+    let counted = line
+        .trim()
+        .strip_prefix("Processing message ")
+        .and_then(|count| count.split_once('/'))
+        .and_then(|(current, total)| Some((current.parse::<usize>().ok()?, total.parse::<usize>().ok()?)));
+    update(progress, |progress| {
+        if !line.starts_with(' ') {
+            progress.stage = line.to_string();
+            progress.detail.clear();
+            progress.items_total = None;
+            return;
+        }
+        progress.detail = line.trim().to_string();
+        if let Some((current, total)) = counted {
+            progress.items_completed = current.saturating_sub(1) as i32;
+            progress.items_total = Some(total as i32);
+        }
+    });
+}
+
+async fn beat(pool: DbPool, progress: Arc<Mutex<HeartbeatRecord>>) {
+    loop {
+        tokio::time::sleep(BEAT_INTERVAL).await;
+        let current = progress.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Err(e) = db::save_heartbeat(&pool, &current).await {
+            log::warn!("{:#}", e);
+        }
+    }
+}
+
+/// Stage, count and latest line in one line
+pub fn describe(heartbeat: &HeartbeatRecord) -> String {
+    let mut described = heartbeat.stage.clone();
+    if let Some(total) = heartbeat.items_total {
+        described.push_str(&format!(" [{}/{}]", heartbeat.items_completed, total));
+    }
+    if !heartbeat.detail.is_empty() {
+        described.push_str(&format!(": {}", heartbeat.detail));
+    }
+    described
+}
+
+/// Runs of this profile that stopped beating without finishing (killed, or the machine went down)
+/// go through the failure path once: they are marked finished and ON_RUN_FAILURE hears about them
+pub async fn report_gone(config: &Config) -> Result<usize> {
+    let Some(stale) = stale_limit(config) else {
+        return Ok(0);
+    };
+    if config.mock_mode {
+        return Ok(0);
+    }
+    let Some(pool) = db::connect_optional("Run heartbeat").await else {
+        return Ok(0);
+    };
+
+    let mut reported = 0;
+    for heartbeat in db::live_heartbeats(&pool, config.profile.as_deref().unwrap_or_default()).await? {
+        if Health::of(&heartbeat, stale, Utc::now()) != Health::Gone {
+            continue;
+        }
+        db::finish_heartbeat(&pool, heartbeat.id).await?;
+        let error = anyhow::anyhow!(
+            "The {} run started {} (pid {}) stopped responding at {} during {}",
+            heartbeat.command,
+            heartbeat.started_at.format("%Y-%m-%d %H:%M UTC"),
+            heartbeat.pid,
+            heartbeat.beat_at.format("%Y-%m-%d %H:%M UTC"),
+            describe(&heartbeat)
+        );
+        log::warn!("{:#}", error);
+        hooks::run_finished(config, &Err::<RunSummary, _>(error), None).await;
+        reported += 1;
+    }
+    Ok(reported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_progress_and_health() {
+        let config = Config::for_test(&[]);
+        let heartbeat = Heartbeat::start(&config, "scheduled").await;
+        for line in ["⬇️ Downloading attachments...", "  Processing message 3/40", "__RESULTS__:{}"] {
+            note_line(&heartbeat.progress, line);
+        }
+        let progress = heartbeat.progress.lock().unwrap().clone();
+        assert_eq!(describe(&progress), "⬇️ Downloading attachments... [2/40]: Processing message 3/40");
+
+        let now = progress.progress_at;
+        let minutes = |m: i64| now - chrono::Duration::minutes(m);
+        let stale = Duration::from_secs(30 * 60);
+        assert_eq!(Health::of(&progress, stale, now), Health::Alive);
+        assert_eq!(Health::of(&HeartbeatRecord { progress_at: minutes(45), ..progress.clone() }, stale, now), Health::Stuck);
+        assert_eq!(Health::of(&HeartbeatRecord { beat_at: minutes(45), progress_at: minutes(45), ..progress.clone() }, stale, now), Health::Gone);
+
+        // A run that stops moving is given up on
+        let stalled = heartbeat.guard(Some(Duration::from_millis(50)), std::future::pending::<Result<()>>()).await;
+        assert!(stalled.unwrap_err().to_string().starts_with("Run stalled"));
+        heartbeat.finish().await;
+    }
+}
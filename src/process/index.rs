@@ -0,0 +1,176 @@
+//! Indexing an existing Drive archive (`index-drive --folder`). PDFs filed by hand before the
+//! agent was set up are downloaded once, their text and invoice fields extracted and recorded in
+//! `invoice_documents`, so search, reports and duplicate checks cover them too.
+
+use crate::config::env::Config;
+use crate::db::{self, DbPool, InvoiceDocument};
+use crate::drive;
+use crate::drive::client::DriveClient;
+use crate::extract;
+use crate::process::retention::{self, join};
+use crate::process::rules;
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate};
+use std::collections::HashSet;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Default)]
+pub struct IndexSummary {
+    pub indexed: usize,
+    /// Files the invoice database already has, by id or by contents
+    pub known: usize,
+    /// Files that aren't PDFs
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+/// Billing month of a file from the folders it sits in: a month folder under a year folder
+/// (`2023/March`, `2023/03`) or a `2023-03` folder, otherwise the month the file was created
+pub fn billing_month(folder: &str, created_time: Option<&str>) -> Option<NaiveDate> {
+    let mut year = None;
+    let mut month = None;
+    for segment in folder.split('/') {
+        if let Some(found) = retention::parse_year(segment) {
+            year = Some(found);
+        } else if let Some((y, m)) = segment.split_once('-')
+            && let (Some(y), Ok(m)) = (retention::parse_year(y), m.parse::<u32>())
+        {
+            year = Some(y);
+            month = Some(m);
+        } else if let Some(found) = retention::parse_month(segment) {
+            month = Some(found);
+        } else if year.is_some() && segment.len() <= 2 && let Ok(found) = segment.parse::<u32>() {
+            month = Some(found);
+        }
+    }
+    if let (Some(year), Some(month)) = (year, month)
+        && let Some(date) = NaiveDate::from_ymd_opt(year, month, 1)
+    {
+        return Some(date);
+    }
+    let created = chrono::DateTime::parse_from_rfc3339(created_time?).ok()?.date_naive();
+    NaiveDate::from_ymd_opt(created.year(), created.month(), 1)
+}
+
+/// Vendor of a file filed by hand: the deepest folder below the one indexed that isn't a year,
+/// month or `Credit Notes` folder (`Revolut/2023/March`), otherwise the start of its name
+/// (`Hetzner-0042.pdf`)
+pub fn vendor(relative_folder: &str, filename: &str) -> String {
+    let is_date = |segment: &str| {
+        retention::parse_year(segment).is_some()
+            || retention::parse_month(segment).is_some()
+            || segment.chars().all(|c| c.is_ascii_digit() || c == '-')
+    };
+    if let Some(folder) = relative_folder
+        .rsplit('/')
+        .find(|segment| !segment.is_empty() && !is_date(segment) && *segment != extract::invoice::CREDIT_NOTES_FOLDER)
+    {
+        return folder.to_string();
+    }
+    let stem = std::path::Path::new(filename).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    stem.split(['-', '_', ' ']).find(|part| !part.is_empty() && !is_date(part)).map(str::to_string).unwrap_or(stem)
+}
+
+/// Record every PDF below `folder_path` the invoice database doesn't have yet
+pub async fn index_drive(
+    config: &Config,
+    client: &DriveClient,
+    pool: &DbPool,
+    folder_path: &str,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<IndexSummary> {
+    let mut summary = IndexSummary::default();
+    let Some(root_id) = drive::folder::find_folder_by_path(client, folder_path).await? else {
+        anyhow::bail!("{} is not a folder in Drive", folder_path);
+    };
+    let profile = config.profile.clone().unwrap_or_default();
+    let recorded = db::archived_files(pool, &profile, None).await?;
+    let mut known_ids: HashSet<String> = recorded.iter().map(|record| record.file_id.clone()).collect();
+    let mut known_sums: HashSet<String> = recorded.into_iter().filter_map(|record| record.sha256).collect();
+
+    let files = retention::list_files(client, &root_id).await?;
+    tx.send(format!("Found {} file(s) under {}", files.len(), folder_path))?;
+    for (path, file) in files {
+        if !rules::is_pdf(&file.name) {
+            summary.skipped += 1;
+            continue;
+        }
+        if known_ids.contains(&file.id) || file.sha256.as_ref().is_some_and(|sum| known_sums.contains(sum)) {
+            summary.known += 1;
+            continue;
+        }
+        let relative = path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or_default();
+        let folder = join(folder_path.trim_matches('/'), relative);
+        let indexed = async {
+            let data = drive::download::download_file(client, &file.id).await?;
+            // Drive leaves out the checksum of some files: a copy of a recorded one is only
+            // recognized by its contents
+            let sha256 = drive::upload::sha256_hex(&data);
+            if known_sums.contains(&sha256) {
+                return Ok(None);
+            }
+            let name = file.name.clone();
+            let text = tokio::task::spawn_blocking({
+                let data = data.clone();
+                move || extract::document_text(&name, &data)
+            })
+            .await
+            .unwrap_or_default();
+            let fields = text.as_deref().map(extract::invoice::invoice_fields).unwrap_or_default();
+            let document = InvoiceDocument {
+                profile: profile.clone(),
+                vendor: vendor(relative, &file.name),
+                invoice_number: fields.number,
+                amount_cents: fields.amount_cents,
+                currency: fields.currency,
+                filename: file.name.clone(),
+                folder: folder.clone(),
+                file_id: file.id.clone(),
+                billing_month: billing_month(&folder, file.created_time.as_deref()),
+                web_link: Some(drive::client::file_link(&file.id)),
+                content: text,
+                size_bytes: Some(data.len() as i64),
+                sha256: Some(sha256),
+                due_date: fields.due_date,
+                ..Default::default()
+            };
+            db::save_invoice_document(pool, &document).await?;
+            Ok::<_, anyhow::Error>(Some(document))
+        };
+        match indexed.await {
+            Ok(None) => summary.known += 1,
+            Ok(Some(document)) => {
+                // Later copies of the same file under the folder are known now too
+                known_ids.insert(document.file_id.clone());
+                known_sums.extend(document.sha256.clone());
+                summary.indexed += 1;
+                tx.send(format!("  ✓ {} ({}, {})", path, document.vendor, document.display_amount()))?;
+            }
+            Err(e) => {
+                summary.failed += 1;
+                tx.send(format!("  ✗ {}: {:#}", path, e))?;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_billing_month_and_vendor_from_path() {
+        let month = |year, month| NaiveDate::from_ymd_opt(year, month, 1);
+        assert_eq!(billing_month("invoices/2023/March/Revolut", None), month(2023, 3));
+        assert_eq!(billing_month("invoices/2022/11", None), month(2022, 11));
+        assert_eq!(billing_month("scans/2021-07", None), month(2021, 7));
+        assert_eq!(billing_month("scans/unsorted", Some("2020-05-14T09:30:00.000Z")), month(2020, 5));
+        assert_eq!(billing_month("scans/unsorted", None), None);
+
+        assert_eq!(vendor("Revolut/2023/March", "statement.pdf"), "Revolut");
+        assert_eq!(vendor("2023/March", "Hetzner-0042.pdf"), "Hetzner");
+        assert_eq!(vendor("2023/March/Credit Notes", "2023-03-04_Stripe_refund.pdf"), "Stripe");
+        assert_eq!(vendor("", "AWS invoice.pdf"), "AWS");
+    }
+}
@@ -0,0 +1,185 @@
+//! Ingestion queue: messages forwarded to a dedicated label or alias (INGEST_QUEUE) are archived
+//! whatever their keywords or attachment names, each message once.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Days, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use crate::auth::oauth::get_token_dir;
+use crate::config::env::{Config, DocumentDateCheck};
+use crate::config::shared;
+use crate::mail::MailSource;
+use crate::process::jobs;
+use crate::process::outcome::{FailureKind, RunSummary, Stage};
+use crate::storage::Storage;
+
+/// Ids of messages already taken from the queue, stored next to the profile's tokens
+const INGESTED_FILE: &str = "ingested.json";
+
+/// Lines that introduce a forwarded message in Gmail, Outlook and Apple Mail
+const FORWARD_MARKERS: &[&str] = &["forwarded message", "original message", "begin forwarded message"];
+
+/// Messages taken from the queue; the mailbox token is read-only, so they can't be relabelled
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IngestedMessages {
+    /// Message id -> when it was archived
+    messages: BTreeMap<String, DateTime<Utc>>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Entries older than this were forgotten since loading
+    #[serde(skip)]
+    cutoff: Option<DateTime<Utc>>,
+}
+
+impl IngestedMessages {
+    /// Load the profile's ingested messages. Mock runs keep them in memory only.
+    pub fn load(config: &Config) -> Result<Self> {
+        if config.mock_mode {
+            return Ok(Self::default());
+        }
+
+        let path = get_token_dir(config.profile.as_deref())?.join(INGESTED_FILE);
+        let mut ingested = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        ingested.path = Some(path);
+        Ok(ingested)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // Another machine sharing the config directory may have taken messages meanwhile
+        shared::update_json(path, |on_disk: &mut Self| {
+            on_disk.messages.extend(self.messages.iter().map(|(id, ingested_at)| (id.clone(), *ingested_at)));
+            if let Some(cutoff) = self.cutoff {
+                on_disk.messages.retain(|_, ingested_at| *ingested_at >= cutoff);
+            }
+        })
+    }
+
+    pub fn contains(&self, message_id: &str) -> bool {
+        self.messages.contains_key(message_id)
+    }
+
+    /// Remember archived messages, forgetting those older than the lookback window (the
+    /// queue search no longer returns them)
+    fn record(&mut self, message_ids: impl IntoIterator<Item = String>, lookback_days: u64) {
+        let now = Utc::now();
+        self.messages.extend(message_ids.into_iter().map(|id| (id, now)));
+        let cutoff = now - chrono::Duration::days(lookback_days as i64 + 1);
+        self.messages.retain(|_, ingested_at| *ingested_at >= cutoff);
+        self.cutoff = Some(cutoff);
+    }
+}
+
+/// Archive every new message in the ingestion queue. Returns an empty summary when the queue
+/// has nothing new.
+pub async fn ingest_queue(
+    config: &Config,
+    source: &dyn MailSource,
+    storage: &dyn Storage,
+    ingested: &mut IngestedMessages,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<RunSummary> {
+    let queue = config
+        .ingest_queue
+        .as_ref()
+        .context("INGEST_QUEUE is not set (a Gmail label or an alias address)")
+        .context(FailureKind::Config)?;
+    let end_date = Local::now().date_naive();
+    let start_date = end_date - Days::new(config.ingest_lookback_days);
+
+    let message_ids: Vec<String> = source
+        .search_queue(queue, start_date)
+        .await?
+        .into_iter()
+        .filter(|id| !ingested.contains(id))
+        .collect();
+    if message_ids.is_empty() {
+        return Ok(RunSummary::default());
+    }
+
+    tx.send(format!("📥 {} new message(s) in {}", message_ids.len(), queue))?;
+
+    let mut messages = Vec::new();
+    let mut fetch_failures = Vec::new();
+    for message_id in &message_ids {
+        match source.fetch_message(message_id).await {
+            Ok(mut message) => {
+                if let Some(sender) = forwarded_sender(&message.body) {
+                    tx.send(format!("      ↪ Forwarded from {}", sender))?;
+                    message.from = sender;
+                }
+                messages.push(message);
+            }
+            Err(e) => {
+                tx.send(format!("      ✗ Failed to fetch message {}: {}", message_id, e))?;
+                fetch_failures.push((message_id, e));
+            }
+        }
+    }
+
+    // Pushed by hand: the user vouches for these, so documents of any date are kept, and the
+    // sender checks would only see the forwarding account
+    let config = Config {
+        document_date_check: DocumentDateCheck::Off,
+        phishing_checks: false,
+        ..config.clone()
+    };
+    let mut summary = jobs::archive_messages(&config, source, storage, &messages, start_date, end_date, tx).await?;
+    for (message_id, error) in fetch_failures {
+        summary.fail(Stage::Fetch, Some(message_id), None, &error);
+    }
+
+    // Messages that could not be fetched, or with an attachment that failed to download, prepare
+    // or upload, stay in the queue for the next pass; a failure not traced to a message keeps
+    // them all there
+    let failed: HashSet<&str> = summary.failures.iter().filter_map(|failure| failure.message_id.as_deref()).collect();
+    if summary.failures.iter().all(|failure| failure.message_id.is_some()) {
+        ingested.record(messages.iter().filter(|message| !failed.contains(message.id.as_str())).map(|message| message.id.clone()), config.ingest_lookback_days);
+    }
+    if let Err(e) = ingested.save() {
+        tx.send(format!("⚠ Could not record ingested messages: {:#}", e))?;
+    }
+    Ok(summary)
+}
+
+/// Original sender of a forwarded message: the first `From:` line after a forward marker
+fn forwarded_sender(body: &str) -> Option<String> {
+    let mut lines = body.lines().map(|line| line.trim().trim_start_matches(['>', '*', ' ']));
+    lines.find(|line| {
+        let line = line.to_lowercase();
+        FORWARD_MARKERS.iter().any(|marker| line.contains(marker))
+    })?;
+    lines
+        .take(8)
+        .find_map(|line| line.get(..5).filter(|key| key.eq_ignore_ascii_case("from:")).map(|_| &line[5..]))
+        .map(|sender| sender.trim_matches(['*', ' ']).to_string())
+        .filter(|sender| !sender.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forwarded_sender() {
+        let gmail = "See attached\n\n---------- Forwarded message ---------\nFrom: Hetzner Online GmbH <billing@hetzner.com>\nDate: Mon, 3 Mar 2025\nSubject: Invoice";
+        assert_eq!(forwarded_sender(gmail).as_deref(), Some("Hetzner Online GmbH <billing@hetzner.com>"));
+
+        let outlook = "-----Original Message-----\n*From:* Wise <noreply@wise.com>\n*Sent:* Tuesday";
+        assert_eq!(forwarded_sender(outlook).as_deref(), Some("Wise <noreply@wise.com>"));
+
+        let apple = "Begin forwarded message:\n\n> From: \"Acme Billing\" <billing@acme.example>";
+        assert_eq!(forwarded_sender(apple).as_deref(), Some("\"Acme Billing\" <billing@acme.example>"));
+
+        // Sent straight to the alias, or a reply quoting someone: the message's own sender stays
+        assert_eq!(forwarded_sender("Invoice attached.\nFrom: someone"), None);
+    }
+}
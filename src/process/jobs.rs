@@ -1,166 +1,1373 @@
-use crate::auth;
-use crate::config::env::Config;
-use crate::drive;
-use crate::gmail;
-use anyhow::Result;
-use chrono::{Datelike, NaiveDate};
-use std::collections::HashMap;
+use crate::config::env::{Config, DocumentDateCheck, DuplicateAction, StorageKind};
+use crate::config::shared;
+use crate::db;
+use crate::extract;
+use crate::hooks;
+use crate::mail::{self, MailFolder, MailMessage, MailSource};
+use crate::mail::attachment::InvoiceAttachmentWithBank;
+use crate::notify;
+use crate::mail::vendors::VendorAliases;
+use crate::plugins::{PluginInput, Plugins};
+use crate::process::{artifact, budget, compress, encrypt, failures, fiscal, rules, sanitize, scan, telemetry, trends, workdir};
+use crate::process::estimate::{self, UploadEstimate};
+use crate::process::rules::Rules;
+use crate::extract::statement::StatementFormats;
+use crate::process::feedback::{Correction, Feedback, ProcessedFile};
+use crate::process::heartbeat::Heartbeat;
+use crate::process::outcome::{Failure, FailureKind, RunSummary, Stage, Step};
+use crate::storage::{self, Storage};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use chrono_tz::Tz;
+use futures_util::stream::{self, StreamExt};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
 use tokio::sync::mpsc;
 
 pub async fn run_manual_processing(
+    config: Config,
     start_date: NaiveDate,
     end_date: NaiveDate,
     tx: &mpsc::UnboundedSender<String>,
-) -> Result<()> {
-    tx.send("Loading configuration...".to_string())?;
-    let config = Config::from_env()?;
+) -> Result<RunSummary> {
+    let started_at = Utc::now();
+    let heartbeat = Heartbeat::start(&config, "run").await;
+    let (relay, forwarder) = heartbeat.relay(tx);
+    let (result, api) = telemetry::track(&config, connect_and_process(&config, start_date, end_date, &relay)).await;
+    drop(relay);
+    let _ = forwarder.await;
+    heartbeat.finish().await;
+    hooks::run_finished(&config, &result, Some(tx)).await;
+    record_run(&config, start_date, end_date, started_at, &result, &api, None).await;
+    result
+}
 
-    tx.send("Authenticating with Gmail...".to_string())?;
+/// Add a finished run to the run history when the database is available, and write its JSON
+/// record to `runs/` either way (mock runs are not recorded). `scheduled_attempt` is the try number of a run started by the `scheduled` command.
+/// `api` is what a run that stopped had requested by then (the record alone keeps it).
+pub async fn record_run(
+    config: &Config,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    started_at: DateTime<Utc>,
+    result: &Result<RunSummary>,
+    api: &telemetry::ApiUsage,
+    scheduled_attempt: Option<u32>,
+) {
+    if config.mock_mode {
+        return;
+    }
 
-    let gmail_token = auth::gmail_auth::get_gmail_token(
-        config.gmail_client_id.clone(),
-        config.gmail_client_secret.clone(),
-    )
-    .await?;
-    let gmail_client = gmail::client::GmailClient::new(gmail_token);
+    let stopped = RunSummary { api: api.clone(), ..RunSummary::default() };
+    let summary = result.as_ref().unwrap_or(&stopped);
+    let run = db::RunRecord {
+        profile: config.profile.clone().unwrap_or_default(),
+        start_date,
+        end_date,
+        started_at,
+        finished_at: Utc::now(),
+        processed: summary.processed as i32,
+        uploaded: summary.uploaded as i32,
+        skipped: summary.skipped as i32,
+        failed: summary.failed as i32,
+        billing_month: summary.billing_month.clone(),
+        folder: summary.folder.clone(),
+        error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        scheduled: scheduled_attempt.is_some(),
+        attempt: scheduled_attempt.unwrap_or(1) as i32,
+    };
+    artifact::save(config, &run, summary).await;
 
-    tx.send("Authenticating with Google Drive...".to_string())?;
+    let Some(pool) = db::connect_optional("Run history").await else {
+        return;
+    };
+    if let Err(e) = db::save_run(&pool, &run).await {
+        log::warn!("{:#}", e);
+    }
+}
 
-    let drive_token = auth::drive_auth::get_drive_token(
-        config.drive_client_id.clone(),
-        config.drive_client_secret.clone(),
-    )
-    .await?;
-    let drive_client = drive::client::DriveClient::new(drive_token);
+async fn connect_and_process(
+    config: &Config,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<RunSummary> {
+    let (source, storage) = connect(config, tx).await?;
+    process_invoices(config, source.as_ref(), storage.as_ref(), start_date, end_date, tx).await
+}
 
-    tx.send(format!("🔍 Searching Gmail for invoices and bank statements from {} to {}...", start_date, end_date))?;
+/// Authenticate with the mail source and the storage (fixtures in mock mode)
+pub async fn connect(config: &Config, tx: &mpsc::UnboundedSender<String>) -> Result<(Box<dyn MailSource>, Box<dyn Storage>)> {
+    Ok(if config.mock_mode {
+        tx.send(format!("🧪 Mock mode: replaying fixtures from {}", config.mock_fixtures_dir.display()))?;
+        (mail::connect(config).await?, storage::connect(config).await?)
+    } else {
+        tx.send("Authenticating with Gmail...".to_string())?;
+
+        let source = mail::connect(config).await?;
+
+        tx.send(match config.storage_backend {
+            StorageKind::Drive => "Authenticating with Google Drive...".to_string(),
+            StorageKind::Sftp => "Connecting to the SFTP server...".to_string(),
+            StorageKind::Gcs => "Authenticating with Google Cloud Storage...".to_string(),
+            StorageKind::Local => "Opening the local archive folder...".to_string(),
+        })?;
+        if let Some(mirror) = config.storage_mirror {
+            tx.send(format!("Connecting to the mirror ({})...", mirror.label()))?;
+        }
+
+        (source, storage::connect(config).await?)
+    })
+}
 
-    let message_ids = gmail::search::search_invoices(&gmail_client, start_date, end_date, &config.target_keywords).await?;
+/// Search the mail source, download attachments and upload them to Drive
+pub async fn process_invoices(
+    config: &Config,
+    source: &dyn MailSource,
+    storage: &dyn Storage,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<RunSummary> {
+    tx.send(format!("🔍 Searching {} for invoices and bank statements from {} to {}...", source.name(), start_date, end_date))?;
+    if let Some(user) = &config.gmail_user {
+        tx.send(format!("  Mailbox: {}", user))?;
+    }
+
+    let estimate = mail::search::estimate_keywords(source, start_date, end_date, &config.target_keywords, &config.search_exclusions(), config.search_concurrency).await;
+    if let Some(line) = estimate.summary() {
+        tx.send(line)?;
+    }
+    if let Some(warning) = estimate.warning(config.search_warn_messages, config.message_limit) {
+        tx.send(warning)?;
+    }
+
+    let search = mail::search::search_keywords(source, start_date, end_date, &config.target_keywords, &config.search_exclusions(), config.search_concurrency, config.message_limit).await?;
+    for line in search.keyword_report() {
+        tx.send(line)?;
+    }
+    let message_ids = search.message_ids;
 
     if message_ids.is_empty() {
         tx.send("No invoices found in the specified date range".to_string())?;
-        return Ok(());
+        return Ok(RunSummary::default());
     }
 
     tx.send(format!("✓ Found {} unique message(s) with potential invoices", message_ids.len()))?;
+
+    let mut messages = Vec::new();
+    let mut fetch_failures = Vec::new();
+    let mut outside_range = 0;
+    for message_id in &message_ids {
+        match source.fetch_message(message_id).await {
+            Ok(message) if !message.received_within(start_date, end_date, config.mailbox_timezone) => outside_range += 1,
+            Ok(mut message) => {
+                for name in mail::attachment::retain_matching_names(&mut message, &config.attachment_name_patterns) {
+                    tx.send(format!("      ⊘ Ignoring {}: name doesn't match ATTACHMENT_NAME_PATTERNS", name))?;
+                }
+                messages.push(message);
+            }
+            Err(e) => {
+                tx.send(format!("      ✗ Failed to fetch message {}: {}", message_id, e))?;
+                fetch_failures.push((message_id, e));
+            }
+        }
+    }
+
+    if outside_range > 0 {
+        tx.send(format!("  ℹ {} message(s) arrived outside the date range in the mailbox timezone and were ignored", outside_range))?;
+    }
+    let drafts = mail::search::drop_drafts_and_chats(&mut messages);
+    if drafts > 0 {
+        tx.send(format!("  ℹ {} draft or chat message(s) ignored", drafts))?;
+    }
+    let left_out = mail::search::cap_messages(&mut messages, config.message_limit, config.newest_first);
+    if left_out > 0 {
+        tx.send(format!("  ℹ {} message(s) left out by the run's limit", left_out))?;
+    }
+
+    let mut summary = archive_messages(config, source, storage, &messages, start_date, end_date, tx).await?;
+    for (message_id, error) in fetch_failures {
+        summary.fail(Stage::Fetch, Some(message_id), None, &error);
+    }
+    failures::save_report(config, start_date, end_date, &summary, Some(tx));
+    Ok(summary)
+}
+
+/// Download the attachments of fetched messages and upload them into the monthly folder.
+/// Shared by the TUI/batch pipeline above and the CLI, which asks for confirmation first.
+pub async fn archive_messages(
+    config: &Config,
+    source: &dyn MailSource,
+    storage: &dyn Storage,
+    messages: &[MailMessage],
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<RunSummary> {
+    config.ensure_writable("Archiving").context(FailureKind::Config)?;
+    let mut summary = RunSummary::default();
+
+    // A base folder shared view-only would fail every upload; find out before downloading anything
+    let base_folder = fiscal::fixed_root(&config.drive_folder_path);
+    if !base_folder.is_empty() {
+        storage.check_writable(&base_folder).await?;
+    }
+
+    let mut vendors = VendorAliases::load(&config.vendor_aliases, config.profile.as_deref()).context(FailureKind::Config)?;
+    let senders: Vec<_> = messages.iter().map(|message| mail::attachment::message_sender(message, &mut vendors)).collect();
+    let rules = Rules::load(config.rules_file.as_deref()).context(FailureKind::Config)?;
+    let plugins = Plugins::load(&config.wasm_plugins).context(FailureKind::Config)?;
+    let notifications = notify::Router::load(config.notifications_file.as_deref()).context(FailureKind::Config)?;
+    let statement_formats = StatementFormats::load(config.statement_formats_file.as_deref()).context(FailureKind::Config)?;
+    let mut feedback = Feedback::load(config).context(FailureKind::Config)?;
+
+    // Invoice metadata is recorded when a database is available (never in mock mode)
+    let pool = if config.mock_mode { None } else { db::connect_optional("Invoice metadata").await };
+    if pool.is_none() && !config.mock_mode && shared::is_shared() {
+        return Err(anyhow::anyhow!(
+            "{} points to a shared config directory, so runs need the database (DATABASE_URL) that holds the shared invoice records and run history",
+            shared::CONFIG_DIR_VAR
+        ))
+        .context(FailureKind::Config);
+    }
+
+    // The estimate and the billing months come from the message metadata, so uploads can start
+    // while later messages are still downloading
+    let files = messages.iter().map(|message| message.attachments.len()).sum();
+    if files == 0 {
+        tx.send("No attachments found in messages".to_string())?;
+        return Ok(summary);
+    }
+    let bytes = messages.iter().flat_map(|message| &message.attachments).filter_map(|attachment| attachment.size).sum();
+    let upload_estimate = UploadEstimate::new(config, files, bytes);
+    tx.send(upload_estimate.summary())?;
+    if !upload_estimate.confirm(config, tx)? {
+        anyhow::bail!("Aborted - nothing was uploaded");
+    }
+
+    let run_month = range_billing_month(start_date, end_date);
+    let months: BTreeSet<NaiveDate> = messages
+        .iter()
+        .filter(|message| !message.attachments.is_empty())
+        .map(|message| attachment_billing_month(Some(message), start_date, end_date, config.mailbox_timezone))
+        .collect();
+    let months = if months.is_empty() { BTreeSet::from([run_month]) } else { months };
+    let billing_month = months.iter().map(|month| month_name(*month)).collect::<Vec<_>>().join(" + ");
+    tx.send(format!("Billing month detected: {}", billing_month))?;
+
     tx.send("⬇️ Downloading attachments...".to_string())?;
+    if !plugins.is_empty() {
+        tx.send("🧩 Running routing plugins...".to_string())?;
+    }
+
+    // Three stages run side by side: each message's attachments are checked, named and filed as
+    // soon as it is downloaded and go on as one batch per folder; batches are prepared (saved,
+    // scanned, compressed, transformed, encrypted) and uploaded from this run's working directory
+    // while later messages download. At most UPLOAD_QUEUE batches wait between two stages.
+    let run_dir = workdir::RunDir::create(config)?;
+    let mut month_counts: BTreeMap<NaiveDate, (usize, usize, usize)> = BTreeMap::new();
+    let recipients = encrypt::parse_recipients(&config.encryption_recipients).context(FailureKind::Config)?;
+    let mut run_documents = Vec::new();
+    let mut processed_files = Vec::new();
+    let (mut uploaded_bytes, mut upload_time) = (0, std::time::Duration::ZERO);
+    // Skips and failures before the upload stage, counted apart as both stages run at once
+    let mut filtered = RunSummary::default();
+    let (batch_tx, mut batch_rx) = mpsc::channel::<Batch>(config.upload_queue.max(1));
+    let (ready_tx, mut ready_rx) = mpsc::channel::<PreparedGroup>(config.upload_queue.max(1));
+
+    let feed = async {
+        // Moved in, so the prepare stage sees the queue close once every message is downloaded
+        let batch_tx = batch_tx;
+        let mut seen_invoices = HashMap::new();
+        let mut windows_names = sanitize::TakenNames::default();
+        let mut upload_names = UploadNames::default();
 
-    let mut all_attachments = Vec::new();
-    for (idx, message_id) in message_ids.iter().enumerate() {
-        tx.send(format!("  Processing message {}/{}", idx + 1, message_ids.len()))?;
+        // Up to DOWNLOAD_CONCURRENCY messages are downloaded at once, their results taken in order
+        let mut downloads = stream::iter(0..messages.len())
+            .map(|idx| {
+                let (message, sender) = (&messages[idx], &senders[idx]);
+                async move { (message, mail::attachment::download_message_attachments(source, message, sender).await) }
+            })
+            .buffered(config.download_concurrency.max(1));
+        let (mut downloaded, mut idx) = (0, 0);
+        while let Some((message, result)) = downloads.next().await {
+            idx += 1;
+            tx.send(format!("  Processing message {}/{}", idx, messages.len()))?;
+            tx.send(Step::Message(idx as u64, messages.len() as u64).marker())?;
 
-        match gmail::attachment::get_message_attachments(&gmail_client, message_id).await {
-            Ok(attachments) => {
-                if attachments.is_empty() {
-                    tx.send("      ⚠ No attachments in this message".to_string())?;
+            let mut attachments = match result {
+                Ok(attachments) => attachments,
+                Err(e) => {
+                    filtered.fail(Stage::Download, Some(&message.id), None, &e);
+                    tx.send(format!("      ✗ Failed to process message: {}", e))?;
+                    continue;
+                }
+            };
+            if attachments.is_empty() {
+                tx.send("      ⚠ No attachments in this message".to_string())?;
+                continue;
+            }
+            // Spam and trash are only searched on request, and what comes from there deserves a look
+            let found_in = match message.folder {
+                MailFolder::Spam | MailFolder::Trash => format!(" ⚠ found in {}", message.folder.label()),
+                _ => String::new(),
+            };
+            for attachment in &attachments {
+                if let Some(ref bank) = attachment.bank_name {
+                    tx.send(format!("      ✓ {}: {} (🏦 {}){}", attachment.attachment.filename.len(), attachment.attachment.filename, bank, found_in))?;
                 } else {
-                    for attachment in &attachments {
-                        if let Some(ref bank) = attachment.bank_name {
-                            tx.send(format!("      ✓ {}: {} (🏦 {})", attachment.attachment.filename.len(), attachment.attachment.filename, bank))?;
-                        } else {
-                            tx.send(format!("      ✓ {}: {} (📄 General)", attachment.attachment.filename.len(), attachment.attachment.filename))?;
+                    tx.send(format!("      ✓ {}: {} (📄 General){}", attachment.attachment.filename.len(), attachment.attachment.filename, found_in))?;
+                }
+            }
+            downloaded += attachments.len();
+
+            if config.decrypt_attachments {
+                attachments = decrypt_attachments(config, attachments, &mut filtered, tx).await?;
+            }
+            if !rules.is_empty() {
+                attachments = apply_rules(config, &rules, messages, attachments, &mut filtered, tx).await?;
+            }
+            if !plugins.is_empty() {
+                attachments = route_with_plugins(&plugins, messages, attachments, &mut filtered, tx)?;
+            }
+            attachments = apply_corrections(&feedback, messages, attachments, &mut filtered, tx)?;
+            if config.phishing_checks {
+                flag_suspicious(config, std::slice::from_ref(message), &mut attachments, tx)?;
+            }
+
+            if config.document_date_check != DocumentDateCheck::Off
+                || config.semantic_duplicates_enabled()
+                || config.credit_notes_subfolder
+                || pool.is_some()
+            {
+                extract_document_texts(&mut attachments).await;
+            }
+            if config.document_date_check != DocumentDateCheck::Off {
+                attachments = check_document_dates(config, attachments, start_date, end_date, &mut filtered, tx)?;
+            }
+            if config.semantic_duplicates_enabled() {
+                attachments = check_semantic_duplicates(config, pool.as_ref(), &mut seen_invoices, attachments, &mut filtered, tx).await?;
+            }
+            if config.credit_notes_subfolder {
+                file_credit_notes(&mut attachments, tx)?;
+            }
+            if config.filename_sanitize != sanitize::Strictness::Off {
+                sanitize_names(config.filename_sanitize, &mut windows_names, &mut attachments, tx)?;
+            }
+            // Templates and corrections may have made names longer than filesystems take
+            for attachment in attachments.iter_mut() {
+                attachment.attachment.filename = mail::attachment::fit_filename(&attachment.attachment.filename, &attachment.attachment.data);
+            }
+
+            // Billing month of the message, one batch per bank folder
+            let month = attachment_billing_month(Some(message), start_date, end_date, config.mailbox_timezone);
+            let mut groups: BTreeMap<(NaiveDate, Option<String>), Vec<InvoiceAttachmentWithBank>> = BTreeMap::new();
+            for attachment in attachments {
+                groups.entry((month, attachment.bank_name.clone())).or_default().push(attachment);
+            }
+            claim_upload_names(&mut upload_names, &mut groups, tx)?;
+            for ((month, bank_name), attachments) in groups {
+                // The prepare stage stopped on an error, which try_join reports
+                if batch_tx.send(Batch { month, bank_name, attachments }).await.is_err() {
+                    return anyhow::Ok(downloaded);
+                }
+            }
+        }
+
+        if downloaded > 0 {
+            tx.send(format!("Downloaded {} attachment(s)", downloaded))?;
+            tx.send(Step::Downloaded(downloaded as u64).marker())?;
+        }
+        anyhow::Ok(downloaded)
+    };
+
+    let prepare = async {
+        // Moved in, so the upload stage sees the queue close once every batch is prepared
+        let ready_tx = ready_tx;
+        let mut month_folders = BTreeSet::new();
+        let (mut compressed_files, mut bytes_saved) = (0, 0);
+        while let Some(Batch { month, bank_name, attachments }) = batch_rx.recv().await {
+            let bank_display_name = bank_name.as_deref().unwrap_or("General");
+            let monthly_folder_path = monthly_folder(config, month);
+            if month_folders.insert(month) {
+                storage.month_folder(&monthly_folder_path, month, None).await?;
+            }
+            if months.len() > 1 {
+                tx.send(format!("  🏦 Processing bank: {} ({})", bank_display_name, month_name(month)))?;
+            } else {
+                tx.send(format!("  🏦 Processing bank: {}", bank_display_name))?;
+            }
+
+            // Create bank-specific folder
+            let folder_path = if let Some(ref bank) = bank_name {
+                format!("{}/{}", monthly_folder_path, bank)
+            } else {
+                monthly_folder_path.clone()
+            };
+            let folder_id = storage.month_folder(&folder_path, month, bank_name.as_deref()).await?;
+
+            // Up to TRANSFORM_CONCURRENCY files of the group are prepared at once
+            let prepared: Vec<_> = stream::iter(attachments)
+                .map(|attachment| async {
+                    let sender = messages
+                        .iter()
+                        .find(|m| m.id == attachment.attachment.message_id)
+                        .map(|m| m.from.as_str())
+                        .unwrap_or_default();
+                    let prepared = prepare_file(config, &attachment, sender, &run_dir.path, bank_display_name, &recipients, tx).await;
+                    (attachment, sender, prepared)
+                })
+                .buffered(config.transform_concurrency.max(1))
+                .collect()
+                .await;
+
+            let mut group = PreparedGroup { month, folder_path, folder_id, file_paths: Vec::new(), sources: HashMap::new(), failures: Vec::new(), quarantined: 0, bank_name };
+            for (attachment, sender, prepared) in prepared {
+                match prepared? {
+                    Prepared::Ready { path, saved } => {
+                        if let Some(saved) = saved {
+                            compressed_files += 1;
+                            bytes_saved += saved;
+                        }
+                        let pending = workdir::PendingFile { path: path.clone(), folder: group.folder_path.clone(), month, bank: group.bank_name.clone() };
+                        if let Err(e) = run_dir.record(&pending) {
+                            log::warn!("Could not note {} in the run's manifest: {:#}", path.display(), e);
                         }
+                        group.sources.insert(path.clone(), (sender, attachment));
+                        group.file_paths.push(path);
+                    }
+                    Prepared::Dropped { error, quarantined } => {
+                        group.failures.push(Failure::new(Stage::Prepare, Some(&attachment.attachment.message_id), Some(&attachment.attachment.filename), &error));
+                        group.quarantined += usize::from(quarantined);
                     }
                 }
-                all_attachments.extend(attachments);
             }
-            Err(e) => {
-                tx.send(format!("      ✗ Failed to process message: {}", e))?;
+
+            // The upload stage stopped on an error, which try_join reports
+            if ready_tx.send(group).await.is_err() {
+                break;
             }
         }
+        anyhow::Ok((compressed_files, bytes_saved))
+    };
+
+    let upload = async {
+        let mut started = false;
+        while let Some(group) = ready_rx.recv().await {
+            if !started {
+                started = true;
+                tx.send(format!("⬆️ Uploading to {}...", storage.name()))?;
+                tx.send(Step::UploadStarted(files as u64).marker())?;
+            }
+            let PreparedGroup { month, bank_name, folder_path: bank_folder_path, folder_id: bank_folder_id, file_paths, sources, failures, quarantined } = group;
+            let bank_display_name = bank_name.as_deref().unwrap_or("General");
+            let before = (summary.uploaded, summary.skipped, summary.failed);
+            summary.failed += failures.len();
+            summary.failures.extend(failures);
+            summary.quarantined += quarantined;
+
+            // Upload files to bank-specific folder
+            let upload_started = std::time::Instant::now();
+            let uploads = storage.upload_files(&file_paths, &bank_folder_id, Some(tx)).await?;
+            upload_time += upload_started.elapsed();
+            uploaded_bytes += uploads.uploaded_files.iter().filter_map(|(path, _)| std::fs::metadata(path).ok()).map(|metadata| metadata.len()).sum::<u64>();
+            summary.uploaded += uploads.uploaded;
+            summary.skipped += uploads.skipped;
+            summary.failed += uploads.failed;
+            for (path, error) in &uploads.failed_files {
+                let attachment = sources.get(path).map(|(_, attachment)| &attachment.attachment);
+                let name = attachment.map_or_else(|| path.display().to_string(), |attachment| attachment.filename.clone());
+                summary.failures.push(Failure::new(Stage::Upload, attachment.map(|attachment| attachment.message_id.as_str()), Some(&name), error));
+            }
+
+            for (path, uploaded) in &uploads.uploaded_files {
+                hooks::file_uploaded(config, path, uploaded, &bank_folder_path, bank_display_name, Some(tx)).await;
+                let Some((sender, attachment)) = sources.get(path) else {
+                    continue;
+                };
+                match messages.iter().find(|m| m.id == attachment.attachment.message_id).map(|m| m.folder) {
+                    Some(MailFolder::Spam) => summary.from_spam += 1,
+                    Some(MailFolder::Trash) => summary.from_trash += 1,
+                    _ => {}
+                }
+                processed_files.push(ProcessedFile {
+                    file_id: uploaded.id.clone(),
+                    filename: uploaded.name.clone(),
+                    sender: sender.to_string(),
+                    folder: bank_folder_path.clone(),
+                    uploaded_at: chrono::Utc::now(),
+                });
+
+                // Recorded for the archive and for the budget check
+                if pool.is_some() || !config.budgets.is_empty() {
+                    let fields = attachment.text.as_deref().map(extract::invoice::invoice_fields).unwrap_or_default();
+                    // Fingerprint of the file as uploaded, for `audit --verify`
+                    let data = std::fs::read(path).ok();
+                    let document = db::InvoiceDocument {
+                        profile: config.profile.clone().unwrap_or_default(),
+                        vendor: attachment.vendor.clone(),
+                        invoice_number: fields.number,
+                        amount_cents: fields.amount_cents,
+                        currency: fields.currency,
+                        filename: uploaded.name.clone(),
+                        folder: bank_folder_path.clone(),
+                        file_id: uploaded.id.clone(),
+                        billing_month: Some(month),
+                        web_link: Some(uploaded.link()),
+                        content: attachment.text.clone(),
+                        snippet: None,
+                        rule_category: attachment.category.clone(),
+                        message_id: Some(attachment.attachment.message_id.clone()),
+                        size_bytes: data.as_ref().map(|data| data.len() as i64),
+                        sha256: data.as_deref().map(crate::drive::upload::sha256_hex),
+                        due_date: fields.due_date,
+                        paid: false,
+                    };
+                    if let Some(pool) = &pool
+                        && let Err(e) = db::save_invoice_document(pool, &document).await
+                    {
+                        tx.send(format!("    ⚠ Could not record {} in the invoice database: {:#}", uploaded.name, e))?;
+                    }
+                    run_documents.push(document);
+                }
+
+                // Transactions of CSV and CAMT.053 statements, for `reconcile --month`
+                // (statements of a detected bank only: XML e-invoices aren't statements)
+                if let Some(pool) = &pool
+                    && attachment.bank_name.is_some()
+                    && extract::statement::is_statement_export(&attachment.attachment.filename, &attachment.attachment.data)
+                {
+                    let mapping = statement_formats.for_bank(bank_display_name);
+                    let recorded = match extract::statement::parse_statement(&attachment.attachment.filename, &attachment.attachment.data, mapping) {
+                        Ok(transactions) => db::save_transactions(pool, &config.profile.clone().unwrap_or_default(), bank_display_name, &uploaded.id, &transactions).await,
+                        Err(e) => Err(e),
+                    };
+                    match recorded {
+                        Ok(count) => tx.send(format!("    📊 {}: {} new transaction(s) recorded", uploaded.name, count))?,
+                        Err(e) => tx.send(format!("    ⚠ Could not read transactions from {}: {:#}", uploaded.name, e))?,
+                    }
+                }
+            }
+
+            tx.send(format!("    ✓ {}: Files uploaded", bank_display_name))?;
+
+            let counts = month_counts.entry(month).or_default();
+            counts.0 += summary.uploaded - before.0;
+            counts.1 += summary.skipped - before.1;
+            counts.2 += summary.failed - before.2;
+        }
+        anyhow::Ok(())
+    };
+
+    let (downloaded, (compressed_files, bytes_saved), ()) = tokio::try_join!(feed, prepare, upload)?;
+    summary.skipped += filtered.skipped;
+    summary.failed += filtered.failed;
+    summary.failures.splice(0..0, filtered.failures);
+    for processed in processed_files {
+        feedback.record_upload(processed);
+    }
+
+    if vendors.newly_learned() > 0 {
+        tx.send(format!("🏷 Learned {} new vendor alias domain(s)", vendors.newly_learned()))?;
+        if let Err(e) = vendors.save() {
+            tx.send(format!("  ⚠ Could not save learned vendor aliases: {:#}", e))?;
+        }
     }
 
-    if all_attachments.is_empty() {
+    if downloaded == 0 {
         tx.send("No attachments found in messages".to_string())?;
-        return Ok(());
+        if let Err(e) = run_dir.finish() {
+            tx.send(format!("Failed to remove temp files: {:#}", e))?;
+        }
+        return Ok(summary);
     }
 
-    tx.send(format!("Downloaded {} attachment(s)", all_attachments.len()))?;
-    tx.send("Preparing upload...".to_string())?;
+    if months.len() > 1 {
+        tx.send("📆 Per-month results:".to_string())?;
+        for (month, (uploaded, skipped, failed)) in &month_counts {
+            tx.send(format!("  {}: {} uploaded, {} skipped, {} failed", month_name(*month), uploaded, skipped, failed))?;
+        }
+    }
 
-    // Determine billing month
-    let billing_month = determine_billing_month(start_date, end_date);
-    tx.send(format!("Billing month detected: {}", billing_month))?;
+    if config.include_spam || config.include_trash {
+        tx.send(format!("📂 Uploaded by folder: {}", summary.folder_counts()))?;
+        if summary.from_spam + summary.from_trash > 0 {
+            tx.send(format!("⚠ {} file(s) came from spam or trash; check they are genuine", summary.from_spam + summary.from_trash))?;
+        }
+    }
 
-    let monthly_folder_path = format!("{}/{}", config.drive_folder_path, billing_month);
-    let _monthly_folder_id = drive::folder::find_or_create_folder(&drive_client, &monthly_folder_path).await?;
+    if let Some(mirror) = storage.take_mirror_summary() {
+        tx.send(format!("🪞 Mirror ({}): {} copied, {} skipped, {} failed", mirror.name, mirror.uploaded, mirror.skipped, mirror.failed))?;
+        summary.mirror = Some(mirror);
+    }
 
-    // Group attachments by bank name
-    let mut bank_groups: HashMap<Option<String>, Vec<gmail::attachment::InvoiceAttachmentWithBank>> = HashMap::new();
-    for attachment in &all_attachments {
-        bank_groups.entry(attachment.bank_name.clone()).or_insert_with(Vec::new).push(attachment.clone());
+    let usage = telemetry::snapshot();
+    if !usage.is_empty() {
+        tx.send(format!("📶 API usage: {}", telemetry::describe(&usage)))?;
     }
 
-    tx.send("⬆️ Uploading to Google Drive...".to_string())?;
+    if let Err(e) = feedback.save() {
+        tx.send(format!("⚠ Could not record processed files for corrections: {:#}", e))?;
+    }
+    if let Err(e) = estimate::record_throughput(config, uploaded_bytes, upload_time) {
+        log::warn!("{:#}", e);
+    }
 
-    // Upload files to bank-specific folders
-    for (bank_name, attachments) in bank_groups {
-        let bank_display_name = bank_name.as_deref().unwrap_or("General");
-        tx.send(format!("  🏦 Processing bank: {}", bank_display_name))?;
+    match budget::check_run(&config.budgets, &config.drive_folder_path, pool.as_ref(), &run_documents).await {
+        Ok(alerts) => {
+            for alert in alerts {
+                tx.send(format!("💸 Over budget: {}", alert))?;
+                hooks::budget_exceeded(config, &alert, Some(tx)).await;
+                summary.budget_alerts.push(alert.to_string());
+            }
+        }
+        Err(e) => tx.send(format!("⚠ Could not check budgets: {:#}", e))?,
+    }
 
-        // Create bank-specific folder
-        let bank_folder_path = if let Some(ref bank) = bank_name {
-            format!("{}/{}", monthly_folder_path, bank)
-        } else {
-            monthly_folder_path.clone()
-        };
+    if notifications.subscribed(notify::Event::LargeInvoice) {
+        for document in run_documents.iter().filter(|document| document.amount_cents.is_some()) {
+            notifications.send(config, &notify::Notification::large_invoice(config, document), Some(tx)).await;
+        }
+    }
+    // Months still under way would flag every vendor that hasn't billed yet
+    if notifications.subscribed(notify::Event::MissingVendor)
+        && let Some(pool) = &pool
+    {
+        let this_month = chrono::Local::now().date_naive().with_day(1).unwrap_or_default();
+        let profile = config.profile.clone().unwrap_or_default();
+        for month in months.iter().filter(|month| **month < this_month) {
+            match trends::TrendReport::load(pool, &profile, *month, trends::DEFAULT_WINDOW).await {
+                Ok(report) => {
+                    for trend in report.vendors.iter().filter(|trend| trend.anomaly == Some(trends::Anomaly::Missing)) {
+                        match notify::first_missing_notice(config, *month, &trend.vendor) {
+                            Ok(true) => notifications.send(config, &notify::Notification::missing_vendor(config, *month, trend), Some(tx)).await,
+                            Ok(false) => {}
+                            Err(e) => log::warn!("{:#}", e),
+                        }
+                    }
+                }
+                Err(e) => tx.send(format!("⚠ Could not check for missing vendors: {:#}", e))?,
+            }
+        }
+    }
+
+    if summary.quarantined > 0 {
+        tx.send(format!("☣ WARNING: {} infected attachment(s) quarantined in {} - NOT uploaded", summary.quarantined, config.quarantine_dir.display()))?;
+    }
+
+    if compressed_files > 0 {
+        tx.send(format!("🗜 PDF compression saved {} across {} file(s)", compress::format_bytes(bytes_saved), compressed_files))?;
+    }
+
+    // Cleanup temp files
+    tx.send("Cleaning up temporary files...".to_string())?;
 
-        let bank_folder_id = drive::folder::find_or_create_folder(&drive_client, &bank_folder_path).await?;
+    if let Err(e) = run_dir.finish() {
+        tx.send(format!("Failed to remove temp files: {:#}", e))?;
+    }
+
+    // Send completion summary
+    summary.processed = downloaded;
+    // A run spread over several months reports the parent folder holding them all
+    summary.folder = Some(match months.first() {
+        Some(month) if months.len() == 1 => monthly_folder(config, *month),
+        _ => fiscal::fixed_root(&config.drive_folder_path),
+    });
+    summary.billing_month = Some(billing_month);
+    // A link to jump straight to the documents; summaries go without it when it can't be had
+    if let Some(folder) = &summary.folder {
+        match storage.folder_link(folder).await {
+            Ok(link) => summary.folder_link = link,
+            Err(e) => tx.send(format!("⚠ Could not look up the link to {}: {:#}", folder, e))?,
+        }
+    }
+    tx.send(summary.results_marker())?;
+
+    tx.send("Processing completed successfully!".to_string())?;
+
+    Ok(summary)
+}
+
+/// The attachments of one message bound for one month's bank folder, waiting to be prepared
+struct Batch {
+    month: NaiveDate,
+    bank_name: Option<String>,
+    attachments: Vec<InvoiceAttachmentWithBank>,
+}
+
+/// A batch with its files ready in its folder, waiting in the upload queue
+struct PreparedGroup<'a> {
+    month: NaiveDate,
+    bank_name: Option<String>,
+    folder_path: String,
+    folder_id: String,
+    file_paths: Vec<PathBuf>,
+    /// Prepared file -> sender and the attachment it came from
+    sources: HashMap<PathBuf, (&'a str, InvoiceAttachmentWithBank)>,
+    /// Files dropped while preparing, already reported
+    failures: Vec<Failure>,
+    quarantined: usize,
+}
+
+enum Prepared {
+    /// Ready for upload at `path`; `saved` is what PDF compression saved, if it ran
+    Ready { path: PathBuf, saved: Option<u64> },
+    /// Not to be uploaded, reported on the progress channel; `quarantined` when infected
+    Dropped { error: anyhow::Error, quarantined: bool },
+}
 
-        // Save attachments to temp directory for this bank
-        let mut file_paths = Vec::new();
-        for attachment in &attachments {
-            match gmail::attachment::save_attachment_to_temp(&attachment.attachment) {
-                Ok(path) => {
-                    file_paths.push(path.clone());
+/// Save an attachment into the run's working directory and get it ready for upload: virus scan,
+/// PDF compression, the transform hook and encryption, in that order
+async fn prepare_file(
+    config: &Config,
+    attachment: &InvoiceAttachmentWithBank,
+    sender: &str,
+    temp_dir: &Path,
+    bank_display_name: &str,
+    recipients: &[age::x25519::Recipient],
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<Prepared> {
+    let filename = &attachment.attachment.filename;
+    let mut path = match mail::attachment::save_attachment_to_temp(&attachment.attachment, temp_dir) {
+        Ok(path) => path,
+        Err(e) => {
+            tx.send(format!("    ✗ Failed to save {}: {}", filename, e))?;
+            return Ok(Prepared::Dropped { error: e.context("Failed to save"), quarantined: false });
+        }
+    };
+    // Virus scan: flagged files are quarantined locally and never uploaded
+    match scan::scan_file(config, &path).await {
+        Ok(Some(scan::ScanVerdict::Infected(signature))) => {
+            match scan::quarantine(config, &path) {
+                Ok(target) => tx.send(format!("    ☣ INFECTED {} ({}) - quarantined to {}", filename, signature, target.display()))?,
+                Err(e) => tx.send(format!("    ☣ INFECTED {} ({}) - not uploaded, quarantine failed: {:#}", filename, signature, e))?,
+            }
+            return Ok(Prepared::Dropped { error: anyhow::anyhow!("Infected ({})", signature), quarantined: true });
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tx.send(format!("    ✗ Skipping {}: virus scan failed: {:#}", filename, e))?;
+            return Ok(Prepared::Dropped { error: e.context("Virus scan failed"), quarantined: false });
+        }
+    }
+
+    let mut saved = None;
+    match compress::compress_pdf(config, &path, sender).await {
+        Ok(Some(compressed)) => {
+            saved = Some(compressed.saved());
+            tx.send(format!("    🗜 {}: {} → {}", filename, compress::format_bytes(compressed.before), compress::format_bytes(compressed.after)))?;
+        }
+        Ok(None) => {}
+        Err(e) => tx.send(format!("    ⚠ Could not compress {} (uploading original): {:#}", filename, e))?,
+    }
+
+    // Optional external transform (OCR, compression, stamping, renaming...)
+    match hooks::transform_file(config, &path, bank_display_name).await {
+        Ok(transformed) => {
+            if transformed != path {
+                tx.send(format!("    ↻ Transformed {} → {}", filename, transformed.display()))?;
+            }
+            path = transformed;
+        }
+        Err(e) => {
+            tx.send(format!("    ✗ Skipping {}: {:#}", filename, e))?;
+            return Ok(Prepared::Dropped { error: e, quarantined: false });
+        }
+    }
+
+    // Client-side encryption: never upload the plaintext when recipients are configured
+    if !recipients.is_empty() {
+        match encrypt::encrypt_file(&path, recipients) {
+            Ok(encrypted) => path = encrypted,
+            Err(e) => {
+                tx.send(format!("    ✗ Skipping {}: encryption failed: {:#}", filename, e))?;
+                return Ok(Prepared::Dropped { error: e.context("Encryption failed"), quarantined: false });
+            }
+        }
+    }
+
+    Ok(Prepared::Ready { path, saved })
+}
+
+/// Replace PGP/S-MIME encrypted attachments with their readable content.
+/// Attachments that can't be decrypted are reported and left out of the upload.
+async fn decrypt_attachments(
+    config: &Config,
+    attachments: Vec<InvoiceAttachmentWithBank>,
+    summary: &mut RunSummary,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<Vec<InvoiceAttachmentWithBank>> {
+    let mut readable = Vec::new();
+    for mut attachment in attachments {
+        let filename = attachment.attachment.filename.clone();
+        match mail::decrypt::decrypt_attachment(config, &mut attachment.attachment).await {
+            Ok(Some(encryption)) => {
+                tx.send(format!("    🔓 {}: {} decrypted → {}", filename, encryption.name(), attachment.attachment.filename))?;
+                readable.push(attachment);
+            }
+            Ok(None) => readable.push(attachment),
+            Err(e) => {
+                summary.fail(Stage::Decrypt, Some(&attachment.attachment.message_id), Some(&filename), &e);
+                tx.send(format!("    ✗ 🔒 Could not decrypt {} (not uploaded): {:#}", filename, e))?;
+            }
+        }
+    }
+    Ok(readable)
+}
+
+/// Apply the sender rules from rules.toml: skip, unlock password-protected PDFs, merge each
+/// message's PDFs, then rename and re-file
+async fn apply_rules(
+    config: &Config,
+    rules: &Rules,
+    messages: &[MailMessage],
+    attachments: Vec<InvoiceAttachmentWithBank>,
+    summary: &mut RunSummary,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<Vec<InvoiceAttachmentWithBank>> {
+    let message_rule = |message_id: &str| {
+        let message = messages.iter().find(|m| m.id == message_id)?;
+        Some((message, rules.for_sender(&message.from)?))
+    };
+
+    let mut kept: Vec<InvoiceAttachmentWithBank> = Vec::new();
+    // Message id -> index in `kept` of its first PDF, and the PDFs to merge into it
+    let mut merges: HashMap<String, (usize, Vec<InvoiceAttachmentWithBank>)> = HashMap::new();
+    for mut attachment in attachments {
+        let Some((_, rule)) = message_rule(&attachment.attachment.message_id) else {
+            kept.push(attachment);
+            continue;
+        };
+        let filename = attachment.attachment.filename.clone();
+        if rule.skip {
+            summary.skipped += 1;
+            tx.send(format!("    ⊘ {}: skipped by rules ({})", filename, rule.sender))?;
+            continue;
+        }
+
+        let pdf = rules::is_pdf(&filename);
+        if let Some(password) = &rule.pdf_password
+            && pdf
+            && rules::is_encrypted_pdf(&attachment.attachment.data)
+        {
+            match rules::unlock_pdf(config, &attachment.attachment.data, password).await {
+                Ok(data) => {
+                    attachment.attachment.data = data;
+                    tx.send(format!("    🔓 {}: password removed", filename))?;
                 }
                 Err(e) => {
-                    tx.send(format!("    ✗ Failed to save {}: {}", attachment.attachment.filename, e))?;
+                    summary.fail(Stage::Rules, Some(&attachment.attachment.message_id), Some(&filename), &e);
+                    tx.send(format!("    ✗ 🔒 Could not unlock {} (not uploaded): {:#}", filename, e))?;
+                    continue;
                 }
             }
         }
 
-        // Upload files to bank-specific folder
-        drive::upload::upload_files(&drive_client, &file_paths, &bank_folder_id, Some(&tx)).await?;
+        if rule.merge && pdf {
+            if let Some((_, parts)) = merges.get_mut(&attachment.attachment.message_id) {
+                parts.push(attachment);
+                continue;
+            }
+            merges.insert(attachment.attachment.message_id.clone(), (kept.len(), Vec::new()));
+        }
+        kept.push(attachment);
+    }
 
-        tx.send(format!("    ✓ {}: Files uploaded", bank_display_name))?;
+    for (first, parts) in merges.into_values().filter(|(_, parts)| !parts.is_empty()) {
+        let documents: Vec<&[u8]> = std::iter::once(&kept[first]).chain(&parts).map(|a| a.attachment.data.as_slice()).collect();
+        match rules::merge_pdfs(config, &documents).await {
+            Ok(data) => {
+                tx.send(format!("    📎 {}: merged {} PDFs", kept[first].attachment.filename, parts.len() + 1))?;
+                kept[first].attachment.data = data;
+            }
+            Err(e) => {
+                tx.send(format!("    ⚠ Could not merge the PDFs of {} (kept separate): {:#}", kept[first].attachment.filename, e))?;
+                kept.extend(parts);
+            }
+        }
     }
 
-    // Cleanup temp files
-    tx.send("Cleaning up temporary files...".to_string())?;
+    for attachment in &mut kept {
+        let Some((message, rule)) = message_rule(&attachment.attachment.message_id) else {
+            continue;
+        };
+        if let Some(template) = &rule.filename {
+            // The amount is read from the document, before the checks that read it too
+            if template.contains("{amount}") && attachment.text.is_none() {
+                extract_document_texts(std::slice::from_mut(attachment)).await;
+            }
+            let fields = attachment.text.as_deref().map(extract::invoice::invoice_fields).unwrap_or_default();
+            let original = attachment.attachment.filename.strip_prefix(&format!("{}-", attachment.vendor)).unwrap_or(&attachment.attachment.filename);
+            let parts = rules::FilenameParts {
+                vendor: &attachment.vendor,
+                original,
+                received: message.received_date(config.mailbox_timezone),
+                subject: &message.subject,
+                amount_cents: fields.amount_cents,
+                currency: fields.currency.as_deref(),
+            };
+            attachment.attachment.filename = rules::render_filename(template, &parts);
+        }
+        if let Some(folder) = &rule.folder {
+            attachment.bank_name = Some(folder.clone());
+        }
+        if let Some(category) = &rule.category {
+            attachment.category = Some(category.clone());
+        }
+        if rule.filename.is_some() || rule.folder.is_some() {
+            tx.send(format!("    📐 {} → {}", attachment.attachment.filename, attachment.bank_name.as_deref().unwrap_or("General")))?;
+        }
+    }
+
+    Ok(kept)
+}
+
+/// Let the configured plugins skip, rename or re-file each attachment
+fn route_with_plugins(
+    plugins: &Plugins,
+    messages: &[MailMessage],
+    attachments: Vec<InvoiceAttachmentWithBank>,
+    summary: &mut RunSummary,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<Vec<InvoiceAttachmentWithBank>> {
+    let mut routed = Vec::new();
+    for mut attachment in attachments {
+        let Some(message) = messages.iter().find(|m| m.id == attachment.attachment.message_id) else {
+            routed.push(attachment);
+            continue;
+        };
 
-    for attachment in &all_attachments {
-        if let Ok(path) = gmail::attachment::save_attachment_to_temp(&attachment.attachment) {
-            if let Err(e) = std::fs::remove_file(&path) {
-                tx.send(format!("Failed to remove temp file {}: {}", path.display(), e))?;
+        let input = PluginInput {
+            from: &message.from,
+            subject: &message.subject,
+            filename: &attachment.attachment.filename,
+            institution: attachment.bank_name.as_deref(),
+            text: &message.body,
+        };
+
+        let decision = match plugins.route(&input) {
+            Ok(decision) => decision,
+            Err(e) => {
+                summary.fail(Stage::Plugin, Some(&attachment.attachment.message_id), Some(&attachment.attachment.filename), &e);
+                tx.send(format!("    ✗ Skipping {}: {:#}", attachment.attachment.filename, e))?;
+                continue;
             }
+        };
+
+        if decision.skip {
+            summary.skipped += 1;
+            tx.send(format!("    ⊘ {}: skipped by plugin", attachment.attachment.filename))?;
+            continue;
         }
+        if let Some(filename) = decision.target_filename() {
+            attachment.attachment.filename = filename;
+        }
+        if let Some(folder) = decision.target_folder() {
+            attachment.bank_name = Some(folder);
+        }
+        if decision != Default::default() {
+            tx.send(format!("    🧩 {} → {}", attachment.attachment.filename, attachment.bank_name.as_deref().unwrap_or("General")))?;
+        }
+
+        routed.push(attachment);
     }
 
-    // Send completion summary
-    tx.send(format!("__RESULTS__:processed={},month={},folder={}",
-        all_attachments.len(), billing_month, monthly_folder_path))?;
+    Ok(routed)
+}
 
-    tx.send("Processing completed successfully!".to_string())?;
+/// Extract the text of every document once, for the checks that read content
+async fn extract_document_texts(attachments: &mut [InvoiceAttachmentWithBank]) {
+    for attachment in attachments.iter_mut().filter(|a| a.text.is_none()) {
+        let filename = attachment.attachment.filename.clone();
+        let data = attachment.attachment.data.clone();
+        attachment.text = tokio::task::spawn_blocking(move || extract::document_text(&filename, &data))
+            .await
+            .unwrap_or_default();
+    }
+}
+
+/// Move credit notes and refunds into the `Credit Notes` subfolder of their institution folder
+/// (of the month folder for vendors filed there)
+fn file_credit_notes(attachments: &mut [InvoiceAttachmentWithBank], tx: &mpsc::UnboundedSender<String>) -> Result<()> {
+    for attachment in attachments.iter_mut() {
+        let fields = attachment.text.as_deref().map(extract::invoice::invoice_fields).unwrap_or_default();
+        if !fields.credit_note {
+            continue;
+        }
+        let folder = match &attachment.bank_name {
+            Some(bank) => format!("{}/{}", bank, extract::invoice::CREDIT_NOTES_FOLDER),
+            None => extract::invoice::CREDIT_NOTES_FOLDER.to_string(),
+        };
+        tx.send(format!("    ↩ Credit note: {} → {}", attachment.attachment.filename, folder))?;
+        attachment.bank_name = Some(folder);
+    }
+    Ok(())
+}
 
+/// Make file and folder names safe for a Windows sync client (FILENAME_SANITIZE). Names the
+/// cleaning makes collide in a folder get a counter; names that were already equal are left alone.
+/// `taken` holds the names of the run's earlier batches.
+fn sanitize_names(
+    strictness: sanitize::Strictness,
+    taken: &mut sanitize::TakenNames,
+    attachments: &mut [InvoiceAttachmentWithBank],
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<()> {
+    let mut renamed = Vec::new();
+    for (index, attachment) in attachments.iter_mut().enumerate() {
+        attachment.bank_name = attachment.bank_name.as_deref().map(|folder| sanitize::sanitize_folder(folder, strictness));
+        let name = sanitize::sanitize_name(&attachment.attachment.filename, strictness);
+        if name == attachment.attachment.filename {
+            taken.claim(attachment.bank_name.as_deref(), &name);
+        } else {
+            renamed.push((index, name));
+        }
+    }
+    for (index, name) in renamed {
+        let attachment = &mut attachments[index];
+        let name = taken.claim(attachment.bank_name.as_deref(), &name);
+        tx.send(format!("    ✎ Renamed for Windows: {} → {}", attachment.attachment.filename, name))?;
+        attachment.attachment.filename = name;
+    }
     Ok(())
 }
 
-/// Determine the billing month from the date range
-fn determine_billing_month(start_date: NaiveDate, end_date: NaiveDate) -> String {
-    let start_month = start_date.month();
-    let end_month = end_date.month();
+/// Upload names given so far in a run (see `claim_upload_names`)
+#[derive(Default)]
+struct UploadNames {
+    taken: sanitize::TakenNames,
+    /// (folder, lowercase name, content hash) → the name it was given
+    claimed: HashMap<(String, String, String), String>,
+}
+
+/// Give same-named attachments of different content bound for one folder names of their own
+/// ("invoice.pdf", "invoice-2.pdf"), so the duplicate check, which goes by name, uploads them
+/// all. The same file sent twice keeps its name and is skipped as before. `names` holds the
+/// names of the run's earlier batches.
+fn claim_upload_names(
+    names: &mut UploadNames,
+    groups: &mut BTreeMap<(NaiveDate, Option<String>), Vec<InvoiceAttachmentWithBank>>,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<()> {
+    for ((month, bank_name), attachments) in groups.iter_mut() {
+        let folder = format!("{}/{}", month, bank_name.as_deref().unwrap_or_default());
+        for attachment in attachments.iter_mut() {
+            let filename = &attachment.attachment.filename;
+            let sha = crate::drive::upload::sha256_hex(&attachment.attachment.data);
+            let key = (folder.clone(), filename.to_lowercase(), sha);
+            if let Some(name) = names.claimed.get(&key) {
+                attachment.attachment.filename = name.clone();
+                continue;
+            }
+            let name = names.taken.claim(Some(&folder), filename);
+            if name != *filename {
+                tx.send(format!("    ✎ Renamed {} → {} (another attachment has its name)", filename, name))?;
+            }
+            names.claimed.insert(key, name.clone());
+            attachment.attachment.filename = name;
+        }
+    }
+    Ok(())
+}
+
+/// Skip or flag invoices that were already archived under another file: same vendor, invoice
+/// number and amount, either earlier in this run (`seen`, kept across the run's batches) or in
+/// the invoice database
+async fn check_semantic_duplicates(
+    config: &Config,
+    pool: Option<&db::DbPool>,
+    seen: &mut HashMap<(String, String, i64), String>,
+    attachments: Vec<InvoiceAttachmentWithBank>,
+    summary: &mut RunSummary,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<Vec<InvoiceAttachmentWithBank>> {
+    let profile = config.profile.clone().unwrap_or_default();
+    let mut kept = Vec::new();
+    for attachment in attachments {
+        let action = config.duplicate_action(&attachment.vendor);
+        let fields = attachment.text.as_deref().map(extract::invoice::invoice_fields).unwrap_or_default();
+        let (Some(number), Some(amount)) = (fields.number.clone(), fields.amount_cents) else {
+            kept.push(attachment);
+            continue;
+        };
+        if action == DuplicateAction::Off {
+            kept.push(attachment);
+            continue;
+        }
+
+        let key = (attachment.vendor.clone(), number.clone(), amount);
+        let earlier = match seen.get(&key) {
+            Some(filename) => Some(format!("{} (this run)", filename)),
+            None => match pool {
+                Some(pool) => db::find_invoice_duplicate(pool, &profile, &attachment.vendor, &number, amount)
+                    .await
+                    .unwrap_or_else(|e| {
+                        let _ = tx.send(format!("    ⚠ Duplicate lookup failed for {}: {:#}", attachment.attachment.filename, e));
+                        None
+                    })
+                    .map(|(filename, folder)| format!("{}/{}", folder, filename)),
+                None => None,
+            },
+        };
+        seen.entry(key).or_insert_with(|| attachment.attachment.filename.clone());
+
+        let Some(earlier) = earlier else {
+            kept.push(attachment);
+            continue;
+        };
+        let described = format!(
+            "{}: invoice {} for {} {} already archived as {}",
+            attachment.attachment.filename,
+            number,
+            extract::invoice::format_amount(amount),
+            fields.currency.as_deref().unwrap_or(""),
+            earlier
+        );
+        if action == DuplicateAction::Skip {
+            summary.skipped += 1;
+            tx.send(format!("    ⧉ Skipping duplicate {}", described))?;
+        } else {
+            tx.send(format!("    ⧉ Possible duplicate {} (uploaded anyway)", described))?;
+            kept.push(attachment);
+        }
+    }
+    Ok(kept)
+}
+
+/// Report (and with DOCUMENT_DATE_CHECK=skip, drop) documents whose own date is far outside
+/// the requested range, e.g. a 2019 invoice attached to this month's email
+fn check_document_dates(
+    config: &Config,
+    attachments: Vec<InvoiceAttachmentWithBank>,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    summary: &mut RunSummary,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<Vec<InvoiceAttachmentWithBank>> {
+    let tolerance = chrono::Duration::days(config.document_date_tolerance_days);
+    let (earliest, latest) = (start_date - tolerance, end_date + tolerance);
+
+    let mut kept = Vec::new();
+    let mut outside = Vec::new();
+    for attachment in attachments {
+        // The text layer wins; fall back to a date in the filename
+        let date = attachment.text.as_deref().and_then(extract::dates::document_date)
+            .or_else(|| extract::dates::document_date(&attachment.attachment.filename));
+
+        match date {
+            Some(date) if date < earliest || date > latest => {
+                outside.push(format!("{} (dated {})", attachment.attachment.filename, date));
+                if config.document_date_check == DocumentDateCheck::Skip {
+                    summary.skipped += 1;
+                } else {
+                    kept.push(attachment);
+                }
+            }
+            _ => kept.push(attachment),
+        }
+    }
+
+    if !outside.is_empty() {
+        let action = if config.document_date_check == DocumentDateCheck::Skip { "skipped" } else { "uploaded anyway" };
+        tx.send(format!("📅 {} document(s) dated outside {} – {} ({}):", outside.len(), earliest, latest, action))?;
+        for line in outside {
+            tx.send(format!("    • {}", line))?;
+        }
+    }
+
+    Ok(kept)
+}
+
+/// Apply the user's earlier corrections: skip senders marked "not an invoice", re-file the rest
+fn apply_corrections(
+    feedback: &Feedback,
+    messages: &[MailMessage],
+    attachments: Vec<InvoiceAttachmentWithBank>,
+    summary: &mut RunSummary,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<Vec<InvoiceAttachmentWithBank>> {
+    let mut kept = Vec::new();
+    for mut attachment in attachments {
+        let from = messages
+            .iter()
+            .find(|m| m.id == attachment.attachment.message_id)
+            .map(|m| m.from.as_str())
+            .unwrap_or_default();
+        match feedback.correction_for(from) {
+            Some(Correction::NotInvoice) => {
+                summary.skipped += 1;
+                tx.send(format!("    ✎ Skipping {}: sender marked as not an invoice", attachment.attachment.filename))?;
+            }
+            Some(Correction::Folder(folder)) => {
+                tx.send(format!("    ✎ {} filed under {} (correction)", attachment.attachment.filename, folder))?;
+                attachment.bank_name = Some(folder.clone());
+                kept.push(attachment);
+            }
+            None => kept.push(attachment),
+        }
+    }
+    Ok(kept)
+}
+
+/// File attachments from messages that look spoofed under the review folder instead of the trusted archive
+fn flag_suspicious(
+    config: &Config,
+    messages: &[MailMessage],
+    attachments: &mut [InvoiceAttachmentWithBank],
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<()> {
+    for message in messages {
+        let reasons = mail::phishing::assess(message);
+        if reasons.is_empty() {
+            continue;
+        }
+
+        let mut flagged = attachments
+            .iter_mut()
+            .filter(|a| a.attachment.message_id == message.id)
+            .peekable();
+        if flagged.peek().is_none() {
+            continue;
+        }
+
+        tx.send(format!("    ⚠ Suspicious sender {}: {}", message.from, reasons.join("; ")))?;
+        for attachment in flagged {
+            tx.send(format!("      → {} filed under {}", attachment.attachment.filename, config.review_folder))?;
+            attachment.bank_name = Some(config.review_folder.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Determine the billing month from the date range (as the first day of that month)
+fn range_billing_month(start_date: NaiveDate, end_date: NaiveDate) -> NaiveDate {
+    let start_month = start_date.with_day(1).unwrap();
+    let end_month = end_date.with_day(1).unwrap();
 
     if start_month == end_month {
-        format!("{}", chrono::Month::try_from(end_month as u8).unwrap().name())
+        end_month
     } else {
-        let days_in_end_month = (end_date - NaiveDate::from_ymd_opt(end_date.year(), end_date.month(), 1).unwrap()).num_days() + 1;
+        let days_in_end_month = (end_date - end_month).num_days() + 1;
         let total_days = (end_date - start_date).num_days() + 1;
 
         if days_in_end_month < 15 && total_days > 20 {
-            format!("{}", chrono::Month::try_from(start_month as u8).unwrap().name())
+            start_month
         } else {
-            format!("{}", chrono::Month::try_from(end_month as u8).unwrap().name())
+            end_month
+        }
+    }
+}
+
+/// Billing month of a single attachment: the month its message arrived in (mailbox timezone).
+/// Falls back to the range's billing month when the range sits in one month or the arrival
+/// time is unknown or outside the range.
+fn attachment_billing_month(message: Option<&MailMessage>, start_date: NaiveDate, end_date: NaiveDate, timezone: Option<Tz>) -> NaiveDate {
+    let range_month = range_billing_month(start_date, end_date);
+    if start_date.with_day(1) == end_date.with_day(1) {
+        return range_month;
+    }
+
+    message
+        .and_then(|message| message.received_date(timezone))
+        .filter(|date| (start_date..=end_date).contains(date))
+        .and_then(|date| date.with_day(1))
+        .unwrap_or(range_month)
+}
+
+pub fn month_name(month: NaiveDate) -> String {
+    chrono::Month::try_from(month.month() as u8).unwrap().name().to_string()
+}
+
+/// Month folder a run files into under the current GOOGLE_DRIVE_FOLDER_LOCATION
+pub fn monthly_folder(config: &Config, month: NaiveDate) -> String {
+    format!("{}/{}", config.fiscal_calendar.render(&config.drive_folder_path, month), month_name(month))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn test_mock_pipeline_uploads_fixtures() {
+        let temp = tempfile::tempdir().unwrap();
+        let drive_dir = temp.path();
+
+        let mut config = Config::for_test(&[]);
+        config.mock_fixtures_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures");
+        config.mock_drive_dir = drive_dir.to_path_buf();
+        config.drive_folder_path = "billing/test".to_string();
+        // The Revolut fixture is an account statement, which no default keyword matches
+        config.target_keywords.push("statement".to_string());
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let start = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2025, 3, 31).unwrap();
+        run_manual_processing(config, start, end, &tx).await.unwrap();
+        drop(tx);
+
+        let mut messages = Vec::new();
+        while let Some(message) = rx.recv().await {
+            messages.push(message);
         }
+        assert!(messages.iter().any(|m| m.starts_with("__RESULTS__:processed=3,uploaded=3,skipped=0,failed=0,month=March")));
+
+        let month_dir = drive_dir.join("billing/test/March");
+        assert!(month_dir.join("Revolut/revolut-account-statement_2025-02-01_2025-02-28.pdf").exists());
+        assert!(month_dir.join("hetzner-online-gmbh-Hetzner_2025-03-07_R0012345678.pdf").exists());
+    }
+
+    #[test]
+    fn test_claim_upload_names() {
+        let month = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        let attachment = |message_id: &str, data: &[u8]| InvoiceAttachmentWithBank {
+            attachment: mail::attachment::InvoiceAttachment { filename: "invoice.pdf".to_string(), data: data.to_vec(), message_id: message_id.to_string() },
+            bank_name: None,
+            vendor: String::new(),
+            text: None,
+            category: None,
+        };
+        let mut groups = BTreeMap::from([
+            ((month, None), vec![attachment("a", b"first"), attachment("b", b"second"), attachment("c", b"first")]),
+            ((month, Some("Revolut".to_string())), vec![attachment("d", b"third")]),
+            // The repeat of the renamed one keeps its new name rather than taking a third
+            ((month, Some("Wise".to_string())), vec![attachment("e", b"first"), attachment("f", b"second"), attachment("g", b"second")]),
+        ]);
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut upload_names = UploadNames::default();
+        claim_upload_names(&mut upload_names, &mut groups, &tx).unwrap();
+        let names: Vec<_> = groups.values().flatten().map(|attachment| attachment.attachment.filename.as_str()).collect();
+        assert_eq!(names, ["invoice.pdf", "invoice-2.pdf", "invoice.pdf", "invoice.pdf", "invoice.pdf", "invoice-2.pdf", "invoice-2.pdf"]);
+
+        // A later message's batch carries on from the names already given
+        let mut later = BTreeMap::from([((month, None), vec![attachment("h", b"second"), attachment("i", b"fourth")])]);
+        claim_upload_names(&mut upload_names, &mut later, &tx).unwrap();
+        let names: Vec<_> = later.values().flatten().map(|attachment| attachment.attachment.filename.as_str()).collect();
+        assert_eq!(names, ["invoice-2.pdf", "invoice-3.pdf"]);
+    }
+
+    #[test]
+    fn test_attachment_billing_month() {
+        let date = |m, d| NaiveDate::from_ymd_opt(2025, m, d).unwrap();
+        let received = |at: &str| MailMessage {
+            received_at: chrono::DateTime::parse_from_rfc3339(at).ok().map(|d| d.to_utc()),
+            ..Default::default()
+        };
+        let utc = Some(chrono_tz::UTC);
+
+        // A range spanning two months files each message under its own month
+        let feb = received("2025-02-20T10:00:00Z");
+        let mar = received("2025-03-03T10:00:00Z");
+        assert_eq!(attachment_billing_month(Some(&feb), date(2, 15), date(3, 15), utc), date(2, 1));
+        assert_eq!(attachment_billing_month(Some(&mar), date(2, 15), date(3, 15), utc), date(3, 1));
+
+        // Unknown or out-of-range arrival falls back to the range's month
+        assert_eq!(attachment_billing_month(None, date(2, 15), date(3, 15), utc), date(3, 1));
+        let late = received("2025-04-02T10:00:00Z");
+        assert_eq!(attachment_billing_month(Some(&late), date(2, 15), date(3, 15), utc), date(3, 1));
+
+        // A single-month range keeps everything together
+        assert_eq!(attachment_billing_month(Some(&feb), date(3, 1), date(3, 31), utc), date(3, 1));
+        assert_eq!(range_billing_month(date(2, 1), date(3, 5)), date(2, 1));
     }
-}
\ No newline at end of file
+}
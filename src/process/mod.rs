@@ -1 +1,33 @@
+pub mod artifact;
+pub mod audit;
+pub mod backup;
+pub mod batch;
+pub mod budget;
+pub mod compress;
+pub mod digest;
+pub mod encrypt;
+pub mod estimate;
+pub mod failures;
+pub mod feedback;
+pub mod fiscal;
+pub mod heartbeat;
+pub mod index;
+pub mod ingest;
 pub mod jobs;
+pub mod outcome;
+pub mod package;
+pub mod payables;
+pub mod reconcile;
+pub mod reorganize;
+pub mod reprocess;
+pub mod retention;
+pub mod rules;
+pub mod sanitize;
+pub mod scan;
+pub mod simulate;
+pub mod telemetry;
+pub mod throttle;
+pub mod tidy;
+pub mod tracker;
+pub mod trends;
+pub mod workdir;
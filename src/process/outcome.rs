@@ -0,0 +1,487 @@
+use super::telemetry::{self, ApiUsage};
+use serde::{Deserialize, Serialize};
+
+/// Exit code when more files failed than `--fail-threshold` allows, or a batch tenant failed
+pub const EXIT_PARTIAL_FAILURE: u8 = 2;
+/// Exit code when Gmail or Drive authentication failed, or an API refused access
+pub const EXIT_AUTH_FAILURE: u8 = 3;
+/// Exit code for missing or invalid configuration and arguments
+pub const EXIT_CONFIG_ERROR: u8 = 4;
+
+/// Failure classes that map to a dedicated exit code; attached to errors with `.context(..)`
+#[derive(Debug, Clone, Copy, PartialEq, thiserror::Error)]
+pub enum FailureKind {
+    #[error("authentication failed")]
+    Auth,
+    #[error("access refused")]
+    Permission,
+    #[error("invalid configuration")]
+    Config,
+}
+
+impl FailureKind {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            FailureKind::Auth | FailureKind::Permission => EXIT_AUTH_FAILURE,
+            FailureKind::Config => EXIT_CONFIG_ERROR,
+        }
+    }
+}
+
+/// How an error is likely to behave when the same thing is tried again
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// Dropped connection, timeout, rate limit or server error: may pass later
+    Transient,
+    /// The message, attachment or file is gone (404, 410)
+    NotFound,
+    /// Access refused (401, or a 403 that isn't a rate limit)
+    Permission,
+    /// Missing or invalid configuration
+    Config,
+    /// Anything else, which usually fails the same way again
+    #[default]
+    Other,
+}
+
+impl ErrorCategory {
+    /// Whether trying the same call again may help
+    pub fn is_retriable(self) -> bool {
+        self == Self::Transient
+    }
+
+    fn from_status(status: u16, body: &str) -> Self {
+        match status {
+            429 | 500.. => Self::Transient,
+            403 if body.to_lowercase().contains("ratelimitexceeded") => Self::Transient,
+            404 | 410 => Self::NotFound,
+            401 | 403 => Self::Permission,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// A failed HTTP API call, kept typed so retries, exit codes and failure reports can tell a
+/// deleted message from a refused token from a flaky network without reading the message
+#[derive(Debug, thiserror::Error)]
+#[error("{api} API error ({status}): {body}")]
+pub struct ApiError {
+    /// "Gmail", "Drive", ...
+    pub api: &'static str,
+    pub status: reqwest::StatusCode,
+    pub body: String,
+}
+
+impl ApiError {
+    pub fn new(api: &'static str, status: reqwest::StatusCode, body: impl Into<String>) -> Self {
+        Self { api, status, body: body.into() }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        ErrorCategory::from_status(self.status.as_u16(), &self.body)
+    }
+}
+
+/// Category of an error: from `FailureKind` and `ApiError` where they were attached, from the
+/// network or I/O error underneath, or, for sources that only say it in words, from a status in
+/// the message like `JMAP API error (503 Service Unavailable): ...`
+pub fn categorize(error: &anyhow::Error) -> ErrorCategory {
+    match error.downcast_ref::<FailureKind>() {
+        Some(FailureKind::Auth | FailureKind::Permission) => return ErrorCategory::Permission,
+        Some(FailureKind::Config) => return ErrorCategory::Config,
+        None => {}
+    }
+    if let Some(api) = error.downcast_ref::<ApiError>() {
+        return api.category();
+    }
+    for cause in error.chain() {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout() || e.is_connect() || e.is_body() || e.is_request() {
+                return ErrorCategory::Transient;
+            }
+            if let Some(status) = e.status() {
+                return ErrorCategory::from_status(status.as_u16(), "");
+            }
+        }
+        // A local file the process may not touch is not an account problem, so
+        // `PermissionDenied` is left to the catch-all
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            use std::io::ErrorKind::*;
+            if matches!(e.kind(), ConnectionReset | ConnectionAborted | BrokenPipe | TimedOut | UnexpectedEof) {
+                return ErrorCategory::Transient;
+            }
+        }
+    }
+    let message = format!("{:#}", error);
+    status_in(&message).map_or_else(
+        || if message.to_lowercase().contains("ratelimitexceeded") { ErrorCategory::Transient } else { ErrorCategory::Other },
+        |status| ErrorCategory::from_status(status, &message),
+    )
+}
+
+/// The HTTP status in messages like `Gmail API error (503 Service Unavailable): ...`
+fn status_in(message: &str) -> Option<u16> {
+    message.split('(').skip(1).find_map(|rest| {
+        let (code, after) = (rest.get(..3)?, rest.get(3..)?);
+        after.starts_with([' ', ')']).then(|| code.parse().ok()).flatten()
+    })
+}
+
+/// Whether a run that ended with this error may succeed if tried again later: authentication,
+/// permission and configuration problems need someone to fix them and something deleted stays
+/// gone, anything else (network, server errors, the unclassified) may pass
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    !matches!(categorize(error), ErrorCategory::Permission | ErrorCategory::Config | ErrorCategory::NotFound)
+}
+
+/// Exit code for an error that ended the run (1 when it isn't classified)
+pub fn exit_code_for_error(error: &anyhow::Error) -> u8 {
+    if let Some(kind) = error.downcast_ref::<FailureKind>() {
+        return kind.exit_code();
+    }
+    match categorize(error) {
+        ErrorCategory::Permission => EXIT_AUTH_FAILURE,
+        _ => 1,
+    }
+}
+
+/// Counts from a single pipeline run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunSummary {
+    pub processed: usize,
+    pub uploaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    // Attachments flagged by the virus scan (also counted in `failed`)
+    pub quarantined: usize,
+    pub billing_month: Option<String>,
+    pub folder: Option<String>,
+    // Where `folder` opens in a browser (Drive's webViewLink), when the storage has one
+    pub folder_link: Option<String>,
+    // Batch mode only: tenants whose run failed entirely
+    pub failed_profiles: usize,
+    // Budgets this run pushed over their monthly limit (BUDGETS)
+    pub budget_alerts: Vec<String>,
+    // Copies written to the secondary storage (STORAGE_MIRROR), tracked apart from the counts above
+    pub mirror: Option<MirrorSummary>,
+    // Uploaded files whose message was in spam or trash (INCLUDE_SPAM, INCLUDE_TRASH), also counted in `uploaded`
+    pub from_spam: usize,
+    pub from_trash: usize,
+    // What failed and where, for the failure report (`retry --from`); one entry per count in `failed`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<Failure>,
+    // Requests, rate limits, retries and bytes per API (Gmail, Drive, ...), for tuning concurrency
+    #[serde(skip_serializing_if = "ApiUsage::is_empty")]
+    pub api: ApiUsage,
+}
+
+/// Pipeline stage a message or file failed in
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Fetch,
+    Download,
+    Decrypt,
+    Rules,
+    Plugin,
+    Prepare,
+    Upload,
+}
+
+/// One failed message or file, with what's needed to find and retry it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Failure {
+    pub stage: Stage,
+    pub message_id: Option<String>,
+    /// Attachment name at that stage (after renaming), None when the whole message failed
+    pub attachment: Option<String>,
+    pub error: String,
+    #[serde(default)]
+    pub category: ErrorCategory,
+}
+
+impl Failure {
+    pub fn new(stage: Stage, message_id: Option<&str>, attachment: Option<&str>, error: &anyhow::Error) -> Self {
+        Self {
+            stage,
+            message_id: message_id.map(str::to_string),
+            attachment: attachment.map(str::to_string),
+            error: format!("{:#}", error),
+            category: categorize(error),
+        }
+    }
+}
+
+/// Counts of the copies a run wrote to the mirror storage
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MirrorSummary {
+    pub name: String,
+    pub uploaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl MirrorSummary {
+    /// Percentage of the copies to the mirror that failed
+    pub fn failure_rate(&self) -> f64 {
+        let attempted = self.uploaded + self.skipped + self.failed;
+        if attempted == 0 {
+            0.0
+        } else {
+            self.failed as f64 * 100.0 / attempted as f64
+        }
+    }
+
+    /// Add another run's mirror counts
+    pub fn absorb(&mut self, other: &MirrorSummary) {
+        if self.name.is_empty() {
+            self.name = other.name.clone();
+        }
+        self.uploaded += other.uploaded;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+    }
+}
+
+impl RunSummary {
+    /// Count a failure and note it for the failure report
+    pub fn fail(&mut self, stage: Stage, message_id: Option<&str>, attachment: Option<&str>, error: &anyhow::Error) {
+        self.failed += 1;
+        self.failures.push(Failure::new(stage, message_id, attachment, error));
+    }
+
+    /// Percentage of files that failed out of all files the run tried to deliver
+    pub fn failure_rate(&self) -> f64 {
+        let attempted = self.uploaded + self.skipped + self.failed;
+        if attempted == 0 {
+            0.0
+        } else {
+            self.failed as f64 * 100.0 / attempted as f64
+        }
+    }
+
+    /// Whether failures exceed the allowed percentage (0 = any failure counts), on the primary
+    /// storage or on the mirror
+    pub fn exceeds_threshold(&self, fail_threshold: u8) -> bool {
+        let threshold = f64::from(fail_threshold);
+        let mirror_exceeds = self.mirror.as_ref().is_some_and(|mirror| mirror.failed > 0 && mirror.failure_rate() > threshold);
+        self.failed > 0 && self.failure_rate() > threshold || mirror_exceeds
+    }
+
+    /// Exit code for a run that finished: partial failure when too many files failed or a tenant failed
+    pub fn exit_code(&self, fail_threshold: u8) -> u8 {
+        if self.failed_profiles > 0 || self.exceeds_threshold(fail_threshold) {
+            EXIT_PARTIAL_FAILURE
+        } else {
+            0
+        }
+    }
+
+    /// Final machine-readable stdout line; field names and order are a stable interface for wrapper scripts
+    pub fn result_line(&self, exit_code: u8) -> String {
+        let folder = self.folder.as_deref().unwrap_or("").replace('\\', "\\\\").replace('"', "\\\"");
+        format!(
+            "RESULT processed={} uploaded={} failed={} skipped={} folder=\"{}\" exit={}",
+            self.processed, self.uploaded, self.failed, self.skipped, folder, exit_code
+        )
+    }
+
+    /// The summary as one JSON line, for `--format json`: the RESULT line's counts, the failures
+    /// and the API usage, plus `exit`
+    pub fn json_line(&self, exit_code: u8) -> String {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = value.as_object_mut() {
+            fields.insert("exit".to_string(), exit_code.into());
+        }
+        value.to_string()
+    }
+
+    /// Add another run's counts (used for batch totals)
+    pub fn absorb(&mut self, other: &RunSummary) {
+        self.processed += other.processed;
+        self.uploaded += other.uploaded;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+        self.quarantined += other.quarantined;
+        self.from_spam += other.from_spam;
+        self.from_trash += other.from_trash;
+        self.budget_alerts.extend(other.budget_alerts.iter().cloned());
+        self.failures.extend(other.failures.iter().cloned());
+        telemetry::absorb(&mut self.api, &other.api);
+        if let Some(mirror) = &other.mirror {
+            self.mirror.get_or_insert_with(MirrorSummary::default).absorb(mirror);
+        }
+    }
+
+    /// "Mail 10, Spam 1, Trash 0": where the uploaded files were found
+    pub fn folder_counts(&self) -> String {
+        let mail = self.uploaded.saturating_sub(self.from_spam + self.from_trash);
+        format!("Mail {}, Spam {}, Trash {}", mail, self.from_spam, self.from_trash)
+    }
+
+    /// Format as the `__RESULTS__:` progress marker understood by the TUI. The folder link comes
+    /// last, as a URL may hold ',' and '='.
+    pub fn results_marker(&self) -> String {
+        let mut marker = format!(
+            "__RESULTS__:processed={},uploaded={},skipped={},failed={},month={},folder={}",
+            self.processed,
+            self.uploaded,
+            self.skipped,
+            self.failed,
+            self.billing_month.as_deref().unwrap_or(""),
+            self.folder.as_deref().unwrap_or("")
+        );
+        if let Some(link) = &self.folder_link {
+            marker.push_str(&format!(",link={}", link));
+        }
+        marker
+    }
+}
+
+/// Starts a pipeline step sent through the progress lines next to the line that tells it, for the
+/// CLI progress bars (see `Step`)
+pub const STEP_MARKER: &str = "__STEP__:";
+
+/// A pipeline step the CLI progress bars follow
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    /// Message 3 of 40 being processed
+    Message(u64, u64),
+    /// Downloads finished with this many attachments
+    Downloaded(u64),
+    /// Uploads to the storage started, of about this many files
+    UploadStarted(u64),
+    /// A file uploaded, copied, skipped as a duplicate, or failed
+    FileDone { failed: bool },
+}
+
+impl Step {
+    /// Format as the `__STEP__:` progress marker
+    pub fn marker(&self) -> String {
+        let step = match self {
+            Step::Message(current, total) => format!("message={}/{}", current, total),
+            Step::Downloaded(count) => format!("downloaded={}", count),
+            Step::UploadStarted(files) => format!("upload={}", files),
+            Step::FileDone { failed: false } => "file=done".to_string(),
+            Step::FileDone { failed: true } => "file=failed".to_string(),
+        };
+        format!("{}{}", STEP_MARKER, step)
+    }
+
+    /// Read a `__STEP__:` progress marker back; None for any other line
+    pub fn parse(line: &str) -> Option<Self> {
+        let step = line.strip_prefix(STEP_MARKER)?;
+        match step.split_once('=')? {
+            ("message", count) => {
+                let (current, total) = count.split_once('/')?;
+                Some(Step::Message(current.parse().ok()?, total.parse().ok()?))
+            }
+            ("downloaded", count) => Some(Step::Downloaded(count.parse().ok()?)),
+            ("upload", files) => Some(Step::UploadStarted(files.parse().ok()?)),
+            ("file", "done") => Some(Step::FileDone { failed: false }),
+            ("file", "failed") => Some(Step::FileDone { failed: true }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_fail_threshold() {
+        let summary = RunSummary { uploaded: 9, failed: 1, ..Default::default() };
+        assert!(summary.exceeds_threshold(0));
+        assert!(!summary.exceeds_threshold(10));
+        assert!(summary.exceeds_threshold(5));
+        assert!(!RunSummary::default().exceeds_threshold(0));
+
+        // Mirror failures count on their own, however the primary storage fared
+        let mirror = MirrorSummary { name: "SFTP".to_string(), uploaded: 8, failed: 2, ..Default::default() };
+        let summary = RunSummary { uploaded: 10, mirror: Some(mirror), ..Default::default() };
+        assert!(summary.exceeds_threshold(10));
+        assert!(!summary.exceeds_threshold(20));
+    }
+
+    #[test]
+    fn test_exit_code_for_error() {
+        let auth: anyhow::Result<()> = Err(anyhow::anyhow!("token refresh failed"));
+        let auth = auth.context(FailureKind::Auth).context("while processing").unwrap_err();
+        assert_eq!(exit_code_for_error(&auth), EXIT_AUTH_FAILURE);
+        assert_eq!(exit_code_for_error(&anyhow::anyhow!("boom")), 1);
+
+        // Typed API errors keep their category under added context
+        let status = |code| reqwest::StatusCode::from_u16(code).unwrap();
+        let gone = anyhow::Error::new(ApiError::new("Gmail", status(404), "Requested entity was not found.")).context("Failed to fetch message");
+        assert_eq!(categorize(&gone), ErrorCategory::NotFound);
+        assert!(!is_transient(&gone));
+        let refused = anyhow::Error::new(ApiError::new("Drive", status(403), "insufficientFilePermissions"));
+        assert_eq!(categorize(&refused), ErrorCategory::Permission);
+        assert_eq!(exit_code_for_error(&refused), EXIT_AUTH_FAILURE);
+        assert!(categorize(&ApiError::new("Drive", status(403), "userRateLimitExceeded").into()).is_retriable());
+        assert!(categorize(&ApiError::new("Gmail", status(502), "").into()).is_retriable());
+        assert_eq!(categorize(&anyhow::anyhow!("JMAP API error (410 Gone): expired")), ErrorCategory::NotFound);
+        assert_eq!(categorize(&anyhow::anyhow!("boom")), ErrorCategory::Other);
+
+        let unreadable = anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::PermissionDenied)).context("Failed to read /var/invoices");
+        assert_eq!(categorize(&unreadable), ErrorCategory::Other);
+        assert_eq!(exit_code_for_error(&unreadable), 1);
+    }
+
+    #[test]
+    fn test_result_line() {
+        let summary = RunSummary {
+            processed: 57,
+            uploaded: 55,
+            failed: 2,
+            skipped: 5,
+            folder: Some("billing/2025/March".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            summary.result_line(2),
+            "RESULT processed=57 uploaded=55 failed=2 skipped=5 folder=\"billing/2025/March\" exit=2"
+        );
+        assert_eq!(
+            RunSummary::default().result_line(4),
+            "RESULT processed=0 uploaded=0 failed=0 skipped=0 folder=\"\" exit=4"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&summary.json_line(2)).unwrap();
+        assert_eq!((json["uploaded"].as_u64(), json["exit"].as_u64()), (Some(55), Some(2)));
+        assert!(json.get("api").is_none());
+    }
+
+    #[test]
+    fn test_results_marker() {
+        let summary = RunSummary {
+            processed: 5,
+            uploaded: 3,
+            skipped: 1,
+            failed: 1,
+            billing_month: Some("March".to_string()),
+            folder: Some("billing/March".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            summary.results_marker(),
+            "__RESULTS__:processed=5,uploaded=3,skipped=1,failed=1,month=March,folder=billing/March"
+        );
+
+        let linked = RunSummary { folder_link: Some("https://drive.google.com/drive/folders/abc?usp=drivesdk".to_string()), ..summary };
+        assert!(linked.results_marker().ends_with(",folder=billing/March,link=https://drive.google.com/drive/folders/abc?usp=drivesdk"));
+    }
+
+    #[test]
+    fn test_step_marker_round_trip() {
+        for step in [Step::Message(3, 40), Step::Downloaded(12), Step::UploadStarted(12), Step::FileDone { failed: false }, Step::FileDone { failed: true }] {
+            assert_eq!(Step::parse(&step.marker()), Some(step));
+        }
+        assert_eq!(Step::Message(3, 40).marker(), "__STEP__:message=3/40");
+        assert_eq!(Step::parse("  Processing message 3/40"), None);
+        assert_eq!(Step::parse("__STEP__:message=3"), None);
+    }
+}
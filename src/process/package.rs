@@ -0,0 +1,226 @@
+use crate::config::env::Config;
+use crate::drive;
+use crate::gmail;
+use crate::storage;
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use std::io::{Cursor, Write};
+use tokio::sync::mpsc;
+use zip::write::SimpleFileOptions;
+
+// Gmail rejects messages over 25 MB; leave room for the base64 overhead
+const MAX_EMAIL_ATTACHMENT_BYTES: usize = 18 * 1024 * 1024;
+
+/// Where the finished package goes
+#[derive(Debug, Clone)]
+pub enum Delivery {
+    /// Email the zip to this address (Gmail send scope)
+    Email(String),
+    /// Upload the zip to this Drive folder path shared with the accountant
+    DriveFolder(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PackageSummary {
+    pub archive_name: String,
+    pub files: usize,
+    pub size_bytes: usize,
+    pub delivered_to: String,
+}
+
+/// A file included in the package, as listed in the CSV manifest
+struct ManifestEntry {
+    path: String,
+    institution: String,
+    size_bytes: usize,
+    drive_file_id: String,
+}
+
+/// Zip a month's archived documents plus a CSV manifest and deliver it to the accountant
+pub async fn package_month(
+    config: &Config,
+    year: i32,
+    month: u32,
+    delivery: Delivery,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<PackageSummary> {
+    let month_name = chrono::Month::try_from(month as u8)
+        .context("Month must be between 1 and 12")?
+        .name();
+
+    if !config.mock_mode {
+        tx.send("Authenticating with Google Drive...".to_string())?;
+    }
+    let drive_client = storage::drive_client(config).await?;
+
+    let first_day = NaiveDate::from_ymd_opt(year, month, 1).context("Invalid month")?;
+    let monthly_folder_path = format!("{}/{}", config.fiscal_calendar.render(&config.drive_folder_path, first_day), month_name);
+    let monthly_folder_id = drive::folder::find_folder_by_path(&drive_client, &monthly_folder_path).await?
+        .with_context(|| format!("No archive found for {} at {}", month_name, monthly_folder_path))?;
+
+    tx.send(format!("📦 Collecting documents from {}...", monthly_folder_path))?;
+
+    let archive_name = format!("invoices-{}-{:02}.zip", year, month);
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut manifest = Vec::new();
+
+    // Walk the monthly folder and its institution subfolders
+    let mut pending = vec![(monthly_folder_id, String::new())];
+    while let Some((folder_id, prefix)) = pending.pop() {
+        for file in drive::folder::list_folder(&drive_client, &folder_id).await? {
+            let path = if prefix.is_empty() { file.name.clone() } else { format!("{}/{}", prefix, file.name) };
+
+            if file.mime_type.as_deref() == Some(drive::folder::FOLDER_MIME_TYPE) {
+                pending.push((file.id, path));
+                continue;
+            }
+
+            // Previously uploaded packages and native Google documents are not archived invoices
+            if file.name.ends_with(".zip") || file.mime_type.as_deref().is_some_and(|m| m.starts_with("application/vnd.google-apps")) {
+                continue;
+            }
+
+            let data = drive::download::download_file(&drive_client, &file.id).await
+                .with_context(|| format!("Failed to download {}", path))?;
+            tx.send(format!("  ✓ {}", path))?;
+
+            zip.start_file(path.as_str(), options)?;
+            zip.write_all(&data)?;
+
+            manifest.push(ManifestEntry {
+                institution: if prefix.is_empty() { "General".to_string() } else { prefix.clone() },
+                path,
+                size_bytes: data.len(),
+                drive_file_id: file.id,
+            });
+        }
+    }
+
+    if manifest.is_empty() {
+        anyhow::bail!("{} contains no documents to package", monthly_folder_path);
+    }
+
+    manifest.sort_by(|a, b| a.path.cmp(&b.path));
+    zip.start_file("manifest.csv", options)?;
+    zip.write_all(build_manifest_csv(&manifest).as_bytes())?;
+    let archive = zip.finish()?.into_inner();
+
+    tx.send(format!("✓ Packaged {} file(s) into {} ({} KB)", manifest.len(), archive_name, archive.len() / 1024))?;
+
+    let delivered_to = match delivery {
+        Delivery::Email(to) => {
+            send_package_email(config, &to, &archive_name, &archive, month_name, year).await?;
+            to
+        }
+        Delivery::DriveFolder(folder_path) => {
+            let folder_id = drive::folder::find_or_create_folder(&drive_client, &folder_path).await?;
+            let temp_dir = config.temp_dir();
+            std::fs::create_dir_all(&temp_dir).context("Failed to create temp directory")?;
+            let archive_path = temp_dir.join(&archive_name);
+            std::fs::write(&archive_path, &archive).context("Failed to write package to temp file")?;
+
+            let result = drive::upload::upload_file(&drive_client, &archive_path, &folder_id, false, Some(tx)).await;
+            let _ = std::fs::remove_file(&archive_path);
+            result?;
+            folder_path
+        }
+    };
+
+    Ok(PackageSummary {
+        archive_name,
+        files: manifest.len(),
+        size_bytes: archive.len(),
+        delivered_to,
+    })
+}
+
+/// Email the package; in mock mode the message is written to `<mock drive dir>/outbox` instead
+async fn send_package_email(
+    config: &Config,
+    to: &str,
+    archive_name: &str,
+    archive: &[u8],
+    month_name: &str,
+    year: i32,
+) -> Result<()> {
+    if archive.len() > MAX_EMAIL_ATTACHMENT_BYTES {
+        anyhow::bail!(
+            "Package is {} MB, too large to email; use --handoff-folder instead",
+            archive.len() / (1024 * 1024)
+        );
+    }
+
+    let raw = gmail::send::build_mime_message(
+        to,
+        &format!("Invoices and statements - {} {}", month_name, year),
+        &format!(
+            "Hello,\r\n\r\nAttached are the invoices and bank statements for {} {}, with a CSV manifest listing every document.\r\n",
+            month_name, year
+        ),
+        archive_name,
+        "application/zip",
+        archive,
+    );
+
+    gmail::send::send_from_profile(config, &raw, &format!("{}.eml", archive_name)).await
+}
+
+/// Render the manifest as CSV (RFC 4180 quoting)
+fn build_manifest_csv(entries: &[ManifestEntry]) -> String {
+    let mut csv = String::from("file,institution,size_bytes,drive_file_id\n");
+    for entry in entries {
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            csv_field(&entry.path),
+            csv_field(&entry.institution),
+            entry.size_bytes,
+            csv_field(&entry.drive_file_id)
+        ));
+    }
+    csv
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[tokio::test]
+    async fn test_package_month_to_handoff_folder() {
+        let temp = tempfile::tempdir().unwrap();
+        let drive_dir = temp.path();
+        let month_dir = drive_dir.join("billing/test/March");
+        std::fs::create_dir_all(month_dir.join("Revolut")).unwrap();
+        std::fs::write(month_dir.join("aws-invoice.pdf"), b"%PDF-aws").unwrap();
+        std::fs::write(month_dir.join("Revolut/statement, feb.pdf"), b"%PDF-revolut").unwrap();
+
+        let mut config = Config::for_test(&[]);
+        config.mock_drive_dir = drive_dir.to_path_buf();
+        config.drive_folder_path = "billing/test".to_string();
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let summary = package_month(&config, 2025, 3, Delivery::DriveFolder("handoff/accountant".to_string()), &tx)
+            .await
+            .unwrap();
+        assert_eq!(summary.files, 2);
+
+        let archive = std::fs::File::open(drive_dir.join("handoff/accountant/invoices-2025-03.zip")).unwrap();
+        let mut zip = zip::ZipArchive::new(archive).unwrap();
+        let mut manifest = String::new();
+        zip.by_name("manifest.csv").unwrap().read_to_string(&mut manifest).unwrap();
+
+        assert!(zip.by_name("Revolut/statement, feb.pdf").is_ok());
+        assert!(manifest.starts_with("file,institution,size_bytes,drive_file_id\n"));
+        assert!(manifest.contains("\"Revolut/statement, feb.pdf\",Revolut,12,"));
+        assert!(manifest.contains("aws-invoice.pdf,General,8,"));
+    }
+}
@@ -0,0 +1,105 @@
+//! Minimal accounts payable (`payables`): archived invoices with a due date stay unpaid until they
+//! are marked paid, and the ones past due or due within DUE_REMINDER_DAYS are reminded of by
+//! email (DIGEST_EMAIL), the ON_PAYMENT_DUE hook and the digest.
+
+use anyhow::Result;
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+use crate::db::{self, DbPool, InvoiceDocument};
+
+/// Unpaid invoices that are past due or due soon
+#[derive(Debug, Clone, Serialize)]
+pub struct Payables {
+    pub today: NaiveDate,
+    /// Days ahead that count as due soon
+    pub days: i64,
+    /// Due before today, oldest first
+    pub overdue: Vec<InvoiceDocument>,
+    /// Due today or within `days`, soonest first
+    pub due_soon: Vec<InvoiceDocument>,
+}
+
+impl Payables {
+    /// Unpaid invoices of the profile due by `days` after `today`
+    pub async fn load(pool: &DbPool, profile: &str, today: NaiveDate, days: i64) -> Result<Self> {
+        let documents = db::unpaid_invoice_documents(pool, profile, today + Duration::days(days)).await?;
+        Ok(Self::split(documents, today, days))
+    }
+
+    fn split(documents: Vec<InvoiceDocument>, today: NaiveDate, days: i64) -> Self {
+        let (overdue, due_soon) = documents
+            .into_iter()
+            .filter(|document| document.due_date.is_some_and(|due| due <= today + Duration::days(days)))
+            .partition(|document| document.due_date.is_some_and(|due| due < today));
+        Self { today, days, overdue, due_soon }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overdue.is_empty() && self.due_soon.is_empty()
+    }
+
+    pub fn subject(&self) -> String {
+        format!("Invoices to pay: {} overdue, {} due soon", self.overdue.len(), self.due_soon.len())
+    }
+
+    /// Plain text list for the terminal, email and hooks
+    pub fn render(&self) -> String {
+        if self.is_empty() {
+            return format!("No unpaid invoice is due in the next {} day(s).\n", self.days);
+        }
+
+        let mut text = String::new();
+        for (heading, documents) in [("Past due", &self.overdue), ("Due soon", &self.due_soon)] {
+            if documents.is_empty() {
+                continue;
+            }
+            text.push_str(&format!("{}:\n", heading));
+            for document in documents {
+                text.push_str(&format!("  {}  {}\n", self.describe_due(document), line(document)));
+            }
+            text.push('\n');
+        }
+        text.push_str("Mark invoices paid with `payables <file id> --paid`.\n");
+        text
+    }
+
+    /// "due 2025-04-03 (5 days late)", "due today", "due 2025-04-10 (in 2 days)"
+    fn describe_due(&self, document: &InvoiceDocument) -> String {
+        let Some(due) = document.due_date else {
+            return String::new();
+        };
+        let days = (due - self.today).num_days();
+        match days {
+            0 => format!("due {} (today)", due),
+            days if days < 0 => format!("due {} ({} day(s) late)", due, -days),
+            days => format!("due {} (in {} day(s))", due, days),
+        }
+    }
+}
+
+/// "hetzner  49.99 EUR  Hetzner_2025-03.pdf  <file id>"
+pub fn line(document: &InvoiceDocument) -> String {
+    format!("{:<24} {:>14}  {}  {}", document.vendor, document.display_amount(), document.filename, document.file_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_payables() {
+        let day = |d| NaiveDate::from_ymd_opt(2025, 4, d).unwrap();
+        let document = |file_id: &str, due| InvoiceDocument { file_id: file_id.to_string(), due_date: Some(due), ..Default::default() };
+        let documents = vec![document("late", day(1)), document("today", day(10)), document("soon", day(15)), document("later", day(30))];
+
+        let payables = Payables::split(documents, day(10), crate::config::env::DEFAULT_REMINDER_DAYS);
+        let ids = |documents: &[InvoiceDocument]| documents.iter().map(|document| document.file_id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&payables.overdue), vec!["late"]);
+        assert_eq!(ids(&payables.due_soon), vec!["today", "soon"]);
+
+        let text = payables.render();
+        assert!(text.contains("due 2025-04-01 (9 day(s) late)"));
+        assert!(text.contains("due 2025-04-15 (in 5 day(s))"));
+        assert!(Payables::split(Vec::new(), day(10), 7).render().starts_with("No unpaid invoice"));
+    }
+}
@@ -0,0 +1,229 @@
+//! Receipt matching (`reconcile`): the transactions of a bank statement are matched against the
+//! archived invoices by amount, date and vendor, and payments with no invoice are reported.
+
+use anyhow::Result;
+use chrono::{Duration, Months, NaiveDate};
+use serde::Serialize;
+use std::collections::HashSet;
+use crate::db::{self, DbPool, InvoiceDocument};
+use crate::extract::invoice::format_amount;
+use crate::extract::statement::Transaction;
+
+/// How far a payment may be from the invoice's due date (or the middle of its billing month)
+const MATCH_DAYS: i64 = 45;
+
+/// Parts of a vendor slug too generic to recognize the vendor by in a transaction description
+const GENERIC_WORDS: &[&str] = &["gmbh", "ltd", "inc", "llc", "sarl", "sas", "bv", "ag", "emea", "europe", "online", "services", "the"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchedTransaction {
+    pub transaction: Transaction,
+    pub document: InvoiceDocument,
+    /// The description names the invoice's vendor; otherwise only amount and date agree
+    pub vendor_matched: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Reconciliation {
+    pub matched: Vec<MatchedTransaction>,
+    /// Payments no archived invoice matches
+    pub unmatched: Vec<Transaction>,
+    /// Incoming money with no credit note, not reported as missing
+    pub unmatched_credits: usize,
+}
+
+impl Reconciliation {
+    /// Match the transactions against the invoices archived for the months around them
+    pub async fn load(pool: &DbPool, profile: &str, transactions: Vec<Transaction>) -> Result<Self> {
+        let (Some(first), Some(last)) = (
+            transactions.iter().map(|transaction| transaction.date).min(),
+            transactions.iter().map(|transaction| transaction.date).max(),
+        ) else {
+            return Ok(Self::default());
+        };
+        let documents = db::invoice_documents_between(pool, profile, first - Months::new(2), last + Months::new(1)).await?;
+        Ok(Self::build(transactions, &documents))
+    }
+
+    /// Each invoice pays for at most one transaction: payments (negative) match invoices of the
+    /// same amount, refunds (positive) match credit notes. Pairs are assigned across the whole
+    /// statement, those where the description names the vendor first, then the closest dates, so
+    /// an unnamed card payment can't take the invoice a later named payment is for.
+    fn build(transactions: Vec<Transaction>, documents: &[InvoiceDocument]) -> Self {
+        // (vendor not named, days apart, transaction, document), best first once sorted
+        let mut pairs = Vec::new();
+        for (t, transaction) in transactions.iter().enumerate() {
+            for (d, document) in documents.iter().enumerate() {
+                if !amounts_match(transaction, document) {
+                    continue;
+                }
+                let Some(date) = reference_date(document) else {
+                    continue;
+                };
+                let distance = (transaction.date - date).num_days().abs();
+                if distance <= MATCH_DAYS {
+                    pairs.push((!vendor_named(&transaction.description, document), distance, t, d));
+                }
+            }
+        }
+        pairs.sort_unstable();
+
+        let mut assigned = vec![None; transactions.len()];
+        let mut used = HashSet::new();
+        for (vendor_unnamed, _, t, d) in pairs {
+            if assigned[t].is_none() && used.insert(d) {
+                assigned[t] = Some((d, !vendor_unnamed));
+            }
+        }
+
+        let mut reconciliation = Self::default();
+        for (transaction, assigned) in transactions.into_iter().zip(assigned) {
+            match assigned {
+                Some((index, vendor_matched)) => {
+                    reconciliation.matched.push(MatchedTransaction { transaction, document: documents[index].clone(), vendor_matched });
+                }
+                None if transaction.amount_cents < 0 => reconciliation.unmatched.push(transaction),
+                None => reconciliation.unmatched_credits += 1,
+            }
+        }
+        reconciliation
+    }
+
+    /// Plain text report for the terminal
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        if !self.unmatched.is_empty() {
+            text.push_str("Payments with no archived invoice:\n");
+            for transaction in &self.unmatched {
+                text.push_str(&format!(
+                    "  {}  You spent {} at {} but have no invoice\n",
+                    transaction.date,
+                    amount(-transaction.amount_cents, transaction.currency.as_deref()),
+                    if transaction.description.is_empty() { "an unnamed payee" } else { &transaction.description }
+                ));
+            }
+            text.push('\n');
+        }
+        if !self.matched.is_empty() {
+            text.push_str("Matched:\n");
+            for matched in &self.matched {
+                text.push_str(&format!(
+                    "  {}  {:>14}  {:<28} {}{}\n",
+                    matched.transaction.date,
+                    amount(matched.transaction.amount_cents, matched.transaction.currency.as_deref()),
+                    matched.transaction.description,
+                    matched.document.filename,
+                    if matched.vendor_matched { "" } else { " (amount and date only)" }
+                ));
+            }
+            text.push('\n');
+        }
+        text.push_str(&format!(
+            "{} matched, {} payment(s) without an invoice, {} incoming transaction(s) ignored\n",
+            self.matched.len(),
+            self.unmatched.len(),
+            self.unmatched_credits
+        ));
+        text
+    }
+}
+
+fn amount(cents: i64, currency: Option<&str>) -> String {
+    format!("{} {}", format_amount(cents), currency.unwrap_or_default()).trim_end().to_string()
+}
+
+/// Opposite amounts, in the same currency when both are known
+fn amounts_match(transaction: &Transaction, document: &InvoiceDocument) -> bool {
+    let currencies_agree = match (&transaction.currency, &document.currency) {
+        (Some(paid), Some(billed)) => paid.eq_ignore_ascii_case(billed),
+        _ => true,
+    };
+    currencies_agree && document.amount_cents.is_some_and(|cents| cents != 0 && cents == -transaction.amount_cents)
+}
+
+/// The invoice's due date, otherwise the middle of its billing month
+fn reference_date(document: &InvoiceDocument) -> Option<NaiveDate> {
+    document.due_date.or_else(|| document.billing_month.map(|month| month + Duration::days(14)))
+}
+
+/// Whether a distinctive word of the vendor slug ("hetzner" of "hetzner-online-gmbh") is in the description
+fn vendor_named(description: &str, document: &InvoiceDocument) -> bool {
+    let description: String = description.to_lowercase().chars().filter(|c| c.is_alphanumeric()).collect();
+    document
+        .vendor
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.len() >= 3 && !GENERIC_WORDS.contains(word))
+        .any(|word| description.contains(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconcile_transactions() {
+        let day = |m, d| NaiveDate::from_ymd_opt(2025, m, d).unwrap();
+        let transaction = |date, cents, description: &str| Transaction {
+            date,
+            amount_cents: cents,
+            currency: Some("EUR".to_string()),
+            description: description.to_string(),
+        };
+        let document = |vendor: &str, cents, filename: &str| InvoiceDocument {
+            vendor: vendor.to_string(),
+            amount_cents: Some(cents),
+            currency: Some("EUR".to_string()),
+            filename: filename.to_string(),
+            billing_month: Some(day(3, 1)),
+            ..Default::default()
+        };
+        let documents = vec![
+            document("google-cloud", 4999, "google.pdf"),
+            document("hetzner-online-gmbh", 4999, "hetzner.pdf"),
+            document("hetzner-online-gmbh", -1250, "hetzner-credit.pdf"),
+        ];
+        let transactions = vec![
+            transaction(day(3, 8), -4999, "HETZNER ONLINE GMBH GUNZENHAUSEN"),
+            transaction(day(3, 9), -4999, "CARD 1234"),
+            transaction(day(3, 20), 1250, "Hetzner refund"),
+            transaction(day(3, 21), -1500, "Figma"),
+            transaction(day(3, 22), 200000, "Salary"),
+            transaction(day(7, 1), -4999, "Hetzner"),
+        ];
+
+        let reconciliation = Reconciliation::build(transactions, &documents);
+        let matched: Vec<(&str, bool)> = reconciliation.matched.iter().map(|m| (m.document.filename.as_str(), m.vendor_matched)).collect();
+        assert_eq!(matched, vec![("hetzner.pdf", true), ("google.pdf", false), ("hetzner-credit.pdf", true)]);
+        // July is too far from March, and both March invoices are taken
+        assert_eq!(reconciliation.unmatched.iter().map(|t| t.description.as_str()).collect::<Vec<_>>(), vec!["Figma", "Hetzner"]);
+        assert_eq!(reconciliation.unmatched_credits, 1);
+        assert!(reconciliation.render().contains("You spent 15.00 EUR at Figma but have no invoice"));
+    }
+
+    #[test]
+    fn test_named_vendor_wins_over_earlier_payment() {
+        let day = |d| NaiveDate::from_ymd_opt(2025, 3, d).unwrap();
+        let transaction = |date, description: &str| Transaction {
+            date,
+            amount_cents: -4999,
+            currency: Some("EUR".to_string()),
+            description: description.to_string(),
+        };
+        let documents = vec![InvoiceDocument {
+            vendor: "hetzner-online-gmbh".to_string(),
+            amount_cents: Some(4999),
+            currency: Some("EUR".to_string()),
+            filename: "hetzner.pdf".to_string(),
+            billing_month: Some(day(1)),
+            ..Default::default()
+        }];
+        // The unnamed payment comes first on the statement
+        let transactions = vec![transaction(day(2), "CARD 1234"), transaction(day(20), "HETZNER ONLINE GMBH")];
+
+        let reconciliation = Reconciliation::build(transactions, &documents);
+        assert_eq!(reconciliation.matched.len(), 1);
+        assert_eq!(reconciliation.matched[0].transaction.description, "HETZNER ONLINE GMBH");
+        assert!(reconciliation.matched[0].vendor_matched);
+        assert_eq!(reconciliation.unmatched[0].description, "CARD 1234");
+    }
+}
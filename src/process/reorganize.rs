@@ -0,0 +1,218 @@
+//! Bringing a past month in line with the current naming (`reorganize --month`). Every document's
+//! place is worked out again from its invoice record and cached message: the month folder under
+//! the current GOOGLE_DRIVE_FOLDER_LOCATION, the filename and folder of the sender's rule and
+//! FILENAME_SANITIZE. Files are then renamed and moved in place, nothing is uploaded again.
+
+use crate::config::env::Config;
+use crate::db::{self, DbPool, InvoiceDocument};
+use crate::drive;
+use crate::drive::client::DriveClient;
+use crate::extract::invoice::CREDIT_NOTES_FOLDER;
+use crate::mail::MailMessage;
+use crate::mail::vendors::VendorAliases;
+use crate::process::jobs;
+use crate::process::rules::Rules;
+use crate::process::sanitize;
+use crate::process::simulate::{self, Simulated};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use tokio::sync::mpsc;
+
+/// A file to rename or move
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relocation {
+    pub file_id: String,
+    pub folder: String,
+    pub filename: String,
+    pub to_folder: String,
+    pub to_filename: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ReorganizePlan {
+    pub relocations: Vec<Relocation>,
+    /// Documents already where the current conventions put them
+    pub unchanged: usize,
+    /// Documents outside a month folder (the review folder) or sharing a file (a zipped month)
+    pub left: usize,
+    /// Documents whose target another document of the month already takes
+    pub conflicts: Vec<String>,
+}
+
+impl ReorganizePlan {
+    /// Plan from a month's documents and the cached messages they came from, by message id.
+    /// Documents without a cached message keep their name and sender folder, and only follow
+    /// a changed GOOGLE_DRIVE_FOLDER_LOCATION.
+    pub fn new(
+        config: &Config,
+        rules: &Rules,
+        vendors: &mut VendorAliases,
+        documents: &[InvoiceDocument],
+        messages: &HashMap<String, MailMessage>,
+    ) -> Self {
+        let mut shared: HashMap<&str, usize> = HashMap::new();
+        for document in documents {
+            *shared.entry(document.file_id.as_str()).or_default() += 1;
+        }
+
+        let mut plan = Self::default();
+        let mut taken: HashSet<(String, String)> = documents.iter().map(|d| (d.folder.clone(), d.filename.clone())).collect();
+        for document in documents {
+            if shared[document.file_id.as_str()] > 1 {
+                plan.left += 1;
+                continue;
+            }
+            let message = document.message_id.as_ref().and_then(|id| messages.get(id));
+            let Some((to_folder, to_filename)) = target(config, rules, vendors, document, message) else {
+                plan.left += 1;
+                continue;
+            };
+            if to_folder == document.folder && to_filename == document.filename {
+                plan.unchanged += 1;
+                continue;
+            }
+            if !taken.insert((to_folder.clone(), to_filename.clone())) {
+                plan.conflicts.push(format!("{}/{} → {}/{}", document.folder, document.filename, to_folder, to_filename));
+                continue;
+            }
+            taken.remove(&(document.folder.clone(), document.filename.clone()));
+            plan.relocations.push(Relocation {
+                file_id: document.file_id.clone(),
+                folder: document.folder.clone(),
+                filename: document.filename.clone(),
+                to_folder,
+                to_filename,
+            });
+        }
+        plan
+    }
+}
+
+/// Folder and filename of a document under the current conventions; None outside a month folder
+pub fn target(
+    config: &Config,
+    rules: &Rules,
+    vendors: &mut VendorAliases,
+    document: &InvoiceDocument,
+    message: Option<&MailMessage>,
+) -> Option<(String, String)> {
+    let old_month_folder = simulate::month_folder(document)?;
+    let (folder, filename) = match message.map(|message| simulate::reclassify(rules, vendors, document, message, config.mailbox_timezone)) {
+        Some(Simulated::Changed { folder, filename, .. }) => (folder, filename),
+        _ => (document.folder.clone(), document.filename.clone()),
+    };
+
+    // The institution folder below the month's, keeping credit notes in their subfolder
+    let mut institution = folder.strip_prefix(&old_month_folder).unwrap_or_default().trim_matches('/').to_string();
+    if document.folder.ends_with(CREDIT_NOTES_FOLDER) && !institution.ends_with(CREDIT_NOTES_FOLDER) {
+        institution = [institution.as_str(), CREDIT_NOTES_FOLDER].iter().filter(|part| !part.is_empty()).copied().collect::<Vec<_>>().join("/");
+    }
+
+    let mut to_folder = jobs::monthly_folder(config, document.billing_month?);
+    if !institution.is_empty() {
+        to_folder = format!("{}/{}", to_folder, sanitize::sanitize_folder(&institution, config.filename_sanitize));
+    }
+    Some((to_folder, sanitize::sanitize_name(&filename, config.filename_sanitize)))
+}
+
+#[derive(Debug, Default)]
+pub struct ReorganizeSummary {
+    pub moved: usize,
+    /// Files the target folder already has a file of the same name for
+    pub taken: usize,
+    pub failed: usize,
+}
+
+/// Rename and move the planned files in Drive and update their invoice records
+pub async fn reorganize(
+    client: &DriveClient,
+    pool: &DbPool,
+    profile: &str,
+    plan: &ReorganizePlan,
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<ReorganizeSummary> {
+    let mut summary = ReorganizeSummary::default();
+    let mut folder_ids: HashMap<String, String> = HashMap::new();
+    for relocation in &plan.relocations {
+        let from = format!("{}/{}", relocation.folder, relocation.filename);
+        let to = format!("{}/{}", relocation.to_folder, relocation.to_filename);
+        let moved = async {
+            let Some(from_id) = drive::folder::find_folder_by_path(client, &relocation.folder).await? else {
+                anyhow::bail!("its folder is no longer in Drive");
+            };
+            let to_id = match folder_ids.get(&relocation.to_folder) {
+                Some(id) => id.clone(),
+                None => {
+                    let id = drive::folder::find_or_create_folder(client, &relocation.to_folder).await?;
+                    folder_ids.insert(relocation.to_folder.clone(), id.clone());
+                    id
+                }
+            };
+            let existing = drive::folder::list_folder(client, &to_id).await?;
+            if existing.iter().any(|file| file.name == relocation.to_filename) {
+                return Ok(None);
+            }
+            let file_id = drive::folder::relocate_item(client, &relocation.file_id, &from_id, &to_id, &relocation.to_filename).await?;
+            db::relocate_archived_file(pool, profile, &relocation.file_id, &file_id, &relocation.to_folder, &relocation.to_filename).await?;
+            Ok(Some(()))
+        };
+        match moved.await {
+            Ok(Some(())) => {
+                summary.moved += 1;
+                tx.send(format!("  ✓ {} → {}", from, to))?;
+            }
+            Ok(None) => {
+                summary.taken += 1;
+                tx.send(format!("  ⊘ {}: {} already exists", from, to))?;
+            }
+            Err(e) => {
+                summary.failed += 1;
+                tx.send(format!("  ✗ {}: {:#}", from, e))?;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_plan_follows_current_conventions() {
+        let mut config = Config::for_test(&[]);
+        config.drive_folder_path = "finance/invoices".to_string();
+        config.filename_sanitize = sanitize::Strictness::Windows;
+        let document = |file_id: &str, folder: &str, filename: &str| InvoiceDocument {
+            vendor: "Hetzner".to_string(),
+            filename: filename.to_string(),
+            folder: folder.to_string(),
+            file_id: file_id.to_string(),
+            billing_month: NaiveDate::from_ymd_opt(2025, 2, 1),
+            ..Default::default()
+        };
+        let documents = vec![
+            document("a", "billing/February/Hetzner", "Hetzner-Invoice: 42.pdf"),
+            document("b", "billing/February/Revolut/Credit Notes", "Refund.pdf"),
+            document("c", "finance/invoices/February", "Done.pdf"),
+            document("d", "billing/Review", "Unsure.pdf"),
+            document("zip", "billing/February", "February.zip"),
+            document("zip", "billing/February", "February.zip"),
+            document("e", "billing/February", "Done.pdf"),
+        ];
+        let plan = ReorganizePlan::new(&config, &Rules::default(), &mut VendorAliases::default(), &documents, &HashMap::new());
+
+        let moves: Vec<(&str, &str)> = plan.relocations.iter().map(|r| (r.to_folder.as_str(), r.to_filename.as_str())).collect();
+        assert_eq!(
+            moves,
+            vec![
+                ("finance/invoices/February/Hetzner", "Hetzner-Invoice- 42.pdf"),
+                ("finance/invoices/February/Revolut/Credit Notes", "Refund.pdf"),
+            ]
+        );
+        assert_eq!(plan.unchanged, 1);
+        assert_eq!(plan.left, 3);
+        assert_eq!(plan.conflicts, vec!["billing/February/Done.pdf → finance/invoices/February/Done.pdf"]);
+    }
+}
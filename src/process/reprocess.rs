@@ -0,0 +1,209 @@
+//! Replaying a past month (`reprocess --month`). The messages its documents were archived from
+//! are fetched again and go through the pipeline under the current naming, rules and routing.
+//! Copies the new run replaces are then moved to the Drive trash.
+
+use crate::config::env::Config;
+use crate::db::{self, ArchivedFile, DbPool};
+use crate::drive;
+use crate::process::jobs;
+use crate::process::outcome::{RunSummary, Stage};
+use crate::storage;
+use anyhow::Result;
+use chrono::{Months, NaiveDate};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::mpsc;
+
+#[derive(Debug)]
+pub struct ReprocessPlan {
+    pub month: NaiveDate,
+    /// Folder the month was filed under (`billing/2024`), which the new run files into again
+    pub folder_path: String,
+    /// Source messages, in the order their documents were archived
+    pub message_ids: Vec<String>,
+    /// The month's documents that name their message
+    pub records: Vec<ArchivedFile>,
+    /// Documents archived before messages were recorded, left as they are
+    pub untraceable: usize,
+}
+
+impl ReprocessPlan {
+    /// Plan from the documents filed under `month`, None when none of them names its message
+    pub fn new(month: NaiveDate, records: Vec<ArchivedFile>) -> Option<Self> {
+        let (records, untraceable): (Vec<_>, Vec<_>) = records.into_iter().partition(|record| record.message_id.is_some());
+        if records.is_empty() {
+            return None;
+        }
+
+        let mut message_ids = Vec::new();
+        for record in &records {
+            let message_id = record.message_id.clone().unwrap_or_default();
+            if !message_ids.contains(&message_id) {
+                message_ids.push(message_id);
+            }
+        }
+
+        // The folder above the month's (`billing/2024/November/Revolut` -> `billing/2024`), the
+        // most common one should documents disagree
+        let month_name = jobs::month_name(month);
+        let mut parents: HashMap<String, usize> = HashMap::new();
+        for record in &records {
+            let parts: Vec<&str> = record.folder.split('/').collect();
+            if let Some(index) = parts.iter().rposition(|part| *part == month_name) {
+                *parents.entry(parts[..index].join("/")).or_default() += 1;
+            }
+        }
+        let folder_path = parents.into_iter().max_by_key(|(path, count)| (*count, std::cmp::Reverse(path.clone())))?.0;
+
+        Some(Self { month, folder_path, message_ids, records, untraceable: untraceable.len() })
+    }
+}
+
+/// Old documents the new run archived again elsewhere: same message, same contents, another
+/// file. A file several documents share (a month zipped by `archive maintain`) is never replaced.
+pub fn superseded<'a>(old: &'a [ArchivedFile], new: &[ArchivedFile]) -> Vec<&'a ArchivedFile> {
+    let mut shared: HashMap<&str, usize> = HashMap::new();
+    for record in old {
+        *shared.entry(record.file_id.as_str()).or_default() += 1;
+    }
+
+    old.iter()
+        .filter(|record| shared[record.file_id.as_str()] == 1 && record.sha256.is_some())
+        .filter(|record| {
+            new.iter().any(|copy| copy.message_id == record.message_id && copy.sha256 == record.sha256 && copy.file_id != record.file_id)
+        })
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct ReprocessSummary {
+    pub run: RunSummary,
+    /// Old copies moved to the trash
+    pub replaced: usize,
+    /// Old copies that could not be removed
+    pub failed: usize,
+    /// Old copies of messages the new run archived with different contents, left for a person to compare
+    pub kept: Vec<String>,
+}
+
+/// Archive the plan's messages again, then remove the copies the new run replaced (unless
+/// `keep_old`). Only documents recorded by the new run count as replacements, so mock runs,
+/// which are never recorded, leave every old copy in place.
+pub async fn reprocess(config: &Config, pool: &DbPool, plan: &ReprocessPlan, keep_old: bool, tx: &mpsc::UnboundedSender<String>) -> Result<ReprocessSummary> {
+    let mut config = config.clone();
+    config.drive_folder_path = plan.folder_path.clone();
+    let (source, storage) = jobs::connect(&config, tx).await?;
+
+    tx.send(format!("📨 Fetching {} message(s) again from {}...", plan.message_ids.len(), source.name()))?;
+    let mut messages = Vec::new();
+    let mut fetch_failures = Vec::new();
+    for message_id in &plan.message_ids {
+        match source.fetch_message(message_id).await {
+            Ok(message) => messages.push(message),
+            Err(e) => {
+                tx.send(format!("      ✗ Failed to fetch message {}: {}", message_id, e))?;
+                fetch_failures.push((message_id, e));
+            }
+        }
+    }
+
+    // The whole range within the month, so every document is filed under it again
+    let last_day = plan.month.checked_add_months(Months::new(1)).and_then(|next| next.pred_opt()).unwrap_or(plan.month);
+    let mut run = if messages.is_empty() {
+        RunSummary::default()
+    } else {
+        jobs::archive_messages(&config, source.as_ref(), storage.as_ref(), &messages, plan.month, last_day, tx).await?
+    };
+    for (message_id, error) in fetch_failures {
+        run.fail(Stage::Fetch, Some(message_id), None, &error);
+    }
+
+    let mut summary = ReprocessSummary { run, ..Default::default() };
+    if keep_old {
+        return Ok(summary);
+    }
+
+    let profile = config.profile.clone().unwrap_or_default();
+    let old_ids: HashSet<i32> = plan.records.iter().map(|record| record.id).collect();
+    let new: Vec<ArchivedFile> = db::archived_files(pool, &profile, Some(plan.month))
+        .await?
+        .into_iter()
+        .filter(|record| !old_ids.contains(&record.id))
+        .collect();
+    let replaced = superseded(&plan.records, &new);
+    summary.kept = plan
+        .records
+        .iter()
+        .filter(|record| !replaced.iter().any(|replaced| replaced.id == record.id))
+        .filter(|record| new.iter().any(|copy| copy.message_id == record.message_id && copy.file_id != record.file_id))
+        .filter(|record| !new.iter().any(|copy| copy.file_id == record.file_id))
+        .map(|record| format!("{}/{}", record.folder, record.filename))
+        .collect();
+    if replaced.is_empty() {
+        return Ok(summary);
+    }
+
+    tx.send(format!("🗑 Moving {} replaced file(s) to the Drive trash...", replaced.len()))?;
+    let client = storage::drive_client(&config).await?;
+    for record in replaced {
+        let removed = async {
+            drive::folder::trash_item(&client, &record.file_id).await?;
+            db::delete_archived_file(pool, record.id).await
+        };
+        match removed.await {
+            Ok(()) => {
+                summary.replaced += 1;
+                tx.send(format!("  ✓ {}/{}", record.folder, record.filename))?;
+            }
+            Err(e) => {
+                summary.failed += 1;
+                tx.send(format!("  ✗ {}/{}: {:#}", record.folder, record.filename, e))?;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: i32, folder: &str, file_id: &str, message_id: Option<&str>, sha256: &str) -> ArchivedFile {
+        ArchivedFile {
+            id,
+            filename: format!("{}.pdf", file_id),
+            folder: folder.to_string(),
+            file_id: file_id.to_string(),
+            message_id: message_id.map(str::to_string),
+            size_bytes: Some(9),
+            sha256: Some(sha256.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_plan_and_supersede() {
+        let month = NaiveDate::from_ymd_opt(2024, 11, 1).unwrap();
+        let old = vec![
+            record(1, "billing/2024/November/Revolut", "a", Some("m1"), "sha-a"),
+            record(2, "billing/2024/November", "b", Some("m1"), "sha-b"),
+            record(3, "billing/2024/November", "c", Some("m2"), "sha-c"),
+            record(4, "billing/2024/November", "zip", Some("m3"), "sha-d"),
+            record(5, "billing/2024/November", "zip", Some("m3"), "sha-e"),
+            record(6, "billing/2024/November", "d", None, "sha-f"),
+        ];
+        let plan = ReprocessPlan::new(month, old).unwrap();
+        assert_eq!(plan.folder_path, "billing/2024");
+        assert_eq!(plan.message_ids, vec!["m1", "m2", "m3"]);
+        assert_eq!(plan.untraceable, 1);
+        assert!(ReprocessPlan::new(month, vec![record(7, "billing/2024/November", "e", None, "sha")]).is_none());
+
+        // `a` moved to a new folder; `b` was skipped as already there; `c` changed in the
+        // pipeline; the zipped documents are never replaced
+        let new = vec![
+            record(10, "billing/2024/November/Revolut Bank", "a2", Some("m1"), "sha-a"),
+            record(11, "billing/2024/November", "c2", Some("m2"), "sha-c2"),
+            record(12, "billing/2024/November", "d2", Some("m3"), "sha-d"),
+        ];
+        let replaced: Vec<i32> = superseded(&plan.records, &new).iter().map(|record| record.id).collect();
+        assert_eq!(replaced, vec![1]);
+    }
+}
@@ -0,0 +1,495 @@
+//! Retention rules for the Drive archive (`archive maintain`). Month folders past the configured
+//! ages are zipped into a single file, moved to a cold-storage folder, or deleted. Ages count
+//! from the end of the month, and a month is dated by the year folder it sits in
+//! (`billing/2019/March`).
+
+use crate::config::env::Config;
+use crate::db;
+use crate::drive;
+use crate::drive::client::{DriveClient, FileInfo};
+use crate::process::fiscal;
+use anyhow::{Context, Result};
+use chrono::{Datelike, Months, NaiveDate};
+use std::io::{Cursor, Write};
+use tokio::sync::mpsc;
+use zip::write::SimpleFileOptions;
+
+/// Folder levels searched for year folders below the top of the archive
+const MAX_DEPTH: usize = 3;
+
+/// Ages, in years after the end of a month, at which the rules apply (RETENTION_*)
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    pub compress_after_years: Option<u32>,
+    pub cold_storage_after_years: Option<u32>,
+    /// Drive folder path months are moved into, under a folder per year
+    pub cold_storage_folder: Option<String>,
+    /// The legal retention period: older months are deleted
+    pub delete_after_years: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Compress,
+    MoveToColdStorage,
+    Delete,
+}
+
+impl Action {
+    fn describe(self) -> &'static str {
+        match self {
+            Action::Compress => "compress into one ZIP",
+            Action::MoveToColdStorage => "move to cold storage",
+            Action::Delete => "delete",
+        }
+    }
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.compress_after_years.is_none() && self.cold_storage_after_years.is_none() && self.delete_after_years.is_none()
+    }
+
+    /// One line describing the rules, for the plan
+    pub fn describe(&self) -> String {
+        let mut rules = Vec::new();
+        if let Some(years) = self.compress_after_years {
+            rules.push(format!("compress after {} year(s)", years));
+        }
+        if let (Some(years), Some(folder)) = (self.cold_storage_after_years, &self.cold_storage_folder) {
+            rules.push(format!("move to {} after {} year(s)", folder, years));
+        }
+        if let Some(years) = self.delete_after_years {
+            rules.push(format!("delete after {} year(s)", years));
+        }
+        rules.join(", ")
+    }
+
+    /// Actions due for a month on `today`, in the order they are applied. Deletion replaces the
+    /// others; months already zipped or in cold storage aren't zipped or moved again.
+    pub fn due(&self, month: NaiveDate, compressed: bool, in_cold_storage: bool, today: NaiveDate) -> Vec<Action> {
+        // Past `years` once that many years have gone by since the month ended
+        let past = |years: Option<u32>| {
+            years
+                .and_then(|years| month.with_day(1)?.checked_add_months(Months::new(12 * years + 1)))
+                .is_some_and(|due| due <= today)
+        };
+
+        if past(self.delete_after_years) {
+            return vec![Action::Delete];
+        }
+        let mut actions = Vec::new();
+        if past(self.compress_after_years) && !compressed {
+            actions.push(Action::Compress);
+        }
+        if past(self.cold_storage_after_years) && !in_cold_storage {
+            actions.push(Action::MoveToColdStorage);
+        }
+        actions
+    }
+}
+
+/// A month folder of the archive
+#[derive(Debug, Clone)]
+pub struct MonthFolder {
+    pub path: String,
+    pub id: String,
+    /// The year folder holding it
+    pub parent_id: String,
+    pub month: NaiveDate,
+    pub in_cold_storage: bool,
+}
+
+/// What `archive maintain` will do with one month
+#[derive(Debug)]
+pub struct PlannedMonth {
+    pub folder: MonthFolder,
+    pub actions: Vec<Action>,
+    /// Files the ZIP will hold, by path inside the month folder
+    files: Vec<(String, FileInfo)>,
+    /// Why the month is left alone although an action is due
+    pub blocked: Option<String>,
+}
+
+impl PlannedMonth {
+    pub fn describe(&self) -> String {
+        let actions: Vec<String> = self
+            .actions
+            .iter()
+            .map(|action| match action {
+                Action::Compress => format!("{} ({} file(s))", action.describe(), self.files.len()),
+                _ => action.describe().to_string(),
+            })
+            .collect();
+        match &self.blocked {
+            Some(reason) => format!("⚠ {}: skipped, {}", self.folder.path, reason),
+            None => format!("{}: {}", self.folder.path, actions.join(", then ")),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MaintenancePlan {
+    /// Months with an action due
+    pub months: Vec<PlannedMonth>,
+    /// Month folders outside a year folder, which can't be dated
+    pub undated: Vec<String>,
+}
+
+impl MaintenancePlan {
+    /// Months that will actually be changed
+    pub fn pending(&self) -> usize {
+        self.months.iter().filter(|month| month.blocked.is_none()).count()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MaintenanceSummary {
+    pub compressed: usize,
+    pub moved: usize,
+    pub deleted: usize,
+    pub failed: usize,
+    /// Size of the ZIPs written
+    pub archive_bytes: usize,
+}
+
+/// Top of the managed tree: the folder holding the year folders when GOOGLE_DRIVE_FOLDER_LOCATION
+/// ends in a year ("billing/all-expenses/2025" → "billing/all-expenses") or a placeholder
+/// ("billing/{year}" → "billing"), otherwise that folder
+pub fn managed_root(drive_folder_path: &str) -> String {
+    let root = fiscal::fixed_root(drive_folder_path);
+    let path = root.trim_matches('/');
+    match path.rsplit_once('/') {
+        Some((parent, last)) if parse_year(last).is_some() => parent.to_string(),
+        None if parse_year(path).is_some() => String::new(),
+        _ => path.to_string(),
+    }
+}
+
+pub fn parse_year(name: &str) -> Option<i32> {
+    if name.len() != 4 {
+        return None;
+    }
+    name.parse().ok().filter(|year| (1900..=2999).contains(year))
+}
+
+/// Month number of a folder named after a month, as the pipeline names them ("March")
+pub fn parse_month(name: &str) -> Option<u32> {
+    (1..=12u8).find(|number| chrono::Month::try_from(*number).is_ok_and(|month| month.name().eq_ignore_ascii_case(name))).map(u32::from)
+}
+
+pub fn join(parent: &str, name: &str) -> String {
+    if parent.is_empty() { name.to_string() } else { format!("{}/{}", parent, name) }
+}
+
+/// Name of the ZIP a month is compressed into
+fn archive_name(month: NaiveDate) -> String {
+    format!("invoices-{}-{:02}.zip", month.year(), month.month())
+}
+
+pub fn is_folder(file: &FileInfo) -> bool {
+    file.mime_type.as_deref() == Some(drive::folder::FOLDER_MIME_TYPE)
+}
+
+/// Month folders below `root_path`, leaving out the `skip` subtree. Month folders that are not in
+/// a year folder are added to `undated`.
+async fn find_month_folders(
+    client: &DriveClient,
+    root_path: &str,
+    skip: Option<&str>,
+    in_cold_storage: bool,
+    undated: &mut Vec<String>,
+) -> Result<Vec<MonthFolder>> {
+    let Some(root_id) = drive::folder::find_folder_by_path(client, root_path).await? else {
+        return Ok(Vec::new());
+    };
+
+    let mut months = Vec::new();
+    let mut pending = vec![(root_id, root_path.to_string(), None, 0)];
+    while let Some((folder_id, path, year, depth)) = pending.pop() {
+        for child in drive::folder::list_folder(client, &folder_id).await? {
+            if !is_folder(&child) {
+                continue;
+            }
+            let child_path = join(&path, &child.name);
+            if let Some(month) = parse_month(&child.name) {
+                match year.and_then(|year| NaiveDate::from_ymd_opt(year, month, 1)) {
+                    Some(month) => months.push(MonthFolder {
+                        path: child_path,
+                        id: child.id,
+                        parent_id: folder_id.clone(),
+                        month,
+                        in_cold_storage,
+                    }),
+                    None => undated.push(child_path),
+                }
+            } else if depth < MAX_DEPTH && skip != Some(child_path.as_str()) {
+                let year = parse_year(&child.name).or(year);
+                pending.push((child.id, child_path, year, depth + 1));
+            }
+        }
+    }
+    Ok(months)
+}
+
+/// Every file below a folder, by path inside it
+pub async fn list_files(client: &DriveClient, folder_id: &str) -> Result<Vec<(String, FileInfo)>> {
+    let mut files = Vec::new();
+    let mut pending = vec![(folder_id.to_string(), String::new())];
+    while let Some((folder_id, prefix)) = pending.pop() {
+        for file in drive::folder::list_folder(client, &folder_id).await? {
+            let path = join(&prefix, &file.name);
+            if is_folder(&file) {
+                pending.push((file.id, path));
+            } else {
+                files.push((path, file));
+            }
+        }
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(files)
+}
+
+/// Find what the rules make due on `today`, reading the tree but changing nothing
+pub async fn plan(client: &DriveClient, drive_folder_path: &str, policy: &RetentionPolicy, today: NaiveDate) -> Result<MaintenancePlan> {
+    let root = managed_root(drive_folder_path);
+    let cold = policy.cold_storage_folder.as_deref().map(|folder| folder.trim_matches('/'));
+
+    let mut plan = MaintenancePlan::default();
+    let mut folders = find_month_folders(client, &root, cold, false, &mut plan.undated).await?;
+    if let Some(cold) = cold {
+        folders.extend(find_month_folders(client, cold, None, true, &mut plan.undated).await?);
+    }
+    folders.sort_by_key(|folder| (folder.month, folder.path.clone()));
+
+    for folder in folders {
+        // Only months that could be due are listed
+        if policy.due(folder.month, false, folder.in_cold_storage, today).is_empty() {
+            continue;
+        }
+
+        let files = list_files(client, &folder.id).await?;
+        let archive = archive_name(folder.month);
+        let compressed = files.iter().any(|(path, _)| *path == archive);
+        // An empty month has nothing to zip, but can still be moved or deleted
+        let compressed = compressed || files.is_empty();
+        let actions = policy.due(folder.month, compressed, folder.in_cold_storage, today);
+        if actions.is_empty() {
+            continue;
+        }
+
+        let mut blocked = None;
+        if compressed && files.len() > 1 {
+            blocked = Some(format!("{} is there next to {} other file(s); remove whichever is left over", archive, files.len() - 1));
+        } else if actions.contains(&Action::Compress)
+            && files.iter().any(|(_, file)| file.mime_type.as_deref().is_some_and(|m| m.starts_with("application/vnd.google-apps")))
+        {
+            blocked = Some("it holds Google Docs files, which can't be zipped".to_string());
+        }
+        if blocked.is_none()
+            && actions.contains(&Action::MoveToColdStorage)
+            && let Some(cold) = cold
+        {
+            let target = format!("{}/{}/{}", cold, folder.month.year(), month_folder_name(&folder.path));
+            if drive::folder::find_folder_by_path(client, &target).await?.is_some() {
+                blocked = Some(format!("{} already exists", target));
+            }
+        }
+
+        plan.months.push(PlannedMonth { folder, actions, files, blocked });
+    }
+    Ok(plan)
+}
+
+fn month_folder_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Apply a plan. A month that fails is reported and the others still go ahead.
+pub async fn apply(config: &Config, client: &DriveClient, plan: &MaintenancePlan, tx: &mpsc::UnboundedSender<String>) -> Result<MaintenanceSummary> {
+    let policy = &config.retention;
+    let pool = if config.mock_mode { None } else { db::connect_optional("Archived document records").await };
+    let profile = config.profile.clone().unwrap_or_default();
+
+    let mut summary = MaintenanceSummary::default();
+    for planned in plan.months.iter().filter(|month| month.blocked.is_none()) {
+        let folder = &planned.folder;
+        tx.send(format!("🗄 {}", folder.path))?;
+        let mut path = folder.path.clone();
+        for action in &planned.actions {
+            let result = match action {
+                Action::Compress => compress(config, client, planned, tx).await.map(|(file_id, link, size)| {
+                    summary.compressed += 1;
+                    summary.archive_bytes += size;
+                    Some((path.clone(), Some((file_id, link))))
+                }),
+                Action::MoveToColdStorage => {
+                    let cold = policy.cold_storage_folder.as_deref().context("RETENTION_COLD_STORAGE_FOLDER not set")?;
+                    let year_path = format!("{}/{}", cold.trim_matches('/'), folder.month.year());
+                    let moved = async {
+                        let year_id = drive::folder::find_or_create_folder(client, &year_path).await?;
+                        drive::folder::move_item(client, &folder.id, &folder.parent_id, &year_id).await
+                    };
+                    moved.await.map(|()| {
+                        summary.moved += 1;
+                        Some((join(&year_path, month_folder_name(&folder.path)), None))
+                    })
+                }
+                Action::Delete => drive::folder::trash_item(client, &folder.id).await.map(|()| {
+                    summary.deleted += 1;
+                    None
+                }),
+            };
+
+            match result {
+                Ok(relocated) => {
+                    tx.send(format!("  ✓ {}", action.describe()))?;
+                    if let Some(pool) = &pool {
+                        let recorded = match &relocated {
+                            Some((new_path, archive)) => {
+                                let archive = archive.as_ref().map(|(file_id, link)| (file_id.as_str(), link.as_str()));
+                                db::relocate_invoice_documents(pool, &profile, &path, new_path, archive).await
+                            }
+                            None => db::delete_invoice_documents(pool, &profile, &path).await,
+                        };
+                        if let Err(e) = recorded {
+                            tx.send(format!("  ⚠ Could not update the invoice database: {:#}", e))?;
+                        }
+                    }
+                    if let Some((new_path, _)) = relocated {
+                        path = new_path;
+                    }
+                }
+                Err(e) => {
+                    summary.failed += 1;
+                    tx.send(format!("  ✗ Failed to {}: {:#}", action.describe(), e))?;
+                    break;
+                }
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Zip a month's files into one, upload it into the month folder, then delete the files it holds
+/// and the subfolders they leave empty. Anything added since the plan isn't in the ZIP, so it is
+/// left in place with a warning. Returns the ZIP's file id, link and size.
+async fn compress(config: &Config, client: &DriveClient, planned: &PlannedMonth, tx: &mpsc::UnboundedSender<String>) -> Result<(String, String, usize)> {
+    let name = archive_name(planned.folder.month);
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (path, file) in &planned.files {
+        let data = drive::download::download_file(client, &file.id).await.with_context(|| format!("Failed to download {}", path))?;
+        zip.start_file(path.as_str(), options)?;
+        zip.write_all(&data)?;
+    }
+    let archive = zip.finish()?.into_inner();
+
+    let temp_dir = config.temp_dir();
+    std::fs::create_dir_all(&temp_dir).context("Failed to create temp directory")?;
+    let archive_path = temp_dir.join(&name);
+    std::fs::write(&archive_path, &archive).context("Failed to write ZIP to temp file")?;
+    let uploaded = drive::upload::upload_file(client, &archive_path, &planned.folder.id, false, None).await;
+    let _ = std::fs::remove_file(&archive_path);
+    let uploaded = uploaded?;
+    tx.send(format!("  ✓ Uploaded {} ({} file(s), {} KB)", name, planned.files.len(), archive.len() / 1024))?;
+
+    // The originals go only once the ZIP is safely in Drive
+    for (path, file) in &planned.files {
+        drive::folder::trash_item(client, &file.id).await.with_context(|| format!("Failed to delete {}", path))?;
+    }
+
+    let mut folders = Vec::new();
+    let mut pending = vec![(planned.folder.id.clone(), String::new())];
+    while let Some((folder_id, prefix)) = pending.pop() {
+        for item in drive::folder::list_folder(client, &folder_id).await? {
+            let path = join(&prefix, &item.name);
+            if is_folder(&item) {
+                pending.push((item.id.clone(), path.clone()));
+                folders.push((item.id, path));
+            } else if item.id != uploaded.id {
+                tx.send(format!("  ⚠ Left {} in place: it was added after the plan, so it isn't in {}", path, name))?;
+            }
+        }
+    }
+    // Subfolders come after their parent, so going backwards empties a folder before its parent
+    for (folder_id, path) in folders.iter().rev() {
+        if drive::folder::list_folder(client, folder_id).await?.is_empty() {
+            drive::folder::trash_item(client, folder_id).await.with_context(|| format!("Failed to delete {}", path))?;
+        }
+    }
+    Ok((uploaded.id.clone(), uploaded.link(), archive.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_plan_by_age() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        for dir in ["billing/2014/March/Revolut", "billing/2019/June", "billing/2024/May", "billing/March", "cold/2018/July"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join("invoice.pdf"), b"%PDF-1.4\n").unwrap();
+        }
+        std::fs::rename(root.join("cold/2018/July/invoice.pdf"), root.join("cold/2018/July/invoices-2018-07.zip")).unwrap();
+
+        let policy = RetentionPolicy {
+            compress_after_years: Some(3),
+            cold_storage_after_years: Some(5),
+            cold_storage_folder: Some("cold".to_string()),
+            delete_after_years: Some(10),
+        };
+        let today = NaiveDate::from_ymd_opt(2025, 10, 15).unwrap();
+        let client = DriveClient::mock(root);
+        let plan = plan(&client, "billing/2025", &policy, today).await.unwrap();
+
+        let due: Vec<(&str, &[Action])> = plan.months.iter().map(|m| (m.folder.path.as_str(), m.actions.as_slice())).collect();
+        assert_eq!(
+            due,
+            vec![
+                ("billing/2014/March", &[Action::Delete][..]),
+                ("billing/2019/June", &[Action::Compress, Action::MoveToColdStorage][..]),
+            ]
+        );
+        // Already zipped and in cold storage, not yet past the retention period
+        assert!(!plan.months.iter().any(|m| m.folder.path.starts_with("cold")));
+        assert_eq!(plan.undated, vec!["billing/March"]);
+
+        // April 2015 is past ten years only once April is over
+        let april = NaiveDate::from_ymd_opt(2015, 4, 1).unwrap();
+        assert!(policy.due(april, false, false, NaiveDate::from_ymd_opt(2025, 4, 30).unwrap()).contains(&Action::Compress));
+        assert_eq!(policy.due(april, false, false, NaiveDate::from_ymd_opt(2025, 5, 1).unwrap()), vec![Action::Delete]);
+    }
+
+    #[tokio::test]
+    async fn test_compress_keeps_files_added_after_the_plan() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let month = root.join("billing/2019/June");
+        std::fs::create_dir_all(month.join("Revolut")).unwrap();
+        std::fs::write(month.join("invoice.pdf"), b"%PDF-1.4\n").unwrap();
+        std::fs::write(month.join("Revolut/statement.pdf"), b"%PDF-1.4\n").unwrap();
+
+        let policy = RetentionPolicy { compress_after_years: Some(3), ..Default::default() };
+        let client = DriveClient::mock(root);
+        let plan = plan(&client, "billing/2025", &policy, NaiveDate::from_ymd_opt(2025, 10, 15).unwrap()).await.unwrap();
+        let planned = plan.months.iter().find(|m| m.folder.path == "billing/2019/June").unwrap();
+        std::fs::write(month.join("late.pdf"), b"%PDF-1.4\n").unwrap();
+
+        let config = Config::for_test(&[]);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        compress(&config, &client, planned, &tx).await.unwrap();
+        assert!(month.join("invoices-2019-06.zip").exists());
+        assert!(!month.join("invoice.pdf").exists());
+        assert!(!month.join("Revolut").exists());
+        assert!(month.join("late.pdf").exists());
+        drop(tx);
+        let mut warned = false;
+        while let Some(line) = rx.recv().await {
+            warned |= line.contains("Left late.pdf in place");
+        }
+        assert!(warned);
+    }
+}
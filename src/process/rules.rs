@@ -0,0 +1,262 @@
+//! Per-sender processing rules (RULES_FILE, `rules.toml` by default): skip a sender, unlock its
+//! password-protected PDFs, merge a message's PDFs into one, or choose the filename, folder and
+//! category of its documents. The first rule whose `sender` matches a message applies.
+//!
+//! ```toml
+//! [[rule]]
+//! sender = "hetzner.com"
+//! filename = "{vendor}-{date}-{name}.{ext}"
+//! folder = "Hosting"
+//! category = "Infrastructure"
+//!
+//! [[rule]]
+//! sender = "aws.com"
+//! filename = "{date}_{vendor}_{amount}.{ext}"
+//!
+//! [[rule]]
+//! sender = "statements@mybank.example"
+//! pdf_password = "123456"
+//! merge = true
+//! ```
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::path::Path;
+use tokio::process::Command;
+use crate::config::env::Config;
+use crate::extract::invoice::format_amount;
+
+/// Placeholders a filename template may use
+const PLACEHOLDERS: &[&str] = &["vendor", "name", "ext", "date", "subject", "amount"];
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rules {
+    #[serde(default, rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Part of the From header, name or address (case-insensitive), e.g. "hetzner.com"
+    pub sender: String,
+    /// Don't archive anything from the sender
+    #[serde(default)]
+    pub skip: bool,
+    /// Filename template, e.g. "{vendor}-{date}-{name}.{ext}" or "{date}_{vendor}_{amount}.{ext}"
+    pub filename: Option<String>,
+    /// Institution folder under the month folder
+    pub folder: Option<String>,
+    /// Category used by the digest and budgets, instead of the folder
+    pub category: Option<String>,
+    /// Opens the sender's password-protected PDFs (the archived copy has no password)
+    pub pdf_password: Option<String>,
+    /// Merge the PDFs attached to one message into a single document
+    #[serde(default)]
+    pub merge: bool,
+}
+
+impl Rules {
+    /// Load the rules file; no file configured means no rules
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read rules file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid rules file {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rules: Self = toml::from_str(text)?;
+        for rule in &mut rules.rules {
+            rule.sender = rule.sender.trim().to_lowercase();
+            anyhow::ensure!(!rule.sender.is_empty(), "every rule needs a sender");
+            if let Some(template) = &rule.filename {
+                check_template(template).with_context(|| format!("rule for {}", rule.sender))?;
+            }
+        }
+        Ok(rules)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The first rule matching a From header
+    pub fn for_sender(&self, from: &str) -> Option<&Rule> {
+        let from = from.to_lowercase();
+        self.rules.iter().find(|rule| from.contains(rule.sender.as_str()))
+    }
+}
+
+fn check_template(template: &str) -> Result<()> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').context("unclosed { in filename template")? + start;
+        let placeholder = &rest[start + 1..end];
+        anyhow::ensure!(
+            PLACEHOLDERS.contains(&placeholder),
+            "unknown placeholder {{{}}} in filename template (use {})",
+            placeholder,
+            PLACEHOLDERS.iter().map(|p| format!("{{{}}}", p)).collect::<Vec<_>>().join(", ")
+        );
+        rest = &rest[end + 1..];
+    }
+    Ok(())
+}
+
+/// What a filename template is filled in with
+pub struct FilenameParts<'a> {
+    /// Sender's filename prefix
+    pub vendor: &'a str,
+    /// Attachment filename as sent
+    pub original: &'a str,
+    pub received: Option<NaiveDate>,
+    pub subject: &'a str,
+    /// Total extracted from the document, with its currency
+    pub amount_cents: Option<i64>,
+    pub currency: Option<&'a str>,
+}
+
+impl FilenameParts<'_> {
+    /// "EUR-231.40", "231.40" without a currency, "EUR-(12.50)" for credit notes
+    fn amount(&self) -> Option<String> {
+        let cents = self.amount_cents?;
+        let amount = if cents < 0 { format!("({})", format_amount(-cents)) } else { format_amount(cents) };
+        Some(match self.currency {
+            Some(currency) => format!("{}-{}", currency, amount),
+            None => amount,
+        })
+    }
+}
+
+/// Fill in a filename template. The extension is added when the template leaves `{ext}` out,
+/// and path separators are replaced so the name stays in its folder. Without an amount,
+/// `{amount}` is dropped together with the separator before it.
+pub fn render_filename(template: &str, parts: &FilenameParts) -> String {
+    let original = Path::new(parts.original);
+    let name = original.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let ext = original.extension().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+
+    let template = match parts.amount() {
+        Some(amount) => template.replace("{amount}", &amount),
+        None => ["_{amount}", "-{amount}", " {amount}", "{amount}"].iter().fold(template.to_string(), |template, placeholder| template.replace(placeholder, "")),
+    };
+    let mut filename = template
+        .replace("{vendor}", parts.vendor)
+        .replace("{name}", &name)
+        .replace("{ext}", &ext)
+        .replace("{date}", &parts.received.map(|date| date.to_string()).unwrap_or_default())
+        .replace("{subject}", parts.subject.trim());
+    if !template.contains("{ext}") && !ext.is_empty() {
+        filename = format!("{}.{}", filename, ext);
+    }
+    filename.replace(['/', '\\'], "-").trim().to_string()
+}
+
+pub fn is_pdf(filename: &str) -> bool {
+    Path::new(filename).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
+
+/// Whether a PDF is encrypted (its trailer references an /Encrypt dictionary)
+pub fn is_encrypted_pdf(data: &[u8]) -> bool {
+    data.windows(b"/Encrypt".len()).any(|window| window == b"/Encrypt")
+}
+
+/// Rewrite a password-protected PDF without the password, with Ghostscript
+pub async fn unlock_pdf(config: &Config, data: &[u8], password: &str) -> Result<Vec<u8>> {
+    run_ghostscript(config, &[data], Some(password)).await
+}
+
+/// Concatenate PDFs into one, in order, with Ghostscript
+pub async fn merge_pdfs(config: &Config, documents: &[&[u8]]) -> Result<Vec<u8>> {
+    run_ghostscript(config, documents, None).await
+}
+
+async fn run_ghostscript(config: &Config, documents: &[&[u8]], password: Option<&str>) -> Result<Vec<u8>> {
+    let dir = config.temp_dir().join(format!("rules-{}-{}", std::process::id(), rand::random::<u32>()));
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let result = async {
+        let mut inputs = Vec::new();
+        for (index, data) in documents.iter().enumerate() {
+            let input = dir.join(format!("input-{}.pdf", index));
+            std::fs::write(&input, data)?;
+            inputs.push(input);
+        }
+        let output = dir.join("output.pdf");
+
+        let mut command = Command::new(&config.ghostscript_path);
+        command
+            .arg("-sDEVICE=pdfwrite")
+            .args(["-dNOPAUSE", "-dQUIET", "-dBATCH"])
+            .arg(format!("-sOutputFile={}", output.display()));
+        if let Some(password) = password {
+            command.arg(format!("-sPDFPassword={}", password));
+        }
+        let status = command
+            .args(&inputs)
+            .kill_on_drop(true)
+            .status()
+            .await
+            .with_context(|| format!("Failed to run {}", config.ghostscript_path))?;
+        anyhow::ensure!(status.success(), "{} exited with {}", config.ghostscript_path, status);
+
+        let data = std::fs::read(&output).context("Ghostscript wrote no output")?;
+        anyhow::ensure!(!data.is_empty(), "Ghostscript wrote an empty document");
+        Ok(data)
+    }
+    .await;
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules() {
+        let rules = Rules::parse(
+            r#"
+            [[rule]]
+            sender = "Hetzner.com"
+            filename = "{vendor}-{date}-{name}"
+            folder = "Hosting"
+            category = "Infrastructure"
+
+            [[rule]]
+            sender = "newsletter@"
+            skip = true
+            "#,
+        )
+        .unwrap();
+
+        let rule = rules.for_sender("Hetzner Online GmbH <billing@HETZNER.com>").unwrap();
+        assert_eq!(rule.folder.as_deref(), Some("Hosting"));
+        assert!(rules.for_sender("news <newsletter@shop.example>").unwrap().skip);
+        assert!(rules.for_sender("AWS <billing@aws.com>").is_none());
+
+        let parts = FilenameParts {
+            vendor: "hetzner-online-gmbh",
+            original: "R0012345678.pdf",
+            received: NaiveDate::from_ymd_opt(2025, 3, 7),
+            subject: "Your invoice",
+            amount_cents: None,
+            currency: None,
+        };
+        assert_eq!(render_filename(rule.filename.as_deref().unwrap(), &parts), "hetzner-online-gmbh-2025-03-07-R0012345678.pdf");
+        assert_eq!(render_filename("{subject}/{name}.{ext}", &parts), "Your invoice-R0012345678.pdf");
+        assert_eq!(render_filename("{date}_{vendor}_{amount}", &parts), "2025-03-07_hetzner-online-gmbh.pdf");
+
+        let aws = FilenameParts { vendor: "aws", received: NaiveDate::from_ymd_opt(2025, 3, 4), amount_cents: Some(23140), currency: Some("EUR"), ..parts };
+        assert_eq!(render_filename("{date}_{vendor}_{amount}", &aws), "2025-03-04_aws_EUR-231.40.pdf");
+        let refund = FilenameParts { amount_cents: Some(-1250), ..aws };
+        assert_eq!(render_filename("{date}_{vendor}_{amount}", &refund), "2025-03-04_aws_EUR-(12.50).pdf");
+
+        assert!(Rules::parse("[[rule]]\nsender = \"x\"\nfilename = \"{invoice}\"").is_err());
+        assert!(Rules::parse("[[rule]]\nsender = \"x\"\nfoldr = \"typo\"").is_err());
+        assert!(Rules::parse("[[rule]]\nsender = \" \"").is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Filename sanitization (FILENAME_SANITIZE): names built from subjects, senders and templates
+//! are made safe for a Drive folder synced to Windows, after every rule, plugin and correction
+//! has had its say and before anything is uploaded or saved.
+
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Longest name kept, leaving room for the folders in front of it under Windows' 260 character paths
+const MAX_NAME_CHARS: usize = 150;
+
+/// Device names Windows won't create a file under, whatever the extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters Windows doesn't allow in a name
+const FORBIDDEN_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum Strictness {
+    /// Names are kept as they are
+    Off,
+    /// Forbidden characters, trailing dots and spaces, reserved and overlong names are fixed
+    Windows,
+    /// As `Windows`, and only ASCII letters, digits, `.`, `-` and `_` are kept
+    Strict,
+}
+
+impl Strictness {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "off" | "" => Ok(Self::Off),
+            "windows" => Ok(Self::Windows),
+            "strict" => Ok(Self::Strict),
+            other => anyhow::bail!("FILENAME_SANITIZE must be off, windows or strict (got '{}')", other),
+        }
+    }
+}
+
+/// A file or folder name made safe at the given strictness
+/// ("Invoice: March?.pdf" → "Invoice- March-.pdf", or "Invoice-March-.pdf" when strict)
+pub fn sanitize_name(name: &str, strictness: Strictness) -> String {
+    if strictness == Strictness::Off {
+        return name.to_string();
+    }
+
+    let mut name: String = match strictness {
+        Strictness::Strict => deunicode::deunicode_with_tofu(name, "-")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '-' })
+            .collect::<String>()
+            .split('-')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-"),
+        _ => name.chars().map(|c| if c.is_control() || FORBIDDEN_CHARS.contains(&c) { '-' } else { c }).collect(),
+    };
+
+    let path = Path::new(&name);
+    let ext = path.extension().map(|ext| ext.to_string_lossy().to_string()).filter(|ext| !ext.trim_end_matches([' ', '.']).is_empty());
+    if name.chars().count() > MAX_NAME_CHARS {
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+        let keep = MAX_NAME_CHARS.saturating_sub(ext.as_ref().map_or(0, |ext| ext.chars().count() + 1));
+        let stem: String = stem.chars().take(keep).collect();
+        name = match &ext {
+            Some(ext) => format!("{}.{}", stem.trim_end_matches([' ', '.']), ext),
+            None => stem,
+        };
+    }
+
+    let mut name = name.trim().trim_end_matches([' ', '.']).to_string();
+    let device = name.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(device)) {
+        name.insert(0, '_');
+    }
+    if name.is_empty() { "file".to_string() } else { name }
+}
+
+/// Each folder of a path sanitized on its own ("Acme: EU/Credit Notes" → "Acme- EU/Credit Notes").
+/// Folders get the Windows fixes only, so `Credit Notes` and bank names keep their spaces.
+pub fn sanitize_folder(path: &str, strictness: Strictness) -> String {
+    let strictness = if strictness == Strictness::Off { Strictness::Off } else { Strictness::Windows };
+    path.split('/').map(|segment| sanitize_name(segment, strictness)).collect::<Vec<_>>().join("/")
+}
+
+/// Names already given out in a run, so two files cleaned to the same name in one folder stay apart
+#[derive(Default)]
+pub struct TakenNames(HashSet<(Option<String>, String)>);
+
+impl TakenNames {
+    /// `name` in `folder`, or "name-2.ext", "name-3.ext", ... when it's taken
+    pub fn claim(&mut self, folder: Option<&str>, name: &str) -> String {
+        let path = Path::new(name);
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+        let ext = path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+        let mut candidate = name.to_string();
+        let mut counter = 2;
+        while !self.0.insert((folder.map(str::to_lowercase), candidate.to_lowercase())) {
+            candidate = format!("{}-{}{}", stem, counter, ext);
+            counter += 1;
+        }
+        candidate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_name() {
+        assert_eq!(sanitize_name("Re: Invoice?*.pdf", Strictness::Off), "Re: Invoice?*.pdf");
+        assert_eq!(sanitize_name("Re: Invoice?*.pdf", Strictness::Windows), "Re- Invoice--.pdf");
+        assert_eq!(sanitize_name("Rechnung März: Nr. 5.pdf", Strictness::Strict), "Rechnung-Marz-Nr.-5.pdf");
+        assert_eq!(sanitize_name("Statement ...", Strictness::Windows), "Statement");
+        assert_eq!(sanitize_name("con.pdf", Strictness::Windows), "_con.pdf");
+        assert_eq!(sanitize_name("console.pdf", Strictness::Windows), "console.pdf");
+        assert_eq!(sanitize_name("?", Strictness::Strict), "file");
+
+        let long = sanitize_name(&format!("{}.pdf", "a".repeat(300)), Strictness::Windows);
+        assert_eq!(long.len(), MAX_NAME_CHARS);
+        assert!(long.ends_with("a.pdf"));
+
+        assert_eq!(sanitize_folder("Acme: EU/Credit Notes", Strictness::Strict), "Acme- EU/Credit Notes");
+
+        let mut taken = TakenNames::default();
+        assert_eq!(taken.claim(Some("Revolut"), "a-b.pdf"), "a-b.pdf");
+        assert_eq!(taken.claim(Some("Revolut"), "A-B.pdf"), "A-B-2.pdf");
+        assert_eq!(taken.claim(None, "a-b.pdf"), "a-b.pdf");
+    }
+}
@@ -0,0 +1,173 @@
+use crate::config::env::Config;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::process::Command;
+
+/// A scan of a large attachment should never take this long
+const SCAN_TIMEOUT: Duration = Duration::from_secs(120);
+const CLAMD_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanVerdict {
+    Clean,
+    Infected(String),
+}
+
+/// Scan a downloaded attachment with clamd (CLAMD_SOCKET) or VIRUS_SCAN_COMMAND.
+/// Returns None when scanning is not configured.
+pub async fn scan_file(config: &Config, path: &Path) -> Result<Option<ScanVerdict>> {
+    let verdict = if let Some(socket) = &config.clamd_socket {
+        tokio::time::timeout(SCAN_TIMEOUT, scan_with_clamd(socket, path)).await
+    } else if let Some(command) = &config.virus_scan_command {
+        tokio::time::timeout(SCAN_TIMEOUT, scan_with_command(command, path)).await
+    } else {
+        return Ok(None);
+    };
+
+    verdict
+        .with_context(|| format!("virus scan timed out after {}s", SCAN_TIMEOUT.as_secs()))?
+        .map(Some)
+}
+
+/// Stream the file to clamd with INSTREAM. `socket` is a unix socket path or host:port.
+async fn scan_with_clamd(socket: &str, path: &Path) -> Result<ScanVerdict> {
+    let data = tokio::fs::read(path).await?;
+
+    #[cfg(unix)]
+    if socket.starts_with('/') {
+        let stream = tokio::net::UnixStream::connect(socket).await
+            .with_context(|| format!("Failed to connect to clamd at {}", socket))?;
+        return clamd_instream(stream, &data).await;
+    }
+
+    let stream = tokio::net::TcpStream::connect(socket).await
+        .with_context(|| format!("Failed to connect to clamd at {}", socket))?;
+    clamd_instream(stream, &data).await
+}
+
+async fn clamd_instream<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, data: &[u8]) -> Result<ScanVerdict> {
+    stream.write_all(b"zINSTREAM\0").await?;
+    for chunk in data.chunks(CLAMD_CHUNK_SIZE) {
+        stream.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+        stream.write_all(chunk).await?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await?;
+    parse_clamd_reply(&String::from_utf8_lossy(&reply))
+}
+
+/// Parse replies like `stream: OK` or `stream: Eicar-Signature FOUND`
+fn parse_clamd_reply(reply: &str) -> Result<ScanVerdict> {
+    let reply = reply.trim_end_matches('\0').trim();
+    let result = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+
+    if result == "OK" {
+        Ok(ScanVerdict::Clean)
+    } else if let Some(signature) = result.strip_suffix("FOUND") {
+        Ok(ScanVerdict::Infected(signature.trim().to_string()))
+    } else {
+        anyhow::bail!("clamd error: {}", reply)
+    }
+}
+
+/// Run an external scanner with the file path appended; follows the clamscan convention:
+/// exit 0 = clean, 1 = infected, anything else = scanner error
+async fn scan_with_command(command: &str, path: &Path) -> Result<ScanVerdict> {
+    #[cfg(windows)]
+    let mut cmd = {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command).arg(path);
+        cmd
+    };
+    #[cfg(not(windows))]
+    let mut cmd = {
+        // The path is passed as $1 so it never needs shell quoting
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(format!("{} \"$1\"", command)).arg("sh").arg(path);
+        cmd
+    };
+
+    let output = cmd.kill_on_drop(true).output().await
+        .with_context(|| format!("Failed to run `{}`", command))?;
+
+    match output.status.code() {
+        Some(0) => Ok(ScanVerdict::Clean),
+        Some(1) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let signature = stdout
+                .lines()
+                .find_map(|line| line.trim().strip_suffix("FOUND"))
+                .and_then(|line| line.rsplit(':').next())
+                .map(|s| s.trim().to_string())
+                .unwrap_or_else(|| "flagged by scanner".to_string());
+            Ok(ScanVerdict::Infected(signature))
+        }
+        _ => anyhow::bail!("`{}` exited with {}", command, output.status),
+    }
+}
+
+/// Move a flagged file into the quarantine directory, never overwriting earlier catches
+pub fn quarantine(config: &Config, path: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(&config.quarantine_dir).context("Failed to create quarantine directory")?;
+
+    let name = path.file_name().context("file has no name")?.to_string_lossy().to_string();
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let mut target = config.quarantine_dir.join(format!("{}-{}", stamp, name));
+    let mut n = 1;
+    while target.exists() {
+        target = config.quarantine_dir.join(format!("{}-{}-{}", stamp, n, name));
+        n += 1;
+    }
+
+    if std::fs::rename(path, &target).is_err() {
+        // Temp and quarantine dirs may live on different filesystems
+        std::fs::copy(path, &target).context("Failed to quarantine file")?;
+        std::fs::remove_file(path)?;
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_clamd_reply() {
+        assert_eq!(parse_clamd_reply("stream: OK\0").unwrap(), ScanVerdict::Clean);
+        assert_eq!(
+            parse_clamd_reply("stream: Win.Test.EICAR_HDB-1 FOUND\0").unwrap(),
+            ScanVerdict::Infected("Win.Test.EICAR_HDB-1".to_string())
+        );
+        assert!(parse_clamd_reply("INSTREAM size limit exceeded. ERROR\0").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_scan_and_quarantine() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let file = dir.join("invoice.pdf");
+        std::fs::write(&file, b"%PDF").unwrap();
+
+        let mut config = Config::for_test(&[]);
+        config.quarantine_dir = dir.join("quarantine");
+
+        config.virus_scan_command = Some("true".to_string());
+        assert_eq!(scan_file(&config, &file).await.unwrap(), Some(ScanVerdict::Clean));
+
+        config.virus_scan_command = Some("echo \"$1: Eicar-Test FOUND\"; exit 1; :".to_string());
+        let verdict = scan_file(&config, &file).await.unwrap();
+        assert_eq!(verdict, Some(ScanVerdict::Infected("Eicar-Test".to_string())));
+
+        config.virus_scan_command = Some("exit 2; :".to_string());
+        assert!(scan_file(&config, &file).await.is_err());
+
+        let quarantined = quarantine(&config, &file).unwrap();
+        assert!(!file.exists());
+        assert!(quarantined.starts_with(dir.join("quarantine")));
+    }
+}
@@ -0,0 +1,175 @@
+//! Trying out a rules change on a past month (`simulate --rules --month`). The month's documents
+//! are classified again from their cached message metadata under the proposed rules, and
+//! nothing is fetched, uploaded or moved: the report only says where each document would go.
+
+use crate::db::InvoiceDocument;
+use crate::extract::invoice::CREDIT_NOTES_FOLDER;
+use crate::mail::MailMessage;
+use crate::mail::attachment;
+use crate::mail::vendors::VendorAliases;
+use crate::process::jobs;
+use crate::process::rules::{self, Rules};
+use chrono_tz::Tz;
+
+/// Where a document would end up under the proposed rules
+#[derive(Debug, Clone, PartialEq)]
+pub enum Simulated {
+    /// The rules leave it where it is, under the same name and category
+    Unchanged,
+    /// A rule skips its sender, so it would no longer be archived
+    Skipped { sender: String },
+    Changed {
+        folder: String,
+        filename: String,
+        category: Option<String>,
+        /// Sender of the rule that applies, None when no rule matches any more
+        sender: Option<String>,
+    },
+}
+
+/// Classify an archived document again under `rules`, as `apply_rules` would have with the
+/// message it came from. Where no rule (or no part of one) applies, the name and folder are what
+/// a run without rules gives: `<sender>-<attachment name>`, in the folder of the bank detected in
+/// the message (or the vendor's, for an aliased vendor), so a rule taken out undoes what it did.
+pub fn reclassify(rules: &Rules, vendors: &mut VendorAliases, document: &InvoiceDocument, message: &MailMessage, timezone: Option<Tz>) -> Simulated {
+    let rule = rules.for_sender(&message.from);
+    if let Some(rule) = rule.filter(|rule| rule.skip) {
+        return Simulated::Skipped { sender: rule.sender.clone() };
+    }
+
+    let original = original_filename(document, message);
+    let (sender_prefix, bank_name) = attachment::message_sender(message, vendors);
+    let filename = match rule.and_then(|rule| rule.filename.as_deref()) {
+        Some(template) => {
+            let parts = rules::FilenameParts {
+                vendor: &document.vendor,
+                original: &original,
+                received: message.received_date(timezone),
+                subject: &message.subject,
+                amount_cents: document.amount_cents,
+                currency: document.currency.as_deref(),
+            };
+            rules::render_filename(template, &parts)
+        }
+        None if sender_prefix.is_empty() => original.clone(),
+        None => format!("{}-{}", sender_prefix, original),
+    };
+    let folder = match month_folder(document) {
+        Some(month_folder) => {
+            let mut folder = match rule.and_then(|rule| rule.folder.clone()).or(bank_name) {
+                Some(institution) => format!("{}/{}", month_folder, institution),
+                None => month_folder,
+            };
+            // Credit notes are filed by their content, which rules don't change
+            if document.folder.ends_with(CREDIT_NOTES_FOLDER) {
+                folder = format!("{}/{}", folder, CREDIT_NOTES_FOLDER);
+            }
+            folder
+        }
+        None => document.folder.clone(),
+    };
+    let category = rule.and_then(|rule| rule.category.clone());
+
+    if filename == document.filename && folder == document.folder && category == document.rule_category {
+        return Simulated::Unchanged;
+    }
+    Simulated::Changed { folder, filename, category, sender: rule.map(|rule| rule.sender.clone()) }
+}
+
+/// The attachment the document was archived from, by name, or the message's only attachment
+/// (a rule may have renamed it beyond recognition), falling back to the archived name without
+/// the sender's prefix
+fn original_filename(document: &InvoiceDocument, message: &MailMessage) -> String {
+    let stripped = document.filename.strip_prefix(&format!("{}-", document.vendor)).unwrap_or(&document.filename);
+    message
+        .attachments
+        .iter()
+        .find(|attachment| attachment.filename == stripped || attachment.filename == document.filename)
+        .or(match message.attachments.as_slice() {
+            [only] => Some(only),
+            _ => None,
+        })
+        .map(|attachment| attachment.filename.clone())
+        .unwrap_or_else(|| stripped.to_string())
+}
+
+/// The month folder a document sits in (`billing/2025/February/Hetzner` -> `billing/2025/February`)
+pub fn month_folder(document: &InvoiceDocument) -> Option<String> {
+    let month_name = jobs::month_name(document.billing_month?);
+    let parts: Vec<&str> = document.folder.split('/').collect();
+    let index = parts.iter().rposition(|part| *part == month_name)?;
+    Some(parts[..=index].join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mail::AttachmentRef;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_reclassify() {
+        let rules = Rules::parse(
+            r#"
+            [[rule]]
+            sender = "hetzner.com"
+            filename = "{date}_{vendor}.{ext}"
+            folder = "Hosting"
+            category = "Infrastructure"
+
+            [[rule]]
+            sender = "newsletter@shop.example"
+            skip = true
+            "#,
+        )
+        .unwrap();
+        let message = |from: &str| MailMessage {
+            id: "m1".to_string(),
+            from: from.to_string(),
+            subject: "Your invoice".to_string(),
+            received_at: "2025-02-03T10:00:00Z".parse().ok(),
+            attachments: vec![AttachmentRef { filename: "Invoice 0042.pdf".to_string(), attachment_id: "a1".to_string(), mime_type: None, size: None }],
+            ..Default::default()
+        };
+        let document = InvoiceDocument {
+            vendor: "hetzner".to_string(),
+            filename: "hetzner-Invoice 0042.pdf".to_string(),
+            folder: "billing/2025/February".to_string(),
+            billing_month: NaiveDate::from_ymd_opt(2025, 2, 1),
+            ..Default::default()
+        };
+        let mut vendors = VendorAliases::default();
+
+        assert_eq!(
+            reclassify(&rules, &mut vendors, &document, &message("Hetzner <billing@hetzner.com>"), Some(chrono_tz::UTC)),
+            Simulated::Changed {
+                folder: "billing/2025/February/Hosting".to_string(),
+                filename: "2025-02-03_hetzner.pdf".to_string(),
+                category: Some("Infrastructure".to_string()),
+                sender: Some("hetzner.com".to_string()),
+            }
+        );
+        assert_eq!(
+            reclassify(&rules, &mut vendors, &document, &message("newsletter@shop.example"), None),
+            Simulated::Skipped { sender: "newsletter@shop.example".to_string() }
+        );
+        assert_eq!(reclassify(&rules, &mut vendors, &document, &message("Hetzner <billing@hetzner-online.example>"), None), Simulated::Unchanged);
+
+        // What a rule that no longer matches did is undone: the sender's name and the bank folder
+        let renamed = InvoiceDocument {
+            filename: "2025-02-03_hetzner.pdf".to_string(),
+            folder: "billing/2025/February/Hosting".to_string(),
+            rule_category: Some("Infrastructure".to_string()),
+            ..document.clone()
+        };
+        assert_eq!(
+            reclassify(&Rules::default(), &mut vendors, &renamed, &message("Hetzner <billing@revolut.com>"), None),
+            Simulated::Changed {
+                folder: "billing/2025/February/Revolut".to_string(),
+                filename: "hetzner-Invoice 0042.pdf".to_string(),
+                category: None,
+                sender: None,
+            }
+        );
+    }
+}
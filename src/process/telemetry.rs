@@ -0,0 +1,294 @@
+//! Per-run counts of the HTTP requests made to each API: how many, how many came back rate
+//! limited (429, or a rate-limit 403) or with a server error, how many downloads were retried,
+//! and the bytes sent and received. They end up in the run summary, so the run record in `runs/`
+//! and `--format json` show what a run cost in quota when tuning concurrency and keyword counts,
+//! for runs that stopped on an error too. The database's run history keeps the file counts only.
+//!
+//! Counts are kept per task, not per process: `track` scopes them to one run's future, so the
+//! tenants of `run-all` running side by side don't mix their figures, and adds them to the scope
+//! around it (the command's, see `measure`) when it ends. Requests made outside a measured run
+//! aren't counted. A tracked run also holds its requests to the adaptive
+//! concurrency limits of `throttle`, which learn from the same answers.
+
+use super::outcome::RunSummary;
+use super::throttle::{Slot, Throttle};
+use crate::config::env::Config;
+use anyhow::Result;
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// Requests and transfer totals for one API
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ApiCalls {
+    pub requests: u64,
+    // Answered 429, or 403 with a rate-limit reason (Gmail's userRateLimitExceeded)
+    pub rate_limited: u64,
+    // Answered 5xx
+    pub server_errors: u64,
+    // Downloads tried again after a transient failure (each retry also counts in `requests`)
+    pub retries: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    // Requests in flight allowed when the run ended (adaptive concurrency), learned for the next run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+}
+
+impl ApiCalls {
+    fn absorb(&mut self, other: &ApiCalls) {
+        self.requests += other.requests;
+        self.rate_limited += other.rate_limited;
+        self.server_errors += other.server_errors;
+        self.retries += other.retries;
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.concurrency = self.concurrency.max(other.concurrency);
+    }
+}
+
+/// Counts by API name ("Gmail", "Drive", "JMAP", "GCS")
+pub type ApiUsage = BTreeMap<String, ApiCalls>;
+
+/// What a measured run keeps track of
+struct Meter {
+    usage: Mutex<ApiUsage>,
+    throttle: Option<Throttle>,
+}
+
+tokio::task_local! {
+    static METER: Arc<Meter>;
+}
+
+/// Run `future`, counting the requests it makes and holding them to `throttle`'s limits. The
+/// counts also go to the enclosing scope, if any.
+async fn meter<T>(throttle: Option<Throttle>, future: impl Future<Output = T>) -> (T, ApiUsage, Option<Throttle>) {
+    let meter = Arc::new(Meter { usage: Mutex::new(ApiUsage::new()), throttle });
+    let output = METER.scope(meter.clone(), future).await;
+    let usage = meter.usage();
+    let _ = METER.try_with(|outer| {
+        if let Ok(mut total) = outer.usage.lock() {
+            absorb(&mut total, &usage);
+        }
+    });
+    let throttle = Arc::into_inner(meter).and_then(|meter| meter.throttle);
+    (output, usage, throttle)
+}
+
+/// Run the pipeline `run` of `config` under adaptive concurrency, adding the requests it made to
+/// its summary. The limits it ended at are saved for the next run, whether it succeeded or not.
+/// The requests are returned as well, for the record of a run that stopped.
+pub async fn track(config: &Config, run: impl Future<Output = Result<RunSummary>>) -> (Result<RunSummary>, ApiUsage) {
+    let (result, usage, throttle) = meter(Throttle::load(config), run).await;
+    if let Some(Err(e)) = throttle.map(|throttle| throttle.save(config)) {
+        log::warn!("{:#}", e);
+    }
+    (result.map(|summary| RunSummary { api: usage.clone(), ..summary }), usage)
+}
+
+/// Run a whole command, counting the requests of the runs it tracks, so the summary of a
+/// command that failed still shows them
+pub async fn measure<T>(command: impl Future<Output = T>) -> (T, ApiUsage) {
+    let (output, usage, _) = meter(None, command).await;
+    (output, usage)
+}
+
+/// The counts so far of the run being measured (empty outside one)
+pub fn snapshot() -> ApiUsage {
+    METER.try_with(|meter| meter.usage()).unwrap_or_default()
+}
+
+impl Meter {
+    /// The counts so far, with the concurrency each API is at
+    fn usage(&self) -> ApiUsage {
+        let mut usage = self.usage.lock().map(|usage| usage.clone()).unwrap_or_default();
+        for (api, limit) in self.throttle.iter().flat_map(Throttle::limits) {
+            usage.entry(api).or_default().concurrency = Some(limit);
+        }
+        usage
+    }
+}
+
+/// Add `other`'s counts to `usage` (used for batch totals)
+pub fn absorb(usage: &mut ApiUsage, other: &ApiUsage) {
+    for (api, calls) in other {
+        usage.entry(api.clone()).or_default().absorb(calls);
+    }
+}
+
+fn record(api: &str, update: impl FnOnce(&mut ApiCalls)) {
+    let _ = METER.try_with(|meter| {
+        if let Ok(mut usage) = meter.usage.lock() {
+            update(usage.entry(api.to_string()).or_default());
+        }
+    });
+}
+
+/// Wait for room under `api`'s concurrency limit, when the run has one
+async fn acquire(api: &str) -> Option<Slot> {
+    let meter = METER.try_with(|meter| meter.clone()).ok()?;
+    Some(meter.throttle.as_ref()?.acquire(api).await)
+}
+
+/// Wait for room under `api`'s concurrency limit for a request whose body the caller streams
+/// itself instead of going through `send`. Hold the slot until the transfer ends, and hand it the
+/// answer with `observe`.
+pub async fn slot(api: &str) -> Option<Slot> {
+    acquire(api).await
+}
+
+/// Adjust the limit of a request's `slot` to the status it was answered with (and the error body,
+/// where a 403 tells whether it was a rate limit)
+pub fn observe(slot: Option<&Slot>, status: StatusCode, body: &[u8]) {
+    if let Some(slot) = slot {
+        slot.observe(is_rate_limited(status, body));
+    }
+}
+
+/// 429, or 403 with a rate-limit reason
+fn is_rate_limited(status: StatusCode, body: &[u8]) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN && String::from_utf8_lossy(body).to_lowercase().contains("ratelimitexceeded"))
+}
+
+/// Count one request to `api`: `status` is None when no answer came back
+pub fn count(api: &str, status: Option<StatusCode>, body: &[u8], sent: u64, received: u64) {
+    record(api, |calls| {
+        calls.requests += 1;
+        calls.bytes_sent += sent;
+        calls.bytes_received += received;
+        match status {
+            Some(status) if is_rate_limited(status, body) => calls.rate_limited += 1,
+            Some(status) if status.is_server_error() => calls.server_errors += 1,
+            _ => {}
+        }
+    });
+}
+
+/// Count a download of `api` tried again
+pub fn retried(api: &str) {
+    record(api, |calls| calls.retries += 1);
+}
+
+/// Send `request` to `api` and count it, waiting for room under the API's concurrency limit
+/// first. The body is read here to know its size; the response handed back has the same status,
+/// headers and body, so callers use it as they would the original (except for `url()`).
+pub async fn send(api: &str, request: RequestBuilder) -> reqwest::Result<Response> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let sent = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map(|bytes| bytes.len() as u64)
+        .or_else(|| request.headers().get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok())
+        .unwrap_or(0);
+
+    let slot = acquire(api).await;
+    let response = match client.execute(request).await {
+        Ok(response) => response,
+        Err(e) => {
+            count(api, None, &[], sent, 0);
+            return Err(e);
+        }
+    };
+    let status = response.status();
+    let version = response.version();
+    let headers = response.headers().clone();
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(e) => {
+            count(api, Some(status), &[], sent, 0);
+            return Err(e);
+        }
+    };
+    count(api, Some(status), &body, sent, body.len() as u64);
+    if let Some(slot) = slot {
+        slot.observe(is_rate_limited(status, &body));
+    }
+
+    let mut rebuilt = http::Response::new(body);
+    *rebuilt.status_mut() = status;
+    *rebuilt.version_mut() = version;
+    *rebuilt.headers_mut() = headers;
+    Ok(Response::from(rebuilt))
+}
+
+/// "Gmail 120 requests (2 rate limited), 0.1 MB out, 4.1 MB in; Drive ...": the usage line of the summary
+pub fn describe(usage: &ApiUsage) -> String {
+    usage
+        .iter()
+        .map(|(api, calls)| {
+            let mut problems = Vec::new();
+            if calls.rate_limited > 0 {
+                problems.push(format!("{} rate limited", calls.rate_limited));
+            }
+            if calls.server_errors > 0 {
+                problems.push(format!("{} server errors", calls.server_errors));
+            }
+            if calls.retries > 0 {
+                problems.push(format!("{} retried", calls.retries));
+            }
+            let problems = if problems.is_empty() { String::new() } else { format!(" ({})", problems.join(", ")) };
+            let concurrency = calls.concurrency.map(|limit| format!(", {} at a time", limit)).unwrap_or_default();
+            format!(
+                "{} {} requests{}, {} out, {} in{}",
+                api,
+                calls.requests,
+                problems,
+                megabytes(calls.bytes_sent),
+                megabytes(calls.bytes_received),
+                concurrency
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_048_576.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_counts_requests_within_a_run() {
+        let mut server = mockito::Server::new_async().await;
+        let _ok = server.mock("POST", "/ok").with_body("hello").create_async().await;
+        let _limited = server.mock("GET", "/limited").with_status(403).with_body("userRateLimitExceeded").create_async().await;
+        let _down = server.mock("GET", "/down").with_status(503).create_async().await;
+        let client = reqwest::Client::new();
+
+        let (body, usage, _) = meter(None, async {
+            let response = send("Gmail", client.post(format!("{}/ok", server.url())).body("abc")).await.unwrap();
+            send("Gmail", client.get(format!("{}/limited", server.url()))).await.unwrap();
+            send("Drive", client.get(format!("{}/down", server.url()))).await.unwrap();
+            retried("Drive");
+            response.text().await.unwrap()
+        })
+        .await;
+        assert_eq!(body, "hello");
+        let gmail = &usage["Gmail"];
+        assert_eq!((gmail.requests, gmail.rate_limited, gmail.server_errors), (2, 1, 0));
+        assert_eq!((gmail.bytes_sent, gmail.bytes_received), (3, 5 + 21));
+        assert_eq!((usage["Drive"].server_errors, usage["Drive"].retries), (1, 1));
+
+        // Outside a measured run nothing is counted, and nothing breaks
+        send("Gmail", client.post(format!("{}/ok", server.url()))).await.unwrap();
+        let mut total = usage.clone();
+        absorb(&mut total, &usage);
+        assert_eq!(total["Gmail"].requests, 4);
+        assert!(describe(&usage).starts_with("Drive 1 requests (1 server errors, 1 retried), 0.0 MB out, 0.0 MB in; Gmail 2 requests (1 rate limited)"));
+
+        // A run that failed still reaches the command's totals
+        let ((), command) = measure(async {
+            let _ = meter(None, send("Gmail", client.post(format!("{}/ok", server.url())))).await;
+        })
+        .await;
+        assert_eq!(command["Gmail"].requests, 1);
+    }
+}
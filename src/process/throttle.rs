@@ -0,0 +1,203 @@
+//! Adaptive concurrency: how many requests each API may have in flight. A run starts at the
+//! concurrency learned by earlier runs (or the configured one), halves it when the API answers
+//! with a rate limit, and raises it by one after every `RAISE_AFTER` requests that went through,
+//! never above the configured concurrency. Where each API ended up is saved next to the
+//! profile's tokens, so a large mailbox settles on what its quota allows instead of hitting the
+//! limit again at the start of every run. ADAPTIVE_CONCURRENCY=false turns it off.
+//!
+//! Every API shares one ceiling, the larger of SEARCH_CONCURRENCY and DOWNLOAD_CONCURRENCY, so
+//! Drive uploads are held to it too; there is no separate upload setting.
+
+use crate::auth::oauth::get_token_dir;
+use crate::config::env::Config;
+use crate::config::shared;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Learned concurrency per API, stored next to the profile's tokens
+const CONCURRENCY_FILE: &str = "concurrency.json";
+
+/// Requests in a row that must go through before one more may run at the same time
+const RAISE_AFTER: u32 = 20;
+
+/// The in-flight limits of one run, per API
+pub struct Throttle {
+    ceiling: usize,
+    learned: BTreeMap<String, usize>,
+    limits: Mutex<BTreeMap<String, Arc<Limit>>>,
+}
+
+struct Limit {
+    api: String,
+    permits: Arc<Semaphore>,
+    state: Mutex<LimitState>,
+}
+
+struct LimitState {
+    limit: usize,
+    ceiling: usize,
+    // Permits still to take back from requests in flight when the limit was lowered
+    owed: usize,
+    // Requests that went through since the limit last changed
+    calm: u32,
+    // Bumped on every cut, so a burst of rate limits answering requests sent before the cut
+    // only lowers the limit once
+    epoch: u64,
+}
+
+/// Permission to send one request; hands its slot back when dropped
+pub struct Slot {
+    limit: Arc<Limit>,
+    permit: Option<OwnedSemaphorePermit>,
+    epoch: u64,
+}
+
+impl Throttle {
+    /// The limits for a run of `config`, starting where earlier runs left them; None when
+    /// ADAPTIVE_CONCURRENCY is off or in mock mode
+    pub fn load(config: &Config) -> Option<Self> {
+        if !config.adaptive_concurrency || config.mock_mode {
+            return None;
+        }
+        Some(Self::new(ceiling(config), load_learned(config).unwrap_or_default()))
+    }
+
+    fn new(ceiling: usize, learned: BTreeMap<String, usize>) -> Self {
+        Self { ceiling: ceiling.max(1), learned, limits: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// Wait for room to send a request to `api`
+    pub async fn acquire(&self, api: &str) -> Slot {
+        let limit = {
+            let mut limits = self.limits.lock().unwrap_or_else(|e| e.into_inner());
+            limits
+                .entry(api.to_string())
+                .or_insert_with(|| {
+                    let start = self.learned.get(api).map_or(self.ceiling, |&learned| learned.clamp(1, self.ceiling));
+                    Arc::new(Limit::new(api, start, self.ceiling))
+                })
+                .clone()
+        };
+        let permit = limit.permits.clone().acquire_owned().await.ok();
+        let epoch = limit.state.lock().unwrap_or_else(|e| e.into_inner()).epoch;
+        Slot { limit, permit, epoch }
+    }
+
+    /// Where each API's limit stands now
+    pub fn limits(&self) -> BTreeMap<String, usize> {
+        let limits = self.limits.lock().unwrap_or_else(|e| e.into_inner());
+        limits.iter().map(|(api, limit)| (api.clone(), limit.state.lock().unwrap_or_else(|e| e.into_inner()).limit)).collect()
+    }
+
+    /// Remember the limits this run ended at for the next run (APIs it didn't use keep theirs)
+    pub fn save(&self, config: &Config) -> Result<()> {
+        let limits = self.limits();
+        if limits.is_empty() {
+            return Ok(());
+        }
+        let path = get_token_dir(config.profile.as_deref())?.join(CONCURRENCY_FILE);
+        shared::update_json(&path, |learned: &mut BTreeMap<String, usize>| learned.extend(limits))
+            .context("Failed to save the learned concurrency")
+    }
+}
+
+impl Limit {
+    fn new(api: &str, start: usize, ceiling: usize) -> Self {
+        Self {
+            api: api.to_string(),
+            permits: Arc::new(Semaphore::new(start)),
+            state: Mutex::new(LimitState { limit: start, ceiling, owed: 0, calm: 0, epoch: 0 }),
+        }
+    }
+}
+
+impl Slot {
+    /// Adjust the limit to how the API answered: halve it on a rate limit, raise it after
+    /// `RAISE_AFTER` requests in a row went through
+    pub fn observe(&self, rate_limited: bool) {
+        let mut state = self.limit.state.lock().unwrap_or_else(|e| e.into_inner());
+        if rate_limited {
+            state.calm = 0;
+            if self.epoch != state.epoch || state.limit == 1 {
+                return;
+            }
+            let lower = state.limit / 2;
+            let cut = state.limit - lower;
+            state.owed += cut - self.limit.permits.forget_permits(cut);
+            state.limit = lower;
+            state.epoch += 1;
+            log::info!("{} rate limited: down to {} request(s) at a time", self.limit.api, lower);
+        } else {
+            state.calm += 1;
+            if state.calm >= RAISE_AFTER && state.limit < state.ceiling {
+                state.calm = 0;
+                state.limit += 1;
+                if state.owed > 0 {
+                    state.owed -= 1;
+                } else {
+                    self.limit.permits.add_permits(1);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut state = self.limit.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(permit) = self.permit.take()
+            && state.owed > 0
+        {
+            state.owed -= 1;
+            permit.forget();
+        }
+    }
+}
+
+/// The configured concurrency a limit starts from and never exceeds
+fn ceiling(config: &Config) -> usize {
+    config.search_concurrency.max(config.download_concurrency)
+}
+
+fn load_learned(config: &Config) -> Option<BTreeMap<String, usize>> {
+    let path = get_token_dir(config.profile.as_deref()).ok()?.join(CONCURRENCY_FILE);
+    serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_halves_on_rate_limits_and_recovers() {
+        let throttle = Throttle::new(8, BTreeMap::from([("Gmail".to_string(), 20)]));
+
+        // Requests sent before the cut answering 429 too only lower the limit once
+        let slots: Vec<_> = futures_util::future::join_all((0..8).map(|_| throttle.acquire("Gmail"))).await;
+        assert_eq!(throttle.limits()["Gmail"], 8);
+        for slot in &slots {
+            slot.observe(true);
+        }
+        drop(slots);
+        assert_eq!(throttle.limits()["Gmail"], 4);
+        let limit = throttle.limits.lock().unwrap()["Gmail"].clone();
+        assert_eq!(limit.permits.available_permits(), 4);
+
+        let slot = throttle.acquire("Gmail").await;
+        slot.observe(true);
+        drop(slot);
+        assert_eq!((throttle.limits()["Gmail"], limit.permits.available_permits()), (2, 2));
+
+        for _ in 0..RAISE_AFTER {
+            throttle.acquire("Gmail").await.observe(false);
+        }
+        assert_eq!((throttle.limits()["Gmail"], limit.permits.available_permits()), (3, 3));
+
+        // A learned limit is where the next run starts
+        let next = Throttle::new(8, throttle.limits());
+        drop(next.acquire("Gmail").await);
+        assert_eq!(next.limits()["Gmail"], 3);
+    }
+}
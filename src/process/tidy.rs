@@ -0,0 +1,243 @@
+//! Duplicate folder cleanup (`tidy`). Drive allows several folders with the same name, and past
+//! runs or manual edits can leave two `March` folders side by side. Each set is merged into its
+//! oldest folder: identical files are kept once, same-name subfolders are merged in turn and the
+//! emptied duplicates go to the Drive trash.
+
+use crate::db::{self, DbPool};
+use crate::drive;
+use crate::drive::client::{DriveClient, FileInfo};
+use crate::process::retention::{is_folder, join, list_files};
+use crate::process::sanitize::TakenNames;
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::mpsc;
+
+/// Folders sharing a name within the same parent
+#[derive(Debug)]
+pub struct DuplicateFolders {
+    pub path: String,
+    /// The oldest folder, which the others are merged into
+    pub keep: FileInfo,
+    pub duplicates: Vec<FileInfo>,
+    /// Files anywhere inside the duplicates
+    pub files: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct TidySummary {
+    /// Duplicate folders merged and trashed
+    pub merged: usize,
+    /// Files and folders moved into the folder kept
+    pub moved: usize,
+    /// Of those, moved under a new name because the folder kept has a different item by that name
+    pub renamed: usize,
+    /// Files already in the folder kept with the same contents, trashed
+    pub identical: usize,
+    /// Invoice records pointed at the copy kept
+    pub relinked: usize,
+    pub failed: usize,
+}
+
+/// Same-name folders among `children`, oldest first; folders Drive reports no creation time for
+/// come last
+pub fn duplicate_groups(children: &[FileInfo]) -> Vec<Vec<FileInfo>> {
+    let mut by_name: BTreeMap<&str, Vec<FileInfo>> = BTreeMap::new();
+    for child in children.iter().filter(|child| is_folder(child)) {
+        by_name.entry(child.name.as_str()).or_default().push(child.clone());
+    }
+    by_name
+        .into_values()
+        .filter(|folders| folders.len() > 1)
+        .map(|mut folders| {
+            folders.sort_by_key(|folder| (folder.created_time.is_none(), folder.created_time.clone()));
+            folders
+        })
+        .collect()
+}
+
+/// Every set of duplicate folders below `root_path`, deepest first so merging one never touches
+/// a set still to be merged
+pub async fn find_duplicates(client: &DriveClient, root_path: &str) -> Result<Vec<DuplicateFolders>> {
+    let Some(root_id) = drive::folder::find_folder_by_path(client, root_path).await? else {
+        return Ok(Vec::new());
+    };
+
+    let mut found = Vec::new();
+    let mut pending = vec![(root_id, root_path.to_string())];
+    while let Some((folder_id, path)) = pending.pop() {
+        let children = drive::folder::list_folder(client, &folder_id).await?;
+        for mut group in duplicate_groups(&children) {
+            let keep = group.remove(0);
+            let mut files = 0;
+            for duplicate in &group {
+                files += list_files(client, &duplicate.id).await?.len();
+            }
+            found.push(DuplicateFolders { path: join(&path, &keep.name), keep, duplicates: group, files });
+        }
+        for child in children.into_iter().filter(is_folder) {
+            pending.push((child.id, join(&path, &child.name)));
+        }
+    }
+    found.sort_by_key(|set| (std::cmp::Reverse(set.path.matches('/').count()), set.path.clone()));
+    Ok(found)
+}
+
+/// Merge the contents of `duplicate_id` into `keep_id`. A file the folder kept already has, with
+/// the same contents, is trashed; its old id is returned with the id of the copy kept. Anything
+/// else whose name is taken moves in as "name-2.pdf", "name-3.pdf", ...
+pub async fn merge_folder(
+    client: &DriveClient,
+    keep_id: &str,
+    duplicate_id: &str,
+    summary: &mut TidySummary,
+) -> Result<Vec<(String, String)>> {
+    let mut replaced = Vec::new();
+    let mut emptied = Vec::new();
+    let mut pending = vec![(keep_id.to_string(), duplicate_id.to_string())];
+    while let Some((keep_id, duplicate_id)) = pending.pop() {
+        let existing: HashMap<String, FileInfo> = drive::folder::list_folder(client, &keep_id)
+            .await?
+            .into_iter()
+            .map(|item| (item.name.clone(), item))
+            .collect();
+        let mut taken = TakenNames::default();
+        for name in existing.keys() {
+            taken.claim(None, name);
+        }
+        for item in drive::folder::list_folder(client, &duplicate_id).await? {
+            match existing.get(&item.name) {
+                Some(kept) if is_folder(kept) && is_folder(&item) => pending.push((kept.id.clone(), item.id)),
+                Some(kept) if !is_folder(kept) && kept.sha256.is_some() && kept.sha256 == item.sha256 => {
+                    drive::folder::trash_item(client, &item.id).await?;
+                    summary.identical += 1;
+                    replaced.push((item.id, kept.id.clone()));
+                }
+                _ => {
+                    let name = taken.claim(None, &item.name);
+                    if name == item.name {
+                        drive::folder::move_item(client, &item.id, &duplicate_id, &keep_id).await?;
+                    } else {
+                        let moved_id = drive::folder::relocate_item(client, &item.id, &duplicate_id, &keep_id, &name).await?;
+                        summary.renamed += 1;
+                        // The mock Drive's ids are paths, which the new name changes
+                        if moved_id != item.id {
+                            replaced.push((item.id, moved_id));
+                        }
+                    }
+                    summary.moved += 1;
+                }
+            }
+        }
+        emptied.push(duplicate_id);
+    }
+
+    // Innermost first; anything still inside (a capped listing) keeps its folder
+    for folder_id in emptied.iter().rev() {
+        if drive::folder::list_folder(client, folder_id).await?.is_empty() {
+            drive::folder::trash_item(client, folder_id).await?;
+        } else {
+            anyhow::bail!("the duplicate folder still has files after merging; run tidy again");
+        }
+    }
+    Ok(replaced)
+}
+
+/// Merge every set in `duplicates`, pointing the invoice records of trashed identical files at the
+/// copies kept (when there is a database)
+pub async fn tidy(
+    client: &DriveClient,
+    pool: Option<&DbPool>,
+    profile: &str,
+    duplicates: &[DuplicateFolders],
+    tx: &mpsc::UnboundedSender<String>,
+) -> Result<TidySummary> {
+    let records = match pool {
+        Some(pool) => db::archived_files(pool, profile, None).await?,
+        None => Vec::new(),
+    };
+
+    let mut summary = TidySummary::default();
+    for set in duplicates {
+        for duplicate in &set.duplicates {
+            let merged = async {
+                let replaced = merge_folder(client, &set.keep.id, &duplicate.id, &mut summary).await?;
+                for (old_id, kept_id) in replaced {
+                    for record in records.iter().filter(|record| record.file_id == old_id) {
+                        if let Some(pool) = pool {
+                            db::update_archived_file(pool, record.id, &kept_id, &drive::client::file_link(&kept_id)).await?;
+                            summary.relinked += 1;
+                        }
+                    }
+                }
+                anyhow::Ok(())
+            };
+            match merged.await {
+                Ok(()) => {
+                    summary.merged += 1;
+                    tx.send(format!("  ✓ {}", set.path))?;
+                }
+                Err(e) => {
+                    summary.failed += 1;
+                    tx.send(format!("  ✗ {}: {:#}", set.path, e))?;
+                }
+            }
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder(id: &str, name: &str, created: Option<&str>) -> FileInfo {
+        FileInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            mime_type: Some(drive::folder::FOLDER_MIME_TYPE.to_string()),
+            size: None,
+            sha256: None,
+            created_time: created.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_groups_and_merge() {
+        let children = vec![
+            folder("b", "March", Some("2025-04-02T10:00:00.000Z")),
+            folder("a", "March", Some("2025-04-01T10:00:00.000Z")),
+            folder("c", "March", None),
+            folder("d", "April", Some("2025-05-01T10:00:00.000Z")),
+            FileInfo { mime_type: None, ..folder("e", "April", None) },
+        ];
+        let groups = duplicate_groups(&children);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].iter().map(|f| f.id.as_str()).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+
+        // Two `March` folders on disk, kept apart by their parents
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let (keep, duplicate) = (root.join("a/March"), root.join("b/March"));
+        for dir in [keep.join("Revolut"), duplicate.join("Revolut")] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::write(keep.join("same.pdf"), b"%PDF-1.4\n").unwrap();
+        std::fs::write(duplicate.join("same.pdf"), b"%PDF-1.4\n").unwrap();
+        std::fs::write(duplicate.join("Revolut/statement.pdf"), b"%PDF-1.4\n%statement\n").unwrap();
+        std::fs::write(keep.join("invoice.pdf"), b"%PDF-1.4\n%first\n").unwrap();
+        std::fs::write(duplicate.join("invoice.pdf"), b"%PDF-1.4\n%second\n").unwrap();
+
+        let client = DriveClient::mock(root);
+        let mut summary = TidySummary::default();
+        let replaced = merge_folder(&client, &keep.to_string_lossy(), &duplicate.to_string_lossy(), &mut summary).await.unwrap();
+        let path = |dir: &std::path::Path, name: &str| dir.join(name).to_string_lossy().to_string();
+        assert!(replaced.contains(&(path(&duplicate, "same.pdf"), path(&keep, "same.pdf"))));
+        assert!(replaced.contains(&(path(&duplicate, "invoice.pdf"), path(&keep, "invoice-2.pdf"))));
+        assert_eq!((summary.moved, summary.renamed, summary.identical), (2, 1, 1));
+        assert!(keep.join("Revolut/statement.pdf").is_file());
+        // A different file by the same name is kept next to the one already there
+        assert_eq!(std::fs::read(keep.join("invoice.pdf")).unwrap(), b"%PDF-1.4\n%first\n");
+        assert_eq!(std::fs::read(keep.join("invoice-2.pdf")).unwrap(), b"%PDF-1.4\n%second\n");
+        assert!(!duplicate.exists());
+    }
+}
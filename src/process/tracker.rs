@@ -0,0 +1,128 @@
+//! The pipeline run started by a long-running server (web dashboard, MCP) and its progress,
+//! so callers can start a run and poll it later
+
+use crate::config::env::Config;
+use crate::process::jobs;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc};
+
+/// Progress lines kept for the current run
+const MAX_LOG_LINES: usize = 500;
+
+/// Events buffered for slow watchers before they start missing lines
+const EVENT_BUFFER: usize = 256;
+
+/// A run started through the tracker, with its progress so far
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveRun {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub started_at: DateTime<Utc>,
+    pub running: bool,
+    pub log: Vec<String>,
+    pub uploaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub error: Option<String>,
+}
+
+/// What watchers of the current run receive
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+#[derive(Debug, Clone)]
+pub enum RunEvent {
+    Progress(String),
+    Finished(LiveRun),
+}
+
+/// One run at a time; clones share the same run
+#[derive(Debug, Clone)]
+pub struct RunTracker {
+    current: Arc<Mutex<Option<LiveRun>>>,
+    events: broadcast::Sender<RunEvent>,
+}
+
+impl Default for RunTracker {
+    fn default() -> Self {
+        Self {
+            current: Arc::new(Mutex::new(None)),
+            events: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+}
+
+impl RunTracker {
+    /// Follow the progress of runs started after this call
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub fn subscribe(&self) -> broadcast::Receiver<RunEvent> {
+        self.events.subscribe()
+    }
+
+    /// The current or last run
+    pub fn snapshot(&self) -> Option<LiveRun> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Start a run in the background; false when one is already in progress
+    pub fn start(&self, config: Config, start_date: NaiveDate, end_date: NaiveDate) -> bool {
+        {
+            let mut current = self.current.lock().unwrap();
+            if current.as_ref().is_some_and(|run| run.running) {
+                return false;
+            }
+            *current = Some(LiveRun {
+                start_date,
+                end_date,
+                started_at: Utc::now(),
+                running: true,
+                log: Vec::new(),
+                uploaded: 0,
+                skipped: 0,
+                failed: 0,
+                error: None,
+            });
+        }
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        let pipeline = tokio::spawn(async move {
+            jobs::run_manual_processing(config, start_date, end_date, &tx).await
+        });
+        let current = self.current.clone();
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            while let Some(line) = rx.recv().await {
+                // Internal markers for the TUI (e.g. __RESULTS__) are not progress
+                if line.starts_with("__") {
+                    continue;
+                }
+                if let Some(run) = current.lock().unwrap().as_mut() {
+                    run.log.push(line.clone());
+                    if run.log.len() > MAX_LOG_LINES {
+                        run.log.remove(0);
+                    }
+                }
+                // No watchers is fine
+                let _ = events.send(RunEvent::Progress(line));
+            }
+            let result = pipeline.await;
+            let finished = current.lock().unwrap().as_mut().map(|run| {
+                run.running = false;
+                match result {
+                    Ok(Ok(summary)) => {
+                        run.uploaded = summary.uploaded;
+                        run.skipped = summary.skipped;
+                        run.failed = summary.failed;
+                    }
+                    Ok(Err(e)) => run.error = Some(format!("{:#}", e)),
+                    Err(e) => run.error = Some(format!("Run aborted: {}", e)),
+                }
+                run.clone()
+            });
+            if let Some(run) = finished {
+                let _ = events.send(RunEvent::Finished(run));
+            }
+        });
+        true
+    }
+}
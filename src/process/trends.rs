@@ -0,0 +1,210 @@
+//! Per-vendor spend trends (`report --trends`). Each vendor's total for a month is set against its
+//! average over the months before, and the billing surprises are flagged: a total more than twice
+//! the usual, a vendor that wasn't billed before, and a regular vendor with nothing that month.
+
+use anyhow::Result;
+use chrono::{Months, NaiveDate};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use crate::db::{self, ArchiveTotal, DbPool};
+use crate::extract::invoice::format_amount;
+
+/// Months before the reported one its totals are compared with, by default
+pub const DEFAULT_WINDOW: u32 = 3;
+
+/// A total more than this many times the usual one is flagged
+const SPIKE_FACTOR: i64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anomaly {
+    /// More than twice the vendor's average in some currency
+    Spike,
+    /// Billed this month and in none of the months before
+    NewVendor,
+    /// Billed in every one of the months before but not this month
+    Missing,
+}
+
+impl Anomaly {
+    pub fn label(self) -> &'static str {
+        match self {
+            Anomaly::Spike => ">2x usual",
+            Anomaly::NewVendor => "new vendor",
+            Anomaly::Missing => "missing usual vendor",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VendorTrend {
+    pub vendor: String,
+    /// Documents archived for the month
+    pub documents: i64,
+    /// Currency ("" when unknown) -> total for the month, of the documents an amount was found in
+    pub month_cents: BTreeMap<String, i64>,
+    /// Currency -> average monthly total over the months before it was billed in
+    pub usual_cents: BTreeMap<String, i64>,
+    /// Months before the reported one the vendor was billed in
+    pub months_billed: u32,
+    pub anomaly: Option<Anomaly>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrendReport {
+    pub month: NaiveDate,
+    /// Months before `month` it is compared with
+    pub window: u32,
+    /// Flagged vendors first, then by name
+    pub vendors: Vec<VendorTrend>,
+}
+
+impl TrendReport {
+    /// Trends of `month` against the `window` months before it, from the invoice database
+    pub async fn load(pool: &DbPool, profile: &str, month: NaiveDate, window: u32) -> Result<Self> {
+        let totals = db::vendor_month_totals(pool, profile, month - Months::new(window), month).await?;
+        Ok(Self::build(&totals, month, window))
+    }
+
+    fn build(totals: &[ArchiveTotal], month: NaiveDate, window: u32) -> Self {
+        let mut current: BTreeMap<&str, Vec<&ArchiveTotal>> = BTreeMap::new();
+        let mut history: BTreeMap<&str, Vec<&ArchiveTotal>> = BTreeMap::new();
+        for total in totals {
+            match total.billing_month {
+                Some(billed) if billed == month => current.entry(&total.vendor).or_default().push(total),
+                Some(billed) if billed < month => history.entry(&total.vendor).or_default().push(total),
+                _ => {}
+            }
+        }
+
+        let names: BTreeSet<&str> = current.keys().chain(history.keys()).copied().collect();
+        let mut vendors: Vec<VendorTrend> = names
+            .into_iter()
+            .filter_map(|vendor| {
+                let now = current.get(vendor).map(Vec::as_slice).unwrap_or_default();
+                let before = history.get(vendor).map(Vec::as_slice).unwrap_or_default();
+                let months_billed = before.iter().filter_map(|total| total.billing_month).collect::<BTreeSet<_>>().len() as u32;
+                let month_cents = sum_by_currency(now);
+
+                let mut usual_cents = BTreeMap::new();
+                for (currency, cents) in sum_by_currency(before) {
+                    let months = before.iter().filter(|total| currency_of(total) == currency && total.total_cents.is_some()).count() as i64;
+                    usual_cents.insert(currency, cents / months.max(1));
+                }
+
+                let anomaly = if now.is_empty() {
+                    // Vendors billed now and then are only listed when they are due
+                    if months_billed < window {
+                        return None;
+                    }
+                    Some(Anomaly::Missing)
+                } else if months_billed == 0 {
+                    Some(Anomaly::NewVendor)
+                } else {
+                    let spiked = month_cents
+                        .iter()
+                        .any(|(currency, cents)| usual_cents.get(currency).is_some_and(|usual| *usual > 0 && *cents > SPIKE_FACTOR * usual));
+                    spiked.then_some(Anomaly::Spike)
+                };
+
+                Some(VendorTrend {
+                    vendor: vendor.to_string(),
+                    documents: now.iter().map(|total| total.documents).sum(),
+                    month_cents,
+                    usual_cents,
+                    months_billed,
+                    anomaly,
+                })
+            })
+            .collect();
+        vendors.sort_by(|a, b| b.anomaly.is_some().cmp(&a.anomaly.is_some()).then_with(|| a.vendor.cmp(&b.vendor)));
+        Self { month, window, vendors }
+    }
+
+    pub fn anomalies(&self) -> usize {
+        self.vendors.iter().filter(|vendor| vendor.anomaly.is_some()).count()
+    }
+
+    /// Plain text table for the terminal
+    pub fn render(&self) -> String {
+        let mut text = format!("Spend trends for {} (vs the {} month(s) before)\n\n", self.month.format("%B %Y"), self.window);
+        if self.vendors.is_empty() {
+            text.push_str("Nothing archived in this period.\n");
+            return text;
+        }
+
+        text.push_str(&format!("  {:<28} {:<22} {:<22} {}\n", "Vendor", "This month", "Usual", "Flag"));
+        for vendor in &self.vendors {
+            text.push_str(&format!(
+                "  {:<28} {:<22} {:<22} {}\n",
+                vendor.vendor,
+                amounts(&vendor.month_cents, vendor.documents),
+                amounts(&vendor.usual_cents, i64::from(vendor.months_billed)),
+                vendor.anomaly.map(Anomaly::label).unwrap_or_default()
+            ));
+        }
+        text.push_str(&format!("\n{} vendor(s), {} flagged\n", self.vendors.len(), self.anomalies()));
+        text
+    }
+}
+
+fn currency_of(total: &ArchiveTotal) -> String {
+    total.currency.clone().unwrap_or_default()
+}
+
+/// Summed amounts per currency; totals with no amount found are left out
+fn sum_by_currency(totals: &[&ArchiveTotal]) -> BTreeMap<String, i64> {
+    let mut sums = BTreeMap::new();
+    for total in totals {
+        if let Some(cents) = total.total_cents {
+            *sums.entry(currency_of(total)).or_default() += cents;
+        }
+    }
+    sums
+}
+
+/// "120.00 EUR, 15.00 USD"; "-" with nothing billed, "no amount" when nothing could be read
+fn amounts(cents: &BTreeMap<String, i64>, documents: i64) -> String {
+    if cents.is_empty() {
+        return if documents > 0 { "no amount".to_string() } else { "-".to_string() };
+    }
+    cents
+        .iter()
+        .map(|(currency, cents)| format!("{} {}", format_amount(*cents), currency).trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trend_anomalies() {
+        let month = |m| NaiveDate::from_ymd_opt(2025, m, 1).unwrap();
+        let total = |vendor: &str, m, cents| ArchiveTotal {
+            billing_month: Some(month(m)),
+            vendor: vendor.to_string(),
+            currency: Some("EUR".to_string()),
+            documents: 1,
+            total_cents: Some(cents),
+        };
+        let totals = vec![
+            total("hetzner", 1, 4000), total("hetzner", 2, 5000), total("hetzner", 3, 4500), total("hetzner", 4, 12000),
+            total("aws", 1, 9000), total("aws", 2, 9500), total("aws", 3, 9000), total("aws", 4, 10000),
+            total("github", 1, 400), total("github", 2, 400), total("github", 3, 400),
+            total("figma", 4, 1500),
+            total("zoom", 2, 1300),
+        ];
+
+        let report = TrendReport::build(&totals, month(4), DEFAULT_WINDOW);
+        let anomaly = |vendor: &str| report.vendors.iter().find(|trend| trend.vendor == vendor).map(|trend| trend.anomaly);
+        assert_eq!(anomaly("hetzner"), Some(Some(Anomaly::Spike)));
+        assert_eq!(anomaly("aws"), Some(None));
+        assert_eq!(anomaly("github"), Some(Some(Anomaly::Missing)));
+        assert_eq!(anomaly("figma"), Some(Some(Anomaly::NewVendor)));
+        assert_eq!(anomaly("zoom"), None);
+        assert_eq!(report.vendors.last().map(|trend| trend.vendor.as_str()), Some("aws"));
+        assert!(report.render().contains("120.00 EUR"));
+    }
+}
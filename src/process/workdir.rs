@@ -0,0 +1,244 @@
+//! Per-run working directories: a run saves its attachments under `run-<pid>-<start>-<n>` in
+//! the profile's temp directory (`n` tells apart runs one process starts in the same second), noting in a manifest where each file is to be uploaded, and removes
+//! the directory when it finishes. One left behind by a run that crashed is found on the next
+//! start and can be uploaded (`recover --resume`) or removed (`recover --clean`).
+
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use tokio::sync::mpsc;
+use crate::config::env::Config;
+use crate::storage::Storage;
+
+const RUN_DIR_PREFIX: &str = "run-";
+
+/// Files ready for upload, one JSON line each
+const MANIFEST_FILE: &str = "pending.jsonl";
+
+const STARTED_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// Runs started by this process so far, to keep their directory names apart
+static RUN_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// A file saved for upload and where it goes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingFile {
+    pub path: PathBuf,
+    /// Storage folder (`<base>/<Month>/<Bank>`)
+    pub folder: String,
+    pub month: NaiveDate,
+    pub bank: Option<String>,
+}
+
+/// The working directory of the current run
+pub struct RunDir {
+    pub path: PathBuf,
+}
+
+impl RunDir {
+    /// Create a directory of its own for a run; another run's directory is never reused
+    pub fn create(config: &Config) -> Result<Self> {
+        let name = format!(
+            "{}{}-{}-{}",
+            RUN_DIR_PREFIX,
+            std::process::id(),
+            chrono::Local::now().format(STARTED_FORMAT),
+            RUN_COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+        std::fs::create_dir_all(config.temp_dir()).context("Failed to create the temp directory")?;
+        let path = config.temp_dir().join(name);
+        std::fs::create_dir(&path).with_context(|| format!("Failed to create the run's working directory {}", path.display()))?;
+        Ok(Self { path })
+    }
+
+    /// Note a file ready for upload, so a crashed run can still deliver it
+    pub fn record(&self, file: &PendingFile) -> Result<()> {
+        let mut manifest = std::fs::OpenOptions::new().create(true).append(true).open(self.path.join(MANIFEST_FILE))?;
+        writeln!(manifest, "{}", serde_json::to_string(file)?)?;
+        Ok(())
+    }
+
+    /// Remove the directory and everything still in it
+    pub fn finish(self) -> Result<()> {
+        std::fs::remove_dir_all(&self.path).with_context(|| format!("Failed to remove {}", self.path.display()))
+    }
+}
+
+/// Working directory of a run that is no longer running
+#[derive(Debug, Clone)]
+pub struct Leftover {
+    pub path: PathBuf,
+    pub pid: u32,
+    pub started: Option<NaiveDateTime>,
+    /// Files in the manifest that are still there
+    pub pending: Vec<PendingFile>,
+    /// All files in the directory and their size
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl Leftover {
+    /// ".../run-4242-20250301T090000 (pid 4242): 3 file(s) ready for upload, 5 file(s) in all,
+    /// 1.2 MB, started 2025-03-01 09:00"
+    pub fn describe(&self) -> String {
+        format!(
+            "{} (pid {}): {} file(s) ready for upload, {} file(s) in all, {}{}",
+            self.path.display(),
+            self.pid,
+            self.pending.len(),
+            self.files,
+            crate::process::compress::format_bytes(self.bytes),
+            self.started.map(|started| format!(", started {}", started.format("%Y-%m-%d %H:%M"))).unwrap_or_default()
+        )
+    }
+
+    /// Upload the pending files to the folders they were meant for, then remove the directory
+    /// when none failed. Returns (uploaded, skipped as already there, failed).
+    pub async fn resume(&self, storage: &dyn Storage, tx: &mpsc::UnboundedSender<String>) -> Result<(usize, usize, usize)> {
+        let mut groups: BTreeMap<(String, NaiveDate, Option<String>), Vec<PathBuf>> = BTreeMap::new();
+        for file in &self.pending {
+            groups.entry((file.folder.clone(), file.month, file.bank.clone())).or_default().push(file.path.clone());
+        }
+
+        let (mut uploaded, mut skipped, mut failed) = (0, 0, 0);
+        for ((folder, month, bank), paths) in groups {
+            tx.send(format!("  📁 {}", folder))?;
+            let folder_id = match storage.month_folder(&folder, month, bank.as_deref()).await {
+                Ok(id) => id,
+                Err(e) => {
+                    failed += paths.len();
+                    tx.send(format!("    ✗ {}: {:#}", folder, e))?;
+                    continue;
+                }
+            };
+            let summary = storage.upload_files(&paths, &folder_id, Some(tx)).await?;
+            uploaded += summary.uploaded;
+            skipped += summary.skipped;
+            failed += summary.failed;
+        }
+        if failed == 0 {
+            self.clean()?;
+        }
+        Ok((uploaded, skipped, failed))
+    }
+
+    pub fn clean(&self) -> Result<()> {
+        std::fs::remove_dir_all(&self.path).with_context(|| format!("Failed to remove {}", self.path.display()))
+    }
+}
+
+/// Working directories of runs of the profile that are gone, oldest first
+pub fn leftovers(config: &Config) -> Vec<Leftover> {
+    let Ok(entries) = std::fs::read_dir(config.temp_dir()) else {
+        return Vec::new();
+    };
+    let mut leftovers: Vec<Leftover> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let (pid, started) = parse_dir_name(&name)?;
+            (pid != std::process::id() && !process_alive(pid, &entry.path())).then(|| load(entry.path(), pid, started))
+        })
+        .collect();
+    leftovers.sort_by_key(|leftover| leftover.started);
+    leftovers
+}
+
+/// "run-4242-20250301T090000-0" -> (4242, 2025-03-01 09:00:00); directories of older versions
+/// have no run number
+fn parse_dir_name(name: &str) -> Option<(u32, Option<NaiveDateTime>)> {
+    let (pid, started) = name.strip_prefix(RUN_DIR_PREFIX)?.split_once('-')?;
+    let started = started.split('-').next().unwrap_or(started);
+    Some((pid.parse().ok()?, NaiveDateTime::parse_from_str(started, STARTED_FORMAT).ok()))
+}
+
+fn load(path: PathBuf, pid: u32, started: Option<NaiveDateTime>) -> Leftover {
+    let pending = std::fs::read_to_string(path.join(MANIFEST_FILE))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str::<PendingFile>(line).ok())
+        .filter(|file| file.path.is_file())
+        .collect();
+    let (files, bytes) = walk(&path).iter().filter(|file| !file.ends_with(MANIFEST_FILE)).fold((0, 0), |(files, bytes), file| {
+        (files + 1, bytes + std::fs::metadata(file).map(|metadata| metadata.len()).unwrap_or(0))
+    });
+    Leftover { path, pid, started, pending, files, bytes }
+}
+
+fn walk(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .flat_map(|entry| if entry.path().is_dir() { walk(&entry.path()) } else { vec![entry.path()] })
+        .collect()
+}
+
+/// Whether the run that made a directory is still going: its process exists (Unix; one of
+/// another user we may not signal counts too), or the directory changed in the last day (elsewhere)
+#[cfg(unix)]
+fn process_alive(pid: u32, _dir: &Path) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks whether the process exists and may be signalled
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32, dir: &Path) -> bool {
+    std::fs::metadata(dir)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age.as_secs() < 24 * 3600))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leftover_run_dirs() {
+        let mut config = Config::for_test(&[]);
+        config.profile = Some(format!("workdir-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(config.temp_dir());
+
+        // This run's own directory is never a leftover, nor is another run started the same second
+        let current = RunDir::create(&config).unwrap();
+        let other = RunDir::create(&config).unwrap();
+        assert_ne!(current.path, other.path);
+        other.finish().unwrap();
+        assert!(current.path.is_dir());
+        assert!(leftovers(&config).is_empty());
+        assert_eq!(parse_dir_name("run-4242-20250301T090000-3").map(|(pid, started)| (pid, started.is_some())), Some((4242, true)));
+        assert!(process_alive(1, &current.path), "init runs as another user but is alive");
+
+        // A directory of a process that doesn't exist, with one pending file and a stray one
+        let crashed = config.temp_dir().join("run-4294967-20250301T090000");
+        std::fs::create_dir_all(crashed.join("message")).unwrap();
+        std::fs::write(crashed.join("message/invoice.pdf"), b"%PDF").unwrap();
+        std::fs::write(crashed.join("stray.pdf"), b"%PDF-1.7").unwrap();
+        let dir = RunDir { path: crashed.clone() };
+        let pending = |name: &str| PendingFile { path: crashed.join(name), folder: "billing/March".to_string(), month: NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(), bank: None };
+        dir.record(&pending("message/invoice.pdf")).unwrap();
+        dir.record(&pending("message/gone.pdf")).unwrap();
+
+        let found = leftovers(&config);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].pid, found[0].pending.len(), found[0].files, found[0].bytes), (4294967, 1, 2, 12));
+        assert!(found[0].describe().contains("started 2025-03-01 09:00"));
+
+        found[0].clean().unwrap();
+        assert!(!crashed.exists());
+        current.finish().unwrap();
+        let _ = std::fs::remove_dir_all(config.temp_dir());
+    }
+}
@@ -1,10 +1,158 @@
-use anyhow::Result;
-use chrono::{Datelike, NaiveDate, Utc};
+use anyhow::{Context, Result};
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+
+/// Day of the month scheduled runs happen on (FETCH_INVOICES_DAY)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScheduleDay {
+    /// Fixed day, 1-31; months without it are skipped
+    Day(u8),
+    /// Nth weekday (1-5, or -1 for the last one), e.g. "last friday"
+    Weekday { nth: i8, weekday: Weekday },
+    /// Nth business day (Monday to Friday, not a holiday; -1 for the last one), e.g. "first business day"
+    BusinessDay(i8),
+}
+
+impl ScheduleDay {
+    /// Parse `5`, `last friday`, `2nd monday`, `first business day` or `last-business-day`
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim().to_lowercase().replace(['-', '_'], " ");
+        if let Ok(day) = value.parse::<u8>() {
+            anyhow::ensure!((1..=31).contains(&day), "day of month must be between 1 and 31");
+            return Ok(Self::Day(day));
+        }
+
+        let words: Vec<&str> = value.split_whitespace().collect();
+        let invalid = || format!("'{}' is not a day of month (5), an Nth weekday (last friday) or an Nth business day (first business day)", value);
+        let (ordinal, rest) = words.split_first().with_context(invalid)?;
+        let nth = match *ordinal {
+            "first" | "1st" => 1,
+            "second" | "2nd" => 2,
+            "third" | "3rd" => 3,
+            "fourth" | "4th" => 4,
+            "fifth" | "5th" => 5,
+            "last" => -1,
+            _ => anyhow::bail!(invalid()),
+        };
+        match rest {
+            ["business", "day"] => Ok(Self::BusinessDay(nth)),
+            [weekday] => Ok(Self::Weekday { nth, weekday: weekday.parse().map_err(|_| anyhow::anyhow!(invalid()))? }),
+            _ => anyhow::bail!(invalid()),
+        }
+    }
+
+    /// The scheduled date in a month, if the month has one
+    pub fn date_in(&self, year: i32, month: u32, holidays: &[Holiday]) -> Option<NaiveDate> {
+        let days = NaiveDate::from_ymd_opt(year, month, 1)?.iter_days().take_while(|date| date.month() == month);
+        let pick = |mut days: Vec<NaiveDate>, nth: i8| match nth {
+            -1 => days.pop(),
+            nth => days.into_iter().nth(nth as usize - 1),
+        };
+        match *self {
+            Self::Day(day) => NaiveDate::from_ymd_opt(year, month, u32::from(day)),
+            Self::Weekday { nth, weekday } => pick(days.filter(|date| date.weekday() == weekday).collect(), nth),
+            Self::BusinessDay(nth) => pick(
+                days.filter(|date| date.weekday().number_from_monday() <= 5 && !holidays.iter().any(|holiday| holiday.matches(*date)))
+                    .collect(),
+                nth,
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for ScheduleDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ordinal = |nth: i8| match nth {
+            -1 => "last".to_string(),
+            1 => "first".to_string(),
+            2 => "2nd".to_string(),
+            3 => "3rd".to_string(),
+            nth => format!("{}th", nth),
+        };
+        match *self {
+            Self::Day(day) => write!(f, "day {}", day),
+            Self::Weekday { nth, weekday } => {
+                let name = match weekday {
+                    Weekday::Mon => "Monday",
+                    Weekday::Tue => "Tuesday",
+                    Weekday::Wed => "Wednesday",
+                    Weekday::Thu => "Thursday",
+                    Weekday::Fri => "Friday",
+                    Weekday::Sat => "Saturday",
+                    Weekday::Sun => "Sunday",
+                };
+                write!(f, "the {} {}", ordinal(nth), name)
+            }
+            Self::BusinessDay(nth) => write!(f, "the {} business day", ordinal(nth)),
+        }
+    }
+}
+
+/// Day off skipped by business-day schedules (HOLIDAYS): a date, or a month and day every year
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Holiday {
+    pub year: Option<i32>,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Holiday {
+    /// Parse `2025-04-18` or `12-25`
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            return Ok(Self { year: Some(date.year()), month: date.month(), day: date.day() });
+        }
+        // Leap year, so 02-29 is accepted
+        let date = NaiveDate::parse_from_str(&format!("2024-{}", value), "%Y-%m-%d")
+            .map_err(|_| anyhow::anyhow!("Holiday '{}' must look like 2025-04-18 or 12-25", value))?;
+        Ok(Self { year: None, month: date.month(), day: date.day() })
+    }
+
+    fn matches(&self, date: NaiveDate) -> bool {
+        self.year.is_none_or(|year| year == date.year()) && self.month == date.month() && self.day == date.day()
+    }
+}
 
 /// Check if today is the scheduled day to fetch invoices
-pub fn should_run_today(scheduled_day: u8) -> bool {
-    let today = Utc::now().day() as u8;
-    today == scheduled_day
+pub fn should_run_today(schedule: &ScheduleDay, holidays: &[Holiday]) -> bool {
+    let today = Utc::now().date_naive();
+    schedule.date_in(today.year(), today.month(), holidays) == Some(today)
+}
+
+/// Next time the scheduled day comes round after `now`. Months without it (a 31st, a fifth
+/// Monday) are skipped, as `should_run_today` never matches in them.
+pub fn next_run(schedule: &ScheduleDay, holidays: &[Holiday], time: NaiveTime, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let this_month = now.date().with_day(1)?;
+    (0..=12)
+        .filter_map(|ahead| {
+            let month = this_month.checked_add_months(Months::new(ahead))?;
+            schedule.date_in(month.year(), month.month(), holidays)
+        })
+        .map(|day| day.and_time(time))
+        .find(|run| *run > now)
+}
+
+/// Short countdown such as "6d 4h", "3h 12m" or "5m"
+pub fn format_countdown(remaining: Duration) -> String {
+    let minutes = remaining.num_minutes().max(0);
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// Random delay of up to `max_minutes` before a scheduled run starts
+pub fn jitter(max_minutes: u64) -> std::time::Duration {
+    std::time::Duration::from_secs(rand::random_range(0..=max_minutes * 60))
+}
+
+/// Wait before retry number `retry` (1-based) of a failed scheduled run: `base`, then doubling
+pub fn retry_delay(base: std::time::Duration, retry: u32) -> std::time::Duration {
+    base.saturating_mul(1 << retry.clamp(1, 16).saturating_sub(1))
 }
 
 /// Calculate the date range for the previous month
@@ -56,6 +204,12 @@ pub fn parse_date_range(range_str: &str) -> Result<(NaiveDate, NaiveDate)> {
     Ok((start_date, end_date))
 }
 
+/// First day of a YYYY-MM month
+pub fn parse_month(month: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d")
+        .map_err(|_| anyhow::anyhow!("Month must be in format YYYY-MM"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,8 +217,61 @@ mod tests {
     #[test]
     fn test_should_run_today() {
         let today = Utc::now().day() as u8;
-        assert!(should_run_today(today));
-        assert!(!should_run_today((today % 28) + 1));
+        assert!(should_run_today(&ScheduleDay::Day(today), &[]));
+        assert!(!should_run_today(&ScheduleDay::Day((today % 28) + 1), &[]));
+    }
+
+    #[test]
+    fn test_schedule_day() {
+        let date = |m, d| NaiveDate::from_ymd_opt(2025, m, d);
+        assert_eq!(ScheduleDay::parse("5").unwrap(), ScheduleDay::Day(5));
+        assert!(ScheduleDay::parse("32").is_err());
+        assert!(ScheduleDay::parse("every friday").is_err());
+
+        let last_friday = ScheduleDay::parse("Last Friday").unwrap();
+        assert_eq!(last_friday.to_string(), "the last Friday");
+        assert_eq!(last_friday.date_in(2025, 2, &[]), date(2, 28));
+        assert_eq!(ScheduleDay::parse("2nd monday").unwrap().date_in(2025, 3, &[]), date(3, 10));
+        assert_eq!(ScheduleDay::parse("fifth monday").unwrap().date_in(2025, 2, &[]), None);
+
+        // Good Friday and Easter Monday 2025 fall on April 18 and 21; New Year's Day every year
+        let holidays = [Holiday::parse("2025-04-18").unwrap(), Holiday::parse("2025-04-21").unwrap(), Holiday::parse("01-01").unwrap()];
+        let first = ScheduleDay::parse("first-business-day").unwrap();
+        assert_eq!(first.date_in(2025, 1, &holidays), date(1, 2));
+        assert_eq!(first.date_in(2025, 3, &[]), date(3, 3));
+        let last = ScheduleDay::parse("last business day").unwrap();
+        assert_eq!(last.date_in(2025, 5, &[]), date(5, 30));
+        assert_eq!(ScheduleDay::parse("3rd business day").unwrap().date_in(2025, 4, &holidays), date(4, 3));
+        assert_eq!(ScheduleDay::BusinessDay(14).date_in(2025, 4, &holidays), date(4, 22));
+    }
+
+    #[test]
+    fn test_next_run() {
+        let at = |m, d, h, min| NaiveDate::from_ymd_opt(2025, m, d).unwrap().and_hms_opt(h, min, 0).unwrap();
+        let nine = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+
+        let day = ScheduleDay::Day;
+
+        assert_eq!(next_run(&day(2), &[], nine, at(2, 24, 5, 0)), Some(at(3, 2, 9, 0)));
+        // Later the same day, or already past it
+        assert_eq!(next_run(&day(24), &[], nine, at(2, 24, 5, 0)), Some(at(2, 24, 9, 0)));
+        assert_eq!(next_run(&day(24), &[], nine, at(2, 24, 9, 0)), Some(at(3, 24, 9, 0)));
+        // April has no 31st
+        assert_eq!(next_run(&day(31), &[], nine, at(4, 1, 0, 0)), Some(at(5, 31, 9, 0)));
+        assert_eq!(next_run(&ScheduleDay::parse("last friday").unwrap(), &[], nine, at(2, 28, 10, 0)), Some(at(3, 28, 9, 0)));
+
+        assert_eq!(format_countdown(at(3, 2, 9, 0) - at(2, 24, 5, 0)), "6d 4h");
+        assert_eq!(format_countdown(Duration::minutes(192)), "3h 12m");
+        assert_eq!(format_countdown(Duration::seconds(30)), "0m");
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let base = std::time::Duration::from_secs(30 * 60);
+        assert_eq!(retry_delay(base, 1), base);
+        assert_eq!(retry_delay(base, 3), base * 4);
+        assert!(jitter(0).is_zero());
+        assert!(jitter(15) <= std::time::Duration::from_secs(15 * 60));
     }
 
     #[test]
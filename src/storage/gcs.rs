@@ -0,0 +1,243 @@
+//! Google Cloud Storage backend. Objects are laid out by date under the archive folder,
+//! `<prefix>/<folder>/<year>/<month>/<bank>/`, so bucket lifecycle rules (`matchesPrefix`, age)
+//! can move or expire whole years, and profiles or mailboxes archived to folders of their own
+//! stay apart as they do on Drive.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{Datelike, NaiveDate};
+use reqwest::Client;
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+use crate::drive::client::UploadedFile;
+use crate::drive::upload::{mime_type_for, UploadSummary};
+use super::Storage;
+use crate::process::outcome::{ApiError, Step};
+use crate::process::telemetry;
+
+pub const STORAGE_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+const GCS_UPLOAD_BASE: &str = "https://storage.googleapis.com/upload/storage/v1";
+const MULTIPART_BOUNDARY: &str = "invoice_pilot_object";
+
+/// Bucket and layout settings (GCS_*)
+#[derive(Debug, Clone)]
+pub struct GcsSettings {
+    pub bucket: String,
+    /// Object name prefix, e.g. "invoices" (none by default)
+    pub prefix: String,
+    /// Service account JSON key; the Drive OAuth client is authorized for Cloud Storage when unset
+    pub service_account_key: Option<PathBuf>,
+    /// Attach the billing month and institution to every object as custom metadata
+    pub object_metadata: bool,
+}
+
+pub struct GcsStorage {
+    client: Client,
+    access_token: String,
+    settings: GcsSettings,
+    upload_base: String,
+    /// Archive folder objects go under (GOOGLE_DRIVE_FOLDER_LOCATION up to its first placeholder)
+    root: String,
+    /// Billing month and institution of the folders handed out by `month_folder`
+    folders: Mutex<HashMap<String, (NaiveDate, String)>>,
+}
+
+impl GcsStorage {
+    pub fn new(access_token: String, settings: GcsSettings, root: &str) -> Self {
+        Self {
+            client: Client::new(),
+            access_token,
+            settings,
+            upload_base: GCS_UPLOAD_BASE.to_string(),
+            root: root.to_string(),
+            folders: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn object_prefix(&self, parts: &[&str]) -> String {
+        let prefix = self.settings.prefix.trim_matches('/');
+        std::iter::once(prefix)
+            .chain(parts.iter().copied())
+            .flat_map(|part| part.split('/'))
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Upload one object, unless one by that name exists. Returns None for an existing object.
+    async fn upload_object(&self, file_path: &Path, folder_id: &str) -> Result<Option<UploadedFile>> {
+        let filename = file_path.file_name().context("Invalid file path")?.to_string_lossy().to_string();
+        let name = format!("{}/{}", folder_id, filename);
+        let mime_type = mime_type_for(&filename);
+
+        let mut metadata = json!({ "name": name, "contentType": mime_type });
+        if self.settings.object_metadata
+            && let Some((month, institution)) = self.folders.lock().expect("gcs folder lock poisoned").get(folder_id)
+        {
+            metadata["metadata"] = json!({
+                "billing-month": month.format("%Y-%m").to_string(),
+                "institution": institution,
+                "source-filename": filename,
+                "archived-by": "invoice-pilot",
+            });
+        }
+
+        let data = std::fs::read(file_path).context("Failed to read file")?;
+        let mut body = format!(
+            "--{boundary}\r\nContent-Type: application/json; charset=UTF-8\r\n\r\n{metadata}\r\n--{boundary}\r\nContent-Type: {mime_type}\r\n\r\n",
+            boundary = MULTIPART_BOUNDARY
+        )
+        .into_bytes();
+        body.extend_from_slice(&data);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", MULTIPART_BOUNDARY).as_bytes());
+
+        // ifGenerationMatch=0 only creates: an existing object is reported, never overwritten
+        let url = format!("{}/b/{}/o?uploadType=multipart&ifGenerationMatch=0", self.upload_base, self.settings.bucket);
+        let request = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.access_token)
+            .header("Content-Type", format!("multipart/related; boundary={}", MULTIPART_BOUNDARY))
+            .body(body);
+        let response = telemetry::send("GCS", request)
+            .await
+            .context("Failed to upload object")?;
+
+        if response.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(ApiError::new("Cloud Storage", status, error_text).into());
+        }
+
+        Ok(Some(UploadedFile {
+            id: format!("gs://{}/{}", self.settings.bucket, name),
+            web_view_link: Some(format!("https://storage.cloud.google.com/{}/{}", self.settings.bucket, name)),
+            name: filename,
+            duplicate: false,
+        }))
+    }
+}
+
+#[async_trait]
+impl Storage for GcsStorage {
+    fn name(&self) -> &'static str {
+        "Google Cloud Storage"
+    }
+
+    /// Objects need no folders: this is only the name prefix
+    async fn find_or_create_folder(&self, folder_path: &str) -> Result<String> {
+        Ok(self.object_prefix(&[folder_path]))
+    }
+
+    async fn month_folder(&self, _folder_path: &str, month: NaiveDate, bank: Option<&str>) -> Result<String> {
+        let year = month.year().to_string();
+        let month_number = format!("{:02}", month.month());
+        let folder = self.object_prefix(&[&self.root, &year, &month_number, bank.unwrap_or_default()]);
+        let institution = bank.unwrap_or("General").to_string();
+        self.folders.lock().expect("gcs folder lock poisoned").insert(folder.clone(), (month, institution));
+        Ok(folder)
+    }
+
+    async fn upload_files(
+        &self,
+        file_paths: &[PathBuf],
+        folder_id: &str,
+        tx: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<UploadSummary> {
+        let mut summary = UploadSummary::default();
+        for file_path in file_paths {
+            let filename = file_path.file_name().unwrap_or_default().to_string_lossy();
+            if let Some(tx) = tx {
+                let _ = tx.send(format!("   ↑ Uploading: {}...", filename));
+            }
+            match self.upload_object(file_path, folder_id).await {
+                Ok(Some(uploaded)) => {
+                    if let Some(tx) = tx {
+                        let _ = tx.send(format!("   ✓ Uploaded: {} ({})", filename, uploaded.id));
+                        let _ = tx.send(Step::FileDone { failed: false }.marker());
+                    }
+                    summary.uploaded += 1;
+                    summary.uploaded_files.push((file_path.clone(), uploaded));
+                }
+                Ok(None) => {
+                    if let Some(tx) = tx {
+                        let _ = tx.send(format!("   ⚠ Skipping duplicate: {} (already exists)", filename));
+                        let _ = tx.send(Step::FileDone { failed: false }.marker());
+                    }
+                    summary.skipped += 1;
+                }
+                Err(e) => {
+                    summary.failed += 1;
+                    if let Some(tx) = tx {
+                        let _ = tx.send(format!("   ✗ Failed to upload {}: {}", file_path.display(), e));
+                        let _ = tx.send(Step::FileDone { failed: true }.marker());
+                    }
+                    summary.failed_files.push((file_path.clone(), e));
+                }
+            }
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+
+    #[tokio::test]
+    async fn test_upload_by_date_with_metadata() {
+        let mut server = mockito::Server::new_async().await;
+        let created = server
+            .mock("POST", "/b/acme-invoices/o")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("uploadType".into(), "multipart".into()),
+                Matcher::UrlEncoded("ifGenerationMatch".into(), "0".into()),
+            ]))
+            .match_header("authorization", "Bearer gcs-token")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex(r#""name":"invoices/billing/ana/2025/03/Revolut/new.pdf""#.to_string()),
+                Matcher::Regex(r#""billing-month":"2025-03""#.to_string()),
+                Matcher::Regex(r#""institution":"Revolut""#.to_string()),
+            ]))
+            .with_body(r#"{"name": "invoices/billing/ana/2025/03/Revolut/new.pdf"}"#)
+            .create_async()
+            .await;
+        let _existing = server
+            .mock("POST", "/b/acme-invoices/o")
+            .match_query(Matcher::Any)
+            .match_body(Matcher::Regex("existing.pdf".to_string()))
+            .with_status(412)
+            .create_async()
+            .await;
+
+        let settings = GcsSettings {
+            bucket: "acme-invoices".to_string(),
+            prefix: "invoices/".to_string(),
+            service_account_key: None,
+            object_metadata: true,
+        };
+        let storage = GcsStorage { upload_base: server.url(), ..GcsStorage::new("gcs-token".to_string(), settings, "billing/ana") };
+        let month = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        let folder = storage.month_folder("billing/ana/2025/March/Revolut", month, Some("Revolut")).await.unwrap();
+        assert_eq!(folder, "invoices/billing/ana/2025/03/Revolut");
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let files = vec![dir.join("new.pdf"), dir.join("existing.pdf")];
+        for file in &files {
+            std::fs::write(file, b"%PDF-1.4\n").unwrap();
+        }
+
+        let summary = storage.upload_files(&files, &folder, None).await.unwrap();
+        created.assert_async().await;
+        assert_eq!((summary.uploaded, summary.skipped, summary.failed), (1, 1, 0));
+        assert_eq!(summary.uploaded_files[0].1.id, "gs://acme-invoices/invoices/billing/ana/2025/03/Revolut/new.pdf");
+    }
+}
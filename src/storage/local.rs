@@ -0,0 +1,97 @@
+//! Local directory storage, e.g. a NAS share or an external disk. Folders mirror the Drive
+//! layout under LOCAL_ARCHIVE_DIR.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use crate::drive::client::UploadedFile;
+use crate::drive::upload::UploadSummary;
+use crate::process::outcome::Step;
+use super::Storage;
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn open(root: &Path) -> Result<Self> {
+        std::fs::create_dir_all(root).with_context(|| format!("Failed to create {}", root.display()))?;
+        // Absolute, so file ids double as file:// links
+        let root = root.canonicalize().with_context(|| format!("Failed to resolve {}", root.display()))?;
+        Ok(Self { root })
+    }
+
+    /// Copy under a hidden temporary name, then rename into place, so a sync client or a
+    /// reader never sees half a file
+    fn copy(file_path: &Path, target: &Path) -> Result<()> {
+        let filename = target.file_name().context("Invalid file path")?.to_string_lossy();
+        let partial = target.with_file_name(format!(".{}.part", filename));
+        std::fs::copy(file_path, &partial).with_context(|| format!("Failed to copy to {}", partial.display()))?;
+        std::fs::rename(&partial, target).with_context(|| format!("Failed to move {} into place", partial.display()))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local folder"
+    }
+
+    async fn find_or_create_folder(&self, folder_path: &str) -> Result<String> {
+        let parts: Vec<&str> = folder_path.split('/').filter(|part| !part.is_empty()).collect();
+        anyhow::ensure!(!parts.is_empty(), "Folder path cannot be empty");
+        let dir = parts.iter().fold(self.root.clone(), |dir, part| dir.join(part));
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(dir.to_string_lossy().to_string())
+    }
+
+    async fn folder_link(&self, folder_path: &str) -> Result<Option<String>> {
+        let dir = folder_path.split('/').filter(|part| !part.is_empty()).fold(self.root.clone(), |dir, part| dir.join(part));
+        Ok(dir.is_dir().then(|| format!("file://{}", dir.display())))
+    }
+
+    async fn upload_files(
+        &self,
+        file_paths: &[PathBuf],
+        folder_id: &str,
+        tx: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<UploadSummary> {
+        let mut summary = UploadSummary::default();
+        for file_path in file_paths {
+            let filename = file_path.file_name().context("Invalid file path")?.to_string_lossy().to_string();
+            let target = Path::new(folder_id).join(&filename);
+            if target.exists() {
+                if let Some(tx) = tx {
+                    let _ = tx.send(format!("   ⚠ Skipping duplicate: {} (already exists)", filename));
+                    let _ = tx.send(Step::FileDone { failed: false }.marker());
+                }
+                summary.skipped += 1;
+                continue;
+            }
+
+            match Self::copy(file_path, &target) {
+                Ok(()) => {
+                    if let Some(tx) = tx {
+                        let _ = tx.send(format!("   ✓ Copied: {} ({})", filename, target.display()));
+                        let _ = tx.send(Step::FileDone { failed: false }.marker());
+                    }
+                    summary.uploaded += 1;
+                    summary.uploaded_files.push((
+                        file_path.clone(),
+                        UploadedFile { id: target.to_string_lossy().to_string(), name: filename, web_view_link: None, duplicate: false },
+                    ));
+                }
+                Err(e) => {
+                    summary.failed += 1;
+                    if let Some(tx) = tx {
+                        let _ = tx.send(format!("   ✗ Failed to copy {}: {:#}", file_path.display(), e));
+                        let _ = tx.send(Step::FileDone { failed: true }.marker());
+                    }
+                    summary.failed_files.push((file_path.clone(), e));
+                }
+            }
+        }
+        Ok(summary)
+    }
+}
@@ -0,0 +1,149 @@
+//! Redundant archiving: every document goes to the primary storage, then to a mirror
+//! (STORAGE_MIRROR). The primary decides the run's outcome and its files are what gets recorded;
+//! the mirror is counted separately, and a mirror failing once the run is under way never stops it.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+use crate::drive::upload::UploadSummary;
+use crate::process::outcome::{MirrorSummary, STEP_MARKER};
+use super::Storage;
+
+pub struct MirroredStorage {
+    primary: Box<dyn Storage>,
+    mirror: Box<dyn Storage>,
+    /// Mirror folder for each primary folder id, or why it couldn't be created
+    folders: Mutex<HashMap<String, Result<String, String>>>,
+    /// Mirror counts since the last `take_mirror_summary`
+    summary: Mutex<MirrorSummary>,
+}
+
+impl MirroredStorage {
+    pub fn new(primary: Box<dyn Storage>, mirror: Box<dyn Storage>) -> Self {
+        Self { primary, mirror, folders: Mutex::new(HashMap::new()), summary: Mutex::new(MirrorSummary::default()) }
+    }
+
+    fn remember(&self, primary_id: &str, mirror_folder: Result<String>) {
+        let mirror_folder = mirror_folder.map_err(|e| format!("{:#}", e));
+        self.folders.lock().expect("mirror folder lock poisoned").insert(primary_id.to_string(), mirror_folder);
+    }
+
+    /// Copy files to the mirror, reporting its progress lines marked as the mirror's
+    async fn mirror_files(&self, file_paths: &[PathBuf], folder_id: &str, tx: Option<&mpsc::UnboundedSender<String>>) -> UploadSummary {
+        let mirror_folder = self.folders.lock().expect("mirror folder lock poisoned").get(folder_id).cloned();
+        let (mirror_tx, mut mirror_rx) = mpsc::unbounded_channel();
+        let result = match mirror_folder {
+            Some(Ok(mirror_folder)) => self.mirror.upload_files(file_paths, &mirror_folder, Some(&mirror_tx)).await.map_err(|e| format!("{:#}", e)),
+            Some(Err(e)) => Err(e),
+            None => Err("no mirror folder for this folder".to_string()),
+        };
+        drop(mirror_tx);
+
+        if let Some(tx) = tx {
+            // The mirror's own steps would count its files twice on the progress bars
+            while let Some(message) = mirror_rx.recv().await {
+                if !message.starts_with(STEP_MARKER) {
+                    let _ = tx.send(format!("   🪞 {}", message.trim_start()));
+                }
+            }
+        }
+        result.unwrap_or_else(|e| {
+            if let Some(tx) = tx {
+                let _ = tx.send(format!("   🪞 ✗ Could not copy {} file(s) to the {}: {}", file_paths.len(), self.mirror.name(), e));
+            }
+            UploadSummary { failed: file_paths.len(), ..Default::default() }
+        })
+    }
+}
+
+#[async_trait]
+impl Storage for MirroredStorage {
+    fn name(&self) -> &'static str {
+        self.primary.name()
+    }
+
+    async fn find_or_create_folder(&self, folder_path: &str) -> Result<String> {
+        let id = self.primary.find_or_create_folder(folder_path).await?;
+        self.remember(&id, self.mirror.find_or_create_folder(folder_path).await);
+        Ok(id)
+    }
+
+    async fn month_folder(&self, folder_path: &str, month: NaiveDate, bank: Option<&str>) -> Result<String> {
+        let id = self.primary.month_folder(folder_path, month, bank).await?;
+        self.remember(&id, self.mirror.month_folder(folder_path, month, bank).await);
+        Ok(id)
+    }
+
+    /// Only the primary: a mirror that can't be written to fails its copies, not the run
+    async fn check_writable(&self, folder_path: &str) -> Result<()> {
+        self.primary.check_writable(folder_path).await
+    }
+
+    /// The primary's folder, where the run summary points
+    async fn folder_link(&self, folder_path: &str) -> Result<Option<String>> {
+        self.primary.folder_link(folder_path).await
+    }
+
+    async fn upload_files(
+        &self,
+        file_paths: &[PathBuf],
+        folder_id: &str,
+        tx: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<UploadSummary> {
+        let uploads = self.primary.upload_files(file_paths, folder_id, tx).await?;
+
+        // Every file, not only the new ones: the mirror may be missing what the primary has
+        let mirrored = self.mirror_files(file_paths, folder_id, tx).await;
+        let mut summary = self.summary.lock().expect("mirror summary lock poisoned");
+        summary.uploaded += mirrored.uploaded;
+        summary.skipped += mirrored.skipped;
+        summary.failed += mirrored.failed;
+        Ok(uploads)
+    }
+
+    fn take_mirror_summary(&self) -> Option<MirrorSummary> {
+        let summary = std::mem::take(&mut *self.summary.lock().expect("mirror summary lock poisoned"));
+        Some(MirrorSummary { name: self.mirror.name().to_string(), ..summary })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::local::LocalStorage;
+
+    #[tokio::test]
+    async fn test_mirror_tracked_separately() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let (primary, mirror) = (dir.join("primary"), dir.join("mirror"));
+        std::fs::create_dir_all(dir.join("primary/billing/March")).unwrap();
+        std::fs::create_dir_all(dir.join("mirror")).unwrap();
+        let files = vec![dir.join("a.pdf"), dir.join("b.pdf")];
+        for file in &files {
+            std::fs::write(file, b"%PDF-1.4\n").unwrap();
+        }
+        // Already archived on the primary by an earlier run, from before the mirror was set up
+        std::fs::write(dir.join("primary/billing/March/a.pdf"), b"%PDF-1.4\n").unwrap();
+
+        let storage = MirroredStorage::new(Box::new(LocalStorage::open(&primary).unwrap()), Box::new(LocalStorage::open(&mirror).unwrap()));
+        let folder = storage.find_or_create_folder("billing/March").await.unwrap();
+        let uploads = storage.upload_files(&files, &folder, None).await.unwrap();
+        assert_eq!((uploads.uploaded, uploads.skipped, uploads.failed), (1, 1, 0));
+        assert!(mirror.join("billing/March/a.pdf").exists() && mirror.join("billing/March/b.pdf").exists());
+
+        let summary = storage.take_mirror_summary().unwrap();
+        assert_eq!((summary.name.as_str(), summary.uploaded, summary.skipped, summary.failed), ("local folder", 2, 0, 0));
+
+        // The mirror going away fails its copies, not the upload
+        std::fs::remove_dir_all(&mirror).unwrap();
+        std::fs::write(&mirror, b"").unwrap();
+        let uploads = storage.upload_files(&files, &folder, None).await.unwrap();
+        assert_eq!((uploads.uploaded, uploads.skipped), (0, 2));
+        assert_eq!(storage.take_mirror_summary().unwrap().failed, 2);
+    }
+}
@@ -0,0 +1,172 @@
+pub mod gcs;
+pub mod local;
+pub mod mirror;
+pub mod sftp;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use crate::auth;
+use crate::config::env::{Config, StorageKind};
+use crate::drive::client::DriveClient;
+use crate::drive::upload::UploadSummary;
+use crate::drive;
+use crate::process::fiscal;
+use crate::process::outcome::{FailureKind, MirrorSummary};
+
+/// Where archived documents are written (Google Drive, an SFTP server, Cloud Storage or a local
+/// directory today, optionally mirrored to a second one)
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Short human readable name used in progress messages
+    fn name(&self) -> &'static str;
+
+    /// Find or create a folder by path (e.g. "billing/all-expenses/2025/March 2025"), returning
+    /// the id uploads into it take
+    async fn find_or_create_folder(&self, folder_path: &str) -> Result<String>;
+
+    /// Folder for a billing month's documents from one institution (`bank`), or the month folder
+    /// itself. `folder_path` is that folder in the Drive layout (`<base>/<Month>/<Bank>`), which
+    /// storages with a layout of their own ignore.
+    async fn month_folder(&self, folder_path: &str, _month: NaiveDate, _bank: Option<&str>) -> Result<String> {
+        self.find_or_create_folder(folder_path).await
+    }
+
+    /// Upload files into a folder, skipping names that are already there
+    async fn upload_files(
+        &self,
+        file_paths: &[PathBuf],
+        folder_id: &str,
+        tx: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<UploadSummary>;
+
+    /// Fail before anything is downloaded when `folder_path` already exists but this account
+    /// can't add files to it (a folder shared view-only), instead of at every upload
+    async fn check_writable(&self, _folder_path: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Link that opens an existing folder in a browser or file manager, for run summaries;
+    /// None when the storage has no such link or the folder isn't there
+    async fn folder_link(&self, _folder_path: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Counts of the copies made to the mirror storage since the last call, when there is one
+    fn take_mirror_summary(&self) -> Option<MirrorSummary> {
+        None
+    }
+}
+
+#[async_trait]
+impl Storage for DriveClient {
+    fn name(&self) -> &'static str {
+        "Google Drive"
+    }
+
+    async fn find_or_create_folder(&self, folder_path: &str) -> Result<String> {
+        drive::folder::find_or_create_folder(self, folder_path).await
+    }
+
+    async fn check_writable(&self, folder_path: &str) -> Result<()> {
+        if self.mock_root().is_some() {
+            return Ok(());
+        }
+        // A folder that isn't there yet is created by this account, which can then write to it
+        let Some(folder_id) = drive::folder::find_folder_by_path(self, folder_path).await? else {
+            return Ok(());
+        };
+        drive::folder::folder_access(self, &folder_id).await?.check(folder_path).context(FailureKind::Permission)
+    }
+
+    async fn folder_link(&self, folder_path: &str) -> Result<Option<String>> {
+        match drive::folder::find_folder_by_path(self, folder_path).await? {
+            Some(folder_id) => Ok(Some(drive::folder::folder_link(self, &folder_id).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn upload_files(
+        &self,
+        file_paths: &[PathBuf],
+        folder_id: &str,
+        tx: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<UploadSummary> {
+        drive::upload::upload_files(self, file_paths, folder_id, tx).await
+    }
+}
+
+/// Build the storage selected by the configuration, authenticating if needed, with the mirror
+/// (STORAGE_MIRROR) behind it when one is configured
+pub async fn connect(config: &Config) -> Result<Box<dyn Storage>> {
+    if config.mock_mode {
+        return Ok(Box::new(DriveClient::mock(&config.mock_drive_dir)));
+    }
+
+    let primary = connect_kind(config, config.storage_backend).await?;
+    match config.storage_mirror {
+        Some(kind) => {
+            let mirror = connect_kind(config, kind).await.context("Failed to connect to the mirror storage")?;
+            Ok(Box::new(mirror::MirroredStorage::new(primary, mirror)))
+        }
+        None => Ok(primary),
+    }
+}
+
+async fn connect_kind(config: &Config, kind: StorageKind) -> Result<Box<dyn Storage>> {
+    match kind {
+        StorageKind::Sftp => {
+            let settings = config.sftp.clone().context("SFTP settings not loaded")?;
+            let storage = sftp::SftpStorage::connect(settings).await.context(FailureKind::Auth)?;
+            Ok(Box::new(storage))
+        }
+        StorageKind::Gcs => {
+            let settings = config.gcs.clone().context("Cloud Storage settings not loaded")?;
+            let access_token = match &settings.service_account_key {
+                Some(key) => auth::service_account::ServiceAccountKey::load(key)?.access_token(None, gcs::STORAGE_SCOPE).await,
+                None => {
+                    auth::drive_auth::get_storage_token_for_profile(
+                        config.drive_client_id.clone(),
+                        config.drive_client_secret.clone(),
+                        config.drive_account.as_deref(),
+                        config.profile.as_deref(),
+                        config.paste_code,
+                    )
+                    .await
+                }
+            }
+            .context(FailureKind::Auth)?;
+            Ok(Box::new(gcs::GcsStorage::new(access_token, settings, &fiscal::fixed_root(&config.drive_folder_path))))
+        }
+        StorageKind::Local => {
+            let root = config.local_archive_dir.as_deref().context("LOCAL_ARCHIVE_DIR not set in .env")?;
+            Ok(Box::new(local::LocalStorage::open(root).context(FailureKind::Config)?))
+        }
+        StorageKind::Drive => Ok(Box::new(drive_client(config).await?)),
+    }
+}
+
+/// Drive client for commands that work on the Drive archive itself (`package`, `archive`),
+/// whatever the storage backend: the mock folder in mock mode
+pub async fn drive_client(config: &Config) -> Result<DriveClient> {
+    if config.mock_mode {
+        return Ok(DriveClient::mock(&config.mock_drive_dir));
+    }
+    let signed_in = async {
+        let drive_token = auth::drive_auth::get_drive_token_for_profile(
+            config.drive_client_id.clone(),
+            config.drive_client_secret.clone(),
+            config.drive_account.as_deref(),
+            config.profile.as_deref(),
+            config.paste_code,
+        )
+        .await
+        .context(FailureKind::Auth)?;
+        auth::preflight::check_token(&drive_token, auth::drive_auth::DRIVE_SCOPE, "Google Drive", "invoice-pilot auth drive").await?;
+        anyhow::Ok(drive_token)
+    };
+    let drive_token = auth::failure::track(config.profile.as_deref(), auth::failure::Service::Drive, signed_in.await)?;
+    Ok(DriveClient::new(drive_token))
+}
@@ -0,0 +1,325 @@
+//! SFTP storage through the OpenSSH `sftp` client, for accountants whose intake system picks
+//! files up from a server. Folders mirror the Drive layout (year/month/bank) under
+//! SFTP_REMOTE_DIR, and every file lands under a temporary name first so nothing is picked up
+//! half-written.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use crate::drive::client::UploadedFile;
+use crate::drive::upload::UploadSummary;
+use crate::process::outcome::Step;
+use super::Storage;
+
+/// Connection settings of the SFTP server (SFTP_*)
+#[derive(Debug, Clone)]
+pub struct SftpSettings {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    /// Private key file; the SSH agent and default keys are tried when neither this nor a
+    /// password is set
+    pub key: Option<PathBuf>,
+    pub password: Option<String>,
+    /// known_hosts file holding the server's key (the user's own by default)
+    pub known_hosts: Option<PathBuf>,
+    /// Directory the archive is written under, relative to the login directory unless absolute
+    pub remote_dir: String,
+}
+
+pub struct SftpStorage {
+    settings: SftpSettings,
+    /// Script handing SFTP_PASSWORD to ssh, in a directory of its own, when password
+    /// authentication is used
+    askpass: Option<PathBuf>,
+}
+
+impl SftpStorage {
+    /// Log in once, so that bad credentials or an unknown host key fail before the run starts
+    pub async fn connect(settings: SftpSettings) -> Result<Self> {
+        let askpass = match &settings.password {
+            Some(_) => Some(write_askpass()?),
+            None => None,
+        };
+        let storage = Self { settings, askpass };
+        storage
+            .batch("pwd\n")
+            .await
+            .with_context(|| format!("Failed to log in to {}@{}", storage.settings.username, storage.settings.host))?;
+        Ok(storage)
+    }
+
+    /// Run a batch of sftp commands, stopping at the first that fails (unless prefixed with
+    /// `-`), and return what they printed
+    async fn batch(&self, commands: &str) -> Result<String> {
+        let settings = &self.settings;
+        let mut cmd = Command::new("sftp");
+        if let (Some(askpass), Some(password)) = (&self.askpass, &settings.password) {
+            // `-b` turns on BatchMode, which would refuse to ask for the password; the first
+            // value given wins, so this goes before it
+            cmd.args(["-o", "BatchMode=no", "-o", "NumberOfPasswordPrompts=1"])
+                .env("SSH_ASKPASS", askpass)
+                .env("SSH_ASKPASS_REQUIRE", "force")
+                .env(ASKPASS_VARIABLE, password);
+        }
+        // Never offer to trust an unknown server (the answer would be the password)
+        cmd.args(["-o", "StrictHostKeyChecking=yes"]);
+        if let Some(known_hosts) = &settings.known_hosts {
+            cmd.arg("-o").arg(format!("UserKnownHostsFile={}", known_hosts.display()));
+        }
+        if let Some(key) = &settings.key {
+            cmd.arg("-i").arg(key).args(["-o", "IdentitiesOnly=yes"]);
+        }
+        cmd.arg("-P")
+            .arg(settings.port.to_string())
+            .args(["-b", "-"])
+            .arg(format!("{}@{}", settings.username, settings.host))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let mut child = cmd.spawn().context("Failed to run sftp (is the OpenSSH client installed?)")?;
+        let mut stdin = child.stdin.take().context("failed to open stdin")?;
+        stdin.write_all(commands.as_bytes()).await?;
+        drop(stdin);
+        let output = child.wait_with_output().await?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("sftp {} ({})", output.status, stderr.trim());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Remote path of a folder of the archive
+    fn remote_path(&self, folder_path: &str) -> String {
+        let parts = folder_path.split('/').filter(|part| !part.is_empty());
+        let base = self.settings.remote_dir.trim_end_matches('/');
+        std::iter::once(base).filter(|base| !base.is_empty()).chain(parts).collect::<Vec<_>>().join("/")
+    }
+
+    /// Names of the files already in a remote folder
+    async fn list(&self, folder: &str) -> Result<HashSet<String>> {
+        let output = self.batch(&format!("ls -1 {}\n", quote(folder))).await?;
+        Ok(listed_names(&output))
+    }
+
+    /// sftp:// URL of a remote path, relative paths being under the login directory
+    fn link(&self, path: &str) -> String {
+        let absolute = if path.starts_with('/') { path.to_string() } else { format!("/~/{}", path) };
+        format!("sftp://{}@{}:{}{}", self.settings.username, self.settings.host, self.settings.port, absolute)
+    }
+
+    fn uploaded_file(&self, folder: &str, filename: &str) -> UploadedFile {
+        let path = format!("{}/{}", folder, filename);
+        UploadedFile {
+            web_view_link: Some(self.link(&path)),
+            id: path,
+            name: filename.to_string(),
+            duplicate: false,
+        }
+    }
+}
+
+impl Drop for SftpStorage {
+    fn drop(&mut self) {
+        if let Some(dir) = self.askpass.as_deref().and_then(Path::parent) {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for SftpStorage {
+    fn name(&self) -> &'static str {
+        "SFTP"
+    }
+
+    async fn find_or_create_folder(&self, folder_path: &str) -> Result<String> {
+        let folder = self.remote_path(folder_path);
+        anyhow::ensure!(!folder.is_empty(), "Folder path cannot be empty");
+        self.batch(&mkdir_script(&folder, &self.settings.remote_dir))
+            .await
+            .with_context(|| format!("Failed to create {} on the SFTP server", folder))?;
+        Ok(folder)
+    }
+
+    /// Not checked on the server: the run that asks has just uploaded into the folder
+    async fn folder_link(&self, folder_path: &str) -> Result<Option<String>> {
+        let folder = self.remote_path(folder_path);
+        Ok((!folder.is_empty()).then(|| self.link(&folder)))
+    }
+
+    async fn upload_files(
+        &self,
+        file_paths: &[PathBuf],
+        folder_id: &str,
+        tx: Option<&mpsc::UnboundedSender<String>>,
+    ) -> Result<UploadSummary> {
+        let existing = self.list(folder_id).await?;
+
+        let mut summary = UploadSummary::default();
+        for file_path in file_paths {
+            let filename = file_path.file_name().context("Invalid file path")?.to_string_lossy().to_string();
+            if existing.contains(&filename) {
+                if let Some(tx) = tx {
+                    let _ = tx.send(format!("   ⚠ Skipping duplicate: {} (already exists)", filename));
+                    let _ = tx.send(Step::FileDone { failed: false }.marker());
+                }
+                summary.skipped += 1;
+                continue;
+            }
+
+            if let Some(tx) = tx {
+                let _ = tx.send(format!("   ↑ Uploading: {}...", filename));
+            }
+            match self.batch(&put_script(file_path, folder_id, &filename)).await {
+                Ok(_) => {
+                    if let Some(tx) = tx {
+                        let _ = tx.send(format!("   ✓ Uploaded: {} (sftp: {}/{})", filename, folder_id, filename));
+                        let _ = tx.send(Step::FileDone { failed: false }.marker());
+                    }
+                    summary.uploaded += 1;
+                    summary.uploaded_files.push((file_path.clone(), self.uploaded_file(folder_id, &filename)));
+                }
+                Err(e) => {
+                    summary.failed += 1;
+                    if let Some(tx) = tx {
+                        let _ = tx.send(format!("   ✗ Failed to upload {}: {}", file_path.display(), e));
+                        let _ = tx.send(Step::FileDone { failed: true }.marker());
+                    }
+                    summary.failed_files.push((file_path.clone(), e));
+                }
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// Environment variable the askpass script reads the password from
+const ASKPASS_VARIABLE: &str = "INVOICE_PILOT_SFTP_PASSWORD";
+
+/// Write the script ssh runs to get the password. It only echoes an environment variable set
+/// for the sftp process, so the password is never written to disk. The script goes in a new
+/// directory only this user can enter, and is created there rather than opened, so nobody else
+/// can swap in a script of their own.
+#[cfg(unix)]
+fn write_askpass() -> Result<PathBuf> {
+    use std::io::Write;
+    use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static ASKPASS_COUNT: AtomicU32 = AtomicU32::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "invoice-pilot-askpass-{}-{}",
+        std::process::id(),
+        ASKPASS_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .with_context(|| format!("Failed to create {} for the SFTP askpass script", dir.display()))?;
+    let path = dir.join("askpass");
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o700)
+        .open(&path)
+        .and_then(|mut file| file.write_all(format!("#!/bin/sh\nprintf '%s\\n' \"${}\"\n", ASKPASS_VARIABLE).as_bytes()))
+        .context("Failed to write the SFTP askpass script")?;
+    Ok(path)
+}
+
+#[cfg(not(unix))]
+fn write_askpass() -> Result<PathBuf> {
+    anyhow::bail!("SFTP_PASSWORD is only supported on Unix; use SFTP_KEY instead")
+}
+
+/// Create every level of a folder below the remote base directory, ignoring levels that exist,
+/// then check the folder is there
+fn mkdir_script(folder: &str, remote_dir: &str) -> String {
+    let base = remote_dir.trim_end_matches('/');
+    let relative = folder.strip_prefix(base).unwrap_or(folder).trim_start_matches('/');
+    let mut script = String::new();
+    let mut path = base.to_string();
+    for part in relative.split('/').filter(|part| !part.is_empty()) {
+        path = if path.is_empty() { part.to_string() } else { format!("{}/{}", path, part) };
+        script.push_str(&format!("-mkdir {}\n", quote(&path)));
+    }
+    script.push_str(&format!("cd {}\n", quote(folder)));
+    script
+}
+
+/// Upload under a hidden temporary name, then rename into place
+fn put_script(local: &Path, folder: &str, filename: &str) -> String {
+    let partial = format!("{}/.{}.part", folder, filename);
+    format!(
+        "put {} {}\nrename {} {}\n",
+        quote(&local.to_string_lossy()),
+        quote(&partial),
+        quote(&partial),
+        quote(&format!("{}/{}", folder, filename))
+    )
+}
+
+/// File names in the output of `ls -1`, which lists them with the folder path in front
+fn listed_names(output: &str) -> HashSet<String> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with("sftp>") && !line.trim().is_empty())
+        .map(|line| line.rsplit('/').next().unwrap_or(line).to_string())
+        .collect()
+}
+
+/// Escape an argument of a batch command. sftp expands globs in some arguments (local files
+/// of `put`, `ls`) even when quoted, so special characters are escaped one by one instead.
+fn quote(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 8);
+    for c in value.chars() {
+        if c.is_whitespace() || matches!(c, '\\' | '"' | '\'' | '*' | '?' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_scripts() {
+        assert_eq!(
+            mkdir_script("intake/billing/March 2025", "intake"),
+            "-mkdir intake/billing\n-mkdir intake/billing/March\\ 2025\ncd intake/billing/March\\ 2025\n"
+        );
+        assert_eq!(
+            put_script(Path::new("/tmp/Invoice [March].pdf"), "billing", "Invoice [March].pdf"),
+            "put /tmp/Invoice\\ \\[March\\].pdf billing/.Invoice\\ \\[March\\].pdf.part\n\
+rename billing/.Invoice\\ \\[March\\].pdf.part billing/Invoice\\ \\[March\\].pdf\n"
+        );
+        let names = listed_names("sftp> ls -1 billing/2025\nbilling/2025/a.pdf\nbilling/2025/b c.pdf\n");
+        assert_eq!(names, HashSet::from(["a.pdf".to_string(), "b c.pdf".to_string()]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_askpass_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (first, second) = (write_askpass().unwrap(), write_askpass().unwrap());
+        assert_ne!(first.parent(), second.parent());
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!((mode(&first), mode(first.parent().unwrap())), (0o700, 0o700));
+        for path in [first, second] {
+            std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        }
+    }
+}